@@ -1,113 +1,85 @@
-use rust_decimal::Decimal;
-use rust_decimal_macros::dec;
-use rust_decimal::prelude::FromPrimitive;
 use rand::{Rng, rng};
-use uuid::Uuid;
 use std::fs::File;
 use csv::Writer;
-use rand::prelude::IndexedRandom;
+use exchange_matching_engine::generator::{FlashCrash, Generator, GeneratorConfig};
+use exchange_matching_engine::operation::SimOp;
+use exchange_matching_engine::source::{connect, Endpoint};
+use std::io::Write;
+use std::str::FromStr;
 
-const INSTRUMENT: &str = "PUMPTHIS";
-const TOTAL_OPERATIONS: usize = 100_000;
-const BOOK_BUILD_OPS: usize = 3_000;
-const MID_PRICE: Decimal = dec!(100);
-const SPREAD: Decimal = dec!(0.0);
-const TICK_SIZE: Decimal = dec!(0.05);
-
-#[derive(Clone, Copy)]
-enum OpType {
-    NewLimit,
-    NewMarket,
-    Cancel,
+/// The operation as a row of the seven-column operations file.
+fn record(operation: &SimOp) -> [String; 7] {
+    match operation {
+        SimOp::New(order) => [
+            "NEW".to_string(),
+            order.instrument.clone(),
+            order.side.to_string(),
+            order.order_type().to_string(),
+            order.quantity.to_string(),
+            order.price.map(|price| price.to_string()).unwrap_or_default(),
+            order.order_id.to_string(),
+        ],
+        SimOp::Cancel { instrument, order_id, .. } => {
+            ["CANCEL".to_string(), instrument.clone(), String::new(), String::new(), String::new(), String::new(), order_id.to_string()]
+        }
+        SimOp::CancelAll { .. } => unreachable!("the generator never cancels by client"),
+    }
 }
 
-const OP_WEIGHTS: &[(OpType, f64)] = &[
-    (OpType::NewLimit, 0.60),
-    (OpType::NewMarket, 0.15),
-    (OpType::Cancel, 0.25),
-];
-
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut rng = rng();
-    let file = File::create("operations.csv")?;
-    let mut wtr = Writer::from_writer(file);
-
-    wtr.write_record(&["operation", "instrument", "side", "order_type", "quantity", "price", "order_to_cancel"])?;
-
-    let mut open_limit_orders: Vec<Uuid> = Vec::with_capacity(TOTAL_OPERATIONS);
-
-    for i in 0..TOTAL_OPERATIONS {
-        let op_type = if i < BOOK_BUILD_OPS {
-            OpType::NewLimit
-        } else {
-            OP_WEIGHTS.choose_weighted(&mut rng, |item| item.1).unwrap().0
-        };
-
-        match op_type {
-            OpType::NewLimit => {
-                let side = if rng.random_range(0..=1) == 1 { "BUY" } else { "SELL" };
-                let price_offset = Decimal::from_f64(rng.random_range(0.05..2.0)).unwrap().round_dp(2);
-                let is_aggressive = rng.random_bool(0.1); 
-
-                let raw_price = if is_aggressive {
-
-                    if side == "BUY" {
-                        MID_PRICE + SPREAD + price_offset
-                    } else {
-                        MID_PRICE - SPREAD - price_offset
-                    }
-                } else {
-
-                    if side == "BUY" {
-                        MID_PRICE - SPREAD - price_offset
-                    } else {
-                        MID_PRICE + SPREAD + price_offset
-                    }
-                };
-
-                let price = (raw_price / TICK_SIZE).round() * TICK_SIZE;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config = GeneratorConfig { seed: rng().random(), ..Default::default() };
+    // With --skip-book-build the run starts from a book loaded with
+    // --load-snapshot instead of building one from scratch.
+    if let Some(index) = args.iter().position(|arg| arg == "--skip-book-build") {
+        args.remove(index);
+        config.book_build = 0;
+    }
+    let flash_crash = match args.iter().position(|arg| arg == "--flash-crash") {
+        Some(index) => {
+            let spec = args.get(index + 1).ok_or("--flash-crash expects side=<buy|sell>,start=<op>,duration=<ops>,size=<qty>,recovery=<ops>,withdraw=<share>")?;
+            let crash = FlashCrash::from_str(spec)?;
+            args.drain(index..=index + 1);
+            Some(crash)
+        }
+        None => None,
+    };
+    let (output, destination): (Box<dyn Write>, String) = match args.as_slice() {
+        [] => (Box::new(File::create("operations.csv")?), "operations.csv".to_string()),
+        [flag, endpoint] if flag == "--connect" => (connect(&endpoint.parse::<Endpoint>()?)?, endpoint.clone()),
+        [flag, endpoint, logon_flag, logon] if flag == "--connect" && logon_flag == "--logon" => {
+            let fields: Vec<&str> = logon.split(':').collect();
+            let [client, _token, ..] = fields.as_slice() else {
+                return Err("--logon expects <client>:<token>[:cancel-on-disconnect]".into());
+            };
+            let client = client.to_string();
+            let mut stream = connect(&endpoint.parse::<Endpoint>()?)?;
+            writeln!(stream, "LOGON,{}", fields.join(","))?;
+            (stream, format!("{} as {}", endpoint, client))
+        }
+        _ => return Err("Usage: data_generator [--skip-book-build] [--flash-crash <key>=<value>,...] [--connect <tcp:host:port|unix:path> [--logon <client>:<token>[:cancel-on-disconnect]]]".into()),
+    };
+    let mut wtr = Writer::from_writer(output);
 
-                let quantity_int = rng.random_range(1..=100); 
-                let quantity = Decimal::from(quantity_int);
-                let new_order_id = Uuid::new_v4();
-                open_limit_orders.push(new_order_id);
+    wtr.write_record(["operation", "instrument", "side", "order_type", "quantity", "price", "order_to_cancel"])?;
 
-                wtr.write_record(&[
-                    "NEW",
-                    INSTRUMENT,
-                    side,
-                    "LIMIT",
-                    &quantity.to_string(),
-                    &price.to_string(),
-                    &new_order_id.to_string(),
-                ])?;
-            }
-            OpType::NewMarket => {
-                let side = if rng.random_range(0..=1) == 1 { "BUY" } else { "SELL" };
-                let quantity_int = rng.random_range(50..=250); 
-                let quantity = Decimal::from(quantity_int);
-                let new_order_id = Uuid::new_v4();
-                wtr.write_record(&[
-                    "NEW",
-                    INSTRUMENT,
-                    side,
-                    "MARKET",
-                    &quantity.to_string(),
-                    "",
-                    &new_order_id.to_string(),
-                ])?;
-            }
-            OpType::Cancel => {
-                if !open_limit_orders.is_empty() {
-                    let index_to_cancel = rng.random_range(open_limit_orders.len()-20..open_limit_orders.len());
-                    let order_id_to_cancel = open_limit_orders.remove(index_to_cancel);
-                    wtr.write_record(&["CANCEL", INSTRUMENT, "", "", "", "", &order_id_to_cancel.to_string()])?;
-                }
-            }
-        }
+    let mut generator = Generator::new(config)?;
+    if let Some(crash) = flash_crash {
+        generator = generator.with_arrivals(crash);
+    }
+    let mut records = 0;
+    for operation in generator {
+        wtr.write_record(record(&operation))?;
+        records += 1;
     }
 
     wtr.flush()?;
-    println!("Generated operations.csv with {} records.", TOTAL_OPERATIONS);
+    println!("Generated {} records to {}.", records, destination);
+    if let Some(crash) = &flash_crash {
+        println!(
+            "Flash crash: {} {} market orders of about {} from operation {}, {} operations of recovery.",
+            crash.duration, crash.side, crash.size, crash.start, crash.recovery
+        );
+    }
     Ok(())
-}
\ No newline at end of file
+}
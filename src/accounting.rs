@@ -0,0 +1,102 @@
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Quantity bookkeeping for one instrument. Every unit that enters the book
+/// (restored from a snapshot or submitted) must leave it exactly once, by a
+/// fill, a cancel, or expiry of an unfilled market remainder, or still be
+/// resting. `filled` counts both sides of every trade.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuantityTally {
+    pub opening: Decimal,
+    pub submitted: Decimal,
+    pub filled: Decimal,
+    pub canceled: Decimal,
+    pub expired: Decimal,
+    pub resting: Decimal,
+}
+
+impl QuantityTally {
+    pub fn inflow(&self) -> Decimal {
+        self.opening + self.submitted
+    }
+
+    pub fn outflow(&self) -> Decimal {
+        self.filled + self.canceled + self.expired + self.resting
+    }
+
+    pub fn is_conserved(&self) -> bool {
+        self.inflow() == self.outflow()
+    }
+
+    fn add(&mut self, other: &QuantityTally) {
+        self.opening += other.opening;
+        self.submitted += other.submitted;
+        self.filled += other.filled;
+        self.canceled += other.canceled;
+        self.expired += other.expired;
+        self.resting += other.resting;
+    }
+}
+
+/// Per-instrument and global tallies taken at one point in time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountingReport {
+    pub per_instrument: BTreeMap<String, QuantityTally>,
+    pub total: QuantityTally,
+}
+
+impl AccountingReport {
+    pub fn from_tallies(tallies: impl IntoIterator<Item = (String, QuantityTally)>) -> Self {
+        let mut report = AccountingReport::default();
+        for (instrument, tally) in tallies {
+            report.total.add(&tally);
+            report.per_instrument.insert(instrument, tally);
+        }
+        report
+    }
+
+    /// Checks conservation for every instrument, naming the first that fails.
+    pub fn verify(&self) -> Result<(), String> {
+        for (instrument, tally) in &self.per_instrument {
+            if !tally.is_conserved() {
+                return Err(format!(
+                    "Quantity not conserved for {}: in={} (opening {} + submitted {}), out={} (filled {} + canceled {} + expired {} + resting {})",
+                    instrument, tally.inflow(), tally.opening, tally.submitted, tally.outflow(),
+                    tally.filled, tally.canceled, tally.expired, tally.resting
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_summary(&self) {
+        println!("\n--- Quantity Accounting ---");
+        println!("{:<12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}", "Instrument", "Opening", "Submitted", "Filled", "Canceled", "Expired", "Resting");
+        let rows = self.per_instrument.iter().map(|(i, t)| (i.as_str(), t)).chain(std::iter::once(("TOTAL", &self.total)));
+        for (instrument, t) in rows {
+            println!("{:<12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}", instrument, t.opening, t.submitted, t.filled, t.canceled, t.expired, t.resting);
+        }
+        println!("---------------------------");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_verify_reports_unbalanced_instrument() {
+        let balanced = QuantityTally { submitted: dec!(10), filled: dec!(4), resting: dec!(6), ..Default::default() };
+        let broken = QuantityTally { submitted: dec!(10), filled: dec!(4), ..Default::default() };
+
+        let report = AccountingReport::from_tallies(vec![("A".to_string(), balanced.clone())]);
+        assert!(report.verify().is_ok());
+        assert_eq!(report.total, balanced);
+
+        let report = AccountingReport::from_tallies(vec![("A".to_string(), balanced), ("B".to_string(), broken)]);
+        let err = report.verify().unwrap_err();
+        assert!(err.contains("for B"));
+        assert_eq!(report.total.submitted, dec!(20));
+    }
+}
@@ -0,0 +1,445 @@
+use crate::config::{InstrumentConfig, PriceCollar, RiskLimits};
+use crate::engine::MatchingEngine;
+use crate::publication::{BookPublisher, BookReader, BookSummary};
+use crate::snapshot::save_snapshot;
+use crate::utils::MatchingEngineError;
+use axum::extract::{FromRef, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// How long a request waits for the matching thread before it is answered
+/// with `202 Accepted` and left queued.
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A change or query against the running engine.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    ListInstruments,
+    CreateInstrument { name: String, config: Box<InstrumentConfig> },
+    Halt(String),
+    Resume(String),
+    SetOrderSizes { instrument: String, tick_size: Option<Decimal>, lot_size: Option<Decimal> },
+    GetRiskLimits,
+    SetRiskLimits(RiskLimits),
+    /// Saves every resting order to the snapshot file at the path.
+    Snapshot(String),
+    /// Busts a trade still within the engine's bust window.
+    BustTrade { instrument: String, trade_id: Uuid },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminError {
+    NotFound(String),
+    BadRequest(String),
+}
+
+impl From<MatchingEngineError> for AdminError {
+    fn from(error: MatchingEngineError) -> Self {
+        match error {
+            MatchingEngineError::MarketNotFound(_) | MatchingEngineError::TradeNotFound(_) => AdminError::NotFound(error.to_string()),
+            _ => AdminError::BadRequest(error.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            AdminError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            AdminError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+pub type AdminReply = Result<Value, AdminError>;
+
+struct AdminRequest {
+    command: AdminCommand,
+    reply: oneshot::Sender<AdminReply>,
+}
+
+/// One instrument as listed by `GET /instruments`.
+#[derive(Debug, Serialize)]
+pub struct InstrumentView {
+    pub name: String,
+    pub halted: bool,
+    pub tick_size: Option<Decimal>,
+    pub lot_size: Option<Decimal>,
+    pub resting_orders: usize,
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+}
+
+impl From<&BookSummary> for InstrumentView {
+    fn from(book: &BookSummary) -> Self {
+        InstrumentView {
+            name: book.instrument.clone(),
+            halted: book.halted,
+            tick_size: book.tick_size,
+            lot_size: book.lot_size,
+            resting_orders: book.resting_orders,
+            best_bid: book.best_bid(),
+            best_ask: book.best_ask(),
+        }
+    }
+}
+
+/// Applies `command` to `engine` on the matching thread.
+pub fn execute(command: AdminCommand, engine: &mut MatchingEngine) -> AdminReply {
+    match command {
+        AdminCommand::ListInstruments => {
+            let instruments: Vec<InstrumentView> = engine
+                .instruments()
+                .into_iter()
+                .filter_map(|name| {
+                    let book = engine.order_book(&name)?;
+                    Some(InstrumentView {
+                        halted: engine.is_halted(&name),
+                        tick_size: book.config().tick_size,
+                        lot_size: book.config().lot_size,
+                        resting_orders: book.resting_orders().len(),
+                        best_bid: book.best_bid(),
+                        best_ask: book.best_ask(),
+                        name,
+                    })
+                })
+                .collect();
+            Ok(json!(instruments))
+        }
+        AdminCommand::CreateInstrument { name, config } => {
+            engine.create_market(name.clone(), *config)?;
+            Ok(json!({ "created": name }))
+        }
+        AdminCommand::Halt(instrument) => {
+            engine.halt_market(&instrument)?;
+            Ok(json!({ "halted": instrument }))
+        }
+        AdminCommand::Resume(instrument) => {
+            engine.resume_market(&instrument)?;
+            Ok(json!({ "resumed": instrument }))
+        }
+        AdminCommand::SetOrderSizes { instrument, tick_size, lot_size } => {
+            engine.set_order_sizes(&instrument, tick_size, lot_size)?;
+            Ok(json!({ "instrument": instrument, "tick_size": tick_size, "lot_size": lot_size }))
+        }
+        AdminCommand::GetRiskLimits => Ok(json!(engine.risk_limits())),
+        AdminCommand::SetRiskLimits(limits) => {
+            engine.set_risk_limits(limits);
+            Ok(json!(engine.risk_limits()))
+        }
+        AdminCommand::Snapshot(path) => {
+            let saved = save_snapshot(&path, &engine.instruments(), engine)
+                .map_err(|e| AdminError::BadRequest(format!("{}: {}", path, e)))?;
+            Ok(json!({ "path": path, "orders": saved }))
+        }
+        AdminCommand::BustTrade { instrument, trade_id } => {
+            let trade = engine.bust_trade(&instrument, &trade_id)?;
+            Ok(json!({ "busted": trade.trade_id, "instrument": trade.instrument, "price": trade.price, "quantity": trade.quantity }))
+        }
+    }
+}
+
+/// An HTTP admin API for a server-mode run. The server runs on its own
+/// thread; requests are queued and applied by [`AdminServer::apply_pending`],
+/// which the run loop calls between operations. Queries of the books are
+/// answered from the snapshot last [`published`](AdminServer::publish),
+/// without involving the matching thread.
+pub struct AdminServer {
+    requests: Receiver<AdminRequest>,
+    address: SocketAddr,
+    publisher: BookPublisher,
+}
+
+/// What the HTTP handlers share: the queue to the matching thread and the
+/// published books.
+#[derive(Clone)]
+struct AdminState {
+    requests: Sender<AdminRequest>,
+    books: BookReader,
+}
+
+impl FromRef<AdminState> for Sender<AdminRequest> {
+    fn from_ref(state: &AdminState) -> Self {
+        state.requests.clone()
+    }
+}
+
+impl FromRef<AdminState> for BookReader {
+    fn from_ref(state: &AdminState) -> Self {
+        state.books.clone()
+    }
+}
+
+impl AdminServer {
+    pub fn start(address: &str) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let (sender, requests) = mpsc::channel();
+        let publisher = BookPublisher::default();
+        let state = AdminState { requests: sender, books: publisher.reader() };
+
+        thread::spawn(move || {
+            let served = runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                axum::serve(listener, router(state)).await
+            });
+            if let Err(e) = served {
+                eprintln!("Admin API stopped: {}", e);
+            }
+        });
+        Ok(Self { requests, address, publisher })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The books as last published, for other readers in the process.
+    pub fn books(&self) -> BookReader {
+        self.publisher.reader()
+    }
+
+    /// Publishes `engine`'s books for the queries to read.
+    pub fn publish(&mut self, engine: &MatchingEngine) {
+        self.publisher.publish(engine);
+    }
+
+    /// Applies every queued request to `engine` and answers it, returning how
+    /// many were applied. The books are published after each request, so a
+    /// query sent after the reply sees its effect.
+    pub fn apply_pending(&mut self, engine: &mut MatchingEngine) -> usize {
+        let mut applied = 0;
+        while let Ok(request) = self.requests.try_recv() {
+            let reply = execute(request.command, engine);
+            self.publisher.publish(engine);
+            let _ = request.reply.send(reply);
+            applied += 1;
+        }
+        applied
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NewInstrument {
+    name: String,
+    tick_size: Option<Decimal>,
+    lot_size: Option<Decimal>,
+    price_collar: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OrderSizes {
+    tick_size: Option<Decimal>,
+    lot_size: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotRequest {
+    path: String,
+}
+
+fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/instruments", get(list_instruments).post(create_instrument))
+        .route("/instruments/{name}/book", get(get_book))
+        .route("/instruments/{name}/halt", post(halt))
+        .route("/instruments/{name}/resume", post(resume))
+        .route("/instruments/{name}/sizes", put(set_order_sizes))
+        .route("/instruments/{name}/trades/{trade_id}/bust", post(bust_trade))
+        .route("/risk", get(get_risk_limits).put(set_risk_limits))
+        .route("/snapshot", post(snapshot))
+        .with_state(state)
+}
+
+/// Hands `command` to the matching thread and waits for its reply.
+async fn dispatch(requests: &Sender<AdminRequest>, command: AdminCommand) -> Response {
+    let (reply, response) = oneshot::channel();
+    if requests.send(AdminRequest { command, reply }).is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": "the run has finished" }))).into_response();
+    }
+    match tokio::time::timeout(REPLY_TIMEOUT, response).await {
+        Ok(Ok(Ok(body))) => Json(body).into_response(),
+        Ok(Ok(Err(error))) => error.into_response(),
+        Ok(Err(_)) => (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": "the run has finished" }))).into_response(),
+        Err(_) => (StatusCode::ACCEPTED, Json(json!({ "status": "queued until the next operation" }))).into_response(),
+    }
+}
+
+async fn list_instruments(State(books): State<BookReader>) -> Response {
+    let snapshot = books.load();
+    let instruments: Vec<InstrumentView> = snapshot.books.iter().map(InstrumentView::from).collect();
+    Json(json!(instruments)).into_response()
+}
+
+async fn get_book(State(books): State<BookReader>, Path(name): Path<String>) -> Response {
+    let snapshot = books.load();
+    match snapshot.book(&name) {
+        Some(book) => Json(json!({ "version": snapshot.version, "published_at": snapshot.published_at, "book": book })).into_response(),
+        None => AdminError::NotFound(format!("No published book for '{}'", name)).into_response(),
+    }
+}
+
+async fn create_instrument(State(requests): State<Sender<AdminRequest>>, Json(body): Json<NewInstrument>) -> Response {
+    let config = Box::new(InstrumentConfig {
+        tick_size: body.tick_size,
+        lot_size: body.lot_size,
+        price_collar: body.price_collar.map(PriceCollar::new),
+        ..Default::default()
+    });
+    dispatch(&requests, AdminCommand::CreateInstrument { name: body.name, config }).await
+}
+
+async fn halt(State(requests): State<Sender<AdminRequest>>, Path(name): Path<String>) -> Response {
+    dispatch(&requests, AdminCommand::Halt(name)).await
+}
+
+async fn resume(State(requests): State<Sender<AdminRequest>>, Path(name): Path<String>) -> Response {
+    dispatch(&requests, AdminCommand::Resume(name)).await
+}
+
+async fn set_order_sizes(
+    State(requests): State<Sender<AdminRequest>>,
+    Path(instrument): Path<String>,
+    Json(body): Json<OrderSizes>,
+) -> Response {
+    dispatch(&requests, AdminCommand::SetOrderSizes { instrument, tick_size: body.tick_size, lot_size: body.lot_size }).await
+}
+
+async fn bust_trade(State(requests): State<Sender<AdminRequest>>, Path((instrument, trade_id)): Path<(String, Uuid)>) -> Response {
+    dispatch(&requests, AdminCommand::BustTrade { instrument, trade_id }).await
+}
+
+async fn get_risk_limits(State(requests): State<Sender<AdminRequest>>) -> Response {
+    dispatch(&requests, AdminCommand::GetRiskLimits).await
+}
+
+async fn set_risk_limits(State(requests): State<Sender<AdminRequest>>, Json(limits): Json<RiskLimits>) -> Response {
+    dispatch(&requests, AdminCommand::SetRiskLimits(limits)).await
+}
+
+async fn snapshot(State(requests): State<Sender<AdminRequest>>, Json(body): Json<SnapshotRequest>) -> Response {
+    dispatch(&requests, AdminCommand::Snapshot(body.path)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::order::Order;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn engine() -> MatchingEngine {
+        MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap()
+    }
+
+    /// Sends one HTTP/1.1 request and returns the status line and body.
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method, path, body.len(), body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[test]
+    fn test_execute_applies_commands_to_the_engine() {
+        let mut engine = engine();
+        let created = AdminCommand::CreateInstrument { name: "NVO".to_string(), config: Box::default() };
+        execute(created.clone(), &mut engine).unwrap();
+        assert!(matches!(execute(created, &mut engine), Err(AdminError::BadRequest(_))));
+
+        execute(AdminCommand::Halt("NVO".to_string()), &mut engine).unwrap();
+        let listed = execute(AdminCommand::ListInstruments, &mut engine).unwrap();
+        assert_eq!(listed[0]["name"], "NVO");
+        assert_eq!(listed[0]["halted"], true);
+        assert_eq!(listed[1]["halted"], false);
+        assert!(matches!(execute(AdminCommand::Halt("TSLA".to_string()), &mut engine), Err(AdminError::NotFound(_))));
+
+        let sizes = AdminCommand::SetOrderSizes { instrument: "SOFI".to_string(), tick_size: Some(dec!(0.01)), lot_size: None };
+        execute(sizes, &mut engine).unwrap();
+        assert_eq!(engine.order_book("SOFI").unwrap().config().tick_size, Some(dec!(0.01)));
+    }
+
+    #[test]
+    fn test_bust_trade_takes_the_trade_off_the_tape() {
+        let mut engine = engine();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5)), &mut logger).unwrap();
+        let (trades, _) = engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(2)), &mut logger).unwrap();
+        let bust = AdminCommand::BustTrade { instrument: "SOFI".to_string(), trade_id: trades[0].trade_id };
+
+        let busted = execute(bust.clone(), &mut engine).unwrap();
+        assert_eq!(busted["quantity"], "2");
+        assert!(engine.recent_trades("SOFI", 10).is_empty());
+        assert!(matches!(execute(bust, &mut engine), Err(AdminError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_http_requests_are_applied_between_operations() {
+        let mut server = AdminServer::start("127.0.0.1:0").unwrap();
+        let address = server.local_addr();
+        let client = thread::spawn(move || {
+            let halted = request(address, "POST", "/instruments/SOFI/halt", "");
+            let risk = request(address, "PUT", "/risk", r#"{"max_order_quantity":"100","max_order_notional":null}"#);
+            let missing = request(address, "POST", "/instruments/TSLA/resume", "");
+            let listed = request(address, "GET", "/instruments", "");
+            (halted, risk, missing, listed)
+        });
+
+        let mut engine = engine();
+        while !client.is_finished() {
+            server.apply_pending(&mut engine);
+            thread::sleep(Duration::from_millis(1));
+        }
+        let (halted, risk, missing, listed) = client.join().unwrap();
+        assert!(listed.1.contains(r#""halted":true"#), "{:?}", listed);
+
+        assert!(halted.0.contains("200"), "{:?}", halted);
+        assert!(engine.is_halted("SOFI"));
+        assert!(risk.0.contains("200"), "{:?}", risk);
+        assert_eq!(engine.risk_limits().max_order_quantity, Some(dec!(100)));
+        assert!(missing.0.contains("404"), "{:?}", missing);
+        assert!(missing.1.contains("TSLA"));
+    }
+
+    #[test]
+    fn test_book_queries_are_answered_without_the_matching_thread() {
+        let mut server = AdminServer::start("127.0.0.1:0").unwrap();
+        let mut engine = engine();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99.5), dec!(7)), &mut logger).unwrap();
+        server.publish(&engine);
+
+        // Nothing calls apply_pending, so these are served from the snapshot.
+        let (status, body) = request(server.local_addr(), "GET", "/instruments/SOFI/book", "");
+        assert!(status.contains("200"), "{}", status);
+        assert!(body.contains(r#""bids":[["99.5","7"]]"#), "{}", body);
+        assert!(request(server.local_addr(), "GET", "/instruments/TSLA/book", "").0.contains("404"));
+        assert_eq!(server.books().load().version, 1);
+    }
+}
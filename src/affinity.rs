@@ -0,0 +1,38 @@
+/// Pins the calling thread to the given core id. Returns false (after printing
+/// a warning) when the core does not exist or the OS refuses the request, in
+/// which case the thread keeps running unpinned.
+pub fn pin_current_thread(core: usize, label: &str) -> bool {
+    let available = core_affinity::get_core_ids().unwrap_or_default();
+    let pinned = match available.into_iter().find(|c| c.id == core) {
+        Some(core_id) => core_affinity::set_for_current(core_id),
+        None => false,
+    };
+    if !pinned {
+        eprintln!("Warning: could not pin {} thread to core {}", label, core);
+    }
+    pinned
+}
+
+/// Parses a comma-separated list of core ids, e.g. `2,3`.
+pub fn parse_core_list(value: &str) -> Result<Vec<usize>, String> {
+    value
+        .split(',')
+        .map(|part| part.trim().parse::<usize>().map_err(|_| format!("Invalid core id '{}'", part)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_core_list() {
+        assert_eq!(parse_core_list("2, 3").unwrap(), vec![2, 3]);
+        assert!(parse_core_list("2,x").is_err());
+    }
+
+    #[test]
+    fn test_pin_to_missing_core_fails() {
+        assert!(!pin_current_thread(usize::MAX, "test"));
+    }
+}
@@ -0,0 +1,62 @@
+use super::{Agent, MarketView};
+use crate::clients::ClientId;
+use crate::generator::Generator;
+use crate::operation::SimOp;
+
+/// Background order flow: sends the next operation of a [`Generator`] every
+/// step, whatever the book looks like, so reactive agents can trade against
+/// the same flow the data generator writes. Falls silent once the generator
+/// runs out.
+pub struct GeneratedFlow {
+    client: ClientId,
+    generator: Generator,
+}
+
+impl GeneratedFlow {
+    pub fn new(client: ClientId, generator: Generator) -> Self {
+        GeneratedFlow { client, generator }
+    }
+}
+
+impl Agent for GeneratedFlow {
+    fn client(&self) -> &ClientId {
+        &self.client
+    }
+
+    fn instrument(&self) -> &str {
+        self.generator.instrument()
+    }
+
+    fn on_step(&mut self, _market: &MarketView, operations: &mut Vec<SimOp>) {
+        operations.extend(self.generator.next());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::market_maker::{MarketMaker, MarketMakerConfig};
+    use crate::agents::run_agents;
+    use crate::engine::MatchingEngine;
+    use crate::generator::GeneratorConfig;
+    use crate::logging::{create_logger, LoggingMode};
+
+    #[test]
+    fn test_market_maker_trades_against_generated_flow() {
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let generator = Generator::new(GeneratorConfig { operations: 300, book_build: 50, ..Default::default() }).unwrap();
+        let mut agents: Vec<Box<dyn Agent>> = vec![
+            Box::new(MarketMaker::new(ClientId::new("mm"), MarketMakerConfig::default())),
+            Box::new(GeneratedFlow::new(ClientId::new("flow"), generator)),
+        ];
+
+        let run = run_agents(&mut logger, &mut engine, &mut agents, 400, &mut Vec::new()).unwrap();
+
+        let flow = run.operations.iter().filter(|operation| operation.client() == Some(&ClientId::new("flow"))).count();
+        assert!((250..=300).contains(&flow), "{} flow operations", flow);
+        assert!(run.report.errors.is_empty(), "{}", run.report.errors[0]);
+        let maker = run.report.leaderboard.iter().find(|standing| standing.client == ClientId::new("mm")).unwrap();
+        assert!(maker.fills.maker_fills > 0);
+    }
+}
@@ -0,0 +1,315 @@
+//! One-way latency between each simulated client and the exchange gateway,
+//! so an agent run can model co-location: who hears of a change first, and
+//! whose order gets there first.
+
+use super::MarketView;
+use crate::clients::ClientId;
+use crate::clock::ManualClock;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+/// A distribution of one-way delays, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Latency {
+    /// No delay: the client sits in the matching engine.
+    #[default]
+    Zero,
+    Fixed(u64),
+    Uniform { min: u64, max: u64 },
+    /// Normal, cut off at zero and at six standard deviations above the mean.
+    Normal { mean: u64, std_dev: u64 },
+}
+
+impl Latency {
+    pub fn sample(&self, rng: &mut StdRng) -> u64 {
+        match *self {
+            Latency::Zero => 0,
+            Latency::Fixed(nanos) => nanos,
+            Latency::Uniform { min, max } => rng.random_range(min..=max.max(min)),
+            Latency::Normal { mean, std_dev } => {
+                // Box-Muller.
+                let (u, v): (f64, f64) = (1.0 - rng.random::<f64>(), rng.random());
+                let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+                (mean as f64 + z * std_dev as f64).clamp(0.0, self.max() as f64) as u64
+            }
+        }
+    }
+
+    /// The longest delay the distribution gives.
+    pub fn max(&self) -> u64 {
+        match *self {
+            Latency::Zero => 0,
+            Latency::Fixed(nanos) => nanos,
+            Latency::Uniform { min, max } => max.max(min),
+            Latency::Normal { mean, std_dev } => mean.saturating_add(std_dev.saturating_mul(6)),
+        }
+    }
+}
+
+/// A client's path to the gateway: how long its orders take to arrive, and
+/// how long the market data takes to reach it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClientLatency {
+    pub order_entry: Latency,
+    pub market_data: Latency,
+}
+
+/// Simulated time for [`run_agents_with`](super::run_agents_with): agents
+/// act every `step_nanos`, each client's operations reach the engine after
+/// its order-entry latency, and each agent sees the book as it stood its
+/// market-data latency ago. Operations of one client arrive in the order
+/// they were sent.
+#[derive(Debug, Clone)]
+pub struct LatencyModel {
+    pub step_nanos: u64,
+    clients: HashMap<ClientId, ClientLatency>,
+    default: ClientLatency,
+    clock: Option<ManualClock>,
+    rng: StdRng,
+}
+
+impl LatencyModel {
+    pub fn new(step_nanos: u64, seed: u64) -> Self {
+        LatencyModel { step_nanos, clients: HashMap::new(), default: ClientLatency::default(), clock: None, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// Sets `client`'s latency.
+    pub fn client(mut self, client: ClientId, latency: ClientLatency) -> Self {
+        self.clients.insert(client, latency);
+        self
+    }
+
+    /// The latency of every client not given one.
+    pub fn default_latency(mut self, latency: ClientLatency) -> Self {
+        self.default = latency;
+        self
+    }
+
+    /// Moves `clock` to the simulated time as the run goes, so an engine
+    /// reading it stamps trades with arrival times.
+    pub fn clock(mut self, clock: ManualClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn latency_of(&self, client: &ClientId) -> &ClientLatency {
+        self.clients.get(client).unwrap_or(&self.default)
+    }
+
+    pub(super) fn order_entry_delay(&mut self, client: &ClientId) -> u64 {
+        let latency = self.latency_of(client).order_entry;
+        latency.sample(&mut self.rng)
+    }
+
+    pub(super) fn market_data_delay(&mut self, client: &ClientId) -> u64 {
+        let latency = self.latency_of(client).market_data;
+        latency.sample(&mut self.rng)
+    }
+
+    /// How far back any client can be looking.
+    pub(super) fn max_market_data_delay(&self) -> u64 {
+        self.clients.values().chain([&self.default]).map(|latency| latency.market_data.max()).max().unwrap_or(0)
+    }
+
+    pub(super) fn set_time(&self, nanos: u64) {
+        if let Some(clock) = &self.clock {
+            clock.set(nanos);
+        }
+    }
+}
+
+impl Default for LatencyModel {
+    /// Every client co-located: the lockstep run of [`run_agents`](super::run_agents).
+    fn default() -> Self {
+        LatencyModel::new(0, 0)
+    }
+}
+
+/// The views of one instrument's book published so far, oldest first, so an
+/// agent can be shown the one that has reached it.
+#[derive(Debug, Default)]
+pub(super) struct ViewHistory {
+    views: Vec<(u64, MarketView)>,
+}
+
+impl ViewHistory {
+    pub(super) fn publish(&mut self, at: u64, view: MarketView) {
+        if let Some(last) = self.views.last_mut()
+            && last.0 == at {
+            last.1 = view;
+            return;
+        }
+        self.views.push((at, view));
+    }
+
+    /// The latest view published at or before `at`.
+    pub(super) fn seen_at(&self, at: u64) -> Option<MarketView> {
+        let published = self.views.partition_point(|&(time, _)| time <= at);
+        published.checked_sub(1).map(|index| self.views[index].1)
+    }
+
+    /// Drops the views no one can still be shown at or after `cutoff`.
+    pub(super) fn prune(&mut self, cutoff: u64) {
+        let stale = self.views.partition_point(|&(time, _)| time <= cutoff).saturating_sub(1);
+        self.views.drain(..stale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::{run_agents_with, Agent};
+    use crate::clock::Clock;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::{create_logger, LoggingMode};
+    use crate::operation::{NewOrder, SimOp};
+    use crate::order::{OrderAttributes, TimeInForce};
+    use crate::utils::Side;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn order(side: Side, price: Decimal, attributes: OrderAttributes) -> SimOp {
+        SimOp::New(NewOrder {
+            order_id: Uuid::new_v4(),
+            instrument: "SOFI".to_string(),
+            side,
+            quantity: dec!(5),
+            price: Some(price),
+            client: None,
+            client_order_id: None,
+            peg: None,
+            discretion: None,
+            attributes,
+            expire_at: None,
+            cancel_after: None,
+            stop_price: None,
+        })
+    }
+
+    /// Offers 5 at 100 on the step it is told to.
+    struct Seller {
+        client: ClientId,
+        on_step: usize,
+        steps: usize,
+    }
+
+    /// Takes whatever is offered, once, the first step it sees an ask.
+    struct Sniper {
+        client: ClientId,
+        fired: bool,
+    }
+
+    impl Agent for Seller {
+        fn client(&self) -> &ClientId {
+            &self.client
+        }
+
+        fn instrument(&self) -> &str {
+            "SOFI"
+        }
+
+        fn on_step(&mut self, _market: &MarketView, operations: &mut Vec<SimOp>) {
+            if self.steps == self.on_step {
+                operations.push(order(Side::Sell, dec!(100), OrderAttributes::default()));
+            }
+            self.steps += 1;
+        }
+    }
+
+    impl Agent for Sniper {
+        fn client(&self) -> &ClientId {
+            &self.client
+        }
+
+        fn instrument(&self) -> &str {
+            "SOFI"
+        }
+
+        fn on_step(&mut self, market: &MarketView, operations: &mut Vec<SimOp>) {
+            if let Some(ask) = market.best_ask
+                && !self.fired {
+                self.fired = true;
+                operations.push(order(Side::Buy, ask, OrderAttributes::default().with_time_in_force(TimeInForce::ImmediateOrCancel)));
+            }
+        }
+    }
+
+    fn agents(seller_step: usize, snipers: &[&str]) -> Vec<Box<dyn Agent>> {
+        let mut agents: Vec<Box<dyn Agent>> = vec![Box::new(Seller { client: ClientId::new("seller"), on_step: seller_step, steps: 0 })];
+        agents.extend(snipers.iter().map(|&client| Box::new(Sniper { client: ClientId::new(client), fired: false }) as Box<dyn Agent>));
+        agents
+    }
+
+    fn engine(clock: &ManualClock) -> MatchingEngine {
+        MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).clock(clock.clone()).build().unwrap()
+    }
+
+    #[test]
+    fn test_the_closer_client_wins_the_race() {
+        let clock = ManualClock::new(0);
+        let mut engine = engine(&clock);
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let remote = ClientLatency { order_entry: Latency::Fixed(50_000), market_data: Latency::Zero };
+        let colocated = ClientLatency { order_entry: Latency::Fixed(5_000), market_data: Latency::Zero };
+        let mut model = LatencyModel::new(100_000, 1).client(ClientId::new("remote"), remote).client(ClientId::new("colo"), colocated).clock(clock.clone());
+        // Both see the offer as soon as it rests; the remote client acts
+        // first but its order lands second.
+        let mut agents = agents(0, &["remote", "colo"]);
+
+        let run = run_agents_with(&mut logger, &mut engine, &mut agents, 3, &mut Vec::new(), &mut model).unwrap();
+
+        let clients: Vec<&str> = run.operations.iter().map(|operation| operation.client().unwrap().0.as_str()).collect();
+        assert_eq!(clients, ["seller", "colo", "remote"]);
+        let trades = engine.recent_trades("SOFI", 10);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].timestamp, 5_000);
+        let colo = run.report.leaderboard.iter().find(|standing| standing.client == ClientId::new("colo")).unwrap();
+        assert_eq!(colo.net_position, dec!(5));
+    }
+
+    #[test]
+    fn test_slow_market_data_delays_the_reaction() {
+        let clock = ManualClock::new(0);
+        let mut engine = engine(&clock);
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let far = ClientLatency { order_entry: Latency::Zero, market_data: Latency::Fixed(250_000) };
+        let mut model = LatencyModel::new(100_000, 1).client(ClientId::new("far"), far).clock(clock.clone());
+        // Offered at 100 us, seen at 350 us, so taken on the step at 400 us.
+        let mut agents = agents(1, &["far"]);
+
+        run_agents_with(&mut logger, &mut engine, &mut agents, 6, &mut Vec::new(), &mut model).unwrap();
+
+        let trades = engine.recent_trades("SOFI", 10);
+        assert_eq!(trades.iter().map(|trade| trade.timestamp).collect::<Vec<_>>(), [400_000]);
+        assert_eq!(clock.now_nanos(), 500_000);
+    }
+
+    #[test]
+    fn test_samples_stay_within_the_distribution() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let normal = Latency::Normal { mean: 1_000, std_dev: 200 };
+        let samples: Vec<u64> = (0..1_000).map(|_| normal.sample(&mut rng)).collect();
+        let mean = samples.iter().sum::<u64>() / samples.len() as u64;
+        assert!((950..=1_050).contains(&mean), "mean {}", mean);
+        assert!(samples.iter().all(|&sample| sample <= normal.max()));
+        assert!((0..100).all(|_| (10..=20).contains(&Latency::Uniform { min: 10, max: 20 }.sample(&mut rng))));
+    }
+
+    #[test]
+    fn test_history_shows_the_view_that_has_arrived() {
+        let view = |bid: i64| MarketView { best_bid: Some(bid.into()), best_ask: None, last_trade: None, imbalance: None, now: 0 };
+        let mut history = ViewHistory::default();
+        history.publish(0, view(1));
+        history.publish(100, view(2));
+        history.publish(100, view(3));
+        history.publish(250, view(4));
+        assert_eq!(history.seen_at(99).unwrap().best_bid, Some(1.into()));
+        assert_eq!(history.seen_at(249).unwrap().best_bid, Some(3.into()));
+        history.prune(120);
+        assert_eq!(history.seen_at(120).unwrap().best_bid, Some(3.into()));
+        assert_eq!(history.views.len(), 2);
+    }
+}
@@ -0,0 +1,344 @@
+use super::{Agent, Fill, MarketView};
+use crate::clients::ClientId;
+use crate::operation::{NewOrder, SimOp};
+use crate::order::OrderAttributes;
+use crate::utils::Side;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// Parameters of [`MarketMaker`]. Inventory enters the model in lots of
+/// `quote_size`, and volatility is the standard deviation of the mid's
+/// change per step, in price units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketMakerConfig {
+    pub instrument: String,
+    /// Risk aversion (gamma): how hard inventory pushes the quotes away.
+    pub risk_aversion: f64,
+    /// Order arrival intensity (kappa): the deeper the flow, the tighter
+    /// the quotes.
+    pub order_intensity: f64,
+    /// Volatility assumed until the mid has moved.
+    pub volatility: f64,
+    /// Weight of each new squared mid change in the volatility estimate.
+    pub volatility_decay: f64,
+    /// How far both quotes move towards the heavier side of the book at
+    /// full imbalance, in price units. Zero ignores the imbalance.
+    pub imbalance_skew: f64,
+    /// Steps in the session; the inventory penalty shrinks towards its end.
+    pub horizon: usize,
+    pub quote_size: Decimal,
+    /// No quote is placed that could take inventory past this on either side.
+    pub max_inventory: Decimal,
+    pub tick_size: Decimal,
+    /// Where to quote around while the book has no mid and no trade.
+    pub reference_price: Decimal,
+    /// Seeds the order ids, so a run is reproducible.
+    pub seed: u64,
+}
+
+impl Default for MarketMakerConfig {
+    fn default() -> Self {
+        MarketMakerConfig {
+            instrument: "PUMPTHIS".to_string(),
+            risk_aversion: 0.1,
+            order_intensity: 1.5,
+            volatility: 0.05,
+            volatility_decay: 0.05,
+            imbalance_skew: 0.0,
+            horizon: 1_000,
+            quote_size: Decimal::from(10),
+            max_inventory: Decimal::from(100),
+            tick_size: Decimal::new(1, 2),
+            reference_price: Decimal::from(100),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Quote {
+    order_id: Uuid,
+    price: Decimal,
+    remaining: Decimal,
+}
+
+/// A liquidity provider after Avellaneda and Stoikov (2008). Each step it
+/// quotes one lot a side around a reservation price
+///
+/// `r = mid - q * gamma * sigma^2 * (T - t)`
+///
+/// with a total spread of
+///
+/// `gamma * sigma^2 * (T - t) + (2 / gamma) * ln(1 + gamma / kappa)`,
+///
+/// so a long inventory `q` lowers both quotes to sell down, and higher
+/// volatility widens them. Quotes whose price is unchanged are left resting
+/// and keep their queue position; the others are canceled and replaced.
+#[derive(Debug)]
+pub struct MarketMaker {
+    client: ClientId,
+    config: MarketMakerConfig,
+    rng: StdRng,
+    step: usize,
+    inventory: Decimal,
+    variance: f64,
+    last_mid: Option<f64>,
+    quotes: [Option<Quote>; 2],
+}
+
+impl MarketMaker {
+    pub fn new(client: ClientId, config: MarketMakerConfig) -> Self {
+        MarketMaker {
+            client,
+            rng: StdRng::seed_from_u64(config.seed),
+            step: 0,
+            inventory: Decimal::ZERO,
+            variance: config.volatility * config.volatility,
+            last_mid: None,
+            quotes: [None, None],
+            config,
+        }
+    }
+
+    /// Net quantity bought so far.
+    pub fn inventory(&self) -> Decimal {
+        self.inventory
+    }
+
+    /// The current volatility estimate.
+    pub fn volatility(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// The prices of the live bid and ask quotes.
+    pub fn quotes(&self) -> (Option<Decimal>, Option<Decimal>) {
+        let price = |side: Side| self.quotes[side as usize].map(|quote| quote.price);
+        (price(Side::Buy), price(Side::Sell))
+    }
+
+    /// The bid and ask the model wants around `mid` now.
+    fn target_quotes(&self, mid: f64) -> (Decimal, Decimal) {
+        let MarketMakerConfig { risk_aversion: gamma, order_intensity: kappa, .. } = self.config;
+        let remaining = self.config.horizon.saturating_sub(self.step).max(1) as f64;
+        let lots = f64::try_from(self.inventory / self.config.quote_size).unwrap_or(0.0);
+        let inventory_risk = gamma * self.variance * remaining;
+        let reservation = mid - lots * inventory_risk;
+        let half_spread = (inventory_risk + 2.0 / gamma * (1.0 + gamma / kappa).ln()) / 2.0;
+
+        let tick = self.config.tick_size;
+        let to_ticks = |price: f64| Decimal::from_f64_retain(price).unwrap_or(Decimal::ZERO) / tick;
+        let bid = to_ticks(reservation - half_spread).floor().max(Decimal::ONE) * tick;
+        let ask = (to_ticks(reservation + half_spread).ceil() * tick).max(bid + tick);
+        (bid, ask)
+    }
+
+    fn requote(&mut self, side: Side, price: Option<Decimal>, operations: &mut Vec<SimOp>) {
+        let current = self.quotes[side as usize];
+        if current.map(|quote| quote.price) == price {
+            return;
+        }
+        if let Some(quote) = current {
+            operations.push(SimOp::Cancel { instrument: self.config.instrument.clone(), order_id: quote.order_id, client: Some(self.client.clone()) });
+        }
+        self.quotes[side as usize] = price.map(|price| {
+            let order_id = Uuid::from_u128(self.rng.random());
+            operations.push(SimOp::New(NewOrder {
+                order_id,
+                instrument: self.config.instrument.clone(),
+                side,
+                quantity: self.config.quote_size,
+                price: Some(price),
+                client: Some(self.client.clone()),
+                client_order_id: None,
+                peg: None,
+                discretion: None,
+                attributes: OrderAttributes::default(),
+                expire_at: None,
+                cancel_after: None,
+                stop_price: None,
+            }));
+            Quote { order_id, price, remaining: self.config.quote_size }
+        });
+    }
+}
+
+impl Agent for MarketMaker {
+    fn client(&self) -> &ClientId {
+        &self.client
+    }
+
+    fn instrument(&self) -> &str {
+        &self.config.instrument
+    }
+
+    fn on_step(&mut self, market: &MarketView, operations: &mut Vec<SimOp>) {
+        let mid = market
+            .mid()
+            .or(market.last_trade)
+            .and_then(|price| f64::try_from(price).ok())
+            .or(self.last_mid)
+            .unwrap_or_else(|| f64::try_from(self.config.reference_price).unwrap_or(0.0));
+        if let Some(last_mid) = self.last_mid {
+            let change = mid - last_mid;
+            self.variance += self.config.volatility_decay * (change * change - self.variance);
+        }
+        self.last_mid = Some(mid);
+
+        let imbalance = market.imbalance.and_then(|imbalance| f64::try_from(imbalance).ok()).unwrap_or(0.0);
+        let (bid, ask) = self.target_quotes(mid + self.config.imbalance_skew * imbalance);
+        let room_to_buy = self.inventory + self.config.quote_size <= self.config.max_inventory;
+        let room_to_sell = self.inventory - self.config.quote_size >= -self.config.max_inventory;
+        self.requote(Side::Buy, room_to_buy.then_some(bid), operations);
+        self.requote(Side::Sell, room_to_sell.then_some(ask), operations);
+        self.step += 1;
+    }
+
+    fn on_fill(&mut self, fill: &Fill) {
+        match fill.side {
+            Side::Buy => self.inventory += fill.quantity,
+            Side::Sell => self.inventory -= fill.quantity,
+        }
+        let slot = &mut self.quotes[fill.side as usize];
+        if let Some(quote) = slot
+            && quote.order_id == fill.order_id {
+            quote.remaining -= fill.quantity;
+            if quote.remaining <= Decimal::ZERO {
+                *slot = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::run_agents;
+    use crate::engine::MatchingEngine;
+    use crate::logging::{create_logger, LoggingMode};
+    use rust_decimal_macros::dec;
+
+    fn market(bid: Decimal, ask: Decimal) -> MarketView {
+        MarketView { best_bid: Some(bid), best_ask: Some(ask), last_trade: None, imbalance: None, now: 0 }
+    }
+
+    fn quoted(maker: &mut MarketMaker, view: &MarketView) -> (Decimal, Decimal) {
+        maker.on_step(view, &mut Vec::new());
+        let (bid, ask) = maker.quotes();
+        (bid.unwrap(), ask.unwrap())
+    }
+
+    #[test]
+    fn test_inventory_skews_quotes_towards_unwinding() {
+        let view = market(dec!(99.90), dec!(100.10));
+        let mut flat = MarketMaker::new(ClientId::new("mm"), MarketMakerConfig::default());
+        let mut long = MarketMaker::new(ClientId::new("mm"), MarketMakerConfig::default());
+        long.on_fill(&Fill { order_id: Uuid::nil(), side: Side::Buy, price: dec!(100), quantity: dec!(30) });
+
+        let (flat_bid, flat_ask) = quoted(&mut flat, &view);
+        let (long_bid, long_ask) = quoted(&mut long, &view);
+        assert!(flat_bid < dec!(100) && flat_ask > dec!(100));
+        assert!(long_bid < flat_bid && long_ask < flat_ask);
+        assert_eq!(long.inventory(), dec!(30));
+    }
+
+    #[test]
+    fn test_imbalance_leans_quotes_towards_the_heavier_side() {
+        let config = MarketMakerConfig { imbalance_skew: 0.5, ..MarketMakerConfig::default() };
+        let (plain_bid, plain_ask) = quoted(&mut MarketMaker::new(ClientId::new("mm"), config.clone()), &market(dec!(99.90), dec!(100.10)));
+        let bid_heavy = MarketView { imbalance: Some(dec!(0.8)), ..market(dec!(99.90), dec!(100.10)) };
+        let (bid, ask) = quoted(&mut MarketMaker::new(ClientId::new("mm"), config), &bid_heavy);
+        assert_eq!((bid - plain_bid, ask - plain_ask), (dec!(0.40), dec!(0.40)));
+    }
+
+    #[test]
+    fn test_volatility_widens_the_spread() {
+        let calm = MarketMakerConfig { volatility: 0.01, ..MarketMakerConfig::default() };
+        let wild = MarketMakerConfig { volatility: 0.2, ..MarketMakerConfig::default() };
+        let view = market(dec!(99.90), dec!(100.10));
+        let (calm_bid, calm_ask) = quoted(&mut MarketMaker::new(ClientId::new("mm"), calm), &view);
+        let (wild_bid, wild_ask) = quoted(&mut MarketMaker::new(ClientId::new("mm"), wild), &view);
+        assert!(wild_ask - wild_bid > calm_ask - calm_bid);
+
+        let mut maker = MarketMaker::new(ClientId::new("mm"), MarketMakerConfig { volatility: 0.01, volatility_decay: 0.5, ..MarketMakerConfig::default() });
+        maker.on_step(&view, &mut Vec::new());
+        maker.on_step(&market(dec!(100.90), dec!(101.10)), &mut Vec::new());
+        assert!(maker.volatility() > 0.5);
+    }
+
+    #[test]
+    fn test_unchanged_quotes_keep_resting_and_full_inventory_stops_a_side() {
+        let config = MarketMakerConfig { max_inventory: dec!(10), volatility_decay: 0.0, ..MarketMakerConfig::default() };
+        let mut maker = MarketMaker::new(ClientId::new("mm"), config);
+        let view = market(dec!(99.90), dec!(100.10));
+        let mut operations = Vec::new();
+        maker.on_step(&view, &mut operations);
+        assert_eq!(operations.len(), 2);
+        let SimOp::New(NewOrder { order_id, price: Some(bid), side: Side::Buy, .. }) = operations[0] else { unreachable!() };
+        operations.clear();
+        maker.on_step(&view, &mut operations);
+        assert!(operations.is_empty());
+
+        maker.on_fill(&Fill { order_id, side: Side::Buy, price: bid, quantity: dec!(10) });
+        maker.on_step(&view, &mut operations);
+        assert_eq!(maker.quotes().0, None);
+        assert!(operations.iter().all(|operation| !matches!(operation, SimOp::New(NewOrder { side: Side::Buy, .. }))));
+    }
+
+    /// Sends a market order every few steps, alternating sides.
+    struct Taker {
+        client: ClientId,
+        steps: usize,
+    }
+
+    impl Agent for Taker {
+        fn client(&self) -> &ClientId {
+            &self.client
+        }
+
+        fn instrument(&self) -> &str {
+            "PUMPTHIS"
+        }
+
+        fn on_step(&mut self, _market: &MarketView, operations: &mut Vec<SimOp>) {
+            self.steps += 1;
+            if self.steps.is_multiple_of(3) {
+                let side = if self.steps.is_multiple_of(2) { Side::Buy } else { Side::Sell };
+                operations.push(SimOp::New(NewOrder {
+                    order_id: Uuid::new_v4(),
+                    instrument: "PUMPTHIS".to_string(),
+                    side,
+                    quantity: dec!(4),
+                    price: None,
+                    client: None,
+                    client_order_id: None,
+                    peg: None,
+                    discretion: None,
+                    attributes: OrderAttributes::default(),
+                    expire_at: None,
+                    cancel_after: None,
+                    stop_price: None,
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn test_market_maker_provides_liquidity_in_an_agent_run() {
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut agents: Vec<Box<dyn Agent>> = vec![
+            Box::new(MarketMaker::new(ClientId::new("mm"), MarketMakerConfig::default())),
+            Box::new(Taker { client: ClientId::new("taker"), steps: 0 }),
+        ];
+
+        let run = run_agents(&mut logger, &mut engine, &mut agents, 60, &mut Vec::new()).unwrap();
+
+        assert!(run.report.trades() > 0);
+        assert!(run.operations.iter().all(|operation| operation.client().is_some()));
+        let maker = run.report.leaderboard.iter().find(|standing| standing.client == ClientId::new("mm")).unwrap();
+        assert_eq!(maker.fills.taker_fills, 0);
+        assert!(maker.net_position.abs() <= MarketMakerConfig::default().max_inventory);
+    }
+}
@@ -0,0 +1,233 @@
+//! Trading agents that generate order flow from what they see in the book,
+//! for scenarios that need participants reacting to the market rather than a
+//! fixed list of operations.
+
+pub mod flow;
+pub mod latency;
+pub mod market_maker;
+
+pub use flow::GeneratedFlow;
+pub use latency::{ClientLatency, Latency, LatencyModel};
+
+use crate::clients::ClientId;
+use crate::engine::MatchingEngine;
+use crate::logging::logger_trait::SimLogger;
+use crate::operation::SimOp;
+use crate::report::RunReport;
+use crate::simulation::{finish_run, process_operation};
+use crate::trade::Trade;
+use crate::utils::{Side, UuidMap};
+use latency::ViewHistory;
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use uuid::Uuid;
+
+/// The top of one instrument's book as an agent sees it before it acts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketView {
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+    pub last_trade: Option<Decimal>,
+    /// The book imbalance the engine last published, from -1 (only asks)
+    /// to 1 (only bids). `None` unless the engine computes it.
+    pub imbalance: Option<Decimal>,
+    /// Engine-clock nanoseconds.
+    pub now: u64,
+}
+
+impl MarketView {
+    pub fn of(engine: &MatchingEngine, instrument: &str) -> Self {
+        let book = engine.order_book(instrument);
+        MarketView {
+            best_bid: book.and_then(|book| book.best_bid()),
+            best_ask: book.and_then(|book| book.best_ask()),
+            last_trade: book.and_then(|book| book.last_trade_price()),
+            imbalance: engine.imbalance(instrument),
+            now: engine.now_nanos(),
+        }
+    }
+
+    pub fn mid(&self) -> Option<Decimal> {
+        Some((self.best_bid? + self.best_ask?) / Decimal::TWO)
+    }
+}
+
+/// One execution of an agent's order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fill {
+    pub order_id: Uuid,
+    pub side: Side,
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+/// A participant driven by [`run_agents`]. Every step the agent looks at its
+/// instrument and appends the operations it wants to send; they are applied
+/// in that order, under the agent's client id, before the next agent acts.
+pub trait Agent {
+    fn client(&self) -> &ClientId;
+
+    fn instrument(&self) -> &str;
+
+    fn on_step(&mut self, market: &MarketView, operations: &mut Vec<SimOp>);
+
+    /// Called for every fill of an order the agent sent, as it happens.
+    fn on_fill(&mut self, _fill: &Fill) {}
+}
+
+/// What an agent run produced: the usual report, and every operation the
+/// agents sent, which replays the run as a fixed scenario.
+#[derive(Debug)]
+pub struct AgentRun {
+    pub report: RunReport,
+    pub operations: Vec<SimOp>,
+}
+
+/// Lets each agent act once per step, in turn, for `steps` steps. Every
+/// agent sees the book as it is and its operations are applied at once.
+pub fn run_agents(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    agents: &mut [Box<dyn Agent>],
+    steps: usize,
+    latencies: &mut Vec<(u128, u128)>,
+) -> Result<AgentRun, Box<dyn Error>> {
+    run_agents_with(logger, engine, agents, steps, latencies, &mut LatencyModel::default())
+}
+
+/// [`run_agents`] with the clients' latency to the gateway. Agents still act
+/// in turn each step, but each sees the book its market-data latency ago,
+/// and its operations are applied once its order-entry latency has passed,
+/// in the order they arrive. Fills are reported to an agent as they happen.
+/// The operations of the run are listed in arrival order.
+pub fn run_agents_with(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    agents: &mut [Box<dyn Agent>],
+    steps: usize,
+    latencies: &mut Vec<(u128, u128)>,
+    model: &mut LatencyModel,
+) -> Result<AgentRun, Box<dyn Error>> {
+    let mut instruments: Vec<String> = agents.iter().map(|agent| agent.instrument().to_string()).collect();
+    instruments.sort();
+    instruments.dedup();
+    let mut run = Wire {
+        logger,
+        engine,
+        latencies,
+        report: RunReport::default(),
+        operations: Vec::new(),
+        owners: UuidMap::default(),
+        in_flight: BinaryHeap::new(),
+        sent: 0,
+        histories: instruments.into_iter().map(|instrument| (instrument, ViewHistory::default())).collect(),
+    };
+    model.set_time(0);
+    run.publish_views(0);
+    let mut pending = Vec::new();
+    let mut last_arrival = vec![0; agents.len()];
+
+    for step in 0..steps {
+        let now = step as u64 * model.step_nanos;
+        for index in 0..agents.len() {
+            run.deliver_until(now, agents, model);
+            model.set_time(now);
+            let client = agents[index].client().clone();
+            let seen = now.saturating_sub(model.market_data_delay(&client));
+            let mut market = run.histories[agents[index].instrument()].seen_at(seen).expect("every history starts at time 0");
+            market.now = run.engine.now_nanos();
+            agents[index].on_step(&market, &mut pending);
+            for mut operation in pending.drain(..) {
+                operation.set_client(client.clone());
+                let arrival = (now + model.order_entry_delay(&client)).max(last_arrival[index]);
+                last_arrival[index] = arrival;
+                run.in_flight.push(Reverse(InFlight { arrival, sent: run.sent, agent: index, operation }));
+                run.sent += 1;
+            }
+        }
+        let cutoff = now.saturating_sub(model.max_market_data_delay());
+        run.histories.values_mut().for_each(|history| history.prune(cutoff));
+    }
+    run.deliver_until(u64::MAX, agents, model);
+
+    let Wire { logger, engine, report, operations, .. } = run;
+    let report = finish_run(logger, engine, report)?;
+    Ok(AgentRun { report, operations })
+}
+
+/// An operation on its way to the gateway, ordered by arrival and then by
+/// when it was sent.
+#[derive(Debug)]
+struct InFlight {
+    arrival: u64,
+    sent: u64,
+    agent: usize,
+    operation: SimOp,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        (self.arrival, self.sent) == (other.arrival, other.sent)
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.arrival, self.sent).cmp(&(other.arrival, other.sent))
+    }
+}
+
+/// The state of an agent run between the agents and the engine.
+struct Wire<'a> {
+    logger: &'a mut Box<dyn SimLogger>,
+    engine: &'a mut MatchingEngine,
+    latencies: &'a mut Vec<(u128, u128)>,
+    report: RunReport,
+    operations: Vec<SimOp>,
+    owners: UuidMap<usize>,
+    in_flight: BinaryHeap<Reverse<InFlight>>,
+    sent: u64,
+    histories: HashMap<String, ViewHistory>,
+}
+
+impl Wire<'_> {
+    /// Applies every operation that has arrived by `until`.
+    fn deliver_until(&mut self, until: u64, agents: &mut [Box<dyn Agent>], model: &LatencyModel) {
+        while self.in_flight.peek().is_some_and(|Reverse(next)| next.arrival <= until) {
+            let Some(Reverse(InFlight { arrival, agent, operation, .. })) = self.in_flight.pop() else { break };
+            model.set_time(arrival);
+            if let SimOp::New(new_order) = &operation {
+                self.owners.insert(new_order.order_id, agent);
+            }
+            for trade in process_operation(self.logger, self.engine, self.operations.len(), &operation, self.latencies, &mut self.report) {
+                notify_fills(agents, &self.owners, &trade);
+            }
+            self.operations.push(operation);
+            self.publish_views(arrival);
+        }
+    }
+
+    fn publish_views(&mut self, at: u64) {
+        for (instrument, history) in &mut self.histories {
+            history.publish(at, MarketView::of(self.engine, instrument));
+        }
+    }
+}
+
+fn notify_fills(agents: &mut [Box<dyn Agent>], owners: &UuidMap<usize>, trade: &Trade) {
+    for (order_id, side) in [(trade.buy_order_id, Side::Buy), (trade.sell_order_id, Side::Sell)] {
+        if let Some(&index) = owners.get(&order_id) {
+            agents[index].on_fill(&Fill { order_id, side, price: trade.price, quantity: trade.quantity });
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::MatchingAlgorithm;
+use crate::fixed::Fixed;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+
+/// Decides which resting order at a price level an incoming order fills next.
+pub trait Allocator: Send {
+    /// The position in a level queue of `len` orders (oldest first, never
+    /// empty) of the next order to fill. `remaining(i)` is the unfilled
+    /// quantity of the order at position `i`.
+    fn pick(&mut self, len: usize, remaining: &dyn Fn(usize) -> Fixed) -> usize;
+}
+
+/// Price-time priority: always the oldest order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FifoAllocator;
+
+impl Allocator for FifoAllocator {
+    fn pick(&mut self, _len: usize, _remaining: &dyn Fn(usize) -> Fixed) -> usize {
+        0
+    }
+}
+
+/// A seeded lottery weighted by remaining size, so an order's chance of being
+/// filled next is proportional to its share of the level. The same seed and
+/// order flow always produce the same allocation.
+#[derive(Clone, Debug)]
+pub struct LotteryAllocator {
+    rng: StdRng,
+}
+
+impl LotteryAllocator {
+    pub fn new(seed: u64) -> Self {
+        LotteryAllocator { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Allocator for LotteryAllocator {
+    fn pick(&mut self, len: usize, remaining: &dyn Fn(usize) -> Fixed) -> usize {
+        let sizes: Vec<Decimal> = (0..len).map(|index| remaining(index).to_decimal()).collect();
+        let total: Decimal = sizes.iter().sum();
+        if total.is_zero() {
+            return 0;
+        }
+        let mut ticket = total * Decimal::from_f64_retain(self.rng.random::<f64>()).unwrap_or_default();
+        for (index, size) in sizes.iter().enumerate() {
+            if ticket < *size {
+                return index;
+            }
+            ticket -= size;
+        }
+        sizes.len() - 1
+    }
+}
+
+/// The allocator implementing `algorithm`.
+pub fn allocator(algorithm: MatchingAlgorithm) -> Box<dyn Allocator> {
+    match algorithm {
+        MatchingAlgorithm::Fifo => Box::new(FifoAllocator),
+        MatchingAlgorithm::Lottery { seed } => Box::new(LotteryAllocator::new(seed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_lottery_is_seeded_and_weighted_by_size() {
+        let sizes = [Fixed::from_validated(dec!(1)), Fixed::from_validated(dec!(99))];
+        let remaining = |index: usize| sizes[index];
+        let picks = |seed| {
+            let mut lottery = LotteryAllocator::new(seed);
+            (0..200).map(|_| lottery.pick(sizes.len(), &remaining)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(picks(7), picks(7));
+        let large = picks(7).iter().filter(|&&index| index == 1).count();
+        assert!(large > 180, "the larger order won only {} of 200 draws", large);
+        assert_eq!(FifoAllocator.pick(sizes.len(), &remaining), 0);
+    }
+}
@@ -0,0 +1,254 @@
+//! An audit trail of every order's life, with each event linked to the
+//! inbound operation that caused it, so a fill can be traced back to the
+//! message that took the liquidity.
+
+use crate::events::EngineEvent;
+use crate::utils::{Side, UuidMap};
+use rust_decimal::Decimal;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// Where `--audit` writes the trail and `audit <order_id>` reads it.
+pub const DEFAULT_AUDIT_PATH: &str = "output_logs/audit.csv";
+
+/// One step in an order's life.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEvent {
+    Submitted { side: Side, quantity: Decimal, price: Option<Decimal> },
+    Rejected { reason: String },
+    /// Held by a speed bump or for a batch auction until `release_at`.
+    Held { release_at: u64 },
+    /// A stop order waiting for a trade at or through `stop_price`.
+    Parked { stop_price: Decimal },
+    Rested { price: Option<Decimal>, remaining: Decimal },
+    Traded { trade_id: Uuid, price: Decimal, quantity: Decimal, aggressor: bool },
+    /// A pegged order's price was moved by the engine. There is no amend
+    /// message, so this is the only way an order changes in place.
+    Amended { old_price: Decimal, new_price: Option<Decimal> },
+    Filled,
+    /// A trade of the order was busted. The order is not reinstated.
+    Busted { trade_id: Uuid, quantity: Decimal },
+    /// The order left the book with `remaining` unfilled: an IOC or market
+    /// remainder, or a batched order that could not rest.
+    Done { remaining: Decimal },
+    Canceled { remaining: Decimal },
+    Expired { remaining: Decimal },
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let price = |price: &Option<Decimal>| price.map_or_else(|| "market".to_string(), |price| price.to_string());
+        match self {
+            AuditEvent::Submitted { side, quantity, price: limit } => write!(f, "submitted {:?} {} @ {}", side, quantity, price(limit)),
+            AuditEvent::Rejected { reason } => write!(f, "rejected: {}", reason),
+            AuditEvent::Held { release_at } => write!(f, "held until {}", release_at),
+            AuditEvent::Parked { stop_price } => write!(f, "parked until a trade reaches {}", stop_price),
+            AuditEvent::Rested { price: limit, remaining } => write!(f, "rested {} @ {}", remaining, price(limit)),
+            AuditEvent::Traded { trade_id, price, quantity, aggressor } => {
+                let role = if *aggressor { "taker" } else { "maker" };
+                write!(f, "traded {} @ {} as {} (trade {})", quantity, price, role, trade_id)
+            }
+            AuditEvent::Amended { old_price, new_price } => write!(f, "amended price {} -> {}", old_price, price(new_price)),
+            AuditEvent::Filled => write!(f, "filled"),
+            AuditEvent::Busted { trade_id, quantity } => write!(f, "trade {} of {} busted", trade_id, quantity),
+            AuditEvent::Done { remaining } => write!(f, "done with {} unfilled", remaining),
+            AuditEvent::Canceled { remaining } => write!(f, "canceled with {} open", remaining),
+            AuditEvent::Expired { remaining } => write!(f, "expired with {} open", remaining),
+        }
+    }
+}
+
+/// An event and the operation it traces back to: the inbound message that
+/// caused it, counted from 1 in arrival order. A trade traces back to the
+/// operation that submitted its aggressor, even if the order was held first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub operation: u64,
+    /// Engine-clock nanoseconds.
+    pub at: u64,
+    pub event: AuditEvent,
+}
+
+/// Every order's events, queryable by order id, and the originating
+/// operation of every trade.
+#[derive(Debug, Default)]
+pub struct AuditTrail {
+    orders: UuidMap<Vec<AuditEntry>>,
+    /// The operation that submitted each order.
+    origins: UuidMap<u64>,
+    trades: UuidMap<u64>,
+    /// Order ids in the order they were first seen, for writing.
+    seen: Vec<Uuid>,
+}
+
+impl AuditTrail {
+    /// Records what `event` means for the orders it touches. `operation` is
+    /// the inbound message being handled and `at` the engine time.
+    pub fn record(&mut self, operation: u64, at: u64, event: &EngineEvent) {
+        match event {
+            EngineEvent::OrderAccepted { order, .. } => {
+                self.origins.insert(order.order_id, operation);
+                let submitted = AuditEvent::Submitted { side: order.side, quantity: order.quantity, price: order.price };
+                self.push(order.order_id, operation, at, submitted);
+            }
+            EngineEvent::OrderRejected { order, reason, .. } => {
+                let submitted = AuditEvent::Submitted { side: order.side, quantity: order.quantity, price: order.price };
+                self.push(order.order_id, operation, at, submitted);
+                self.push(order.order_id, operation, at, AuditEvent::Rejected { reason: reason.to_string() });
+            }
+            EngineEvent::OrderRested(order) => {
+                let origin = self.origin(&order.order_id, operation);
+                self.push(order.order_id, origin, at, AuditEvent::Rested { price: order.price, remaining: order.remaining_quantity });
+            }
+            EngineEvent::Trade(trade) => {
+                let aggressor_id = match trade.taker_side {
+                    Side::Buy => trade.buy_order_id,
+                    Side::Sell => trade.sell_order_id,
+                };
+                let origin = self.origin(&aggressor_id, operation);
+                self.trades.insert(trade.trade_id, origin);
+                for order_id in [trade.buy_order_id, trade.sell_order_id] {
+                    let traded = AuditEvent::Traded {
+                        trade_id: trade.trade_id,
+                        price: trade.price,
+                        quantity: trade.quantity,
+                        aggressor: order_id == aggressor_id,
+                    };
+                    self.push(order_id, origin, at, traded);
+                }
+            }
+            EngineEvent::OrderFilled { order, .. } => {
+                let origin = self.last_operation(&order.order_id, operation);
+                let event = if order.is_filled() { AuditEvent::Filled } else { AuditEvent::Done { remaining: order.remaining_quantity } };
+                self.push(order.order_id, origin, at, event);
+            }
+            EngineEvent::OrderCanceled(order) => self.push(order.order_id, operation, at, AuditEvent::Canceled { remaining: order.remaining_quantity }),
+            EngineEvent::OrderExpired(order) => {
+                let origin = self.origin(&order.order_id, operation);
+                self.push(order.order_id, origin, at, AuditEvent::Expired { remaining: order.remaining_quantity });
+            }
+            EngineEvent::OrderRepriced { order, old_price } => {
+                self.push(order.order_id, operation, at, AuditEvent::Amended { old_price: *old_price, new_price: order.price });
+            }
+            EngineEvent::TradeBusted(trade) => {
+                for order_id in [trade.buy_order_id, trade.sell_order_id] {
+                    self.push(order_id, operation, at, AuditEvent::Busted { trade_id: trade.trade_id, quantity: trade.quantity });
+                }
+            }
+            EngineEvent::IndexPrice { .. } | EngineEvent::Imbalance { .. } => {}
+        }
+    }
+
+    /// Records an event the engine does not publish, such as holding an order.
+    pub fn push(&mut self, order_id: Uuid, operation: u64, at: u64, event: AuditEvent) {
+        let entry = AuditEntry { operation, at, event };
+        match self.orders.get_mut(&order_id) {
+            Some(entries) => entries.push(entry),
+            None => {
+                self.seen.push(order_id);
+                self.orders.insert(order_id, vec![entry]);
+            }
+        }
+    }
+
+    fn origin(&self, order_id: &Uuid, fallback: u64) -> u64 {
+        self.origins.get(order_id).copied().unwrap_or(fallback)
+    }
+
+    /// A fill completes the order's latest step, so it shares its operation.
+    fn last_operation(&self, order_id: &Uuid, fallback: u64) -> u64 {
+        self.orders.get(order_id).and_then(|entries| entries.last()).map_or(fallback, |entry| entry.operation)
+    }
+
+    /// The order's events in the order they happened.
+    pub fn story(&self, order_id: &Uuid) -> &[AuditEntry] {
+        self.orders.get(order_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The operation that submitted the aggressor of the trade.
+    pub fn trade_origin(&self, trade_id: &Uuid) -> Option<u64> {
+        self.trades.get(trade_id).copied()
+    }
+
+    pub fn orders(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Writes one `order_id,operation,at,event` line per entry, each order's
+    /// entries together in the order the orders first appeared.
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "order_id,operation,at,event")?;
+        for order_id in &self.seen {
+            for entry in self.story(order_id) {
+                writeln!(writer, "{},{},{},{}", order_id, entry.operation, entry.at, entry.event)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// Prints the life of `order_id` from a trail written by `write_csv`.
+pub fn print_story(path: impl AsRef<Path>, order_id: &Uuid) -> io::Result<()> {
+    let path = path.as_ref();
+    let reader = BufReader::new(File::open(path)?);
+    let prefix = format!("{},", order_id);
+    let mut found = false;
+    println!("--- Audit trail of {} ---", order_id);
+    println!("{:>10} {:>22}  Event", "Operation", "Engine time");
+    for line in reader.lines() {
+        let line = line?;
+        let Some(rest) = line.strip_prefix(&prefix) else {
+            continue;
+        };
+        let mut fields = rest.splitn(3, ',');
+        if let (Some(operation), Some(at), Some(event)) = (fields.next(), fields.next(), fields.next()) {
+            println!("{:>10} {:>22}  {}", operation, at, event);
+            found = true;
+        }
+    }
+    if !found {
+        println!("No events for this order in {}.", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+    use crate::trade::Trade;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_trades_trace_back_to_the_aggressor_submission() {
+        let mut trail = AuditTrail::default();
+        let mut maker = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100), dec!(5));
+        let mut taker = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100), dec!(5));
+        trail.record(1, 10, &EngineEvent::OrderAccepted { order: &maker, client: None });
+        trail.record(1, 10, &EngineEvent::OrderRested(&maker));
+        trail.record(2, 20, &EngineEvent::OrderAccepted { order: &taker, client: None });
+        trail.push(taker.order_id, 2, 20, AuditEvent::Held { release_at: 50 });
+
+        // Released while operation 3 is handled, the trade still belongs to 2.
+        let trade = Trade::new("SOFI".to_string(), dec!(100), dec!(5), taker.order_id, maker.order_id, Side::Buy);
+        trail.record(3, 50, &EngineEvent::Trade(&trade));
+        maker.remaining_quantity = dec!(0);
+        taker.remaining_quantity = dec!(0);
+        trail.record(3, 50, &EngineEvent::OrderFilled { order: &maker, execution: None });
+
+        assert_eq!(trail.trade_origin(&trade.trade_id), Some(2));
+        let operations: Vec<(u64, String)> = trail.story(&maker.order_id).iter().map(|e| (e.operation, e.event.to_string())).collect();
+        assert_eq!(operations, [
+            (1, "submitted Sell 5 @ 100".to_string()),
+            (1, "rested 5 @ 100".to_string()),
+            (2, format!("traded 5 @ 100 as maker (trade {})", trade.trade_id)),
+            (2, "filled".to_string()),
+        ]);
+        assert!(matches!(trail.story(&taker.order_id)[2].event, AuditEvent::Traded { aggressor: true, .. }));
+        assert!(trail.story(&Uuid::new_v4()).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Dynamic price bands. A band's reference is the trade-weighted average
+//! price over a rolling window of engine time, so it follows the market
+//! instead of a fixed opening price.
+
+use crate::config::{BandAction, PriceBand};
+use crate::fixed::Fixed;
+use crate::trade::Trade;
+use crate::utils::Side;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::VecDeque;
+
+/// The prices trades may execute at, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Band {
+    pub lower: Decimal,
+    pub upper: Decimal,
+    pub action: BandAction,
+}
+
+impl Band {
+    /// The edge an order on `side` may not trade through: the upper edge for
+    /// a buy, the lower for a sell.
+    pub fn edge(&self, side: Side) -> Decimal {
+        match side {
+            Side::Buy => self.upper,
+            Side::Sell => self.lower,
+        }
+    }
+
+    /// Whether `price` lies through the edge an order on `side` could trade
+    /// through. A buy below the band only posts liquidity, so it is not.
+    pub fn beyond(&self, side: Side, price: Decimal) -> bool {
+        match side {
+            Side::Buy => price > self.upper,
+            Side::Sell => price < self.lower,
+        }
+    }
+}
+
+/// The trades behind one book's band reference.
+#[derive(Debug, Clone)]
+pub struct BandReference {
+    config: PriceBand,
+    /// Time, price and quantity of the trades in the window of the latest.
+    trades: VecDeque<(u64, Decimal, Decimal)>,
+    /// The average as of the latest trade, kept once its window has passed.
+    last: Option<Decimal>,
+    /// The price of the index the band follows, which replaces the trades
+    /// once the index has a price.
+    index: Option<Decimal>,
+    widened_until: u64,
+}
+
+impl BandReference {
+    pub fn new(config: PriceBand) -> Self {
+        Self { config, trades: VecDeque::new(), last: None, index: None, widened_until: 0 }
+    }
+
+    pub fn record(&mut self, trades: &[Trade]) {
+        let Some(latest) = trades.iter().map(|trade| trade.timestamp).max() else { return };
+        self.trades.extend(trades.iter().map(|trade| (trade.timestamp, trade.price, trade.quantity)));
+        let window = self.config.window.as_nanos() as u64;
+        while self.trades.front().is_some_and(|&(at, _, _)| at.saturating_add(window) < latest) {
+            self.trades.pop_front();
+        }
+        self.last = average(self.trades.iter());
+    }
+
+    /// Widens the band for one window from `now`, while the reference
+    /// catches up with where the market reopens.
+    pub fn widen(&mut self, now: u64) {
+        self.widened_until = now.saturating_add(self.config.window.as_nanos() as u64);
+    }
+
+    /// Centres the band on `price`, the latest price of its index.
+    pub fn anchor(&mut self, price: Decimal) {
+        self.index = Some(price);
+    }
+
+    /// The index price, if the band follows one. Otherwise the trade-weighted
+    /// average price of the window ending at `now`, or the last one while no
+    /// trade is that recent. `None` before the first trade.
+    pub fn reference(&self, now: u64) -> Option<Decimal> {
+        if self.index.is_some() {
+            return self.index;
+        }
+        let window = self.config.window.as_nanos() as u64;
+        average(self.trades.iter().filter(|&&(at, _, _)| at.saturating_add(window) >= now)).or(self.last)
+    }
+
+    /// The band in force at `now`, with its edges rounded inwards to `tick`.
+    pub fn band(&self, now: u64, tick: Option<Decimal>) -> Option<Band> {
+        let reference = self.reference(now)?;
+        let width = if now < self.widened_until { self.config.width * self.config.halt_widening } else { self.config.width };
+        let round = |price: Decimal, strategy: RoundingStrategy| match tick {
+            Some(tick) => (price / tick).round_dp_with_strategy(0, strategy) * tick,
+            None => price.round_dp_with_strategy(Fixed::SCALE, strategy),
+        };
+        Some(Band {
+            lower: round(reference * (Decimal::ONE - width), RoundingStrategy::ToPositiveInfinity),
+            upper: round(reference * (Decimal::ONE + width), RoundingStrategy::ToNegativeInfinity),
+            action: self.config.action,
+        })
+    }
+}
+
+fn average<'a>(trades: impl Iterator<Item = &'a (u64, Decimal, Decimal)>) -> Option<Decimal> {
+    let (notional, quantity) = trades.fold((Decimal::ZERO, Decimal::ZERO), |(notional, quantity), &(_, price, size)| (notional + price * size, quantity + size));
+    (!quantity.is_zero()).then(|| notional / quantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn trade(price: Decimal, quantity: Decimal, timestamp: u64) -> Trade {
+        Trade { timestamp, ..Trade::new("SOFI".to_string(), price, quantity, Uuid::new_v4(), Uuid::new_v4(), Side::Buy) }
+    }
+
+    #[test]
+    fn test_reference_is_the_trade_weighted_average_of_the_window() {
+        let config = PriceBand { window: Duration::from_nanos(100), ..PriceBand::new(dec!(0.1)) };
+        let mut reference = BandReference::new(config);
+        assert_eq!(reference.band(0, None), None);
+
+        reference.record(&[trade(dec!(100), dec!(1), 0), trade(dec!(104), dec!(3), 50)]);
+        assert_eq!(reference.reference(60), Some(dec!(103)));
+        // The first trade has left the window.
+        assert_eq!(reference.reference(120), Some(dec!(104)));
+        // With no trade in the window, the last average stands.
+        assert_eq!(reference.reference(1_000), Some(dec!(103)));
+
+        reference.record(&[trade(dec!(110), dec!(1), 1_000)]);
+        let band = reference.band(1_000, Some(dec!(0.5))).unwrap();
+        assert_eq!((band.lower, band.upper), (dec!(99), dec!(121)));
+
+        reference.widen(1_000);
+        let widened = reference.band(1_050, None).unwrap();
+        assert_eq!((widened.lower, widened.upper), (dec!(88), dec!(132)));
+        assert_eq!(reference.band(1_101, None).unwrap().upper, dec!(121));
+
+        reference.anchor(dec!(50));
+        assert_eq!(reference.band(1_101, None).map(|band| (band.lower, band.upper)), Some((dec!(45), dec!(55))));
+    }
+}
@@ -0,0 +1,152 @@
+use exchange_matching_engine::engine::MatchingEngine;
+use exchange_matching_engine::generator::{Generator, GeneratorConfig};
+use exchange_matching_engine::logging::{create_logger, LoggingMode};
+use exchange_matching_engine::operation::{load_operations, NewOrder, ParsePolicy, SimOp};
+use exchange_matching_engine::order::OrderAttributes;
+use exchange_matching_engine::simulation::run_simulation;
+use exchange_matching_engine::utils::{percentile, OrderType, Side};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+use std::time::Instant;
+use uuid::Uuid;
+
+const DEFAULT_PATH: &str = "operations.csv";
+const DEFAULT_RUNS: usize = 5;
+const DEFAULT_GENERATED_OPS: usize = 100_000;
+const CANCEL_SHARE: f64 = 0.3;
+const LEVELS_PER_SIDE: i64 = 10;
+const USAGE: &str = "Usage: book_bench [operations.csv] [runs] | book_bench <--generate|--cancel-heavy> [operations] [runs]";
+
+struct RunResult {
+    mean: f64,
+    /// Mean latency of limit orders, market orders and cancels.
+    by_kind: [f64; 3],
+    median: u128,
+    p99: u128,
+    p999: u128,
+    total_ns: u128,
+}
+
+/// Replays the workload through a fresh engine with logging disabled, timing
+/// the matching work of every operation.
+fn bench_run(operations: &[SimOp]) -> Result<RunResult, Box<dyn std::error::Error>> {
+    let mut engine = MatchingEngine::builder().auto_create_markets(true).build()?;
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let mut latencies = Vec::with_capacity(operations.len());
+
+    let run_start = Instant::now();
+    run_simulation(&mut logger, &mut engine, operations, &mut latencies)?;
+    let total_ns = run_start.elapsed().as_nanos();
+
+    let mut engine_latencies: Vec<u128> = latencies.iter().map(|(total, log)| total.saturating_sub(*log)).collect();
+    let mut kinds = [(0u128, 0u128); 3];
+    for (operation, latency) in operations.iter().zip(&engine_latencies) {
+        let kind = match operation {
+            SimOp::New(order) if order.order_type() == OrderType::Limit => 0,
+            SimOp::New(_) => 1,
+            SimOp::Cancel { .. } | SimOp::CancelAll { .. } => 2,
+        };
+        kinds[kind].0 += latency;
+        kinds[kind].1 += 1;
+    }
+    engine_latencies.sort_unstable();
+    let sum: u128 = engine_latencies.iter().sum();
+    Ok(RunResult {
+        mean: sum as f64 / engine_latencies.len() as f64,
+        by_kind: kinds.map(|(sum, count)| if count == 0 { 0.0 } else { sum as f64 / count as f64 }),
+        median: engine_latencies[engine_latencies.len() / 2],
+        p99: percentile(&engine_latencies, 0.99),
+        p999: percentile(&engine_latencies, 0.999),
+        total_ns,
+    })
+}
+
+/// A seeded workload that builds deep levels and cancels from the middle of
+/// them: passive limit orders on a few prices per side, never crossing, with
+/// `CANCEL_SHARE` of operations canceling a random live order.
+fn cancel_heavy_workload(count: usize) -> Vec<SimOp> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut live: Vec<Uuid> = Vec::new();
+    let mut operations = Vec::with_capacity(count);
+    for _ in 0..count {
+        if !live.is_empty() && rng.random_bool(CANCEL_SHARE) {
+            let order_id = live.swap_remove(rng.random_range(0..live.len()));
+            operations.push(SimOp::Cancel { instrument: "BENCH".to_string(), order_id, client: None });
+            continue;
+        }
+        let side = if rng.random_bool(0.5) { Side::Buy } else { Side::Sell };
+        let ticks = rng.random_range(0..LEVELS_PER_SIDE);
+        let price = match side {
+            Side::Buy => Decimal::new(10_000 - 5 * ticks, 2),
+            Side::Sell => Decimal::new(10_005 + 5 * ticks, 2),
+        };
+        let order_id = Uuid::new_v4();
+        live.push(order_id);
+        operations.push(SimOp::New(NewOrder {
+            order_id,
+            instrument: "BENCH".to_string(),
+            side,
+            quantity: Decimal::from(rng.random_range(1..=100)),
+            price: Some(price),
+            client: None,
+            client_order_id: None,
+            peg: None,
+            discretion: None,
+            attributes: OrderAttributes::default(),
+            expire_at: None,
+            cancel_after: None,
+            stop_price: None,
+        }));
+    }
+    operations
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let workload = args.first().filter(|arg| *arg == "--generate" || *arg == "--cancel-heavy").cloned();
+    let args = if workload.is_some() { &args[1..] } else { &args[..] };
+    let runs = match args.get(1) {
+        Some(n) => n.parse::<usize>().map_err(|_| USAGE)?,
+        None => DEFAULT_RUNS,
+    };
+    if runs == 0 {
+        return Err("Number of runs must be positive".into());
+    }
+
+    let count = match args.first() {
+        Some(n) if workload.is_some() => n.parse::<usize>().map_err(|_| USAGE)?,
+        _ => DEFAULT_GENERATED_OPS,
+    };
+    let (operations, path) = match workload.as_deref() {
+        Some("--generate") => {
+            let config = GeneratorConfig { operations: count, seed: 42, ..Default::default() };
+            (Generator::new(config)?.collect(), "the generator's default flow")
+        }
+        Some(_) => (cancel_heavy_workload(count), "a cancel-heavy workload"),
+        None => {
+            let path = args.first().map(String::as_str).unwrap_or(DEFAULT_PATH);
+            (load_operations(path, ParsePolicy::Strict)?.operations, path)
+        }
+    };
+    if operations.is_empty() {
+        return Err(format!("No operations in {}", path).into());
+    }
+    let results = (0..runs).map(|_| bench_run(&operations)).collect::<Result<Vec<_>, _>>()?;
+
+    println!("\n--- Matching Engine ({} operations from {}, nanoseconds) ---", operations.len(), path);
+    println!(
+        "{:<6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>14}",
+        "Run", "Mean", "Limit", "Market", "Cancel", "Median", "p99", "p99.9", "Total"
+    );
+    for (run, r) in results.iter().enumerate() {
+        let [limit, market, cancel] = r.by_kind;
+        println!(
+            "{:<6} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10} {:>10} {:>10} {:>14}",
+            run + 1, r.mean, limit, market, cancel, r.median, r.p99, r.p999, r.total_ns
+        );
+    }
+    println!("Latencies exclude logging; Total is the wall time of the whole run.");
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use exchange_matching_engine::comparison::{compare, print_comparison, RunRecord, DEFAULT_REGRESSION_THRESHOLD};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: compare_runs <baseline.json> <candidate.json> [--threshold <percent>]";
+
+/// Compares two runs saved with `--save-run` and fails if any latency
+/// percentile or the elapsed time got worse by more than the threshold.
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut paths = Vec::new();
+    let mut threshold = DEFAULT_REGRESSION_THRESHOLD;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--threshold" => {
+                let value = iter.next().ok_or(USAGE)?;
+                threshold = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|percent| percent.is_finite() && *percent >= 0.0)
+                    .ok_or_else(|| format!("Invalid threshold '{}'", value))?;
+            }
+            path => paths.push(path),
+        }
+    }
+    let [baseline, candidate] = paths[..] else {
+        return Err(USAGE.into());
+    };
+
+    let baseline_run = RunRecord::load(baseline)?;
+    let candidate_run = RunRecord::load(candidate)?;
+    println!("\n--- {} ({}) vs {} ({}), threshold {}% ---", baseline, baseline_run.modes, candidate, candidate_run.modes, threshold);
+    if baseline_run.processing.count != candidate_run.processing.count {
+        println!("Warning: the runs recorded {} and {} operations.", baseline_run.processing.count, candidate_run.processing.count);
+    }
+    if print_comparison(&compare(&baseline_run, &candidate_run), threshold) {
+        println!("No regression beyond {}%.", threshold);
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("Regression beyond {}%.", threshold);
+        Ok(ExitCode::FAILURE)
+    }
+}
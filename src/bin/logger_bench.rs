@@ -0,0 +1,126 @@
+use exchange_matching_engine::logging::{create_logger, LoggingMode, SimLogger};
+use exchange_matching_engine::memory::alloc_counters;
+use exchange_matching_engine::order::Order;
+use exchange_matching_engine::trade::{Execution, Trade};
+use exchange_matching_engine::utils::{percentile, Side};
+use rust_decimal_macros::dec;
+use std::fs;
+use std::str::FromStr;
+use std::time::Instant;
+use uuid::Uuid;
+
+const DEFAULT_EVENTS: usize = 1_000_000;
+const DEFAULT_MODES: &[LoggingMode] = &[
+    LoggingMode::Baseline,
+    LoggingMode::AsyncEnum,
+    LoggingMode::AsyncClosure,
+    LoggingMode::AsyncString,
+    LoggingMode::AsyncBatch,
+    LoggingMode::BufferedFileWrite,
+    LoggingMode::DirectFileWrite,
+    LoggingMode::NaiveFileWrite,
+    LoggingMode::TracingFile,
+];
+
+struct BenchResult {
+    mode: LoggingMode,
+    mean: f64,
+    median: u128,
+    p99: u128,
+    p999: u128,
+    total_ns: u128,
+    drain_ns: u128,
+    /// Allocations per event by the logger and its background thread, with
+    /// the `alloc-stats` feature.
+    allocations: Option<f64>,
+}
+
+/// Drives a logger with the same four-event cycle (submission, trade, fill,
+/// cancel) with no engine involved, timing every call individually.
+fn bench_logger(mode: LoggingMode, events: usize) -> BenchResult {
+    let mut logger: Box<dyn SimLogger> = create_logger(mode);
+
+    let order = Order::new_limit(Uuid::new_v4(), "PUMPTHIS".to_string(), Side::Buy, dec!(100.25), dec!(50));
+    let mut filled = order.clone();
+    filled.fill(dec!(50));
+    let trade = Trade::new("PUMPTHIS".to_string(), dec!(100.25), dec!(50), order.order_id, Uuid::new_v4(), Side::Sell);
+
+    let mut latencies: Vec<u128> = Vec::with_capacity(events);
+    let allocations_before = alloc_counters();
+    let run_start = Instant::now();
+    for i in 0..events {
+        let start = Instant::now();
+        match i % 4 {
+            0 => logger.log_order_submission(&order),
+            1 => logger.log_trade(&trade),
+            2 => logger.log_order_filled(&filled, Some(Execution::of(&trade))),
+            _ => logger.log_order_cancel(&order.order_id, true),
+        }
+        latencies.push(start.elapsed().as_nanos());
+    }
+    let total_ns = run_start.elapsed().as_nanos();
+
+    let drain_start = Instant::now();
+    let stats = logger.finalize();
+    let drain_ns = drain_start.elapsed().as_nanos();
+    // Whatever finalize returns is not the logger's per-event work.
+    let allocations = alloc_counters()
+        .zip(allocations_before)
+        .map(|(after, before)| (after.allocations - before.allocations).saturating_sub(stats.capacity() as u64 + 1) as f64 / events as f64);
+
+    latencies.sort_unstable();
+    let sum: u128 = latencies.iter().sum();
+
+    BenchResult {
+        mode,
+        mean: sum as f64 / events as f64,
+        median: latencies[events / 2],
+        p99: percentile(&latencies, 0.99),
+        p999: percentile(&latencies, 0.999),
+        total_ns,
+        drain_ns,
+        allocations,
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all("output_logs")?;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let events = match args.first() {
+        Some(n) => n.parse::<usize>().map_err(|_| "Usage: logger_bench [events] [logging_mode...]")?,
+        None => DEFAULT_EVENTS,
+    };
+    if events == 0 {
+        return Err("Number of events must be positive".into());
+    }
+    let modes = if args.len() > 1 {
+        args[1..]
+            .iter()
+            .map(|m| LoggingMode::from_str(m))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        DEFAULT_MODES.to_vec()
+    };
+
+    let results: Vec<BenchResult> = modes.iter().map(|mode| bench_logger(*mode, events)).collect();
+
+    println!("\n--- Logger Overhead ({} events, nanoseconds) ---", events);
+    println!(
+        "{:<20} {:>10} {:>10} {:>10} {:>10} {:>14} {:>14} {:>13}",
+        "Mode", "Mean", "Median", "p99", "p99.9", "Total", "Drain", "Allocs/event"
+    );
+    for r in &results {
+        let allocations = r.allocations.map_or("-".to_string(), |allocations| format!("{:.3}", allocations));
+        println!(
+            "{:<20} {:>10.2} {:>10} {:>10} {:>10} {:>14} {:>14} {:>13}",
+            format!("{:?}", r.mode), r.mean, r.median, r.p99, r.p999, r.total_ns, r.drain_ns, allocations
+        );
+    }
+    println!("Total is the producer-side time for all events; Drain is the time finalize spends emptying the queue.");
+    if results.iter().all(|r| r.allocations.is_none()) {
+        println!("Build with --features alloc-stats to count allocations per event.");
+    }
+
+    Ok(())
+}
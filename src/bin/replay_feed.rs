@@ -0,0 +1,28 @@
+use exchange_matching_engine::market_data::replay::{FeedReplayer, Pace};
+use exchange_matching_engine::market_data::FeedWriter;
+use std::io::{self, BufWriter};
+use std::str::FromStr;
+
+const USAGE: &str = "Usage: replay_feed <recording.csv> [--pace <max|original|<factor>x>]";
+
+/// Replays a feed recorded with `--feed` to stdout, at the recorded pace or
+/// faster, as a stand-in feed for developing a feed handler.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut path = None;
+    let mut pace = Pace::Original;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--pace" => pace = Pace::from_str(iter.next().ok_or(USAGE)?)?,
+            value if path.is_none() => path = Some(value),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let stdout = FeedWriter::new(BufWriter::new(io::stdout()));
+    let stats = FeedReplayer::open(path).map_err(|e| format!("{}: {}", path, e))?.pace(pace).subscribe(Box::new(stdout)).run()?;
+    eprintln!("Replayed {} messages in {:.2?} ({} sequence gaps in the recording)", stats.messages, stats.elapsed, stats.sequence_gaps);
+    Ok(())
+}
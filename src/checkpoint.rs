@@ -0,0 +1,131 @@
+//! Periodic checkpoints of a long simulation, so an interrupted run can be
+//! resumed. The operations file is the write-ahead log: a checkpoint is a
+//! book snapshot plus the number of operations it covers, and resuming
+//! restores the books, with their trade numbering, and replays the file
+//! from that offset.
+
+use crate::engine::MatchingEngine;
+use crate::snapshot::{load_snapshot, save_snapshot};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Where `--checkpoint-every` writes checkpoints and `--resume` reads them.
+pub const DEFAULT_CHECKPOINT_DIR: &str = "output_logs/checkpoint";
+
+/// Operations between checkpoints when `--resume` is given without
+/// `--checkpoint-every`.
+pub const DEFAULT_CHECKPOINT_INTERVAL: usize = 10_000;
+
+const STATE_FILE: &str = "checkpoint.json";
+const BOOK_FILE: &str = "book.csv";
+const LATENCY_FILE: &str = "latencies.csv";
+
+/// What the last checkpoint covers. Written last, so a run interrupted while
+/// checkpointing resumes from the previous one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointState {
+    /// Operations applied before the snapshot was taken.
+    pub operations: usize,
+    /// Latency samples recorded by then. Later rows in the latency file are
+    /// from after the checkpoint and are dropped on resume.
+    pub latency_rows: usize,
+}
+
+/// Writes a checkpoint every `every` operations into `dir`.
+#[derive(Debug)]
+pub struct Checkpointer {
+    dir: PathBuf,
+    every: usize,
+    state: CheckpointState,
+}
+
+impl Checkpointer {
+    /// Starts a fresh run, discarding any checkpoint already in `dir`.
+    pub fn new(dir: impl Into<PathBuf>, every: usize) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        for file in [STATE_FILE, BOOK_FILE, LATENCY_FILE] {
+            let path = dir.join(file);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(Checkpointer { dir, every: every.max(1), state: CheckpointState::default() })
+    }
+
+    /// Restores the books of the last checkpoint in `dir` into `engine` and
+    /// its latency samples into `latencies`, and continues checkpointing
+    /// there.
+    pub fn resume(dir: impl Into<PathBuf>, every: usize, engine: &mut MatchingEngine, latencies: &mut Vec<(u128, u128)>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.into();
+        let state_path = dir.join(STATE_FILE);
+        let file = File::open(&state_path).map_err(|e| format!("No checkpoint to resume at {}: {}", state_path.display(), e))?;
+        let state: CheckpointState = serde_json::from_reader(BufReader::new(file))?;
+
+        load_snapshot(path_str(&dir.join(BOOK_FILE))?, engine)?;
+        let restored = read_latencies(&dir.join(LATENCY_FILE), state.latency_rows)?;
+        // Drop samples written after the checkpoint so the file matches it again.
+        write_latencies(&dir.join(LATENCY_FILE), &restored, false)?;
+        latencies.extend(restored);
+        Ok(Checkpointer { dir, every: every.max(1), state })
+    }
+
+    /// Operations the last checkpoint covers, which a resumed run skips.
+    pub fn completed(&self) -> usize {
+        self.state.operations
+    }
+
+    /// Checkpoints if `operations` completed operations is a multiple of the
+    /// interval.
+    pub fn after_operation(&mut self, operations: usize, engine: &MatchingEngine, latencies: &[(u128, u128)]) -> Result<(), Box<dyn Error>> {
+        if operations.is_multiple_of(self.every) && operations > self.state.operations {
+            self.checkpoint(operations, engine, latencies)?;
+        }
+        Ok(())
+    }
+
+    /// Appends the latencies recorded since the last checkpoint, snapshots
+    /// the books, then records the new state.
+    pub fn checkpoint(&mut self, operations: usize, engine: &MatchingEngine, latencies: &[(u128, u128)]) -> Result<(), Box<dyn Error>> {
+        write_latencies(&self.dir.join(LATENCY_FILE), &latencies[self.state.latency_rows.min(latencies.len())..], true)?;
+        save_snapshot(path_str(&self.dir.join(BOOK_FILE))?, &engine.instruments(), engine)?;
+
+        let state = CheckpointState { operations, latency_rows: latencies.len() };
+        let staging = self.dir.join(format!("{}.tmp", STATE_FILE));
+        serde_json::to_writer(BufWriter::new(File::create(&staging)?), &state)?;
+        fs::rename(staging, self.dir.join(STATE_FILE))?;
+        self.state = state;
+        Ok(())
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str, Box<dyn Error>> {
+    path.to_str().ok_or_else(|| format!("Checkpoint path {} is not valid UTF-8", path.display()).into())
+}
+
+fn write_latencies(path: &Path, latencies: &[(u128, u128)], append: bool) -> Result<(), Box<dyn Error>> {
+    let file = OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path)?;
+    let mut writer = BufWriter::new(file);
+    for (process, log) in latencies {
+        writeln!(writer, "{},{}", process, log)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_latencies(path: &Path, rows: usize) -> Result<Vec<(u128, u128)>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut latencies = Vec::with_capacity(rows);
+    for line in reader.lines().take(rows) {
+        let line = line?;
+        let (process, log) = line.split_once(',').ok_or_else(|| format!("Invalid latency row '{}'", line))?;
+        latencies.push((process.parse()?, log.parse()?));
+    }
+    if latencies.len() < rows {
+        return Err(format!("The checkpoint has {} latency rows, expected {}", latencies.len(), rows).into());
+    }
+    Ok(latencies)
+}
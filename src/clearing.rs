@@ -0,0 +1,248 @@
+//! Post-trade clearing: nets each client's fills over the session into what
+//! it must deliver or receive per instrument and one cash amount, after
+//! exchange fees.
+
+use crate::clients::ClientId;
+use crate::options::{Exercise, OptionExpiry};
+use crate::trade::Trade;
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Fees as fractions of notional, charged per fill. The maker side of a
+/// fill pays `maker`, the aggressor `taker`; a negative maker fee is a
+/// rebate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+impl FromStr for FeeSchedule {
+    type Err = String;
+
+    /// Parses `<maker>:<taker>`, e.g. `-0.0001:0.0003`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid fee schedule '{}', expected <maker>:<taker> as fractions of notional", s);
+        let (maker, taker) = s.split_once(':').ok_or_else(invalid)?;
+        let maker = Decimal::from_str(maker).map_err(|_| invalid())?;
+        let taker = Decimal::from_str(taker).map_err(|_| invalid())?;
+        if maker.abs() >= Decimal::ONE || taker.abs() >= Decimal::ONE {
+            return Err(invalid());
+        }
+        if maker + taker < Decimal::ZERO {
+            return Err(format!("Fee schedule '{}' pays out more in rebates than it charges", s));
+        }
+        Ok(FeeSchedule { maker, taker })
+    }
+}
+
+/// One client's fills in one instrument, netted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Account {
+    quantity: Decimal,
+    cash: Decimal,
+    maker_notional: Decimal,
+    taker_notional: Decimal,
+    fills: usize,
+}
+
+/// Collects the fills of client orders through a session.
+#[derive(Debug, Default)]
+pub struct ClearingHouse {
+    accounts: BTreeMap<ClientId, BTreeMap<String, Account>>,
+}
+
+impl ClearingHouse {
+    /// Books both sides of `trade` that `owner` attributes to a client.
+    pub fn record_trade<'a>(&mut self, trade: &Trade, owner: impl Fn(&Uuid) -> Option<&'a ClientId>) {
+        self.book_trade(trade, owner, false);
+    }
+
+    /// Takes a busted trade back out of the accounts it was booked to, so
+    /// it is neither settled nor charged fees.
+    pub fn bust<'a>(&mut self, trade: &Trade, owner: impl Fn(&Uuid) -> Option<&'a ClientId>) {
+        self.book_trade(trade, owner, true);
+    }
+
+    fn book_trade<'a>(&mut self, trade: &Trade, owner: impl Fn(&Uuid) -> Option<&'a ClientId>, reverse: bool) {
+        let (quantity, notional) = match reverse {
+            false => (trade.quantity, trade.price * trade.quantity),
+            true => (-trade.quantity, -trade.price * trade.quantity),
+        };
+        for (order_id, side) in [(trade.buy_order_id, Side::Buy), (trade.sell_order_id, Side::Sell)] {
+            let Some(client) = owner(&order_id) else { continue };
+            let account = self.accounts.entry(client.clone()).or_default().entry(trade.instrument.clone()).or_default();
+            match side {
+                Side::Buy => {
+                    account.quantity += quantity;
+                    account.cash -= notional;
+                }
+                Side::Sell => {
+                    account.quantity -= quantity;
+                    account.cash += notional;
+                }
+            }
+            if trade.aggressor_order_id() == order_id {
+                account.taker_notional += notional;
+            } else {
+                account.maker_notional += notional;
+            }
+            account.fills = if reverse { account.fills.saturating_sub(1) } else { account.fills + 1 };
+        }
+    }
+
+    /// Adds funding `client` received (positive) or paid on `instrument` to
+    /// its cash.
+    pub fn record_funding(&mut self, client: &ClientId, instrument: &str, amount: Decimal) {
+        self.accounts.entry(client.clone()).or_default().entry(instrument.to_string()).or_default().cash += amount;
+    }
+
+    /// Cancels the deliveries of the expired option and books its exercises
+    /// and assignments in the underlying.
+    pub fn record_exercises(&mut self, expiry: &OptionExpiry, exercises: &[Exercise]) {
+        for accounts in self.accounts.values_mut() {
+            if let Some(account) = accounts.get_mut(&expiry.instrument) {
+                account.quantity = Decimal::ZERO;
+            }
+        }
+        for exercise in exercises {
+            let account = self.accounts.entry(exercise.client.clone()).or_default().entry(exercise.underlying.clone()).or_default();
+            account.quantity += exercise.quantity;
+            account.cash += exercise.cash();
+        }
+    }
+
+    /// The end-of-session obligations under `fees`.
+    pub fn settle(&self, fees: &FeeSchedule) -> SettlementReport {
+        let clients = self
+            .accounts
+            .iter()
+            .map(|(client, accounts)| {
+                let mut settlement = ClientSettlement::default();
+                for (instrument, account) in accounts {
+                    if !account.quantity.is_zero() {
+                        settlement.deliveries.insert(instrument.clone(), account.quantity);
+                    }
+                    settlement.gross_cash += account.cash;
+                    settlement.fees += account.maker_notional * fees.maker + account.taker_notional * fees.taker;
+                    settlement.fills += account.fills;
+                }
+                (client.clone(), settlement)
+            })
+            .collect();
+        SettlementReport { fees: *fees, clients }
+    }
+}
+
+/// What one client settles at the end of the session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientSettlement {
+    /// Net quantity per instrument: positive to receive, negative to
+    /// deliver. Instruments the client traded flat are left out.
+    pub deliveries: BTreeMap<String, Decimal>,
+    /// Cash from the fills, perpetual funding and option exercises: positive
+    /// to receive, negative to pay.
+    pub gross_cash: Decimal,
+    /// Fees charged, negative when rebates outweigh them.
+    pub fees: Decimal,
+    pub fills: usize,
+}
+
+impl ClientSettlement {
+    /// The cash the client receives (positive) or pays (negative) after fees.
+    pub fn net_cash(&self) -> Decimal {
+        self.gross_cash - self.fees
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettlementReport {
+    pub fees: FeeSchedule,
+    pub clients: BTreeMap<ClientId, ClientSettlement>,
+}
+
+impl SettlementReport {
+    /// Fees the exchange keeps, net of rebates.
+    pub fn fees_collected(&self) -> Decimal {
+        self.clients.values().map(|settlement| settlement.fees).sum()
+    }
+
+    /// One row per client and instrument to deliver, then one per client for
+    /// cash.
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "client,asset,amount,fees")?;
+        for (client, settlement) in &self.clients {
+            for (instrument, quantity) in &settlement.deliveries {
+                writeln!(writer, "{},{},{},", client, instrument, quantity)?;
+            }
+            writeln!(writer, "{},CASH,{},{}", client, settlement.net_cash(), settlement.fees)?;
+        }
+        writer.flush()
+    }
+
+    pub fn print(&self) {
+        if self.clients.is_empty() {
+            return;
+        }
+        println!("\n--- Settlement (maker {}, taker {}) ---", self.fees.maker, self.fees.taker);
+        println!("{:<16} {:>8} {:>16} {:>14} {:>16}  Deliveries", "Client", "Fills", "Gross cash", "Fees", "Net cash");
+        for (client, settlement) in &self.clients {
+            let deliveries: Vec<String> = settlement.deliveries.iter().map(|(instrument, quantity)| format!("{} {:+}", instrument, quantity)).collect();
+            println!(
+                "{:<16} {:>8} {:>16} {:>14} {:>16}  {}",
+                client.to_string(),
+                settlement.fills,
+                settlement.gross_cash,
+                settlement.fees.round_dp(4),
+                settlement.net_cash().round_dp(4),
+                if deliveries.is_empty() { "-".to_string() } else { deliveries.join(", ") }
+            );
+        }
+        println!("Fees collected: {}", self.fees_collected().round_dp(4));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_fee_schedule() {
+        assert_eq!("-0.0001:0.0003".parse::<FeeSchedule>(), Ok(FeeSchedule { maker: dec!(-0.0001), taker: dec!(0.0003) }));
+        assert!("0.001".parse::<FeeSchedule>().is_err());
+        assert!("-0.002:0.001".parse::<FeeSchedule>().is_err());
+        assert!("0:2".parse::<FeeSchedule>().is_err());
+    }
+
+    #[test]
+    fn test_fills_net_into_deliveries_and_cash_after_fees() {
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+        let (ask, lift, bid, hit) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let owners = HashMap::from([(ask, alice.clone()), (bid, alice.clone()), (lift, bob.clone()), (hit, bob.clone())]);
+        let mut clearing = ClearingHouse::default();
+
+        // Bob lifts Alice's offer for 10 @ 100, then sells 4 back into her bid at 99.
+        clearing.record_trade(&Trade::new("SOFI".to_string(), dec!(100), dec!(10), lift, ask, Side::Buy), |id| owners.get(id));
+        clearing.record_trade(&Trade::new("SOFI".to_string(), dec!(99), dec!(4), bid, hit, Side::Sell), |id| owners.get(id));
+        clearing.record_trade(&Trade::new("NVO".to_string(), dec!(50), dec!(2), Uuid::new_v4(), Uuid::new_v4(), Side::Buy), |id| owners.get(id));
+
+        let report = clearing.settle(&FeeSchedule { maker: dec!(-0.001), taker: dec!(0.002) });
+        let bob_settles = &report.clients[&bob];
+        assert_eq!(bob_settles.deliveries, BTreeMap::from([("SOFI".to_string(), dec!(6))]));
+        assert_eq!(bob_settles.gross_cash, dec!(-604));
+        // Taker on 1000 and 396 of notional.
+        assert_eq!(bob_settles.fees, dec!(2.792));
+        assert_eq!(bob_settles.net_cash(), dec!(-606.792));
+        assert_eq!(report.clients[&alice].fees, dec!(-1.396));
+        assert_eq!(report.fees_collected(), dec!(1.396));
+    }
+}
@@ -0,0 +1,817 @@
+use crate::affinity::parse_core_list;
+use crate::clearing::FeeSchedule;
+use crate::config::{CapacityLimits, MatchingAlgorithm, OptionContract, Perpetual, Precision, PriceBand, PriceCollar};
+use crate::ids::TradeIdStrategy;
+use crate::index::IndexDefinition;
+use crate::control::{Breakpoint, ControlInput};
+use crate::export::lobster::DEFAULT_LOBSTER_LEVELS;
+use crate::governor::RateLimit;
+use crate::import::ImportSpec;
+use crate::market_data::stream::StreamTarget;
+use crate::margin::MarginRequirement;
+use crate::market_data::{FeedKind, FeedMode, FeedSpec};
+use crate::logging::types::{EventFilter, LoggerOptions, LoggingMode, WaitStrategy};
+use crate::messaging::OtrPolicy;
+use crate::operation::ParsePolicy;
+use crate::positions::DEFAULT_LEADERBOARD_SIZE;
+use crate::source::Endpoint;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const USAGE: &str = concat!(
+    "Usage: cargo run <logging_mode>[+<logging_mode>...] [options]\n",
+    "  [--config <path>]  reads options from a file, one <option> [= <value>] per line\n",
+    "Logging:\n",
+    "  [--log-events <types>] [--log-timings] [--pin-engine <core>] [--pin-loggers <core,...>]\n",
+    "  [--wait-strategy <block|yield|spin>] [--flush-every <events>] [--flush-interval <micros>]\n",
+    "Markets:\n",
+    "  [--price-collar <fraction>] [--price-band <width>[:<window_ms>][:reject|:slice]]\n",
+    "  [--precision <price_decimals>:<quantity_decimals>[:reject|:half-even|:half-up|:down|:up]] [--price-grouping <bucket>]\n",
+    "  [--perpetual <index_price>:<interval_ms>[:<rate_cap>]] [--option <instrument>=<call|put>:<underlying>:<strike>[:<expiry_ns>]]...\n",
+    "  [--index <name>=<instrument>[*<weight>],...[@trade|@mid]]... [--follow-index <name>]\n",
+    "  [--matching <fifo|lottery:<seed>>] [--speed-bump <micros>] [--batch-interval <ms>]\n",
+    "  [--trade-ids <instrument|random|sequential:<seed>>] [--capacity <orders|client-orders|levels|instruments>=<n>,...]\n",
+    "Risk and clearing:\n",
+    "  [--otr-cap <ratio>[:reject|:penalty:<fee>]] [--margin <initial>:<maintenance> --collateral <amount>]\n",
+    "  [--fees <maker>:<taker>] [--settlement <path>] [--leaderboard <n>]\n",
+    "Input:\n",
+    "  [--strict] [--fail-fast] [--rate <msgs_per_sec>[:<burst>]]\n",
+    "  [--listen <tcp:host:port|unix:path> [--sessions <path>] [--admin <host:port>] [--otlp <url>]]\n",
+    "  [--import <lobster|binance-trades|binance-depth>:<path>]... [--instrument <name>]\n",
+    "Control and state:\n",
+    "  [--control <stdin|tcp:host:port|unix:path>] [--start-paused] [--break <order:<id>|crossed|band:<low>:<high>>]\n",
+    "  [--load-snapshot <path>] [--save-snapshot <path>] [--save-run <path>] [--checkpoint-every <operations>] [--resume]\n",
+    "Checks:\n",
+    "  [--audit] [--shadow <checksum_every>] [--check-crossed]\n",
+    "Market data:\n",
+    "  [--export-lobster <dir>] [--lobster-levels <n>] [--imbalance-levels <n>] [--confirmations <dir>]\n",
+    "  [--feed <mbo|mbp|conflated@<ms>>:<path>]...\n",
+    "  [--stream <nats:host:port|lines:path> [--stream-prefix <prefix>] [--stream-feed <mbo|mbp|conflated@<ms>>]]",
+);
+
+/// Instrument that imported market data is replayed into unless
+/// `--instrument` names another.
+pub const DEFAULT_IMPORT_INSTRUMENT: &str = "PUMPTHIS";
+
+/// Topic prefix of a `--stream` unless `--stream-prefix` names another.
+pub const DEFAULT_STREAM_PREFIX: &str = "exchange";
+
+#[derive(Debug)]
+pub struct CliArgs {
+    pub modes: Vec<LoggingMode>,
+    pub log_events: EventFilter,
+    /// Time each logging call per backend and event type.
+    pub log_timings: bool,
+    pub logger_options: LoggerOptions,
+    pub engine_core: Option<usize>,
+    pub price_collar: Option<PriceCollar>,
+    pub price_band: Option<PriceBand>,
+    pub precision: Option<Precision>,
+    /// Bucket width the printed books are aggregated to.
+    pub price_grouping: Option<Decimal>,
+    pub perpetual: Option<Perpetual>,
+    /// Option instruments registered next to `PUMPTHIS`.
+    pub options: Vec<(String, OptionContract)>,
+    pub indexes: Vec<IndexDefinition>,
+    /// The index `PUMPTHIS` takes its reference price from.
+    pub follow_index: Option<String>,
+    pub matching: MatchingAlgorithm,
+    pub speed_bump: Option<Duration>,
+    pub batch_interval: Option<Duration>,
+    pub parse_policy: ParsePolicy,
+    /// Abort the run on the first engine error or malformed streamed row.
+    pub fail_fast: bool,
+    pub listen: Option<Endpoint>,
+    pub sessions: Option<String>,
+    pub admin: Option<String>,
+    /// The OpenTelemetry collector to export metrics and order spans to.
+    pub otlp: Option<String>,
+    pub imports: Vec<ImportSpec>,
+    pub import_instrument: String,
+    pub control: Option<ControlInput>,
+    pub start_paused: bool,
+    pub breakpoints: Vec<Breakpoint>,
+    pub load_snapshot: Option<String>,
+    pub save_snapshot: Option<String>,
+    /// Where to write the run's latency summary as JSON.
+    pub save_run: Option<String>,
+    /// Checkpoint into `DEFAULT_CHECKPOINT_DIR` every this many operations.
+    pub checkpoint_every: Option<usize>,
+    /// Continue from the last checkpoint instead of starting over.
+    pub resume: bool,
+    /// Pace the run to this many operations per second.
+    pub rate: Option<RateLimit>,
+    pub export_lobster: Option<String>,
+    pub lobster_levels: usize,
+    /// Directory for each client's drop copy of its own confirmations.
+    pub confirmations: Option<String>,
+    /// Levels a side the published book imbalance covers.
+    pub imbalance_levels: Option<usize>,
+    pub leaderboard: usize,
+    /// Fees charged when client fills are settled at the end of the run.
+    pub fees: FeeSchedule,
+    /// Where to write the settlement report as CSV.
+    pub settlement: Option<String>,
+    /// Keep an audit trail and write it to `DEFAULT_AUDIT_PATH`.
+    pub audit: bool,
+    /// Run a legacy book beside each engine book, hashing both every so many
+    /// operations, and report where they part.
+    pub shadow: Option<u64>,
+    /// Panic as soon as an order leaves a book locked or crossed (debug builds).
+    pub check_crossed: bool,
+    pub trade_ids: TradeIdStrategy,
+    pub otr_cap: Option<OtrPolicy>,
+    /// Caps on open orders, price levels and markets.
+    pub capacity: CapacityLimits,
+    /// Margin on every client account, each opened with `collateral`.
+    pub margin: Option<(MarginRequirement, Decimal)>,
+    pub feeds: Vec<FeedSpec>,
+    pub stream: Option<StreamTarget>,
+    pub stream_prefix: String,
+    pub stream_feed: FeedKind,
+}
+
+impl CliArgs {
+    /// Parses the command line, with the options of a `--config` file read
+    /// ahead of the flags given alongside it, which override them.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (config_modes, args) = expand_config(args)?;
+        let mut modes = None;
+        let mut log_events = EventFilter::ALL;
+        let mut log_timings = false;
+        let mut logger_options = LoggerOptions::default();
+        let mut engine_core = None;
+        let mut price_collar = None;
+        let mut price_band = None;
+        let mut precision = None;
+        let mut price_grouping = None;
+        let mut perpetual = None;
+        let mut options = Vec::new();
+        let mut indexes = Vec::new();
+        let mut follow_index = None;
+        let mut matching = MatchingAlgorithm::default();
+        let mut speed_bump = None;
+        let mut batch_interval = None;
+        let mut parse_policy = ParsePolicy::default();
+        let mut fail_fast = false;
+        let mut listen = None;
+        let mut sessions = None;
+        let mut admin = None;
+        let mut otlp = None;
+        let mut imports = Vec::new();
+        let mut import_instrument = DEFAULT_IMPORT_INSTRUMENT.to_string();
+        let mut control = None;
+        let mut start_paused = false;
+        let mut breakpoints = Vec::new();
+        let mut load_snapshot = None;
+        let mut save_snapshot = None;
+        let mut save_run = None;
+        let mut checkpoint_every = None;
+        let mut resume = false;
+        let mut rate = None;
+        let mut export_lobster = None;
+        let mut lobster_levels = DEFAULT_LOBSTER_LEVELS;
+        let mut imbalance_levels = None;
+        let mut confirmations = None;
+        let mut leaderboard = DEFAULT_LEADERBOARD_SIZE;
+        let mut fees = FeeSchedule::default();
+        let mut settlement = None;
+        let mut audit = false;
+        let mut shadow = None;
+        let mut check_crossed = false;
+        let mut trade_ids = TradeIdStrategy::default();
+        let mut otr_cap = None;
+        let mut capacity = CapacityLimits::default();
+        let mut margin = None;
+        let mut collateral = None;
+        let mut feeds = Vec::new();
+        let mut stream = None;
+        let mut stream_prefix = None;
+        let mut stream_feed = None;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--log-events" => log_events = EventFilter::from_str(&flag_value(&mut iter, arg)?)?,
+                "--log-timings" => log_timings = true,
+                "--pin-engine" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    engine_core = Some(value.parse::<usize>().map_err(|_| format!("Invalid core id '{}'", value))?);
+                }
+                "--pin-loggers" => logger_options.logger_cores = parse_core_list(&flag_value(&mut iter, arg)?)?,
+                "--wait-strategy" => {
+                    logger_options.wait = WaitStrategy::from_str(&flag_value(&mut iter, arg)?)?;
+                }
+                "--flush-every" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    logger_options.flush.events = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&events| events > 0)
+                        .ok_or_else(|| format!("Invalid flush event count '{}'", value))?;
+                }
+                "--flush-interval" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let micros = value.parse::<u64>().map_err(|_| format!("Invalid flush interval '{}'", value))?;
+                    logger_options.flush.interval = Duration::from_micros(micros);
+                }
+                "--price-collar" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let deviation = Decimal::from_str(&value).map_err(|_| format!("Invalid price collar '{}'", value))?;
+                    price_collar = Some(PriceCollar::new(deviation));
+                }
+                "--price-band" => price_band = Some(PriceBand::from_str(&flag_value(&mut iter, arg)?)?),
+                "--precision" => precision = Some(Precision::from_str(&flag_value(&mut iter, arg)?)?),
+                "--price-grouping" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let group = Decimal::from_str(&value)
+                        .ok()
+                        .filter(|group| *group > Decimal::ZERO)
+                        .ok_or_else(|| format!("Invalid price grouping '{}', expected a positive bucket width", value))?;
+                    price_grouping = Some(group);
+                }
+                "--perpetual" => perpetual = Some(Perpetual::from_str(&flag_value(&mut iter, arg)?)?),
+                "--option" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let (instrument, contract) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("Invalid option '{}', expected <instrument>=<call|put>:<underlying>:<strike>[:<expiry_ns>]", value))?;
+                    options.push((instrument.to_string(), OptionContract::from_str(contract)?));
+                }
+                "--index" => indexes.push(IndexDefinition::from_str(&flag_value(&mut iter, arg)?)?),
+                "--follow-index" => follow_index = Some(flag_value(&mut iter, arg)?),
+                "--matching" => matching = MatchingAlgorithm::from_str(&flag_value(&mut iter, arg)?)?,
+                "--speed-bump" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let micros = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&micros| micros > 0)
+                        .ok_or_else(|| format!("Invalid speed bump '{}'", value))?;
+                    speed_bump = Some(Duration::from_micros(micros));
+                }
+                "--batch-interval" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let millis = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&millis| millis > 0)
+                        .ok_or_else(|| format!("Invalid batch interval '{}'", value))?;
+                    batch_interval = Some(Duration::from_millis(millis));
+                }
+                "--strict" => parse_policy = ParsePolicy::Strict,
+                "--fail-fast" => fail_fast = true,
+                "--control" => control = Some(ControlInput::from_str(&flag_value(&mut iter, arg)?)?),
+                "--start-paused" => start_paused = true,
+                "--break" => breakpoints.push(Breakpoint::from_str(&flag_value(&mut iter, arg)?)?),
+                "--import" => imports.push(ImportSpec::from_str(&flag_value(&mut iter, arg)?)?),
+                "--instrument" => import_instrument = flag_value(&mut iter, arg)?,
+                "--sessions" => sessions = Some(flag_value(&mut iter, arg)?),
+                "--admin" => admin = Some(flag_value(&mut iter, arg)?),
+                "--otlp" => otlp = Some(flag_value(&mut iter, arg)?),
+                "--listen" => listen = Some(Endpoint::from_str(&flag_value(&mut iter, arg)?)?),
+                "--load-snapshot" => load_snapshot = Some(flag_value(&mut iter, arg)?),
+                "--save-snapshot" => save_snapshot = Some(flag_value(&mut iter, arg)?),
+                "--save-run" => save_run = Some(flag_value(&mut iter, arg)?),
+                "--checkpoint-every" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    checkpoint_every = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&every| every > 0)
+                            .ok_or_else(|| format!("Invalid checkpoint interval '{}'", value))?,
+                    );
+                }
+                "--resume" => resume = true,
+                "--rate" => rate = Some(RateLimit::from_str(&flag_value(&mut iter, arg)?)?),
+                "--feed" => feeds.push(FeedSpec::from_str(&flag_value(&mut iter, arg)?)?),
+                "--stream" => stream = Some(StreamTarget::from_str(&flag_value(&mut iter, arg)?)?),
+                "--stream-prefix" => stream_prefix = Some(flag_value(&mut iter, arg)?),
+                "--stream-feed" => stream_feed = Some(FeedKind::from_str(&flag_value(&mut iter, arg)?)?),
+                "--export-lobster" => export_lobster = Some(flag_value(&mut iter, arg)?),
+                "--confirmations" => confirmations = Some(flag_value(&mut iter, arg)?),
+                "--lobster-levels" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    lobster_levels = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&levels| levels > 0)
+                        .ok_or_else(|| format!("Invalid LOBSTER level count '{}'", value))?;
+                }
+                "--imbalance-levels" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let levels = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&levels| levels > 0)
+                        .ok_or_else(|| format!("Invalid imbalance level count '{}'", value))?;
+                    imbalance_levels = Some(levels);
+                }
+                "--margin" => margin = Some(MarginRequirement::from_str(&flag_value(&mut iter, arg)?)?),
+                "--collateral" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    collateral = Some(
+                        Decimal::from_str(&value)
+                            .ok()
+                            .filter(|amount| *amount >= Decimal::ZERO)
+                            .ok_or_else(|| format!("Invalid collateral '{}'", value))?,
+                    );
+                }
+                "--otr-cap" => otr_cap = Some(OtrPolicy::from_str(&flag_value(&mut iter, arg)?)?),
+                "--capacity" => capacity = CapacityLimits::from_str(&flag_value(&mut iter, arg)?)?,
+                "--leaderboard" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    leaderboard = value.parse::<usize>().map_err(|_| format!("Invalid leaderboard size '{}'", value))?;
+                }
+                "--fees" => fees = FeeSchedule::from_str(&flag_value(&mut iter, arg)?)?,
+                "--settlement" => settlement = Some(flag_value(&mut iter, arg)?),
+                "--audit" => audit = true,
+                "--shadow" => {
+                    let value = flag_value(&mut iter, arg)?;
+                    let every = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&every| every > 0)
+                        .ok_or_else(|| format!("Invalid shadow checksum interval '{}'", value))?;
+                    shadow = Some(every);
+                }
+                "--check-crossed" => check_crossed = true,
+                "--trade-ids" => trade_ids = TradeIdStrategy::from_str(&flag_value(&mut iter, arg)?)?,
+                flag if flag.starts_with("--") => return Err(format!("Unknown flag '{}'\n{}", flag, USAGE)),
+                value if modes.is_none() => modes = Some(parse_modes(value)?),
+                value => return Err(format!("Unexpected argument '{}'\n{}", value, USAGE)),
+            }
+        }
+
+        if start_paused && control.is_none() {
+            return Err("--start-paused requires --control".to_string());
+        }
+        if listen.is_some() && !imports.is_empty() {
+            return Err("--listen and --import cannot be combined".to_string());
+        }
+        if sessions.is_some() && listen.is_none() {
+            return Err("--sessions requires --listen".to_string());
+        }
+        if admin.is_some() && listen.is_none() {
+            return Err("--admin requires --listen".to_string());
+        }
+        if otlp.is_some() && listen.is_none() {
+            return Err("--otlp requires --listen".to_string());
+        }
+        if otlp.is_some() && !cfg!(feature = "otel") {
+            return Err("--otlp needs a build with --features otel".to_string());
+        }
+        if speed_bump.is_some() && batch_interval.is_some() {
+            return Err("--speed-bump and --batch-interval cannot be combined".to_string());
+        }
+        if (stream_prefix.is_some() || stream_feed.is_some()) && stream.is_none() {
+            return Err("--stream-prefix and --stream-feed require --stream".to_string());
+        }
+        if !breakpoints.is_empty() && control.is_none() {
+            control = Some(ControlInput::Stdin);
+        }
+
+        let margin = match (margin, collateral) {
+            (Some(margin), Some(collateral)) => Some((margin, collateral)),
+            (None, None) => None,
+            _ => return Err("--margin and --collateral must be given together".to_string()),
+        };
+        if resume && load_snapshot.is_some() {
+            return Err("--resume restores the books from its checkpoint and cannot be combined with --load-snapshot".to_string());
+        }
+        if rate.is_some() && listen.is_some() {
+            return Err("--rate paces a file run; a listening engine goes at the rate its feed sends".to_string());
+        }
+        if rate.is_some() && (resume || checkpoint_every.is_some()) {
+            return Err("--rate cannot be combined with --checkpoint-every or --resume".to_string());
+        }
+
+        Ok(CliArgs {
+            modes: modes.or(config_modes).ok_or(USAGE)?,
+            log_events,
+            log_timings,
+            logger_options,
+            engine_core,
+            price_collar,
+            price_band,
+            precision,
+            price_grouping,
+            perpetual,
+            options,
+            indexes,
+            follow_index,
+            matching,
+            speed_bump,
+            batch_interval,
+            parse_policy,
+            fail_fast,
+            listen,
+            sessions,
+            admin,
+            otlp,
+            imports,
+            import_instrument,
+            control,
+            start_paused,
+            breakpoints,
+            load_snapshot,
+            save_snapshot,
+            save_run,
+            checkpoint_every,
+            resume,
+            rate,
+            export_lobster,
+            lobster_levels,
+            imbalance_levels,
+            confirmations,
+            leaderboard,
+            fees,
+            settlement,
+            audit,
+            shadow,
+            check_crossed,
+            trade_ids,
+            otr_cap,
+            capacity,
+            margin,
+            feeds,
+            stream,
+            stream_prefix: stream_prefix.unwrap_or_else(|| DEFAULT_STREAM_PREFIX.to_string()),
+            stream_feed: stream_feed.unwrap_or(FeedKind::Live(FeedMode::ByPrice)),
+        })
+    }
+}
+
+/// Replaces `--config <path>` with the options in the file, placed before
+/// the remaining arguments so that those win. Each line of the file is an
+/// option without its leading `--`, followed by `= <value>` if it takes one,
+/// so `log-events = trades,fills` stands for `--log-events trades,fills`.
+/// `mode = <logging_mode>` sets the logging modes when the command line
+/// names none. Blank lines and lines starting with `#` are skipped.
+fn expand_config(args: &[String]) -> Result<(Option<Vec<LoggingMode>>, Vec<String>), String> {
+    let Some(index) = args.iter().position(|arg| arg == "--config") else {
+        return Ok((None, args.to_vec()));
+    };
+    let path = args.get(index + 1).ok_or("Missing value for '--config'")?;
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Cannot read config file '{}': {}", path, e))?;
+    let mut modes = None;
+    let mut expanded = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (option, value) = match line.split_once('=') {
+            Some((option, value)) => (option.trim(), Some(value.trim())),
+            None => (line, None),
+        };
+        match (option, value) {
+            ("mode", Some(value)) => modes = Some(parse_modes(value)?),
+            ("config", _) => return Err(format!("{}:{}: a config file cannot include another", path, number + 1)),
+            (option, _) if option.is_empty() || option.starts_with('-') => {
+                return Err(format!("{}:{}: invalid option '{}', expected <option> [= <value>]", path, number + 1, line));
+            }
+            (option, value) => {
+                expanded.push(format!("--{}", option));
+                expanded.extend(value.map(str::to_string));
+            }
+        }
+    }
+    expanded.extend(args[..index].iter().chain(&args[index + 2..]).cloned());
+    if expanded.iter().any(|arg| arg == "--config") {
+        return Err("--config can only be given once".to_string());
+    }
+    Ok((modes, expanded))
+}
+
+/// Parses a `+`-separated list of logging modes, e.g. `ae+bfw`.
+fn parse_modes(value: &str) -> Result<Vec<LoggingMode>, String> {
+    let mut modes = Vec::new();
+    for part in value.split('+') {
+        let mode = LoggingMode::from_str(part).map_err(|_| format!("Invalid logging mode '{}'", part))?;
+        if modes.contains(&mode) {
+            return Err(format!("Logging mode '{}' given more than once", part));
+        }
+        modes.push(mode);
+    }
+
+    let tracing_modes = modes
+        .iter()
+        .filter(|m| matches!(m, LoggingMode::TracingConsole | LoggingMode::TracingFile))
+        .count();
+    if tracing_modes > 1 {
+        return Err("Only one tracing logging mode can be used at a time".to_string());
+    }
+
+    Ok(modes)
+}
+
+fn flag_value<'a>(iter: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<String, String> {
+    iter.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for '{}'", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_mode_and_snapshot_flags() {
+        let parsed = CliArgs::parse(&args(&["ae", "--load-snapshot", "in.csv", "--save-snapshot", "out.csv", "--save-run", "run.json"])).unwrap();
+        assert_eq!(parsed.modes, vec![LoggingMode::AsyncEnum]);
+        assert_eq!(parsed.load_snapshot.as_deref(), Some("in.csv"));
+        assert_eq!(parsed.save_snapshot.as_deref(), Some("out.csv"));
+        assert_eq!(parsed.save_run.as_deref(), Some("run.json"));
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let path = std::env::temp_dir().join(format!("cli_{}.conf", std::process::id()));
+        std::fs::write(&path, "# executions only\nmode = bfw\nlog-events = trades,fills\nprice-collar = 0.2\naudit\n\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let parsed = CliArgs::parse(&args(&["--config", config])).unwrap();
+        assert_eq!(parsed.modes, vec![LoggingMode::BufferedFileWrite]);
+        assert!(parsed.log_events.trades && !parsed.log_events.submissions);
+        assert!(parsed.audit);
+        // The command line overrides the file, modes included.
+        let parsed = CliArgs::parse(&args(&["ae", "--config", config, "--price-collar", "0.1"])).unwrap();
+        assert_eq!(parsed.modes, vec![LoggingMode::AsyncEnum]);
+        assert_eq!(parsed.price_collar.map(|c| c.max_deviation), Some(Decimal::from_str("0.1").unwrap()));
+
+        std::fs::write(&path, "--audit\n").unwrap();
+        assert!(CliArgs::parse(&args(&["ae", "--config", config])).unwrap_err().contains("invalid option"));
+        std::fs::write(&path, "no-such-flag = 1\n").unwrap();
+        assert!(CliArgs::parse(&args(&["ae", "--config", config])).unwrap_err().contains("Unknown flag '--no-such-flag'"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(CliArgs::parse(&args(&["ae", "--config", config])).unwrap_err().contains("Cannot read config file"));
+    }
+
+    #[test]
+    fn test_parse_checkpoint_flags() {
+        let parsed = CliArgs::parse(&args(&["ae", "--checkpoint-every", "5000", "--resume"])).unwrap();
+        assert_eq!(parsed.checkpoint_every, Some(5000));
+        assert!(parsed.resume);
+        assert!(CliArgs::parse(&args(&["ae", "--checkpoint-every", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_log_events() {
+        let parsed = CliArgs::parse(&args(&["bfw", "--log-events", "trades,fills"])).unwrap();
+        assert!(parsed.log_events.trades && parsed.log_events.fills);
+        assert!(!parsed.log_events.submissions && !parsed.log_events.cancels);
+        assert!(CliArgs::parse(&args(&["bfw"])).unwrap().log_events.is_all());
+        assert!(CliArgs::parse(&args(&["ae+bfw", "--log-timings"])).unwrap().log_timings);
+        assert!(!CliArgs::parse(&args(&["bfw"])).unwrap().log_timings);
+    }
+
+    #[test]
+    fn test_parse_pinning_flags() {
+        let parsed = CliArgs::parse(&args(&["ae+as", "--pin-engine", "1", "--pin-loggers", "2,3"])).unwrap();
+        assert_eq!(parsed.engine_core, Some(1));
+        assert_eq!(parsed.logger_options.worker(0).core, Some(2));
+        assert_eq!(parsed.logger_options.worker(1).core, Some(3));
+        assert!(CliArgs::parse(&args(&["ae", "--pin-engine", "x"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_price_collar() {
+        let parsed = CliArgs::parse(&args(&["ae", "--price-collar", "0.2"])).unwrap();
+        assert_eq!(parsed.price_collar.map(|c| c.max_deviation), Some(Decimal::from_str("0.2").unwrap()));
+        assert!(CliArgs::parse(&args(&["ae"])).unwrap().price_collar.is_none());
+        assert!(CliArgs::parse(&args(&["ae", "--price-collar", "abc"])).is_err());
+        let parsed = CliArgs::parse(&args(&["ae", "--price-band", "0.05:1000:slice"])).unwrap();
+        assert_eq!(parsed.price_band.map(|band| band.width), Some(Decimal::from_str("0.05").unwrap()));
+        assert!(CliArgs::parse(&args(&["ae", "--price-band", "5"])).is_err());
+        let parsed = CliArgs::parse(&args(&["ae", "--precision", "2:0:reject"])).unwrap();
+        assert_eq!(parsed.precision.and_then(|precision| precision.price_decimals), Some(2));
+        let parsed = CliArgs::parse(&args(&["ae", "--price-grouping", "0.25"])).unwrap();
+        assert_eq!(parsed.price_grouping, Some(Decimal::from_str("0.25").unwrap()));
+        assert!(CliArgs::parse(&args(&["ae", "--price-grouping", "0"])).is_err());
+        let parsed = CliArgs::parse(&args(&["ae", "--perpetual", "100:60000"])).unwrap();
+        assert_eq!(parsed.perpetual.map(|perpetual| perpetual.index_price), Some(Decimal::from(100)));
+        let parsed = CliArgs::parse(&args(&["ae", "--option", "PUMP-C10=call:PUMPTHIS:10"])).unwrap();
+        assert_eq!(parsed.options.iter().map(|(instrument, contract)| (instrument.as_str(), contract.strike)).collect::<Vec<_>>(), vec![("PUMP-C10", Decimal::from(10))]);
+        assert!(CliArgs::parse(&args(&["ae", "--option", "call:PUMPTHIS:10"])).is_err());
+        let parsed = CliArgs::parse(&args(&["ae", "--index", "PUMPX=PUMPTHIS*2,NVO", "--follow-index", "PUMPX"])).unwrap();
+        assert_eq!((parsed.indexes.len(), parsed.follow_index.as_deref()), (1, Some("PUMPX")));
+    }
+
+    #[test]
+    fn test_parse_strict_flag() {
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().parse_policy, ParsePolicy::Lenient);
+        assert_eq!(CliArgs::parse(&args(&["ae", "--strict"])).unwrap().parse_policy, ParsePolicy::Strict);
+        assert!(CliArgs::parse(&args(&["ae", "--fail-fast"])).unwrap().fail_fast);
+    }
+
+    #[test]
+    fn test_parse_listen_endpoint() {
+        let parsed = CliArgs::parse(&args(&["ae", "--listen", "tcp:127.0.0.1:9000"])).unwrap();
+        assert_eq!(parsed.listen, Some(Endpoint::Tcp("127.0.0.1:9000".to_string())));
+        assert!(CliArgs::parse(&args(&["ae", "--listen"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_control_flags() {
+        let parsed = CliArgs::parse(&args(&["ae", "--control", "stdin", "--start-paused"])).unwrap();
+        assert_eq!(parsed.control, Some(ControlInput::Stdin));
+        assert!(parsed.start_paused);
+        let parsed = CliArgs::parse(&args(&["ae", "--control", "unix:/tmp/ctl.sock"])).unwrap();
+        assert!(matches!(parsed.control, Some(ControlInput::Socket(_))));
+    }
+
+    #[test]
+    fn test_breakpoints_default_to_stdin_control() {
+        let parsed = CliArgs::parse(&args(&["ae", "--break", "crossed", "--break", "band:90:110"])).unwrap();
+        assert_eq!(parsed.breakpoints.len(), 2);
+        assert_eq!(parsed.control, Some(ControlInput::Stdin));
+    }
+
+    #[test]
+    fn test_parse_imports() {
+        let parsed = CliArgs::parse(&args(&["ae", "--import", "binance-depth:snap.json", "--import", "binance-trades:trades.csv", "--instrument", "BTCUSDT"])).unwrap();
+        assert_eq!(parsed.imports.len(), 2);
+        assert_eq!(parsed.import_instrument, "BTCUSDT");
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().import_instrument, DEFAULT_IMPORT_INSTRUMENT);
+        assert!(CliArgs::parse(&args(&["ae", "--import", "lobster:m.csv", "--listen", "tcp:127.0.0.1:9000"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_wait_strategy() {
+        let parsed = CliArgs::parse(&args(&["ae", "--wait-strategy", "spin"])).unwrap();
+        assert_eq!(parsed.logger_options.worker(0).wait, WaitStrategy::Spin);
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().logger_options.wait, WaitStrategy::Block);
+        assert!(CliArgs::parse(&args(&["ae", "--wait-strategy", "sleep"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_flush_policy() {
+        let parsed = CliArgs::parse(&args(&["ab", "--flush-every", "256", "--flush-interval", "500"])).unwrap();
+        assert_eq!(parsed.modes, vec![LoggingMode::AsyncBatch]);
+        let flush = parsed.logger_options.worker(0).flush;
+        assert_eq!((flush.events, flush.interval), (256, Duration::from_micros(500)));
+        assert!(CliArgs::parse(&args(&["ab", "--flush-every", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_combined_modes() {
+        let parsed = CliArgs::parse(&args(&["ae+bfw"])).unwrap();
+        assert_eq!(parsed.modes, vec![LoggingMode::AsyncEnum, LoggingMode::BufferedFileWrite]);
+        assert!(CliArgs::parse(&args(&["ae+ae"])).is_err());
+        assert!(CliArgs::parse(&args(&["tc+tf"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae+bogus"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_lobster_export() {
+        let parsed = CliArgs::parse(&args(&["ae", "--export-lobster", "lobster", "--lobster-levels", "5"])).unwrap();
+        assert_eq!(parsed.export_lobster.as_deref(), Some("lobster"));
+        assert_eq!(parsed.lobster_levels, 5);
+        assert_eq!(CliArgs::parse(&args(&["ae", "--confirmations", "drop"])).unwrap().confirmations.as_deref(), Some("drop"));
+        assert_eq!(CliArgs::parse(&args(&["ae", "--imbalance-levels", "3"])).unwrap().imbalance_levels, Some(3));
+        assert!(CliArgs::parse(&args(&["ae", "--imbalance-levels", "0"])).is_err());
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().lobster_levels, DEFAULT_LOBSTER_LEVELS);
+        assert!(CliArgs::parse(&args(&["ae", "--lobster-levels", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_leaderboard_size() {
+        assert_eq!(CliArgs::parse(&args(&["ae", "--leaderboard", "3"])).unwrap().leaderboard, 3);
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().leaderboard, DEFAULT_LEADERBOARD_SIZE);
+        assert!(CliArgs::parse(&args(&["ae", "--leaderboard", "many"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_fees_and_settlement_path() {
+        let parsed = CliArgs::parse(&args(&["ae", "--fees", "-0.0001:0.0003", "--settlement", "settlement.csv"])).unwrap();
+        assert_eq!(parsed.fees.taker, Decimal::from_str("0.0003").unwrap());
+        assert_eq!(parsed.settlement.as_deref(), Some("settlement.csv"));
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().fees, FeeSchedule::default());
+        assert!(CliArgs::parse(&args(&["ae", "--fees", "0.1"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_audit() {
+        assert!(CliArgs::parse(&args(&["ae", "--audit"])).unwrap().audit);
+        assert_eq!(CliArgs::parse(&args(&["ae", "--shadow", "100"])).unwrap().shadow, Some(100));
+        assert!(CliArgs::parse(&args(&["ae", "--shadow", "0"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--check-crossed"])).unwrap().check_crossed);
+        assert_eq!(CliArgs::parse(&args(&["ae", "--trade-ids", "instrument"])).unwrap().trade_ids, TradeIdStrategy::Instrument);
+        assert!(!CliArgs::parse(&args(&["ae"])).unwrap().audit);
+    }
+
+    #[test]
+    fn test_parse_otr_cap() {
+        let parsed = CliArgs::parse(&args(&["ae", "--otr-cap", "25:penalty:0.1"])).unwrap();
+        assert_eq!(parsed.otr_cap.map(|policy| policy.max_ratio), Some(25.0));
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().otr_cap, None);
+        assert!(CliArgs::parse(&args(&["ae", "--otr-cap", "lots"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_capacity() {
+        let parsed = CliArgs::parse(&args(&["ae", "--capacity", "orders=5000,instruments=8"])).unwrap();
+        assert_eq!((parsed.capacity.max_orders_per_instrument, parsed.capacity.max_instruments), (Some(5000), Some(8)));
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().capacity, CapacityLimits::default());
+        assert!(CliArgs::parse(&args(&["ae", "--capacity", "orders=-1"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_margin() {
+        let parsed = CliArgs::parse(&args(&["ae", "--margin", "0.2:0.1", "--collateral", "5000"])).unwrap();
+        let (requirement, collateral) = parsed.margin.unwrap();
+        assert_eq!((requirement.initial, collateral), (Decimal::from_str("0.2").unwrap(), Decimal::from(5000)));
+        assert!(CliArgs::parse(&args(&["ae", "--margin", "0.2:0.1"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--margin", "0.2:0.1", "--collateral", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_matching() {
+        let parsed = CliArgs::parse(&args(&["ae", "--matching", "lottery:7"])).unwrap();
+        assert_eq!(parsed.matching, MatchingAlgorithm::Lottery { seed: 7 });
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().matching, MatchingAlgorithm::Fifo);
+        assert!(CliArgs::parse(&args(&["ae", "--matching", "pro-rata"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_speed_bump() {
+        let parsed = CliArgs::parse(&args(&["ae", "--speed-bump", "350"])).unwrap();
+        assert_eq!(parsed.speed_bump, Some(Duration::from_micros(350)));
+        assert!(CliArgs::parse(&args(&["ae"])).unwrap().speed_bump.is_none());
+        assert!(CliArgs::parse(&args(&["ae", "--speed-bump", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_batch_interval() {
+        let parsed = CliArgs::parse(&args(&["ae", "--batch-interval", "100"])).unwrap();
+        assert_eq!(parsed.batch_interval, Some(Duration::from_millis(100)));
+        assert!(CliArgs::parse(&args(&["ae", "--batch-interval", "0"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--batch-interval", "100", "--speed-bump", "350"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        let parsed = CliArgs::parse(&args(&["ae", "--rate", "50000:100"])).unwrap();
+        assert_eq!(parsed.rate, Some(RateLimit { per_second: 50_000.0, burst: 100 }));
+        assert_eq!(CliArgs::parse(&args(&["ae"])).unwrap().rate, None);
+        assert!(CliArgs::parse(&args(&["ae", "--rate", "0"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--rate", "1000", "--checkpoint-every", "100"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--rate", "1000", "--listen", "tcp:127.0.0.1:9000"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_sessions_requires_listen() {
+        let parsed = CliArgs::parse(&args(&["ae", "--listen", "tcp:127.0.0.1:9000", "--sessions", "clients.csv"])).unwrap();
+        assert_eq!(parsed.sessions.as_deref(), Some("clients.csv"));
+        assert!(CliArgs::parse(&args(&["ae", "--sessions", "clients.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_admin_requires_listen() {
+        let parsed = CliArgs::parse(&args(&["ae", "--listen", "tcp:127.0.0.1:9000", "--admin", "127.0.0.1:9100"])).unwrap();
+        assert_eq!(parsed.admin.as_deref(), Some("127.0.0.1:9100"));
+        assert!(CliArgs::parse(&args(&["ae", "--admin", "127.0.0.1:9100"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_otlp_requires_listen_and_the_feature() {
+        let parsed = CliArgs::parse(&args(&["ae", "--listen", "tcp:127.0.0.1:9000", "--otlp", "http://localhost:4318"]));
+        if cfg!(feature = "otel") {
+            assert_eq!(parsed.unwrap().otlp.as_deref(), Some("http://localhost:4318"));
+        } else {
+            assert!(parsed.unwrap_err().contains("--features otel"));
+        }
+        assert!(CliArgs::parse(&args(&["ae", "--otlp", "http://localhost:4318"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_feeds() {
+        let parsed = CliArgs::parse(&args(&["ae", "--feed", "mbo:mbo.csv", "--feed", "mbp:mbp.csv", "--feed", "conflated@100:book.csv"])).unwrap();
+        assert_eq!(parsed.feeds.len(), 3);
+        assert_eq!(parsed.feeds[1].path, "mbp.csv");
+        assert!(CliArgs::parse(&args(&["ae", "--feed", "itch:feed.bin"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        let parsed = CliArgs::parse(&args(&["ae", "--stream", "nats:localhost:4222", "--stream-feed", "mbo"])).unwrap();
+        assert_eq!(parsed.stream, Some(StreamTarget::Nats("localhost:4222".to_string())));
+        assert_eq!(parsed.stream_prefix, DEFAULT_STREAM_PREFIX);
+        assert_eq!(parsed.stream_feed, FeedKind::Live(FeedMode::ByOrder));
+        assert!(CliArgs::parse(&args(&["ae", "--stream-prefix", "sim"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_missing_mode_and_values() {
+        assert!(CliArgs::parse(&args(&[])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--load-snapshot"])).is_err());
+        assert!(CliArgs::parse(&args(&["ae", "--unknown"])).is_err());
+    }
+}
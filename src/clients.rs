@@ -0,0 +1,139 @@
+use crate::clock::NANOS_PER_DAY;
+use crate::order::Order;
+use crate::utils::{UuidMap, UuidSet};
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+/// The identity a gateway session logged on with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub String);
+
+impl ClientId {
+    pub fn new(id: impl Into<String>) -> Self {
+        ClientId(id.into())
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Which client owns each live order, and the live orders of each client.
+#[derive(Debug, Default)]
+pub struct ClientIndex {
+    owners: UuidMap<ClientId>,
+    orders: HashMap<ClientId, UuidSet>,
+}
+
+impl ClientIndex {
+    pub fn insert(&mut self, order_id: Uuid, client: ClientId) {
+        self.orders.entry(client.clone()).or_default().insert(order_id);
+        self.owners.insert(order_id, client);
+    }
+
+    /// Forgets an order once it is filled or canceled.
+    pub fn remove(&mut self, order_id: &Uuid) -> Option<ClientId> {
+        let client = self.owners.remove(order_id)?;
+        if let Some(orders) = self.orders.get_mut(&client) {
+            orders.remove(order_id);
+            if orders.is_empty() {
+                self.orders.remove(&client);
+            }
+        }
+        Some(client)
+    }
+
+    pub fn owner(&self, order_id: &Uuid) -> Option<&ClientId> {
+        self.owners.get(order_id)
+    }
+
+    /// How many live orders the client has.
+    pub fn order_count(&self, client: &ClientId) -> usize {
+        self.orders.get(client).map_or(0, |orders| orders.len())
+    }
+
+    /// The client's live orders, in no particular order.
+    pub fn orders_of(&self, client: &ClientId) -> Vec<Uuid> {
+        self.orders.get(client).map(|orders| orders.iter().copied().collect()).unwrap_or_default()
+    }
+}
+
+/// Client order ids seen today, each with the latest state of the order it
+/// created, so a retried submission can be answered without a second order.
+/// Ids are unique per client per UTC day; the table starts over at midnight.
+#[derive(Debug, Default)]
+pub struct ClientOrderIds {
+    day: u64,
+    orders: HashMap<(ClientId, String), Order>,
+    keys: UuidMap<(ClientId, String)>,
+}
+
+impl ClientOrderIds {
+    /// The order a client already entered under `client_order_id` today.
+    pub fn lookup(&mut self, client: &ClientId, client_order_id: &str, now: u64) -> Option<&Order> {
+        let day = now / NANOS_PER_DAY;
+        if day != self.day {
+            self.day = day;
+            self.orders.clear();
+            self.keys.clear();
+        }
+        self.orders.get(&(client.clone(), client_order_id.to_string()))
+    }
+
+    pub fn insert(&mut self, client: ClientId, client_order_id: String, order: &Order) {
+        let key = (client, client_order_id);
+        self.keys.insert(order.order_id, key.clone());
+        self.orders.insert(key, order.clone());
+    }
+
+    /// Records a later state of an order entered with a client order id.
+    pub fn update(&mut self, order: &Order) {
+        if let Some(key) = self.keys.get(&order.order_id)
+            && let Some(state) = self.orders.get_mut(key) {
+            state.clone_from(order);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_index_tracks_owners_both_ways() {
+        let mut index = ClientIndex::default();
+        let alice = ClientId::new("alice");
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        index.insert(first, alice.clone());
+        index.insert(second, alice.clone());
+
+        assert_eq!(index.owner(&first), Some(&alice));
+        assert_eq!(index.orders_of(&alice).len(), 2);
+        assert_eq!(index.remove(&first), Some(alice.clone()));
+        assert_eq!(index.owner(&first), None);
+        assert_eq!(index.orders_of(&alice), vec![second]);
+        index.remove(&second);
+        assert!(index.orders_of(&alice).is_empty());
+        assert_eq!(index.remove(&second), None);
+    }
+
+    #[test]
+    fn test_client_order_ids_are_unique_per_client_per_day() {
+        let mut ids = ClientOrderIds::default();
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+        let mut order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        assert!(ids.lookup(&alice, "A-1", 0).is_none());
+        ids.insert(alice.clone(), "A-1".to_string(), &order);
+
+        order.remaining_quantity = dec!(2);
+        ids.update(&order);
+        assert_eq!(ids.lookup(&alice, "A-1", 10).unwrap().remaining_quantity, dec!(2));
+        assert!(ids.lookup(&bob, "A-1", 10).is_none());
+        assert!(ids.lookup(&alice, "A-1", NANOS_PER_DAY).is_none());
+    }
+}
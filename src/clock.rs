@@ -0,0 +1,173 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+
+/// Source of nanosecond timestamps: the engine stamps orders and trades from
+/// one, and times its own work with another, monotonic one.
+pub trait Clock: Send + Sync {
+    fn now_nanos(&self) -> u64;
+}
+
+/// Wall-clock time since the UNIX epoch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_nanos(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the UNIX epoch, something is very wrong.")
+            .as_nanos() as u64
+    }
+}
+
+/// Monotonic time since the clock was created, for measuring how long work
+/// takes rather than stamping events.
+#[derive(Clone, Copy, Debug)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self { origin: Instant::now() }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_nanos(&self) -> u64 {
+        self.origin.elapsed().as_nanos() as u64
+    }
+}
+
+/// Wraps a clock and adds a random delay of up to `max_jitter_nanos` on every
+/// read. The delays accumulate, so time never goes backwards, and the same
+/// seed gives the same delays.
+#[derive(Debug)]
+pub struct JitterClock<C> {
+    inner: C,
+    max_jitter_nanos: u64,
+    state: Mutex<(StdRng, u64)>,
+}
+
+impl<C: Clock> JitterClock<C> {
+    pub fn new(inner: C, max_jitter_nanos: u64, seed: u64) -> Self {
+        Self {
+            inner,
+            max_jitter_nanos,
+            state: Mutex::new((StdRng::seed_from_u64(seed), 0)),
+        }
+    }
+}
+
+impl<C: Clock> Clock for JitterClock<C> {
+    fn now_nanos(&self) -> u64 {
+        let mut state = self.state.lock().expect("jitter clock lock poisoned");
+        let (rng, offset) = &mut *state;
+        *offset += rng.random_range(0..=self.max_jitter_nanos);
+        self.inner.now_nanos() + *offset
+    }
+}
+
+/// A venue clock that runs `offset_nanos` off a reference clock and drifts
+/// from it by `drift_ppm` nanoseconds per millisecond of reference time, i.e.
+/// parts per million, counted from when it was created.
+#[derive(Clone, Debug)]
+pub struct SkewedClock<C> {
+    reference: C,
+    offset_nanos: i64,
+    drift_ppm: f64,
+    origin: u64,
+}
+
+impl<C: Clock> SkewedClock<C> {
+    pub fn new(reference: C, offset_nanos: i64, drift_ppm: f64) -> Self {
+        let origin = reference.now_nanos();
+        Self { reference, offset_nanos, drift_ppm, origin }
+    }
+
+    /// What this clock reads when the reference reads `reference_nanos`.
+    pub fn at(&self, reference_nanos: u64) -> u64 {
+        let elapsed = reference_nanos.saturating_sub(self.origin) as f64;
+        let drift = (elapsed * self.drift_ppm / 1_000_000.0) as i64;
+        reference_nanos.saturating_add_signed(self.offset_nanos.saturating_add(drift))
+    }
+}
+
+impl<C: Clock> Clock for SkewedClock<C> {
+    fn now_nanos(&self) -> u64 {
+        self.at(self.reference.now_nanos())
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a test
+/// can keep one handle and advance the clock the engine is reading.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(start_nanos: u64) -> Self {
+        Self {
+            nanos: Arc::new(AtomicU64::new(start_nanos)),
+        }
+    }
+
+    pub fn set(&self, nanos: u64) {
+        self.nanos.store(nanos, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, nanos: u64) {
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_nanos(&self) -> u64 {
+        self.nanos.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_handles_share_time() {
+        let clock = ManualClock::new(100);
+        let handle = clock.clone();
+        handle.advance(50);
+        assert_eq!(clock.now_nanos(), 150);
+        handle.set(10);
+        assert_eq!(clock.now_nanos(), 10);
+    }
+
+    #[test]
+    fn test_jitter_clock_is_seeded_and_never_goes_backwards() {
+        let readings = |seed| {
+            let clock = JitterClock::new(ManualClock::new(1_000), 50, seed);
+            (0..100).map(|_| clock.now_nanos()).collect::<Vec<_>>()
+        };
+        let first = readings(7);
+        assert_eq!(first, readings(7));
+        assert_ne!(first, readings(8));
+        assert!(first.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(first[0] >= 1_000 && first[99] <= 1_000 + 100 * 50);
+    }
+
+    #[test]
+    fn test_skewed_clock_adds_offset_and_drift() {
+        let reference = ManualClock::new(1_000);
+        let clock = SkewedClock::new(reference.clone(), -300, 50.0);
+        assert_eq!(clock.now_nanos(), 700);
+        // 50 ppm of 2 ms is 100 ns.
+        reference.advance(2_000_000);
+        assert_eq!(clock.now_nanos(), 2_000_800);
+        assert_eq!(SkewedClock::new(ManualClock::new(100), -300, 0.0).now_nanos(), 0);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::fixed::Fixed;
+use crate::order::{CancelTimer, Order, OrderAttributes, Peg};
+use crate::utils::{OrderStatus, OrderType, Side, UuidMap};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// Where a resting order lives in an [`OrderSlab`]: the slot index in the
+/// low half and the slot's generation in the high half, so a key kept after
+/// its order left the book never matches the order that reuses the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OrderKey(u64);
+
+impl OrderKey {
+    fn new(slot: u32, generation: u32) -> Self {
+        OrderKey(((generation as u64) << 32) | slot as u64)
+    }
+
+    fn slot(self) -> usize {
+        self.0 as u32 as usize
+    }
+
+    fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+}
+
+/// Everything matching reads or writes for a resting order, in one 64-byte
+/// cache line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C, align(64))]
+pub struct CompactOrder {
+    pub key: OrderKey,
+    pub price: Fixed,
+    pub quantity: Fixed,
+    pub remaining: Fixed,
+    pub timestamp: u64,
+    pub sequence: u64,
+    pub instrument: u32,
+    pub side: Side,
+    pub status: OrderStatus,
+    pub attributes: OrderAttributes,
+}
+
+impl CompactOrder {
+    pub fn is_filled(&self) -> bool {
+        self.remaining.is_zero()
+    }
+
+    pub fn fill(&mut self, quantity: Fixed) {
+        self.remaining = (self.remaining - quantity).max(Fixed::ZERO);
+        self.status = if self.is_filled() { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+    }
+}
+
+/// The parts of a resting order matching rarely needs, stored apart from
+/// the `CompactOrder` records so they do not dilute the lines a level walk
+/// loads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderMeta {
+    pub order_id: Uuid,
+    pub peg: Option<Peg>,
+    pub discretion: Option<Decimal>,
+    pub expire_at: Option<u64>,
+    pub cancel_at: Option<u64>,
+    pub stop_price: Option<Decimal>,
+}
+
+/// Storage for one book's resting orders. Records and metadata sit in
+/// parallel slot vectors whose slots are reused once freed; an index maps
+/// order ids to keys.
+#[derive(Debug, Default)]
+pub struct OrderSlab {
+    records: Vec<CompactOrder>,
+    meta: Vec<Option<OrderMeta>>,
+    free: Vec<u32>,
+    index: UuidMap<OrderKey>,
+}
+
+impl OrderSlab {
+    /// Stores a priced order and returns its key.
+    ///
+    /// Panics if the price or a quantity does not fit in fixed point, which
+    /// the engine rules out before orders reach the book.
+    pub fn insert(&mut self, order: &Order, price: Fixed, instrument: u32) -> OrderKey {
+        let (slot, generation) = match self.free.pop() {
+            Some(slot) => (slot, self.records[slot as usize].key.generation().wrapping_add(1)),
+            None => (self.records.len() as u32, 0),
+        };
+        let key = OrderKey::new(slot, generation);
+        let record = CompactOrder {
+            key,
+            price,
+            quantity: Fixed::from_validated(order.quantity),
+            remaining: Fixed::from_validated(order.remaining_quantity),
+            timestamp: order.timestamp,
+            sequence: order.sequence,
+            instrument,
+            side: order.side,
+            status: order.status,
+            attributes: order.attributes,
+        };
+        let meta = OrderMeta {
+            order_id: order.order_id,
+            peg: order.peg,
+            discretion: order.discretion,
+            expire_at: order.expire_at,
+            cancel_at: order.cancel_at(),
+            stop_price: order.stop_price,
+        };
+        if key.slot() == self.records.len() {
+            self.records.push(record);
+            self.meta.push(Some(meta));
+        } else {
+            self.records[key.slot()] = record;
+            self.meta[key.slot()] = Some(meta);
+        }
+        self.index.insert(order.order_id, key);
+        key
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn key(&self, order_id: &Uuid) -> Option<OrderKey> {
+        self.index.get(order_id).copied()
+    }
+
+    pub fn contains(&self, order_id: &Uuid) -> bool {
+        self.index.contains_key(order_id)
+    }
+
+    fn is_live(&self, key: OrderKey) -> bool {
+        self.meta.get(key.slot()).is_some_and(Option::is_some) && self.records[key.slot()].key == key
+    }
+
+    pub fn get(&self, key: OrderKey) -> Option<&CompactOrder> {
+        self.is_live(key).then(|| &self.records[key.slot()])
+    }
+
+    pub fn get_mut(&mut self, key: OrderKey) -> Option<&mut CompactOrder> {
+        if self.is_live(key) { Some(&mut self.records[key.slot()]) } else { None }
+    }
+
+    pub fn meta(&self, key: OrderKey) -> Option<&OrderMeta> {
+        if self.is_live(key) { self.meta[key.slot()].as_ref() } else { None }
+    }
+
+    /// The record and metadata stored under `key`, to fill an order and name
+    /// it in the trade.
+    pub fn entry_mut(&mut self, key: OrderKey) -> Option<(&mut CompactOrder, &OrderMeta)> {
+        if !self.is_live(key) {
+            return None;
+        }
+        Some((&mut self.records[key.slot()], self.meta[key.slot()].as_ref()?))
+    }
+
+    /// `entry_mut` without checking that `key` is live. Debug builds still
+    /// check.
+    ///
+    /// # Safety
+    ///
+    /// `key` must be live: returned by `insert` and not removed since.
+    #[cfg(feature = "fast-path")]
+    pub unsafe fn entry_unchecked(&mut self, key: OrderKey) -> (&mut CompactOrder, &OrderMeta) {
+        debug_assert!(self.is_live(key), "stale order key {:?}", key);
+        // SAFETY: a live key's slot is in bounds of both vectors and its
+        // metadata is set.
+        unsafe {
+            let record = self.records.get_unchecked_mut(key.slot());
+            let meta = self.meta.get_unchecked(key.slot()).as_ref().unwrap_unchecked();
+            (record, meta)
+        }
+    }
+
+    /// Frees the order's slot and returns the full order.
+    pub fn remove(&mut self, key: OrderKey, instrument: &str) -> Option<Order> {
+        if !self.is_live(key) {
+            return None;
+        }
+        let meta = self.meta[key.slot()].take()?;
+        self.index.remove(&meta.order_id);
+        self.free.push(key.slot() as u32);
+        Some(expand(&self.records[key.slot()], &meta, instrument))
+    }
+
+    /// Rebuilds the full order stored under `key`.
+    pub fn order(&self, key: OrderKey, instrument: &str) -> Option<Order> {
+        Some(expand(self.get(key)?, self.meta(key)?, instrument))
+    }
+
+    /// Every stored record, in slot order.
+    pub fn records(&self) -> impl Iterator<Item = &CompactOrder> {
+        self.records.iter().zip(&self.meta).filter(|(_, meta)| meta.is_some()).map(|(record, _)| record)
+    }
+}
+
+fn expand(record: &CompactOrder, meta: &OrderMeta, instrument: &str) -> Order {
+    Order {
+        order_id: meta.order_id,
+        instrument: instrument.to_string(),
+        side: record.side,
+        order_type: OrderType::Limit,
+        status: record.status,
+        price: Some(record.price.to_decimal()),
+        quantity: record.quantity.to_decimal(),
+        remaining_quantity: record.remaining.to_decimal(),
+        timestamp: record.timestamp,
+        sequence: record.sequence,
+        peg: meta.peg,
+        discretion: meta.discretion,
+        attributes: record.attributes,
+        expire_at: meta.expire_at,
+        cancel_timer: meta.cancel_at.map(CancelTimer::At),
+        stop_price: meta.stop_price,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_slab_round_trips_orders_and_reuses_slots() {
+        assert_eq!(std::mem::size_of::<CompactOrder>(), 64);
+
+        let mut slab = OrderSlab::default();
+        let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99.50), dec!(10)).with_discretion(dec!(100));
+        let key = slab.insert(&order, Fixed::from_validated(dec!(99.5)), 0);
+        slab.get_mut(key).unwrap().fill(Fixed::from_validated(dec!(4)));
+
+        let stored = slab.order(key, "SOFI").unwrap();
+        assert_eq!((stored.order_id, stored.price, stored.remaining_quantity), (order.order_id, Some(dec!(99.5)), dec!(6)));
+        assert_eq!(stored.status, OrderStatus::PartiallyFilled);
+        assert_eq!(stored.discretion, Some(dec!(100)));
+
+        assert_eq!(slab.remove(key, "SOFI").unwrap().order_id, order.order_id);
+        assert!(slab.is_empty());
+        let other = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101), dec!(1));
+        let reused = slab.insert(&other, Fixed::from_validated(dec!(101)), 0);
+        assert_eq!(reused.slot(), key.slot());
+        assert!(slab.get(key).is_none());
+        assert_eq!(slab.key(&other.order_id), Some(reused));
+    }
+}
@@ -0,0 +1,132 @@
+//! Recorded run results and the comparison of two of them, so a latency
+//! regression between branches shows up as a failed check over saved files.
+
+use crate::utils::{summarize_latencies, LatencyStats};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// The largest slowdown `compare_runs` accepts by default, in percent.
+pub const DEFAULT_REGRESSION_THRESHOLD: f64 = 10.0;
+
+/// What a run measured, as saved by `--save-run`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// The logging modes, joined with `+`.
+    pub modes: String,
+    pub elapsed_ns: u128,
+    pub processing: LatencyStats,
+    pub logging: LatencyStats,
+}
+
+impl RunRecord {
+    /// Summarizes a run's `(processing, logging)` latencies, or returns
+    /// `None` if none were recorded.
+    pub fn new(modes: impl Into<String>, elapsed_ns: u128, latencies: &[(u128, u128)]) -> Option<Self> {
+        let (processing, logging) = summarize_latencies(latencies)?;
+        Some(RunRecord { modes: modes.into(), elapsed_ns, processing, logging })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+}
+
+/// One metric of two runs. Every metric is a time, so higher is worse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDelta {
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub candidate: f64,
+}
+
+impl MetricDelta {
+    /// The candidate's change relative to the baseline, in percent. A zero
+    /// baseline counts any increase as infinitely worse.
+    pub fn change_percent(&self) -> f64 {
+        if self.baseline == 0.0 {
+            return if self.candidate == 0.0 { 0.0 } else { f64::INFINITY };
+        }
+        (self.candidate - self.baseline) / self.baseline * 100.0
+    }
+
+    pub fn regressed(&self, threshold_percent: f64) -> bool {
+        self.change_percent() > threshold_percent
+    }
+}
+
+/// Every percentile of both latency kinds, then the run's elapsed time.
+pub fn compare(baseline: &RunRecord, candidate: &RunRecord) -> Vec<MetricDelta> {
+    let stats = |metric, of: fn(&RunRecord) -> f64| MetricDelta { metric, baseline: of(baseline), candidate: of(candidate) };
+    vec![
+        stats("processing mean", |r| r.processing.mean),
+        stats("processing median", |r| r.processing.median as f64),
+        stats("processing p99", |r| r.processing.p99 as f64),
+        stats("processing p99.9", |r| r.processing.p999 as f64),
+        stats("logging mean", |r| r.logging.mean),
+        stats("logging median", |r| r.logging.median as f64),
+        stats("logging p99", |r| r.logging.p99 as f64),
+        stats("logging p99.9", |r| r.logging.p999 as f64),
+        stats("elapsed", |r| r.elapsed_ns as f64),
+    ]
+}
+
+/// Prints the deltas and returns whether every metric is within the
+/// threshold.
+pub fn print_comparison(deltas: &[MetricDelta], threshold_percent: f64) -> bool {
+    println!("{:<20} {:>16} {:>16} {:>10}  Result", "Metric", "Baseline", "Candidate", "Change");
+    let mut passed = true;
+    for delta in deltas {
+        let regressed = delta.regressed(threshold_percent);
+        passed &= !regressed;
+        println!(
+            "{:<20} {:>16.2} {:>16.2} {:>9.1}%  {}",
+            delta.metric, delta.baseline, delta.candidate, delta.change_percent(), if regressed { "FAIL" } else { "ok" }
+        );
+    }
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(latencies: &[(u128, u128)], elapsed_ns: u128) -> RunRecord {
+        RunRecord::new("ae", elapsed_ns, latencies).unwrap()
+    }
+
+    #[test]
+    fn test_regressions_past_the_threshold_fail() {
+        let baseline = record(&[(100, 10), (200, 20)], 1_000);
+        let candidate = record(&[(100, 10), (230, 20)], 950);
+        let deltas = compare(&baseline, &candidate);
+
+        let median = deltas.iter().find(|d| d.metric == "processing median").unwrap();
+        assert_eq!((median.baseline, median.candidate), (200.0, 230.0));
+        assert!((median.change_percent() - 15.0).abs() < 1e-9);
+        assert!(median.regressed(10.0) && !median.regressed(20.0));
+        let elapsed = deltas.iter().find(|d| d.metric == "elapsed").unwrap();
+        assert!(elapsed.change_percent() < 0.0 && !elapsed.regressed(0.0));
+        assert!(!compare(&baseline, &baseline).iter().any(|d| d.regressed(0.0)));
+    }
+
+    #[test]
+    fn test_records_round_trip_through_json() {
+        let path = std::env::temp_dir().join(format!("run-record-{}.json", std::process::id()));
+        // Means with a short decimal form, since JSON floats may not round-trip exactly.
+        let saved = record(&[(120, 30), (80, 10), (100, 20)], 5_000);
+        saved.save(&path).unwrap();
+        assert_eq!(RunRecord::load(&path).unwrap(), saved);
+        std::fs::remove_file(&path).unwrap();
+        assert!(RunRecord::load(&path).is_err());
+    }
+}
@@ -0,0 +1,641 @@
+use crate::fixed::Fixed;
+use crate::order::Order;
+use crate::utils::MatchingEngineError;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How resting orders at the same price level are allocated against an
+/// incoming order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchingAlgorithm {
+    /// Strict price-time priority.
+    #[default]
+    Fifo,
+    /// Each fill goes to a resting order drawn at random, weighted by its
+    /// remaining size, from a generator seeded with `seed`.
+    Lottery { seed: u64 },
+}
+
+impl FromStr for MatchingAlgorithm {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "fifo" => Ok(MatchingAlgorithm::Fifo),
+            Some(("lottery", seed)) => seed
+                .parse()
+                .map(|seed| MatchingAlgorithm::Lottery { seed })
+                .map_err(|_| format!("Invalid lottery seed '{}'", seed)),
+            _ => Err(format!("Unknown matching algorithm '{}', expected fifo or lottery:<seed>", s)),
+        }
+    }
+}
+
+impl fmt::Display for MatchingAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchingAlgorithm::Fifo => f.write_str("fifo"),
+            MatchingAlgorithm::Lottery { seed } => write!(f, "lottery:{}", seed),
+        }
+    }
+}
+
+/// The price a [`PriceCollar`] measures deviation from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReferencePrice {
+    /// The last traded price, falling back to the mid before the first trade.
+    #[default]
+    LastTrade,
+    /// The midpoint of the best bid and best ask.
+    Mid,
+}
+
+/// Rejects limit orders priced more than `max_deviation` (a fraction, so
+/// `0.1` is 10%) away from the reference price. Orders are not checked while
+/// no reference price is available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceCollar {
+    pub max_deviation: Decimal,
+    pub reference: ReferencePrice,
+}
+
+impl PriceCollar {
+    pub fn new(max_deviation: Decimal) -> Self {
+        Self { max_deviation, reference: ReferencePrice::default() }
+    }
+
+    pub fn check(&self, price: Decimal, reference: Decimal) -> Result<(), MatchingEngineError> {
+        let band = reference * self.max_deviation;
+        if (price - reference).abs() > band {
+            return Err(MatchingEngineError::PriceOutsideCollar { price, reference, max_deviation: self.max_deviation });
+        }
+        Ok(())
+    }
+}
+
+/// How long the trades behind a [`PriceBand`] reference count, unless the
+/// band names another window.
+pub const DEFAULT_BAND_WINDOW: Duration = Duration::from_secs(300);
+/// How many times wider a band is while it re-establishes its reference
+/// after a halt.
+pub const DEFAULT_HALT_WIDENING: Decimal = Decimal::TWO;
+
+/// What happens to a limit order priced through a [`PriceBand`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BandAction {
+    #[default]
+    Reject,
+    /// The order is repriced to the band edge and trades and rests there.
+    Slice,
+}
+
+/// Limit up-limit down bands: no trade executes more than `width` (a
+/// fraction) away from the trade-weighted average price of the last
+/// `window` of engine time. Market orders trade up to the band edge and
+/// expire the rest; limit orders priced through it are handled by `action`.
+/// For `window` after a halt ends the band is `halt_widening` times wider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceBand {
+    pub width: Decimal,
+    pub window: Duration,
+    pub halt_widening: Decimal,
+    pub action: BandAction,
+}
+
+impl PriceBand {
+    pub fn new(width: Decimal) -> Self {
+        Self { width, window: DEFAULT_BAND_WINDOW, halt_widening: DEFAULT_HALT_WIDENING, action: BandAction::default() }
+    }
+}
+
+impl FromStr for PriceBand {
+    type Err = String;
+
+    /// Parses `<width>[:<window_ms>][:reject|:slice]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let width = parts
+            .next()
+            .and_then(|width| Decimal::from_str(width).ok())
+            .filter(|width| *width > Decimal::ZERO && *width < Decimal::ONE)
+            .ok_or_else(|| format!("Invalid price band '{}', expected a width between 0 and 1", s))?;
+        let mut band = PriceBand::new(width);
+        for part in parts {
+            match part {
+                "reject" => band.action = BandAction::Reject,
+                "slice" => band.action = BandAction::Slice,
+                millis => {
+                    let millis = millis.parse::<u64>().ok().filter(|&millis| millis > 0).ok_or_else(|| format!("Invalid price band window '{}'", millis))?;
+                    band.window = Duration::from_millis(millis);
+                }
+            }
+        }
+        Ok(band)
+    }
+}
+
+/// What happens to a price or quantity with more decimals than its
+/// instrument's [`Precision`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrecisionAction {
+    Reject,
+    Round(RoundingStrategy),
+}
+
+impl Default for PrecisionAction {
+    fn default() -> Self {
+        PrecisionAction::Round(RoundingStrategy::MidpointNearestEven)
+    }
+}
+
+/// Decimal places an instrument keeps prices and quantities to. Accepted
+/// orders are rescaled to exactly that many, so `100.0` and `100.00` rest on
+/// and print as the same level; inputs with more places are rounded or
+/// rejected according to `action`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Precision {
+    pub price_decimals: Option<u32>,
+    pub quantity_decimals: Option<u32>,
+    pub action: PrecisionAction,
+}
+
+impl Precision {
+    /// `price` written with the instrument's price decimals, for display.
+    pub fn scale_price(&self, price: Decimal) -> Decimal {
+        rescaled(price, self.price_decimals)
+    }
+
+    /// `quantity` written with the instrument's quantity decimals.
+    pub fn scale_quantity(&self, quantity: Decimal) -> Decimal {
+        rescaled(quantity, self.quantity_decimals)
+    }
+
+    fn normalize(&self, value: Decimal, decimals: Option<u32>) -> Result<Decimal, MatchingEngineError> {
+        let Some(decimals) = decimals else { return Ok(value) };
+        let mut normalized = match self.action {
+            PrecisionAction::Round(strategy) => value.round_dp_with_strategy(decimals, strategy),
+            PrecisionAction::Reject if value.round_dp(decimals) != value => {
+                return Err(MatchingEngineError::InstrumentPrecision { value, decimals });
+            }
+            PrecisionAction::Reject => value,
+        };
+        normalized.rescale(decimals);
+        Ok(normalized)
+    }
+}
+
+fn rescaled(mut value: Decimal, decimals: Option<u32>) -> Decimal {
+    if let Some(decimals) = decimals {
+        value.rescale(decimals);
+    }
+    value
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    /// Parses `<price_decimals>:<quantity_decimals>[:reject|:half-even|:half-up|:down|:up]`.
+    /// Either count may be left empty to keep that side as it comes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid precision '{}', expected <price_decimals>:<quantity_decimals>[:reject|:half-even|:half-up|:down|:up]", s);
+        let decimals = |part: Option<&str>| match part {
+            Some("") => Ok(None),
+            Some(part) => part.parse::<u32>().ok().filter(|&decimals| decimals <= Fixed::SCALE).map(Some).ok_or_else(invalid),
+            None => Err(invalid()),
+        };
+        let mut parts = s.split(':');
+        let price_decimals = decimals(parts.next())?;
+        let quantity_decimals = decimals(parts.next())?;
+        let action = match parts.next() {
+            None | Some("half-even") => PrecisionAction::Round(RoundingStrategy::MidpointNearestEven),
+            Some("half-up") => PrecisionAction::Round(RoundingStrategy::MidpointAwayFromZero),
+            Some("down") => PrecisionAction::Round(RoundingStrategy::ToZero),
+            Some("up") => PrecisionAction::Round(RoundingStrategy::AwayFromZero),
+            Some("reject") => PrecisionAction::Reject,
+            Some(_) => return Err(invalid()),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Precision { price_decimals, quantity_decimals, action })
+    }
+}
+
+/// The largest funding rate a [`Perpetual`] charges per interval, unless it
+/// names another cap.
+pub const DEFAULT_FUNDING_RATE_CAP: Decimal = Decimal::from_parts(75, 0, 0, false, 4);
+
+/// Makes an instrument a perpetual swap. At the end of every `funding_interval`
+/// of engine time, holders exchange funding at the premium of the mark over
+/// `index_price`, capped at `rate_cap` either way: longs pay shorts while
+/// the perpetual trades above the index, and shorts pay longs below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Perpetual {
+    pub index_price: Decimal,
+    pub funding_interval: Duration,
+    pub rate_cap: Decimal,
+}
+
+impl Perpetual {
+    pub fn new(index_price: Decimal, funding_interval: Duration) -> Self {
+        Self { index_price, funding_interval, rate_cap: DEFAULT_FUNDING_RATE_CAP }
+    }
+
+    /// The funding rate for a mark of `mark`: its premium over the index,
+    /// capped.
+    pub fn funding_rate(&self, mark: Decimal) -> Decimal {
+        ((mark - self.index_price) / self.index_price).clamp(-self.rate_cap, self.rate_cap)
+    }
+}
+
+impl FromStr for Perpetual {
+    type Err = String;
+
+    /// Parses `<index_price>:<interval_ms>[:<rate_cap>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid perpetual '{}', expected <index_price>:<interval_ms>[:<rate_cap>]", s);
+        let mut parts = s.split(':');
+        let index_price = parts.next().and_then(|price| Decimal::from_str(price).ok()).ok_or_else(invalid)?;
+        let millis = parts.next().and_then(|millis| millis.parse::<u64>().ok()).ok_or_else(invalid)?;
+        let mut perpetual = Perpetual::new(index_price, Duration::from_millis(millis));
+        if let Some(cap) = parts.next() {
+            perpetual.rate_cap = Decimal::from_str(cap).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(perpetual)
+    }
+}
+
+/// Whether an option is the right to buy or to sell its underlying.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    Call,
+    Put,
+}
+
+/// Makes an instrument an option on `underlying`. Its book matches like any
+/// other until `expiry` on the engine clock, or the end of the session when
+/// there is none. Then positions in the money against the underlying's
+/// settlement price are exercised at `strike`, and the rest expire.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OptionContract {
+    pub kind: OptionKind,
+    pub underlying: String,
+    pub strike: Decimal,
+    pub expiry: Option<u64>,
+}
+
+impl OptionContract {
+    /// How far one option is in the money at `settlement`, or zero.
+    pub fn intrinsic_value(&self, settlement: Decimal) -> Decimal {
+        let value = match self.kind {
+            OptionKind::Call => settlement - self.strike,
+            OptionKind::Put => self.strike - settlement,
+        };
+        value.max(Decimal::ZERO)
+    }
+}
+
+impl FromStr for OptionContract {
+    type Err = String;
+
+    /// Parses `<call|put>:<underlying>:<strike>[:<expiry_ns>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid option '{}', expected <call|put>:<underlying>:<strike>[:<expiry_ns>]", s);
+        let parts: Vec<&str> = s.split(':').collect();
+        let (kind, underlying, strike, expiry) = match parts[..] {
+            [kind, underlying, strike] => (kind, underlying, strike, None),
+            [kind, underlying, strike, expiry] => (kind, underlying, strike, Some(expiry.parse::<u64>().map_err(|_| invalid())?)),
+            _ => return Err(invalid()),
+        };
+        let kind = match kind {
+            "call" => OptionKind::Call,
+            "put" => OptionKind::Put,
+            _ => return Err(invalid()),
+        };
+        let strike = Decimal::from_str(strike).map_err(|_| invalid())?;
+        Ok(OptionContract { kind, underlying: underlying.to_string(), strike, expiry })
+    }
+}
+
+/// Per-instrument trading rules. `None` disables the corresponding check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstrumentConfig {
+    pub tick_size: Option<Decimal>,
+    pub lot_size: Option<Decimal>,
+    pub matching: MatchingAlgorithm,
+    pub price_collar: Option<PriceCollar>,
+    pub price_band: Option<PriceBand>,
+    /// Holds orders that would take liquidity for this long (on the engine
+    /// clock) before they reach the book. Orders that only post liquidity are
+    /// not delayed.
+    pub speed_bump: Option<Duration>,
+    /// Trades the instrument in frequent batch auctions instead of
+    /// continuously: orders are collected over each interval of engine time
+    /// and uncrossed together at a single price when it ends.
+    pub batch_interval: Option<Duration>,
+    pub perpetual: Option<Perpetual>,
+    pub option: Option<OptionContract>,
+    /// The index the instrument takes its reference price from: a
+    /// perpetual's index price, an option's settlement price and the centre
+    /// of the price band. It must be defined on the engine.
+    pub index: Option<String>,
+    pub precision: Option<Precision>,
+    /// Merges the displayed price levels into buckets this wide, fx 0.25
+    /// for an instrument with a 0.05 tick. Matching still uses every level.
+    pub price_grouping: Option<Decimal>,
+}
+
+impl InstrumentConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tick_size.is_some_and(|tick| tick <= Decimal::ZERO) {
+            return Err("tick size must be positive".to_string());
+        }
+        if self.lot_size.is_some_and(|lot| lot <= Decimal::ZERO) {
+            return Err("lot size must be positive".to_string());
+        }
+        if self.price_collar.is_some_and(|collar| collar.max_deviation <= Decimal::ZERO) {
+            return Err("price collar deviation must be positive".to_string());
+        }
+        if self.price_band.is_some_and(|band| band.width <= Decimal::ZERO || band.window.is_zero() || band.halt_widening < Decimal::ONE) {
+            return Err("price band needs a positive width and window, and cannot narrow after halts".to_string());
+        }
+        if self.price_grouping.is_some_and(|group| group <= Decimal::ZERO) {
+            return Err("price grouping must be positive".to_string());
+        }
+        if self.speed_bump.is_some_and(|delay| delay.is_zero()) {
+            return Err("speed bump must be positive".to_string());
+        }
+        if self.batch_interval.is_some_and(|interval| interval.is_zero()) {
+            return Err("batch interval must be positive".to_string());
+        }
+        if let Some(perpetual) = self.perpetual
+            && (perpetual.index_price <= Decimal::ZERO || perpetual.funding_interval.is_zero() || perpetual.rate_cap <= Decimal::ZERO) {
+            return Err("perpetual needs a positive index price, funding interval and rate cap".to_string());
+        }
+        if let Some(option) = &self.option {
+            if option.strike <= Decimal::ZERO || option.underlying.is_empty() {
+                return Err("option needs an underlying and a positive strike".to_string());
+            }
+            if self.perpetual.is_some() || self.batch_interval.is_some() {
+                return Err("options cannot be perpetuals or trade in batch auctions".to_string());
+            }
+        }
+        if self.speed_bump.is_some() && self.batch_interval.is_some() {
+            return Err("speed bump and batch auctions cannot be combined".to_string());
+        }
+        if let Some(precision) = self.precision {
+            let finer = |size: Option<Decimal>, decimals: Option<u32>| size.zip(decimals).is_some_and(|(size, decimals)| size.normalize().scale() > decimals);
+            if finer(self.tick_size, precision.price_decimals) || finer(self.lot_size, precision.quantity_decimals) {
+                return Err("tick and lot sizes cannot be finer than the precision".to_string());
+            }
+            if precision.price_decimals.max(precision.quantity_decimals).is_some_and(|decimals| decimals > Fixed::SCALE) {
+                return Err(format!("precision cannot exceed {} decimals", Fixed::SCALE));
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings a new order's prices and quantity to the instrument's
+    /// precision.
+    pub fn normalize_order(&self, order: &mut Order) -> Result<(), MatchingEngineError> {
+        let Some(precision) = self.precision else { return Ok(()) };
+        let limit = order.peg.as_mut().and_then(|peg| peg.limit.as_mut());
+        for price in [order.price.as_mut(), order.discretion.as_mut(), order.stop_price.as_mut(), limit].into_iter().flatten() {
+            *price = precision.normalize(*price, precision.price_decimals)?;
+        }
+        let filled = order.quantity - order.remaining_quantity;
+        let quantity = precision.normalize(order.quantity, precision.quantity_decimals)?;
+        if quantity.is_zero() && !order.quantity.is_zero() {
+            return Err(MatchingEngineError::InstrumentPrecision { value: order.quantity, decimals: precision.quantity_decimals.unwrap_or_default() });
+        }
+        order.quantity = quantity;
+        order.remaining_quantity = order.quantity - filled;
+        Ok(())
+    }
+
+    pub fn check_order(&self, order: &Order) -> Result<(), MatchingEngineError> {
+        if let Some(tick) = self.tick_size {
+            for price in [order.price, order.discretion].into_iter().flatten() {
+                if !(price % tick).is_zero() {
+                    return Err(MatchingEngineError::InvalidTickSize { price, tick_size: tick });
+                }
+            }
+        }
+        if let Some(lot) = self.lot_size
+            && !(order.quantity % lot).is_zero() {
+            return Err(MatchingEngineError::InvalidLotSize { quantity: order.quantity, lot_size: lot });
+        }
+        Ok(())
+    }
+}
+
+/// Engine-wide pre-trade limits applied to every incoming order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RiskLimits {
+    pub max_order_quantity: Option<Decimal>,
+    pub max_order_notional: Option<Decimal>,
+}
+
+impl RiskLimits {
+    pub fn check_order(&self, order: &Order) -> Result<(), MatchingEngineError> {
+        if let Some(max) = self.max_order_quantity
+            && order.quantity > max {
+            return Err(MatchingEngineError::RiskLimitExceeded(format!("quantity {} exceeds maximum {}", order.quantity, max)));
+        }
+        if let (Some(max), Some(price)) = (self.max_order_notional, order.price)
+            && price * order.quantity > max {
+            return Err(MatchingEngineError::RiskLimitExceeded(format!("notional {} exceeds maximum {}", price * order.quantity, max)));
+        }
+        Ok(())
+    }
+}
+
+/// Hard caps on how much the engine holds, so a flood of orders or markets
+/// is rejected instead of growing memory without bound. Each is off unless
+/// set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapacityLimits {
+    /// Open orders in one instrument: resting, held by a speed bump or
+    /// batch, and parked stops.
+    pub max_orders_per_instrument: Option<usize>,
+    /// Resting orders of one client across every instrument.
+    pub max_orders_per_client: Option<usize>,
+    /// Price levels with orders resting on each side of a book.
+    pub max_price_levels: Option<usize>,
+    /// Registered markets, those created on their first order included.
+    pub max_instruments: Option<usize>,
+}
+
+impl CapacityLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        let limits = [self.max_orders_per_instrument, self.max_orders_per_client, self.max_price_levels, self.max_instruments];
+        if limits.contains(&Some(0)) {
+            return Err("capacity limits must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Fails if `count` markets leave no room for another.
+    pub fn check_instruments(&self, count: usize) -> Result<(), MatchingEngineError> {
+        match self.max_instruments {
+            Some(max) if count >= max => Err(MatchingEngineError::CapacityExceeded(format!("{} instruments already registered", max))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for CapacityLimits {
+    type Err = String;
+
+    /// Parses a comma separated list of `orders=<n>`, `client-orders=<n>`,
+    /// `levels=<n>` and `instruments=<n>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut limits = CapacityLimits::default();
+        for part in s.split(',') {
+            let invalid = || format!("Invalid capacity limit '{}', expected orders|client-orders|levels|instruments=<n>", part);
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            let value = value.parse::<usize>().ok().filter(|&value| value > 0).ok_or_else(invalid)?;
+            let limit = match key {
+                "orders" => &mut limits.max_orders_per_instrument,
+                "client-orders" => &mut limits.max_orders_per_client,
+                "levels" => &mut limits.max_price_levels,
+                "instruments" => &mut limits.max_instruments,
+                _ => return Err(invalid()),
+            };
+            *limit = Some(value);
+        }
+        Ok(limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn limit(price: Decimal, quantity: Decimal) -> Order {
+        Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, price, quantity)
+    }
+
+    #[test]
+    fn test_tick_and_lot_size_checks() {
+        let config = InstrumentConfig { tick_size: Some(dec!(0.05)), lot_size: Some(dec!(10)), ..Default::default() };
+        assert!(config.check_order(&limit(dec!(100.05), dec!(20))).is_ok());
+        assert!(matches!(config.check_order(&limit(dec!(100.03), dec!(20))), Err(MatchingEngineError::InvalidTickSize { .. })));
+        assert!(matches!(config.check_order(&limit(dec!(100.05), dec!(25))), Err(MatchingEngineError::InvalidLotSize { .. })));
+    }
+
+    #[test]
+    fn test_precision_rounds_or_rejects_extra_decimals() {
+        let precision = "2:0".parse::<Precision>().unwrap();
+        let config = InstrumentConfig { precision: Some(precision), ..Default::default() };
+        let mut order = limit(dec!(100.125), dec!(10.5));
+        config.normalize_order(&mut order).unwrap();
+        assert_eq!((order.price.unwrap().to_string(), order.quantity, order.remaining_quantity), ("100.12".to_string(), dec!(10), dec!(10)));
+        let mut order = limit(dec!(100.1), dec!(10));
+        config.normalize_order(&mut order).unwrap();
+        assert_eq!(order.price.unwrap().to_string(), "100.10");
+
+        let config = InstrumentConfig { precision: Some("2::reject".parse().unwrap()), ..Default::default() };
+        assert!(config.normalize_order(&mut limit(dec!(100.10), dec!(10.123))).is_ok());
+        assert!(matches!(config.normalize_order(&mut limit(dec!(100.125), dec!(10))), Err(MatchingEngineError::InstrumentPrecision { decimals: 2, .. })));
+
+        assert_eq!("4:2:down".parse::<Precision>().map(|precision| precision.action), Ok(PrecisionAction::Round(RoundingStrategy::ToZero)));
+        assert!(config.normalize_order(&mut limit(dec!(100), dec!(0.4))).is_ok());
+        assert!(InstrumentConfig { precision: Some(precision), ..Default::default() }.normalize_order(&mut limit(dec!(100), dec!(0.4))).is_err());
+        assert!("9:0".parse::<Precision>().is_err());
+        assert!("2:0:nearest".parse::<Precision>().is_err());
+        assert!(InstrumentConfig { tick_size: Some(dec!(0.005)), precision: Some(precision), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        assert!(InstrumentConfig { tick_size: Some(dec!(0)), ..Default::default() }.validate().is_err());
+        assert!(InstrumentConfig { price_collar: Some(PriceCollar::new(dec!(-0.1))), ..Default::default() }.validate().is_err());
+        assert!(InstrumentConfig { speed_bump: Some(Duration::ZERO), ..Default::default() }.validate().is_err());
+        assert!(InstrumentConfig { batch_interval: Some(Duration::ZERO), ..Default::default() }.validate().is_err());
+        let both = InstrumentConfig {
+            speed_bump: Some(Duration::from_micros(350)),
+            batch_interval: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        assert!(both.validate().is_err());
+        assert!(InstrumentConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_capacity_limits() {
+        let limits = "orders=1000,levels=50".parse::<CapacityLimits>().unwrap();
+        assert_eq!(limits, CapacityLimits { max_orders_per_instrument: Some(1000), max_price_levels: Some(50), ..Default::default() });
+        assert_eq!("client-orders=10,instruments=2".parse::<CapacityLimits>().map(|limits| (limits.max_orders_per_client, limits.max_instruments)), Ok((Some(10), Some(2))));
+        assert!("orders=0".parse::<CapacityLimits>().is_err());
+        assert!("depth=10".parse::<CapacityLimits>().is_err());
+        assert!("orders".parse::<CapacityLimits>().is_err());
+        assert!(limits.check_instruments(5).is_ok());
+        assert!(CapacityLimits { max_instruments: Some(2), ..Default::default() }.check_instruments(2).is_err());
+    }
+
+    #[test]
+    fn test_parse_matching_algorithm() {
+        assert_eq!("fifo".parse(), Ok(MatchingAlgorithm::Fifo));
+        assert_eq!("lottery:42".parse(), Ok(MatchingAlgorithm::Lottery { seed: 42 }));
+        assert!("lottery".parse::<MatchingAlgorithm>().is_err());
+        assert!("lottery:x".parse::<MatchingAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_parse_price_band() {
+        assert_eq!("0.05".parse::<PriceBand>(), Ok(PriceBand::new(dec!(0.05))));
+        let band = "0.1:60000:slice".parse::<PriceBand>().unwrap();
+        assert_eq!((band.window, band.action), (Duration::from_secs(60), BandAction::Slice));
+        assert!("1.5".parse::<PriceBand>().is_err());
+        assert!("0.1:0".parse::<PriceBand>().is_err());
+        assert!("0.1:clip".parse::<PriceBand>().is_err());
+    }
+
+    #[test]
+    fn test_perpetual_funding_rate_is_the_capped_premium() {
+        let perpetual = "100:3600000".parse::<Perpetual>().unwrap();
+        assert_eq!(perpetual.funding_interval, Duration::from_secs(3600));
+        assert_eq!(perpetual.funding_rate(dec!(100.5)), dec!(0.005));
+        assert_eq!(perpetual.funding_rate(dec!(90)), dec!(-0.0075));
+        assert_eq!("100:1000:0.01".parse::<Perpetual>().unwrap().funding_rate(dec!(99.2)), dec!(-0.008));
+        assert!("100".parse::<Perpetual>().is_err());
+        assert!(InstrumentConfig { perpetual: Some(Perpetual::new(dec!(0), Duration::from_secs(1))), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_option_intrinsic_value() {
+        let call = "call:SOFI:10:5000".parse::<OptionContract>().unwrap();
+        assert_eq!((call.underlying.as_str(), call.expiry), ("SOFI", Some(5000)));
+        assert_eq!((call.intrinsic_value(dec!(12)), call.intrinsic_value(dec!(9))), (dec!(2), dec!(0)));
+        let put = "put:SOFI:10".parse::<OptionContract>().unwrap();
+        assert_eq!((put.intrinsic_value(dec!(9)), put.expiry), (dec!(1), None));
+        assert!("straddle:SOFI:10".parse::<OptionContract>().is_err());
+        assert!(InstrumentConfig { option: Some(OptionContract { strike: dec!(0), ..put }), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_price_collar_band() {
+        let collar = PriceCollar::new(dec!(0.1));
+        assert!(collar.check(dec!(110), dec!(100)).is_ok());
+        assert!(collar.check(dec!(90), dec!(100)).is_ok());
+        assert!(matches!(collar.check(dec!(110.01), dec!(100)), Err(MatchingEngineError::PriceOutsideCollar { .. })));
+        assert!(matches!(collar.check(dec!(89.99), dec!(100)), Err(MatchingEngineError::PriceOutsideCollar { .. })));
+    }
+
+    #[test]
+    fn test_risk_limits() {
+        let limits = RiskLimits { max_order_quantity: Some(dec!(100)), max_order_notional: Some(dec!(5000)) };
+        assert!(limits.check_order(&limit(dec!(10), dec!(100))).is_ok());
+        assert!(matches!(limits.check_order(&limit(dec!(10), dec!(101))), Err(MatchingEngineError::RiskLimitExceeded(_))));
+        assert!(matches!(limits.check_order(&limit(dec!(100), dec!(60))), Err(MatchingEngineError::RiskLimitExceeded(_))));
+    }
+}
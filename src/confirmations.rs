@@ -0,0 +1,299 @@
+//! Private drop copies: each client's own order acknowledgements, executions
+//! and order state changes, routed off the engine's events by the client that
+//! entered each order, separately from the public market data.
+
+use crate::clients::ClientId;
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::trade::Trade;
+use crate::trade_store::DEFAULT_TRADE_RETENTION;
+use crate::utils::{OrderStatus, Side, UuidMap};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// One message of a client's drop copy.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Confirmation {
+    /// Position in the client's own stream, from 1, so a gap shows.
+    pub sequence: u64,
+    pub order_id: Uuid,
+    pub instrument: String,
+    #[serde(flatten)]
+    pub kind: ConfirmationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum ConfirmationKind {
+    Accepted { side: Side, price: Option<Decimal>, quantity: Decimal },
+    Rejected { reason: String },
+    /// What was left of the order after matching now rests in the book.
+    Rested { price: Option<Decimal>, remaining: Decimal },
+    /// One fill of the order. `maker` is true if the order was resting.
+    Execution { trade_id: Uuid, side: Side, price: Decimal, quantity: Decimal, maker: bool },
+    /// The order is done: filled, or the unfilled rest of an immediate order
+    /// expired.
+    Done { status: OrderStatus, filled: Decimal },
+    Canceled { remaining: Decimal },
+    /// A good-till-date order reached its expiry time.
+    Expired { remaining: Decimal },
+    /// A pegged order moved with its reference and lost its time priority.
+    Repriced { old_price: Decimal, price: Option<Decimal> },
+    /// An execution of the order was busted. The fill is reversed.
+    Busted { trade_id: Uuid, price: Decimal, quantity: Decimal },
+}
+
+/// Where a [`ConfirmationSink`] delivers each client's confirmations.
+pub trait ConfirmationOutput: Send {
+    fn deliver(&mut self, client: &ClientId, confirmation: &Confirmation) -> io::Result<()>;
+}
+
+/// Writes each client's confirmations as JSON lines to `<client>.jsonl` in a
+/// directory, creating the file at the client's first confirmation.
+pub struct ConfirmationFiles {
+    dir: PathBuf,
+    files: HashMap<ClientId, BufWriter<File>>,
+}
+
+impl ConfirmationFiles {
+    pub fn create(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(ConfirmationFiles { dir, files: HashMap::new() })
+    }
+
+    /// The file a client's confirmations go to. Characters that could leave
+    /// the directory or are awkward in a file name become `_`.
+    pub fn path(&self, client: &ClientId) -> PathBuf {
+        let name: String = client.0.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+        self.dir.join(format!("{}.jsonl", name))
+    }
+}
+
+impl ConfirmationOutput for ConfirmationFiles {
+    fn deliver(&mut self, client: &ClientId, confirmation: &Confirmation) -> io::Result<()> {
+        if !self.files.contains_key(client) {
+            let file = File::create(self.path(client))?;
+            self.files.insert(client.clone(), BufWriter::new(file));
+        }
+        let writer = self.files.get_mut(client).expect("inserted above");
+        serde_json::to_writer(&mut *writer, confirmation)?;
+        writer.write_all(b"\n")
+    }
+}
+
+/// Hands each client's confirmations to the channel it subscribed with.
+/// Confirmations for clients without one are dropped. Clones share the
+/// subscriptions, so clients can subscribe after the sink is registered.
+#[derive(Debug, Clone, Default)]
+pub struct ConfirmationChannels {
+    senders: Arc<Mutex<HashMap<ClientId, Sender<Confirmation>>>>,
+}
+
+impl ConfirmationChannels {
+    /// Starts a channel for `client`, replacing any earlier one.
+    pub fn subscribe(&self, client: ClientId) -> Receiver<Confirmation> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.lock().expect("confirmation channels poisoned").insert(client, sender);
+        receiver
+    }
+}
+
+impl ConfirmationOutput for ConfirmationChannels {
+    fn deliver(&mut self, client: &ClientId, confirmation: &Confirmation) -> io::Result<()> {
+        let mut senders = self.senders.lock().expect("confirmation channels poisoned");
+        if let Some(sender) = senders.get(client)
+            && sender.send(confirmation.clone()).is_err() {
+            // The receiver is gone; stop sending until the client subscribes again.
+            senders.remove(client);
+        }
+        Ok(())
+    }
+}
+
+/// Routes engine events to the clients whose orders they concern. An
+/// order's client is learned when it is accepted, and a trade goes to the
+/// clients on both sides of it. Orders without a client get no
+/// confirmations.
+pub struct ConfirmationSink<O: ConfirmationOutput> {
+    output: O,
+    owners: UuidMap<ClientId>,
+    /// The buyer and seller of recent trades, for confirming busts after
+    /// the orders are done. Oldest first, as many as the engine keeps.
+    parties: UuidMap<[Option<ClientId>; 2]>,
+    recent: VecDeque<Uuid>,
+    sequences: HashMap<ClientId, u64>,
+    failed: bool,
+}
+
+impl<O: ConfirmationOutput> ConfirmationSink<O> {
+    pub fn new(output: O) -> Self {
+        ConfirmationSink { output, owners: UuidMap::default(), parties: UuidMap::default(), recent: VecDeque::new(), sequences: HashMap::new(), failed: false }
+    }
+
+    fn send(&mut self, client: &ClientId, order_id: Uuid, instrument: &str, kind: ConfirmationKind) {
+        let sequence = self.sequences.entry(client.clone()).or_default();
+        *sequence += 1;
+        let confirmation = Confirmation { sequence: *sequence, order_id, instrument: instrument.to_string(), kind };
+        if let Err(e) = self.output.deliver(client, &confirmation) {
+            if !self.failed {
+                eprintln!("Failed to deliver confirmations to {}: {}", client, e);
+            }
+            self.failed = true;
+        }
+    }
+
+    /// Confirms to the owner of `order_id`, if it has one.
+    fn send_to_owner(&mut self, order_id: Uuid, instrument: &str, kind: ConfirmationKind) {
+        if let Some(client) = self.owners.get(&order_id).cloned() {
+            self.send(&client, order_id, instrument, kind);
+        }
+    }
+
+    fn on_trade(&mut self, trade: &Trade) {
+        let buyer = self.owners.get(&trade.buy_order_id).cloned();
+        let seller = self.owners.get(&trade.sell_order_id).cloned();
+        for (client, order_id, side) in [(&buyer, trade.buy_order_id, Side::Buy), (&seller, trade.sell_order_id, Side::Sell)] {
+            if let Some(client) = client {
+                let kind = ConfirmationKind::Execution { trade_id: trade.trade_id, side, price: trade.price, quantity: trade.quantity, maker: trade.taker_side != side };
+                self.send(client, order_id, &trade.instrument, kind);
+            }
+        }
+        if buyer.is_some() || seller.is_some() {
+            self.parties.insert(trade.trade_id, [buyer, seller]);
+            self.recent.push_back(trade.trade_id);
+            if self.recent.len() > DEFAULT_TRADE_RETENTION
+                && let Some(oldest) = self.recent.pop_front() {
+                self.parties.remove(&oldest);
+            }
+        }
+    }
+
+    fn on_bust(&mut self, trade: &Trade) {
+        let Some([buyer, seller]) = self.parties.remove(&trade.trade_id) else { return };
+        for (client, order_id) in [(buyer, trade.buy_order_id), (seller, trade.sell_order_id)] {
+            if let Some(client) = client {
+                let kind = ConfirmationKind::Busted { trade_id: trade.trade_id, price: trade.price, quantity: trade.quantity };
+                self.send(&client, order_id, &trade.instrument, kind);
+            }
+        }
+    }
+}
+
+impl<O: ConfirmationOutput> EngineSubscriber for ConfirmationSink<O> {
+    fn on_event(&mut self, event: &EngineEvent) {
+        match event {
+            EngineEvent::OrderAccepted { order, client: Some(client) } => {
+                self.owners.insert(order.order_id, (*client).clone());
+                let kind = ConfirmationKind::Accepted { side: order.side, price: order.price, quantity: order.quantity };
+                self.send(client, order.order_id, &order.instrument, kind);
+            }
+            EngineEvent::OrderRejected { order, reason, client: Some(client) } => {
+                self.send(client, order.order_id, &order.instrument, ConfirmationKind::Rejected { reason: reason.to_string() });
+            }
+            EngineEvent::OrderRested(order) => {
+                let kind = ConfirmationKind::Rested { price: order.price, remaining: order.remaining_quantity };
+                self.send_to_owner(order.order_id, &order.instrument, kind);
+            }
+            EngineEvent::Trade(trade) => self.on_trade(trade),
+            EngineEvent::OrderFilled { order, .. } => {
+                let kind = ConfirmationKind::Done { status: order.status, filled: order.quantity - order.remaining_quantity };
+                self.send_to_owner(order.order_id, &order.instrument, kind);
+                self.owners.remove(&order.order_id);
+            }
+            EngineEvent::OrderCanceled(order) => {
+                self.send_to_owner(order.order_id, &order.instrument, ConfirmationKind::Canceled { remaining: order.remaining_quantity });
+                self.owners.remove(&order.order_id);
+            }
+            EngineEvent::OrderExpired(order) => {
+                self.send_to_owner(order.order_id, &order.instrument, ConfirmationKind::Expired { remaining: order.remaining_quantity });
+                self.owners.remove(&order.order_id);
+            }
+            EngineEvent::OrderRepriced { order, old_price } => {
+                let kind = ConfirmationKind::Repriced { old_price: *old_price, price: order.price };
+                self.send_to_owner(order.order_id, &order.instrument, kind);
+            }
+            EngineEvent::TradeBusted(trade) => self.on_bust(trade),
+            EngineEvent::OrderAccepted { client: None, .. } | EngineEvent::OrderRejected { client: None, .. } | EngineEvent::IndexPrice { .. } | EngineEvent::Imbalance { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::{create_logger, LoggingMode};
+    use crate::order::Order;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_each_client_sees_only_its_own_orders() {
+        let channels = ConfirmationChannels::default();
+        let alice = channels.subscribe(ClientId::new("alice"));
+        let bob = channels.subscribe(ClientId::new("bob"));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .subscriber(Box::new(ConfirmationSink::new(channels.clone())))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut submit = |engine: &mut MatchingEngine, client: Option<&str>, side, price, quantity| {
+            let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+            let order_id = order.order_id;
+            let _ = engine.submit_order(order, client.map(ClientId::new), &mut logger);
+            order_id
+        };
+
+        let ask = submit(&mut engine, Some("alice"), Side::Sell, dec!(100), dec!(5));
+        // An order without a client trades with alice's but is confirmed to no one.
+        submit(&mut engine, None, Side::Buy, dec!(100), dec!(2));
+        let bid = submit(&mut engine, Some("bob"), Side::Buy, dec!(100), dec!(3));
+        let unknown = Order::new_limit(Uuid::new_v4(), "NONE".to_string(), Side::Buy, dec!(100), dec!(3));
+        assert!(engine.submit_order(unknown, Some(ClientId::new("bob")), &mut create_logger(LoggingMode::Baseline)).is_err());
+        let trades = engine.recent_trades("SOFI", 1).into_iter().map(|trade| trade.trade_id).collect::<Vec<_>>();
+        engine.bust_trade("SOFI", &trades[0]).unwrap();
+        drop(engine);
+
+        let kinds = |receiver: &Receiver<Confirmation>| receiver.try_iter().map(|confirmation| (confirmation.sequence, confirmation.order_id, confirmation.kind)).collect::<Vec<_>>();
+        let alice = kinds(&alice);
+        assert_eq!(alice.len(), 6);
+        assert!(alice.iter().all(|(_, order_id, _)| *order_id == ask));
+        assert_eq!(alice.iter().map(|(sequence, ..)| *sequence).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+        assert!(matches!(alice[1].2, ConfirmationKind::Rested { remaining, .. } if remaining == dec!(5)));
+        assert!(matches!(alice[2].2, ConfirmationKind::Execution { quantity, maker: true, side: Side::Sell, .. } if quantity == dec!(2)));
+        assert!(matches!(alice[4].2, ConfirmationKind::Done { status: OrderStatus::Filled, filled, .. } if filled == dec!(5)));
+        assert!(matches!(alice[5].2, ConfirmationKind::Busted { quantity, .. } if quantity == dec!(3)));
+
+        let bob = kinds(&bob);
+        let bob_kinds: Vec<&ConfirmationKind> = bob.iter().map(|(_, _, kind)| kind).collect();
+        assert!(matches!(bob_kinds[..], [ConfirmationKind::Accepted { .. }, ConfirmationKind::Execution { maker: false, .. }, ConfirmationKind::Done { .. }, ConfirmationKind::Rejected { .. }, ConfirmationKind::Busted { .. }]));
+        assert_eq!(bob[1].1, bid);
+    }
+
+    #[test]
+    fn test_files_hold_one_json_line_per_confirmation() {
+        let dir = std::env::temp_dir().join(format!("confirmations-{}", Uuid::new_v4()));
+        let mut sink = ConfirmationSink::new(ConfirmationFiles::create(&dir).unwrap());
+        let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99), dec!(1));
+        let client = ClientId::new("../desk 1");
+        sink.on_event(&EngineEvent::OrderAccepted { order: &order, client: Some(&client) });
+        sink.on_event(&EngineEvent::OrderCanceled(&order));
+        drop(sink);
+
+        let written = fs::read_to_string(dir.join("___desk_1.jsonl")).unwrap();
+        let lines: Vec<serde_json::Value> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "Accepted");
+        assert_eq!(lines[1]["type"], "Canceled");
+        assert_eq!(lines[1]["sequence"], 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,406 @@
+use crate::engine::MatchingEngine;
+use crate::operation::SimOp;
+use crate::source::{Endpoint, OperationListener};
+use crate::trade::Trade;
+use crate::utils::display_order_books;
+use rust_decimal::Decimal;
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use uuid::Uuid;
+
+pub const CONTROL_HELP: &str = "Commands: pause | resume | step [n] | dump";
+
+/// A command typed on the keyboard or sent over the control socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    /// Process the next `n` operations, then pause again.
+    Step(usize),
+    /// Print every order book at the current position.
+    Dump,
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let command = parts.next().unwrap_or_default().to_lowercase();
+        let command = match command.as_str() {
+            "pause" | "p" => ControlCommand::Pause,
+            "resume" | "r" | "continue" | "c" => ControlCommand::Resume,
+            "step" | "s" => {
+                let n = match parts.next() {
+                    Some(n) => n.parse::<usize>().map_err(|_| format!("Invalid step count '{}'", n))?,
+                    None => 1,
+                };
+                ControlCommand::Step(n)
+            }
+            "dump" | "d" => ControlCommand::Dump,
+            _ => return Err(format!("Unknown command '{}'. {}", s.trim(), CONTROL_HELP)),
+        };
+        if parts.next().is_some() {
+            return Err(format!("Unexpected arguments in '{}'", s.trim()));
+        }
+        Ok(command)
+    }
+}
+
+/// A condition that halts the run after the operation that triggers it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// An operation submits or cancels this order.
+    OrderId(Uuid),
+    /// Any book has its best bid at or above its best ask.
+    BookCrossed,
+    /// A trade executes outside `low..=high`.
+    TradeOutsideBand { low: Decimal, high: Decimal },
+}
+
+impl Breakpoint {
+    /// Describes why the breakpoint fired, or `None` if it did not.
+    fn check(&self, operation: &SimOp, trades: &[Trade], engine: &MatchingEngine) -> Option<String> {
+        match self {
+            Breakpoint::OrderId(id) => {
+                let touched = match operation {
+                    SimOp::New(new_order) => new_order.order_id == *id,
+                    SimOp::Cancel { order_id, .. } => order_id == id,
+                    SimOp::CancelAll { .. } => false,
+                };
+                touched.then(|| format!("order {} processed", id))
+            }
+            Breakpoint::BookCrossed => engine.instruments().into_iter().find_map(|instrument| {
+                let book = engine.order_book(&instrument)?;
+                match (book.best_bid(), book.best_ask()) {
+                    (Some(bid), Some(ask)) if bid >= ask => Some(format!("{} crossed: bid {} >= ask {}", instrument, bid, ask)),
+                    _ => None,
+                }
+            }),
+            Breakpoint::TradeOutsideBand { low, high } => trades
+                .iter()
+                .find(|trade| trade.price < *low || trade.price > *high)
+                .map(|trade| format!("trade {} at {} outside {}..={}", trade.trade_id, trade.price, low, high)),
+        }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid breakpoint '{}', expected order:<uuid>, crossed or band:<low>:<high>", s);
+        let mut parts = s.split(':');
+        match parts.next().map(str::to_lowercase).as_deref() {
+            Some("order") => {
+                let id = parts.next().and_then(|id| Uuid::parse_str(id).ok()).ok_or_else(invalid)?;
+                Ok(Breakpoint::OrderId(id))
+            }
+            Some("crossed") => Ok(Breakpoint::BookCrossed),
+            Some("band") => {
+                let mut bound = || parts.next().and_then(|v| Decimal::from_str(v).ok()).ok_or_else(invalid);
+                let (low, high) = (bound()?, bound()?);
+                if low > high {
+                    return Err(invalid());
+                }
+                Ok(Breakpoint::TradeOutsideBand { low, high })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::OrderId(id) => write!(f, "order:{}", id),
+            Breakpoint::BookCrossed => f.write_str("crossed"),
+            Breakpoint::TradeOutsideBand { low, high } => write!(f, "band:{}:{}", low, high),
+        }
+    }
+}
+
+/// Where control commands are read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlInput {
+    Stdin,
+    Socket(Endpoint),
+}
+
+impl FromStr for ControlInput {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdin" | "-" => Ok(ControlInput::Stdin),
+            other => Ok(ControlInput::Socket(Endpoint::from_str(other)?)),
+        }
+    }
+}
+
+/// Pauses, resumes and single-steps a simulation run between operations.
+/// Commands arrive on a channel and are applied before each operation.
+pub struct SimulationControl {
+    commands: Receiver<ControlCommand>,
+    paused: bool,
+    steps_remaining: usize,
+    disconnected: bool,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl SimulationControl {
+    pub fn new(commands: Receiver<ControlCommand>, start_paused: bool) -> Self {
+        let mut control = Self { commands, paused: false, steps_remaining: 0, disconnected: false, breakpoints: Vec::new() };
+        if start_paused {
+            control.pause(0);
+        }
+        control
+    }
+
+    /// Reads commands from the terminal, one per line.
+    pub fn from_stdin(start_paused: bool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || forward_commands(io::stdin().lock(), &sender));
+        Self::new(receiver, start_paused)
+    }
+
+    /// Accepts control connections on `endpoint`, one at a time, and reads
+    /// commands from each, one per line.
+    pub fn listen(endpoint: &Endpoint, start_paused: bool) -> io::Result<Self> {
+        let listener = OperationListener::bind(endpoint)?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(stream) = listener.accept_stream() {
+                if !forward_commands(BufReader::new(stream), &sender) {
+                    break;
+                }
+            }
+        });
+        Ok(Self::new(receiver, start_paused))
+    }
+
+    pub fn open(input: &ControlInput, start_paused: bool) -> io::Result<Self> {
+        match input {
+            ControlInput::Stdin => Ok(Self::from_stdin(start_paused)),
+            ControlInput::Socket(endpoint) => Self::listen(endpoint, start_paused),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Called before operation `index` is processed. Applies pending commands
+    /// and blocks while the run is paused.
+    pub fn before_operation(&mut self, index: usize, engine: &MatchingEngine) {
+        self.count_step(index);
+        loop {
+            let command = if self.paused && !self.disconnected {
+                match self.commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => {
+                        eprintln!("Control input closed, resuming");
+                        self.disconnected = true;
+                        self.paused = false;
+                        return;
+                    }
+                }
+            } else {
+                match self.commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => return,
+                    Err(TryRecvError::Disconnected) => {
+                        self.disconnected = true;
+                        return;
+                    }
+                }
+            };
+            self.apply(command, index, engine);
+        }
+    }
+
+    /// Counts down a `step` command, pausing before operation `index` once
+    /// its operations have run.
+    fn count_step(&mut self, index: usize) {
+        if self.steps_remaining > 0 {
+            self.steps_remaining -= 1;
+            if self.steps_remaining == 0 {
+                self.pause(index);
+            }
+        }
+    }
+
+    /// Called once operation `index` has been processed with the trades it
+    /// produced. A matching breakpoint dumps the engine state and pauses the
+    /// run before the next operation.
+    pub fn after_operation(&mut self, index: usize, operation: &SimOp, trades: &[Trade], engine: &MatchingEngine) {
+        let Some(reason) = self.breakpoints.iter().find_map(|bp| bp.check(operation, trades, engine)) else {
+            return;
+        };
+        println!("\nBreakpoint hit at operation {}: {}", index, reason);
+        dump_engine_state(engine);
+        self.pause(index + 1);
+    }
+
+    /// Halts the run before operation `index` until a resume or step command.
+    pub fn pause(&mut self, index: usize) {
+        self.paused = true;
+        self.steps_remaining = 0;
+        println!("Paused before operation {}. {}", index, CONTROL_HELP);
+    }
+
+    fn apply(&mut self, command: ControlCommand, index: usize, engine: &MatchingEngine) {
+        match command {
+            ControlCommand::Pause if !self.paused => self.pause(index),
+            ControlCommand::Pause => {}
+            ControlCommand::Resume => {
+                self.paused = false;
+                self.steps_remaining = 0;
+            }
+            ControlCommand::Step(0) => {}
+            ControlCommand::Step(n) => {
+                self.paused = false;
+                self.steps_remaining = n;
+            }
+            ControlCommand::Dump => {
+                println!("\n--- ORDER BOOKS BEFORE OPERATION {} ---", index);
+                display_order_books(&engine.instruments(), engine);
+            }
+        }
+    }
+}
+
+/// Prints books, resting orders, the latest trades and the quantity tally of
+/// every instrument.
+pub fn dump_engine_state(engine: &MatchingEngine) {
+    let instruments = engine.instruments();
+    println!("\n--- ENGINE STATE ---");
+    display_order_books(&instruments, engine);
+    for instrument in &instruments {
+        println!("\n--- RESTING ORDERS: {} ---", instrument);
+        for order in engine.resting_orders(instrument) {
+            println!(
+                "  id={} side={:?} price={} remaining={}/{}",
+                order.order_id, order.side, order.price.unwrap_or_default(), order.remaining_quantity, order.quantity
+            );
+        }
+        println!("--- RECENT TRADES: {} ---", instrument);
+        for trade in engine.recent_trades(instrument, 5) {
+            println!(
+                "  id={} price={} qty={} taker={:?} buy={} sell={}",
+                trade.trade_id, trade.price, trade.quantity, trade.taker_side, trade.buy_order_id, trade.sell_order_id
+            );
+        }
+    }
+    engine.accounting_report().print_summary();
+}
+
+/// Parses lines from `input` into commands. Returns `false` once the run has
+/// finished and nobody is listening any more.
+fn forward_commands(input: impl BufRead, sender: &Sender<ControlCommand>) -> bool {
+    for line in input.lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match ControlCommand::from_str(&line) {
+            Ok(command) => {
+                if sender.send(command).is_err() {
+                    return false;
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!("pause".parse::<ControlCommand>(), Ok(ControlCommand::Pause));
+        assert_eq!("c".parse::<ControlCommand>(), Ok(ControlCommand::Resume));
+        assert_eq!("step".parse::<ControlCommand>(), Ok(ControlCommand::Step(1)));
+        assert_eq!("step 25".parse::<ControlCommand>(), Ok(ControlCommand::Step(25)));
+        assert_eq!(" DUMP ".parse::<ControlCommand>(), Ok(ControlCommand::Dump));
+        assert!("step x".parse::<ControlCommand>().is_err());
+        assert!("jump".parse::<ControlCommand>().is_err());
+    }
+
+    #[test]
+    fn test_step_runs_n_operations_then_pauses() {
+        let engine = MatchingEngine::new();
+        let (sender, receiver) = mpsc::channel();
+        let mut control = SimulationControl::new(receiver, true);
+
+        sender.send(ControlCommand::Step(2)).unwrap();
+        control.before_operation(0, &engine);
+        assert!(!control.is_paused());
+        control.before_operation(1, &engine);
+        assert!(!control.is_paused());
+        control.count_step(2);
+        assert!(control.is_paused());
+
+        // Paused before operation 2, the run blocks until it is resumed.
+        let (passed, passed_receiver) = mpsc::channel();
+        let run = thread::spawn(move || {
+            control.before_operation(2, &engine);
+            passed.send(control.is_paused()).unwrap();
+        });
+        let blocked = passed_receiver.recv_timeout(std::time::Duration::from_millis(100));
+        assert_eq!(blocked, Err(mpsc::RecvTimeoutError::Timeout));
+        sender.send(ControlCommand::Resume).unwrap();
+        assert_eq!(passed_receiver.recv(), Ok(false));
+        run.join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_breakpoints() {
+        let id = Uuid::new_v4();
+        assert_eq!(format!("order:{}", id).parse::<Breakpoint>(), Ok(Breakpoint::OrderId(id)));
+        assert_eq!("crossed".parse::<Breakpoint>(), Ok(Breakpoint::BookCrossed));
+        let band = "band:95:105".parse::<Breakpoint>().unwrap();
+        assert_eq!(band.to_string(), "band:95:105");
+        assert!("band:105:95".parse::<Breakpoint>().is_err());
+        assert!("order:nope".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn test_breakpoints_fire_on_order_id_and_trade_band() {
+        use crate::operation::NewOrder;
+        use crate::order::OrderAttributes;
+        use crate::utils::Side;
+        use rust_decimal_macros::dec;
+
+        let engine = MatchingEngine::new();
+        let id = Uuid::new_v4();
+        let op = SimOp::New(NewOrder { order_id: id, instrument: "TEST".to_string(), side: Side::Buy, quantity: dec!(1), price: None, client: None, client_order_id: None, peg: None, discretion: None, attributes: OrderAttributes::default(), expire_at: None, cancel_after: None, stop_price: None });
+        let trade = Trade::new("TEST".to_string(), dec!(120), dec!(1), id, Uuid::new_v4(), Side::Buy);
+
+        assert!(Breakpoint::OrderId(id).check(&op, &[], &engine).is_some());
+        assert!(Breakpoint::OrderId(Uuid::new_v4()).check(&op, &[], &engine).is_none());
+        let band = Breakpoint::TradeOutsideBand { low: dec!(90), high: dec!(110) };
+        assert!(band.check(&op, std::slice::from_ref(&trade), &engine).is_some());
+        assert!(band.check(&op, &[], &engine).is_none());
+        assert!(Breakpoint::BookCrossed.check(&op, &[], &engine).is_none());
+    }
+
+    #[test]
+    fn test_closed_input_never_blocks() {
+        let engine = MatchingEngine::new();
+        let (sender, receiver) = mpsc::channel();
+        sender.send(ControlCommand::Dump).unwrap();
+        drop(sender);
+
+        let mut control = SimulationControl::new(receiver, true);
+        control.before_operation(0, &engine);
+        assert!(!control.is_paused());
+    }
+}
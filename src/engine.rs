@@ -1,28 +1,623 @@
-use crate::order::Order;
-use crate::orderbook::OrderBook;
-use crate::trade::Trade;
-use crate::utils::{MatchingEngineError, OrderBookDisplay, OrderType};
-use std::collections::HashMap;
+use crate::accounting::AccountingReport;
+use crate::audit::{AuditEvent, AuditTrail};
+use crate::clients::{ClientId, ClientIndex, ClientOrderIds};
+use crate::clock::{Clock, MonotonicClock, SystemClock};
+use crate::config::{CapacityLimits, InstrumentConfig, OptionContract, RiskLimits};
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::fixed::Fixed;
+use crate::ids::{instrument_id, EngineOrderIds, IdGenerator, TradeIdStrategy};
+use crate::spans::OperationSpan;
+use crate::order::{CancelTimer, Order, SelfTradePrevention, TimeInForce};
+use crate::orderbook::{LevelStats, OrderBook, Uncross};
+use crate::trade::{Execution, Trade};
+use crate::trade_store::{TradeStore, DEFAULT_BUST_WINDOW, DEFAULT_TRADE_RETENTION};
+use crate::utils::{EngineSummary, InstrumentSummary, MatchingEngineError, OrderBookDisplay, OrderStatus, OrderType, Side, UuidMap};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
 use uuid::Uuid;
 use crate::logging::logger_trait::SimLogger;
-use std::time::Instant;
+use crate::messaging::{MessageAccounting, OtrPolicy};
+use crate::margin::{Liquidation, MarginBook};
+use crate::stops::StopCascade;
+use crate::funding::FundingRound;
+use crate::options::OptionExpiry;
+use crate::index::IndexDefinition;
+use crate::shadow::ShadowBooks;
+
+/// The injectable sources of time and ids used while matching.
+pub struct EngineContext {
+    pub clock: Box<dyn Clock>,
+    pub ids: Box<dyn IdGenerator>,
+    /// Times matching and logging for the latency report. It is separate
+    /// from `clock` so a test can control one without the other.
+    pub latency_clock: Box<dyn Clock>,
+    /// Panics when an order leaves its book locked or crossed. Only debug
+    /// builds check.
+    pub check_crossed: bool,
+}
+
+impl Default for EngineContext {
+    fn default() -> Self {
+        EngineContext {
+            clock: Box::new(SystemClock),
+            ids: TradeIdStrategy::default().generator(),
+            latency_clock: Box::new(MonotonicClock::default()),
+            check_crossed: false,
+        }
+    }
+}
 
 pub struct MatchingEngine {
     books: HashMap<String, OrderBook>,
+    trade_stores: HashMap<String, TradeStore>,
+    trade_retention: usize,
+    bust_window: Duration,
+    /// Trades busted since the caller last took them.
+    busted: Vec<Trade>,
+    risk_limits: RiskLimits,
+    capacity_limits: CapacityLimits,
+    ctx: EngineContext,
+    subscribers: Vec<Box<dyn EngineSubscriber>>,
+    auto_create_markets: bool,
+    clients: ClientIndex,
+    client_order_ids: ClientOrderIds,
+    delayed: HashMap<String, VecDeque<DelayedOrder>>,
+    batches: HashMap<String, Vec<DelayedOrder>>,
+    repriced: Vec<(Order, Decimal)>,
+    halted: HashSet<String>,
+    messages: MessageAccounting,
+    /// Stop orders waiting for a trade at or through their stop price, in
+    /// arrival order. `release_at` is when they arrived.
+    stops: HashMap<String, Vec<DelayedOrder>>,
+    /// Stops triggered and matched since the caller last took them.
+    triggered: Vec<ReleasedOrder>,
+    cascades: Vec<StopCascade>,
+    margin: Option<MarginBook>,
+    /// Close-out orders sent since the caller last took them.
+    liquidations: Vec<Liquidation>,
+    liquidation_ids: EngineOrderIds,
+    /// When each perpetual next fixes its funding rate, in engine nanos.
+    funding_due: HashMap<String, u64>,
+    /// Options past their expiry, which take no more orders.
+    expired_options: HashSet<String>,
+    indexes: Vec<IndexDefinition>,
+    /// The last published price of each index.
+    index_prices: HashMap<String, Decimal>,
+    /// How many levels a side the book imbalance is computed over, if it is.
+    imbalance_levels: Option<usize>,
+    /// The last published imbalance of each book that has one.
+    imbalances: HashMap<String, Decimal>,
+    /// Inbound messages received so far: orders, cancels and mass cancels.
+    sequence: u64,
+    audit: Option<AuditTrail>,
+    shadow: Option<ShadowBooks>,
+}
+
+/// The result of submitting an order.
+#[derive(Debug)]
+pub enum Submission {
+    /// A new order was created, with its trades and the logging time.
+    New(Vec<Trade>, u128),
+    /// The order takes liquidity on an instrument with a speed bump, or its
+    /// instrument trades in batch auctions. It was accepted and will be
+    /// matched once released at `release_at`.
+    Delayed { release_at: u64 },
+    /// The client already used the id today; nothing was created and this is
+    /// the current state of the original order.
+    Duplicate(Order),
+    /// A stop order was accepted and waits for a trade at or through its
+    /// stop price. It is matched as soon as one prints.
+    Stopped,
+}
+
+/// How an accepted order proceeds.
+enum Admission {
+    Match,
+    Delay(u64),
+    Batch(u64),
+}
+
+/// An order held back by a speed bump, waiting for a batch auction or
+/// waiting for its stop price.
+struct DelayedOrder {
+    release_at: u64,
+    order: Order,
+    owner: Option<ClientId>,
+}
+
+/// A delayed or stop order that has now been matched, as it was submitted.
+#[derive(Debug)]
+pub struct ReleasedOrder {
+    pub order: Order,
+    pub trades: Vec<Trade>,
+    pub log_duration: u128,
+}
+
+/// A batch auction that has been run.
+#[derive(Debug)]
+pub struct AuctionResult {
+    pub instrument: String,
+    pub price: Option<Decimal>,
+    /// The batched orders' states after the auction, in arrival order.
+    pub orders: Vec<Order>,
+    pub trades: Vec<Trade>,
+    pub log_duration: u128,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collects the full engine configuration up front; `build` validates it and
+/// returns an engine with every market already registered.
+pub struct MatchingEngineBuilder {
+    instruments: Vec<(String, InstrumentConfig)>,
+    risk_limits: RiskLimits,
+    capacity_limits: CapacityLimits,
+    trade_retention: usize,
+    bust_window: Duration,
+    ctx: EngineContext,
+    subscribers: Vec<Box<dyn EngineSubscriber>>,
+    auto_create_markets: bool,
+    otr_policy: Option<OtrPolicy>,
+    audit_trail: bool,
+    shadow_books: Option<u64>,
+    margin: Option<MarginBook>,
+    indexes: Vec<IndexDefinition>,
+    imbalance_levels: Option<usize>,
+}
+
+impl Default for MatchingEngineBuilder {
+    fn default() -> Self {
+        MatchingEngineBuilder {
+            instruments: Vec::new(),
+            risk_limits: RiskLimits::default(),
+            capacity_limits: CapacityLimits::default(),
+            trade_retention: DEFAULT_TRADE_RETENTION,
+            bust_window: DEFAULT_BUST_WINDOW,
+            ctx: EngineContext::default(),
+            subscribers: Vec::new(),
+            auto_create_markets: false,
+            otr_policy: None,
+            audit_trail: false,
+            shadow_books: None,
+            margin: None,
+            indexes: Vec::new(),
+            imbalance_levels: None,
+        }
+    }
+}
+
+impl MatchingEngineBuilder {
+    pub fn instrument(mut self, instrument: impl Into<String>, config: InstrumentConfig) -> Self {
+        self.instruments.push((instrument.into(), config));
+        self
+    }
+
+    pub fn risk_limits(mut self, risk_limits: RiskLimits) -> Self {
+        self.risk_limits = risk_limits;
+        self
+    }
+
+    /// Caps on open orders, price levels and markets; an order or market
+    /// beyond them is rejected with [`MatchingEngineError::CapacityExceeded`].
+    pub fn capacity_limits(mut self, capacity_limits: CapacityLimits) -> Self {
+        self.capacity_limits = capacity_limits;
+        self
+    }
+
+    pub fn trade_retention(mut self, trade_retention: usize) -> Self {
+        self.trade_retention = trade_retention;
+        self
+    }
+
+    /// How long after it printed a trade can still be busted.
+    pub fn bust_window(mut self, bust_window: Duration) -> Self {
+        self.bust_window = bust_window;
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.ctx.clock = Box::new(clock);
+        self
+    }
+
+    /// The clock the recorded latencies are measured with.
+    pub fn latency_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.ctx.latency_clock = Box::new(clock);
+        self
+    }
+
+    pub fn id_generator(mut self, ids: impl IdGenerator + 'static) -> Self {
+        self.ctx.ids = Box::new(ids);
+        self
+    }
+
+    /// Sets the id generator by strategy, as chosen on the command line.
+    pub fn trade_ids(mut self, strategy: TradeIdStrategy) -> Self {
+        self.ctx.ids = strategy.generator();
+        self
+    }
+
+    pub fn subscriber(mut self, subscriber: Box<dyn EngineSubscriber>) -> Self {
+        self.subscribers.push(subscriber);
+        self
+    }
+
+    /// Creates a market with the default instrument config when an order
+    /// arrives for an unknown instrument, instead of rejecting it.
+    pub fn auto_create_markets(mut self, enabled: bool) -> Self {
+        self.auto_create_markets = enabled;
+        self
+    }
+
+    /// Caps each client's order-to-trade ratio. Without a policy messages
+    /// are still counted.
+    pub fn otr_policy(mut self, policy: OtrPolicy) -> Self {
+        self.otr_policy = Some(policy);
+        self
+    }
+
+    /// After every order added to a book, asserts that its best bid is below
+    /// its best ask and panics with the top of the book if not. Has no
+    /// effect in release builds.
+    pub fn check_crossed(mut self, enabled: bool) -> Self {
+        self.ctx.check_crossed = enabled;
+        self
+    }
+
+    /// Keeps an audit trail of every order's events, linked to the inbound
+    /// messages that caused them.
+    pub fn audit_trail(mut self, enabled: bool) -> Self {
+        self.audit_trail = enabled;
+        self
+    }
+
+    /// Replays every matched order and cancel through a legacy book as
+    /// well, hashing both books every `checksum_every` operations of an
+    /// instrument, and reports the first operation where the two disagree.
+    /// See [`ShadowBooks`].
+    pub fn shadow_books(mut self, checksum_every: u64) -> Self {
+        self.shadow_books = Some(checksum_every);
+        self
+    }
+
+    /// Puts client accounts on margin: orders need initial margin and
+    /// accounts below maintenance margin are liquidated.
+    pub fn margin(mut self, margin: MarginBook) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    /// Computes an index price from the books of its constituents.
+    /// Instruments whose config names the index follow it.
+    pub fn index(mut self, index: IndexDefinition) -> Self {
+        self.indexes.push(index);
+        self
+    }
+
+    /// Publishes the imbalance of the top `levels` levels of each book
+    /// whenever it changes. See [`OrderBook::imbalance`].
+    pub fn imbalance_levels(mut self, levels: usize) -> Self {
+        self.imbalance_levels = Some(levels);
+        self
+    }
+
+    pub fn build(self) -> Result<MatchingEngine, MatchingEngineError> {
+        if self.imbalance_levels == Some(0) {
+            return Err(MatchingEngineError::InvalidConfig("imbalance needs at least one level".to_string()));
+        }
+        self.capacity_limits.validate().map_err(MatchingEngineError::InvalidConfig)?;
+        if self.shadow_books == Some(0) {
+            return Err(MatchingEngineError::InvalidConfig("shadow books need a checksum interval of at least one operation".to_string()));
+        }
+        if let Some(margin) = &self.margin {
+            margin.validate().map_err(|e| MatchingEngineError::InvalidConfig(format!("margin: {}", e)))?;
+        }
+        for (position, index) in self.indexes.iter().enumerate() {
+            index.validate().map_err(MatchingEngineError::InvalidConfig)?;
+            if self.indexes[..position].iter().any(|other| other.name == index.name) {
+                return Err(MatchingEngineError::InvalidConfig(format!("index '{}' defined twice", index.name)));
+            }
+        }
+        let mut engine = MatchingEngine {
+            books: HashMap::new(),
+            trade_stores: HashMap::new(),
+            trade_retention: self.trade_retention,
+            bust_window: self.bust_window,
+            busted: Vec::new(),
+            risk_limits: self.risk_limits,
+            capacity_limits: self.capacity_limits,
+            ctx: self.ctx,
+            subscribers: self.subscribers,
+            auto_create_markets: self.auto_create_markets,
+            clients: ClientIndex::default(),
+            client_order_ids: ClientOrderIds::default(),
+            delayed: HashMap::new(),
+            batches: HashMap::new(),
+            repriced: Vec::new(),
+            halted: HashSet::new(),
+            stops: HashMap::new(),
+            triggered: Vec::new(),
+            cascades: Vec::new(),
+            margin: self.margin,
+            liquidations: Vec::new(),
+            liquidation_ids: EngineOrderIds::default(),
+            funding_due: HashMap::new(),
+            expired_options: HashSet::new(),
+            indexes: self.indexes,
+            index_prices: HashMap::new(),
+            imbalance_levels: self.imbalance_levels,
+            imbalances: HashMap::new(),
+            messages: MessageAccounting::new(self.otr_policy),
+            sequence: 0,
+            audit: self.audit_trail.then(AuditTrail::default),
+            shadow: self.shadow_books.map(ShadowBooks::new),
+        };
+
+        for (instrument, config) in self.instruments {
+            engine.create_market(instrument, config)?;
+        }
+
+        Ok(engine)
+    }
 }
 
 impl MatchingEngine {
     pub fn new() -> Self {
-        MatchingEngine {
-            books: HashMap::new(),
+        MatchingEngineBuilder::default()
+            .build()
+            .expect("The default configuration is always valid")
+    }
+
+    pub fn builder() -> MatchingEngineBuilder {
+        MatchingEngineBuilder::default()
+    }
+
+    /// Registers a market with its own rules, failing if the config is
+    /// invalid or the instrument already exists.
+    pub fn create_market(&mut self, instrument: String, config: InstrumentConfig) -> Result<(), MatchingEngineError> {
+        config
+            .validate()
+            .map_err(|e| MatchingEngineError::InvalidConfig(format!("{}: {}", instrument, e)))?;
+        if self.has_market(&instrument) {
+            return Err(MatchingEngineError::InvalidConfig(format!("instrument '{}' registered twice", instrument)));
+        }
+        self.capacity_limits.check_instruments(self.books.len())?;
+        if let Some(index) = &config.index
+            && !self.indexes.iter().any(|defined| defined.name == *index) {
+            return Err(MatchingEngineError::InvalidConfig(format!("{}: no index named '{}'", instrument, index)));
+        }
+        self.add_market_with_config(instrument, config);
+        Ok(())
+    }
+
+    fn add_market_with_config(&mut self, instrument: String, config: InstrumentConfig) {
+        self.trade_stores.insert(instrument.clone(), TradeStore::new(self.trade_retention));
+        // Two names hashing alike is rare; the later market takes the next
+        // free id, which snapshots keep.
+        let mut id = instrument_id(&instrument);
+        while self.books.values().any(|book| book.instrument_id() == id) {
+            id = id.wrapping_add(1);
         }
+        if let Some(perpetual) = config.perpetual {
+            let interval = perpetual.funding_interval.as_nanos() as u64;
+            self.funding_due.insert(instrument.clone(), (self.ctx.clock.now_nanos() / interval + 1) * interval);
+        }
+        self.books.insert(instrument.clone(), OrderBook::with_config(instrument, config).with_instrument_id(id));
     }
 
-    pub fn add_market(&mut self, instrument: String) {
-        self.books.insert(instrument.clone(), OrderBook::new(instrument));
+    /// Registered instruments in alphabetical order.
+    pub fn instruments(&self) -> Vec<String> {
+        let mut instruments: Vec<String> = self.books.keys().cloned().collect();
+        instruments.sort();
+        instruments
     }
 
-    pub fn process_order(&mut self, order: Order, logger: &mut Box<dyn SimLogger>) -> Result<(Vec<Trade>, u128), MatchingEngineError> {
+    pub fn has_market(&self, instrument: &str) -> bool {
+        self.books.contains_key(instrument)
+    }
+
+    /// Rejects new orders for `instrument` until it is resumed. Resting
+    /// orders can still be canceled. Orders held by a speed bump or for a
+    /// batch auction stay held until the resume, or the end of the run.
+    pub fn halt_market(&mut self, instrument: &str) -> Result<(), MatchingEngineError> {
+        if !self.has_market(instrument) {
+            return Err(MatchingEngineError::MarketNotFound(instrument.to_string()));
+        }
+        self.halted.insert(instrument.to_string());
+        Ok(())
+    }
+
+    /// Lets new orders for `instrument` in again. A price band is widened
+    /// for one window while the market finds its level.
+    pub fn resume_market(&mut self, instrument: &str) -> Result<(), MatchingEngineError> {
+        let now = self.ctx.clock.now_nanos();
+        let Some(book) = self.books.get_mut(instrument) else {
+            return Err(MatchingEngineError::MarketNotFound(instrument.to_string()));
+        };
+        if self.halted.remove(instrument) {
+            book.widen_price_band(now);
+        }
+        Ok(())
+    }
+
+    pub fn is_halted(&self, instrument: &str) -> bool {
+        self.halted.contains(instrument)
+    }
+
+    /// Changes the tick and lot size that new orders for `instrument` must
+    /// respect.
+    pub fn set_order_sizes(&mut self, instrument: &str, tick_size: Option<Decimal>, lot_size: Option<Decimal>) -> Result<(), MatchingEngineError> {
+        let book = self
+            .books
+            .get_mut(instrument)
+            .ok_or_else(|| MatchingEngineError::MarketNotFound(instrument.to_string()))?;
+        book.set_order_sizes(tick_size, lot_size)
+            .map_err(|e| MatchingEngineError::InvalidConfig(format!("{}: {}", instrument, e)))
+    }
+
+    /// Moves the index price the funding rate of the perpetual `instrument`
+    /// is measured against.
+    pub fn set_index_price(&mut self, instrument: &str, index_price: Decimal) -> Result<(), MatchingEngineError> {
+        let book = self
+            .books
+            .get_mut(instrument)
+            .ok_or_else(|| MatchingEngineError::MarketNotFound(instrument.to_string()))?;
+        book.set_index_price(index_price)
+            .map_err(|e| MatchingEngineError::InvalidConfig(format!("{}: {}", instrument, e)))
+    }
+
+    pub fn risk_limits(&self) -> &RiskLimits {
+        &self.risk_limits
+    }
+
+    pub fn set_risk_limits(&mut self, risk_limits: RiskLimits) {
+        self.risk_limits = risk_limits;
+    }
+
+    pub fn capacity_limits(&self) -> &CapacityLimits {
+        &self.capacity_limits
+    }
+
+    /// Gives `instrument` the id and trade count it had when a snapshot was
+    /// taken, so its trades go on with the next sequence number and trade
+    /// id. The book must not have traded or taken orders yet.
+    pub fn restore_market(&mut self, instrument: &str, instrument_id: u32, trade_sequence: u64) -> Result<(), MatchingEngineError> {
+        if self.books.iter().any(|(name, book)| name != instrument && book.instrument_id() == instrument_id) {
+            return Err(MatchingEngineError::InvalidConfig(format!("{}: instrument id {} is taken", instrument, instrument_id)));
+        }
+        let Some(book) = self.books.get_mut(instrument) else {
+            return Err(MatchingEngineError::MarketNotFound(instrument.to_string()));
+        };
+        if book.order_count() > 0 || book.trade_count() > 0 {
+            return Err(MatchingEngineError::InvalidConfig(format!("{}: a market is restored before it trades or takes orders", instrument)));
+        }
+        book.restore_numbering(instrument_id, trade_sequence);
+        Ok(())
+    }
+
+    /// Rests `order` without matching, as loaded from a snapshot. A
+    /// `cancel_after` lifetime starts now, on the engine clock.
+    pub fn restore_order(&mut self, mut order: Order) -> Result<(), MatchingEngineError> {
+        if order.cancel_timer == Some(CancelTimer::After(0)) {
+            return Err(MatchingEngineError::InvalidCancelAfter(order.order_id));
+        }
+        order.start_cancel_timer(self.ctx.clock.now_nanos());
+        let Some(book) = self.books.get_mut(&order.instrument) else {
+            return Err(MatchingEngineError::MarketNotFound(order.instrument));
+        };
+        if let Some(shadow) = &mut self.shadow {
+            book.restore_order(order.clone())?;
+            shadow.restore(&order);
+            return Ok(());
+        }
+        book.restore_order(order)
+    }
+
+    pub fn resting_orders(&self, instrument: &str) -> Vec<Order> {
+        self.books.get(instrument).map(|book| book.resting_orders()).unwrap_or_default()
+    }
+
+    fn notify(&mut self, event: EngineEvent) {
+        if let Some(audit) = &mut self.audit {
+            audit.record(self.sequence, self.ctx.clock.now_nanos(), &event);
+        }
+        for subscriber in &mut self.subscribers {
+            subscriber.on_event(&event);
+        }
+    }
+
+    /// Whether anything listens to engine events, so events that cost work
+    /// to build can be skipped otherwise.
+    fn publishes_events(&self) -> bool {
+        !self.subscribers.is_empty() || self.audit.is_some()
+    }
+
+    /// The audit trail, if the engine was built with one.
+    pub fn audit(&self) -> Option<&AuditTrail> {
+        self.audit.as_ref()
+    }
+
+    pub fn shadow_books(&self) -> Option<&ShadowBooks> {
+        self.shadow.as_ref()
+    }
+
+    /// Hashes every shadowed book against its engine book, catching a
+    /// divergence since the last interval's checksum.
+    pub fn verify_shadow_books(&mut self) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow.verify_all(self.sequence, self.books.values());
+        }
+    }
+
+    /// Inbound messages received so far. Each order entry, cancel and mass
+    /// cancel is one, so in a simulation this is the number of operations.
+    pub fn messages_received(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The first phase of an order: prices it if it is pegged or banded,
+    /// normalizes it to the instrument's precision, runs every check on it
+    /// (message rate, type and prices, tick and lot, halt, attributes, risk,
+    /// capacity and margin) and decides whether it matches now, waits out a speed
+    /// bump or joins the current batch auction. Nothing in the engine
+    /// changes, so an order it rejects never touches a book, an account or
+    /// the list of markets.
+    fn validate(&self, order: &mut Order, owner: Option<&ClientId>) -> Result<Admission, MatchingEngineError> {
+        let created;
+        let book = match self.books.get(&order.instrument) {
+            Some(book) => Some(book),
+            // A market created on its first order starts with the default
+            // rules, so the order is checked against those.
+            None if self.auto_create_markets => {
+                self.capacity_limits.check_instruments(self.books.len())?;
+                created = OrderBook::new(order.instrument.clone());
+                Some(&created)
+            }
+            None => None,
+        };
+        let now = self.ctx.clock.now_nanos();
+        if let Some(book) = book {
+            if order.peg.is_some() {
+                order.price = book.peg_price(order);
+            }
+            if let Some(edge) = book.banded_price(order, now) {
+                order.price = Some(edge);
+            }
+        }
+        if let Some(owner) = owner {
+            self.messages.check_new_order(owner)?;
+        }
+        if let Some(book) = book {
+            book.config().normalize_order(order)?;
+        }
+        self.validate_order(order, book)?;
+        self.check_capacity(order, book.expect("validated orders have a market"), owner)?;
+        if let (Some(margin), Some(owner)) = (&self.margin, owner) {
+            margin.check_order(owner, order)?;
+        }
+
+        let config = book.expect("validated orders have a market").config();
+        Ok(match config {
+            InstrumentConfig { batch_interval: Some(interval), .. } => {
+                let interval = interval.as_nanos() as u64;
+                Admission::Batch((now / interval + 1) * interval)
+            }
+            InstrumentConfig { speed_bump: Some(delay), .. } if book.is_some_and(|book| book.is_marketable(order)) => {
+                Admission::Delay(now + delay.as_nanos() as u64)
+            }
+            _ => Admission::Match,
+        })
+    }
+
+    fn validate_order(&self, order: &Order, book: Option<&OrderBook>) -> Result<(), MatchingEngineError> {
+        if order.peg.is_some() && order.price.is_none() {
+            return Err(MatchingEngineError::NoPegReference(order.order_id));
+        }
         match order.order_type {
             OrderType::Market if order.price.is_some() => {
                 return Err(MatchingEngineError::InvalidOrderPrice)
@@ -32,54 +627,2382 @@ impl MatchingEngine {
             }
             _ => (),
         }
+        if let Some(stop_price) = order.stop_price
+            && (stop_price <= Decimal::ZERO || order.peg.is_some()) {
+            return Err(MatchingEngineError::InvalidStopPrice { order_id: order.order_id, stop_price });
+        }
+        for value in [order.price, Some(order.quantity), order.discretion, order.stop_price].into_iter().flatten() {
+            if Fixed::from_decimal(value).is_none() {
+                return Err(MatchingEngineError::ExcessPrecision(value));
+            }
+        }
+        if let Some(expire_at) = order.expire_at
+            && (!order.rests() || expire_at <= self.ctx.clock.now_nanos()) {
+            return Err(MatchingEngineError::InvalidExpiry(order.order_id));
+        }
+        if order.cancel_timer.is_some_and(|timer| timer == CancelTimer::After(0) || !order.rests()) {
+            return Err(MatchingEngineError::InvalidCancelAfter(order.order_id));
+        }
+        if let Some(discretion) = order.discretion {
+            let price = order.price.unwrap_or_default();
+            let beyond = match order.side {
+                Side::Buy => discretion >= price,
+                Side::Sell => discretion <= price,
+            };
+            if order.order_type != OrderType::Limit || !beyond {
+                return Err(MatchingEngineError::InvalidDiscretion { price, discretion });
+            }
+        }
+
+        let Some(book) = book else {
+            return Err(MatchingEngineError::MarketNotFound(order.instrument.clone()));
+        };
+        if self.is_halted(&order.instrument) {
+            return Err(MatchingEngineError::MarketHalted(order.instrument.clone()));
+        }
+        if self.expired_options.contains(&order.instrument) {
+            return Err(MatchingEngineError::InstrumentExpired(order.instrument.clone()));
+        }
+        if order.stop_price.is_some() && book.config().batch_interval.is_some() {
+            return Err(MatchingEngineError::UnsupportedAttribute("stop orders on a batch auction instrument".to_string()));
+        }
+        book.config().check_order(order)?;
+        book.check_price_collar(order)?;
+        book.check_price_band(order, self.ctx.clock.now_nanos())?;
+        check_attributes(order, book)?;
+        self.risk_limits.check_order(order)
+    }
 
-        match self.books.get_mut(&order.instrument) {
-            Some(book) => {
-                let (trades, filled_orders, final_incoming_state) = book.add_order(order);
+    /// Rejects an order that could stay open, resting or held, once its
+    /// instrument, its client or the price levels on its side are full.
+    /// Orders that cannot outlive matching are always let through, since
+    /// they only ever shrink the book.
+    fn check_capacity(&self, order: &Order, book: &OrderBook, owner: Option<&ClientId>) -> Result<(), MatchingEngineError> {
+        let limits = &self.capacity_limits;
+        if !order.rests() && order.stop_price.is_none() {
+            return Ok(());
+        }
+        if let Some(max) = limits.max_orders_per_instrument
+            && self.open_orders(&order.instrument) >= max {
+            return Err(MatchingEngineError::CapacityExceeded(format!("{} already has {} open orders", order.instrument, max)));
+        }
+        if let (Some(max), Some(owner)) = (limits.max_orders_per_client, owner)
+            && self.clients.order_count(owner) >= max {
+            return Err(MatchingEngineError::CapacityExceeded(format!("client {} already has {} open orders", owner, max)));
+        }
+        // A marketable order that may not fill completely could rest its
+        // remainder at a new level, so it counts as opening one.
+        if let (Some(max), Some(price)) = (limits.max_price_levels, order.price)
+            && order.stop_price.is_none()
+            && book.fillable_quantity(order) < order.remaining_quantity
+            && book.opens_level(order.side, price)
+            && book.level_count(order.side) >= max {
+            return Err(MatchingEngineError::CapacityExceeded(format!("{} already has {} {} price levels", order.instrument, max, order.side)));
+        }
+        Ok(())
+    }
 
-                let log_start = Instant::now();
-                for trade in &trades {
-                    logger.log_trade(trade);
-                }
-                for filled_order in filled_orders {
-                    logger.log_order_filled(&filled_order);
+    /// Orders resting in the instrument's book, held by a speed bump or
+    /// batch, or parked until their stop price trades.
+    fn open_orders(&self, instrument: &str) -> usize {
+        let resting = self.books.get(instrument).map_or(0, OrderBook::order_count);
+        resting
+            + self.delayed.get(instrument).map_or(0, VecDeque::len)
+            + self.batches.get(instrument).map_or(0, Vec::len)
+            + self.stops.get(instrument).map_or(0, Vec::len)
+    }
+
+    pub fn process_order(&mut self, order: Order, logger: &mut Box<dyn SimLogger>) -> Result<(Vec<Trade>, u128), MatchingEngineError> {
+        match self.submit_order(order, None, logger)? {
+            Submission::New(trades, log_duration) => Ok((trades, log_duration)),
+            Submission::Delayed { .. } | Submission::Duplicate(_) | Submission::Stopped => Ok((Vec::new(), 0)),
+        }
+    }
+
+    /// Like `process_order`, but lends each trade to `on_trade` in execution
+    /// order instead of returning them, so a caller that only reads the
+    /// trades never owns or clones them. Returns the logging time.
+    pub fn process_order_with<F: FnMut(&Trade)>(
+        &mut self,
+        order: Order,
+        logger: &mut Box<dyn SimLogger>,
+        on_trade: F,
+    ) -> Result<u128, MatchingEngineError> {
+        match self.submit_order(order, None, logger)? {
+            Submission::New(trades, log_duration) => {
+                trades.iter().for_each(on_trade);
+                Ok(log_duration)
+            }
+            Submission::Delayed { .. } | Submission::Duplicate(_) | Submission::Stopped => Ok(0),
+        }
+    }
+
+    /// Like `process_order`, recording `owner` as the client that owns the
+    /// order for as long as it rests.
+    pub fn submit_order(
+        &mut self,
+        order: Order,
+        owner: Option<ClientId>,
+        logger: &mut Box<dyn SimLogger>,
+    ) -> Result<Submission, MatchingEngineError> {
+        OperationSpan::order(&order).submit(|| self.submit(order, owner, logger))
+    }
+
+    fn submit(&mut self, mut order: Order, owner: Option<ClientId>, logger: &mut Box<dyn SimLogger>) -> Result<Submission, MatchingEngineError> {
+        profile_scope!("submit_order");
+        self.sequence += 1;
+        match self.admit(&mut order, owner.as_ref(), logger)? {
+            _ if self.waits_for_stop(&order) => {
+                self.park(order, owner);
+                Ok(Submission::Stopped)
+            }
+            Admission::Match => {
+                let (trades, log_duration, _) = self.match_order(order, owner, logger);
+                self.follow_up(&trades, logger);
+                Ok(Submission::New(trades, log_duration))
+            }
+            admission => Ok(Submission::Delayed { release_at: self.hold(order, owner, admission) }),
+        }
+    }
+
+    /// Validates the order and, if it passes, starts its second phase:
+    /// creates its market if this is the market's first order, opens its
+    /// owner's margin account and publishes the acceptance. Either way the
+    /// order counts as a message from its owner.
+    fn admit(&mut self, order: &mut Order, owner: Option<&ClientId>, logger: &mut Box<dyn SimLogger>) -> Result<Admission, MatchingEngineError> {
+        // Whatever the caller stamped, the order's time is the engine's, so a
+        // replay under the same clock stamps it the same.
+        order.timestamp = self.ctx.clock.now_nanos();
+        // A market created on its first order goes through the same checks
+        // as one registered up front.
+        let validated = self.validate(order, owner).and_then(|admission| {
+            if !self.has_market(&order.instrument) {
+                self.create_market(order.instrument.clone(), InstrumentConfig::default())?;
+            }
+            Ok(admission)
+        });
+        if let Some(owner) = owner {
+            self.messages.count_new_order(owner);
+        }
+        let admission = match validated {
+            Ok(admission) => admission,
+            Err(reason) => {
+                logger.log_order_rejected(order, &reason.to_string());
+                self.notify(EngineEvent::OrderRejected { order, reason: &reason, client: owner });
+                return Err(reason);
+            }
+        };
+
+        if let (Some(margin), Some(owner)) = (&mut self.margin, owner) {
+            margin.open_account(owner);
+        }
+        self.notify(EngineEvent::OrderAccepted { order, client: owner });
+        Ok(admission)
+    }
+
+    /// Holds an admitted order until it is released, returning when.
+    fn hold(&mut self, order: Order, owner: Option<ClientId>, admission: Admission) -> u64 {
+        let instrument = order.instrument.clone();
+        let order_id = order.order_id;
+        let release_at = match admission {
+            Admission::Delay(release_at) => {
+                self.delayed.entry(instrument).or_default().push_back(DelayedOrder { release_at, order, owner });
+                release_at
+            }
+            Admission::Batch(closes_at) => {
+                let batch = self.batches.entry(instrument).or_default();
+                // An order arriving after an unrun auction was due joins that batch.
+                let release_at = batch.first().map_or(closes_at, |first| first.release_at);
+                batch.push(DelayedOrder { release_at, order, owner });
+                release_at
+            }
+            Admission::Match => unreachable!("matched orders are not held"),
+        };
+        let now = self.ctx.clock.now_nanos();
+        if let Some(audit) = &mut self.audit {
+            audit.push(order_id, self.sequence, now, AuditEvent::Held { release_at });
+        }
+        release_at
+    }
+
+    /// Whether `order` is a stop that the last trade has not yet triggered.
+    fn waits_for_stop(&self, order: &Order) -> bool {
+        if order.stop_price.is_none() {
+            return false;
+        }
+        let last = self.books.get(&order.instrument).and_then(OrderBook::last_trade_price);
+        !last.is_some_and(|last| order.stop_triggered(last, last))
+    }
+
+    /// Holds a stop order until a trade reaches its stop price.
+    fn park(&mut self, order: Order, owner: Option<ClientId>) {
+        let now = self.ctx.clock.now_nanos();
+        if let Some(audit) = &mut self.audit {
+            audit.push(order.order_id, self.sequence, now, AuditEvent::Parked { stop_price: order.stop_price.unwrap_or_default() });
+        }
+        self.stops.entry(order.instrument.clone()).or_default().push(DelayedOrder { release_at: now, order, owner });
+    }
+
+    /// Triggers the stops that `trades` reach and closes out the accounts
+    /// they put below maintenance margin.
+    fn follow_up(&mut self, trades: &[Trade], logger: &mut Box<dyn SimLogger>) {
+        self.trigger_stops(trades, logger);
+        if !trades.is_empty() {
+            self.liquidate(logger);
+        }
+        self.update_indexes();
+    }
+
+    /// Recomputes every index and, for each whose price moved, publishes it
+    /// and moves the reference price of the instruments following it.
+    fn update_indexes(&mut self) {
+        let mut moved = Vec::new();
+        for index in &self.indexes {
+            let price = index.compute(|instrument| index.source.price(self.books.get(instrument)?));
+            if let Some(price) = price
+                && price > Decimal::ZERO
+                && self.index_prices.get(&index.name) != Some(&price) {
+                moved.push((index.name.clone(), price));
+            }
+        }
+        for (name, price) in moved {
+            self.index_prices.insert(name.clone(), price);
+            for book in self.books.values_mut().filter(|book| book.config().index.as_ref() == Some(&name)) {
+                book.follow_index(price);
+            }
+            self.notify(EngineEvent::IndexPrice { name: &name, price });
+        }
+    }
+
+    /// The last published price of the index `name`.
+    pub fn index_price(&self, name: &str) -> Option<Decimal> {
+        self.index_prices.get(name).copied()
+    }
+
+    /// Sends a market order closing each open position of every account
+    /// below maintenance margin. The client's resting orders are left alone.
+    /// A close-out the market would reject, e.g. while it is halted or its
+    /// option has expired, is not sent; the account is called again on the
+    /// next trade or funding round and closed out once the market takes it.
+    fn liquidate(&mut self, logger: &mut Box<dyn SimLogger>) {
+        let Some(margin) = &mut self.margin else { return };
+        let now = self.ctx.clock.now_nanos();
+        for client in margin.margin_calls(now) {
+            let positions = self.margin.as_ref().and_then(|margin| margin.account(&client)).map(|account| account.positions.clone()).unwrap_or_default();
+            for (instrument, position) in positions {
+                if position.quantity.is_zero() || !self.has_market(&instrument) {
+                    continue;
                 }
-                if final_incoming_state.is_filled() || final_incoming_state.order_type == OrderType::Market {
-                    logger.log_order_filled(&final_incoming_state);
+                let side = if position.quantity > Decimal::ZERO { Side::Sell } else { Side::Buy };
+                let order = Order::new_market(Uuid::nil(), instrument, side, position.quantity.abs());
+                if self.validate_order(&order, self.books.get(&order.instrument)).is_err() {
+                    continue;
                 }
-                let log_duration = log_start.elapsed().as_nanos();
+                let order = Order { timestamp: now, order_id: self.liquidation_ids.next_order_id(), ..order };
+                let (trades, log_duration, _) = self.match_order(order.clone(), Some(client.clone()), logger);
+                self.trigger_stops(&trades, logger);
+                self.liquidations.push(Liquidation { client: client.clone(), order, trades, log_duration });
+            }
+        }
+    }
+
+    /// Close-out orders sent since the last call, in the order they were
+    /// matched.
+    pub fn take_liquidations(&mut self) -> Vec<Liquidation> {
+        std::mem::take(&mut self.liquidations)
+    }
+
+    pub fn margin(&self) -> Option<&MarginBook> {
+        self.margin.as_ref()
+    }
+
+    /// Opens a margin account for `client` with `collateral`, or adds to it.
+    pub fn deposit(&mut self, client: &ClientId, collateral: Decimal) -> Result<(), MatchingEngineError> {
+        let margin = self.margin.as_mut().ok_or_else(|| MatchingEngineError::InvalidConfig("margin is not enabled".to_string()))?;
+        margin.deposit(client, collateral);
+        Ok(())
+    }
 
-                Ok((trades, log_duration))
+    /// Fixes the funding rate of every perpetual whose funding interval has
+    /// ended on the engine clock, charges it to the margin accounts holding
+    /// the instrument and closes out the accounts it puts below maintenance
+    /// margin. A perpetual that has no mark yet skips the round. Like
+    /// `release_delayed_orders`, call this before each new operation.
+    pub fn run_due_funding(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<FundingRound> {
+        let now = self.ctx.clock.now_nanos();
+        let mut rounds = Vec::new();
+        for (instrument, due) in self.funding_due.iter_mut() {
+            if *due > now {
+                continue;
             }
-            None => Err(MatchingEngineError::MarketNotFound(order.instrument)),
+            let Some(book) = self.books.get(instrument) else { continue };
+            let Some(perpetual) = book.config().perpetual else { continue };
+            let fixing = *due;
+            // Rounds missed while no operation arrived are not made up.
+            let interval = perpetual.funding_interval.as_nanos() as u64;
+            *due = (now / interval + 1) * interval;
+            let Some(mark) = book.mid_price().or_else(|| book.last_trade_price()) else { continue };
+            rounds.push(FundingRound {
+                instrument: instrument.clone(),
+                timestamp: fixing,
+                mark,
+                index: perpetual.index_price,
+                rate: perpetual.funding_rate(mark),
+            });
+        }
+        if rounds.is_empty() {
+            return rounds;
         }
+        rounds.sort_by(|a, b| a.instrument.cmp(&b.instrument));
+        if let Some(margin) = &mut self.margin {
+            rounds.iter().for_each(|round| margin.apply_funding(round));
+        }
+        self.liquidate(logger);
+        rounds
     }
 
-    pub fn cancel_order_by_id(&mut self, order_id: &Uuid, instrument: &str) -> Result<Order, MatchingEngineError> {
-        if let Some(book) = self.books.get_mut(instrument) {
-            book.cancel_order(order_id)
-        } else {
-            Err(MatchingEngineError::MarketNotFound(instrument.to_string()))
+    /// Expires the options whose expiry time has passed on the engine clock.
+    /// Like `release_delayed_orders`, call this before each new operation.
+    pub fn run_due_expiries(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<OptionExpiry> {
+        let now = self.ctx.clock.now_nanos();
+        self.expire_options(|expiry| expiry.is_some_and(|expiry| expiry <= now), logger)
+    }
+
+    /// Expires the options that expire with the session, and those whose
+    /// expiry time has passed, for the end of a run. Options expiring later
+    /// stay open.
+    pub fn flush_expiries(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<OptionExpiry> {
+        let now = self.ctx.clock.now_nanos();
+        self.expire_options(|expiry| expiry.is_none_or(|expiry| expiry <= now), logger)
+    }
+
+    /// Closes the books of the options whose expiry is `due`: their resting
+    /// and held orders are removed and new ones rejected. Margin accounts'
+    /// positions are exercised against the option's index, or else the
+    /// underlying's last trade price, or its mid before the first trade.
+    fn expire_options(&mut self, due: impl Fn(Option<u64>) -> bool, logger: &mut Box<dyn SimLogger>) -> Vec<OptionExpiry> {
+        let now = self.ctx.clock.now_nanos();
+        let mut expiring: Vec<(String, OptionContract, Option<String>)> = self
+            .books
+            .iter()
+            .filter(|(instrument, _)| !self.expired_options.contains(*instrument))
+            .filter_map(|(instrument, book)| Some((instrument.clone(), book.config().option.clone()?, book.config().index.clone())))
+            .filter(|(_, contract, _)| due(contract.expiry))
+            .collect();
+        expiring.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut expiries = Vec::new();
+        for (instrument, contract, index) in expiring {
+            let settlement = match index {
+                Some(index) => self.index_price(&index),
+                None => self.books.get(&contract.underlying).and_then(|book| book.last_trade_price().or_else(|| book.mid_price())),
+            };
+            let mut orders = self.books.get_mut(&instrument).map(OrderBook::expire_all).unwrap_or_default();
+            let held = self.delayed.remove(&instrument).into_iter().flatten().chain(self.stops.remove(&instrument).into_iter().flatten());
+            orders.extend(held.map(|held| held.order));
+            for order in &orders {
+                self.clients.remove(&order.order_id);
+                self.client_order_ids.update(order);
+                logger.log_order_filled(order, None);
+                self.notify(EngineEvent::OrderExpired(order));
+            }
+            self.expired_options.insert(instrument.clone());
+            let expiry = OptionExpiry { instrument, timestamp: contract.expiry.unwrap_or(now), contract, settlement, orders };
+            if let Some(margin) = &mut self.margin {
+                margin.exercise(&expiry);
+            }
+            expiries.push(expiry);
         }
+        expiries
     }
 
-    pub fn get_order_book_display(&self, instrument: &str) -> Option<OrderBookDisplay> {
-        self.books.get(instrument).map(|book| book.display())
+    /// Matches the stops that `trades` trigger, then those their own trades
+    /// trigger, until a round triggers none, and records the cascade. The
+    /// triggered orders wait in `take_triggered_stops`.
+    fn trigger_stops(&mut self, trades: &[Trade], logger: &mut Box<dyn SimLogger>) {
+        let Some(first) = trades.first() else { return };
+        if self.stops.get(&first.instrument).is_none_or(Vec::is_empty) {
+            return;
+        }
+        let instrument = first.instrument.clone();
+        let mut cascade = StopCascade {
+            instrument: instrument.clone(),
+            depth: 0,
+            stops: 0,
+            volume: Decimal::ZERO,
+            start_price: first.price,
+            end_price: trades[trades.len() - 1].price,
+        };
+        let mut round: Vec<Trade> = trades.to_vec();
+        while let Some(low) = round.iter().map(|trade| trade.price).min() {
+            let high = round.iter().map(|trade| trade.price).max().unwrap_or(low);
+            let Some(waiting) = self.stops.get_mut(&instrument) else { break };
+            let (due, still_waiting): (Vec<DelayedOrder>, Vec<DelayedOrder>) =
+                std::mem::take(waiting).into_iter().partition(|stop| stop.order.stop_triggered(low, high));
+            *waiting = still_waiting;
+            if due.is_empty() {
+                break;
+            }
+            cascade.depth += 1;
+            round.clear();
+            for DelayedOrder { order, owner, .. } in due {
+                cascade.stops += 1;
+                let (fills, log_duration, _) = self.match_order(order.clone(), owner, logger);
+                cascade.volume += fills.iter().map(|trade| trade.quantity).sum::<Decimal>();
+                round.extend(fills.iter().cloned());
+                self.triggered.push(ReleasedOrder { order, trades: fills, log_duration });
+            }
+            if let Some(last) = round.last() {
+                cascade.end_price = last.price;
+            }
+        }
+        self.stops.retain(|_, waiting| !waiting.is_empty());
+        if cascade.stops > 0 {
+            self.cascades.push(cascade);
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::logging::types::LoggingMode;
-    use crate::logging::create_logger;
-    use crate::order::{Order};
-    use crate::utils::{Side, OrderType};
-    use crate::utils::MatchingEngineError;
-    use rust_decimal_macros::dec;
-    use uuid::Uuid;
+    /// Stops triggered and matched since the last call, in the order they
+    /// were matched. Their trades are not part of the result of the order
+    /// that triggered them.
+    pub fn take_triggered_stops(&mut self) -> Vec<ReleasedOrder> {
+        std::mem::take(&mut self.triggered)
+    }
+
+    /// Stop orders still waiting for their stop price.
+    pub fn pending_stops(&self) -> usize {
+        self.stops.values().map(Vec::len).sum()
+    }
+
+    /// Every stop cascade so far, in the order they happened.
+    pub fn stop_cascades(&self) -> &[StopCascade] {
+        &self.cascades
+    }
 
+    /// Removes good-till-date orders whose expiry time has passed on the
+    /// engine clock, on every instrument, and returns them. Callers driving
+    /// the engine should call this before each new operation, ahead of
+    /// `release_delayed_orders`, so an expired order never trades.
+    pub fn expire_orders(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<Order> {
+        let now = self.ctx.clock.now_nanos();
+        let mut expired = Vec::new();
+        let mut touched = Vec::new();
+        for (instrument, book) in &mut self.books {
+            let orders = book.expire_orders(now);
+            if !orders.is_empty() {
+                touched.push(instrument.clone());
+                expired.extend(orders);
+            }
+        }
+        for order in &expired {
+            self.clients.remove(&order.order_id);
+            self.client_order_ids.update(order);
+            logger.log_order_filled(order, None);
+            self.notify(EngineEvent::OrderExpired(order));
+        }
+        for instrument in touched {
+            self.book_changed(&instrument);
+        }
+        self.log_repricing(logger);
+        expired
+    }
 
+    /// Cancels resting orders whose `cancel_timer` lifetime has run out on
+    /// the engine clock, on every instrument, and returns them. Callers
+    /// driving the engine should call this with `expire_orders`.
+    pub fn cancel_timed_out_orders(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<Order> {
+        let now = self.ctx.clock.now_nanos();
+        let mut canceled = Vec::new();
+        let mut touched = Vec::new();
+        for (instrument, book) in &mut self.books {
+            let orders = book.cancel_timed_out(now);
+            if orders.is_empty() {
+                continue;
+            }
+            if let Some(shadow) = &mut self.shadow {
+                let order_ids: Vec<Uuid> = orders.iter().map(|order| order.order_id).collect();
+                shadow.cancel(self.sequence, &order_ids, book);
+            }
+            touched.push(instrument.clone());
+            canceled.extend(orders);
+        }
+        for order in &canceled {
+            self.clients.remove(&order.order_id);
+            self.client_order_ids.update(order);
+            logger.log_order_cancel(&order.order_id, true);
+            self.notify(EngineEvent::OrderCanceled(order));
+        }
+        for instrument in touched {
+            self.book_changed(&instrument);
+        }
+        self.log_repricing(logger);
+        canceled
+    }
+
+    /// Matches delayed orders whose speed bump has elapsed on the engine
+    /// clock, in arrival order. Callers driving the engine should call this
+    /// before each new operation so released orders keep their priority.
+    pub fn release_delayed_orders(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<ReleasedOrder> {
+        let now = self.ctx.clock.now_nanos();
+        self.release_until(now, false, logger)
+    }
+
+    /// Matches every delayed order regardless of its release time, for the
+    /// end of a run.
+    pub fn flush_delayed_orders(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<ReleasedOrder> {
+        self.release_until(u64::MAX, true, logger)
+    }
+
+    pub fn delayed_orders(&self) -> usize {
+        self.delayed.values().map(VecDeque::len).sum()
+    }
+
+    /// Matches the delayed orders due by `now`, leaving those of halted
+    /// instruments held unless `include_halted`.
+    fn release_until(&mut self, now: u64, include_halted: bool, logger: &mut Box<dyn SimLogger>) -> Vec<ReleasedOrder> {
+        let mut due = Vec::new();
+        for (instrument, queue) in self.delayed.iter_mut() {
+            if !include_halted && self.halted.contains(instrument) {
+                continue;
+            }
+            while queue.front().is_some_and(|delayed| delayed.release_at <= now) {
+                due.extend(queue.pop_front());
+            }
+        }
+        due.sort_by_key(|delayed| delayed.release_at);
+
+        due.into_iter()
+            .map(|DelayedOrder { order, owner, .. }| {
+                let (trades, log_duration, _) = self.match_order(order.clone(), owner, logger);
+                self.follow_up(&trades, logger);
+                ReleasedOrder { order, trades, log_duration }
+            })
+            .collect()
+    }
+
+    /// Runs the batch auctions whose interval has ended on the engine clock.
+    /// Like `release_delayed_orders`, call this before each new operation.
+    pub fn run_due_auctions(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<AuctionResult> {
+        let now = self.ctx.clock.now_nanos();
+        self.run_auctions_until(now, false, logger)
+    }
+
+    /// Runs every pending batch auction, for the end of a run.
+    pub fn flush_auctions(&mut self, logger: &mut Box<dyn SimLogger>) -> Vec<AuctionResult> {
+        self.run_auctions_until(u64::MAX, true, logger)
+    }
+
+    pub fn batched_orders(&self) -> usize {
+        self.batches.values().map(Vec::len).sum()
+    }
+
+    fn run_auctions_until(&mut self, now: u64, include_halted: bool, logger: &mut Box<dyn SimLogger>) -> Vec<AuctionResult> {
+        let mut due: Vec<(u64, String)> = self
+            .batches
+            .iter()
+            .filter(|(instrument, _)| include_halted || !self.halted.contains(*instrument))
+            .filter_map(|(instrument, batch)| Some((batch.first()?.release_at, instrument.clone())))
+            .filter(|(closes_at, _)| *closes_at <= now)
+            .collect();
+        due.sort();
+
+        let batches: Vec<Vec<DelayedOrder>> = due.into_iter().filter_map(|(_, instrument)| self.batches.remove(&instrument)).collect();
+        let auctions = batches.into_iter().map(|batch| self.run_auction(batch, logger)).collect();
+        self.update_indexes();
+        auctions
+    }
+
+    fn run_auction(&mut self, batch: Vec<DelayedOrder>, logger: &mut Box<dyn SimLogger>) -> AuctionResult {
+        let instrument = batch[0].order.instrument.clone();
+        let mut owners = UuidMap::default();
+        let orders = batch
+            .into_iter()
+            .map(|DelayedOrder { order, owner, .. }| {
+                if let Some(owner) = owner {
+                    owners.insert(order.order_id, owner);
+                }
+                order
+            })
+            .collect();
+
+        let book = self.books.get_mut(&instrument).expect("batched orders have a market");
+        let Uncross { price, trades, filled, batch } = book.uncross(orders, &mut self.ctx);
+        if let Some(store) = self.trade_stores.get_mut(&instrument) {
+            for trade in &trades {
+                store.record(trade.clone());
+            }
+        }
+        let expired: Vec<&Order> = batch.iter().filter(|order| !order.rests() && !order.is_filled()).collect();
+
+        let log_start = self.latency_nanos();
+        for trade in &trades {
+            logger.log_trade(trade);
+        }
+        for order in filled.iter().chain(expired.iter().copied()) {
+            logger.log_order_filled(order, Execution::last_for(order.order_id, &trades));
+        }
+        let log_duration = self.elapsed_since(log_start);
+
+        for trade in &trades {
+            for order_id in [trade.buy_order_id, trade.sell_order_id] {
+                let client = owners.get(&order_id).or_else(|| self.clients.owner(&order_id));
+                if let Some(client) = client {
+                    self.messages.execution(client, trade.quantity);
+                }
+                if let Some(margin) = &mut self.margin {
+                    let side = if order_id == trade.buy_order_id { Side::Buy } else { Side::Sell };
+                    margin.record_fill(client, side, trade);
+                }
+            }
+        }
+        for order in &filled {
+            self.clients.remove(&order.order_id);
+            self.client_order_ids.update(order);
+        }
+        for order in &batch {
+            if let Some(owner) = owners.remove(&order.order_id)
+                && order.rests()
+                && !order.is_filled() {
+                self.clients.insert(order.order_id, owner);
+            }
+            self.client_order_ids.update(order);
+        }
+
+        if self.publishes_events() {
+            for trade in &trades {
+                self.notify(EngineEvent::Trade(trade));
+            }
+            for order in filled.iter().chain(expired.iter().copied()) {
+                self.notify(EngineEvent::OrderFilled { order, execution: Execution::last_for(order.order_id, &trades) });
+            }
+            for order in batch.iter().filter(|order| order.rests() && !order.is_filled()) {
+                self.notify(EngineEvent::OrderRested(order));
+            }
+        }
+
+        self.book_changed(&instrument);
+        let log_duration = log_duration + self.log_repricing(logger);
+        AuctionResult { instrument, price, orders: batch, trades, log_duration }
+    }
+
+    /// Matches an admitted order, returning its trades, the time spent
+    /// logging and the order's state once matching is done.
+    fn match_order(&mut self, order: Order, owner: Option<ClientId>, logger: &mut Box<dyn SimLogger>) -> (Vec<Trade>, u128, Order) {
+        profile_scope!("match_order");
+        let book = self.books.get_mut(&order.instrument).expect("admitted orders have a market");
+        let shadowed = self.shadow.is_some().then(|| order.clone());
+        let (trades, filled_orders, final_incoming_state) = {
+            profile_scope!("book_match");
+            book.add_order_with(order, &mut self.ctx)
+        };
+        if let (Some(shadow), Some(order)) = (&mut self.shadow, shadowed) {
+            shadow.match_order(self.sequence, order, &trades, book);
+        }
+        if let Some(store) = self.trade_stores.get_mut(&final_incoming_state.instrument) {
+            for trade in &trades {
+                store.record(trade.clone());
+            }
+        }
+
+        let incoming_done = final_incoming_state.is_filled() || !final_incoming_state.rests();
+
+        let log_start = self.latency_nanos();
+        {
+            profile_scope!("log_fills");
+            for trade in &trades {
+                logger.log_trade(trade);
+            }
+            for filled_order in &filled_orders {
+                logger.log_order_filled(filled_order, Execution::last_for(filled_order.order_id, &trades));
+            }
+            if incoming_done {
+                logger.log_order_filled(&final_incoming_state, trades.last().map(Execution::of));
+            }
+        }
+        let log_duration = self.elapsed_since(log_start);
+
+        for trade in &trades {
+            for order_id in [trade.buy_order_id, trade.sell_order_id] {
+                let client = if order_id == final_incoming_state.order_id { owner.as_ref() } else { self.clients.owner(&order_id) };
+                if let Some(client) = client {
+                    self.messages.execution(client, trade.quantity);
+                }
+                if let Some(margin) = &mut self.margin {
+                    let side = if order_id == trade.buy_order_id { Side::Buy } else { Side::Sell };
+                    margin.record_fill(client, side, trade);
+                }
+            }
+        }
+        for filled_order in &filled_orders {
+            self.clients.remove(&filled_order.order_id);
+            self.client_order_ids.update(filled_order);
+        }
+        if let Some(owner) = owner
+            && !incoming_done {
+            self.clients.insert(final_incoming_state.order_id, owner);
+        }
+
+        if self.publishes_events() {
+            for trade in &trades {
+                self.notify(EngineEvent::Trade(trade));
+            }
+            for filled_order in &filled_orders {
+                let execution = Execution::last_for(filled_order.order_id, &trades);
+                self.notify(EngineEvent::OrderFilled { order: filled_order, execution });
+            }
+            if incoming_done {
+                let execution = trades.last().map(Execution::of);
+                self.notify(EngineEvent::OrderFilled { order: &final_incoming_state, execution });
+            } else {
+                self.notify(EngineEvent::OrderRested(&final_incoming_state));
+            }
+        }
+
+        self.client_order_ids.update(&final_incoming_state);
+        self.book_changed(&final_incoming_state.instrument);
+        let log_duration = log_duration + self.log_repricing(logger);
+        (trades, log_duration, final_incoming_state)
+    }
+
+    /// Follows up a change to the book of `instrument`: re-prices its
+    /// pegged orders, then publishes its imbalance if that moved.
+    fn book_changed(&mut self, instrument: &str) {
+        let Some(book) = self.books.get_mut(instrument) else { return };
+        let repriced = book.reprice_pegs();
+        for (order, old_price) in &repriced {
+            self.notify(EngineEvent::OrderRepriced { order, old_price: *old_price });
+        }
+        self.repriced.extend(repriced);
+        self.update_imbalance(instrument);
+    }
+
+    fn update_imbalance(&mut self, instrument: &str) {
+        let Some(levels) = self.imbalance_levels else { return };
+        let imbalance = self.books.get(instrument).and_then(|book| book.imbalance(levels));
+        let moved = match imbalance {
+            Some(imbalance) => match self.imbalances.get_mut(instrument) {
+                Some(last) => std::mem::replace(last, imbalance) != imbalance,
+                None => self.imbalances.insert(instrument.to_string(), imbalance).is_none(),
+            },
+            None => self.imbalances.remove(instrument).is_some(),
+        };
+        if moved {
+            self.notify(EngineEvent::Imbalance { instrument, imbalance });
+        }
+    }
+
+    /// The last published imbalance of `instrument`'s book. `None` if the
+    /// engine does not compute it or the top levels are empty.
+    pub fn imbalance(&self, instrument: &str) -> Option<Decimal> {
+        self.imbalances.get(instrument).copied()
+    }
+
+    /// Logs the pegged orders re-priced since the last call and returns the
+    /// time spent logging. Order entry does this itself; call it after
+    /// cancels, which do not take a logger.
+    pub fn log_repricing(&mut self, logger: &mut Box<dyn SimLogger>) -> u128 {
+        if self.repriced.is_empty() {
+            return 0;
+        }
+        let log_start = self.latency_nanos();
+        for (order, old_price) in self.repriced.drain(..) {
+            logger.log_order_repriced(&order, old_price);
+        }
+        self.elapsed_since(log_start)
+    }
+
+    /// A reading of the latency clock, in nanoseconds.
+    pub fn latency_nanos(&self) -> u64 {
+        self.ctx.latency_clock.now_nanos()
+    }
+
+    /// Nanoseconds on the latency clock since the reading `start`.
+    pub fn elapsed_since(&self, start: u64) -> u128 {
+        self.latency_nanos().saturating_sub(start) as u128
+    }
+
+    /// Cancels a resting order, or one still held by a speed bump or waiting
+    /// for a batch auction.
+    pub fn cancel_order_by_id(&mut self, order_id: &Uuid, instrument: &str) -> Result<Order, MatchingEngineError> {
+        OperationSpan::cancel(order_id, instrument).run(|| {
+            self.sequence += 1;
+            self.cancel_by_id(order_id, instrument)
+        })
+    }
+
+    fn cancel_by_id(&mut self, order_id: &Uuid, instrument: &str) -> Result<Order, MatchingEngineError> {
+        if let Some(held) = self.take_held_order(order_id, instrument) {
+            return Ok(self.cancel_held(held));
+        }
+        let canceled = match self.books.get_mut(instrument) {
+            Some(book) => book.cancel_order(order_id)?,
+            None => return Err(MatchingEngineError::MarketNotFound(instrument.to_string())),
+        };
+        if let (Some(shadow), Some(book)) = (&mut self.shadow, self.books.get(instrument)) {
+            shadow.cancel(self.sequence, &[*order_id], book);
+        }
+        self.clients.remove(order_id);
+        self.client_order_ids.update(&canceled);
+        self.notify(EngineEvent::OrderCanceled(&canceled));
+        self.book_changed(instrument);
+        Ok(canceled)
+    }
+
+    /// Enters an order under a client order id. A retry of an id the client
+    /// already used today returns the original order instead of creating a
+    /// second one. Rejected orders do not use up their id.
+    pub fn submit_client_order(
+        &mut self,
+        order: Order,
+        client: ClientId,
+        client_order_id: &str,
+        logger: &mut Box<dyn SimLogger>,
+    ) -> Result<Submission, MatchingEngineError> {
+        OperationSpan::order(&order).submit(|| self.submit_client(order, client, client_order_id, logger))
+    }
+
+    fn submit_client(
+        &mut self,
+        mut order: Order,
+        client: ClientId,
+        client_order_id: &str,
+        logger: &mut Box<dyn SimLogger>,
+    ) -> Result<Submission, MatchingEngineError> {
+        self.sequence += 1;
+        let now = self.ctx.clock.now_nanos();
+        if let Some(original) = self.client_order_ids.lookup(&client, client_order_id, now) {
+            // A resting order may have been partially filled since it was recorded.
+            let current = self.books.get(&original.instrument).and_then(|book| book.order(&original.order_id));
+            return Ok(Submission::Duplicate(current.unwrap_or_else(|| original.clone())));
+        }
+
+        match self.admit(&mut order, Some(&client), logger)? {
+            _ if self.waits_for_stop(&order) => {
+                self.client_order_ids.insert(client.clone(), client_order_id.to_string(), &order);
+                self.park(order, Some(client));
+                Ok(Submission::Stopped)
+            }
+            Admission::Match => {
+                let (trades, log_duration, state) = self.match_order(order, Some(client.clone()), logger);
+                self.client_order_ids.insert(client, client_order_id.to_string(), &state);
+                self.follow_up(&trades, logger);
+                Ok(Submission::New(trades, log_duration))
+            }
+            admission => {
+                self.client_order_ids.insert(client.clone(), client_order_id.to_string(), &order);
+                Ok(Submission::Delayed { release_at: self.hold(order, Some(client), admission) })
+            }
+        }
+    }
+
+    /// Cancels an order on behalf of `client`. Orders owned by another client,
+    /// or by none, are left alone and the attempt is logged as a rejection.
+    pub fn cancel_client_order(
+        &mut self,
+        client: &ClientId,
+        order_id: &Uuid,
+        instrument: &str,
+        logger: &mut Box<dyn SimLogger>,
+    ) -> Result<Order, MatchingEngineError> {
+        self.sequence += 1;
+        self.messages.cancel(client);
+        let foreign = match self.held_order(order_id, instrument) {
+            Some(held) => (held.owner.as_ref() != Some(client)).then(|| held.order.clone()),
+            None if self.clients.owner(order_id) != Some(client) => self.books.get(instrument).and_then(|book| book.order(order_id)),
+            None => None,
+        };
+        if let Some(order) = foreign {
+            let violation = MatchingEngineError::NotOrderOwner { order_id: *order_id, client: client.to_string() };
+            logger.log_order_rejected(&order, &format!("cancel rejected: {}", violation));
+            return Err(violation);
+        }
+        self.cancel_by_id(order_id, instrument)
+    }
+
+    /// Cancels every live order `client` owns, as if each had been canceled
+    /// individually, and returns them. Orders still held by a speed bump or
+    /// waiting for a batch auction are canceled too, before they reach the
+    /// book.
+    pub fn cancel_client_orders(&mut self, client: &ClientId) -> Vec<Order> {
+        self.sequence += 1;
+        self.messages.cancel(client);
+        let mut canceled = self.take_held_orders_of(client);
+        for order_id in self.clients.orders_of(client) {
+            let instrument = self
+                .books
+                .iter()
+                .find(|(_, book)| book.contains(&order_id))
+                .map(|(instrument, _)| instrument.clone());
+            if let Some(instrument) = instrument
+                && let Ok(order) = self.cancel_by_id(&order_id, &instrument) {
+                canceled.push(order);
+            }
+        }
+        canceled
+    }
+
+    /// The order `order_id` if a speed bump or batch auction still holds it.
+    fn held_order(&self, order_id: &Uuid, instrument: &str) -> Option<&DelayedOrder> {
+        let is_it = |held: &&DelayedOrder| held.order.order_id == *order_id;
+        let delayed = self.delayed.get(instrument).and_then(|queue| queue.iter().find(is_it));
+        delayed
+            .or_else(|| self.batches.get(instrument)?.iter().find(is_it))
+            .or_else(|| self.stops.get(instrument)?.iter().find(is_it))
+    }
+
+    /// Removes the order `order_id` from the speed bump or batch auction
+    /// holding it.
+    fn take_held_order(&mut self, order_id: &Uuid, instrument: &str) -> Option<DelayedOrder> {
+        let is_it = |held: &DelayedOrder| held.order.order_id == *order_id;
+        if let Some(queue) = self.delayed.get_mut(instrument)
+            && let Some(index) = queue.iter().position(is_it) {
+            return queue.remove(index);
+        }
+        if let Some(stops) = self.stops.get_mut(instrument)
+            && let Some(index) = stops.iter().position(is_it) {
+            return Some(stops.remove(index));
+        }
+        let batch = self.batches.get_mut(instrument)?;
+        let held = batch.remove(batch.iter().position(is_it)?);
+        if batch.is_empty() {
+            self.batches.remove(instrument);
+        }
+        Some(held)
+    }
+
+    /// Removes every order `client` has held by a speed bump or waiting for
+    /// a batch auction, and returns them canceled.
+    fn take_held_orders_of(&mut self, client: &ClientId) -> Vec<Order> {
+        let owned = |held: &DelayedOrder| held.owner.as_ref() == Some(client);
+        let mut taken: Vec<DelayedOrder> = Vec::new();
+        for queue in self.delayed.values_mut() {
+            let (mine, others) = std::mem::take(queue).into_iter().partition(owned);
+            *queue = others;
+            taken.extend(mine);
+        }
+        for batch in self.batches.values_mut() {
+            let (mine, others): (Vec<_>, _) = std::mem::take(batch).into_iter().partition(owned);
+            *batch = others;
+            taken.extend(mine);
+        }
+        self.batches.retain(|_, batch| !batch.is_empty());
+        for stops in self.stops.values_mut() {
+            let (mine, others): (Vec<_>, _) = std::mem::take(stops).into_iter().partition(owned);
+            *stops = others;
+            taken.extend(mine);
+        }
+        taken.into_iter().map(|held| self.cancel_held(held)).collect()
+    }
+
+    /// Cancels an order that never reached the book. Subscribers have seen it
+    /// accepted but never rested, so there is nothing for them to remove.
+    fn cancel_held(&mut self, held: DelayedOrder) -> Order {
+        let mut order = held.order;
+        order.status = OrderStatus::Canceled;
+        self.client_order_ids.update(&order);
+        let now = self.ctx.clock.now_nanos();
+        if let Some(audit) = &mut self.audit {
+            audit.push(order.order_id, self.sequence, now, AuditEvent::Canceled { remaining: order.remaining_quantity });
+        }
+        order
+    }
+
+    pub fn order_owner(&self, order_id: &Uuid) -> Option<&ClientId> {
+        self.clients.owner(order_id)
+    }
+
+    /// Live orders owned by `client`.
+    pub fn client_orders(&self, client: &ClientId) -> Vec<Uuid> {
+        self.clients.orders_of(client)
+    }
+
+    pub fn accounting_report(&self) -> AccountingReport {
+        AccountingReport::from_tallies(self.books.iter().map(|(instrument, book)| (instrument.clone(), book.tally())))
+    }
+
+    /// Price level churn summed over every book.
+    pub fn level_stats(&self) -> LevelStats {
+        let mut stats = LevelStats::default();
+        for book in self.books.values() {
+            stats.add(&book.level_stats());
+        }
+        stats
+    }
+
+    pub fn trades_between(&self, instrument: &str, from: u64, to: u64) -> Vec<&Trade> {
+        self.trade_stores
+            .get(instrument)
+            .map(|store| store.trades_between(from, to).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn recent_trades(&self, instrument: &str, count: usize) -> Vec<&Trade> {
+        self.trade_stores
+            .get(instrument)
+            .map(|store| store.recent(count).collect())
+            .unwrap_or_default()
+    }
+
+    /// Busts a trade printed within the bust window: takes it off the tape,
+    /// restates the book's last trade price and publishes the bust. The
+    /// orders it filled stay filled. Margin accounts are not restated,
+    /// because the engine no longer knows who owned a filled order; the
+    /// caller reverses the fills from `take_busted_trades`.
+    pub fn bust_trade(&mut self, instrument: &str, trade_id: &Uuid) -> Result<Trade, MatchingEngineError> {
+        let store = self.trade_stores.get_mut(instrument).ok_or_else(|| MatchingEngineError::MarketNotFound(instrument.to_string()))?;
+        let trade = store.get(trade_id).ok_or(MatchingEngineError::TradeNotFound(*trade_id))?;
+        let now = self.ctx.clock.now_nanos();
+        if now.saturating_sub(trade.timestamp) > self.bust_window.as_nanos() as u64 {
+            return Err(MatchingEngineError::BustWindowElapsed { trade_id: *trade_id, window: self.bust_window });
+        }
+        let trade = store.remove(trade_id).ok_or(MatchingEngineError::TradeNotFound(*trade_id))?;
+        let last_price = store.recent(1).next().map(|last| last.price);
+        if let Some(book) = self.books.get_mut(instrument) {
+            book.restate_last_trade(last_price);
+        }
+        self.notify(EngineEvent::TradeBusted(&trade));
+        self.update_indexes();
+        self.busted.push(trade.clone());
+        Ok(trade)
+    }
+
+    /// Trades busted since the last call, in the order they were busted.
+    pub fn take_busted_trades(&mut self) -> Vec<Trade> {
+        std::mem::take(&mut self.busted)
+    }
+
+    /// The current time on the engine clock.
+    pub fn now_nanos(&self) -> u64 {
+        self.ctx.clock.now_nanos()
+    }
+
+    pub fn order_book(&self, instrument: &str) -> Option<&OrderBook> {
+        self.books.get(instrument)
+    }
+
+    /// Message counts of every client that has sent an order or cancel.
+    pub fn message_accounting(&self) -> &MessageAccounting {
+        &self.messages
+    }
+
+    /// Replaces the order-to-trade cap; `None` lifts it.
+    pub fn set_otr_policy(&mut self, policy: Option<OtrPolicy>) {
+        self.messages.set_policy(policy);
+    }
+
+    /// Every instrument's book, in no particular order.
+    pub fn order_books(&self) -> impl Iterator<Item = (&str, &OrderBook)> {
+        self.books.iter().map(|(instrument, book)| (instrument.as_str(), book))
+    }
+
+    /// The book of `instrument`, with level ages measured to now.
+    pub fn get_order_book_display(&self, instrument: &str) -> Option<OrderBookDisplay> {
+        self.books.get(instrument).map(|book| OrderBookDisplay { as_of: Some(self.now_nanos()), ..book.display() })
+    }
+
+    /// A line per instrument: top of book, last trade and counts.
+    pub fn summary(&self) -> EngineSummary {
+        let instruments = self
+            .instruments()
+            .into_iter()
+            .map(|instrument| {
+                let book = &self.books[&instrument];
+                let touch = |side: Side| book.iter_levels(side).next().map(|(price, volume, _)| (price, volume));
+                InstrumentSummary {
+                    bid: touch(Side::Buy),
+                    ask: touch(Side::Sell),
+                    last_trade_price: book.last_trade_price(),
+                    resting_orders: book.order_count(),
+                    trades: book.trade_count(),
+                    halted: self.is_halted(&instrument),
+                    instrument,
+                }
+            })
+            .collect();
+        EngineSummary { instruments }
+    }
+}
+
+/// Rejects order attributes the book cannot honour: those not implemented
+/// yet, post-only orders that would trade on arrival, and options that have
+/// no meaning in a batch auction.
+fn check_attributes(order: &Order, book: &OrderBook) -> Result<(), MatchingEngineError> {
+    let attributes = order.attributes;
+    let unsupported = [
+        (attributes.is_reduce_only(), "REDUCE_ONLY"),
+        (attributes.is_hidden(), "HIDDEN"),
+        (attributes.is_all_or_none(), "AON"),
+        (attributes.self_trade_prevention() != SelfTradePrevention::Allow, "self-trade prevention"),
+    ];
+    if let Some((_, name)) = unsupported.iter().find(|(set, _)| *set) {
+        return Err(MatchingEngineError::UnsupportedAttribute(name.to_string()));
+    }
+    if book.config().batch_interval.is_some() {
+        if attributes.is_post_only() {
+            return Err(MatchingEngineError::UnsupportedAttribute("POST_ONLY in batch auctions".to_string()));
+        }
+        if attributes.time_in_force() == TimeInForce::FillOrKill {
+            return Err(MatchingEngineError::UnsupportedAttribute("FOK in batch auctions".to_string()));
+        }
+    } else if attributes.is_post_only() && book.is_marketable(order) {
+        return Err(MatchingEngineError::PostOnlyWouldTrade(order.order_id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::IdGenerator;
+    use crate::logging::types::LoggingMode;
+    use crate::logging::create_logger;
+    use crate::order::{Order, OrderAttributes, Peg, PegReference};
+    use crate::utils::{Side, OrderType};
+    use crate::utils::MatchingEngineError;
+    use crate::clock::ManualClock;
+    use crate::config::{BandAction, InstrumentConfig, OptionContract, OptionKind, Perpetual, PriceBand, PriceCollar, RiskLimits};
+    use crate::margin::{MarginBook, MarginRequirement};
+    use crate::index::IndexDefinition;
+    use crate::ids::{InstrumentSequenceIds, SequentialIds};
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    struct RecordingSubscriber {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl EngineSubscriber for RecordingSubscriber {
+        fn on_event(&mut self, event: &EngineEvent) {
+            let name = match event {
+                EngineEvent::OrderAccepted { .. } => "accepted",
+                EngineEvent::OrderRested(_) => "rested",
+                EngineEvent::OrderRejected { .. } => "rejected",
+                EngineEvent::Trade(_) => "trade",
+                EngineEvent::OrderFilled { .. } => "filled",
+                EngineEvent::OrderCanceled(_) => "canceled",
+                EngineEvent::OrderExpired(_) => "expired",
+                EngineEvent::OrderRepriced { .. } => "repriced",
+                EngineEvent::TradeBusted(_) => "busted",
+                EngineEvent::IndexPrice { .. } => "index",
+                EngineEvent::Imbalance { .. } => "imbalance",
+            };
+            self.events.lock().unwrap().push(name.to_string());
+        }
+    }
+
+    #[test]
+    fn test_builder_registers_configured_instruments() {
+        let engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("NVO", InstrumentConfig { tick_size: Some(dec!(0.05)), ..Default::default() })
+            .build()
+            .unwrap();
+
+        assert_eq!(engine.instruments(), vec!["NVO".to_string(), "SOFI".to_string()]);
+    }
+
+    #[test]
+    fn test_orders_are_stamped_by_the_engine_clock_and_timed_by_the_latency_clock() {
+        let (clock, latency_clock) = (ManualClock::new(9_000), ManualClock::new(100));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .latency_clock(latency_clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let first = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        let first_id = first.order_id;
+        engine.process_order(first, &mut logger).unwrap();
+        // The wall clock is stepped back; elapsed times are not affected.
+        clock.set(4_000);
+        let start = engine.latency_nanos();
+        latency_clock.advance(250);
+        let second = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        let second_id = second.order_id;
+        engine.process_order(second, &mut logger).unwrap();
+
+        let book = engine.order_book("SOFI").unwrap();
+        assert_eq!(book.order(&first_id).map(|order| order.timestamp), Some(9_000));
+        assert_eq!(book.order(&second_id).map(|order| order.timestamp), Some(4_000));
+        assert_eq!(engine.elapsed_since(start), 250);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_configuration() {
+        let duplicate = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("SOFI", InstrumentConfig::default())
+            .build();
+        assert!(matches!(duplicate, Err(MatchingEngineError::InvalidConfig(_))));
+
+        let bad_tick = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { tick_size: Some(dec!(-1)), ..Default::default() })
+            .build();
+        assert!(matches!(bad_tick, Err(MatchingEngineError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_only_the_owning_client_can_cancel() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+
+        let owned = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        let owned_id = owned.order_id;
+        engine.submit_order(owned, Some(alice.clone()), &mut logger).unwrap();
+        let unowned = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(9), dec!(5));
+        let unowned_id = unowned.order_id;
+        engine.process_order(unowned, &mut logger).unwrap();
+        assert_eq!(engine.order_owner(&owned_id), Some(&alice));
+
+        assert!(matches!(
+            engine.cancel_client_order(&bob, &owned_id, "SOFI", &mut logger),
+            Err(MatchingEngineError::NotOrderOwner { .. })
+        ));
+        assert!(matches!(
+            engine.cancel_client_order(&alice, &unowned_id, "SOFI", &mut logger),
+            Err(MatchingEngineError::NotOrderOwner { .. })
+        ));
+        assert!(matches!(
+            engine.cancel_client_order(&bob, &Uuid::new_v4(), "SOFI", &mut logger),
+            Err(MatchingEngineError::OrderNotFound(_))
+        ));
+
+        engine.cancel_client_order(&alice, &owned_id, "SOFI", &mut logger).unwrap();
+        assert!(engine.client_orders(&alice).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_client_order_id_returns_the_original_order() {
+        let clock = ManualClock::new(0);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let alice = ClientId::new("alice");
+
+        let first = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        let first_id = first.order_id;
+        assert!(matches!(engine.submit_client_order(first, alice.clone(), "A-1", &mut logger), Ok(Submission::New(..))));
+        let sell = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(2));
+        engine.process_order(sell, &mut logger).unwrap();
+
+        let retry = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        let Ok(Submission::Duplicate(original)) = engine.submit_client_order(retry, alice.clone(), "A-1", &mut logger) else {
+            panic!("expected the retry to be detected as a duplicate");
+        };
+        assert_eq!(original.order_id, first_id);
+        assert_eq!(original.remaining_quantity, dec!(3));
+        assert_eq!(engine.resting_orders("SOFI").len(), 1);
+
+        let other_client = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        assert!(matches!(engine.submit_client_order(other_client, ClientId::new("bob"), "A-1", &mut logger), Ok(Submission::New(..))));
+        clock.advance(crate::clock::NANOS_PER_DAY);
+        let next_day = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        assert!(matches!(engine.submit_client_order(next_day, alice, "A-1", &mut logger), Ok(Submission::New(..))));
+    }
+
+    #[test]
+    fn test_cancel_client_orders_leaves_other_clients_alone() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("NVO", InstrumentConfig::default())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+
+        for (instrument, client) in [("SOFI", &alice), ("NVO", &alice), ("SOFI", &bob)] {
+            let order = Order::new_limit(Uuid::new_v4(), instrument.to_string(), Side::Buy, dec!(10), dec!(5));
+            engine.submit_order(order, Some(client.clone()), &mut logger).unwrap();
+        }
+
+        assert_eq!(engine.cancel_client_orders(&alice).len(), 2);
+        assert!(engine.client_orders(&alice).is_empty());
+        assert_eq!(engine.client_orders(&bob).len(), 1);
+        assert!(engine.resting_orders("NVO").is_empty());
+    }
+
+    #[test]
+    fn test_otr_cap_rejects_a_client_that_quotes_without_trading() {
+        let policy = OtrPolicy { max_ratio: 2.0, grace: 2, action: crate::messaging::OtrAction::Reject };
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).otr_policy(policy).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+        let order = |side, price| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, dec!(5));
+
+        let resting = order(Side::Sell, dec!(10));
+        let resting_id = resting.order_id;
+        engine.submit_order(resting, Some(alice.clone()), &mut logger).unwrap();
+        engine.cancel_client_order(&alice, &resting_id, "SOFI", &mut logger).unwrap();
+        let over = engine.submit_order(order(Side::Sell, dec!(10)), Some(alice.clone()), &mut logger);
+        assert!(matches!(over, Err(MatchingEngineError::OrderToTradeRatioExceeded { .. })));
+        // Orders without a client are never capped.
+        engine.submit_order(order(Side::Sell, dec!(10)), None, &mut logger).unwrap();
+        engine.submit_order(order(Side::Buy, dec!(10)), Some(bob.clone()), &mut logger).unwrap();
+
+        let clients = engine.message_accounting().clients();
+        assert_eq!((clients[&alice].new_orders, clients[&alice].cancels, clients[&alice].rejected), (2, 1, 1));
+        assert_eq!((clients[&bob].trades, clients[&bob].executed_quantity), (1, dec!(5)));
+        assert_eq!(clients[&bob].order_to_trade_ratio(), Some(1.0));
+    }
+
+    #[test]
+    fn test_filled_orders_leave_the_client_index() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let alice = ClientId::new("alice");
+
+        let resting = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5));
+        engine.submit_order(resting, Some(alice.clone()), &mut logger).unwrap();
+        let taker = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5));
+        engine.submit_order(taker, Some(alice.clone()), &mut logger).unwrap();
+
+        assert!(engine.client_orders(&alice).is_empty());
+    }
+
+    #[test]
+    fn test_speed_bump_delays_only_aggressive_orders() {
+        let clock = ManualClock::new(0);
+        let bump = std::time::Duration::from_micros(350);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { speed_bump: Some(bump), ..Default::default() })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let resting = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5));
+        assert!(matches!(engine.submit_order(resting, None, &mut logger), Ok(Submission::New(..))));
+        assert_eq!(engine.resting_orders("SOFI").len(), 1);
+
+        let taker = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(2));
+        let taker_id = taker.order_id;
+        assert!(matches!(
+            engine.submit_order(taker, None, &mut logger),
+            Ok(Submission::Delayed { release_at: 350_000 })
+        ));
+        assert_eq!(engine.delayed_orders(), 1);
+        assert!(engine.release_delayed_orders(&mut logger).is_empty());
+
+        let passive = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(9), dec!(1));
+        assert!(matches!(engine.submit_order(passive, None, &mut logger), Ok(Submission::New(..))));
+        assert_eq!(engine.resting_orders("SOFI").len(), 2);
+
+        clock.advance(bump.as_nanos() as u64);
+        let released = engine.release_delayed_orders(&mut logger);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].order.order_id, taker_id);
+        assert_eq!(released[0].trades.len(), 1);
+        assert_eq!(engine.delayed_orders(), 0);
+    }
+
+    #[test]
+    fn test_batch_auction_runs_when_the_interval_ends() {
+        let clock = ManualClock::new(30_000_000);
+        let interval = std::time::Duration::from_millis(100);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { batch_interval: Some(interval), ..Default::default() })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let alice = ClientId::new("alice");
+
+        let sell = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5));
+        assert!(matches!(
+            engine.submit_order(sell, None, &mut logger),
+            Ok(Submission::Delayed { release_at: 100_000_000 })
+        ));
+        let buy = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(11), dec!(8));
+        let buy_id = buy.order_id;
+        engine.submit_order(buy, Some(alice.clone()), &mut logger).unwrap();
+        assert_eq!(engine.batched_orders(), 2);
+        assert!(engine.resting_orders("SOFI").is_empty());
+        assert!(engine.run_due_auctions(&mut logger).is_empty());
+
+        clock.set(100_000_000);
+        let auctions = engine.run_due_auctions(&mut logger);
+        assert_eq!(auctions.len(), 1);
+        assert_eq!(auctions[0].price, Some(dec!(10)));
+        assert_eq!(auctions[0].trades.len(), 1);
+        assert_eq!(engine.batched_orders(), 0);
+        assert_eq!(engine.client_orders(&alice), vec![buy_id]);
+        assert_eq!(engine.resting_orders("SOFI")[0].remaining_quantity, dec!(3));
+    }
+
+    #[test]
+    fn test_halted_market_rejects_orders_and_keeps_held_ones() {
+        let clock = ManualClock::new(0);
+        let bump = std::time::Duration::from_micros(350);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { speed_bump: Some(bump), ..Default::default() })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let resting = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5));
+        let resting_id = resting.order_id;
+        engine.process_order(resting, &mut logger).unwrap();
+        let taker = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(2));
+        engine.submit_order(taker, None, &mut logger).unwrap();
+
+        engine.halt_market("SOFI").unwrap();
+        assert!(engine.is_halted("SOFI"));
+        let rejected = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(11), dec!(1));
+        assert!(matches!(engine.process_order(rejected, &mut logger), Err(MatchingEngineError::MarketHalted(_))));
+        clock.advance(bump.as_nanos() as u64);
+        assert!(engine.release_delayed_orders(&mut logger).is_empty());
+        assert_eq!(engine.delayed_orders(), 1);
+
+        engine.resume_market("SOFI").unwrap();
+        assert_eq!(engine.release_delayed_orders(&mut logger)[0].trades.len(), 1);
+        engine.cancel_order_by_id(&resting_id, "SOFI").unwrap();
+        assert!(matches!(engine.halt_market("NVO"), Err(MatchingEngineError::MarketNotFound(_))));
+    }
+
+    #[test]
+    fn test_markets_sizes_and_limits_change_at_runtime() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        engine.create_market("NVO".to_string(), InstrumentConfig { lot_size: Some(dec!(10)), ..Default::default() }).unwrap();
+        assert!(matches!(
+            engine.create_market("SOFI".to_string(), InstrumentConfig::default()),
+            Err(MatchingEngineError::InvalidConfig(_))
+        ));
+        assert_eq!(engine.instruments(), vec!["NVO".to_string(), "SOFI".to_string()]);
+
+        engine.set_order_sizes("SOFI", Some(dec!(0.05)), None).unwrap();
+        let off_tick = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10.01), dec!(1));
+        assert!(matches!(engine.process_order(off_tick, &mut logger), Err(MatchingEngineError::InvalidTickSize { .. })));
+        assert!(engine.set_order_sizes("SOFI", Some(dec!(0)), None).is_err());
+        assert_eq!(engine.order_book("SOFI").unwrap().config().tick_size, Some(dec!(0.05)));
+
+        engine.set_risk_limits(RiskLimits { max_order_quantity: Some(dec!(5)), ..Default::default() });
+        let too_big = Order::new_limit(Uuid::new_v4(), "NVO".to_string(), Side::Buy, dec!(10), dec!(20));
+        assert!(matches!(engine.process_order(too_big, &mut logger), Err(MatchingEngineError::RiskLimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_held_orders_can_be_canceled_by_their_owner() {
+        let clock = ManualClock::new(0);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { speed_bump: Some(std::time::Duration::from_micros(350)), ..Default::default() })
+            .instrument("NVO", InstrumentConfig { batch_interval: Some(std::time::Duration::from_millis(100)), ..Default::default() })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+
+        engine.submit_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5)), None, &mut logger).unwrap();
+        let delayed = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(2));
+        let delayed_id = delayed.order_id;
+        assert!(matches!(engine.submit_order(delayed, Some(alice.clone()), &mut logger), Ok(Submission::Delayed { .. })));
+        let batched = Order::new_limit(Uuid::new_v4(), "NVO".to_string(), Side::Buy, dec!(20), dec!(1));
+        let batched_id = batched.order_id;
+        engine.submit_order(batched, None, &mut logger).unwrap();
+
+        assert!(matches!(
+            engine.cancel_client_order(&bob, &delayed_id, "SOFI", &mut logger),
+            Err(MatchingEngineError::NotOrderOwner { .. })
+        ));
+        assert_eq!(engine.delayed_orders(), 1);
+        let canceled = engine.cancel_client_order(&alice, &delayed_id, "SOFI", &mut logger).unwrap();
+        assert_eq!((canceled.order_id, canceled.status), (delayed_id, OrderStatus::Canceled));
+        assert_eq!(engine.cancel_order_by_id(&batched_id, "NVO").unwrap().status, OrderStatus::Canceled);
+        assert!(matches!(engine.cancel_order_by_id(&batched_id, "NVO"), Err(MatchingEngineError::OrderNotFound(_))));
+        assert_eq!((engine.delayed_orders(), engine.batched_orders()), (0, 0));
+
+        clock.advance(100_000_000);
+        assert!(engine.release_delayed_orders(&mut logger).is_empty());
+        assert!(engine.run_due_auctions(&mut logger).is_empty());
+        assert_eq!(engine.resting_orders("SOFI").len(), 1);
+        assert!(engine.resting_orders("NVO").is_empty());
+    }
+
+    #[test]
+    fn test_disconnect_cancels_orders_that_are_still_held() {
+        let clock = ManualClock::new(0);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { speed_bump: Some(std::time::Duration::from_micros(350)), ..Default::default() })
+            .instrument("NVO", InstrumentConfig { batch_interval: Some(std::time::Duration::from_millis(100)), ..Default::default() })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+
+        engine.submit_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5)), Some(bob.clone()), &mut logger).unwrap();
+        let delayed = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(8));
+        let batched = Order::new_limit(Uuid::new_v4(), "NVO".to_string(), Side::Buy, dec!(20), dec!(1));
+        let held_ids = vec![delayed.order_id, batched.order_id];
+        engine.submit_client_order(delayed, alice.clone(), "A-1", &mut logger).unwrap();
+        engine.submit_order(batched, Some(alice.clone()), &mut logger).unwrap();
+        engine.submit_order(Order::new_limit(Uuid::new_v4(), "NVO".to_string(), Side::Sell, dec!(21), dec!(1)), Some(bob.clone()), &mut logger).unwrap();
+        assert_eq!((engine.delayed_orders(), engine.batched_orders()), (1, 2));
+
+        let canceled = engine.cancel_client_orders(&alice);
+        assert_eq!(canceled.iter().map(|order| order.order_id).collect::<Vec<_>>(), held_ids);
+        assert!(canceled.iter().all(|order| order.status == OrderStatus::Canceled));
+        assert_eq!((engine.delayed_orders(), engine.batched_orders()), (0, 1));
+
+        clock.advance(100_000_000);
+        assert!(engine.release_delayed_orders(&mut logger).is_empty());
+        assert_eq!(engine.run_due_auctions(&mut logger)[0].trades.len(), 0);
+        assert!(engine.client_orders(&alice).is_empty());
+        let resting: Vec<Uuid> = ["SOFI", "NVO"].iter().flat_map(|instrument| engine.resting_orders(instrument)).map(|order| order.order_id).collect();
+        assert!(held_ids.iter().all(|id| !resting.contains(id)));
+        assert_eq!(resting.len(), 2);
+    }
+
+    #[test]
+    fn test_stop_orders_cascade_until_no_stop_is_reached() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let sofi = |side, price, quantity| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+        for (price, quantity) in [(dec!(100), dec!(1)), (dec!(99), dec!(2)), (dec!(97), dec!(5)), (dec!(90), dec!(10))] {
+            engine.submit_order(sofi(Side::Buy, price, quantity), None, &mut logger).unwrap();
+        }
+        // The first sell stop sells through 99 to 97, which reaches the second;
+        // that one only trades at 97, out of reach of the third.
+        let stop = |stop_price, quantity| Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, quantity).with_stop(stop_price);
+        let stops = [stop(dec!(100), dec!(3)), stop(dec!(98), dec!(3)), stop(dec!(95), dec!(1))];
+        let ids: Vec<Uuid> = stops.iter().map(|order| order.order_id).collect();
+        for order in stops {
+            assert!(matches!(engine.submit_order(order, None, &mut logger), Ok(Submission::Stopped)));
+        }
+        assert_eq!(engine.pending_stops(), 3);
+
+        let (trades, _) = engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(1)), &mut logger).unwrap();
+        assert_eq!(trades.len(), 1);
+        let triggered = engine.take_triggered_stops();
+        assert_eq!(triggered.iter().map(|released| released.order.order_id).collect::<Vec<_>>(), ids[..2]);
+        assert_eq!(triggered[1].trades.iter().map(|trade| trade.price).collect::<Vec<_>>(), vec![dec!(97)]);
+        let [cascade] = engine.stop_cascades() else { panic!("expected one cascade") };
+        assert_eq!((cascade.depth, cascade.stops, cascade.volume), (2, 2, dec!(6)));
+        assert_eq!(cascade.price_move(), dec!(-3));
+
+        assert_eq!(engine.cancel_order_by_id(&ids[2], "SOFI").unwrap().status, OrderStatus::Canceled);
+        assert_eq!(engine.pending_stops(), 0);
+        // A stop the last trade already reached is matched on arrival.
+        assert!(matches!(engine.submit_order(stop(dec!(99), dec!(1)), None, &mut logger), Ok(Submission::New(trades, _)) if trades.len() == 1));
+        assert_eq!(engine.stop_cascades().len(), 1);
+    }
+
+    #[test]
+    fn test_accounts_below_maintenance_margin_are_liquidated() {
+        let margin = MarginBook::new(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }).default_collateral(dec!(100));
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).margin(margin).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let alice = ClientId::new("alice");
+        let sofi = |side, price, quantity| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+
+        engine.submit_order(sofi(Side::Sell, dec!(10), dec!(200)), None, &mut logger).unwrap();
+        let too_big = engine.submit_order(sofi(Side::Buy, dec!(10), dec!(101)), Some(alice.clone()), &mut logger);
+        assert!(matches!(too_big, Err(MatchingEngineError::InsufficientMargin { .. })));
+        engine.submit_order(sofi(Side::Buy, dec!(10), dec!(100)), Some(alice.clone()), &mut logger).unwrap();
+        let rest = engine.resting_orders("SOFI")[0].order_id;
+        engine.cancel_order_by_id(&rest, "SOFI").unwrap();
+        assert!(engine.take_liquidations().is_empty());
+
+        // A print at 9.4 leaves 40 of equity against 47 of maintenance.
+        engine.submit_order(sofi(Side::Buy, dec!(9.4), dec!(1)), None, &mut logger).unwrap();
+        engine.submit_order(sofi(Side::Buy, dec!(9.3), dec!(200)), None, &mut logger).unwrap();
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(1)), &mut logger).unwrap();
+
+        let liquidations = engine.take_liquidations();
+        let [liquidation] = &liquidations[..] else { panic!("expected one liquidation, got {:?}", liquidations) };
+        assert_eq!((&liquidation.client, liquidation.order.side, liquidation.order.quantity), (&alice, Side::Sell, dec!(100)));
+        assert_eq!(liquidation.trades.iter().map(|trade| trade.price).collect::<Vec<_>>(), vec![dec!(9.3)]);
+        let margin = engine.margin().unwrap();
+        assert_eq!(margin.calls().len(), 1);
+        assert!(margin.account(&alice).unwrap().positions["SOFI"].quantity.is_zero());
+        assert_eq!(margin.equity(&alice), Some(dec!(30)));
+    }
+
+    #[test]
+    fn test_liquidation_waits_for_a_halted_market_to_resume() {
+        let margin = MarginBook::new(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }).default_collateral(dec!(100));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("AAPL", InstrumentConfig::default())
+            .margin(margin)
+            .trade_ids(TradeIdStrategy::Sequential { seed: 7 })
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let alice = ClientId::new("alice");
+        let limit = |instrument: &str, side, price, quantity| Order::new_limit(Uuid::new_v4(), instrument.to_string(), side, price, quantity);
+        let market = |instrument: &str, side, quantity| Order::new_market(Uuid::new_v4(), instrument.to_string(), side, quantity);
+
+        engine.submit_order(limit("SOFI", Side::Sell, dec!(10), dec!(80)), None, &mut logger).unwrap();
+        engine.submit_order(limit("SOFI", Side::Buy, dec!(10), dec!(80)), Some(alice.clone()), &mut logger).unwrap();
+        engine.submit_order(limit("AAPL", Side::Sell, dec!(10), dec!(20)), None, &mut logger).unwrap();
+        engine.submit_order(limit("AAPL", Side::Buy, dec!(10), dec!(20)), Some(alice.clone()), &mut logger).unwrap();
+        engine.halt_market("SOFI").unwrap();
+
+        // A print at 7 leaves 40 of equity against 50 of maintenance, but
+        // only the AAPL position can be closed while SOFI is halted.
+        engine.submit_order(limit("AAPL", Side::Buy, dec!(7), dec!(1)), None, &mut logger).unwrap();
+        engine.submit_order(limit("AAPL", Side::Buy, dec!(6.9), dec!(100)), None, &mut logger).unwrap();
+        engine.process_order(market("AAPL", Side::Sell, dec!(1)), &mut logger).unwrap();
+        let liquidations = engine.take_liquidations();
+        let [liquidation] = &liquidations[..] else { panic!("expected one liquidation, got {:?}", liquidations) };
+        assert_eq!((liquidation.order.instrument.as_str(), liquidation.order.quantity), ("AAPL", dec!(20)));
+        assert_eq!(engine.margin().unwrap().account(&alice).unwrap().positions["SOFI"].quantity, dec!(80));
+        assert_eq!(engine.resting_orders("SOFI").len(), 0);
+
+        // Once SOFI trades again the deferred close-out goes out.
+        engine.resume_market("SOFI").unwrap();
+        engine.submit_order(limit("SOFI", Side::Buy, dec!(10), dec!(100)), None, &mut logger).unwrap();
+        engine.process_order(market("SOFI", Side::Sell, dec!(1)), &mut logger).unwrap();
+        let liquidations = engine.take_liquidations();
+        let [closed] = &liquidations[..] else { panic!("expected one liquidation, got {:?}", liquidations) };
+        assert_eq!((closed.order.instrument.as_str(), closed.order.quantity), ("SOFI", dec!(80)));
+        assert!(engine.margin().unwrap().account(&alice).unwrap().positions["SOFI"].quantity.is_zero());
+        // Close-out ids come from their own counter, so the sixth trade
+        // still has the sixth trade id.
+        assert_ne!(closed.order.order_id, liquidation.order.order_id);
+        let mut ids = SequentialIds::new(7);
+        let sixth = (0..6).map(|_| ids.next_trade_id()).last().unwrap();
+        assert_eq!(closed.trades.iter().map(|trade| trade.trade_id).collect::<Vec<_>>(), vec![sixth]);
+    }
+
+    #[test]
+    fn test_perpetual_funding_is_charged_to_margin_accounts_each_interval() {
+        let clock = ManualClock::new(0);
+        let perpetual = Perpetual { rate_cap: dec!(0.005), ..Perpetual::new(dec!(10), std::time::Duration::from_secs(1)) };
+        let margin = MarginBook::new(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }).default_collateral(dec!(100));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { perpetual: Some(perpetual), ..Default::default() })
+            .clock(clock.clone())
+            .margin(margin)
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+        let sofi = |side, price, quantity| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+
+        engine.submit_order(sofi(Side::Sell, dec!(10.1), dec!(50)), Some(bob.clone()), &mut logger).unwrap();
+        engine.submit_order(sofi(Side::Buy, dec!(10.1), dec!(50)), Some(alice.clone()), &mut logger).unwrap();
+        engine.submit_order(sofi(Side::Buy, dec!(10), dec!(5)), None, &mut logger).unwrap();
+        engine.submit_order(sofi(Side::Sell, dec!(10.2), dec!(5)), None, &mut logger).unwrap();
+        clock.set(500_000_000);
+        assert!(engine.run_due_funding(&mut logger).is_empty());
+
+        // A mid of 10.1 is 1% over the index, capped at 0.5%: the long pays.
+        clock.set(1_000_000_000);
+        let rounds = engine.run_due_funding(&mut logger);
+        assert_eq!(rounds.iter().map(|round| (round.timestamp, round.mark, round.rate)).collect::<Vec<_>>(), vec![(1_000_000_000, dec!(10.1), dec!(0.005))]);
+        let margin = engine.margin().unwrap();
+        assert_eq!((margin.equity(&alice), margin.equity(&bob)), (Some(dec!(97.475)), Some(dec!(102.525))));
+
+        // Rounds missed between operations are not made up.
+        engine.set_index_price("SOFI", dec!(10.2)).unwrap();
+        clock.set(3_500_000_000);
+        let rounds = engine.run_due_funding(&mut logger);
+        assert_eq!(rounds.iter().map(|round| (round.timestamp, round.rate)).collect::<Vec<_>>(), vec![(2_000_000_000, dec!(-0.005))]);
+        assert_eq!(engine.margin().unwrap().equity(&alice), Some(dec!(100)));
+        assert!(engine.set_index_price("SOFI", dec!(0)).is_err());
+    }
+
+    #[test]
+    fn test_options_in_the_money_are_exercised_at_expiry() {
+        let clock = ManualClock::new(0);
+        let call = OptionContract { kind: OptionKind::Call, underlying: "SOFI".to_string(), strike: dec!(10), expiry: Some(1_000_000_000) };
+        let margin = MarginBook::new(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }).default_collateral(dec!(100));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("SOFI-C10", InstrumentConfig { option: Some(call), ..Default::default() })
+            .clock(clock.clone())
+            .margin(margin)
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+        let order = |instrument: &str, side, price, quantity| Order::new_limit(Uuid::new_v4(), instrument.to_string(), side, price, quantity);
+
+        engine.submit_order(order("SOFI-C10", Side::Sell, dec!(1), dec!(2)), Some(bob.clone()), &mut logger).unwrap();
+        engine.submit_order(order("SOFI-C10", Side::Buy, dec!(1), dec!(2)), Some(alice.clone()), &mut logger).unwrap();
+        engine.submit_order(order("SOFI-C10", Side::Buy, dec!(0.5), dec!(3)), None, &mut logger).unwrap();
+        engine.submit_order(order("SOFI", Side::Sell, dec!(12), dec!(1)), None, &mut logger).unwrap();
+        engine.submit_order(order("SOFI", Side::Buy, dec!(12), dec!(1)), None, &mut logger).unwrap();
+        clock.set(500_000_000);
+        assert!(engine.run_due_expiries(&mut logger).is_empty());
+
+        clock.set(1_000_000_000);
+        let expiries = engine.run_due_expiries(&mut logger);
+        let [expiry] = &expiries[..] else { panic!("expected one expiry, got {:?}", expiries) };
+        assert_eq!((expiry.settlement, expiry.orders.len()), (Some(dec!(12)), 1));
+        assert!(engine.resting_orders("SOFI-C10").is_empty());
+        let margin = engine.margin().unwrap();
+        let positions = |client| margin.account(client).unwrap().positions.clone();
+        assert_eq!((positions(&alice)["SOFI"].quantity, positions(&alice)["SOFI"].cash), (dec!(2), dec!(-20)));
+        assert_eq!((positions(&bob)["SOFI"].quantity, positions(&bob)["SOFI"].cash), (dec!(-2), dec!(20)));
+        assert!(positions(&alice)["SOFI-C10"].quantity.is_zero());
+        // The premium of 2 plus 2 in the money at 12.
+        assert_eq!(margin.equity(&alice), Some(dec!(102)));
+
+        let late = engine.process_order(order("SOFI-C10", Side::Buy, dec!(1), dec!(1)), &mut logger);
+        assert!(matches!(late, Err(MatchingEngineError::InstrumentExpired(_))));
+        assert!(engine.flush_expiries(&mut logger).is_empty());
+    }
+
+    #[test]
+    fn test_index_price_drives_funding_bands_and_settlement() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let follows = |config: InstrumentConfig| InstrumentConfig { index: Some("TECH".to_string()), ..config };
+        let call = OptionContract { kind: OptionKind::Call, underlying: "SOFI".to_string(), strike: dec!(15), expiry: None };
+        let mut engine = MatchingEngine::builder()
+            .index("TECH=SOFI,NVO".parse::<IndexDefinition>().unwrap())
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("NVO", InstrumentConfig::default())
+            .instrument("PERP", follows(InstrumentConfig {
+                perpetual: Some(Perpetual::new(dec!(100), std::time::Duration::from_secs(1))),
+                price_band: Some(PriceBand::new(dec!(0.1))),
+                ..Default::default()
+            }))
+            .instrument("SOFI-C15", follows(InstrumentConfig { option: Some(call), ..Default::default() }))
+            .subscriber(Box::new(RecordingSubscriber { events: Arc::clone(&events) }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut trade = |engine: &mut MatchingEngine, instrument: &str, price| {
+            for side in [Side::Sell, Side::Buy] {
+                engine.process_order(Order::new_limit(Uuid::new_v4(), instrument.to_string(), side, price, dec!(1)), &mut logger).unwrap();
+            }
+        };
+
+        // No index until every constituent has traded.
+        trade(&mut engine, "SOFI", dec!(10));
+        assert_eq!(engine.index_price("TECH"), None);
+        trade(&mut engine, "NVO", dec!(30));
+        assert_eq!(engine.index_price("TECH"), Some(dec!(20)));
+        assert_eq!(events.lock().unwrap().iter().filter(|event| *event == "index").count(), 1);
+
+        let perp = engine.order_book("PERP").unwrap();
+        assert_eq!(perp.config().perpetual.map(|perpetual| perpetual.index_price), Some(dec!(20)));
+        assert_eq!(perp.price_band(0).map(|band| (band.lower, band.upper)), Some((dec!(18), dec!(22))));
+        let through = Order::new_limit(Uuid::new_v4(), "PERP".to_string(), Side::Buy, dec!(23), dec!(1));
+        assert!(matches!(engine.process_order(through, &mut logger), Err(MatchingEngineError::PriceOutsideBand { .. })));
+
+        let expiries = engine.flush_expiries(&mut logger);
+        assert_eq!(expiries.iter().map(|expiry| expiry.settlement).collect::<Vec<_>>(), vec![Some(dec!(20))]);
+
+        let unknown = MatchingEngine::builder().instrument("PERP", InstrumentConfig { index: Some("TECH".to_string()), ..Default::default() }).build();
+        assert!(matches!(unknown, Err(MatchingEngineError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_busted_trades_leave_the_tape_within_the_bust_window() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let clock = ManualClock::new(0);
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .bust_window(std::time::Duration::from_secs(1))
+            .audit_trail(true)
+            .subscriber(Box::new(RecordingSubscriber { events: Arc::clone(&events) }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut trade = |engine: &mut MatchingEngine, price| {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, price, dec!(1)), &mut logger).unwrap();
+            let (trades, _) = engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(1)), &mut logger).unwrap();
+            trades[0].clone()
+        };
+
+        let old = trade(&mut engine, dec!(10));
+        clock.advance(2_000_000_000);
+        let recent = trade(&mut engine, dec!(11));
+
+        assert!(matches!(engine.bust_trade("SOFI", &old.trade_id), Err(MatchingEngineError::BustWindowElapsed { .. })));
+        assert!(matches!(engine.bust_trade("NVO", &recent.trade_id), Err(MatchingEngineError::MarketNotFound(_))));
+        assert_eq!(engine.bust_trade("SOFI", &recent.trade_id).unwrap().trade_id, recent.trade_id);
+        assert!(matches!(engine.bust_trade("SOFI", &recent.trade_id), Err(MatchingEngineError::TradeNotFound(_))));
+
+        assert_eq!(engine.recent_trades("SOFI", 10).iter().map(|trade| trade.trade_id).collect::<Vec<_>>(), vec![old.trade_id]);
+        assert_eq!(engine.order_book("SOFI").unwrap().last_trade_price(), Some(dec!(10)));
+        assert_eq!(engine.take_busted_trades().iter().map(|trade| trade.trade_id).collect::<Vec<_>>(), vec![recent.trade_id]);
+        assert_eq!(events.lock().unwrap().last().map(String::as_str), Some("busted"));
+        let story = engine.audit().unwrap().story(&recent.sell_order_id);
+        assert_eq!(story.last().map(|entry| &entry.event), Some(&AuditEvent::Busted { trade_id: recent.trade_id, quantity: dec!(1) }));
+    }
+
+    #[test]
+    fn test_invalid_stops_are_rejected() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("NVO", InstrumentConfig { batch_interval: Some(std::time::Duration::from_millis(100)), ..Default::default() })
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let negative = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(1)).with_stop(dec!(-1));
+        assert!(matches!(engine.process_order(negative, &mut logger), Err(MatchingEngineError::InvalidStopPrice { .. })));
+        let batched = Order::new_market(Uuid::new_v4(), "NVO".to_string(), Side::Buy, dec!(1)).with_stop(dec!(20));
+        assert!(matches!(engine.process_order(batched, &mut logger), Err(MatchingEngineError::UnsupportedAttribute(_))));
+    }
+
+    type FillReports = Arc<Mutex<Vec<(Uuid, Option<Execution>)>>>;
+
+    struct FillSubscriber {
+        fills: FillReports,
+    }
+
+    impl EngineSubscriber for FillSubscriber {
+        fn on_event(&mut self, event: &EngineEvent) {
+            if let EngineEvent::OrderFilled { order, execution } = event {
+                self.fills.lock().unwrap().push((order.order_id, *execution));
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_order_with_lends_each_trade_in_order() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        for price in [dec!(101), dec!(100)] {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, price, dec!(5)), &mut logger).unwrap();
+        }
+
+        let mut fills = Vec::new();
+        let taker = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(7));
+        engine.process_order_with(taker, &mut logger, |trade| fills.push((trade.price, trade.quantity))).unwrap();
+        assert_eq!(fills, [(dec!(100), dec!(5)), (dec!(101), dec!(2))]);
+
+        let mut called = false;
+        let resting = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99), dec!(1));
+        engine.process_order_with(resting, &mut logger, |_| called = true).unwrap();
+        assert!(!called);
+    }
+
+    #[test]
+    fn test_fill_reports_carry_the_completing_trade_and_aggressor() {
+        let fills = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .subscriber(Box::new(FillSubscriber { fills: Arc::clone(&fills) }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let makers: Vec<Order> = (0..2).map(|_| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100), dec!(5))).collect();
+        for maker in &makers {
+            engine.process_order(maker.clone(), &mut logger).unwrap();
+        }
+
+        let taker = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(7));
+        let taker_id = taker.order_id;
+        let (trades, _) = engine.process_order(taker, &mut logger).unwrap();
+
+        let fill = |trade: &Trade| Some(Execution { trade_id: trade.trade_id, aggressor_order_id: taker_id });
+        // The second maker is only partly filled, so it has no fill report yet.
+        assert_eq!(*fills.lock().unwrap(), vec![(makers[0].order_id, fill(&trades[0])), (taker_id, fill(&trades[1]))]);
+    }
+
+    #[test]
+    fn test_good_till_date_orders_expire_on_the_engine_clock() {
+        let clock = ManualClock::new(1_000);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .subscriber(Box::new(RecordingSubscriber { events: Arc::clone(&events) }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let bid = |price| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, price, dec!(5));
+
+        let past = bid(dec!(99)).with_expiry(1_000);
+        assert!(matches!(engine.process_order(past, &mut logger), Err(MatchingEngineError::InvalidExpiry(_))));
+        let ioc = bid(dec!(99)).with_attributes(OrderAttributes::default().with_time_in_force(TimeInForce::ImmediateOrCancel)).with_expiry(5_000);
+        assert!(matches!(engine.process_order(ioc, &mut logger), Err(MatchingEngineError::InvalidExpiry(_))));
+
+        let early = bid(dec!(100)).with_expiry(2_000);
+        let late = bid(dec!(99)).with_expiry(3_000);
+        let (early_id, late_id) = (early.order_id, late.order_id);
+        for order in [early, late, bid(dec!(98))] {
+            engine.process_order(order, &mut logger).unwrap();
+        }
+        assert!(engine.expire_orders(&mut logger).is_empty());
+
+        clock.set(2_000);
+        let expired: Vec<Uuid> = engine.expire_orders(&mut logger).iter().map(|order| order.order_id).collect();
+        assert_eq!(expired, vec![early_id]);
+        assert_eq!(engine.order_book("SOFI").unwrap().best_bid(), Some(dec!(99)));
+        assert!(events.lock().unwrap().contains(&"expired".to_string()));
+
+        engine.cancel_order_by_id(&late_id, "SOFI").unwrap();
+        clock.set(10_000);
+        assert!(engine.expire_orders(&mut logger).is_empty());
+        assert_eq!(engine.resting_orders("SOFI").len(), 1);
+        assert_eq!(engine.order_book("SOFI").unwrap().tally().expired, dec!(5));
+    }
+
+    #[test]
+    fn test_cancel_after_counts_from_when_the_order_rests() {
+        let clock = ManualClock::new(1_000);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .subscriber(Box::new(RecordingSubscriber { events: Arc::clone(&events) }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let order = |side, price| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, dec!(5));
+
+        assert!(matches!(engine.process_order(order(Side::Buy, dec!(99)).with_cancel_after(0), &mut logger), Err(MatchingEngineError::InvalidCancelAfter(_))));
+        let ioc = order(Side::Buy, dec!(99)).with_attributes(OrderAttributes::default().with_time_in_force(TimeInForce::ImmediateOrCancel));
+        assert!(matches!(engine.process_order(ioc.with_cancel_after(500), &mut logger), Err(MatchingEngineError::InvalidCancelAfter(_))));
+
+        let quote = order(Side::Sell, dec!(102)).with_cancel_after(500);
+        let quote_id = quote.order_id;
+        for order in [order(Side::Sell, dec!(101)), quote] {
+            engine.process_order(order, &mut logger).unwrap();
+        }
+        clock.set(1_200);
+        let bid = Order { quantity: dec!(8), remaining_quantity: dec!(8), ..order(Side::Buy, dec!(101)).with_cancel_after(500) };
+        let bid_id = bid.order_id;
+        engine.process_order(bid, &mut logger).unwrap();
+
+        clock.set(1_499);
+        assert!(engine.cancel_timed_out_orders(&mut logger).is_empty());
+        clock.set(1_500);
+        let timed_out = |engine: &mut MatchingEngine, logger: &mut Box<dyn SimLogger>| -> Vec<Uuid> {
+            engine.cancel_timed_out_orders(logger).iter().map(|order| order.order_id).collect()
+        };
+        assert_eq!(timed_out(&mut engine, &mut logger), vec![quote_id]);
+        clock.set(1_699);
+        assert!(timed_out(&mut engine, &mut logger).is_empty());
+        clock.set(1_700);
+        assert_eq!(timed_out(&mut engine, &mut logger), vec![bid_id]);
+
+        assert!(engine.resting_orders("SOFI").is_empty());
+        assert!(events.lock().unwrap().contains(&"canceled".to_string()));
+        let tally = engine.order_book("SOFI").unwrap().tally();
+        assert_eq!((tally.canceled, tally.expired), (dec!(8), dec!(0)));
+
+        let restored = order(Side::Buy, dec!(99)).with_cancel_after(500);
+        let restored_id = restored.order_id;
+        engine.restore_order(restored).unwrap();
+        clock.set(2_199);
+        assert!(timed_out(&mut engine, &mut logger).is_empty());
+        clock.set(2_200);
+        assert_eq!(timed_out(&mut engine, &mut logger), vec![restored_id]);
+    }
+
+    #[test]
+    fn test_pegged_orders_are_priced_on_entry_and_repriced() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .subscriber(Box::new(RecordingSubscriber { events: events.clone() }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let midpoint = Order::new_pegged(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, Peg::new(PegReference::Midpoint), dec!(2));
+        assert!(matches!(engine.process_order(midpoint.clone(), &mut logger), Err(MatchingEngineError::NoPegReference(_))));
+
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(98), dec!(5)), &mut logger).unwrap();
+        let ask = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(102), dec!(5));
+        let ask_id = ask.order_id;
+        engine.process_order(ask, &mut logger).unwrap();
+        engine.process_order(midpoint.clone(), &mut logger).unwrap();
+        assert_eq!(engine.get_order_book_display("SOFI").unwrap().bids[0].price, dec!(100));
+
+        events.lock().unwrap().clear();
+        engine.cancel_order_by_id(&ask_id, "SOFI").unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101), dec!(5)), &mut logger).unwrap();
+        assert_eq!(engine.get_order_book_display("SOFI").unwrap().bids[0].price, dec!(99.5));
+        assert!(events.lock().unwrap().contains(&"repriced".to_string()));
+        assert!(engine.repriced.is_empty());
+    }
+
+    #[test]
+    fn test_discretion_must_be_beyond_the_limit_price() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { tick_size: Some(dec!(0.05)), ..Default::default() })
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let bid = |discretion| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99), dec!(5)).with_discretion(discretion);
+
+        assert!(matches!(engine.process_order(bid(dec!(98.5)), &mut logger), Err(MatchingEngineError::InvalidDiscretion { .. })));
+        assert!(matches!(engine.process_order(bid(dec!(99.52)), &mut logger), Err(MatchingEngineError::InvalidTickSize { .. })));
+        let market = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(5)).with_discretion(dec!(100));
+        assert!(matches!(engine.process_order(market, &mut logger), Err(MatchingEngineError::InvalidDiscretion { .. })));
+        assert!(engine.process_order(bid(dec!(99.5)), &mut logger).is_ok());
+    }
+
+    #[test]
+    fn test_post_only_and_unsupported_attributes_are_rejected() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101), dec!(5)), &mut logger).unwrap();
+        let bid = |price, attributes| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, price, dec!(1)).with_attributes(attributes);
+
+        let post_only = OrderAttributes::default().post_only();
+        assert!(matches!(engine.process_order(bid(dec!(101), post_only), &mut logger), Err(MatchingEngineError::PostOnlyWouldTrade(_))));
+        assert!(engine.process_order(bid(dec!(100), post_only), &mut logger).is_ok());
+        assert_eq!(engine.get_order_book_display("SOFI").unwrap().bids[0].price, dec!(100));
+
+        let hidden = OrderAttributes::default().hidden();
+        assert_eq!(
+            engine.process_order(bid(dec!(100), hidden), &mut logger).unwrap_err().to_string(),
+            "Order attribute not supported: HIDDEN"
+        );
+    }
+
+    #[test]
+    fn test_configured_rules_reject_orders() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { tick_size: Some(dec!(0.05)), lot_size: Some(dec!(5)), ..Default::default() })
+            .risk_limits(RiskLimits { max_order_quantity: Some(dec!(100)), ..Default::default() })
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let off_tick = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100.01), dec!(5));
+        assert!(matches!(engine.process_order(off_tick, &mut logger), Err(MatchingEngineError::InvalidTickSize { .. })));
+
+        let odd_lot = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100.05), dec!(7));
+        assert!(matches!(engine.process_order(odd_lot, &mut logger), Err(MatchingEngineError::InvalidLotSize { .. })));
+
+        let too_large = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(105));
+        assert!(matches!(engine.process_order(too_large, &mut logger), Err(MatchingEngineError::RiskLimitExceeded(_))));
+
+        assert!(engine.get_order_book_display("SOFI").unwrap().bids.is_empty());
+    }
+
+    #[test]
+    fn test_orders_are_normalized_to_instrument_precision() {
+        let precision = "2:0:half-up".parse().unwrap();
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig { precision: Some(precision), ..Default::default() }).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100.5), dec!(5)), &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100.495), dec!(2.5)), &mut logger).unwrap();
+        let display = engine.get_order_book_display("SOFI").unwrap();
+        assert_eq!(display.bids.len(), 1);
+        assert_eq!((display.bids[0].price.to_string(), display.bids[0].volume.to_string()), ("100.50".to_string(), "8".to_string()));
+    }
+
+    #[test]
+    fn test_values_beyond_fixed_point_are_rejected() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let too_precise = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100.000000001), dec!(5));
+        assert!(matches!(engine.process_order(too_precise, &mut logger), Err(MatchingEngineError::ExcessPrecision(_))));
+        let too_large = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100_000_000_000));
+        assert!(matches!(engine.process_order(too_large, &mut logger), Err(MatchingEngineError::ExcessPrecision(_))));
+
+        // A midpoint between one-unit prices is rounded onto the grid.
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(0.00000001), dec!(1)), &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(0.00000004), dec!(1)), &mut logger).unwrap();
+        let midpoint = Order::new_pegged(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, Peg::new(PegReference::Midpoint), dec!(1));
+        engine.process_order(midpoint, &mut logger).unwrap();
+        assert_eq!(engine.get_order_book_display("SOFI").unwrap().asks[0].price, dec!(0.00000003));
+    }
+
+    #[test]
+    fn test_price_collar_rejects_outlier_limit_orders() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { price_collar: Some(PriceCollar::new(dec!(0.05))), ..Default::default() })
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let sell = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100), dec!(10));
+        let buy = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100), dec!(5));
+        engine.process_order(sell, &mut logger).unwrap();
+        engine.process_order(buy, &mut logger).unwrap();
+
+        let outlier = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(1000), dec!(10));
+        assert!(matches!(engine.process_order(outlier, &mut logger), Err(MatchingEngineError::PriceOutsideCollar { .. })));
+
+        let inside = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(104), dec!(10));
+        assert!(engine.process_order(inside, &mut logger).is_ok());
+    }
+
+    #[test]
+    fn test_price_bands_stop_trades_at_the_edge_and_widen_after_a_halt() {
+        let band = PriceBand { window: std::time::Duration::from_secs(1), ..PriceBand::new(dec!(0.1)) };
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { price_band: Some(band), ..Default::default() })
+            .instrument("NVO", InstrumentConfig { price_band: Some(PriceBand { action: BandAction::Slice, ..band }), ..Default::default() })
+            .clock(ManualClock::new(0))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut submit = |engine: &mut MatchingEngine, instrument: &str, side, price: Option<Decimal>, quantity| {
+            let order = match price {
+                Some(price) => Order::new_limit(Uuid::new_v4(), instrument.to_string(), side, price, quantity),
+                None => Order::new_market(Uuid::new_v4(), instrument.to_string(), side, quantity),
+            };
+            engine.process_order(order, &mut logger).map(|(trades, _)| trades.iter().map(|trade| trade.price).collect::<Vec<_>>())
+        };
+        for instrument in ["SOFI", "NVO"] {
+            submit(&mut engine, instrument, Side::Sell, Some(dec!(100)), dec!(1)).unwrap();
+            submit(&mut engine, instrument, Side::Buy, Some(dec!(100)), dec!(1)).unwrap();
+            for price in [dec!(105), dec!(108), dec!(112)] {
+                submit(&mut engine, instrument, Side::Sell, Some(price), dec!(1)).unwrap();
+            }
+        }
+
+        assert!(matches!(submit(&mut engine, "SOFI", Side::Buy, Some(dec!(111)), dec!(1)), Err(MatchingEngineError::PriceOutsideBand { .. })));
+        // The market order stops at the 110 edge and the rest expires.
+        assert_eq!(submit(&mut engine, "SOFI", Side::Buy, None, dec!(5)).unwrap(), vec![dec!(105), dec!(108)]);
+        // The reference is now the average of 100, 105 and 108.
+        assert_eq!(engine.order_book("SOFI").unwrap().price_band(0).unwrap().upper, dec!(114.76666666));
+        assert!(submit(&mut engine, "SOFI", Side::Buy, Some(dec!(115)), dec!(1)).is_err());
+        engine.halt_market("SOFI").unwrap();
+        engine.resume_market("SOFI").unwrap();
+        assert_eq!(submit(&mut engine, "SOFI", Side::Buy, Some(dec!(115)), dec!(1)).unwrap(), vec![dec!(112)]);
+
+        // A slicing band reprices the order to its edge, where it rests.
+        assert_eq!(submit(&mut engine, "NVO", Side::Buy, Some(dec!(115)), dec!(3)).unwrap(), vec![dec!(105), dec!(108)]);
+        assert_eq!(engine.order_book("NVO").unwrap().best_bid(), Some(dec!(110)));
+    }
+
+    #[test]
+    fn test_injected_clock_ids_and_subscribers() {
+        let clock = ManualClock::new(1_000);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .id_generator(SequentialIds::new(42))
+            .subscriber(Box::new(RecordingSubscriber { events: Arc::clone(&events) }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let resting = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100.0), dec!(5));
+        let resting_id = resting.order_id;
+        engine.process_order(resting, &mut logger).unwrap();
+        clock.advance(500);
+        let (trades, _) = engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(100.0), dec!(2)), &mut logger).unwrap();
+        engine.cancel_order_by_id(&resting_id, "SOFI").unwrap();
+        let _ = engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(1)), &mut logger);
+        let _ = engine.process_order(Order::new_market(Uuid::new_v4(), "NONE".to_string(), Side::Buy, dec!(1)), &mut logger);
+
+        assert_eq!(trades[0].timestamp, 1_500);
+        assert_eq!(trades[0].trade_id, SequentialIds::new(42).next_trade_id());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["accepted", "rested", "accepted", "trade", "filled", "canceled", "accepted", "filled", "rejected"]
+        );
+    }
+
+    #[test]
+    fn test_imbalance_is_published_when_it_moves() {
+        struct ImbalanceSubscriber(Arc<Mutex<Vec<Option<Decimal>>>>);
+        impl EngineSubscriber for ImbalanceSubscriber {
+            fn on_event(&mut self, event: &EngineEvent) {
+                if let EngineEvent::Imbalance { imbalance, .. } = event {
+                    self.0.lock().unwrap().push(*imbalance);
+                }
+            }
+        }
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .imbalance_levels(2)
+            .subscriber(Box::new(ImbalanceSubscriber(Arc::clone(&published))))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut submit = |engine: &mut MatchingEngine, side, price, quantity| {
+            let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+            let order_id = order.order_id;
+            engine.process_order(order, &mut logger).unwrap();
+            order_id
+        };
+
+        submit(&mut engine, Side::Buy, dec!(99), dec!(3));
+        let ask = submit(&mut engine, Side::Sell, dec!(101), dec!(1));
+        // A third bid level is out of reach and changes nothing.
+        submit(&mut engine, Side::Buy, dec!(98), dec!(4));
+        submit(&mut engine, Side::Buy, dec!(97), dec!(4));
+        engine.cancel_order_by_id(&ask, "SOFI").unwrap();
+        // Selling through the best bid leaves 8 resting a side.
+        submit(&mut engine, Side::Sell, dec!(99), dec!(11));
+
+        assert_eq!(*published.lock().unwrap(), [Some(Decimal::ONE), Some(dec!(0.5)), Some(dec!(0.75)), Some(Decimal::ONE), Some(Decimal::ZERO)]);
+        assert_eq!(engine.imbalance("SOFI"), Some(Decimal::ZERO));
+        assert!(MatchingEngine::builder().imbalance_levels(0).build().is_err());
+    }
+
+    #[test]
+    fn test_trades_are_sequenced_per_instrument() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("NVO", InstrumentConfig::default())
+            .trade_ids(TradeIdStrategy::Instrument)
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut sequences = Vec::new();
+        for instrument in ["SOFI", "NVO", "SOFI"] {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), instrument.to_string(), Side::Sell, dec!(100), dec!(1)), &mut logger).unwrap();
+            engine.process_order(Order::new_limit(Uuid::new_v4(), instrument.to_string(), Side::Sell, dec!(101), dec!(1)), &mut logger).unwrap();
+            let (trades, _) = engine.process_order(Order::new_market(Uuid::new_v4(), instrument.to_string(), Side::Buy, dec!(2)), &mut logger).unwrap();
+            sequences.extend(trades.iter().map(|trade| (trade.instrument.clone(), trade.sequence, InstrumentSequenceIds::decode(trade.trade_id).unwrap().1)));
+        }
+        let expected: Vec<_> = [("SOFI", 1), ("SOFI", 2), ("NVO", 1), ("NVO", 2), ("SOFI", 3), ("SOFI", 4)]
+            .into_iter()
+            .map(|(instrument, sequence)| (instrument.to_string(), sequence, sequence))
+            .collect();
+        assert_eq!(sequences, expected);
+    }
+
+    #[test]
+    fn test_auto_create_markets_on_first_order() {
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let order = Order::new_limit(Uuid::new_v4(), "NEW-STOCK".to_string(), Side::Buy, dec!(10), dec!(5));
+        assert!(engine.process_order(order, &mut logger).is_ok());
+        assert_eq!(engine.instruments(), vec!["NEW-STOCK".to_string()]);
+        assert_eq!(engine.get_order_book_display("NEW-STOCK").unwrap().bids.len(), 1);
+    }
+
+    #[test]
+    fn test_shadow_books_follow_matching_cancels_and_restores() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .instrument("NVO", InstrumentConfig::default())
+            .shadow_books(1)
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let order = |instrument: &str, side, price, quantity| Order::new_limit(Uuid::new_v4(), instrument.to_string(), side, price, quantity);
+
+        let restored = order("SOFI", Side::Sell, dec!(101), dec!(4));
+        let restored_id = restored.order_id;
+        engine.restore_order(restored).unwrap();
+        engine.process_order(order("SOFI", Side::Sell, dec!(100), dec!(5)), &mut logger).unwrap();
+        engine.process_order(order("SOFI", Side::Buy, dec!(101), dec!(7)), &mut logger).unwrap();
+        engine.process_order(order("SOFI", Side::Sell, dec!(100), dec!(1)), &mut logger).unwrap();
+        engine.cancel_order_by_id(&restored_id, "SOFI").unwrap();
+        engine.process_order(order("NVO", Side::Sell, dec!(50), dec!(1)), &mut logger).unwrap();
+        engine.process_order(order("NVO", Side::Buy, dec!(48), dec!(1)), &mut logger).unwrap();
+        let pegged = Order::new_pegged(Uuid::new_v4(), "NVO".to_string(), Side::Buy, Peg::new(PegReference::Midpoint), dec!(1));
+        engine.process_order(pegged, &mut logger).unwrap();
+        engine.verify_shadow_books();
+
+        let shadow = engine.shadow_books().unwrap();
+        assert_eq!(shadow.divergence(), None);
+        assert_eq!(shadow.compared(), 6);
+        assert_eq!(shadow.unshadowed().collect::<Vec<_>>(), [("NVO", "pegged orders")]);
+        assert!(MatchingEngine::builder().shadow_books(0).build().is_err());
+    }
+
+    #[test]
+    fn test_capacity_limits_reject_orders_and_markets_beyond_them() {
+        let limits = CapacityLimits {
+            max_orders_per_instrument: Some(4),
+            max_orders_per_client: Some(2),
+            max_price_levels: Some(2),
+            max_instruments: Some(2),
+        };
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .capacity_limits(limits)
+            .auto_create_markets(true)
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let alice = ClientId::new("alice");
+        let order = |instrument: &str, side, price| Order::new_limit(Uuid::new_v4(), instrument.to_string(), side, price, dec!(1));
+        let full = |result: Result<Submission, MatchingEngineError>| matches!(result, Err(MatchingEngineError::CapacityExceeded(_)));
+
+        // Two bid levels are the most the side holds; joining one is fine.
+        engine.process_order(order("SOFI", Side::Buy, dec!(99)), &mut logger).unwrap();
+        engine.process_order(order("SOFI", Side::Buy, dec!(98)), &mut logger).unwrap();
+        assert!(full(engine.submit_order(order("SOFI", Side::Buy, dec!(97)), None, &mut logger)));
+        engine.process_order(order("SOFI", Side::Buy, dec!(98)), &mut logger).unwrap();
+
+        // The client's third resting order is one too many, wherever it is.
+        engine.submit_order(order("SOFI", Side::Sell, dec!(101)), Some(alice.clone()), &mut logger).unwrap();
+        assert!(engine.submit_order(order("NVO", Side::Sell, dec!(50)), Some(alice.clone()), &mut logger).is_ok());
+        assert!(full(engine.submit_order(order("SOFI", Side::Sell, dec!(101)), Some(alice.clone()), &mut logger)));
+
+        // SOFI now holds four orders, but an order that cannot rest still trades.
+        assert!(full(engine.submit_order(order("SOFI", Side::Sell, dec!(101)), None, &mut logger)));
+        let (trades, _) = engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(1)), &mut logger).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert!(engine.submit_order(order("SOFI", Side::Sell, dec!(102)), None, &mut logger).is_ok());
+
+        // A third market is neither created by an order nor registered.
+        assert!(full(engine.submit_order(order("AAPL", Side::Buy, dec!(10)), None, &mut logger)));
+        assert!(matches!(engine.create_market("AAPL".to_string(), InstrumentConfig::default()), Err(MatchingEngineError::CapacityExceeded(_))));
+        assert_eq!(engine.instruments().len(), 2);
+        assert!(MatchingEngine::builder().capacity_limits(CapacityLimits { max_instruments: Some(0), ..Default::default() }).build().is_err());
+    }
+
+    #[test]
+    fn test_level_limit_counts_the_remainder_of_marketable_orders() {
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .capacity_limits(CapacityLimits { max_price_levels: Some(1), ..Default::default() })
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let order = |side, price, quantity| Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+        engine.process_order(order(Side::Buy, dec!(99), dec!(1)), &mut logger).unwrap();
+        engine.process_order(order(Side::Sell, dec!(101), dec!(1)), &mut logger).unwrap();
+
+        // Filling completely, the buy never rests at a second bid level.
+        let (trades, _) = engine.process_order(order(Side::Buy, dec!(101), dec!(1)), &mut logger).unwrap();
+        assert_eq!(trades.len(), 1);
+        engine.process_order(order(Side::Sell, dec!(101), dec!(1)), &mut logger).unwrap();
+        let partial = engine.process_order(order(Side::Buy, dec!(101), dec!(2)), &mut logger);
+        assert!(matches!(partial, Err(MatchingEngineError::CapacityExceeded(_))));
+
+        let book = engine.order_book("SOFI").unwrap();
+        assert_eq!((book.level_count(Side::Buy), book.level_count(Side::Sell)), (1, 1));
+        assert_eq!(book.audit(), Ok(()));
+    }
+
+    #[test]
+    fn test_rejected_orders_leave_the_engine_as_it_was() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let margin = MarginBook::new(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }).default_collateral(dec!(100));
+        let mut engine = MatchingEngine::builder()
+            .auto_create_markets(true)
+            .margin(margin)
+            .subscriber(Box::new(RecordingSubscriber { events: events.clone() }))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let bob = ClientId::new("bob");
+        let buy = |quantity| Order::new_limit(Uuid::new_v4(), "NEW-STOCK".to_string(), Side::Buy, dec!(10), quantity);
+
+        // Checked against the market and account it would have opened.
+        let too_big = engine.submit_order(buy(dec!(101)), Some(bob.clone()), &mut logger);
+        assert!(matches!(too_big, Err(MatchingEngineError::InsufficientMargin { .. })));
+        assert!(engine.instruments().is_empty());
+        assert_eq!(engine.margin().unwrap().account(&bob), None);
+        assert_eq!(*events.lock().unwrap(), ["rejected"]);
+        // It was still a message.
+        assert_eq!(engine.message_accounting().clients()[&bob].new_orders, 1);
+
+        engine.submit_order(buy(dec!(100)), Some(bob.clone()), &mut logger).unwrap();
+        assert_eq!(engine.instruments(), ["NEW-STOCK"]);
+        assert_eq!(engine.margin().unwrap().account(&bob).unwrap().collateral, dec!(100));
+        assert_eq!(*events.lock().unwrap(), ["rejected", "accepted", "rested"]);
+    }
 
     #[test]
     fn test_process_order_for_non_existent_market() {
@@ -96,7 +3019,7 @@ mod tests {
     #[test]
     fn test_process_order_invalid_price_rules() {
         let mut engine = MatchingEngine::new();
-        engine.add_market("SOFI".to_string());
+        engine.create_market("SOFI".to_string(), InstrumentConfig::default()).unwrap();
         let mut logger = create_logger(LoggingMode::Baseline);
 
         let mut limit_no_price = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10));
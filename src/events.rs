@@ -0,0 +1,41 @@
+use crate::clients::ClientId;
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use crate::utils::MatchingEngineError;
+use rust_decimal::Decimal;
+
+/// Everything the engine reports to its subscribers, borrowed from the engine
+/// so that notifying a subscriber never clones an order or trade.
+#[derive(Debug)]
+pub enum EngineEvent<'a> {
+    /// `client` is the order's owner, if it has one. Later events about the
+    /// order carry only its id.
+    OrderAccepted { order: &'a Order, client: Option<&'a ClientId> },
+    /// The unfilled part of an incoming limit order was added to the book.
+    OrderRested(&'a Order),
+    OrderRejected { order: &'a Order, reason: &'a MatchingEngineError, client: Option<&'a ClientId> },
+    Trade(&'a Trade),
+    /// The order is done, filled or expired. `execution` is the trade that
+    /// completed it, if it traded.
+    OrderFilled { order: &'a Order, execution: Option<Execution> },
+    OrderCanceled(&'a Order),
+    /// A resting good-till-date order reached its expiry time and left the
+    /// book.
+    OrderExpired(&'a Order),
+    /// A resting pegged order moved from `old_price` to its current price,
+    /// losing its time priority.
+    OrderRepriced { order: &'a Order, old_price: Decimal },
+    /// An earlier trade was busted: it is off the tape and its fills are
+    /// reversed. The orders it filled are not reinstated.
+    TradeBusted(&'a Trade),
+    /// The index `name` moved to `price`.
+    IndexPrice { name: &'a str, price: Decimal },
+    /// The imbalance of `instrument`'s top levels moved, to `None` if they
+    /// emptied on both sides. Only with `imbalance_levels` set.
+    Imbalance { instrument: &'a str, imbalance: Option<Decimal> },
+}
+
+/// Receives engine events synchronously on the matching thread.
+pub trait EngineSubscriber: Send {
+    fn on_event(&mut self, event: &EngineEvent);
+}
@@ -0,0 +1,288 @@
+use crate::config::{InstrumentConfig, MatchingAlgorithm};
+use crate::engine::MatchingEngine;
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::generator::{Generator, GeneratorConfig};
+use crate::ids::SequentialIds;
+use crate::logging::{create_logger, LoggingMode};
+use crate::operation::SimOp;
+use crate::simulation::run_simulation;
+use crate::utils::percentile;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub const EXPERIMENT_USAGE: &str = "Usage: cargo run --release experiment [--modes <mode,...>] [--matching <fifo|lottery:<seed>,...>] [--operations <n>] [--seed <n>] [--runs <n>] [--output <path>]";
+
+const INSTRUMENT: &str = "PUMPTHIS";
+const BOOK_BUILD_SHARE: f64 = 0.03;
+
+/// The matrix of configurations an experiment runs the workload through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentConfig {
+    pub modes: Vec<LoggingMode>,
+    pub matching: Vec<MatchingAlgorithm>,
+    pub operations: usize,
+    pub seed: u64,
+    pub runs: usize,
+    /// CSV file the results are appended to, so cells from builds with
+    /// different book features end up in one table.
+    pub output: String,
+}
+
+impl Default for ExperimentConfig {
+    fn default() -> Self {
+        Self {
+            modes: vec![LoggingMode::Baseline, LoggingMode::AsyncEnum, LoggingMode::AsyncBatch, LoggingMode::BufferedFileWrite],
+            matching: vec![MatchingAlgorithm::Fifo, MatchingAlgorithm::Lottery { seed: 42 }],
+            operations: 100_000,
+            seed: 42,
+            runs: 3,
+            output: "output_logs/experiment.csv".to_string(),
+        }
+    }
+}
+
+impl ExperimentConfig {
+    /// Parses the arguments that follow the `experiment` subcommand.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = iter.next().ok_or_else(|| format!("{} requires a value\n{}", arg, EXPERIMENT_USAGE))?;
+            let count = || value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid {} '{}'", arg, value));
+            match arg.as_str() {
+                "--modes" => {
+                    config.modes = value
+                        .split(',')
+                        .map(|mode| LoggingMode::from_str(mode).map_err(|_| format!("Invalid logging mode '{}'", mode)))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--matching" => config.matching = value.split(',').map(MatchingAlgorithm::from_str).collect::<Result<_, _>>()?,
+                "--operations" => config.operations = count()?,
+                "--runs" => config.runs = count()?,
+                "--seed" => config.seed = value.parse().map_err(|_| format!("Invalid seed '{}'", value))?,
+                "--output" => config.output = value.clone(),
+                other => return Err(format!("Unknown flag '{}'\n{}", other, EXPERIMENT_USAGE)),
+            }
+        }
+        let tracing_modes = config
+            .modes
+            .iter()
+            .filter(|mode| matches!(mode, LoggingMode::TracingConsole | LoggingMode::TracingFile))
+            .count();
+        if tracing_modes > 1 {
+            return Err("Only one tracing mode can be used per experiment".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// The book storage compiled into this binary. Features are chosen at build
+/// time, so each build contributes its own rows to the output file.
+pub fn build_label() -> &'static str {
+    match (cfg!(feature = "fxhash"), cfg!(feature = "fast-path")) {
+        (false, false) => "default",
+        (true, false) => "fxhash",
+        (false, true) => "fast-path",
+        (true, true) => "fxhash+fast-path",
+    }
+}
+
+/// The same mix of operations as the data generator, drawn from a generator
+/// seeded with `seed` so every cell of an experiment sees identical input.
+pub fn seeded_workload(operations: usize, seed: u64) -> Vec<SimOp> {
+    let book_build = (operations as f64 * BOOK_BUILD_SHARE) as usize;
+    let config = GeneratorConfig { instrument: INSTRUMENT.to_string(), operations, book_build, seed, ..Default::default() };
+    Generator::new(config).expect("the default order mix is valid").collect()
+}
+
+/// Hashes every trade in execution order, so cells that must match the same
+/// orders the same way can be checked for it.
+#[derive(Clone, Default)]
+struct TradeFingerprint {
+    state: Arc<Mutex<DefaultHasher>>,
+}
+
+impl TradeFingerprint {
+    fn value(&self) -> u64 {
+        self.state.lock().unwrap().finish()
+    }
+}
+
+impl EngineSubscriber for TradeFingerprint {
+    fn on_event(&mut self, event: &EngineEvent) {
+        if let EngineEvent::Trade(trade) = event {
+            let mut hasher = self.state.lock().unwrap();
+            (trade.trade_id, trade.price, trade.quantity, trade.buy_order_id, trade.sell_order_id).hash(&mut *hasher);
+        }
+    }
+}
+
+/// Metrics of one cell of the matrix over all of its runs.
+#[derive(Debug, Clone)]
+pub struct CellResult {
+    pub build: &'static str,
+    pub mode: LoggingMode,
+    pub matching: MatchingAlgorithm,
+    pub operations: usize,
+    pub trades: usize,
+    pub mean_process_ns: f64,
+    pub p50_process_ns: u128,
+    pub p99_process_ns: u128,
+    pub p999_process_ns: u128,
+    pub mean_logging_ns: f64,
+    /// Operations per second of wall time, including the logger's finalize.
+    pub throughput: f64,
+    /// Hash of the trades, identical across runs of a deterministic cell.
+    pub fingerprint: u64,
+}
+
+/// Runs `workload` `runs` times through a fresh engine configured for one
+/// cell. Fails if two runs trade differently.
+pub fn run_cell(mode: LoggingMode, matching: MatchingAlgorithm, workload: &[SimOp], runs: usize) -> Result<CellResult, Box<dyn Error>> {
+    let mut process_latencies = Vec::with_capacity(workload.len() * runs);
+    let mut logging_total = 0u128;
+    let mut wall_ns = 0u128;
+    let mut trades = 0;
+    let mut fingerprint = None;
+
+    for _ in 0..runs {
+        let trade_hash = TradeFingerprint::default();
+        let mut engine = MatchingEngine::builder()
+            .instrument(INSTRUMENT, InstrumentConfig { matching, ..Default::default() })
+            .id_generator(SequentialIds::new(0))
+            .subscriber(Box::new(trade_hash.clone()))
+            .build()?;
+        let mut logger = create_logger(mode);
+        let mut latencies = Vec::with_capacity(workload.len());
+
+        let start = Instant::now();
+        let report = run_simulation(&mut logger, &mut engine, workload, &mut latencies)?;
+        logger.finalize();
+        wall_ns += start.elapsed().as_nanos();
+
+        for (process, logging) in latencies {
+            process_latencies.push(process);
+            logging_total += logging;
+        }
+        trades = report.trades();
+        match fingerprint {
+            None => fingerprint = Some(trade_hash.value()),
+            Some(previous) if previous != trade_hash.value() => {
+                return Err(format!("{:?} with {} traded differently between runs", mode, matching).into());
+            }
+            Some(_) => {}
+        }
+    }
+
+    process_latencies.sort_unstable();
+    let samples = process_latencies.len().max(1) as f64;
+    Ok(CellResult {
+        build: build_label(),
+        mode,
+        matching,
+        operations: workload.len(),
+        trades,
+        mean_process_ns: process_latencies.iter().sum::<u128>() as f64 / samples,
+        p50_process_ns: percentile(&process_latencies, 0.5),
+        p99_process_ns: percentile(&process_latencies, 0.99),
+        p999_process_ns: percentile(&process_latencies, 0.999),
+        mean_logging_ns: logging_total as f64 / samples,
+        throughput: (workload.len() * runs) as f64 / (wall_ns as f64 / 1e9),
+        fingerprint: fingerprint.unwrap_or_default(),
+    })
+}
+
+/// Runs every combination of logging mode and matching algorithm on one
+/// seeded workload.
+pub fn run_experiment(config: &ExperimentConfig) -> Result<Vec<CellResult>, Box<dyn Error>> {
+    let workload = seeded_workload(config.operations, config.seed);
+    if workload.is_empty() {
+        return Err("The seeded workload has no operations".into());
+    }
+    let mut results = Vec::new();
+    for &matching in &config.matching {
+        for &mode in &config.modes {
+            results.push(run_cell(mode, matching, &workload, config.runs)?);
+        }
+    }
+    Ok(results)
+}
+
+pub fn print_results(config: &ExperimentConfig, results: &[CellResult]) {
+    println!(
+        "\n--- Experiment ({} operations, seed {}, {} runs per cell, {} build, nanoseconds) ---",
+        config.operations, config.seed, config.runs, build_label()
+    );
+    println!(
+        "{:<18} {:<18} {:>8} {:>10} {:>8} {:>8} {:>8} {:>10} {:>12} {:>18}",
+        "Mode", "Matching", "Trades", "Process", "p50", "p99", "p99.9", "Logging", "Ops/s", "Fingerprint"
+    );
+    for r in results {
+        println!(
+            "{:<18} {:<18} {:>8} {:>10.2} {:>8} {:>8} {:>8} {:>10.2} {:>12.0} {:>18x}",
+            format!("{:?}", r.mode), r.matching.to_string(), r.trades, r.mean_process_ns, r.p50_process_ns,
+            r.p99_process_ns, r.p999_process_ns, r.mean_logging_ns, r.throughput, r.fingerprint
+        );
+    }
+    println!("Process is the time to apply each operation, as in the main run's latency report, and Logging the time spent logging it.");
+    println!("Cells with the same matching algorithm should share a fingerprint.");
+}
+
+/// Appends `results` to the CSV at `path`, writing the header when the file
+/// is new.
+pub fn append_results(path: &str, config: &ExperimentConfig, results: &[CellResult]) -> Result<(), Box<dyn Error>> {
+    let new_file = !Path::new(path).exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if new_file {
+        writeln!(file, "build,mode,matching,operations,seed,runs,trades,mean_process_ns,p50_process_ns,p99_process_ns,p999_process_ns,mean_logging_ns,ops_per_sec,fingerprint")?;
+    }
+    for r in results {
+        writeln!(
+            file,
+            "{},{:?},{},{},{},{},{},{:.2},{},{},{},{:.2},{:.0},{:x}",
+            r.build, r.mode, r.matching, r.operations, config.seed, config.runs, r.trades, r.mean_process_ns, r.p50_process_ns,
+            r.p99_process_ns, r.p999_process_ns, r.mean_logging_ns, r.throughput, r.fingerprint
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_experiment_matrix() {
+        let config = ExperimentConfig::parse(&args(&["--modes", "baseline,ae", "--matching", "fifo,lottery:7", "--runs", "2"])).unwrap();
+        assert_eq!(config.modes, vec![LoggingMode::Baseline, LoggingMode::AsyncEnum]);
+        assert_eq!(config.matching, vec![MatchingAlgorithm::Fifo, MatchingAlgorithm::Lottery { seed: 7 }]);
+        assert_eq!(config.runs, 2);
+        assert!(ExperimentConfig::parse(&args(&["--modes", "tc,tf"])).is_err());
+        assert!(ExperimentConfig::parse(&args(&["--runs", "0"])).is_err());
+        assert!(ExperimentConfig::parse(&args(&["--runs"])).is_err());
+    }
+
+    #[test]
+    fn test_cells_with_the_same_matching_trade_identically() {
+        let workload = seeded_workload(2_000, 7);
+        assert_eq!(workload, seeded_workload(2_000, 7));
+
+        let fifo = run_cell(LoggingMode::Baseline, MatchingAlgorithm::Fifo, &workload, 2).unwrap();
+        let lottery = run_cell(LoggingMode::Baseline, MatchingAlgorithm::Lottery { seed: 1 }, &workload, 2).unwrap();
+        assert!(fifo.trades > 0);
+        assert_eq!(fifo.operations, workload.len());
+        assert_ne!(fifo.fingerprint, lottery.fingerprint);
+        assert_eq!(fifo.fingerprint, run_cell(LoggingMode::Baseline, MatchingAlgorithm::Fifo, &workload, 1).unwrap().fingerprint);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::clock::{Clock, SystemClock, NANOS_PER_DAY};
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::market_data::depth::DepthBook;
+use crate::order::Order;
+use crate::utils::{Side, UuidMap};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+pub const DEFAULT_LOBSTER_LEVELS: usize = 10;
+
+const NEW_LIMIT: u8 = 1;
+const DELETE: u8 = 3;
+const VISIBLE_EXECUTION: u8 = 4;
+
+/// LOBSTER prices are integers in units of 1/10000 of a dollar.
+const PRICE_MULTIPLIER: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+/// Placeholder prices LOBSTER uses for empty levels.
+const EMPTY_ASK_PRICE: i64 = 9_999_999_999;
+const EMPTY_BID_PRICE: i64 = -9_999_999_999;
+
+/// The message and orderbook files of one instrument, plus the aggregated
+/// levels needed to write an orderbook row after every message.
+struct InstrumentFiles {
+    messages: BufWriter<File>,
+    orderbook: BufWriter<File>,
+    depth: DepthBook,
+}
+
+/// An engine subscriber that writes the event stream as LOBSTER message and
+/// orderbook files, one pair per instrument, named
+/// `<instrument>_message_<levels>.csv` and `<instrument>_orderbook_<levels>.csv`.
+///
+/// Resting limit orders are type 1 messages, cancels type 3 and executions
+/// type 4 against the resting order. Aggressive orders only appear through
+/// their executions, as in LOBSTER. Order ids are numbered in order of first
+/// appearance since LOBSTER ids are integers.
+pub struct LobsterExporter {
+    dir: PathBuf,
+    levels: usize,
+    clock: Box<dyn Clock>,
+    files: HashMap<String, InstrumentFiles>,
+    order_ids: UuidMap<u64>,
+    /// Displayed price of every resting order, where its executions come off
+    /// the book even when it trades at a better (discretionary) price.
+    resting: UuidMap<Decimal>,
+    next_order_id: u64,
+    error: Option<io::Error>,
+}
+
+impl LobsterExporter {
+    pub fn new(dir: impl Into<PathBuf>, levels: usize) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            levels: levels.max(1),
+            clock: Box::new(SystemClock),
+            files: HashMap::new(),
+            order_ids: UuidMap::default(),
+            resting: UuidMap::default(),
+            next_order_id: 1,
+            error: None,
+        })
+    }
+
+    /// Stamps rest and cancel messages with `clock` instead of the system
+    /// time. Executions always carry the trade timestamp.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    fn order_id(&mut self, id: Uuid) -> u64 {
+        *self.order_ids.entry(id).or_insert_with(|| {
+            self.next_order_id += 1;
+            self.next_order_id - 1
+        })
+    }
+
+    fn files(&mut self, instrument: &str) -> io::Result<&mut InstrumentFiles> {
+        if !self.files.contains_key(instrument) {
+            let open = |kind: &str| -> io::Result<BufWriter<File>> {
+                let path = self.dir.join(format!("{}_{}_{}.csv", instrument, kind, self.levels));
+                Ok(BufWriter::new(File::create(path)?))
+            };
+            let files = InstrumentFiles {
+                messages: open("message")?,
+                orderbook: open("orderbook")?,
+                depth: DepthBook::default(),
+            };
+            self.files.insert(instrument.to_string(), files);
+        }
+        Ok(self.files.get_mut(instrument).expect("inserted above"))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record(
+        &mut self,
+        instrument: &str,
+        timestamp: u64,
+        event_type: u8,
+        order_id: u64,
+        size: Decimal,
+        price: Decimal,
+        side: Side,
+        level_delta: Decimal,
+    ) -> io::Result<()> {
+        let levels = self.levels;
+        let files = self.files(instrument)?;
+
+        files.depth.apply(side, price, level_delta);
+
+        let direction = match side {
+            Side::Buy => 1,
+            Side::Sell => -1,
+        };
+        writeln!(
+            files.messages,
+            "{},{},{},{},{},{}",
+            format_time(timestamp),
+            event_type,
+            order_id,
+            size.normalize(),
+            lobster_price(price),
+            direction
+        )?;
+
+        let mut asks = files.depth.asks();
+        let mut bids = files.depth.bids();
+        let mut row = Vec::with_capacity(levels * 4);
+        for _ in 0..levels {
+            match asks.next() {
+                Some((price, size)) => row.extend([lobster_price(price).to_string(), size.normalize().to_string()]),
+                None => row.extend([EMPTY_ASK_PRICE.to_string(), "0".to_string()]),
+            }
+            match bids.next() {
+                Some((price, size)) => row.extend([lobster_price(price).to_string(), size.normalize().to_string()]),
+                None => row.extend([EMPTY_BID_PRICE.to_string(), "0".to_string()]),
+            }
+        }
+        writeln!(files.orderbook, "{}", row.join(","))
+    }
+
+    fn handle(&mut self, event: &EngineEvent) -> io::Result<()> {
+        match event {
+            EngineEvent::OrderRested(order) => {
+                let Some(price) = order.price else { return Ok(()) };
+                self.resting.insert(order.order_id, price);
+                let id = self.order_id(order.order_id);
+                let now = self.clock.now_nanos();
+                let size = order.remaining_quantity;
+                self.record(&order.instrument, now, NEW_LIMIT, id, size, price, order.side, size)
+            }
+            EngineEvent::Trade(trade) => {
+                let (resting_id, resting_side) = match trade.taker_side {
+                    Side::Buy => (trade.sell_order_id, Side::Sell),
+                    Side::Sell => (trade.buy_order_id, Side::Buy),
+                };
+                let price = self.resting.get(&resting_id).copied().unwrap_or(trade.price);
+                let id = self.order_id(resting_id);
+                let size = trade.quantity;
+                self.record(&trade.instrument, trade.timestamp, VISIBLE_EXECUTION, id, size, price, resting_side, -size)
+            }
+            EngineEvent::OrderCanceled(order) | EngineEvent::OrderExpired(order) => {
+                let Some(price) = order.price else { return Ok(()) };
+                let id = self.order_id(order.order_id);
+                let now = self.clock.now_nanos();
+                let size = order.remaining_quantity;
+                self.record(&order.instrument, now, DELETE, id, size, price, order.side, -size)?;
+                self.forget(order);
+                Ok(())
+            }
+            EngineEvent::OrderFilled { order, .. } => {
+                self.forget(order);
+                Ok(())
+            }
+            EngineEvent::OrderRepriced { order, old_price } => {
+                // LOBSTER has no modify event, so a re-price is a delete and a new order.
+                let Some(price) = order.price else { return Ok(()) };
+                self.resting.insert(order.order_id, price);
+                let id = self.order_id(order.order_id);
+                let now = self.clock.now_nanos();
+                let size = order.remaining_quantity;
+                self.record(&order.instrument, now, DELETE, id, size, *old_price, order.side, -size)?;
+                self.record(&order.instrument, now, NEW_LIMIT, id, size, price, order.side, size)
+            }
+            EngineEvent::OrderAccepted { .. } | EngineEvent::OrderRejected { .. } | EngineEvent::TradeBusted(_) | EngineEvent::IndexPrice { .. } | EngineEvent::Imbalance { .. } => Ok(()),
+        }
+    }
+
+    fn forget(&mut self, order: &Order) {
+        self.order_ids.remove(&order.order_id);
+        self.resting.remove(&order.order_id);
+    }
+}
+
+impl EngineSubscriber for LobsterExporter {
+    fn on_event(&mut self, event: &EngineEvent) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.handle(event) {
+            eprintln!("LOBSTER export failed: {}", e);
+            self.error = Some(e);
+        }
+    }
+}
+
+/// Seconds after midnight (UTC) with nanosecond precision.
+fn format_time(timestamp: u64) -> String {
+    let since_midnight = timestamp % NANOS_PER_DAY;
+    format!("{}.{:09}", since_midnight / 1_000_000_000, since_midnight % 1_000_000_000)
+}
+
+fn lobster_price(price: Decimal) -> i64 {
+    (price * PRICE_MULTIPLIER).round().try_into().unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_time_and_price_encoding() {
+        assert_eq!(format_time(NANOS_PER_DAY + 34_200_000_000_001), "34200.000000001");
+        assert_eq!(lobster_price(dec!(585.33)), 5_853_300);
+    }
+
+    #[test]
+    fn test_export_messages_and_orderbook() {
+        let dir = std::env::temp_dir().join(format!("lobster-export-{}", Uuid::new_v4()));
+        let clock = ManualClock::new(34_200_000_000_000);
+        let exporter = LobsterExporter::new(&dir, 2).unwrap().with_clock(clock.clone());
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock)
+            .subscriber(Box::new(exporter))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let ask = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), dec!(100));
+        let ask_id = ask.order_id;
+        engine.process_order(ask, &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(50)), &mut logger).unwrap();
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(30)), &mut logger).unwrap();
+        engine.cancel_order_by_id(&ask_id, "SOFI").unwrap();
+        drop(engine);
+
+        let messages = std::fs::read_to_string(dir.join("SOFI_message_2.csv")).unwrap();
+        let orderbook = std::fs::read_to_string(dir.join("SOFI_orderbook_2.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            messages.lines().collect::<Vec<_>>(),
+            vec![
+                "34200.000000000,1,1,100,105000,-1",
+                "34200.000000000,1,2,50,100000,1",
+                "34200.000000000,4,1,30,105000,-1",
+                "34200.000000000,3,1,70,105000,-1",
+            ]
+        );
+        assert_eq!(
+            orderbook.lines().collect::<Vec<_>>(),
+            vec![
+                "105000,100,-9999999999,0,9999999999,0,-9999999999,0",
+                "105000,100,100000,50,9999999999,0,-9999999999,0",
+                "105000,70,100000,50,9999999999,0,-9999999999,0",
+                "9999999999,0,100000,50,9999999999,0,-9999999999,0",
+            ]
+        );
+    }
+}
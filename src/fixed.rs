@@ -0,0 +1,129 @@
+use rust_decimal::Decimal;
+use std::fmt;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+/// A price or quantity as a whole number of 10^-8 units, which is how the
+/// order book stores them. Values with more decimal places, or beyond about
+/// ±92 billion, do not fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    /// Decimal places kept.
+    pub const SCALE: u32 = 8;
+    pub const ZERO: Fixed = Fixed(0);
+    const UNIT: i64 = 100_000_000;
+
+    /// `value` in fixed point, or `None` if it does not fit.
+    pub fn from_decimal(value: Decimal) -> Option<Fixed> {
+        let value = if value.scale() > Self::SCALE { value.normalize() } else { value };
+        let scale = value.scale();
+        if scale > Self::SCALE {
+            return None;
+        }
+        let units = value.mantissa().checked_mul(10i128.pow(Self::SCALE - scale))?;
+        i64::try_from(units).ok().map(Fixed)
+    }
+
+    /// `value` in fixed point, for values the engine has already checked.
+    ///
+    /// Panics if `value` does not fit.
+    pub fn from_validated(value: Decimal) -> Fixed {
+        Self::from_decimal(value).unwrap_or_else(|| panic!("{} does not fit in fixed point", value))
+    }
+
+    /// The value as a normalized decimal: 100.5, not 100.50000000.
+    pub fn to_decimal(self) -> Decimal {
+        if self.0 % Self::UNIT == 0 {
+            Decimal::from(self.0 / Self::UNIT)
+        } else {
+            Decimal::new(self.0, Self::SCALE).normalize()
+        }
+    }
+
+    /// The exact sum of `values`, which may exceed the fixed-point range.
+    pub fn total(values: impl IntoIterator<Item = Fixed>) -> Decimal {
+        let units: i128 = values.into_iter().map(|value| value.0 as i128).sum();
+        Decimal::from_i128_with_scale(units, Self::SCALE).normalize()
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn abs(self) -> Fixed {
+        Fixed(self.0.abs())
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0 + other.0)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0 - other.0)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(-self.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Fixed) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Fixed) {
+        self.0 -= other.0;
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_decimal().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_decimal_round_trip() {
+        assert_eq!(Fixed::from_decimal(dec!(99.50)).unwrap().to_decimal().to_string(), "99.5");
+        assert_eq!(Fixed::from_decimal(dec!(0.00000001)), Some(Fixed(1)));
+        assert_eq!(Fixed::from_decimal(dec!(-1.5)), Some(Fixed(-150_000_000)));
+        assert_eq!(Fixed::from_decimal(dec!(0.000000001)), None);
+        assert_eq!(Fixed::from_decimal(dec!(100_000_000_000)), None);
+        assert_eq!(Fixed::from_decimal(dec!(2.5000000000)), Some(Fixed(250_000_000)));
+        assert_eq!(Fixed::from_decimal(dec!(-3)).unwrap().to_decimal().to_string(), "-3");
+        assert_eq!(Fixed::total([Fixed::from_validated(dec!(90_000_000_000)); 2]), dec!(180_000_000_000));
+    }
+
+    #[test]
+    fn test_to_decimal_round_trips_whole_numbers_and_the_limits() {
+        for value in [dec!(-3), dec!(-1), dec!(0), dec!(7), dec!(-92_000_000_000), dec!(92_000_000_000)] {
+            let fixed = Fixed::from_validated(value);
+            assert_eq!(fixed.to_decimal(), value);
+            assert_eq!(Fixed::from_decimal(fixed.to_decimal()), Some(fixed));
+        }
+        for fixed in [Fixed(i64::MAX), Fixed(i64::MIN), Fixed(i64::MIN + 1), Fixed(-Fixed::UNIT), Fixed(-250_000_000)] {
+            assert_eq!(fixed.to_decimal(), Decimal::new(fixed.0, Fixed::SCALE));
+            assert_eq!(Fixed::from_decimal(fixed.to_decimal()), Some(fixed));
+        }
+        assert_eq!(Fixed(-250_000_000).to_decimal().to_string(), "-2.5");
+        assert_eq!(Fixed::from_decimal(Decimal::new(i64::MAX, Fixed::SCALE) + dec!(0.00000001)), None);
+    }
+}
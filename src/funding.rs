@@ -0,0 +1,94 @@
+//! Funding of perpetual swaps: each interval the engine fixes a rate from
+//! the premium of the perpetual over its index, and every position pays or
+//! receives its notional times that rate.
+
+use crate::clients::ClientId;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// One funding fixing of one perpetual.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FundingRound {
+    pub instrument: String,
+    pub timestamp: u64,
+    /// The price positions were valued at: the mid, or the last trade while
+    /// one side of the book is empty.
+    pub mark: Decimal,
+    pub index: Decimal,
+    pub rate: Decimal,
+}
+
+/// What a position of `quantity` receives (positive) or pays (negative) in a
+/// round: longs pay a positive rate, shorts receive it.
+pub fn funding_payment(quantity: Decimal, round: &FundingRound) -> Decimal {
+    -quantity * round.mark * round.rate
+}
+
+/// Funding rounds of a run and what each client paid or received in them.
+#[derive(Debug, Default)]
+pub struct FundingLedger {
+    rounds: Vec<FundingRound>,
+    /// Net funding by client and instrument, positive when received.
+    clients: BTreeMap<ClientId, BTreeMap<String, Decimal>>,
+}
+
+impl FundingLedger {
+    pub fn record(&mut self, round: FundingRound, payments: Vec<(ClientId, Decimal)>) {
+        for (client, amount) in payments {
+            *self.clients.entry(client).or_default().entry(round.instrument.clone()).or_default() += amount;
+        }
+        self.rounds.push(round);
+    }
+
+    pub fn rounds(&self) -> &[FundingRound] {
+        &self.rounds
+    }
+
+    /// Net funding of `client` over all instruments.
+    pub fn net(&self, client: &ClientId) -> Decimal {
+        self.clients.get(client).map_or(Decimal::ZERO, |instruments| instruments.values().sum())
+    }
+
+    pub fn print(&self) {
+        if self.rounds.is_empty() {
+            return;
+        }
+        println!("\n--- Funding ---");
+        println!("{:<12} {:>8} {:>12} {:>12} {:>12}", "Instrument", "Rounds", "Mean rate", "Min rate", "Max rate");
+        let mut instruments: BTreeMap<&str, Vec<Decimal>> = BTreeMap::new();
+        for round in &self.rounds {
+            instruments.entry(&round.instrument).or_default().push(round.rate);
+        }
+        for (instrument, rates) in instruments {
+            let mean = rates.iter().sum::<Decimal>() / Decimal::from(rates.len());
+            let (min, max) = (rates.iter().min().copied().unwrap_or_default(), rates.iter().max().copied().unwrap_or_default());
+            println!("{:<12} {:>8} {:>12} {:>12} {:>12}", instrument, rates.len(), mean.round_dp(6), min.round_dp(6), max.round_dp(6));
+        }
+        if !self.clients.is_empty() {
+            println!("{:<16} {:>16}", "Client", "Net funding");
+            for client in self.clients.keys() {
+                println!("{:<16} {:>16}", client.to_string(), self.net(client).round_dp(4));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_longs_pay_shorts_when_the_perpetual_trades_rich() {
+        let round = FundingRound { instrument: "BTC-PERP".to_string(), timestamp: 0, mark: dec!(101), index: dec!(100), rate: dec!(0.01) };
+        assert_eq!(funding_payment(dec!(2), &round), dec!(-2.02));
+        assert_eq!(funding_payment(dec!(-2), &round), dec!(2.02));
+
+        let (alice, bob) = (ClientId::new("alice"), ClientId::new("bob"));
+        let mut ledger = FundingLedger::default();
+        ledger.record(round.clone(), vec![(alice.clone(), dec!(-2.02)), (bob.clone(), dec!(2.02))]);
+        ledger.record(round, vec![(alice.clone(), dec!(-2.02))]);
+        assert_eq!((ledger.net(&alice), ledger.net(&bob)), (dec!(-4.04), dec!(2.02)));
+        assert_eq!(ledger.rounds().len(), 2);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::clients::ClientId;
+use crate::operation::{OperationReader, OperationSource, ParseError, SimOp};
+use crate::source::{Endpoint, OperationListener};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// The tokens clients log on with, loaded from a `client,token` CSV.
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    tokens: HashMap<ClientId, String>,
+}
+
+impl Credentials {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_reader(file)
+    }
+
+    pub fn from_reader<R: Read>(source: R) -> Result<Self, Box<dyn Error>> {
+        let mut credentials = Credentials::default();
+        for record in csv::Reader::from_reader(source).records() {
+            let record = record?;
+            match (record.get(0), record.get(1)) {
+                (Some(client), Some(token)) if !client.is_empty() => credentials.insert(ClientId::new(client), token),
+                _ => return Err(format!("Invalid credentials row '{}'", record.iter().collect::<Vec<_>>().join(",")).into()),
+            }
+        }
+        Ok(credentials)
+    }
+
+    pub fn insert(&mut self, client: ClientId, token: impl Into<String>) {
+        self.tokens.insert(client, token.into());
+    }
+
+    fn verify(&self, client: &ClientId, token: &str) -> bool {
+        self.tokens.get(client).is_some_and(|expected| expected == token)
+    }
+}
+
+/// Flag a session can add to its logon line to have its orders canceled when
+/// the connection drops.
+pub const CANCEL_ON_DISCONNECT: &str = "cancel-on-disconnect";
+
+/// An authenticated session and the options it logged on with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Logon {
+    pub client: ClientId,
+    pub cancel_on_disconnect: bool,
+}
+
+/// Reads the `LOGON,<client>,<token>[,cancel-on-disconnect]` line a session
+/// must open with.
+pub fn logon(reader: &mut impl BufRead, credentials: &Credentials) -> Result<Logon, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let fields: Vec<&str> = line.trim_end().split(',').collect();
+    let (client, token, options) = match fields.as_slice() {
+        ["LOGON", client, token, options @ ..] => (ClientId::new(*client), *token, options),
+        _ => return Err(format!("expected LOGON,<client>,<token>, got '{}'", line.trim_end())),
+    };
+    if !credentials.verify(&client, token) {
+        return Err(format!("invalid credentials for client '{}'", client));
+    }
+    let mut cancel_on_disconnect = false;
+    for option in options {
+        match *option {
+            CANCEL_ON_DISCONNECT => cancel_on_disconnect = true,
+            other => return Err(format!("unknown logon option '{}'", other)),
+        }
+    }
+    Ok(Logon { client, cancel_on_disconnect })
+}
+
+/// What the session threads report to the matching thread.
+#[derive(Debug)]
+pub enum SessionEvent {
+    LoggedOn(ClientId),
+    /// Boxed, as a parsed new order is several times larger than the other
+    /// variants.
+    Operation(Box<Result<SimOp, ParseError>>),
+    Disconnected(ClientId),
+}
+
+/// Accepts any number of authenticated sessions on one endpoint and merges
+/// their operations into a single source. Every operation is attributed to
+/// the session it arrived on, whatever client it names itself. A session that
+/// logged on with cancel-on-disconnect ends with a cancel of all its client's
+/// orders.
+///
+/// The source ends once every session that logged on has disconnected.
+pub struct Gateway {
+    events: Receiver<SessionEvent>,
+    endpoint: Endpoint,
+    sessions: usize,
+}
+
+impl Gateway {
+    pub fn start(listener: OperationListener, credentials: Credentials) -> std::io::Result<Self> {
+        let endpoint = listener.local_endpoint()?;
+        let (sender, events) = channel();
+        thread::spawn(move || accept_sessions(listener, credentials, sender));
+        Ok(Gateway { events, endpoint, sessions: 0 })
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// The next session event, blocking until one arrives. `None` once the
+    /// last session has disconnected.
+    pub fn next_event(&mut self) -> Option<SessionEvent> {
+        let event = self.events.recv().ok()?;
+        match &event {
+            SessionEvent::LoggedOn(client) => {
+                self.sessions += 1;
+                println!("Session logged on: {}", client);
+            }
+            SessionEvent::Disconnected(client) => {
+                self.sessions -= 1;
+                println!("Session disconnected: {}", client);
+            }
+            SessionEvent::Operation(_) => {}
+        }
+        Some(event)
+    }
+}
+
+impl OperationSource for Gateway {
+    fn next_operation(&mut self) -> Option<Result<SimOp, ParseError>> {
+        loop {
+            match self.next_event()? {
+                SessionEvent::Operation(operation) => return Some(*operation),
+                SessionEvent::Disconnected(_) if self.sessions == 0 => return None,
+                SessionEvent::LoggedOn(_) | SessionEvent::Disconnected(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        // The acceptor thread keeps the listener alive until the process exits.
+        if let Endpoint::Unix(path) = &self.endpoint {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn accept_sessions(listener: OperationListener, credentials: Credentials, events: Sender<SessionEvent>) {
+    loop {
+        let stream = match listener.accept_stream() {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Gateway stopped accepting sessions: {}", e);
+                return;
+            }
+        };
+        let credentials = credentials.clone();
+        let events = events.clone();
+        thread::spawn(move || run_session(stream, &credentials, &events));
+    }
+}
+
+fn run_session(stream: Box<dyn Read + Send>, credentials: &Credentials, events: &Sender<SessionEvent>) {
+    let mut reader = BufReader::new(stream);
+    let Logon { client, cancel_on_disconnect } = match logon(&mut reader, credentials) {
+        Ok(logon) => logon,
+        Err(e) => {
+            eprintln!("Rejected session logon: {}", e);
+            return;
+        }
+    };
+    if events.send(SessionEvent::LoggedOn(client.clone())).is_err() {
+        return;
+    }
+
+    match OperationReader::new(reader) {
+        Ok(mut operations) => {
+            while let Some(mut operation) = operations.next_operation() {
+                if let Ok(op) = &mut operation {
+                    op.set_client(client.clone());
+                }
+                if events.send(SessionEvent::Operation(Box::new(operation))).is_err() {
+                    return;
+                }
+            }
+        }
+        Err(e) => eprintln!("Session {} sent no operations header: {}", client, e),
+    }
+    if cancel_on_disconnect {
+        let _ = events.send(SessionEvent::Operation(Box::new(Ok(SimOp::CancelAll { client: client.clone() }))));
+    }
+    let _ = events.send(SessionEvent::Disconnected(client));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::connect;
+    use std::io::Write;
+
+    const HEADER: &str = "operation,instrument,side,order_type,quantity,price,order_to_cancel,client\n";
+
+    fn credentials() -> Credentials {
+        Credentials::from_reader("client,token\nalice,secret\nbob,hunter2\n".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_logon_checks_credentials() {
+        let credentials = credentials();
+        let alice = logon(&mut "LOGON,alice,secret\n".as_bytes(), &credentials).unwrap();
+        assert_eq!(alice, Logon { client: ClientId::new("alice"), cancel_on_disconnect: false });
+        assert!(logon(&mut "LOGON,bob,hunter2,cancel-on-disconnect\n".as_bytes(), &credentials).unwrap().cancel_on_disconnect);
+        assert!(logon(&mut "LOGON,bob,hunter2,sometimes\n".as_bytes(), &credentials).is_err());
+        assert!(logon(&mut "LOGON,alice,hunter2\n".as_bytes(), &credentials).is_err());
+        assert!(logon(&mut "LOGON,mallory,secret\n".as_bytes(), &credentials).is_err());
+        assert!(logon(&mut HEADER.as_bytes(), &credentials).is_err());
+    }
+
+    #[test]
+    fn test_gateway_attributes_operations_to_the_session() {
+        let listener = OperationListener::bind(&Endpoint::Tcp("127.0.0.1:0".to_string())).unwrap();
+        let mut gateway = Gateway::start(listener, credentials()).unwrap();
+
+        let mut rejected = connect(gateway.endpoint()).unwrap();
+        rejected.write_all(b"LOGON,alice,wrong\n").unwrap();
+        drop(rejected);
+
+        let mut session = connect(gateway.endpoint()).unwrap();
+        session.write_all(b"LOGON,alice,secret\n").unwrap();
+        session.write_all(HEADER.as_bytes()).unwrap();
+        session.write_all(b"NEW,PUMPTHIS,BUY,LIMIT,10,99.50,00000000-0000-0000-0000-000000000001,bob\n").unwrap();
+        drop(session);
+
+        let operation = gateway.next_operation().unwrap().unwrap();
+        assert_eq!(operation.client(), Some(&ClientId::new("alice")));
+        assert!(gateway.next_operation().is_none());
+    }
+
+    #[test]
+    fn test_cancel_on_disconnect_session_ends_with_mass_cancel() {
+        let listener = OperationListener::bind(&Endpoint::Tcp("127.0.0.1:0".to_string())).unwrap();
+        let mut gateway = Gateway::start(listener, credentials()).unwrap();
+
+        let mut session = connect(gateway.endpoint()).unwrap();
+        session.write_all(b"LOGON,bob,hunter2,cancel-on-disconnect\n").unwrap();
+        session.write_all(HEADER.as_bytes()).unwrap();
+        session.write_all(b"NEW,PUMPTHIS,BUY,LIMIT,10,99.50,00000000-0000-0000-0000-000000000001,\n").unwrap();
+        drop(session);
+
+        assert!(matches!(gateway.next_operation(), Some(Ok(SimOp::New(_)))));
+        assert_eq!(gateway.next_operation().unwrap().unwrap(), SimOp::CancelAll { client: ClientId::new("bob") });
+        assert!(gateway.next_operation().is_none());
+    }
+}
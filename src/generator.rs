@@ -0,0 +1,504 @@
+//! Synthetic order flow, produced in memory. A [`Generator`] combines a
+//! price process (where the mid is), an arrival process (what kind of
+//! operation comes next) and an order mix (how ordinary flow is drawn), and
+//! yields the operations one at a time. The data generator writes them to
+//! `operations.csv`; experiments, benches and agents use them directly.
+
+use crate::operation::{NewOrder, SimOp};
+use crate::order::OrderAttributes;
+use crate::utils::Side;
+use rand::prelude::IndexedRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::VecDeque;
+use std::ops::{Range, RangeInclusive};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Steps in the data generator's default file.
+pub const DEFAULT_OPERATIONS: usize = 100_000;
+
+/// Where the mid price is. Called once per step of the flow, before the
+/// step's operation is drawn.
+pub trait PriceProcess {
+    fn next_mid(&mut self, rng: &mut dyn RngCore) -> Decimal;
+}
+
+/// A mid that never moves, as in the data generator's default file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedMid(pub Decimal);
+
+impl PriceProcess for FixedMid {
+    fn next_mid(&mut self, _rng: &mut dyn RngCore) -> Decimal {
+        self.0
+    }
+}
+
+/// A mid that moves one `step` up or down with even odds every step, and
+/// never below `step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomWalk {
+    pub mid: Decimal,
+    pub step: Decimal,
+}
+
+impl PriceProcess for RandomWalk {
+    fn next_mid(&mut self, rng: &mut dyn RngCore) -> Decimal {
+        let step = if rng.random_bool(0.5) { self.step } else { -self.step };
+        self.mid = (self.mid + step).max(self.step);
+        self.mid
+    }
+}
+
+/// What the flow sends at one step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arrival {
+    /// An operation drawn from the order mix.
+    Mix,
+    /// A limit order drawn from the mix, or, with a side, a passive limit
+    /// order on that side.
+    Limit(Option<Side>),
+    /// A market order of about `size` (±20%) on `side`, after a `withdraw`
+    /// share of the open limit orders it would hit is canceled.
+    Burst { side: Side, size: u32, withdraw: f64 },
+}
+
+/// Decides what kind of operation comes at each step after the book build.
+pub trait ArrivalProcess {
+    fn arrival(&mut self, step: usize, rng: &mut dyn RngCore) -> Arrival;
+}
+
+/// Ordinary flow from the order mix at every step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SteadyFlow;
+
+impl ArrivalProcess for SteadyFlow {
+    fn arrival(&mut self, _step: usize, _rng: &mut dyn RngCore) -> Arrival {
+        Arrival::Mix
+    }
+}
+
+/// A flash crash: from step `start`, `duration` consecutive market orders
+/// of about `size` on `side`, after a `withdraw` share of the resting orders
+/// they hit is canceled. For the next `recovery` steps the hit side refills,
+/// with new limit orders there crowding out the usual mix less and less as
+/// the book recovers. Ordinary flow otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlashCrash {
+    pub side: Side,
+    pub start: usize,
+    pub duration: usize,
+    pub size: u32,
+    pub recovery: usize,
+    pub withdraw: f64,
+}
+
+impl Default for FlashCrash {
+    fn default() -> Self {
+        FlashCrash { side: Side::Sell, start: DEFAULT_OPERATIONS / 2, duration: 200, size: 500, recovery: 5_000, withdraw: 0.0 }
+    }
+}
+
+impl FromStr for FlashCrash {
+    type Err = String;
+
+    /// Parses comma-separated `key=value` pairs, e.g.
+    /// `side=sell,start=50000,duration=200,size=500,recovery=5000,withdraw=0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut crash = FlashCrash::default();
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("Invalid flash crash parameter '{}', expected <key>=<value>", pair))?;
+            let invalid = || format!("Invalid flash crash {} '{}'", key, value);
+            match key {
+                "side" => crash.side = Side::from_str(value)?,
+                "start" => crash.start = value.parse().map_err(|_| invalid())?,
+                "duration" => crash.duration = value.parse().ok().filter(|&ops| ops > 0).ok_or_else(invalid)?,
+                "size" => crash.size = value.parse().ok().filter(|&size| size > 0).ok_or_else(invalid)?,
+                "recovery" => crash.recovery = value.parse().map_err(|_| invalid())?,
+                "withdraw" => crash.withdraw = value.parse().ok().filter(|share| (0.0..=1.0).contains(share)).ok_or_else(invalid)?,
+                _ => return Err(format!("Unknown flash crash parameter '{}', expected side, start, duration, size, recovery or withdraw", key)),
+            }
+        }
+        Ok(crash)
+    }
+}
+
+impl FlashCrash {
+    /// The side whose resting orders the burst takes out.
+    pub fn hit_side(&self) -> Side {
+        self.side.opposite()
+    }
+
+    fn in_burst(&self, step: usize) -> bool {
+        (self.start..self.start + self.duration).contains(&step)
+    }
+
+    /// How far through the recovery `step` is, if it is in it.
+    fn recovery_progress(&self, step: usize) -> Option<f64> {
+        let end = self.start + self.duration;
+        (end..end + self.recovery).contains(&step).then(|| (step - end) as f64 / self.recovery as f64)
+    }
+}
+
+impl ArrivalProcess for FlashCrash {
+    fn arrival(&mut self, step: usize, rng: &mut dyn RngCore) -> Arrival {
+        if self.in_burst(step) {
+            let withdraw = if step == self.start { self.withdraw } else { 0.0 };
+            return Arrival::Burst { side: self.side, size: self.size, withdraw };
+        }
+        match self.recovery_progress(step) {
+            Some(progress) if rng.random_bool(1.0 - progress) => Arrival::Limit(Some(self.hit_side())),
+            _ => Arrival::Mix,
+        }
+    }
+}
+
+/// How ordinary flow is drawn. The default is the data generator's mix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderMix {
+    /// Relative weights of limit orders, market orders and cancels.
+    pub limit: f64,
+    pub market: f64,
+    pub cancel: f64,
+    /// Share of limit orders from the mix priced through the mid instead of
+    /// behind it.
+    pub aggressive: f64,
+    /// Distance of limit prices from the mid, before rounding to the tick.
+    pub offset: Range<f64>,
+    pub tick_size: Decimal,
+    pub limit_quantity: RangeInclusive<u32>,
+    pub market_quantity: RangeInclusive<u32>,
+    /// A cancel picks one of this many most recently sent limit orders still
+    /// open as far as the flow knows.
+    pub cancel_window: usize,
+}
+
+impl Default for OrderMix {
+    fn default() -> Self {
+        OrderMix {
+            limit: 0.60,
+            market: 0.15,
+            cancel: 0.25,
+            aggressive: 0.1,
+            offset: 0.05..2.0,
+            tick_size: dec!(0.05),
+            limit_quantity: 1..=100,
+            market_quantity: 50..=250,
+            cancel_window: 20,
+        }
+    }
+}
+
+impl OrderMix {
+    pub fn validate(&self) -> Result<(), String> {
+        let weights = [self.limit, self.market, self.cancel];
+        if weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+            return Err("Order mix weights must be non-negative with a positive sum".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.aggressive) {
+            return Err(format!("Aggressive share {} must be between 0 and 1", self.aggressive));
+        }
+        if self.offset.is_empty() || self.offset.start < 0.0 {
+            return Err(format!("Price offset range {:?} must be non-empty and non-negative", self.offset));
+        }
+        if self.tick_size <= Decimal::ZERO {
+            return Err(format!("Tick size {} must be positive", self.tick_size));
+        }
+        for quantities in [&self.limit_quantity, &self.market_quantity] {
+            if quantities.is_empty() || *quantities.start() == 0 {
+                return Err(format!("Quantity range {:?} must be non-empty and positive", quantities));
+            }
+        }
+        if self.cancel_window == 0 {
+            return Err("Cancel window must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of a [`Generator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    pub instrument: String,
+    /// Steps of flow. A cancel with no open order to pick sends nothing, and
+    /// a burst's withdrawals add operations, so the count of operations can
+    /// differ slightly.
+    pub operations: usize,
+    /// Leading steps that only send limit orders, to build the book.
+    pub book_build: usize,
+    pub mix: OrderMix,
+    /// Seeds every draw and the order ids, so a flow is reproducible.
+    pub seed: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            instrument: "PUMPTHIS".to_string(),
+            operations: DEFAULT_OPERATIONS,
+            book_build: 3_000,
+            mix: OrderMix::default(),
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Limit,
+    Market,
+    Cancel,
+}
+
+/// Yields the operations of a synthetic flow on one instrument. The mid is
+/// fixed at 100 unless another price process is given, and the flow is
+/// ordinary throughout unless another arrival process is given.
+pub struct Generator {
+    config: GeneratorConfig,
+    price: Box<dyn PriceProcess>,
+    arrivals: Box<dyn ArrivalProcess>,
+    rng: StdRng,
+    step: usize,
+    /// Limit orders sent and not yet canceled, oldest first. Fills are not
+    /// seen, so a cancel can target an order that already traded.
+    open_orders: Vec<(Uuid, Side)>,
+    pending: VecDeque<SimOp>,
+}
+
+impl Generator {
+    pub fn new(config: GeneratorConfig) -> Result<Self, String> {
+        config.mix.validate()?;
+        Ok(Generator {
+            price: Box::new(FixedMid(dec!(100))),
+            arrivals: Box::new(SteadyFlow),
+            rng: StdRng::seed_from_u64(config.seed),
+            step: 0,
+            open_orders: Vec::new(),
+            pending: VecDeque::new(),
+            config,
+        })
+    }
+
+    pub fn with_price_process(mut self, price: impl PriceProcess + 'static) -> Self {
+        self.price = Box::new(price);
+        self
+    }
+
+    pub fn with_arrivals(mut self, arrivals: impl ArrivalProcess + 'static) -> Self {
+        self.arrivals = Box::new(arrivals);
+        self
+    }
+
+    pub fn instrument(&self) -> &str {
+        &self.config.instrument
+    }
+
+    /// Runs one step of the flow, queueing whatever operations it sends.
+    fn advance(&mut self) {
+        let step = self.step;
+        self.step += 1;
+        let mid = self.price.next_mid(&mut self.rng);
+        let arrival = if step < self.config.book_build { Arrival::Limit(None) } else { self.arrivals.arrival(step, &mut self.rng) };
+        match arrival {
+            Arrival::Mix => {
+                let mix = &self.config.mix;
+                let kinds = [(Kind::Limit, mix.limit), (Kind::Market, mix.market), (Kind::Cancel, mix.cancel)];
+                let kind = kinds.choose_weighted(&mut self.rng, |kind| kind.1).expect("the mix was validated").0;
+                match kind {
+                    Kind::Limit => self.limit(mid, None),
+                    Kind::Market => {
+                        let side = self.random_side();
+                        let quantity = self.rng.random_range(self.config.mix.market_quantity.clone());
+                        self.market(side, quantity);
+                    }
+                    Kind::Cancel => self.cancel(),
+                }
+            }
+            Arrival::Limit(side) => self.limit(mid, side),
+            Arrival::Burst { side, size, withdraw } => {
+                if withdraw > 0.0 {
+                    self.withdraw(side.opposite(), withdraw);
+                }
+                let quantity = self.rng.random_range(size * 4 / 5..=size * 6 / 5).max(1);
+                self.market(side, quantity);
+            }
+        }
+    }
+
+    fn random_side(&mut self) -> Side {
+        if self.rng.random_bool(0.5) { Side::Buy } else { Side::Sell }
+    }
+
+    /// Sends a limit order around `mid`: passive on `side` if given, else on
+    /// a random side and through the mid with the mix's aggressive share.
+    fn limit(&mut self, mid: Decimal, side: Option<Side>) {
+        let aggressive = side.is_none() && self.rng.random_bool(self.config.mix.aggressive);
+        let side = side.unwrap_or_else(|| self.random_side());
+        let mix = &self.config.mix;
+        let offset = Decimal::from_f64(self.rng.random_range(mix.offset.clone())).unwrap_or_default().round_dp(2);
+        let price = match (side, aggressive) {
+            (Side::Buy, false) | (Side::Sell, true) => mid - offset,
+            (Side::Sell, false) | (Side::Buy, true) => mid + offset,
+        };
+        let price = ((price / mix.tick_size).round() * mix.tick_size).max(mix.tick_size);
+        let quantity = self.rng.random_range(mix.limit_quantity.clone());
+        let order_id = Uuid::from_u128(self.rng.random());
+        self.open_orders.push((order_id, side));
+        self.send(order_id, side, quantity, Some(price));
+    }
+
+    fn market(&mut self, side: Side, quantity: u32) {
+        let order_id = Uuid::from_u128(self.rng.random());
+        self.send(order_id, side, quantity, None);
+    }
+
+    fn send(&mut self, order_id: Uuid, side: Side, quantity: u32, price: Option<Decimal>) {
+        self.pending.push_back(SimOp::New(NewOrder {
+            order_id,
+            instrument: self.config.instrument.clone(),
+            side,
+            quantity: Decimal::from(quantity),
+            price,
+            client: None,
+            client_order_id: None,
+            peg: None,
+            discretion: None,
+            attributes: OrderAttributes::default(),
+            expire_at: None,
+            cancel_after: None,
+            stop_price: None,
+        }));
+    }
+
+    /// Cancels one of the most recently sent open limit orders, if any.
+    fn cancel(&mut self) {
+        let open = self.open_orders.len();
+        if open == 0 {
+            return;
+        }
+        let index = self.rng.random_range(open.saturating_sub(self.config.mix.cancel_window)..open);
+        let (order_id, _) = self.open_orders.remove(index);
+        self.pending.push_back(SimOp::Cancel { instrument: self.config.instrument.clone(), order_id, client: None });
+    }
+
+    /// Cancels a `share` of the open limit orders on `side`.
+    fn withdraw(&mut self, side: Side, share: f64) {
+        let mut withdrawn = Vec::new();
+        let rng = &mut self.rng;
+        self.open_orders.retain(|&(order_id, order_side)| {
+            let withdraw = order_side == side && rng.random_bool(share);
+            if withdraw {
+                withdrawn.push(order_id);
+            }
+            !withdraw
+        });
+        for order_id in withdrawn {
+            self.pending.push_back(SimOp::Cancel { instrument: self.config.instrument.clone(), order_id, client: None });
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = SimOp;
+
+    fn next(&mut self) -> Option<SimOp> {
+        while self.pending.is_empty() && self.step < self.config.operations {
+            self.advance();
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::OrderType;
+
+    fn flow(config: GeneratorConfig) -> Vec<SimOp> {
+        Generator::new(config).unwrap().collect()
+    }
+
+    #[test]
+    fn test_seeded_flow_is_reproducible_and_cancels_only_its_own_orders() {
+        let config = GeneratorConfig { operations: 2_000, book_build: 100, ..Default::default() };
+        let operations = flow(config.clone());
+        assert_eq!(operations, flow(config.clone()));
+        assert_ne!(operations, flow(GeneratorConfig { seed: 7, ..config }));
+
+        assert!(operations[..100].iter().all(|op| matches!(op, SimOp::New(order) if order.order_type() == OrderType::Limit)));
+        let mut sent = std::collections::HashSet::new();
+        let mut counts = [0; 3];
+        for operation in &operations {
+            match operation {
+                SimOp::New(order) if order.price.is_some() => {
+                    assert!(order.price.unwrap() % dec!(0.05) == Decimal::ZERO);
+                    sent.insert(order.order_id);
+                    counts[0] += 1;
+                }
+                SimOp::New(_) => counts[1] += 1,
+                SimOp::Cancel { order_id, .. } => {
+                    assert!(sent.remove(order_id), "cancel of an order the flow never sent");
+                    counts[2] += 1;
+                }
+                SimOp::CancelAll { .. } => unreachable!(),
+            }
+        }
+        assert_eq!(operations.len(), 2_000);
+        assert!(counts[0] > counts[2] && counts[2] > counts[1], "{:?}", counts);
+    }
+
+    #[test]
+    fn test_flash_crash_withdraws_bursts_and_refills() {
+        let crash = FlashCrash::from_str("side=sell,start=500,duration=10,size=400,recovery=100,withdraw=1").unwrap();
+        let config = GeneratorConfig { operations: 700, book_build: 300, ..Default::default() };
+        let operations: Vec<SimOp> = Generator::new(config).unwrap().with_arrivals(crash).collect();
+
+        let burst_start = operations
+            .iter()
+            .position(|op| matches!(op, SimOp::New(order) if order.order_type() == OrderType::Market && order.quantity >= dec!(320)))
+            .unwrap();
+        let withdrawn = operations[..burst_start].iter().rev().take_while(|op| matches!(op, SimOp::Cancel { .. })).count();
+        assert!(withdrawn > 50, "{} withdrawn", withdrawn);
+        let burst = &operations[burst_start..burst_start + 10];
+        assert!(burst.iter().all(|op| matches!(op, SimOp::New(order) if order.side == Side::Sell && order.price.is_none())));
+        let refills = operations[burst_start + 10..burst_start + 40]
+            .iter()
+            .filter(|op| matches!(op, SimOp::New(order) if order.side == Side::Buy && order.price.is_some_and(|price| price < dec!(100))))
+            .count();
+        assert!(refills > 20, "{} refills", refills);
+
+        assert!(FlashCrash::from_str("size=0").is_err());
+        assert!(FlashCrash::from_str("withdraw=2").is_err());
+        assert_eq!(FlashCrash::from_str("").unwrap(), FlashCrash::default());
+    }
+
+    #[test]
+    fn test_random_walk_moves_the_prices() {
+        let config = GeneratorConfig { operations: 3_000, book_build: 3_000, ..Default::default() };
+        let walk = RandomWalk { mid: dec!(100), step: dec!(0.05) };
+        let prices: Vec<Decimal> = Generator::new(config)
+            .unwrap()
+            .with_price_process(walk)
+            .filter_map(|op| match op {
+                SimOp::New(order) => order.price,
+                _ => None,
+            })
+            .collect();
+        let (low, high) = (prices.iter().min().unwrap(), prices.iter().max().unwrap());
+        assert!(high - low > dec!(4), "prices stayed within {}..{}", low, high);
+        assert!(*low > Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_invalid_mix_is_rejected() {
+        let invalid = |mix: OrderMix| Generator::new(GeneratorConfig { mix, ..Default::default() }).is_err();
+        assert!(invalid(OrderMix { limit: 0.0, market: 0.0, cancel: 0.0, ..Default::default() }));
+        assert!(invalid(OrderMix { aggressive: 1.5, ..Default::default() }));
+        assert!(invalid(OrderMix { market_quantity: 0..=10, ..Default::default() }));
+        assert!(invalid(OrderMix { cancel_window: 0, ..Default::default() }));
+        assert!(!invalid(OrderMix::default()));
+    }
+}
@@ -0,0 +1,161 @@
+//! Paces a simulation to a target rate of operations per second, whatever
+//! the timestamps of the operations, so logger backends can be compared
+//! under the same sustained load instead of as fast as the engine goes.
+
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Waits shorter than this are spun rather than slept, since a sleep can
+/// overshoot by tens of microseconds.
+const SPIN_THRESHOLD: Duration = Duration::from_micros(200);
+
+/// A target rate and how many operations may go at once after a stall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    /// At least 1: one operation is always allowed.
+    pub burst: u32,
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+    /// `<msgs_per_sec>[:<burst>]`, fx `50000` or `50000:100`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rate, burst) = s.split_once(':').map_or((s, None), |(rate, burst)| (rate, Some(burst)));
+        let per_second = rate
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .ok_or_else(|| format!("Invalid rate '{}', expected messages per second above zero", rate))?;
+        let burst = match burst {
+            Some(burst) => burst.parse::<u32>().ok().filter(|burst| *burst > 0).ok_or_else(|| format!("Invalid burst '{}', expected at least 1", burst))?,
+            None => 1,
+        };
+        Ok(RateLimit { per_second, burst })
+    }
+}
+
+/// Holds each operation back until the rate allows it, as a token bucket of
+/// `burst` tokens refilled at the target rate.
+#[derive(Debug, Clone)]
+pub struct SpeedGovernor {
+    limit: RateLimit,
+    interval: Duration,
+    /// When the next operation would be due if there were no burst: the
+    /// bucket holds a token for each interval this is behind the clock.
+    due: Option<Instant>,
+    started: Option<Instant>,
+    /// When the latest operation was let through.
+    last: Option<Instant>,
+    operations: u64,
+    throttled: u64,
+}
+
+impl SpeedGovernor {
+    pub fn new(limit: RateLimit) -> Self {
+        SpeedGovernor { limit, interval: Duration::from_secs_f64(1.0 / limit.per_second), due: None, started: None, last: None, operations: 0, throttled: 0 }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// How long an operation arriving at `now` has to wait, taking its
+    /// token as if it waited.
+    pub fn delay(&mut self, now: Instant) -> Duration {
+        self.started.get_or_insert(now);
+        let due = self.due.map_or(now, |due| due.max(now));
+        let wait = due.saturating_duration_since(now + self.interval * (self.limit.burst - 1));
+        self.due = Some(due + self.interval);
+        self.last = Some(now + wait);
+        self.operations += 1;
+        if !wait.is_zero() {
+            self.throttled += 1;
+        }
+        wait
+    }
+
+    /// Blocks until the next operation may go.
+    pub fn wait(&mut self) {
+        let start = Instant::now();
+        let wait = self.delay(start);
+        if wait.is_zero() {
+            return;
+        }
+        let until = start + wait;
+        if wait > SPIN_THRESHOLD {
+            thread::sleep(wait - SPIN_THRESHOLD);
+        }
+        while Instant::now() < until {
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Operations paced so far, and how many of them had to wait.
+    pub fn counts(&self) -> (u64, u64) {
+        (self.operations, self.throttled)
+    }
+
+    /// The rate reached between the first operation and the latest, in
+    /// operations per second.
+    pub fn achieved_rate(&self) -> f64 {
+        match (self.started, self.last) {
+            (Some(started), Some(last)) if last > started => (self.operations - 1) as f64 / (last - started).as_secs_f64(),
+            _ => 0.0,
+        }
+    }
+
+    pub fn print_summary(&self) {
+        let (operations, throttled) = self.counts();
+        println!(
+            "Paced {} operations at {:.0} msgs/sec (target {:.0}, burst {}), {} held back",
+            operations,
+            self.achieved_rate(),
+            self.limit.per_second,
+            self.limit.burst,
+            throttled
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_limit() {
+        assert_eq!(RateLimit::from_str("50000"), Ok(RateLimit { per_second: 50_000.0, burst: 1 }));
+        assert_eq!(RateLimit::from_str("2.5:10"), Ok(RateLimit { per_second: 2.5, burst: 10 }));
+        for invalid in ["0", "-5", "fast", "100:0", "100:x", "inf"] {
+            assert!(RateLimit::from_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_bursts_then_holds_to_the_rate() {
+        // 1000 per second is one every millisecond, three at once.
+        let mut governor = SpeedGovernor::new(RateLimit { per_second: 1_000.0, burst: 3 });
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let delays: Vec<Duration> = (0..5).map(|_| governor.delay(start)).collect();
+        assert_eq!(delays, [Duration::ZERO, Duration::ZERO, Duration::ZERO, ms(1), ms(2)]);
+        // Idle long enough to refill the whole bucket, but no more.
+        let later = start + ms(100);
+        let delays: Vec<Duration> = (0..4).map(|_| governor.delay(later)).collect();
+        assert_eq!(delays, [Duration::ZERO, Duration::ZERO, Duration::ZERO, ms(1)]);
+        assert_eq!(governor.counts(), (9, 3));
+        assert_eq!(governor.achieved_rate(), 8.0 / 0.101);
+    }
+
+    #[test]
+    fn test_wait_keeps_a_sustained_rate() {
+        let mut governor = SpeedGovernor::new(RateLimit { per_second: 2_000.0, burst: 1 });
+        let start = Instant::now();
+        for _ in 0..41 {
+            governor.wait();
+        }
+        // 40 intervals of half a millisecond.
+        assert!(start.elapsed() >= Duration::from_millis(20), "{:?}", start.elapsed());
+    }
+}
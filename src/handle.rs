@@ -0,0 +1,199 @@
+use crate::clients::ClientId;
+use crate::engine::{MatchingEngine, Submission};
+use crate::logging::SimLogger;
+use crate::order::Order;
+use crate::utils::MatchingEngineError;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+type Query = Box<dyn FnOnce(&MatchingEngine) + Send>;
+
+enum Command {
+    Submit {
+        order: Order,
+        owner: Option<ClientId>,
+        reply: oneshot::Sender<Result<Submission, MatchingEngineError>>,
+    },
+    Cancel {
+        order_id: Uuid,
+        instrument: String,
+        client: Option<ClientId>,
+        reply: oneshot::Sender<Result<Order, MatchingEngineError>>,
+    },
+    Query(Query),
+}
+
+/// A cloneable, thread-safe way to drive an engine that lives on its own
+/// thread. Commands are applied one at a time in the order they reach the
+/// queue, so the book is never shared or locked; each method returns a future
+/// that resolves once the engine thread has applied the command.
+#[derive(Clone)]
+pub struct EngineHandle {
+    commands: Sender<Command>,
+}
+
+/// The engine thread. It runs until every [`EngineHandle`] is dropped.
+pub struct EngineThread {
+    handle: JoinHandle<MatchingEngine>,
+}
+
+impl EngineThread {
+    /// Waits for the engine thread to drain its queue, flush held orders and
+    /// finalize the logger, and returns the engine.
+    pub fn join(self) -> MatchingEngine {
+        self.handle.join().expect("the engine thread panicked")
+    }
+}
+
+impl EngineHandle {
+    /// Moves `engine` and `logger` onto a new thread and returns the handle
+    /// used to reach them.
+    pub fn spawn(engine: MatchingEngine, logger: Box<dyn SimLogger>) -> (EngineHandle, EngineThread) {
+        let (commands, receiver) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("matching-engine".to_string())
+            .spawn(move || run(engine, logger, receiver))
+            .expect("failed to spawn the engine thread");
+        (EngineHandle { commands }, EngineThread { handle })
+    }
+
+    /// Submits `order` on behalf of `owner`, if any.
+    pub async fn submit(&self, order: Order, owner: Option<ClientId>) -> Result<Submission, MatchingEngineError> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Submit { order, owner, reply })?;
+        response.await.map_err(|_| MatchingEngineError::EngineStopped)?
+    }
+
+    /// Cancels `order_id`, checking that `client` owns it when one is given.
+    pub async fn cancel(
+        &self,
+        order_id: Uuid,
+        instrument: impl Into<String>,
+        client: Option<ClientId>,
+    ) -> Result<Order, MatchingEngineError> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Cancel { order_id, instrument: instrument.into(), client, reply })?;
+        response.await.map_err(|_| MatchingEngineError::EngineStopped)?
+    }
+
+    /// Runs `query` against the engine between two commands and returns what
+    /// it computed, e.g. `handle.query(|engine| engine.resting_orders("SOFI"))`.
+    pub async fn query<R, F>(&self, query: F) -> Result<R, MatchingEngineError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&MatchingEngine) -> R + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Query(Box::new(move |engine| {
+            let _ = reply.send(query(engine));
+        })))?;
+        response.await.map_err(|_| MatchingEngineError::EngineStopped)
+    }
+
+    fn send(&self, command: Command) -> Result<(), MatchingEngineError> {
+        self.commands.send(command).map_err(|_| MatchingEngineError::EngineStopped)
+    }
+}
+
+fn run(mut engine: MatchingEngine, mut logger: Box<dyn SimLogger>, commands: Receiver<Command>) -> MatchingEngine {
+    for command in commands.iter() {
+        // As in a simulation run, expiries and held orders that are due go first.
+        engine.expire_orders(&mut logger);
+        engine.cancel_timed_out_orders(&mut logger);
+        engine.release_delayed_orders(&mut logger);
+        engine.run_due_auctions(&mut logger);
+        match command {
+            Command::Submit { order, owner, reply } => {
+                logger.log_order_submission(&order);
+                let _ = reply.send(engine.submit_order(order, owner, &mut logger));
+            }
+            Command::Cancel { order_id, instrument, client, reply } => {
+                let canceled = match client {
+                    Some(client) => engine.cancel_client_order(&client, &order_id, &instrument, &mut logger),
+                    None => engine.cancel_order_by_id(&order_id, &instrument),
+                };
+                logger.log_order_cancel(&order_id, canceled.is_ok());
+                engine.log_repricing(&mut logger);
+                let _ = reply.send(canceled);
+            }
+            Command::Query(query) => query(&engine),
+        }
+    }
+    engine.flush_delayed_orders(&mut logger);
+    engine.flush_auctions(&mut logger);
+    logger.finalize();
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::logging::{create_logger, LoggingMode};
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    fn spawn_engine() -> (EngineHandle, EngineThread) {
+        let engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        EngineHandle::spawn(engine, create_logger(LoggingMode::Baseline))
+    }
+
+    #[test]
+    fn test_handles_on_several_threads_share_one_book() {
+        let (handle, engine_thread) = spawn_engine();
+        let producers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    for _ in 0..25 {
+                        let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(1));
+                        assert!(matches!(block_on(handle.submit(order, None)), Ok(Submission::New(..))));
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let resting = block_on(handle.query(|engine| engine.resting_orders("SOFI").len())).unwrap();
+        assert_eq!(resting, 100);
+        drop(handle);
+        let engine = engine_thread.join();
+        assert_eq!(engine.order_book("SOFI").unwrap().best_bid(), Some(dec!(10)));
+    }
+
+    #[test]
+    fn test_submit_cancel_and_errors_come_back_through_the_future() {
+        let (handle, engine_thread) = spawn_engine();
+        block_on(async {
+            let resting = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5));
+            let resting_id = resting.order_id;
+            handle.submit(resting, Some(ClientId::new("alice"))).await.unwrap();
+            let taker = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(2));
+            let Submission::New(trades, _) = handle.submit(taker, None).await.unwrap() else {
+                panic!("market orders are never held here");
+            };
+            assert_eq!(trades[0].quantity, dec!(2));
+
+            let foreign = handle.cancel(resting_id, "SOFI", Some(ClientId::new("bob"))).await;
+            assert!(matches!(foreign, Err(MatchingEngineError::NotOrderOwner { .. })));
+            let canceled = handle.cancel(resting_id, "SOFI", Some(ClientId::new("alice"))).await.unwrap();
+            assert_eq!(canceled.remaining_quantity, dec!(3));
+            assert!(matches!(handle.cancel(resting_id, "SOFI", None).await, Err(MatchingEngineError::OrderNotFound(_))));
+            let unknown = Order::new_limit(Uuid::new_v4(), "TSLA".to_string(), Side::Buy, dec!(1), dec!(1));
+            assert!(matches!(handle.submit(unknown, None).await, Err(MatchingEngineError::MarketNotFound(_))));
+        });
+
+        let stale = handle.clone();
+        drop(handle);
+        drop(stale);
+        engine_thread.join();
+    }
+}
@@ -0,0 +1,188 @@
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Source of the ids the engine assigns to trades.
+pub trait IdGenerator: Send {
+    fn next_trade_id(&mut self) -> Uuid;
+
+    /// The id of the trade numbered `sequence` on the instrument the engine
+    /// knows as `instrument_id`. By default just the next id.
+    fn trade_id(&mut self, _instrument_id: u32, _sequence: u64) -> Uuid {
+        self.next_trade_id()
+    }
+}
+
+/// Random v4 UUIDs, the default with the `uuid-v4` feature.
+#[cfg(feature = "uuid-v4")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomIds;
+
+#[cfg(feature = "uuid-v4")]
+impl IdGenerator for RandomIds {
+    fn next_trade_id(&mut self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+/// Deterministic ids: the seed fills the upper 64 bits and a counter the lower
+/// 64 bits, so two runs with the same seed produce identical trade ids.
+#[derive(Clone, Copy, Debug)]
+pub struct SequentialIds {
+    seed: u64,
+    next: u64,
+}
+
+impl SequentialIds {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, next: 1 }
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn next_trade_id(&mut self) -> Uuid {
+        let id = Uuid::from_u128(((self.seed as u128) << 64) | self.next as u128);
+        self.next += 1;
+        id
+    }
+}
+
+/// Trade ids that are the instrument's trade sequence in UUID form: the
+/// engine's instrument id in the upper 64 bits and the trade's sequence
+/// number in the lower 64, so an instrument's ids sort in trade order and
+/// [`InstrumentSequenceIds::decode`] gets both back. Ids asked for outside
+/// an instrument's sequence count up under an upper half no instrument id
+/// reaches.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InstrumentSequenceIds {
+    next: u64,
+}
+
+impl InstrumentSequenceIds {
+    const OTHER: u128 = (u64::MAX as u128) << 64;
+
+    /// The instrument id and sequence number of a trade id this generator
+    /// made, or `None` for an id it did not.
+    pub fn decode(id: Uuid) -> Option<(u32, u64)> {
+        let value = id.as_u128();
+        let instrument_id = u32::try_from(value >> 64).ok()?;
+        let sequence = value as u64;
+        (sequence > 0).then_some((instrument_id, sequence))
+    }
+}
+
+impl IdGenerator for InstrumentSequenceIds {
+    fn next_trade_id(&mut self) -> Uuid {
+        self.next += 1;
+        Uuid::from_u128(Self::OTHER | self.next as u128)
+    }
+
+    fn trade_id(&mut self, instrument_id: u32, sequence: u64) -> Uuid {
+        Uuid::from_u128(((instrument_id as u128) << 64) | sequence as u128)
+    }
+}
+
+/// The engine's id for `instrument`: an FNV-1a hash of its name, so the
+/// same instrument gets the same id on every run whatever order markets are
+/// created in.
+pub fn instrument_id(instrument: &str) -> u32 {
+    instrument.bytes().fold(0x811c_9dc5, |hash: u32, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// Ids of the orders the engine sends on its own, such as liquidation
+/// close-outs. They count up under their own upper half, so they never use
+/// up or repeat a trade id whatever the trade id strategy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EngineOrderIds {
+    next: u64,
+}
+
+impl EngineOrderIds {
+    const PREFIX: u128 = ((u64::MAX - 1) as u128) << 64;
+
+    pub fn next_order_id(&mut self) -> Uuid {
+        self.next += 1;
+        Uuid::from_u128(Self::PREFIX | self.next as u128)
+    }
+}
+
+/// Which [`IdGenerator`] a run uses. `Instrument` is the default, since its
+/// ids survive a snapshot or checkpoint; random ids need the `uuid-v4`
+/// feature.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TradeIdStrategy {
+    #[cfg(feature = "uuid-v4")]
+    Random,
+    Sequential { seed: u64 },
+    #[default]
+    Instrument,
+}
+
+impl TradeIdStrategy {
+    pub fn generator(self) -> Box<dyn IdGenerator> {
+        match self {
+            #[cfg(feature = "uuid-v4")]
+            TradeIdStrategy::Random => Box::new(RandomIds),
+            TradeIdStrategy::Sequential { seed } => Box::new(SequentialIds::new(seed)),
+            TradeIdStrategy::Instrument => Box::new(InstrumentSequenceIds::default()),
+        }
+    }
+}
+
+impl FromStr for TradeIdStrategy {
+    type Err = String;
+
+    /// Parses `random`, `sequential:<seed>` or `instrument`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            #[cfg(feature = "uuid-v4")]
+            None if s == "random" => Ok(TradeIdStrategy::Random),
+            #[cfg(not(feature = "uuid-v4"))]
+            None if s == "random" => Err("Random trade ids need the uuid-v4 feature".to_string()),
+            None if s == "instrument" => Ok(TradeIdStrategy::Instrument),
+            Some(("sequential", seed)) => seed
+                .parse()
+                .map(|seed| TradeIdStrategy::Sequential { seed })
+                .map_err(|_| format!("Invalid trade id seed '{}'", seed)),
+            _ => Err(format!("Invalid trade id strategy '{}', expected random, sequential:<seed> or instrument", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequential_ids_are_deterministic_per_seed() {
+        let mut a = SequentialIds::new(7);
+        let mut b = SequentialIds::new(7);
+        let first = a.next_trade_id();
+        assert_eq!(first, b.next_trade_id());
+        assert_ne!(first, a.next_trade_id());
+        assert_ne!(first, SequentialIds::new(8).next_trade_id());
+    }
+
+    #[test]
+    fn test_instrument_sequence_ids_decode_to_their_trade() {
+        let mut ids = InstrumentSequenceIds::default();
+        let id = ids.trade_id(3, 17);
+        assert_eq!(InstrumentSequenceIds::decode(id), Some((3, 17)));
+        assert!(ids.trade_id(3, 16) < id);
+        assert_eq!(InstrumentSequenceIds::decode(ids.next_trade_id()), None);
+        assert_eq!("sequential:7".parse(), Ok(TradeIdStrategy::Sequential { seed: 7 }));
+        assert_eq!("instrument".parse(), Ok(TradeIdStrategy::Instrument));
+        assert!("sequential".parse::<TradeIdStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_instrument_ids_are_the_default() {
+        assert_eq!(TradeIdStrategy::default(), TradeIdStrategy::Instrument);
+        assert_eq!("random".parse::<TradeIdStrategy>().is_ok(), cfg!(feature = "uuid-v4"));
+    }
+
+    #[test]
+    fn test_instrument_ids_depend_only_on_the_name() {
+        assert_ne!(instrument_id("SOFI"), instrument_id("AAPL"));
+        assert_eq!(instrument_id(""), 0x811c_9dc5);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::import::Imported;
+use crate::operation::{NewOrder, SimOp};
+use crate::order::OrderAttributes;
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::error::Error;
+use std::io::Read;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const MAKER_NAMESPACE: u64 = 1;
+const TAKER_NAMESPACE: u64 = 2;
+const DEPTH_NAMESPACE: u64 = 3;
+
+/// Translates a Binance aggTrades dump (agg id, price, quantity, first trade
+/// id, last trade id, time, is buyer maker[, is best match]) into operations.
+///
+/// Each aggregate trade becomes a resting limit order for the maker followed
+/// by a market order for the taker, so replaying them reproduces the trade
+/// tape. A header row, present in newer dumps, is skipped.
+pub fn import_agg_trades<R: Read>(source: R, instrument: &str) -> Result<Imported, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(source);
+    let mut imported = Imported::default();
+
+    for (index, result) in reader.records().enumerate() {
+        let record = result?;
+        let line = index + 1;
+        let field = |i: usize| record.get(i).map(str::trim).ok_or_else(|| format!("line {}: missing field {}", line, i + 1));
+
+        let Ok(agg_id) = field(0)?.parse::<u64>() else {
+            if index == 0 {
+                continue;
+            }
+            return Err(format!("line {}: invalid aggregate trade id", line).into());
+        };
+        let price = Decimal::from_str(field(1)?).map_err(|_| format!("line {}: invalid price", line))?;
+        let quantity = Decimal::from_str(field(2)?).map_err(|_| format!("line {}: invalid quantity", line))?;
+        let maker_side = match field(6)?.to_lowercase().as_str() {
+            "true" => Side::Buy,
+            "false" => Side::Sell,
+            other => return Err(format!("line {}: invalid is_buyer_maker '{}'", line, other).into()),
+        };
+        let taker_side = match maker_side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+
+        imported.operations.push(SimOp::New(NewOrder {
+            order_id: Uuid::from_u64_pair(MAKER_NAMESPACE, agg_id),
+            instrument: instrument.to_string(),
+            side: maker_side,
+            quantity,
+            price: Some(price),
+            client: None,
+            client_order_id: None,
+            peg: None,
+            discretion: None,
+            attributes: OrderAttributes::default(),
+            expire_at: None,
+            cancel_after: None,
+            stop_price: None,
+        }));
+        imported.operations.push(SimOp::New(NewOrder {
+            order_id: Uuid::from_u64_pair(TAKER_NAMESPACE, agg_id),
+            instrument: instrument.to_string(),
+            side: taker_side,
+            quantity,
+            price: None,
+            client: None,
+            client_order_id: None,
+            peg: None,
+            discretion: None,
+            attributes: OrderAttributes::default(),
+            expire_at: None,
+            cancel_after: None,
+            stop_price: None,
+        }));
+    }
+
+    Ok(imported)
+}
+
+#[derive(Deserialize)]
+struct DepthSnapshot {
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
+}
+
+/// Translates a Binance REST depth snapshot (`{"bids": [[price, qty], ...],
+/// "asks": [...]}`) into one resting limit order per level, seeding the book
+/// before a trade replay.
+pub fn import_depth_snapshot<R: Read>(source: R, instrument: &str) -> Result<Imported, Box<dyn Error>> {
+    let snapshot: DepthSnapshot = serde_json::from_reader(source)?;
+    let mut imported = Imported::default();
+
+    let levels = snapshot
+        .bids
+        .iter()
+        .map(|level| (Side::Buy, level))
+        .chain(snapshot.asks.iter().map(|level| (Side::Sell, level)));
+    for (index, (side, (price, quantity))) in levels.enumerate() {
+        let price = Decimal::from_str(price).map_err(|_| format!("invalid depth price '{}'", price))?;
+        let quantity = Decimal::from_str(quantity).map_err(|_| format!("invalid depth quantity '{}'", quantity))?;
+        if quantity.is_zero() {
+            imported.skipped += 1;
+            continue;
+        }
+        imported.operations.push(SimOp::New(NewOrder {
+            order_id: Uuid::from_u64_pair(DEPTH_NAMESPACE, index as u64),
+            instrument: instrument.to_string(),
+            side,
+            quantity,
+            price: Some(price),
+            client: None,
+            client_order_id: None,
+            peg: None,
+            discretion: None,
+            attributes: OrderAttributes::default(),
+            expire_at: None,
+            cancel_after: None,
+            stop_price: None,
+        }));
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_import_agg_trades_with_and_without_header() {
+        let body = "\
+26129,0.01633102,4.70443515,27781,27781,1498793709153,true,true
+26130,0.01633103,1.00000000,27782,27783,1498793709200,false,true
+";
+        let with_header = format!("agg_trade_id,price,quantity,first_trade_id,last_trade_id,transact_time,is_buyer_maker\n{}", body);
+
+        for input in [body.to_string(), with_header] {
+            let imported = import_agg_trades(input.as_bytes(), "BTCUSDT").unwrap();
+            assert_eq!(imported.operations.len(), 4);
+            let SimOp::New(maker) = &imported.operations[0] else { panic!("expected a new order") };
+            assert_eq!((maker.side, maker.price), (Side::Buy, Some(dec!(0.01633102))));
+            let SimOp::New(taker) = &imported.operations[1] else { panic!("expected a new order") };
+            assert_eq!((taker.side, taker.price, taker.quantity), (Side::Sell, None, dec!(4.70443515)));
+        }
+    }
+
+    #[test]
+    fn test_import_depth_snapshot() {
+        let json = r#"{"lastUpdateId":1027024,"bids":[["4.00000000","431.00000000"],["3.99","0"]],"asks":[["4.00000200","12.00000000"]]}"#;
+        let imported = import_depth_snapshot(json.as_bytes(), "BNBBTC").unwrap();
+
+        assert_eq!(imported.operations.len(), 2);
+        assert_eq!(imported.skipped, 1);
+        let SimOp::New(ask) = &imported.operations[1] else { panic!("expected a new order") };
+        assert_eq!((ask.side, ask.price, ask.quantity), (Side::Sell, Some(dec!(4.000002)), dec!(12)));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::import::Imported;
+use crate::operation::{NewOrder, SimOp};
+use crate::order::OrderAttributes;
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::io::Read;
+use uuid::Uuid;
+
+/// LOBSTER prices are integers in units of 1/10000 of a dollar.
+const PRICE_SCALE: u32 = 4;
+
+const NEW_LIMIT: u8 = 1;
+const PARTIAL_CANCEL: u8 = 2;
+const DELETE: u8 = 3;
+const VISIBLE_EXECUTION: u8 = 4;
+
+/// Translates a LOBSTER message file (time, event type, order id, size,
+/// price, direction; no header) into operations.
+///
+/// New limit orders and deletions map directly. A visible execution becomes a
+/// market order from the opposite side, so the engine reproduces the trade
+/// against the resting order. Partial cancels, hidden executions, cross
+/// trades and halts have no engine equivalent and are skipped.
+pub fn import_messages<R: Read>(source: R, instrument: &str) -> Result<Imported, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(source);
+    let mut imported = Imported::default();
+
+    for (index, result) in reader.records().enumerate() {
+        let record = result?;
+        let line = index + 1;
+        let field = |i: usize| record.get(i).map(str::trim).ok_or_else(|| format!("line {}: missing field {}", line, i + 1));
+
+        let event_type: u8 = field(1)?.parse().map_err(|_| format!("line {}: invalid event type", line))?;
+        let order_id: u64 = field(2)?.parse().map_err(|_| format!("line {}: invalid order id", line))?;
+        let size: Decimal = field(3)?.parse().map_err(|_| format!("line {}: invalid size", line))?;
+        let price: i64 = field(4)?.parse().map_err(|_| format!("line {}: invalid price", line))?;
+        let side = match field(5)? {
+            "1" => Side::Buy,
+            "-1" => Side::Sell,
+            other => return Err(format!("line {}: invalid direction '{}'", line, other).into()),
+        };
+
+        let operation = match event_type {
+            NEW_LIMIT => SimOp::New(NewOrder {
+                order_id: order_uuid(order_id),
+                instrument: instrument.to_string(),
+                side,
+                quantity: size,
+                price: Some(Decimal::new(price, PRICE_SCALE)),
+                client: None,
+                client_order_id: None,
+                peg: None,
+                discretion: None,
+                attributes: OrderAttributes::default(),
+                expire_at: None,
+                cancel_after: None,
+                stop_price: None,
+            }),
+            DELETE => SimOp::Cancel { instrument: instrument.to_string(), order_id: order_uuid(order_id), client: None },
+            VISIBLE_EXECUTION => SimOp::New(NewOrder {
+                order_id: Uuid::from_u64_pair(u64::MAX, line as u64),
+                instrument: instrument.to_string(),
+                side: opposite(side),
+                quantity: size,
+                price: None,
+                client: None,
+                client_order_id: None,
+                peg: None,
+                discretion: None,
+                attributes: OrderAttributes::default(),
+                expire_at: None,
+                cancel_after: None,
+                stop_price: None,
+            }),
+            PARTIAL_CANCEL | 5..=7 => {
+                imported.skipped += 1;
+                continue;
+            }
+            other => return Err(format!("line {}: unknown event type {}", line, other).into()),
+        };
+        imported.operations.push(operation);
+    }
+
+    Ok(imported)
+}
+
+/// LOBSTER order ids are integers; the same id always maps to the same UUID
+/// so deletions find the order they refer to.
+fn order_uuid(order_id: u64) -> Uuid {
+    Uuid::from_u64_pair(0, order_id)
+}
+
+fn opposite(side: Side) -> Side {
+    match side {
+        Side::Buy => Side::Sell,
+        Side::Sell => Side::Buy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_import_lobster_messages() {
+        let messages = "\
+34200.004,1,16113575,18,5853300,1
+34200.025,1,16120456,50,5859400,-1
+34200.190,4,16120456,20,5859400,-1
+34200.201,2,16113575,8,5853300,1
+34200.350,3,16113575,10,5853300,1
+";
+        let imported = import_messages(messages.as_bytes(), "AAPL").unwrap();
+
+        assert_eq!(imported.operations.len(), 4);
+        assert_eq!(imported.skipped, 1);
+        let SimOp::New(first) = &imported.operations[0] else { panic!("expected a new order") };
+        assert_eq!(first.price, Some(dec!(585.33)));
+        assert_eq!(first.side, Side::Buy);
+        let SimOp::New(execution) = &imported.operations[2] else { panic!("expected a market order") };
+        assert_eq!(execution.side, Side::Buy);
+        assert_eq!(execution.price, None);
+        assert_eq!(imported.operations[3], SimOp::Cancel { instrument: "AAPL".to_string(), order_id: first.order_id, client: None });
+    }
+
+    #[test]
+    fn test_invalid_lobster_row_reports_line() {
+        let err = import_messages("34200.0,1,1,10,100,2\n".as_bytes(), "AAPL").unwrap_err();
+        assert!(err.to_string().starts_with("line 1"));
+    }
+}
@@ -0,0 +1,86 @@
+pub mod binance;
+pub mod lobster;
+
+use crate::operation::SimOp;
+use std::error::Error;
+use std::fs::File;
+use std::str::FromStr;
+
+/// Operations translated from a public market data format, so real order flow
+/// can be replayed through the engine, and the number of source records that
+/// have no equivalent in the engine.
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub operations: Vec<SimOp>,
+    pub skipped: usize,
+}
+
+impl Imported {
+    pub fn extend(&mut self, other: Imported) {
+        self.operations.extend(other.operations);
+        self.skipped += other.skipped;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// LOBSTER message file.
+    Lobster,
+    /// Binance aggTrades CSV dump.
+    BinanceAggTrades,
+    /// Binance REST depth snapshot (JSON).
+    BinanceDepth,
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lobster" => Ok(Self::Lobster),
+            "binance-trades" | "aggtrades" => Ok(Self::BinanceAggTrades),
+            "binance-depth" | "depth" => Ok(Self::BinanceDepth),
+            _ => Err(format!("Unknown import format '{}'", s)),
+        }
+    }
+}
+
+/// A `<format>:<path>` import request from the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSpec {
+    pub format: ImportFormat,
+    pub path: String,
+}
+
+impl FromStr for ImportSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid import '{}', expected <format>:<path>", s))?;
+        Ok(ImportSpec { format: ImportFormat::from_str(format)?, path: path.to_string() })
+    }
+}
+
+pub fn import_file(spec: &ImportSpec, instrument: &str) -> Result<Imported, Box<dyn Error>> {
+    let file = File::open(&spec.path).map_err(|e| format!("{}: {}", spec.path, e))?;
+    match spec.format {
+        ImportFormat::Lobster => lobster::import_messages(file, instrument),
+        ImportFormat::BinanceAggTrades => binance::import_agg_trades(file, instrument),
+        ImportFormat::BinanceDepth => binance::import_depth_snapshot(file, instrument),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_import_spec() {
+        let spec = ImportSpec::from_str("lobster:data/AAPL_message.csv").unwrap();
+        assert_eq!(spec.format, ImportFormat::Lobster);
+        assert_eq!(spec.path, "data/AAPL_message.csv");
+        assert_eq!(ImportSpec::from_str("depth:snap.json").unwrap().format, ImportFormat::BinanceDepth);
+        assert!(ImportSpec::from_str("lobster").is_err());
+        assert!(ImportSpec::from_str("itch:file.bin").is_err());
+    }
+}
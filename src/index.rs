@@ -0,0 +1,126 @@
+//! Index prices computed from the books of constituent instruments. An
+//! instrument that follows an index takes it as its reference price: the
+//! index price of a perpetual, the settlement price of an option, and the
+//! centre of a price band.
+
+use crate::fixed::Fixed;
+use crate::orderbook::OrderBook;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Which price of a constituent's book goes into an index. Either falls
+/// back to the other while it is missing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexSource {
+    #[default]
+    LastTrade,
+    Mid,
+}
+
+impl IndexSource {
+    pub fn price(&self, book: &OrderBook) -> Option<Decimal> {
+        match self {
+            IndexSource::LastTrade => book.last_trade_price().or_else(|| book.mid_price()),
+            IndexSource::Mid => book.mid_price().or_else(|| book.last_trade_price()),
+        }
+    }
+}
+
+/// A weighted average of the prices of its constituents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub constituents: Vec<(String, Decimal)>,
+    pub source: IndexSource,
+}
+
+impl IndexDefinition {
+    pub fn new(name: impl Into<String>) -> Self {
+        IndexDefinition { name: name.into(), constituents: Vec::new(), source: IndexSource::default() }
+    }
+
+    pub fn constituent(mut self, instrument: impl Into<String>, weight: Decimal) -> Self {
+        self.constituents.push((instrument.into(), weight));
+        self
+    }
+
+    pub fn source(mut self, source: IndexSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || self.constituents.is_empty() {
+            return Err("an index needs a name and at least one constituent".to_string());
+        }
+        if self.constituents.iter().any(|(_, weight)| *weight <= Decimal::ZERO) {
+            return Err(format!("index '{}' has a constituent without a positive weight", self.name));
+        }
+        Ok(())
+    }
+
+    /// The weighted average of the constituents' prices, rounded to the
+    /// engine's precision. `None` until every constituent has a price.
+    pub fn compute(&self, price: impl Fn(&str) -> Option<Decimal>) -> Option<Decimal> {
+        let mut weighted = Decimal::ZERO;
+        let mut weights = Decimal::ZERO;
+        for (instrument, weight) in &self.constituents {
+            weighted += price(instrument)? * weight;
+            weights += weight;
+        }
+        Some((weighted / weights).round_dp(Fixed::SCALE))
+    }
+}
+
+impl FromStr for IndexDefinition {
+    type Err = String;
+
+    /// Parses `<name>=<instrument>[*<weight>][,<instrument>[*<weight>]...][@trade|@mid]`,
+    /// e.g. `TECH=SOFI*2,NVO@mid`. Weights default to 1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid index '{}', expected <name>=<instrument>[*<weight>],...[@trade|@mid]", s);
+        let (name, rest) = s.split_once('=').ok_or_else(invalid)?;
+        let (constituents, source) = match rest.rsplit_once('@') {
+            Some((constituents, "trade")) => (constituents, IndexSource::LastTrade),
+            Some((constituents, "mid")) => (constituents, IndexSource::Mid),
+            Some(_) => return Err(invalid()),
+            None => (rest, IndexSource::LastTrade),
+        };
+        let mut index = IndexDefinition::new(name).source(source);
+        for constituent in constituents.split(',') {
+            let (instrument, weight) = match constituent.split_once('*') {
+                Some((instrument, weight)) => (instrument, Decimal::from_str(weight).map_err(|_| invalid())?),
+                None => (constituent, Decimal::ONE),
+            };
+            if instrument.is_empty() {
+                return Err(invalid());
+            }
+            index = index.constituent(instrument, weight);
+        }
+        index.validate().map_err(|e| format!("Invalid index '{}': {}", s, e))?;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_index_is_the_weighted_average_once_every_constituent_is_priced() {
+        let index: IndexDefinition = "TECH=SOFI*3,NVO@mid".parse().unwrap();
+        assert_eq!(index.source, IndexSource::Mid);
+        assert_eq!(index.constituents, vec![("SOFI".to_string(), dec!(3)), ("NVO".to_string(), dec!(1))]);
+
+        let mut prices = HashMap::from([("SOFI", dec!(10))]);
+        assert_eq!(index.compute(|instrument| prices.get(instrument).copied()), None);
+        prices.insert("NVO", dec!(30));
+        assert_eq!(index.compute(|instrument| prices.get(instrument).copied()), Some(dec!(15)));
+
+        assert!("TECH=SOFI*0".parse::<IndexDefinition>().is_err());
+        assert!("TECH=SOFI@vwap".parse::<IndexDefinition>().is_err());
+        assert!("SOFI".parse::<IndexDefinition>().is_err());
+    }
+}
@@ -1,7 +1,73 @@
+#[macro_use]
+pub mod profiling;
+#[macro_use]
+pub mod phase_timing;
+pub mod spans;
+#[cfg(feature = "otel")]
+pub mod telemetry;
 pub mod order;
+pub mod operation;
 pub mod trade;
+pub mod trade_store;
 pub mod orderbook;
 pub mod utils;
 pub mod engine;
+#[cfg(feature = "csv")]
 pub mod simulation;
-pub mod logging;
\ No newline at end of file
+#[cfg(feature = "csv")]
+pub mod source;
+pub mod logging;
+#[cfg(feature = "csv")]
+pub mod snapshot;
+#[cfg(feature = "csv")]
+pub mod cli;
+pub mod affinity;
+pub mod clock;
+pub mod ids;
+#[cfg(feature = "csv")]
+pub mod import;
+pub mod config;
+#[cfg(feature = "csv")]
+pub mod control;
+pub mod events;
+pub mod export;
+#[cfg(feature = "csv")]
+pub mod gateway;
+pub mod market_data;
+#[cfg(feature = "csv")]
+pub mod report;
+pub mod accounting;
+pub mod clients;
+pub mod allocation;
+pub mod fixed;
+pub mod compact;
+#[cfg(feature = "csv")]
+pub mod admin;
+#[cfg(feature = "csv")]
+pub mod experiment;
+pub mod memory;
+pub mod handle;
+pub mod timer_wheel;
+pub mod positions;
+#[cfg(feature = "csv")]
+pub mod agents;
+pub mod markouts;
+pub mod market_quality;
+pub mod messaging;
+pub mod audit;
+pub mod comparison;
+#[cfg(feature = "csv")]
+pub mod checkpoint;
+pub mod venues;
+pub mod confirmations;
+pub mod stops;
+pub mod bands;
+pub mod clearing;
+pub mod margin;
+pub mod funding;
+pub mod options;
+pub mod index;
+pub mod publication;
+pub mod governor;
+pub mod shadow;
+pub mod generator;
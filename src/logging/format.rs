@@ -0,0 +1,96 @@
+//! Formats the timestamps that prefix every log line into a stack buffer.
+//! Formatting them through chrono's `format` allocated four times per line,
+//! more than everything else in a line together.
+
+use crate::clock::{Clock, SystemClock};
+use std::fmt;
+
+const LEN: usize = "YYYY-MM-DD HH:MM:SS.mmm".len();
+
+/// A UTC time formatted as `YYYY-MM-DD HH:MM:SS.mmm`, truncated to the
+/// millisecond.
+#[derive(Clone, Copy)]
+pub struct LogTime([u8; LEN]);
+
+impl LogTime {
+    /// Nanoseconds since the UNIX epoch, as in order and trade timestamps.
+    pub fn from_nanos(nanos: u64) -> Self {
+        let secs = nanos / 1_000_000_000;
+        let millis = (nanos % 1_000_000_000 / 1_000_000) as u32;
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let secs_of_day = (secs % 86_400) as u32;
+        let mut buf = *b"0000-00-00 00:00:00.000";
+        put(&mut buf[0..4], year as u32);
+        put(&mut buf[5..7], month);
+        put(&mut buf[8..10], day);
+        put(&mut buf[11..13], secs_of_day / 3600);
+        put(&mut buf[14..16], secs_of_day / 60 % 60);
+        put(&mut buf[17..19], secs_of_day % 60);
+        put(&mut buf[20..23], millis);
+        LogTime(buf)
+    }
+
+    pub fn now() -> Self {
+        Self::from_nanos(SystemClock.now_nanos())
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII digits and separators are ever written.
+        std::str::from_utf8(&self.0).expect("timestamp is ASCII")
+    }
+}
+
+impl fmt::Display for LogTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Writes `value` as zero-padded decimal digits filling `digits`.
+fn put(digits: &mut [u8], mut value: u32) {
+    for digit in digits.iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+}
+
+/// The proleptic Gregorian date of a day counted from 1970-01-01, after
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_matches_chrono() {
+        let day = 86_400 * 1_000_000_000u64;
+        let samples = [
+            0,
+            999_999_999,
+            1_700_000_000_123_456_789,
+            // Leap days, a century that is not a leap year and one that is.
+            951_782_400_000_000_000,
+            4_107_542_399_999_000_000,
+            4_107_542_400_000_000_000,
+            u64::from(u32::MAX) * 1_000_000_000,
+        ];
+        let stepped = (0..4000u64).map(|i| i * 20 * day + i * 1_234_567_891);
+        for nanos in samples.into_iter().chain(stepped) {
+            let expected = Utc.timestamp_nanos(nanos as i64).format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+            assert_eq!(LogTime::from_nanos(nanos).to_string(), expected, "{}", nanos);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::logging::log_methods::async_enum::{write_bust, write_cancel, write_filled, write_rejected, write_repriced, write_submission, write_trade};
+use crate::logging::logger_trait::SimLogger;
+use crate::logging::types::{FlushPolicy, LoggingMode, WorkerOptions};
+use crate::logging::worker::{LogWorker, WorkerReceiver, LoggerStats};
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use std::fs::File;
+use std::io::Write;
+use std::time::Instant;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// A throughput logger for runs where per-event latency is not the target.
+/// Events are formatted on the calling thread into a local buffer, and the
+/// whole buffer goes to the background thread in one channel send, and one
+/// file write, when the `FlushPolicy` says so. Events reach the file late,
+/// and a quiet engine leaves the tail buffered until the next event or
+/// `finalize`.
+pub struct AsyncBatchLogger {
+    worker: LogWorker<Vec<u8>>,
+    buffer: Vec<u8>,
+    buffered: usize,
+    oldest: Option<Instant>,
+    policy: FlushPolicy,
+}
+
+impl AsyncBatchLogger {
+    pub fn new(path: &str) -> Self {
+        Self::with_options(path, WorkerOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: WorkerOptions) -> Self {
+        let path_owned = path.to_string();
+
+        let worker = LogWorker::spawn(options, move |receiver: WorkerReceiver<Vec<u8>>| {
+            match File::create(&path_owned) {
+                Ok(file) => {
+                    let mut file = receiver.counted(file);
+                    while let Some(batch) = receiver.recv(options.wait) {
+                        if file.write_all(&batch).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Failed to create log file: {} ({})", path_owned, e),
+            }
+        });
+
+        Self { worker, buffer: Vec::new(), buffered: 0, oldest: None, policy: options.flush }
+    }
+
+    /// Counts the event just formatted into the buffer, which cannot fail,
+    /// and flushes if the policy is met.
+    fn count_event(&mut self) {
+        self.buffered += 1;
+        let oldest = *self.oldest.get_or_insert_with(Instant::now);
+        if self.buffered >= self.policy.events || oldest.elapsed() >= self.policy.interval {
+            self.flush();
+        }
+    }
+
+    /// Hands the buffered events to the background thread.
+    fn flush(&mut self) {
+        if self.buffered == 0 {
+            return;
+        }
+        let capacity = self.buffer.capacity();
+        let batch = std::mem::replace(&mut self.buffer, Vec::with_capacity(capacity));
+        self.worker.send_events(batch, self.buffered as u64);
+        self.buffered = 0;
+        self.oldest = None;
+    }
+}
+
+impl SimLogger for AsyncBatchLogger {
+    fn log_order_submission(&mut self, order: &Order) {
+        let _ = write_submission(&mut self.buffer, order);
+        self.count_event();
+    }
+
+    fn log_trade(&mut self, trade: &Trade) {
+        let _ = write_trade(&mut self.buffer, trade);
+        self.count_event();
+    }
+
+    fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
+        let _ = write_cancel(&mut self.buffer, order_id, success);
+        self.count_event();
+    }
+
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        let _ = write_filled(&mut self.buffer, order, execution);
+        self.count_event();
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        let _ = write_rejected(&mut self.buffer, order, reason);
+        self.count_event();
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        let _ = write_repriced(&mut self.buffer, order, old_price);
+        self.count_event();
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        let _ = write_bust(&mut self.buffer, trade);
+        self.count_event();
+    }
+
+    fn finalize(mut self: Box<Self>) -> Vec<LoggerStats> {
+        self.flush();
+        vec![LoggerStats { logger: LoggingMode::AsyncBatch, worker: self.worker.shutdown() }]
+    }
+}
+
+impl Drop for AsyncBatchLogger {
+    /// Hands over what is still buffered, so the worker writes it before it
+    /// is joined, even when the run unwinds past `finalize`.
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::log_methods::capture::log_every_event;
+    use std::time::Duration;
+
+    #[test]
+    fn test_events_are_flushed_in_batches_and_on_finalize() {
+        let path = std::env::temp_dir().join(format!("batch_{}.log", Uuid::new_v4()));
+        let policy = FlushPolicy { events: 4, interval: Duration::from_secs(3600) };
+        let mut logger = AsyncBatchLogger::with_options(path.to_str().unwrap(), WorkerOptions { flush: policy, ..Default::default() });
+        for _ in 0..5 {
+            logger.log_order_cancel(&Uuid::new_v4(), true);
+        }
+        assert_eq!(logger.buffered, 1);
+
+        log_every_event(Box::new(logger));
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.lines().count(), 12);
+        assert_eq!(contents.lines().filter(|line| line.contains("ORDER CANCEL")).count(), 6);
+    }
+
+    #[test]
+    fn test_interval_flushes_a_partial_batch() {
+        let path = std::env::temp_dir().join(format!("batch_{}.log", Uuid::new_v4()));
+        let policy = FlushPolicy { events: 1000, interval: Duration::ZERO };
+        let mut logger = AsyncBatchLogger::with_options(path.to_str().unwrap(), WorkerOptions { flush: policy, ..Default::default() });
+        logger.log_order_cancel(&Uuid::new_v4(), false);
+        assert_eq!(logger.buffered, 0);
+        let stats = Box::new(logger).finalize();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(contents.contains("already filled"));
+        assert_eq!(stats[0].logger, LoggingMode::AsyncBatch);
+        assert_eq!((stats[0].worker.events, stats[0].worker.bytes_written), (1, contents.len() as u64));
+    }
+}
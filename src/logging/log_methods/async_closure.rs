@@ -1,34 +1,38 @@
 use crate::logging::logger_trait::SimLogger;
+use crate::logging::types::{ExecutionFields, LoggingMode, WorkerOptions};
+use crate::logging::worker::{CountedWriter, LogWorker, WorkerReceiver, LoggerStats};
 use crate::order::Order;
-use crate::trade::Trade;
-use chrono::{TimeZone, Utc};
+use crate::trade::{Execution, Trade};
+use crate::logging::format::LogTime;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::sync::mpsc::{self, Sender};
-use std::thread::{self, JoinHandle};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
-type LogClosure = Box<dyn FnOnce(&mut BufWriter<File>) + Send>;
+type LogWriter = BufWriter<CountedWriter<File>>;
+type LogClosure = Box<dyn FnOnce(&mut LogWriter) + Send>;
 
 /// An advanced asynchronous logger that offloads both I/O and string formatting.
 /// It works by sending a closure (the "instructions" for logging) to a
 /// dedicated background thread, which then executes the closure to perform
 /// the expensive work away from the main application thread.
 pub struct AsyncClosureLogger {
-    sender: Sender<LogClosure>,
-    handle: Option<JoinHandle<()>>,
+    worker: LogWorker<LogClosure>,
 }
 
 impl AsyncClosureLogger {
     pub fn new(path: &str) -> Self {
-        let (sender, receiver) = mpsc::channel::<LogClosure>();
+        Self::with_options(path, WorkerOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: WorkerOptions) -> Self {
         let path_owned = path.to_string();
 
-        let handle = thread::spawn(move || {
+        let worker = LogWorker::spawn(options, move |receiver: WorkerReceiver<LogClosure>| {
             if let Ok(file) = File::create(&path_owned) {
-                let mut writer = BufWriter::new(file);
+                let mut writer = BufWriter::new(receiver.counted(file));
 
-                for log_closure in receiver.iter() {
+                while let Some(log_closure) = receiver.recv(options.wait) {
                     log_closure(&mut writer);
                 }
                 let _ = writer.flush();
@@ -37,22 +41,19 @@ impl AsyncClosureLogger {
             }
         });
 
-        Self {
-            sender,
-            handle: Some(handle),
-        }
+        Self { worker }
     }
 }
 
 impl SimLogger for AsyncClosureLogger {
     fn log_order_submission(&mut self, order: &Order) {
         let order_data = order.clone();
-        let log_closure = move |writer: &mut BufWriter<File>| {
-            let dt = Utc.timestamp_nanos(order_data.timestamp as i64);
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::from_nanos(order_data.timestamp);
             let _ = writeln!(
                 writer,
                 "{} | ORDER RECEIVED: id={}, instrument={}, side={:?}, type={:?}, qty={}, price={}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                dt,
                 order_data.order_id,
                 order_data.instrument,
                 order_data.side,
@@ -61,17 +62,17 @@ impl SimLogger for AsyncClosureLogger {
                 order_data.price.unwrap_or_default()
             );
         };
-        let _ = self.sender.send(Box::new(log_closure));
+        self.worker.send(Box::new(log_closure));
     }
 
     fn log_trade(&mut self, trade: &Trade) {
         let trade_data = trade.clone();
-        let log_closure = move |writer: &mut BufWriter<File>| {
-            let dt = Utc.timestamp_nanos(trade_data.timestamp as i64);
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::from_nanos(trade_data.timestamp);
             let _ = writeln!(
                 writer,
                 "{} | TRADE EXECUTED: id={}, instrument={}, price={}, qty={}, taker_side={:?}, buy_order_id={}, sell_order_id={}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                dt,
                 trade_data.trade_id,
                 trade_data.instrument,
                 trade_data.price,
@@ -81,13 +82,13 @@ impl SimLogger for AsyncClosureLogger {
                 trade_data.sell_order_id
             );
         };
-        let _ = self.sender.send(Box::new(log_closure));
+        self.worker.send(Box::new(log_closure));
     }
 
     fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
         let order_id_data = *order_id;
-        let log_closure = move |writer: &mut BufWriter<File>| {
-            let dt = Utc::now();
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::now();
             let status = if success {
                 "successfully cancelled"
             } else {
@@ -96,37 +97,89 @@ impl SimLogger for AsyncClosureLogger {
             let _ = writeln!(
                 writer,
                 "{} | ORDER CANCEL: id={} {}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                dt,
                 order_id_data,
                 status
             );
         };
-        let _ = self.sender.send(Box::new(log_closure));
+        self.worker.send(Box::new(log_closure));
     }
 
-    fn log_order_filled(&mut self, order: &Order) {
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
         let order_data = order.clone();
-        let log_closure = move |writer: &mut BufWriter<File>| {
-            let dt = Utc::now();
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::now();
             let _ = writeln!(
                 writer,
-                "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}, {}",
+                dt,
                 order_data.order_id,
                 order_data.instrument,
                 order_data.order_type,
                 order_data.status,
                 order_data.quantity,
-                order_data.quantity - order_data.remaining_quantity
+                order_data.quantity - order_data.remaining_quantity,
+                ExecutionFields(execution)
+            );
+        };
+        self.worker.send(Box::new(log_closure));
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        let order_data = order.clone();
+        let reason_data = reason.to_string();
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::now();
+            let _ = writeln!(
+                writer,
+                "{} | ORDER REJECTED: id={}, instrument={}, reason={}",
+                dt,
+                order_data.order_id,
+                order_data.instrument,
+                reason_data
+            );
+        };
+        self.worker.send(Box::new(log_closure));
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        let order_data = order.clone();
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::now();
+            let _ = writeln!(
+                writer,
+                "{} | ORDER REPRICED: id={}, instrument={}, side={:?}, old_price={}, new_price={}",
+                dt,
+                order_data.order_id,
+                order_data.instrument,
+                order_data.side,
+                old_price,
+                order_data.price.unwrap_or_default()
+            );
+        };
+        self.worker.send(Box::new(log_closure));
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        let trade_data = trade.clone();
+        let log_closure = move |writer: &mut LogWriter| {
+            let dt = LogTime::now();
+            let _ = writeln!(
+                writer,
+                "{} | TRADE BUSTED: id={}, instrument={}, price={}, qty={}, buy_order_id={}, sell_order_id={}",
+                dt,
+                trade_data.trade_id,
+                trade_data.instrument,
+                trade_data.price,
+                trade_data.quantity,
+                trade_data.buy_order_id,
+                trade_data.sell_order_id
             );
         };
-        let _ = self.sender.send(Box::new(log_closure));
+        self.worker.send(Box::new(log_closure));
     }
 
-    fn finalize(mut self: Box<Self>) {
-        drop(self.sender);
-        if let Some(handle) = self.handle.take() {
-            let _ = handle.join();
-        }
+    fn finalize(mut self: Box<Self>) -> Vec<LoggerStats> {
+        vec![LoggerStats { logger: LoggingMode::AsyncClosure, worker: self.worker.shutdown() }]
     }
 }
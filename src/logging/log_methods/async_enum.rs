@@ -1,51 +1,41 @@
 use crate::logging::logger_trait::SimLogger;
-use crate::logging::types::{LogMessage, OrderCancelLogData};
+use crate::logging::pool::{LogSlot, MessagePool, DEFAULT_POOL_SIZE};
+use crate::logging::types::{ExecutionFields, LogEvent, LogMessage, LoggingMode, WorkerOptions};
+use crate::logging::worker::{LogWorker, WorkerReceiver, LoggerStats};
 use crate::order::Order;
-use crate::trade::Trade;
-use chrono::{TimeZone, Utc};
+use crate::trade::{Execution, Trade};
+use crate::logging::format::LogTime;
 use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::sync::mpsc::{self, Sender};
-use std::thread::{self, JoinHandle};
+use std::io::{self, BufWriter, Write};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
 /// The final and most performant logger. It offloads all I/O and formatting
 /// work to a background thread and avoids heap allocations on the critical path
-/// by sending stack-allocated enums over the channel.
+/// by copying each event into a pooled record, which the background thread
+/// hands back once it has been written.
 pub struct AsyncEnumLogger {
-    sender: Sender<LogMessage>,
-    handle: Option<JoinHandle<()>>,
+    worker: LogWorker<Box<LogSlot>>,
+    pool: MessagePool,
 }
 
 impl AsyncEnumLogger {
     pub fn new(path: &str) -> Self {
-        let (sender, receiver) = mpsc::channel::<LogMessage>();
+        Self::with_options(path, WorkerOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: WorkerOptions) -> Self {
         let path_owned = path.to_string();
+        let pool = MessagePool::with_capacity(DEFAULT_POOL_SIZE);
+        let recycler = pool.recycler();
 
-        let handle = thread::spawn(move || {
+        let worker = LogWorker::spawn(options, move |receiver: WorkerReceiver<Box<LogSlot>>| {
             if let Ok(file) = File::create(&path_owned) {
-                let mut writer = BufWriter::new(file);
-
-                for msg in receiver.iter() {
-                    match msg {
-                        LogMessage::OrderSubmission(order) => {
-                            let dt = Utc.timestamp_nanos(order.timestamp as i64);
-                            let _ = writeln!(writer,"{} | ORDER RECEIVED: id={}, instrument={}, side={:?}, type={:?}, qty={}, price={}",dt.format("%Y-%m-%d %H:%M:%S%.3f"),order.order_id,order.instrument,order.side,order.order_type,order.quantity,order.price.unwrap_or_default());
-                        }
-                        LogMessage::Trade(trade) => {
-                            let dt = Utc.timestamp_nanos(trade.timestamp as i64);
-                            let _ = writeln!(writer,"{} | TRADE EXECUTED: id={}, instrument={}, price={}, qty={}, taker_side={:?}, buy_order_id={}, sell_order_id={}",dt.format("%Y-%m-%d %H:%M:%S%.3f"),trade.trade_id,trade.instrument,trade.price,trade.quantity,trade.taker_side,trade.buy_order_id,trade.sell_order_id);
-                        }
-                        LogMessage::OrderCancel(data) => {
-                            let dt = Utc::now();
-                            let status = if data.success { "successfully cancelled" } else { "already filled" };
-                            let _ = writeln!(writer,"{} | ORDER CANCEL: id={} {}",dt.format("%Y-%m-%d %H:%M:%S%.3f"),data.order_id,status);
-                        }
-                        LogMessage::OrderFilled(order) => {
-                            let dt = Utc::now();
-                            let _ = writeln!(writer,"{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}",dt.format("%Y-%m-%d %H:%M:%S%.3f"),order.order_id,order.instrument,order.order_type,order.status,order.quantity,order.quantity - order.remaining_quantity);
-                        }
-                    }
+                let mut writer = BufWriter::new(receiver.counted(file));
+
+                while let Some(slot) = receiver.recv(options.wait) {
+                    let _ = slot.write(&mut writer);
+                    let _ = recycler.send(slot);
                 }
                 let _ = writer.flush();
             } else {
@@ -53,41 +43,129 @@ impl AsyncEnumLogger {
             }
         });
 
-        Self {
-            sender,
-            handle: Some(handle),
-        }
+        Self { worker, pool }
+    }
+
+    /// Records allocated by the pool, preallocated ones included. More than
+    /// `DEFAULT_POOL_SIZE` means the background thread fell that far behind.
+    pub fn pooled_records(&self) -> usize {
+        self.pool.allocated()
+    }
+
+    fn send(&mut self, fill: impl FnOnce(&mut LogSlot)) {
+        let mut slot = self.pool.take();
+        fill(&mut slot);
+        self.worker.send(slot);
+    }
+}
+
+/// Formats a single `LogMessage` as one log line. Shared by the loggers that
+/// defer formatting to their background thread.
+pub(crate) fn write_message<W: Write>(writer: &mut W, msg: LogMessage) -> io::Result<()> {
+    match msg {
+        LogMessage::OrderSubmission(order) => write_submission(writer, &order),
+        LogMessage::Trade(trade) => write_trade(writer, &trade),
+        LogMessage::OrderCancel(data) => write_cancel(writer, &data.order_id, data.success),
+        LogMessage::OrderFilled(data) => write_filled(writer, &data.order, data.execution),
+        LogMessage::OrderRejected(data) => write_rejected(writer, &data.order, &data.reason),
+        LogMessage::OrderRepriced(data) => write_repriced(writer, &data.order, data.old_price),
+        LogMessage::TradeBust(trade) => write_bust(writer, &trade),
     }
 }
 
+pub(crate) fn write_submission<W: Write>(writer: &mut W, order: &Order) -> io::Result<()> {
+    let dt = LogTime::from_nanos(order.timestamp);
+    writeln!(writer,"{} | ORDER RECEIVED: id={}, instrument={}, side={:?}, type={:?}, qty={}, price={}",dt,order.order_id,order.instrument,order.side,order.order_type,order.quantity,order.price.unwrap_or_default())
+}
+
+pub(crate) fn write_trade<W: Write>(writer: &mut W, trade: &Trade) -> io::Result<()> {
+    let dt = LogTime::from_nanos(trade.timestamp);
+    writeln!(writer,"{} | TRADE EXECUTED: id={}, instrument={}, price={}, qty={}, taker_side={:?}, buy_order_id={}, sell_order_id={}",dt,trade.trade_id,trade.instrument,trade.price,trade.quantity,trade.taker_side,trade.buy_order_id,trade.sell_order_id)
+}
+
+pub(crate) fn write_cancel<W: Write>(writer: &mut W, order_id: &Uuid, success: bool) -> io::Result<()> {
+    let dt = LogTime::now();
+    let status = if success { "successfully cancelled" } else { "already filled" };
+    writeln!(writer,"{} | ORDER CANCEL: id={} {}",dt,order_id,status)
+}
+
+pub(crate) fn write_filled<W: Write>(writer: &mut W, order: &Order, execution: Option<Execution>) -> io::Result<()> {
+    let dt = LogTime::now();
+    writeln!(writer,"{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}, {}",dt,order.order_id,order.instrument,order.order_type,order.status,order.quantity,order.quantity - order.remaining_quantity,ExecutionFields(execution))
+}
+
+pub(crate) fn write_rejected<W: Write>(writer: &mut W, order: &Order, reason: &str) -> io::Result<()> {
+    let dt = LogTime::now();
+    writeln!(writer,"{} | ORDER REJECTED: id={}, instrument={}, reason={}",dt,order.order_id,order.instrument,reason)
+}
+
+pub(crate) fn write_repriced<W: Write>(writer: &mut W, order: &Order, old_price: Decimal) -> io::Result<()> {
+    let dt = LogTime::now();
+    writeln!(writer,"{} | ORDER REPRICED: id={}, instrument={}, side={:?}, old_price={}, new_price={}",dt,order.order_id,order.instrument,order.side,old_price,order.price.unwrap_or_default())
+}
+
+pub(crate) fn write_bust<W: Write>(writer: &mut W, trade: &Trade) -> io::Result<()> {
+    let dt = LogTime::now();
+    writeln!(writer,"{} | TRADE BUSTED: id={}, instrument={}, price={}, qty={}, buy_order_id={}, sell_order_id={}",dt,trade.trade_id,trade.instrument,trade.price,trade.quantity,trade.buy_order_id,trade.sell_order_id)
+}
+
 impl SimLogger for AsyncEnumLogger {
     fn log_order_submission(&mut self, order: &Order) {
-        let _ = self
-            .sender
-            .send(LogMessage::OrderSubmission(order.clone()));
+        self.send(|slot| {
+            slot.event = LogEvent::Submission;
+            slot.set_order(order);
+        });
     }
 
     fn log_trade(&mut self, trade: &Trade) {
-        let _ = self.sender.send(LogMessage::Trade(trade.clone()));
+        self.send(|slot| {
+            slot.event = LogEvent::Trade;
+            slot.set_trade(trade);
+        });
     }
 
     fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
-        let data = OrderCancelLogData {
-            order_id: *order_id,
-            success,
-        };
-        let _ = self.sender.send(LogMessage::OrderCancel(data));
+        self.send(|slot| {
+            slot.event = LogEvent::Cancel;
+            slot.order_id = *order_id;
+            slot.success = success;
+        });
+    }
+
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        self.send(|slot| {
+            slot.event = LogEvent::Fill;
+            slot.set_order(order);
+            slot.execution = execution;
+        });
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        self.send(|slot| {
+            slot.event = LogEvent::Rejection;
+            slot.set_order(order);
+            slot.reason.clear();
+            slot.reason.push_str(reason);
+        });
     }
 
-    fn log_order_filled(&mut self, order: &Order) {
-        let _ = self.sender.send(LogMessage::OrderFilled(order.clone()));
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        self.send(|slot| {
+            slot.event = LogEvent::Reprice;
+            slot.set_order(order);
+            slot.old_price = old_price;
+        });
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        self.send(|slot| {
+            slot.event = LogEvent::Bust;
+            slot.set_trade(trade);
+        });
     }
 
-    fn finalize(mut self: Box<Self>) {
-        drop(self.sender);
-        if let Some(handle) = self.handle.take() {
-            let _ = handle.join();
-        }
+    fn finalize(mut self: Box<Self>) -> Vec<LoggerStats> {
+        vec![LoggerStats { logger: LoggingMode::AsyncEnum, worker: self.worker.shutdown() }]
     }
 }
 
@@ -1,31 +1,33 @@
 use crate::logging::logger_trait::SimLogger;
+use crate::logging::types::{ExecutionFields, LoggingMode, WorkerOptions};
+use crate::logging::worker::{LogWorker, LoggerStats};
 use crate::order::Order;
-use crate::trade::Trade;
-use chrono::{TimeZone, Utc};
+use crate::trade::{Execution, Trade};
+use crate::logging::format::LogTime;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::sync::mpsc::{self, Sender};
-use std::thread::{self, JoinHandle};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 /// An asynchronous logger that performs string formatting on the main thread
 /// but sends the resulting string to a dedicated background thread for file I/O.
 /// This decouples the main application from slow, blocking disk writes.
 pub struct AsyncStringLogger {
-    sender: Sender<String>,
-    handle: Option<JoinHandle<()>>,
+    worker: LogWorker<String>,
 }
 
 impl AsyncStringLogger {
     pub fn new(path: &str) -> Self {
-        let (sender, receiver) = mpsc::channel::<String>();
+        Self::with_options(path, WorkerOptions::default())
+    }
 
+    pub fn with_options(path: &str, options: WorkerOptions) -> Self {
         let path_owned = path.to_string();
 
-        let handle = thread::spawn(move || {
+        let worker = LogWorker::spawn(options, move |receiver| {
             if let Ok(file) = File::create(&path_owned) {
-                let mut writer = BufWriter::new(file);
+                let mut writer = BufWriter::new(receiver.counted(file));
 
-                for msg in receiver.iter() {
+                while let Some(msg) = receiver.recv(options.wait) {
                     if writeln!(&mut writer, "{}", msg).is_err() {
                         break;
                     }
@@ -36,19 +38,16 @@ impl AsyncStringLogger {
             }
         });
 
-        Self {
-            sender,
-            handle: Some(handle),
-        }
+        Self { worker }
     }
 }
 
 impl SimLogger for AsyncStringLogger {
     fn log_order_submission(&mut self, order: &Order) {
-        let dt = Utc.timestamp_nanos(order.timestamp as i64);
+        let dt = LogTime::from_nanos(order.timestamp);
         let msg = format!(
             "{} | ORDER RECEIVED: id={}, instrument={}, side={:?}, type={:?}, qty={}, price={}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            dt,
             order.order_id,
             order.instrument,
             order.side,
@@ -56,14 +55,14 @@ impl SimLogger for AsyncStringLogger {
             order.quantity,
             order.price.unwrap_or_default()
         );
-        let _ = self.sender.send(msg);
+        self.worker.send(msg);
     }
 
     fn log_trade(&mut self, trade: &Trade) {
-        let dt = Utc.timestamp_nanos(trade.timestamp as i64);
+        let dt = LogTime::from_nanos(trade.timestamp);
         let msg = format!(
             "{} | TRADE EXECUTED: id={}, instrument={}, price={}, qty={}, taker_side={:?}, buy_order_id={}, sell_order_id={}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            dt,
             trade.trade_id,
             trade.instrument,
             trade.price,
@@ -72,11 +71,11 @@ impl SimLogger for AsyncStringLogger {
             trade.buy_order_id,
             trade.sell_order_id
         );
-        let _ = self.sender.send(msg);
+        self.worker.send(msg);
     }
 
     fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
-        let dt = Utc::now();
+        let dt = LogTime::now();
         let status = if success {
             "successfully cancelled"
         } else {
@@ -84,33 +83,71 @@ impl SimLogger for AsyncStringLogger {
         };
         let msg = format!(
             "{} | ORDER CANCEL: id={} {}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            dt,
             order_id,
             status
         );
-        let _ = self.sender.send(msg);
+        self.worker.send(msg);
     }
 
-    fn log_order_filled(&mut self, order: &Order) {
-        let dt = Utc::now();
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        let dt = LogTime::now();
         let msg = format!(
-            "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}, {}",
+            dt,
             order.order_id,
             order.instrument,
             order.order_type,
             order.status,
             order.quantity,
-            order.quantity - order.remaining_quantity
+            order.quantity - order.remaining_quantity,
+            ExecutionFields(execution)
+        );
+        self.worker.send(msg);
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        let dt = LogTime::now();
+        let msg = format!(
+            "{} | ORDER REJECTED: id={}, instrument={}, reason={}",
+            dt,
+            order.order_id,
+            order.instrument,
+            reason
+        );
+        self.worker.send(msg);
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        let dt = LogTime::now();
+        let msg = format!(
+            "{} | ORDER REPRICED: id={}, instrument={}, side={:?}, old_price={}, new_price={}",
+            dt,
+            order.order_id,
+            order.instrument,
+            order.side,
+            old_price,
+            order.price.unwrap_or_default()
         );
-        let _ = self.sender.send(msg);
+        self.worker.send(msg);
     }
 
-    fn finalize(mut self: Box<Self>) {
-        drop(self.sender);
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        let dt = LogTime::now();
+        let msg = format!(
+            "{} | TRADE BUSTED: id={}, instrument={}, price={}, qty={}, buy_order_id={}, sell_order_id={}",
+            dt,
+            trade.trade_id,
+            trade.instrument,
+            trade.price,
+            trade.quantity,
+            trade.buy_order_id,
+            trade.sell_order_id
+        );
+        self.worker.send(msg);
+    }
 
-        if let Some(handle) = self.handle.take() {
-            let _ = handle.join();
-        }
+    fn finalize(mut self: Box<Self>) -> Vec<LoggerStats> {
+        vec![LoggerStats { logger: LoggingMode::AsyncString, worker: self.worker.shutdown() }]
     }
 }
@@ -1,9 +1,12 @@
 use crate::logging::logger_trait::SimLogger;
+use crate::logging::types::ExecutionFields;
+use crate::logging::worker::LoggerStats;
 use crate::order::Order;
-use crate::trade::Trade;
-use chrono::{TimeZone, Utc};
+use crate::trade::{Execution, Trade};
+use crate::logging::format::LogTime;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
 /// A logger that writes to a buffered file writer. This is more efficient
@@ -25,11 +28,11 @@ impl BufferedFileWriteLogger {
 impl SimLogger for BufferedFileWriteLogger {
     fn log_order_submission(&mut self, order: &Order) {
         if let Ok(writer) = &mut self.writer {
-            let dt = Utc.timestamp_nanos(order.timestamp as i64);
+            let dt = LogTime::from_nanos(order.timestamp);
             let _ = writeln!(
                 writer,
                 "{} | ORDER RECEIVED: id={}, instrument={}, side={:?}, type={:?}, qty={}, price={}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                dt,
                 order.order_id,
                 order.instrument,
                 order.side,
@@ -42,11 +45,11 @@ impl SimLogger for BufferedFileWriteLogger {
 
     fn log_trade(&mut self, trade: &Trade) {
         if let Ok(writer) = &mut self.writer {
-            let dt = Utc.timestamp_nanos(trade.timestamp as i64);
+            let dt = LogTime::from_nanos(trade.timestamp);
             let _ = writeln!(
                 writer,
                 "{} | TRADE EXECUTED: id={}, instrument={}, price={}, qty={}, taker_side={:?}, buy_order_id={}, sell_order_id={}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                dt,
                 trade.trade_id,
                 trade.instrument,
                 trade.price,
@@ -60,45 +63,94 @@ impl SimLogger for BufferedFileWriteLogger {
 
     fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
         if let Ok(writer) = &mut self.writer {
-            let dt = Utc::now();
+            let dt = LogTime::now();
             if success {
                 let _ = writeln!(
                     writer,
                     "{} | ORDER CANCEL: id={} successfully cancelled",
-                    dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    dt,
                     order_id
                 );
             } else {
                 let _ = writeln!(
                     writer,
                     "{} | ORDER CANCEL: id={} already filled",
-                    dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    dt,
                     order_id
                 );
             }
         }
     }
 
-    fn log_order_filled(&mut self, order: &Order) {
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
         if let Ok(writer) = &mut self.writer {
-            let dt = Utc::now();
+            let dt = LogTime::now();
             let _ = writeln!(
                 writer,
-                "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}",
-                dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+                "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}, {}",
+                dt,
                 order.order_id,
                 order.instrument,
                 order.order_type,
                 order.status,
                 order.quantity,
-                order.quantity - order.remaining_quantity
+                order.quantity - order.remaining_quantity,
+                ExecutionFields(execution)
             );
         }
     }
 
-    fn finalize(mut self: Box<Self>) {
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        if let Ok(writer) = &mut self.writer {
+            let dt = LogTime::now();
+            let _ = writeln!(
+                writer,
+                "{} | ORDER REJECTED: id={}, instrument={}, reason={}",
+                dt,
+                order.order_id,
+                order.instrument,
+                reason
+            );
+        }
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        if let Ok(writer) = &mut self.writer {
+            let dt = LogTime::now();
+            let _ = writeln!(
+                writer,
+                "{} | ORDER REPRICED: id={}, instrument={}, side={:?}, old_price={}, new_price={}",
+                dt,
+                order.order_id,
+                order.instrument,
+                order.side,
+                old_price,
+                order.price.unwrap_or_default()
+            );
+        }
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        if let Ok(writer) = &mut self.writer {
+            let dt = LogTime::now();
+            let _ = writeln!(
+                writer,
+                "{} | TRADE BUSTED: id={}, instrument={}, price={}, qty={}, buy_order_id={}, sell_order_id={}",
+                dt,
+                trade.trade_id,
+                trade.instrument,
+                trade.price,
+                trade.quantity,
+                trade.buy_order_id,
+                trade.sell_order_id
+            );
+        }
+    }
+
+    fn finalize(mut self: Box<Self>) -> Vec<LoggerStats> {
         if let Ok(writer) = &mut self.writer {
             let _ = writer.flush();
         }
+        Vec::new()
     }
 }
@@ -0,0 +1,74 @@
+use crate::logging::logger_trait::SimLogger;
+use crate::logging::worker::LoggerStats;
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// A test logger that records the name of every event it receives, and
+/// `finalize`, in a list shared with the test.
+pub struct CapturingLogger {
+    events: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl CapturingLogger {
+    pub fn new() -> (Self, Arc<Mutex<Vec<&'static str>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        (Self { events: Arc::clone(&events) }, events)
+    }
+
+    fn record(&self, event: &'static str) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl SimLogger for CapturingLogger {
+    fn log_order_submission(&mut self, _order: &Order) {
+        self.record("submission");
+    }
+
+    fn log_trade(&mut self, _trade: &Trade) {
+        self.record("trade");
+    }
+
+    fn log_order_cancel(&mut self, _order_id: &Uuid, _success: bool) {
+        self.record("cancel");
+    }
+
+    fn log_order_filled(&mut self, _order: &Order, _execution: Option<Execution>) {
+        self.record("fill");
+    }
+
+    fn log_order_rejected(&mut self, _order: &Order, _reason: &str) {
+        self.record("rejection");
+    }
+
+    fn log_order_repriced(&mut self, _order: &Order, _old_price: Decimal) {
+        self.record("reprice");
+    }
+
+    fn log_trade_bust(&mut self, _trade: &Trade) {
+        self.record("bust");
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats> {
+        self.record("finalize");
+        Vec::new()
+    }
+}
+
+/// Sends one event of every type to `logger`, then finalizes it.
+pub fn log_every_event(mut logger: Box<dyn SimLogger>) {
+    let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, Decimal::ONE_HUNDRED, Decimal::ONE);
+    let trade = Trade::new("SOFI".to_string(), Decimal::ONE_HUNDRED, Decimal::ONE, order.order_id, Uuid::new_v4(), Side::Sell);
+    logger.log_order_submission(&order);
+    logger.log_trade(&trade);
+    logger.log_order_cancel(&order.order_id, true);
+    logger.log_order_filled(&order, Some(Execution::of(&trade)));
+    logger.log_order_rejected(&order, "test");
+    logger.log_order_repriced(&order, Decimal::ONE);
+    logger.log_trade_bust(&trade);
+    logger.finalize();
+}
@@ -0,0 +1,88 @@
+use crate::logging::logger_trait::SimLogger;
+use crate::logging::types::EventFilter;
+use crate::logging::worker::LoggerStats;
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// Wraps another logger and drops every event type that is disabled in the
+/// filter before it reaches the inner logger, so filtered events cost only a
+/// branch on the critical path.
+pub struct FilteredLogger {
+    inner: Box<dyn SimLogger>,
+    filter: EventFilter,
+}
+
+impl FilteredLogger {
+    pub fn new(inner: Box<dyn SimLogger>, filter: EventFilter) -> Self {
+        Self { inner, filter }
+    }
+}
+
+impl SimLogger for FilteredLogger {
+    fn log_order_submission(&mut self, order: &Order) {
+        if self.filter.submissions {
+            self.inner.log_order_submission(order);
+        }
+    }
+
+    fn log_trade(&mut self, trade: &Trade) {
+        if self.filter.trades {
+            self.inner.log_trade(trade);
+        }
+    }
+
+    fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
+        if self.filter.cancels {
+            self.inner.log_order_cancel(order_id, success);
+        }
+    }
+
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        if self.filter.fills {
+            self.inner.log_order_filled(order, execution);
+        }
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        if self.filter.rejections {
+            self.inner.log_order_rejected(order, reason);
+        }
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        if self.filter.reprices {
+            self.inner.log_order_repriced(order, old_price);
+        }
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        if self.filter.busts {
+            self.inner.log_trade_bust(trade);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats> {
+        self.inner.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::log_methods::capture::{log_every_event, CapturingLogger};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_only_enabled_events_reach_the_inner_logger() {
+        let (inner, events) = CapturingLogger::new();
+        let filter = EventFilter::from_str("trades,cancels,reprices").unwrap();
+        log_every_event(Box::new(FilteredLogger::new(Box::new(inner), filter)));
+        assert_eq!(*events.lock().unwrap(), ["trade", "cancel", "reprice", "finalize"]);
+
+        let (inner, events) = CapturingLogger::new();
+        log_every_event(Box::new(FilteredLogger::new(Box::new(inner), EventFilter::NONE)));
+        assert_eq!(*events.lock().unwrap(), ["finalize"]);
+    }
+}
@@ -1,17 +1,47 @@
 pub mod no_logging;
+#[cfg(feature = "log-backends")]
 pub mod println;
+#[cfg(feature = "log-backends")]
 pub mod naive_file_write;
+#[cfg(feature = "log-backends")]
 pub mod buffered_file;
+#[cfg(feature = "log-backends")]
 pub mod async_string;
+#[cfg(feature = "log-backends")]
 pub mod async_closure;
+#[cfg(feature = "log-backends")]
 pub mod async_enum;
+#[cfg(feature = "log-backends")]
+pub mod async_batch;
+#[cfg(all(target_os = "linux", feature = "log-backends"))]
+pub mod direct_file;
+#[cfg(feature = "tracing")]
 pub mod tracing_logger;
+pub mod multi;
+pub mod filtered;
+pub mod timed;
+#[cfg(test)]
+pub mod capture;
 
+#[cfg(feature = "log-backends")]
+pub use async_batch::AsyncBatchLogger;
+#[cfg(feature = "log-backends")]
 pub use async_closure::AsyncClosureLogger;
+#[cfg(feature = "log-backends")]
 pub use async_enum::AsyncEnumLogger;
+#[cfg(feature = "log-backends")]
 pub use async_string::AsyncStringLogger;
+#[cfg(feature = "log-backends")]
 pub use buffered_file::BufferedFileWriteLogger;
+#[cfg(all(target_os = "linux", feature = "log-backends"))]
+pub use direct_file::DirectFileWriteLogger;
+pub use filtered::FilteredLogger;
+pub use multi::MultiLogger;
+#[cfg(feature = "log-backends")]
 pub use naive_file_write::NaiveFileWriteLogger;
 pub use no_logging::NoOpLogger;
+#[cfg(feature = "log-backends")]
 pub use println::PrintlnLogger;
-pub use tracing_logger::TracingLogger;
\ No newline at end of file
+pub use timed::TimedLogger;
+#[cfg(feature = "tracing")]
+pub use tracing_logger::TracingLogger;
@@ -0,0 +1,84 @@
+use crate::logging::logger_trait::SimLogger;
+use crate::logging::worker::LoggerStats;
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// A combinator that fans every event out to a list of child loggers in order.
+/// Useful for producing several output formats from a single benchmark run;
+/// the measured latency is the sum of all children's costs.
+pub struct MultiLogger {
+    loggers: Vec<Box<dyn SimLogger>>,
+}
+
+impl MultiLogger {
+    pub fn new(loggers: Vec<Box<dyn SimLogger>>) -> Self {
+        Self { loggers }
+    }
+}
+
+impl SimLogger for MultiLogger {
+    fn log_order_submission(&mut self, order: &Order) {
+        for logger in &mut self.loggers {
+            logger.log_order_submission(order);
+        }
+    }
+
+    fn log_trade(&mut self, trade: &Trade) {
+        for logger in &mut self.loggers {
+            logger.log_trade(trade);
+        }
+    }
+
+    fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
+        for logger in &mut self.loggers {
+            logger.log_order_cancel(order_id, success);
+        }
+    }
+
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        for logger in &mut self.loggers {
+            logger.log_order_filled(order, execution);
+        }
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        for logger in &mut self.loggers {
+            logger.log_order_rejected(order, reason);
+        }
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        for logger in &mut self.loggers {
+            logger.log_order_repriced(order, old_price);
+        }
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        for logger in &mut self.loggers {
+            logger.log_trade_bust(trade);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats> {
+        self.loggers.into_iter().flat_map(|logger| logger.finalize()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::log_methods::capture::{log_every_event, CapturingLogger};
+
+    #[test]
+    fn test_every_event_reaches_every_child() {
+        let (first, first_events) = CapturingLogger::new();
+        let (second, second_events) = CapturingLogger::new();
+        log_every_event(Box::new(MultiLogger::new(vec![Box::new(first), Box::new(second)])));
+
+        let expected = ["submission", "trade", "cancel", "fill", "rejection", "reprice", "bust", "finalize"];
+        assert_eq!(*first_events.lock().unwrap(), expected);
+        assert_eq!(*second_events.lock().unwrap(), expected);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::logging::logger_trait::SimLogger;
+use crate::logging::worker::LoggerStats;
 use crate::order::Order;
-use crate::trade::Trade;
+use crate::trade::{Execution, Trade};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
 /// A no-operation logger that implements the `SimLogger` trait but performs no actions.
@@ -11,6 +13,11 @@ impl SimLogger for NoOpLogger {
     fn log_order_submission(&mut self, _order: &Order) {}
     fn log_trade(&mut self, _trade: &Trade) {}
     fn log_order_cancel(&mut self, _order_id: &Uuid, _success: bool) {}
-    fn log_order_filled(&mut self, _order: &Order) {}
-    fn finalize(self: Box<Self>) {}
+    fn log_order_filled(&mut self, _order: &Order, _execution: Option<Execution>) {}
+    fn log_order_rejected(&mut self, _order: &Order, _reason: &str) {}
+    fn log_order_repriced(&mut self, _order: &Order, _old_price: Decimal) {}
+    fn log_trade_bust(&mut self, _trade: &Trade) {}
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,91 @@
+use crate::clock::{Clock, MonotonicClock};
+use crate::logging::logger_trait::SimLogger;
+use crate::logging::timings::LogTimings;
+use crate::logging::types::LogEvent;
+use crate::logging::worker::LoggerStats;
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// Wraps another logger and records how long each call into it takes, per
+/// event type, under the name `backend`. For an async logger that is the time
+/// to hand the event to its background thread.
+pub struct TimedLogger {
+    inner: Box<dyn SimLogger>,
+    backend: String,
+    timings: LogTimings,
+    clock: MonotonicClock,
+}
+
+impl TimedLogger {
+    pub fn new(inner: Box<dyn SimLogger>, backend: impl Into<String>, timings: LogTimings) -> Self {
+        Self { inner, backend: backend.into(), timings, clock: MonotonicClock::default() }
+    }
+
+    fn timed(&mut self, event: LogEvent, log: impl FnOnce(&mut dyn SimLogger)) {
+        let start = self.clock.now_nanos();
+        log(self.inner.as_mut());
+        let nanos = self.clock.now_nanos().saturating_sub(start);
+        self.timings.record(&self.backend, event, nanos);
+    }
+}
+
+impl SimLogger for TimedLogger {
+    fn log_order_submission(&mut self, order: &Order) {
+        self.timed(LogEvent::Submission, |logger| logger.log_order_submission(order));
+    }
+
+    fn log_trade(&mut self, trade: &Trade) {
+        self.timed(LogEvent::Trade, |logger| logger.log_trade(trade));
+    }
+
+    fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
+        self.timed(LogEvent::Cancel, |logger| logger.log_order_cancel(order_id, success));
+    }
+
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        self.timed(LogEvent::Fill, |logger| logger.log_order_filled(order, execution));
+    }
+
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        self.timed(LogEvent::Rejection, |logger| logger.log_order_rejected(order, reason));
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        self.timed(LogEvent::Reprice, |logger| logger.log_order_repriced(order, old_price));
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        self.timed(LogEvent::Bust, |logger| logger.log_trade_bust(trade));
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats> {
+        self.inner.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::log_methods::capture::{log_every_event, CapturingLogger};
+
+    #[test]
+    fn test_every_event_is_timed_and_forwarded() {
+        let (inner, events) = CapturingLogger::new();
+        let timings = LogTimings::default();
+        log_every_event(Box::new(TimedLogger::new(Box::new(inner), "capture", timings.clone())));
+
+        assert_eq!(*events.lock().unwrap(), ["submission", "trade", "cancel", "fill", "rejection", "reprice", "bust", "finalize"]);
+        let timed: Vec<(String, String, usize)> = timings
+            .summary()
+            .into_iter()
+            .map(|(backend, event, stats)| (backend, event.to_string(), stats.count))
+            .collect();
+        let expected: Vec<(String, String, usize)> = ["submission", "trade", "cancel", "fill", "rejection", "reprice", "bust"]
+            .iter()
+            .map(|event| ("capture".to_string(), event.to_string(), 1))
+            .collect();
+        assert_eq!(timed, expected);
+    }
+}
@@ -1,9 +1,12 @@
 use crate::logging::logger_trait::SimLogger;
+use crate::logging::types::ExecutionFields;
+use crate::logging::worker::LoggerStats;
 use crate::order::Order;
-use crate::trade::Trade;
-use chrono::{TimeZone, Utc};
+use crate::trade::{Execution, Trade};
+use crate::logging::format::LogTime;
 use tracing::info;
 use tracing_appender::non_blocking::WorkerGuard;
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
 pub struct TracingLogger {
@@ -19,10 +22,10 @@ impl TracingLogger {
 
 impl SimLogger for TracingLogger {
     fn log_order_submission(&mut self, order: &Order) {
-        let dt = Utc.timestamp_nanos(order.timestamp as i64);
+        let dt = LogTime::from_nanos(order.timestamp);
         info!(
             "{} | ORDER RECEIVED: id={}, instrument={}, side={:?}, type={:?}, qty={}, price={}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            dt,
             order.order_id,
             order.instrument,
             order.side,
@@ -33,10 +36,10 @@ impl SimLogger for TracingLogger {
     }
 
     fn log_trade(&mut self, trade: &Trade) {
-        let dt = Utc.timestamp_nanos(trade.timestamp as i64);
+        let dt = LogTime::from_nanos(trade.timestamp);
         info!(
             "{} | TRADE EXECUTED: id={}, instrument={}, price={}, qty={}, taker_side={:?}, buy_order_id={}, sell_order_id={}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            dt,
             trade.trade_id,
             trade.instrument,
             trade.price,
@@ -48,7 +51,7 @@ impl SimLogger for TracingLogger {
     }
 
     fn log_order_cancel(&mut self, order_id: &Uuid, success: bool) {
-        let dt = Utc::now();
+        let dt = LogTime::now();
         let status_msg = if success {
             "successfully cancelled"
         } else {
@@ -56,26 +59,66 @@ impl SimLogger for TracingLogger {
         };
         info!(
             "{} | ORDER CANCEL: id={} {}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            dt,
             order_id,
             status_msg
         );
     }
 
-    fn log_order_filled(&mut self, order: &Order) {
-        let dt = Utc::now();
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>) {
+        let dt = LogTime::now();
         info!(
-            "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}",
-            dt.format("%Y-%m-%d %H:%M:%S%.3f"),
+            "{} | ORDER FILLED: id={}, instrument={}, type={:?}, final_status={:?}, quantity={}, quantity_filled={}, {}",
+            dt,
             order.order_id,
             order.instrument,
             order.order_type,
             order.status,
             order.quantity,
-            order.quantity - order.remaining_quantity
+            order.quantity - order.remaining_quantity,
+            ExecutionFields(execution)
         );
     }
 
-    fn finalize(self: Box<Self>) {
+    fn log_order_rejected(&mut self, order: &Order, reason: &str) {
+        let dt = LogTime::now();
+        info!(
+            "{} | ORDER REJECTED: id={}, instrument={}, reason={}",
+            dt,
+            order.order_id,
+            order.instrument,
+            reason
+        );
+    }
+
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal) {
+        let dt = LogTime::now();
+        info!(
+            "{} | ORDER REPRICED: id={}, instrument={}, side={:?}, old_price={}, new_price={}",
+            dt,
+            order.order_id,
+            order.instrument,
+            order.side,
+            old_price,
+            order.price.unwrap_or_default()
+        );
+    }
+
+    fn log_trade_bust(&mut self, trade: &Trade) {
+        let dt = LogTime::now();
+        info!(
+            "{} | TRADE BUSTED: id={}, instrument={}, price={}, qty={}, buy_order_id={}, sell_order_id={}",
+            dt,
+            trade.trade_id,
+            trade.instrument,
+            trade.price,
+            trade.quantity,
+            trade.buy_order_id,
+            trade.sell_order_id
+        );
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats> {
+        Vec::new()
     }
 }
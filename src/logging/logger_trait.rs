@@ -1,11 +1,22 @@
+use crate::logging::worker::LoggerStats;
 use crate::order::Order;
-use crate::trade::Trade;
+use crate::trade::{Execution, Trade};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
 pub trait SimLogger: Send {
     fn log_order_submission(&mut self, order: &Order);
     fn log_trade(&mut self, trade: &Trade);
     fn log_order_cancel(&mut self, order_id: &Uuid, success: bool);
-    fn log_order_filled(&mut self, order: &Order);
-    fn finalize(self: Box<Self>);
+    /// `order` is done: filled, or expired with its remainder. `execution` is
+    /// the trade that completed it, if it traded.
+    fn log_order_filled(&mut self, order: &Order, execution: Option<Execution>);
+    fn log_order_rejected(&mut self, order: &Order, reason: &str);
+    /// A resting pegged order moved from `old_price` to its current price.
+    fn log_order_repriced(&mut self, order: &Order, old_price: Decimal);
+    /// An earlier trade was busted: taken off the tape and reversed.
+    fn log_trade_bust(&mut self, trade: &Trade);
+    /// Flushes and shuts down, returning what each background thread of
+    /// the logger handled. Synchronous loggers have none.
+    fn finalize(self: Box<Self>) -> Vec<LoggerStats>;
 }
\ No newline at end of file
@@ -1,45 +1,128 @@
 
+#[cfg(feature = "log-backends")]
+pub mod format;
 pub mod log_methods;
 pub mod logger_trait;
+#[cfg(feature = "log-backends")]
+pub mod pool;
+pub mod timings;
 pub mod types;
+pub mod worker;
 
 pub use logger_trait::SimLogger;
-pub use types::LoggingMode;
+pub use timings::LogTimings;
+pub use types::{EventFilter, LogEvent, FlushPolicy, LoggerOptions, LoggingMode, WorkerOptions};
+pub use worker::{print_logger_stats, LoggerStats, WorkerStats};
 
-use log_methods::{
-    AsyncClosureLogger, AsyncEnumLogger, AsyncStringLogger, BufferedFileWriteLogger,
-    NaiveFileWriteLogger, NoOpLogger, PrintlnLogger, TracingLogger
-};
+use log_methods::{FilteredLogger, MultiLogger, NoOpLogger, TimedLogger};
+#[cfg(feature = "log-backends")]
+use log_methods::{AsyncBatchLogger, AsyncClosureLogger, AsyncEnumLogger, AsyncStringLogger, BufferedFileWriteLogger, NaiveFileWriteLogger, PrintlnLogger};
+#[cfg(feature = "tracing")]
+use log_methods::TracingLogger;
+#[cfg(feature = "log-backends")]
 use std::path::Path;
 
+/// Creates one logger per mode and tees events to all of them. A single mode
+/// returns that logger directly so it is measured without the fan-out overhead.
+pub fn create_multi_logger(modes: &[LoggingMode], options: &LoggerOptions) -> Box<dyn SimLogger> {
+    match modes {
+        [mode] => create_logger_with(*mode, options.worker(0)),
+        _ => Box::new(MultiLogger::new(
+            modes
+                .iter()
+                .enumerate()
+                .map(|(i, mode)| create_logger_with(*mode, options.worker(i)))
+                .collect(),
+        )),
+    }
+}
+
+/// Like `create_multi_logger`, but times every call into each logger and
+/// records it in `timings` under the mode's name. With several modes the
+/// fan-out as a whole is also recorded, as `all`.
+pub fn create_timed_logger(modes: &[LoggingMode], options: &LoggerOptions, timings: &LogTimings) -> Box<dyn SimLogger> {
+    let timed = |i: usize, mode: &LoggingMode| -> Box<dyn SimLogger> {
+        Box::new(TimedLogger::new(create_logger_with(*mode, options.worker(i)), format!("{:?}", mode), timings.clone()))
+    };
+    match modes {
+        [mode] => timed(0, mode),
+        _ => {
+            let children = modes.iter().enumerate().map(|(i, mode)| timed(i, mode)).collect();
+            Box::new(TimedLogger::new(Box::new(MultiLogger::new(children)), "all", timings.clone()))
+        }
+    }
+}
+
+/// Wraps the logger in a `FilteredLogger` unless every event type is enabled.
+pub fn with_event_filter(logger: Box<dyn SimLogger>, filter: EventFilter) -> Box<dyn SimLogger> {
+    if filter.is_all() {
+        logger
+    } else {
+        Box::new(FilteredLogger::new(logger, filter))
+    }
+}
+
 pub fn create_logger(mode: LoggingMode) -> Box<dyn SimLogger> {
+    create_logger_with(mode, WorkerOptions::default())
+}
 
+/// Like `create_logger`, applying `worker` to the background thread of the
+/// async loggers. Synchronous loggers ignore it. A mode whose backend the
+/// build leaves out logs nothing, with a warning.
+#[cfg_attr(not(feature = "log-backends"), allow(unused_variables))]
+pub fn create_logger_with(mode: LoggingMode, worker: WorkerOptions) -> Box<dyn SimLogger> {
+
+    #[cfg(feature = "log-backends")]
     const OUTPUT_DIR: &str = "output_logs";
 
+    #[allow(unreachable_patterns)]
     match mode {
         LoggingMode::Baseline => Box::new(NoOpLogger),
+        #[cfg(feature = "log-backends")]
         LoggingMode::Naive => Box::new(PrintlnLogger),
+        #[cfg(feature = "log-backends")]
         LoggingMode::NaiveFileWrite => {
             let path = Path::new(OUTPUT_DIR).join("naive_output.log");
             Box::new(NaiveFileWriteLogger::new(path.to_str().unwrap()))
         }
+        #[cfg(feature = "log-backends")]
         LoggingMode::BufferedFileWrite => {
             let path = Path::new(OUTPUT_DIR).join("buffered_output.log");
             Box::new(BufferedFileWriteLogger::new(path.to_str().unwrap()))
         }
+        #[cfg(feature = "log-backends")]
         LoggingMode::AsyncString => {
             let path = Path::new(OUTPUT_DIR).join("async_string_output.log");
-            Box::new(AsyncStringLogger::new(path.to_str().unwrap()))
+            Box::new(AsyncStringLogger::with_options(path.to_str().unwrap(), worker))
         }
+        #[cfg(feature = "log-backends")]
         LoggingMode::AsyncClosure => {
             let path = Path::new(OUTPUT_DIR).join("async_closure_output.log");
-            Box::new(AsyncClosureLogger::new(path.to_str().unwrap()))
+            Box::new(AsyncClosureLogger::with_options(path.to_str().unwrap(), worker))
         }
+        #[cfg(feature = "log-backends")]
         LoggingMode::AsyncEnum => {
             let path = Path::new(OUTPUT_DIR).join("async_enum_output.log");
-            Box::new(AsyncEnumLogger::new(path.to_str().unwrap()))
+            Box::new(AsyncEnumLogger::with_options(path.to_str().unwrap(), worker))
+        }
+        #[cfg(feature = "log-backends")]
+        LoggingMode::AsyncBatch => {
+            let path = Path::new(OUTPUT_DIR).join("async_batch_output.log");
+            Box::new(AsyncBatchLogger::with_options(path.to_str().unwrap(), worker))
+        }
+        #[cfg(all(target_os = "linux", feature = "log-backends"))]
+        LoggingMode::DirectFileWrite => {
+            let path = Path::new(OUTPUT_DIR).join("direct_output.log");
+            Box::new(log_methods::DirectFileWriteLogger::with_options(path.to_str().unwrap(), worker))
+        }
+        #[cfg(all(not(target_os = "linux"), feature = "log-backends"))]
+        LoggingMode::DirectFileWrite => {
+            eprintln!("DirectFileWrite is only available on Linux, falling back to AsyncEnum");
+            let path = Path::new(OUTPUT_DIR).join("direct_output.log");
+            Box::new(AsyncEnumLogger::with_options(path.to_str().unwrap(), worker))
         }
 
+        #[cfg(feature = "tracing")]
         LoggingMode::TracingFile => {
             let log_file = Path::new(OUTPUT_DIR).join("tracing_output.log");
             let file_appender = tracing_appender::rolling::never("", log_file);
@@ -60,6 +143,7 @@ pub fn create_logger(mode: LoggingMode) -> Box<dyn SimLogger> {
             Box::new(TracingLogger::new(Some(guard)))
         }
 
+        #[cfg(feature = "tracing")]
         LoggingMode::TracingConsole => {
             let subscriber = tracing_subscriber::fmt()
                 .with_writer(std::io::stdout)
@@ -74,5 +158,10 @@ pub fn create_logger(mode: LoggingMode) -> Box<dyn SimLogger> {
             
             Box::new(TracingLogger::new(None))
         }
+
+        _ => {
+            eprintln!("{:?} logging is not built in (see the log-backends and tracing features); not logging", mode);
+            Box::new(NoOpLogger)
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,211 @@
+//! Reusable log records passed between an async logger and its background
+//! thread, so logging an event copies it into a buffer that is already
+//! allocated instead of cloning the `Order` or `Trade` onto the heap.
+
+use crate::logging::log_methods::async_enum::{write_bust, write_cancel, write_filled, write_rejected, write_repriced, write_submission, write_trade};
+use crate::logging::types::LogEvent;
+use crate::order::Order;
+use crate::trade::{Execution, Trade};
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use uuid::Uuid;
+
+/// Records allocated up front for each pooled logger.
+pub const DEFAULT_POOL_SIZE: usize = 4096;
+
+/// Initial capacity of the string buffers in a record, enough for typical
+/// instrument names and reject reasons.
+const STRING_CAPACITY: usize = 64;
+
+/// One event's data in reusable buffers. Only the fields used by `event` are
+/// current; the rest hold whatever an earlier event left there.
+pub struct LogSlot {
+    pub event: LogEvent,
+    pub order: Order,
+    pub trade: Trade,
+    pub order_id: Uuid,
+    pub success: bool,
+    pub execution: Option<Execution>,
+    pub reason: String,
+    pub old_price: Decimal,
+}
+
+impl Default for LogSlot {
+    fn default() -> Self {
+        LogSlot {
+            event: LogEvent::Submission,
+            order: Order::new_limit(Uuid::nil(), String::with_capacity(STRING_CAPACITY), Side::Buy, Decimal::ZERO, Decimal::ZERO),
+            trade: Trade {
+                trade_id: Uuid::nil(),
+                sequence: 0,
+                instrument: String::with_capacity(STRING_CAPACITY),
+                price: Decimal::ZERO,
+                quantity: Decimal::ZERO,
+                timestamp: 0,
+                buy_order_id: Uuid::nil(),
+                sell_order_id: Uuid::nil(),
+                taker_side: Side::Buy,
+            },
+            order_id: Uuid::nil(),
+            success: false,
+            execution: None,
+            reason: String::with_capacity(STRING_CAPACITY),
+            old_price: Decimal::ZERO,
+        }
+    }
+}
+
+impl LogSlot {
+    /// Copies `order` into the slot, reusing the instrument buffer.
+    pub fn set_order(&mut self, order: &Order) {
+        let Order { order_id, instrument, side, order_type, status, price, quantity, remaining_quantity, timestamp, sequence, peg, discretion, attributes, expire_at, cancel_timer, stop_price } = order;
+        let slot = &mut self.order;
+        slot.order_id = *order_id;
+        slot.instrument.clone_from(instrument);
+        slot.side = *side;
+        slot.order_type = *order_type;
+        slot.status = *status;
+        slot.price = *price;
+        slot.quantity = *quantity;
+        slot.remaining_quantity = *remaining_quantity;
+        slot.timestamp = *timestamp;
+        slot.sequence = *sequence;
+        slot.peg = *peg;
+        slot.discretion = *discretion;
+        slot.attributes = *attributes;
+        slot.expire_at = *expire_at;
+        slot.cancel_timer = *cancel_timer;
+        slot.stop_price = *stop_price;
+    }
+
+    /// Copies `trade` into the slot, reusing the instrument buffer.
+    pub fn set_trade(&mut self, trade: &Trade) {
+        let Trade { trade_id, sequence, instrument, price, quantity, timestamp, buy_order_id, sell_order_id, taker_side } = trade;
+        let slot = &mut self.trade;
+        slot.trade_id = *trade_id;
+        slot.sequence = *sequence;
+        slot.instrument.clone_from(instrument);
+        slot.price = *price;
+        slot.quantity = *quantity;
+        slot.timestamp = *timestamp;
+        slot.buy_order_id = *buy_order_id;
+        slot.sell_order_id = *sell_order_id;
+        slot.taker_side = *taker_side;
+    }
+
+    /// Formats the event as one log line, the same line `write_message`
+    /// produces for it.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self.event {
+            LogEvent::Submission => write_submission(writer, &self.order),
+            LogEvent::Trade => write_trade(writer, &self.trade),
+            LogEvent::Cancel => write_cancel(writer, &self.order_id, self.success),
+            LogEvent::Fill => write_filled(writer, &self.order, self.execution),
+            LogEvent::Rejection => write_rejected(writer, &self.order, &self.reason),
+            LogEvent::Reprice => write_repriced(writer, &self.order, self.old_price),
+            LogEvent::Bust => write_bust(writer, &self.trade),
+        }
+    }
+}
+
+/// The producer's end of the pool. Records come back from the background
+/// thread through `recycler` once written; a new one is allocated only when
+/// every record is in flight.
+pub struct MessagePool {
+    free: Receiver<Box<LogSlot>>,
+    recycler: Sender<Box<LogSlot>>,
+    allocated: usize,
+}
+
+impl MessagePool {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (recycler, free) = mpsc::channel();
+        for _ in 0..capacity {
+            let _ = recycler.send(Box::default());
+        }
+        MessagePool { free, recycler, allocated: capacity }
+    }
+
+    /// The sender the background thread returns written records through.
+    pub fn recycler(&self) -> Sender<Box<LogSlot>> {
+        self.recycler.clone()
+    }
+
+    /// A free record, allocating one if none has come back yet.
+    pub fn take(&mut self) -> Box<LogSlot> {
+        match self.free.try_recv() {
+            Ok(slot) => slot,
+            Err(_) => {
+                self.allocated += 1;
+                Box::default()
+            }
+        }
+    }
+
+    /// Records allocated so far, preallocated ones included.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::log_methods::async_enum::write_message;
+    use crate::logging::types::{LogMessage, OrderFillLogData, OrderRejectLogData};
+    use rust_decimal_macros::dec;
+
+    fn line(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut buffer = Vec::new();
+        write(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_records_are_reused_once_returned() {
+        let mut pool = MessagePool::with_capacity(2);
+        let recycler = pool.recycler();
+        for _ in 0..100 {
+            let first = pool.take();
+            let second = pool.take();
+            recycler.send(first).unwrap();
+            recycler.send(second).unwrap();
+        }
+        assert_eq!(pool.allocated(), 2);
+        let _held = [pool.take(), pool.take(), pool.take()];
+        assert_eq!(pool.allocated(), 3);
+    }
+
+    #[test]
+    fn test_slots_write_the_same_lines_as_messages() {
+        let mut order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101.5), dec!(10));
+        let trade = Trade::new("SOFI".to_string(), dec!(101.5), dec!(4), Uuid::new_v4(), order.order_id, Side::Buy);
+        let mut slot = LogSlot { event: LogEvent::Submission, ..Default::default() };
+        slot.set_order(&order);
+        assert_eq!(line(|w| slot.write(w)), line(|w| write_message(w, LogMessage::OrderSubmission(order.clone()))));
+
+        slot.event = LogEvent::Trade;
+        slot.set_trade(&trade);
+        assert_eq!(line(|w| slot.write(w)), line(|w| write_message(w, LogMessage::Trade(trade.clone()))));
+
+        // A longer instrument in the same slot replaces the earlier one.
+        order.instrument = "SOFI-2030-CALL".to_string();
+        order.remaining_quantity = dec!(0);
+        slot.event = LogEvent::Rejection;
+        slot.set_order(&order);
+        slot.reason.clear();
+        slot.reason.push_str("halted");
+        // Reject and fill lines stamp the wall clock; compare past the time.
+        let strip = |line: String| line.split_once(" | ").map(|(_, rest)| rest.to_string()).unwrap();
+        let rejected = LogMessage::OrderRejected(OrderRejectLogData { order: order.clone(), reason: "halted".to_string() });
+        assert_eq!(strip(line(|w| slot.write(w))), strip(line(|w| write_message(w, rejected))));
+        assert_eq!(slot.order.instrument, "SOFI-2030-CALL");
+
+        slot.event = LogEvent::Fill;
+        slot.execution = Some(Execution::of(&trade));
+        let filled = LogMessage::OrderFilled(OrderFillLogData { order: order.clone(), execution: Some(Execution::of(&trade)) });
+        assert_eq!(strip(line(|w| slot.write(w))), strip(line(|w| write_message(w, filled))));
+    }
+}
@@ -0,0 +1,57 @@
+//! Logging time broken down by backend and event type, so a tail in the
+//! aggregate logging latency can be traced to the event and logger behind it.
+
+use crate::logging::types::LogEvent;
+use crate::utils::LatencyStats;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Durations in nanoseconds, keyed by backend name and event type.
+type Samples = BTreeMap<(String, LogEvent), Vec<u64>>;
+
+/// Every logging call's duration in nanoseconds, keyed by backend and event.
+/// Clones share the same samples, so each timed logger holds one and the run
+/// reads them at the end.
+#[derive(Debug, Clone, Default)]
+pub struct LogTimings {
+    samples: Arc<Mutex<Samples>>,
+}
+
+impl LogTimings {
+    pub fn record(&self, backend: &str, event: LogEvent, nanos: u64) {
+        let mut samples = self.samples.lock().expect("log timings lock poisoned");
+        match samples.get_mut(&(backend.to_string(), event)) {
+            Some(durations) => durations.push(nanos),
+            None => {
+                samples.insert((backend.to_string(), event), vec![nanos]);
+            }
+        }
+    }
+
+    /// Latency statistics for every backend and event type that was logged.
+    pub fn summary(&self) -> Vec<(String, LogEvent, LatencyStats)> {
+        let samples = self.samples.lock().expect("log timings lock poisoned");
+        samples
+            .iter()
+            .filter_map(|((backend, event), durations)| {
+                let stats = LatencyStats::of(durations.iter().map(|&nanos| nanos as u128).collect())?;
+                Some((backend.clone(), *event, stats))
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        let summary = self.summary();
+        if summary.is_empty() {
+            return;
+        }
+        println!("\n--- Logging Latency by Event (nanoseconds) ---");
+        println!("{:<20} {:<12} {:>10} {:>12} {:>10} {:>10} {:>10}", "Backend", "Event", "Count", "Mean", "Median", "p99", "p99.9");
+        for (backend, event, stats) in summary {
+            println!(
+                "{:<20} {:<12} {:>10} {:>12.2} {:>10} {:>10} {:>10}",
+                backend, event.to_string(), stats.count, stats.mean, stats.median, stats.p99, stats.p999
+            );
+        }
+    }
+}
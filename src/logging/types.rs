@@ -1,6 +1,10 @@
 use crate::order::Order;
-use crate::trade::Trade;
+use crate::trade::{Execution, Trade};
+use std::fmt;
+use rust_decimal::Decimal;
 use std::str::FromStr;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -12,6 +16,8 @@ pub enum LoggingMode {
     AsyncString,
     AsyncClosure,
     AsyncEnum,
+    AsyncBatch,
+    DirectFileWrite,
     TracingConsole,
     TracingFile,
 }
@@ -29,21 +35,268 @@ impl FromStr for LoggingMode {
             "asyncstring" | "as" => Ok(Self::AsyncString),
             "asyncclosure" | "ac" => Ok(Self::AsyncClosure),
             "asyncenum" | "ae" => Ok(Self::AsyncEnum),
+            "asyncbatch" | "ab" => Ok(Self::AsyncBatch),
+            "directfilewrite" | "dfw" => Ok(Self::DirectFileWrite),
             _ => Err("Unknown logging mode"),
         }
     }
 }
 
+/// How an async logger's background thread waits for the next message.
+/// `Block` parks the thread inside the channel and pays the wakeup latency on
+/// every burst; `Yield` and `Spin` poll the channel and keep a core busy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitStrategy {
+    #[default]
+    Block,
+    Yield,
+    Spin,
+}
+
+impl WaitStrategy {
+    /// Receives the next message, or `None` once every sender is gone.
+    pub fn recv<T>(&self, receiver: &Receiver<T>) -> Option<T> {
+        match self {
+            WaitStrategy::Block => receiver.recv().ok(),
+            WaitStrategy::Yield | WaitStrategy::Spin => loop {
+                match receiver.try_recv() {
+                    Ok(msg) => return Some(msg),
+                    Err(TryRecvError::Disconnected) => return None,
+                    Err(TryRecvError::Empty) if *self == WaitStrategy::Yield => std::thread::yield_now(),
+                    Err(TryRecvError::Empty) => std::hint::spin_loop(),
+                }
+            },
+        }
+    }
+}
+
+impl FromStr for WaitStrategy {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "block" => Ok(Self::Block),
+            "yield" => Ok(Self::Yield),
+            "spin" | "busy-spin" => Ok(Self::Spin),
+            _ => Err("Unknown wait strategy"),
+        }
+    }
+}
+
+/// When a batching logger hands its buffered events to its background
+/// thread: once `events` have accumulated, or on the first event after the
+/// oldest buffered one is `interval` old.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlushPolicy {
+    pub events: usize,
+    pub interval: Duration,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy { events: 1024, interval: Duration::from_millis(1) }
+    }
+}
+
+/// Settings for the background thread of an async logger.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorkerOptions {
+    pub core: Option<usize>,
+    pub wait: WaitStrategy,
+    pub flush: FlushPolicy,
+}
+
+/// Logger settings chosen on the command line. When several loggers are teed
+/// together, their background threads are assigned `logger_cores` round-robin.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoggerOptions {
+    pub logger_cores: Vec<usize>,
+    pub wait: WaitStrategy,
+    pub flush: FlushPolicy,
+}
+
+impl LoggerOptions {
+    pub fn worker(&self, index: usize) -> WorkerOptions {
+        WorkerOptions {
+            core: match self.logger_cores.len() {
+                0 => None,
+                len => Some(self.logger_cores[index % len]),
+            },
+            wait: self.wait,
+            flush: self.flush,
+        }
+    }
+}
+
+/// Selects which event types reach a logger. Parsed from a comma-separated
+/// list such as `trades,fills`; `all` enables everything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventFilter {
+    pub submissions: bool,
+    pub trades: bool,
+    pub cancels: bool,
+    pub fills: bool,
+    pub rejections: bool,
+    pub reprices: bool,
+    pub busts: bool,
+}
+
+impl EventFilter {
+    pub const ALL: EventFilter = EventFilter {
+        submissions: true,
+        trades: true,
+        cancels: true,
+        fills: true,
+        rejections: true,
+        reprices: true,
+        busts: true,
+    };
+
+    pub const NONE: EventFilter = EventFilter {
+        submissions: false,
+        trades: false,
+        cancels: false,
+        fills: false,
+        rejections: false,
+        reprices: false,
+        busts: false,
+    };
+
+    pub fn is_all(&self) -> bool {
+        *self == Self::ALL
+    }
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Self::NONE;
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.to_lowercase().as_str() {
+                "all" => filter = Self::ALL,
+                "submissions" | "submission" | "orders" => filter.submissions = true,
+                "trades" | "trade" => filter.trades = true,
+                "cancels" | "cancel" => filter.cancels = true,
+                "fills" | "fill" | "filled" => filter.fills = true,
+                "rejections" | "rejects" | "rejected" => filter.rejections = true,
+                "reprices" | "reprice" | "repriced" => filter.reprices = true,
+                "busts" | "bust" | "busted" => filter.busts = true,
+                other => return Err(format!("Unknown log event type '{}'", other)),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// The kinds of event a `SimLogger` receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogEvent {
+    Submission,
+    Trade,
+    Cancel,
+    Fill,
+    Rejection,
+    Reprice,
+    Bust,
+}
+
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogEvent::Submission => "submission",
+            LogEvent::Trade => "trade",
+            LogEvent::Cancel => "cancel",
+            LogEvent::Fill => "fill",
+            LogEvent::Rejection => "rejection",
+            LogEvent::Reprice => "reprice",
+            LogEvent::Bust => "bust",
+        };
+        f.write_str(name)
+    }
+}
+
 #[derive(Clone)]
 pub struct OrderCancelLogData {
     pub order_id: Uuid,
     pub success: bool,
 }
 
+#[derive(Clone)]
+pub struct OrderFillLogData {
+    pub order: Order,
+    pub execution: Option<Execution>,
+}
+
+/// The `trade_id` and `aggressor_order_id` fields of an `ORDER FILLED` line,
+/// `none` for an order that expired without trading.
+pub struct ExecutionFields(pub Option<Execution>);
+
+impl fmt::Display for ExecutionFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(execution) => write!(f, "trade_id={}, aggressor_order_id={}", execution.trade_id, execution.aggressor_order_id),
+            None => write!(f, "trade_id=none, aggressor_order_id=none"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OrderRejectLogData {
+    pub order: Order,
+    pub reason: String,
+}
+
+#[derive(Clone)]
+pub struct OrderRepriceLogData {
+    pub order: Order,
+    pub old_price: Decimal,
+}
+
 #[derive(Clone)]
 pub enum LogMessage {
     OrderSubmission(Order),
     Trade(Trade),
     OrderCancel(OrderCancelLogData),
-    OrderFilled(Order),
+    OrderFilled(OrderFillLogData),
+    OrderRejected(OrderRejectLogData),
+    OrderRepriced(OrderRepriceLogData),
+    TradeBust(Trade),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_strategies_drain_then_stop() {
+        for wait in [WaitStrategy::Block, WaitStrategy::Yield, WaitStrategy::Spin] {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let producer = std::thread::spawn(move || {
+                for i in 0..100 {
+                    sender.send(i).unwrap();
+                }
+            });
+
+            let mut received = Vec::new();
+            while let Some(msg) = wait.recv(&receiver) {
+                received.push(msg);
+            }
+            producer.join().unwrap();
+            assert_eq!(received, (0..100).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_event_filter_parsing() {
+        let filter = EventFilter::from_str("trades, fills").unwrap();
+        assert_eq!(filter, EventFilter { submissions: false, trades: true, cancels: false, fills: true, rejections: false, reprices: false, busts: false });
+        assert!(EventFilter::from_str("all").unwrap().is_all());
+        assert_eq!(EventFilter::from_str("").unwrap(), EventFilter::NONE);
+        assert!(EventFilter::from_str("trades,quotes").is_err());
+    }
 }
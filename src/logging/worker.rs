@@ -0,0 +1,241 @@
+use crate::affinity::pin_current_thread;
+use crate::logging::types::{LoggingMode, WaitStrategy, WorkerOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// What a background thread handled before it was shut down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// Events sent. A batching logger sends many in one message.
+    pub events: u64,
+    /// Most messages sent but not yet taken by the background thread, as
+    /// seen on each send.
+    pub peak_queue_depth: u64,
+    pub bytes_written: u64,
+    /// How long shutting down waited for the thread to empty the queue.
+    pub drain_nanos: u64,
+}
+
+/// The [`WorkerStats`] of an async logger's background thread, returned by
+/// `finalize` so the backends can be compared on the same workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggerStats {
+    pub logger: LoggingMode,
+    pub worker: WorkerStats,
+}
+
+/// Counters shared by the two ends of a worker's channel. The producer
+/// keeps the first three.
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    events: AtomicU64,
+    peak_queue_depth: AtomicU64,
+    received: AtomicU64,
+    bytes: AtomicU64,
+}
+
+/// The background thread's end of the channel. It counts what is taken off
+/// the queue and, through [`counted`](WorkerReceiver::counted), what is
+/// written.
+pub struct WorkerReceiver<T> {
+    receiver: Receiver<T>,
+    counters: Arc<Counters>,
+}
+
+impl<T> WorkerReceiver<T> {
+    /// The next message, waiting as `wait` says, or `None` once the logger
+    /// has shut down and the queue is empty.
+    pub fn recv(&self, wait: WaitStrategy) -> Option<T> {
+        let msg = wait.recv(&self.receiver)?;
+        self.counters.received.fetch_add(1, Ordering::Relaxed);
+        Some(msg)
+    }
+
+    /// Wraps the thread's output so the bytes written to it are counted.
+    pub fn counted<W: Write>(&self, inner: W) -> CountedWriter<W> {
+        CountedWriter { inner, counters: Arc::clone(&self.counters) }
+    }
+}
+
+/// A writer that adds the bytes it passes on to its worker's count.
+pub struct CountedWriter<W> {
+    inner: W,
+    counters: Arc<Counters>,
+}
+
+impl<W: Write> Write for CountedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.counters.bytes.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Owns the producer end of an async logger's channel together with its
+/// background thread. Shutting down closes the channel and joins the thread;
+/// this also happens on `Drop`, so the tail of the log is still written when
+/// the simulation panics and unwinds past `finalize`.
+pub struct LogWorker<T> {
+    sender: Option<Sender<T>>,
+    handle: Option<JoinHandle<()>>,
+    counters: Arc<Counters>,
+}
+
+impl<T: Send + 'static> LogWorker<T> {
+    /// Spawns the background thread, pinned according to `options`, and runs
+    /// `body` on it with the receiving end of the channel.
+    pub fn spawn<F>(options: WorkerOptions, body: F) -> Self
+    where
+        F: FnOnce(WorkerReceiver<T>) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel::<T>();
+        let counters = Arc::new(Counters::default());
+        let receiver = WorkerReceiver { receiver, counters: Arc::clone(&counters) };
+
+        let handle = thread::spawn(move || {
+            if let Some(core) = options.core {
+                pin_current_thread(core, "logger");
+            }
+            body(receiver);
+        });
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            counters,
+        }
+    }
+}
+
+impl<T> LogWorker<T> {
+    pub fn send(&self, msg: T) {
+        self.send_events(msg, 1);
+    }
+
+    /// Sends a message carrying `events` events.
+    pub fn send_events(&self, msg: T, events: u64) {
+        profile_scope!("logger_send");
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(msg);
+            let counters = &self.counters;
+            let sent = counters.sent.fetch_add(1, Ordering::Relaxed) + 1;
+            counters.events.fetch_add(events, Ordering::Relaxed);
+            let depth = sent.saturating_sub(counters.received.load(Ordering::Relaxed));
+            if depth > counters.peak_queue_depth.load(Ordering::Relaxed) {
+                counters.peak_queue_depth.fetch_max(depth, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Closes the channel and waits for the background thread to drain it.
+    /// Calling it again is a no-op, and reports a drain of zero.
+    pub fn shutdown(&mut self) -> WorkerStats {
+        let start = Instant::now();
+        drop(self.sender.take());
+        let drain_nanos = match self.handle.take() {
+            Some(handle) => {
+                let _ = handle.join();
+                start.elapsed().as_nanos() as u64
+            }
+            None => 0,
+        };
+        WorkerStats {
+            events: self.counters.events.load(Ordering::Relaxed),
+            peak_queue_depth: self.counters.peak_queue_depth.load(Ordering::Relaxed),
+            bytes_written: self.counters.bytes.load(Ordering::Relaxed),
+            drain_nanos,
+        }
+    }
+}
+
+impl<T> Drop for LogWorker<T> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+pub fn print_logger_stats(stats: &[LoggerStats]) {
+    if stats.is_empty() {
+        return;
+    }
+    println!("{:<16} {:>12} {:>12} {:>14} {:>14}", "Logger", "Events", "Peak queue", "Bytes", "Drain ns");
+    for LoggerStats { logger, worker } in stats {
+        println!(
+            "{:<16} {:>12} {:>12} {:>14} {:>14}",
+            format!("{:?}", logger), worker.events, worker.peak_queue_depth, worker.bytes_written, worker.drain_nanos
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_drop_drains_and_joins_background_thread() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+
+        let worker = LogWorker::spawn(WorkerOptions::default(), move |receiver: WorkerReceiver<u32>| {
+            while let Some(msg) = receiver.recv(WaitStrategy::Block) {
+                sink.lock().unwrap().push(msg);
+            }
+        });
+        for i in 0..10 {
+            worker.send(i);
+        }
+        drop(worker);
+
+        assert_eq!(*received.lock().unwrap(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_panic_unwinding_still_flushes() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+
+        let result = std::panic::catch_unwind(move || {
+            let worker = LogWorker::spawn(WorkerOptions::default(), move |receiver: WorkerReceiver<u32>| {
+                while let Some(msg) = receiver.recv(WaitStrategy::Block) {
+                    sink.lock().unwrap().push(msg);
+                }
+            });
+            worker.send(7);
+            panic!("simulation failed");
+        });
+
+        assert!(result.is_err());
+        assert_eq!(*received.lock().unwrap(), vec![7]);
+    }
+
+    #[test]
+    fn test_shutdown_reports_what_the_thread_handled() {
+        let (release, gate) = mpsc::channel::<()>();
+        let mut worker = LogWorker::spawn(WorkerOptions::default(), move |receiver: WorkerReceiver<Vec<u8>>| {
+            let _ = gate.recv();
+            let mut writer = receiver.counted(io::sink());
+            while let Some(batch) = receiver.recv(WaitStrategy::Block) {
+                let _ = writer.write_all(&batch);
+            }
+        });
+        // The thread takes nothing until released, so all three sends queue.
+        worker.send_events(b"one\ntwo\n".to_vec(), 2);
+        worker.send(b"three\n".to_vec());
+        worker.send(b"four\n".to_vec());
+        release.send(()).unwrap();
+
+        let stats = worker.shutdown();
+        assert_eq!((stats.events, stats.peak_queue_depth, stats.bytes_written), (4, 3, 19));
+        assert_eq!(worker.shutdown().drain_nanos, 0);
+    }
+}
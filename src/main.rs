@@ -1,55 +1,332 @@
-mod engine;
-mod orderbook;
-mod trade;
-mod order;
-mod simulation;
-mod utils;
-mod logging;
-
+use exchange_matching_engine::admin::AdminServer;
+use exchange_matching_engine::affinity::pin_current_thread;
+use exchange_matching_engine::audit::{print_story, DEFAULT_AUDIT_PATH};
+use exchange_matching_engine::checkpoint::{Checkpointer, DEFAULT_CHECKPOINT_DIR, DEFAULT_CHECKPOINT_INTERVAL};
+use exchange_matching_engine::cli::CliArgs;
+use exchange_matching_engine::comparison::RunRecord;
+use exchange_matching_engine::config::InstrumentConfig;
+use exchange_matching_engine::confirmations::{ConfirmationFiles, ConfirmationSink};
+use exchange_matching_engine::control::SimulationControl;
+use exchange_matching_engine::engine::MatchingEngine;
+use exchange_matching_engine::experiment::{append_results, print_results, run_experiment, ExperimentConfig};
+use exchange_matching_engine::export::lobster::LobsterExporter;
+use exchange_matching_engine::gateway::{Credentials, Gateway};
+use exchange_matching_engine::import::{import_file, Imported};
+use exchange_matching_engine::market_data::reconstruct::{reconstruct_book_from, ReplayPoint};
+use exchange_matching_engine::market_data::stream::StreamSink;
+use exchange_matching_engine::memory::MemoryTracker;
+use exchange_matching_engine::market_data::{FeedWriter, MarketDataPublisher};
+use exchange_matching_engine::logging::{create_multi_logger, create_timed_logger, print_logger_stats, with_event_filter, LogTimings, WorkerOptions};
+use exchange_matching_engine::governor::SpeedGovernor;
+use exchange_matching_engine::simulation::{run_checkpointed, run_governed, run_server, run_simulation_with};
+use exchange_matching_engine::source::OperationListener;
+use exchange_matching_engine::snapshot::{load_snapshot, save_snapshot};
+use exchange_matching_engine::messaging::print_message_counts;
+use exchange_matching_engine::operation::load_operations;
+use exchange_matching_engine::positions::print_leaderboard;
+use exchange_matching_engine::margin::{print_margin_calls, MarginBook};
+use exchange_matching_engine::utils::{display_final_matching_engine, report_latencies};
 use std::str::FromStr;
-use logging::types::LoggingMode;
-use engine::MatchingEngine;
 use std::time::Instant;
-use std::fs;
-use utils::{display_final_matching_engine, load_operations, report_latencies};
-use simulation::run_simulation;
-
-use crate::logging::create_logger;
+use std::fs::{self, File};
+use std::io::BufWriter;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut memory = MemoryTracker::start();
     fs::create_dir_all("output_logs")?;
-    
-    let args: Vec<String> = std::env::args().collect();
-    let mode_str = args.get(1).ok_or("Usage: cargo run <logging_mode>")?;
-    let mode = LoggingMode::from_str(mode_str).map_err(|_| "Invalid logging mode")?;
-    
-    let mut logger = create_logger(mode);
-
-    let mut engine = MatchingEngine::new();
-    let instruments = vec!["PUMPTHIS".to_string()];
-
-    for instrument in &instruments {
-        engine.add_market(instrument.clone());
-        println!("Market created for {}", instrument);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "experiment") {
+        let config = ExperimentConfig::parse(&args[1..])?;
+        let results = run_experiment(&config)?;
+        print_results(&config, &results);
+        append_results(&config.output, &config, &results)?;
+        println!("Appended {} cells to {}", results.len(), config.output);
+        return Ok(());
+    }
+    if args.first().is_some_and(|arg| arg == "audit") {
+        let order_id = args.get(1).ok_or("Usage: cargo run --release audit <order_id> [<audit.csv>]")?;
+        let order_id = uuid::Uuid::parse_str(order_id).map_err(|_| format!("Invalid order id '{}'", order_id))?;
+        let path = args.get(2).map_or(DEFAULT_AUDIT_PATH, String::as_str);
+        print_story(path, &order_id)?;
+        return Ok(());
     }
+    if args.first().is_some_and(|arg| arg == "book") {
+        let usage = "Usage: cargo run --release book <feed.csv> <instrument> <seq:<n>|time:<ns>>";
+        let [path, instrument, at] = &args[1..] else { return Err(usage.into()) };
+        reconstruct_book_from(path, instrument, ReplayPoint::from_str(at)?)?.print();
+        return Ok(());
+    }
+    // Printed as is rather than through `Debug`, so the usage keeps its lines.
+    let cli = CliArgs::parse(&args).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        std::process::exit(2);
+    });
+
+    #[cfg(feature = "profiling")]
+    let profiler = exchange_matching_engine::profiling::FlamegraphProfiler::start(1000)?;
 
-    let operations = load_operations("operations.csv")?;
+    let log_timings = cli.log_timings.then(LogTimings::default);
+    let logger = match &log_timings {
+        Some(timings) => create_timed_logger(&cli.modes, &cli.logger_options, timings),
+        None => create_multi_logger(&cli.modes, &cli.logger_options),
+    };
+    let mut logger = with_event_filter(logger, cli.log_events);
 
-    let mut latencies: Vec<(u128, u128)> = Vec::with_capacity(operations.len());
+    if let Some(core) = cli.engine_core {
+        pin_current_thread(core, "matching engine");
+    }
 
-    let start = Instant::now();
-    if let Err(e) = run_simulation(&mut logger, &mut engine, &operations, &mut latencies) {
-        eprintln!("Application error: {}", e);
+    let mut builder = MatchingEngine::builder()
+        .instrument("PUMPTHIS", InstrumentConfig {
+            price_collar: cli.price_collar,
+            price_band: cli.price_band,
+            precision: cli.precision,
+            price_grouping: cli.price_grouping,
+            perpetual: cli.perpetual,
+            index: cli.follow_index,
+            matching: cli.matching,
+            speed_bump: cli.speed_bump,
+            batch_interval: cli.batch_interval,
+            ..Default::default()
+        })
+        .auto_create_markets(true)
+        .audit_trail(cli.audit)
+        .check_crossed(cli.check_crossed)
+        .trade_ids(cli.trade_ids)
+        .capacity_limits(cli.capacity);
+    for index in cli.indexes {
+        builder = builder.index(index);
+    }
+    for (instrument, contract) in cli.options {
+        builder = builder.instrument(instrument, InstrumentConfig { option: Some(contract), ..Default::default() });
+    }
+    if let Some(checksum_every) = cli.shadow {
+        builder = builder.shadow_books(checksum_every);
+    }
+    if let Some(levels) = cli.imbalance_levels {
+        builder = builder.imbalance_levels(levels);
+    }
+    if let Some((requirement, collateral)) = cli.margin {
+        builder = builder.margin(MarginBook::new(requirement).default_collateral(collateral));
+    }
+    if let Some(policy) = cli.otr_cap {
+        builder = builder.otr_policy(policy);
+    }
+    if let Some(dir) = &cli.export_lobster {
+        builder = builder.subscriber(Box::new(LobsterExporter::new(dir, cli.lobster_levels)?));
+    }
+    if let Some(dir) = &cli.confirmations {
+        builder = builder.subscriber(Box::new(ConfirmationSink::new(ConfirmationFiles::create(dir)?)));
+    }
+    let stream = match &cli.stream {
+        Some(target) => {
+            let transport = target.connect().map_err(|e| format!("{:?}: {}", target, e))?;
+            let sink = StreamSink::new(&cli.stream_prefix, transport, WorkerOptions::default());
+            builder = builder.subscriber(Box::new(sink.clone()));
+            Some(sink)
+        }
+        None => None,
+    };
+    if !cli.feeds.is_empty() || stream.is_some() {
+        let mut publisher = MarketDataPublisher::new();
+        if let Some(sink) = stream {
+            publisher = publisher.subscribe_to(cli.stream_feed, Box::new(sink));
+        }
+        for feed in &cli.feeds {
+            let file = File::create(&feed.path).map_err(|e| format!("{}: {}", feed.path, e))?;
+            publisher = publisher.subscribe_to(feed.kind, Box::new(FeedWriter::new(BufWriter::new(file))));
+        }
+        builder = builder.subscriber(Box::new(publisher));
+    }
+    #[cfg(feature = "otel")]
+    let telemetry = match &cli.otlp {
+        Some(endpoint) => {
+            let telemetry = exchange_matching_engine::telemetry::Telemetry::start(endpoint)?;
+            builder = builder.subscriber(Box::new(telemetry.engine_metrics()));
+            println!("Exporting metrics and order spans to {}", endpoint);
+            Some(telemetry)
+        }
+        None => None,
+    };
+    let mut engine = builder.build()?;
+
+    for instrument in engine.instruments() {
+        println!("Market created for {}", instrument);
     }
+
+    if let Some(path) = &cli.load_snapshot {
+        let restored = load_snapshot(path, &mut engine)?;
+        println!("Restored {} resting orders from {}", restored, path);
+    }
+
+    let mut control = match &cli.control {
+        Some(input) => {
+            let mut control = SimulationControl::open(input, cli.start_paused)?;
+            for breakpoint in &cli.breakpoints {
+                control.add_breakpoint(breakpoint.clone());
+            }
+            Some(control)
+        }
+        None => None,
+    };
+    let mut admin = match &cli.admin {
+        Some(address) => {
+            let server = AdminServer::start(address).map_err(|e| format!("{}: {}", address, e))?;
+            println!("Admin API listening on http://{}", server.local_addr());
+            Some(server)
+        }
+        None => None,
+    };
+    let mut latencies: Vec<(u128, u128)> = Vec::new();
+    let mut governor = cli.rate.map(SpeedGovernor::new);
+
+    let start;
+    let result = match (&cli.listen, &cli.sessions) {
+        (Some(endpoint), Some(sessions)) => {
+            let credentials = Credentials::load(sessions)?;
+            let mut gateway = Gateway::start(OperationListener::bind(endpoint)?, credentials)?;
+            println!("Gateway accepting sessions on {}", gateway.endpoint());
+            memory.end_phase("load");
+            start = Instant::now();
+            run_server(&mut logger, &mut engine, &mut gateway, &mut latencies, control.as_mut(), admin.as_mut(), cli.fail_fast)
+        }
+        (Some(endpoint), None) => {
+            let listener = OperationListener::bind(endpoint)?;
+            println!("Waiting for an operation feed on {}", listener.local_endpoint()?);
+            let mut source = listener.accept()?;
+            memory.end_phase("load");
+            start = Instant::now();
+            run_server(&mut logger, &mut engine, &mut source, &mut latencies, control.as_mut(), admin.as_mut(), cli.fail_fast)
+        }
+        (None, _) if !cli.imports.is_empty() => {
+            let mut imported = Imported::default();
+            for spec in &cli.imports {
+                imported.extend(import_file(spec, &cli.import_instrument)?);
+            }
+            println!(
+                "Imported {} operations into {} ({} records without an engine equivalent skipped)",
+                imported.operations.len(), cli.import_instrument, imported.skipped
+            );
+            latencies.reserve(imported.operations.len());
+            memory.end_phase("load");
+            start = Instant::now();
+            match governor.as_mut() {
+                Some(governor) => run_governed(&mut logger, &mut engine, &imported.operations, &mut latencies, control.as_mut(), governor, cli.fail_fast),
+                None => run_simulation_with(&mut logger, &mut engine, &imported.operations, &mut latencies, control.as_mut(), cli.fail_fast),
+            }
+        }
+        (None, _) => {
+            let parsed = load_operations("operations.csv", cli.parse_policy)?;
+            parsed.print_summary();
+            latencies.reserve(parsed.operations.len());
+            let every = cli.checkpoint_every.unwrap_or(DEFAULT_CHECKPOINT_INTERVAL);
+            let mut checkpointer = if cli.resume {
+                let checkpointer = Checkpointer::resume(DEFAULT_CHECKPOINT_DIR, every, &mut engine, &mut latencies)?;
+                println!("Resuming after operation {} from {}", checkpointer.completed(), DEFAULT_CHECKPOINT_DIR);
+                Some(checkpointer)
+            } else if cli.checkpoint_every.is_some() {
+                Some(Checkpointer::new(DEFAULT_CHECKPOINT_DIR, every)?)
+            } else {
+                None
+            };
+            memory.end_phase("load");
+            start = Instant::now();
+            match (checkpointer.as_mut(), governor.as_mut()) {
+                (Some(checkpointer), _) => run_checkpointed(&mut logger, &mut engine, &parsed.operations, &mut latencies, control.as_mut(), checkpointer, cli.fail_fast),
+                (None, Some(governor)) => run_governed(&mut logger, &mut engine, &parsed.operations, &mut latencies, control.as_mut(), governor, cli.fail_fast),
+                (None, None) => run_simulation_with(&mut logger, &mut engine, &parsed.operations, &mut latencies, control.as_mut(), cli.fail_fast),
+            }
+        }
+    };
+    memory.end_phase("simulate");
+    let mut report = match result {
+        Ok(report) => Some(report),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            None
+        }
+    };
+    let instruments = engine.instruments();
     display_final_matching_engine(&instruments, &engine);
-    println!("Simulation completed in {:.2?}", start.elapsed());
+    let elapsed = start.elapsed();
+    println!("Simulation completed in {:.2?}", elapsed);
+    if let Some(governor) = &governor {
+        governor.print_summary();
+    }
 
     report_latencies(&latencies);
+    if let Some(path) = &cli.save_run {
+        let modes: Vec<String> = cli.modes.iter().map(|mode| format!("{:?}", mode)).collect();
+        match RunRecord::new(modes.join("+"), elapsed.as_nanos(), &latencies) {
+            Some(record) => {
+                record.save(path)?;
+                println!("Run saved to {}", path);
+            }
+            None => eprintln!("No latencies recorded, {} not written", path),
+        }
+    }
+    if let Some(timings) = &log_timings {
+        timings.print();
+    }
+    if let Some(report) = &report {
+        report.print_summary();
+        print_leaderboard(&report.leaderboard, cli.leaderboard);
+        let settlement = report.clearing.settle(&cli.fees);
+        settlement.print();
+        if let Some(path) = &cli.settlement {
+            settlement.write_csv(path).map_err(|e| format!("{}: {}", path, e))?;
+            println!("Settlement written to {}", path);
+        }
+        report.markouts.print_curves();
+    }
+    print_message_counts(engine.message_accounting());
+    if let Some(margin) = engine.margin() {
+        print_margin_calls(margin);
+    }
 
     let finalize_start = Instant::now();
-    logger.finalize();
+    let logger_stats = logger.finalize();
     let finalize_duration = finalize_start.elapsed().as_nanos();
     println!("Logger finalize took {} ns", finalize_duration);
+    print_logger_stats(&logger_stats);
+    memory.end_phase("finalize");
+    if let Some(report) = &mut report {
+        report.memory = memory.into_phases();
+        report.loggers = logger_stats;
+        report.print_memory();
+    }
+
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
+
+    #[cfg(feature = "profiling")]
+    {
+        exchange_matching_engine::profiling::print_scopes();
+        profiler.write_flamegraph("output_logs/flamegraph.svg")?;
+        println!("Flamegraph written to output_logs/flamegraph.svg");
+    }
+
+    if let Some(path) = &cli.save_snapshot {
+        let saved = save_snapshot(path, &instruments, &engine)?;
+        println!("Saved {} resting orders to {}", saved, path);
+    }
+    if let Some(audit) = engine.audit() {
+        audit.write_csv(DEFAULT_AUDIT_PATH)?;
+        println!("Audit trail of {} orders written to {}", audit.orders(), DEFAULT_AUDIT_PATH);
+    }
+    engine.verify_shadow_books();
+    if let Some(shadow) = engine.shadow_books() {
+        match shadow.divergence() {
+            Some(divergence) => println!("Shadow book diverged after {} matching operations, at {}", shadow.compared(), divergence),
+            None => println!("Shadow book agreed on all {} matching operations", shadow.compared()),
+        }
+        for (instrument, reason) in shadow.unshadowed() {
+            println!("  {} not shadowed: {}", instrument, reason);
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,338 @@
+//! Margin for leveraged client accounts. Every fill marks the instrument to
+//! its price; an account whose equity falls below its maintenance margin
+//! gets a margin call and its positions are closed out by the engine.
+
+use crate::clients::ClientId;
+use crate::funding::{funding_payment, FundingRound};
+use crate::options::OptionExpiry;
+use crate::order::Order;
+use crate::positions::Position;
+use crate::trade::Trade;
+use crate::utils::{MatchingEngineError, Side};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Margin as fractions of a position's marked notional. Opening or adding
+/// to a position needs `initial`; below `maintenance` the account is
+/// liquidated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarginRequirement {
+    pub initial: Decimal,
+    pub maintenance: Decimal,
+}
+
+impl MarginRequirement {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.maintenance <= Decimal::ZERO || self.initial > Decimal::ONE {
+            return Err("margin must be a fraction of notional above 0 and at most 1".to_string());
+        }
+        if self.maintenance > self.initial {
+            return Err("maintenance margin cannot exceed initial margin".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MarginRequirement {
+    type Err = String;
+
+    /// Parses `<initial>:<maintenance>`, e.g. `0.1:0.05` for 10x leverage.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid margin '{}', expected <initial>:<maintenance>", s);
+        let (initial, maintenance) = s.split_once(':').ok_or_else(invalid)?;
+        let requirement = MarginRequirement {
+            initial: Decimal::from_str(initial).map_err(|_| invalid())?,
+            maintenance: Decimal::from_str(maintenance).map_err(|_| invalid())?,
+        };
+        requirement.validate().map_err(|e| format!("Invalid margin '{}': {}", s, e))?;
+        Ok(requirement)
+    }
+}
+
+/// An account's equity fell below its maintenance margin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarginCall {
+    pub client: ClientId,
+    pub equity: Decimal,
+    pub maintenance: Decimal,
+    pub timestamp: u64,
+}
+
+/// An order the engine sent to close out an account after a margin call.
+#[derive(Debug, Clone)]
+pub struct Liquidation {
+    pub client: ClientId,
+    pub order: Order,
+    pub trades: Vec<Trade>,
+    pub log_duration: u128,
+}
+
+/// Collateral and positions of one leveraged client. Cash paid or received
+/// for fills is in the positions; `collateral` is what was deposited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarginAccount {
+    pub collateral: Decimal,
+    pub positions: BTreeMap<String, Position>,
+}
+
+/// The margin accounts of a venue and the marks they are valued at.
+#[derive(Debug, Clone)]
+pub struct MarginBook {
+    requirement: MarginRequirement,
+    instruments: HashMap<String, MarginRequirement>,
+    /// Collateral of the account opened for a client on its first order, if
+    /// clients get one without being opened explicitly.
+    default_collateral: Option<Decimal>,
+    accounts: BTreeMap<ClientId, MarginAccount>,
+    marks: HashMap<String, Decimal>,
+    calls: Vec<MarginCall>,
+}
+
+impl MarginBook {
+    pub fn new(requirement: MarginRequirement) -> Self {
+        MarginBook {
+            requirement,
+            instruments: HashMap::new(),
+            default_collateral: None,
+            accounts: BTreeMap::new(),
+            marks: HashMap::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Sets the margin of one instrument, instead of the venue-wide one.
+    pub fn instrument(mut self, instrument: impl Into<String>, requirement: MarginRequirement) -> Self {
+        self.instruments.insert(instrument.into(), requirement);
+        self
+    }
+
+    /// Opens an account with `collateral` for every client on its first
+    /// order.
+    pub fn default_collateral(mut self, collateral: Decimal) -> Self {
+        self.default_collateral = Some(collateral);
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.requirement.validate()?;
+        self.instruments.values().try_for_each(MarginRequirement::validate)
+    }
+
+    fn requirement(&self, instrument: &str) -> &MarginRequirement {
+        self.instruments.get(instrument).unwrap_or(&self.requirement)
+    }
+
+    /// Opens an account for `client`, or adds `collateral` to its account.
+    pub fn deposit(&mut self, client: &ClientId, collateral: Decimal) {
+        self.accounts.entry(client.clone()).or_default().collateral += collateral;
+    }
+
+    pub fn account(&self, client: &ClientId) -> Option<&MarginAccount> {
+        self.accounts.get(client)
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<ClientId, MarginAccount> {
+        &self.accounts
+    }
+
+    pub fn calls(&self) -> &[MarginCall] {
+        &self.calls
+    }
+
+    pub fn mark(&self, instrument: &str) -> Option<Decimal> {
+        self.marks.get(instrument).copied()
+    }
+
+    /// Collateral plus the marked value of every position. Positions in an
+    /// instrument without a mark count at the cash paid for them.
+    pub fn equity(&self, client: &ClientId) -> Option<Decimal> {
+        self.accounts.get(client).map(|account| self.equity_of(account))
+    }
+
+    fn equity_of(&self, account: &MarginAccount) -> Decimal {
+        let marked: Decimal = account
+            .positions
+            .iter()
+            .map(|(instrument, position)| position.cash + position.quantity * self.mark(instrument).unwrap_or(Decimal::ZERO))
+            .sum();
+        account.collateral + marked
+    }
+
+    /// The maintenance margin of the account's positions at their marks.
+    pub fn maintenance_margin(&self, client: &ClientId) -> Decimal {
+        let Some(account) = self.accounts.get(client) else { return Decimal::ZERO };
+        account
+            .positions
+            .iter()
+            .filter_map(|(instrument, position)| Some(position.quantity.abs() * self.mark(instrument)? * self.requirement(instrument).maintenance))
+            .sum()
+    }
+
+    /// Rejects an order that would take the account's positions beyond what
+    /// its equity covers at initial margin, if it filled completely. Orders
+    /// that only reduce a position always pass, as do clients without an
+    /// account when accounts are not opened automatically. A client without
+    /// one is checked against the account its first order would open.
+    pub fn check_order(&self, client: &ClientId, order: &Order) -> Result<(), MatchingEngineError> {
+        let opened;
+        let account = match (self.accounts.get(client), self.default_collateral) {
+            (Some(account), _) => account,
+            (None, Some(collateral)) => {
+                opened = MarginAccount { collateral, ..Default::default() };
+                &opened
+            }
+            (None, None) => return Ok(()),
+        };
+        let current = account.positions.get(&order.instrument).map_or(Decimal::ZERO, |position| position.quantity);
+        let projected = match order.side {
+            Side::Buy => current + order.quantity,
+            Side::Sell => current - order.quantity,
+        };
+        if projected.abs() <= current.abs() {
+            return Ok(());
+        }
+        let Some(price) = order.price.or_else(|| self.mark(&order.instrument)) else {
+            return Ok(());
+        };
+        let others: Decimal = account
+            .positions
+            .iter()
+            .filter(|(instrument, _)| **instrument != order.instrument)
+            .filter_map(|(instrument, position)| Some(position.quantity.abs() * self.mark(instrument)? * self.requirement(instrument).initial))
+            .sum();
+        let required = others + projected.abs() * price * self.requirement(&order.instrument).initial;
+        let equity = self.equity_of(account);
+        if equity < required {
+            return Err(MatchingEngineError::InsufficientMargin { client: client.to_string(), equity, required });
+        }
+        Ok(())
+    }
+
+    /// Opens the account of `client` with the default collateral, if
+    /// accounts are opened automatically and it has none yet.
+    pub fn open_account(&mut self, client: &ClientId) {
+        if let Some(collateral) = self.default_collateral
+            && !self.accounts.contains_key(client) {
+            self.deposit(client, collateral);
+        }
+    }
+
+    /// Marks the trade's instrument and books its fill to `client` on
+    /// `side`, if the client has an account.
+    pub fn record_fill(&mut self, client: Option<&ClientId>, side: Side, trade: &Trade) {
+        self.marks.insert(trade.instrument.clone(), trade.price);
+        let Some(account) = client.and_then(|client| self.accounts.get_mut(client)) else { return };
+        let position = account.positions.entry(trade.instrument.clone()).or_default();
+        let notional = trade.price * trade.quantity;
+        match side {
+            Side::Buy => {
+                position.quantity += trade.quantity;
+                position.cash -= notional;
+            }
+            Side::Sell => {
+                position.quantity -= trade.quantity;
+                position.cash += notional;
+            }
+        }
+    }
+
+    /// Pays or charges the funding of `round` to every account holding its
+    /// instrument.
+    pub fn apply_funding(&mut self, round: &FundingRound) {
+        for account in self.accounts.values_mut() {
+            if let Some(position) = account.positions.get_mut(&round.instrument) {
+                position.cash += funding_payment(position.quantity, round);
+            }
+        }
+    }
+
+    /// Exercises every account's position in the expiring option into the
+    /// underlying.
+    pub fn exercise(&mut self, expiry: &OptionExpiry) {
+        for account in self.accounts.values_mut() {
+            expiry.exercise(&mut account.positions);
+        }
+    }
+
+    /// Records a margin call for every account with open positions whose
+    /// equity is below its maintenance margin, and returns those clients.
+    pub fn margin_calls(&mut self, timestamp: u64) -> Vec<ClientId> {
+        let called: Vec<MarginCall> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.positions.values().any(|position| !position.quantity.is_zero()))
+            .filter_map(|(client, _)| {
+                let (equity, maintenance) = (self.equity(client)?, self.maintenance_margin(client));
+                (equity < maintenance).then(|| MarginCall { client: client.clone(), equity, maintenance, timestamp })
+            })
+            .collect();
+        let clients = called.iter().map(|call| call.client.clone()).collect();
+        self.calls.extend(called);
+        clients
+    }
+}
+
+pub fn print_margin_calls(margin: &MarginBook) {
+    if margin.calls().is_empty() {
+        return;
+    }
+    println!("\n--- Margin Calls ---");
+    println!("{:<16} {:>8} {:>16} {:>16} {:>16}", "Client", "Calls", "Last equity", "Maintenance", "Equity now");
+    let mut last: BTreeMap<&ClientId, (usize, &MarginCall)> = BTreeMap::new();
+    for call in margin.calls() {
+        let entry = last.entry(&call.client).or_insert((0, call));
+        *entry = (entry.0 + 1, call);
+    }
+    for (client, (calls, call)) in last {
+        let now = margin.equity(client).unwrap_or_default();
+        println!("{:<16} {:>8} {:>16} {:>16} {:>16}", client.to_string(), calls, call.equity.round_dp(4), call.maintenance.round_dp(4), now.round_dp(4));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn fill(price: Decimal, quantity: Decimal) -> Trade {
+        Trade::new("SOFI".to_string(), price, quantity, Uuid::new_v4(), Uuid::new_v4(), Side::Buy)
+    }
+
+    #[test]
+    fn test_parse_margin_requirement() {
+        assert_eq!("0.1:0.05".parse::<MarginRequirement>(), Ok(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }));
+        assert!("0.05:0.1".parse::<MarginRequirement>().is_err());
+        assert!("0.1".parse::<MarginRequirement>().is_err());
+        assert!("2:0.5".parse::<MarginRequirement>().is_err());
+    }
+
+    #[test]
+    fn test_initial_margin_and_margin_calls() {
+        let alice = ClientId::new("alice");
+        let mut margin = MarginBook::new(MarginRequirement { initial: dec!(0.1), maintenance: dec!(0.05) }).default_collateral(dec!(100));
+        // 100 of collateral supports 1000 of notional at 10%.
+        let too_big = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(101));
+        assert!(matches!(margin.check_order(&alice, &too_big), Err(MatchingEngineError::InsufficientMargin { .. })));
+        let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(100));
+        margin.check_order(&alice, &order).unwrap();
+        // Checking is not opening.
+        assert_eq!(margin.account(&alice), None);
+        margin.open_account(&alice);
+        margin.record_fill(Some(&alice), Side::Buy, &fill(dec!(10), dec!(100)));
+        assert_eq!(margin.equity(&alice), Some(dec!(100)));
+
+        // A drop to 9.6 leaves 60 of equity against 48 of maintenance.
+        margin.record_fill(None, Side::Buy, &fill(dec!(9.6), dec!(1)));
+        assert!(margin.margin_calls(1).is_empty());
+        // At 9.4, 40 of equity is below 47.
+        margin.record_fill(None, Side::Buy, &fill(dec!(9.4), dec!(1)));
+        assert_eq!(margin.margin_calls(2), vec![alice.clone()]);
+        assert_eq!(margin.calls()[0].maintenance, dec!(47));
+
+        // Selling is reducing, so it passes even under water.
+        let close = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100));
+        margin.check_order(&alice, &close).unwrap();
+    }
+}
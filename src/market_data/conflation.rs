@@ -0,0 +1,156 @@
+use super::depth::DepthBook;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const DEFAULT_CONFLATED_LEVELS: usize = 10;
+/// Levels per side that fit in a changed-levels bitmap.
+pub const MAX_CONFLATED_LEVELS: usize = 32;
+
+/// How often a conflated subscriber hears about an instrument and how much of
+/// the book it gets each time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflation {
+    pub interval: Duration,
+    pub levels: usize,
+}
+
+impl Conflation {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, levels: DEFAULT_CONFLATED_LEVELS }
+    }
+
+    pub fn with_levels(mut self, levels: usize) -> Self {
+        self.levels = levels.clamp(1, MAX_CONFLATED_LEVELS);
+        self
+    }
+}
+
+/// The top of one instrument's book as of the end of an interval. Bit `i` of
+/// a changed bitmap is set when level `i` of that side differs from the
+/// previous update the subscriber received.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConflatedBook {
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+    pub changed_bids: u32,
+    pub changed_asks: u32,
+}
+
+#[derive(Default)]
+struct InstrumentState {
+    last_sent_at: Option<u64>,
+    bids: Vec<(Decimal, Decimal)>,
+    asks: Vec<(Decimal, Decimal)>,
+    pending: bool,
+}
+
+/// Coalesces book changes so each instrument produces at most one update per
+/// interval. The first change after a quiet interval goes out immediately;
+/// later ones wait until the interval has passed.
+pub struct Conflator {
+    conflation: Conflation,
+    instruments: HashMap<String, InstrumentState>,
+}
+
+impl Conflator {
+    pub fn new(conflation: Conflation) -> Self {
+        Self { conflation, instruments: HashMap::new() }
+    }
+
+    /// Records a change to `instrument` and returns an update if one is due.
+    pub fn on_change(&mut self, instrument: &str, timestamp: u64, depth: &DepthBook) -> Option<ConflatedBook> {
+        let interval = self.conflation.interval.as_nanos() as u64;
+        let state = self.instruments.entry(instrument.to_string()).or_default();
+        state.pending = true;
+        let due = state.last_sent_at.is_none_or(|sent| timestamp.saturating_sub(sent) >= interval);
+        if !due {
+            return None;
+        }
+        Self::take_update(state, self.conflation.levels, timestamp, depth)
+    }
+
+    /// Instruments with changes that have not been sent yet.
+    pub fn pending(&self) -> impl Iterator<Item = &str> {
+        self.instruments.iter().filter(|(_, state)| state.pending).map(|(instrument, _)| instrument.as_str())
+    }
+
+    /// Sends whatever is pending for `instrument` regardless of the interval.
+    pub fn flush(&mut self, instrument: &str, timestamp: u64, depth: &DepthBook) -> Option<ConflatedBook> {
+        let state = self.instruments.get_mut(instrument)?;
+        if !state.pending {
+            return None;
+        }
+        Self::take_update(state, self.conflation.levels, timestamp, depth)
+    }
+
+    fn take_update(state: &mut InstrumentState, levels: usize, timestamp: u64, depth: &DepthBook) -> Option<ConflatedBook> {
+        state.pending = false;
+        let bids: Vec<_> = depth.bids().take(levels).collect();
+        let asks: Vec<_> = depth.asks().take(levels).collect();
+        let changed_bids = changed_levels(&state.bids, &bids);
+        let changed_asks = changed_levels(&state.asks, &asks);
+        if changed_bids == 0 && changed_asks == 0 {
+            return None;
+        }
+
+        state.last_sent_at = Some(timestamp);
+        state.bids.clone_from(&bids);
+        state.asks.clone_from(&asks);
+        Some(ConflatedBook { bids, asks, changed_bids, changed_asks })
+    }
+}
+
+fn changed_levels(before: &[(Decimal, Decimal)], after: &[(Decimal, Decimal)]) -> u32 {
+    (0..before.len().max(after.len()))
+        .filter(|&i| before.get(i) != after.get(i))
+        .fold(0, |bitmap, i| bitmap | (1 << i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_changes_within_interval_are_coalesced() {
+        let mut conflator = Conflator::new(Conflation::new(Duration::from_nanos(100)).with_levels(2));
+        let mut depth = DepthBook::default();
+
+        depth.apply(Side::Buy, dec!(10), dec!(5));
+        let first = conflator.on_change("SOFI", 0, &depth).unwrap();
+        assert_eq!(first.bids, vec![(dec!(10), dec!(5))]);
+        assert_eq!(first.changed_bids, 0b1);
+
+        depth.apply(Side::Buy, dec!(11), dec!(1));
+        assert_eq!(conflator.on_change("SOFI", 40, &depth), None);
+        depth.apply(Side::Sell, dec!(12), dec!(3));
+        assert_eq!(conflator.on_change("SOFI", 80, &depth), None);
+        assert_eq!(conflator.pending().collect::<Vec<_>>(), vec!["SOFI"]);
+
+        depth.apply(Side::Sell, dec!(12), dec!(1));
+        let second = conflator.on_change("SOFI", 120, &depth).unwrap();
+        assert_eq!(second.bids, vec![(dec!(11), dec!(1)), (dec!(10), dec!(5))]);
+        assert_eq!(second.asks, vec![(dec!(12), dec!(4))]);
+        assert_eq!(second.changed_bids, 0b11);
+        assert_eq!(second.changed_asks, 0b1);
+        assert_eq!(conflator.pending().count(), 0);
+    }
+
+    #[test]
+    fn test_flush_sends_pending_changes_early() {
+        let mut conflator = Conflator::new(Conflation::new(Duration::from_secs(1)));
+        let mut depth = DepthBook::default();
+        depth.apply(Side::Sell, dec!(12), dec!(3));
+        conflator.on_change("SOFI", 0, &depth).unwrap();
+
+        depth.apply(Side::Sell, dec!(12), dec!(-3));
+        assert_eq!(conflator.on_change("SOFI", 10, &depth), None);
+        let flushed = conflator.flush("SOFI", 20, &depth).unwrap();
+        assert!(flushed.asks.is_empty());
+        assert_eq!(flushed.changed_asks, 0b1);
+        assert_eq!(conflator.flush("SOFI", 30, &depth), None);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Resting quantity aggregated per price level, rebuilt from the engine's
+/// event stream by subscribers that have no access to the order book itself.
+#[derive(Debug, Default, Clone)]
+pub struct DepthBook {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl DepthBook {
+    /// Adds `delta` (negative to remove) to a level and returns the new
+    /// quantity there. Levels that reach zero are dropped.
+    pub fn apply(&mut self, side: Side, price: Decimal, delta: Decimal) -> Decimal {
+        let levels = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        let level = levels.entry(price).or_default();
+        *level += delta;
+        if *level <= Decimal::ZERO {
+            levels.remove(&price);
+            return Decimal::ZERO;
+        }
+        *level
+    }
+
+    pub fn quantity_at(&self, side: Side, price: Decimal) -> Decimal {
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        levels.get(&price).copied().unwrap_or_default()
+    }
+
+    /// Bid levels as `(price, quantity)`, best first.
+    pub fn bids(&self) -> impl Iterator<Item = (Decimal, Decimal)> + '_ {
+        self.bids.iter().rev().map(|(price, quantity)| (*price, *quantity))
+    }
+
+    /// Ask levels as `(price, quantity)`, best first.
+    pub fn asks(&self) -> impl Iterator<Item = (Decimal, Decimal)> + '_ {
+        self.asks.iter().map(|(price, quantity)| (*price, *quantity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_levels_aggregate_and_drop_at_zero() {
+        let mut depth = DepthBook::default();
+        assert_eq!(depth.apply(Side::Buy, dec!(10), dec!(5)), dec!(5));
+        assert_eq!(depth.apply(Side::Buy, dec!(10), dec!(3)), dec!(8));
+        depth.apply(Side::Buy, dec!(11), dec!(1));
+        depth.apply(Side::Sell, dec!(12), dec!(2));
+        depth.apply(Side::Sell, dec!(13), dec!(4));
+
+        assert_eq!(depth.bids().collect::<Vec<_>>(), vec![(dec!(11), dec!(1)), (dec!(10), dec!(8))]);
+        assert_eq!(depth.asks().next(), Some((dec!(12), dec!(2))));
+        assert_eq!(depth.apply(Side::Buy, dec!(10), dec!(-8)), Decimal::ZERO);
+        assert_eq!(depth.quantity_at(Side::Buy, dec!(10)), Decimal::ZERO);
+        assert_eq!(depth.bids().count(), 1);
+    }
+}
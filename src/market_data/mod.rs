@@ -0,0 +1,628 @@
+pub mod conflation;
+pub mod depth;
+pub mod queue;
+pub mod reconstruct;
+pub mod replay;
+pub mod stream;
+
+use crate::clock::{Clock, SystemClock};
+use crate::trade::Trade;
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::utils::{Side, UuidMap};
+use conflation::{Conflation, ConflatedBook, Conflator};
+use depth::DepthBook;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The two standard feed flavours. Market-by-order carries every change to an
+/// individual resting order; market-by-price only the resulting total at each
+/// price level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedMode {
+    ByOrder,
+    ByPrice,
+}
+
+impl FromStr for FeedMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mbo" | "by-order" => Ok(FeedMode::ByOrder),
+            "mbp" | "by-price" => Ok(FeedMode::ByPrice),
+            other => Err(format!("Unknown feed mode '{}', expected 'mbo' or 'mbp'", other)),
+        }
+    }
+}
+
+/// What a feed subscriber receives: every change in one of the two live
+/// flavours, or the top of the book at most once per interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedKind {
+    Live(FeedMode),
+    Conflated(Conflation),
+}
+
+impl FromStr for FeedKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('@') {
+            Some(("conflated", millis)) => {
+                let millis = millis
+                    .strip_suffix("ms")
+                    .unwrap_or(millis)
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid conflation interval '{}'", millis))?;
+                Ok(FeedKind::Conflated(Conflation::new(Duration::from_millis(millis))))
+            }
+            Some(_) => Err(format!("Unknown feed '{}', expected 'mbo', 'mbp' or 'conflated@<ms>'", s)),
+            None => Ok(FeedKind::Live(FeedMode::from_str(s)?)),
+        }
+    }
+}
+
+/// A feed written to a file, given on the command line as `<kind>:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedSpec {
+    pub kind: FeedKind,
+    pub path: String,
+}
+
+impl FromStr for FeedSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid feed '{}', expected <mbo|mbp|conflated@<ms>>:<path>", s))?;
+        Ok(FeedSpec { kind: FeedKind::from_str(kind)?, path: path.to_string() })
+    }
+}
+
+/// A change to one resting order, as sent on a market-by-order feed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum OrderUpdate {
+    Add { order_id: Uuid, side: Side, price: Decimal, quantity: Decimal },
+    /// `quantity` of the resting order traded at its price.
+    Execute { order_id: Uuid, side: Side, price: Decimal, quantity: Decimal, trade_id: Uuid },
+    /// The remaining `quantity` of the order was canceled.
+    Delete { order_id: Uuid, side: Side, price: Decimal, quantity: Decimal },
+}
+
+/// The new total resting quantity at a price level, as sent on a
+/// market-by-price feed. Zero means the level is gone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LevelUpdate {
+    pub side: Side,
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum FeedBody {
+    Order(OrderUpdate),
+    Level(LevelUpdate),
+    Book(ConflatedBook),
+    /// `missed` messages before this one were dropped for a slow consumer.
+    Gap { missed: u64 },
+    /// An earlier trade was busted. The book is unchanged: the orders it
+    /// filled are not reinstated.
+    Bust { trade_id: Uuid, price: Decimal, quantity: Decimal },
+}
+
+/// One message on a feed. Sequence numbers are per subscription and start at
+/// 1, so a consumer can detect a gap in what it received.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeedMessage {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub instrument: String,
+    pub body: FeedBody,
+}
+
+impl fmt::Display for FeedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},", self.sequence, self.timestamp, self.instrument)?;
+        match &self.body {
+            FeedBody::Order(OrderUpdate::Add { order_id, side, price, quantity }) => {
+                write!(f, "ADD,{},{},{},{}", order_id, side, price, quantity)
+            }
+            FeedBody::Order(OrderUpdate::Execute { order_id, side, price, quantity, trade_id }) => {
+                write!(f, "EXECUTE,{},{},{},{},{}", order_id, side, price, quantity, trade_id)
+            }
+            FeedBody::Order(OrderUpdate::Delete { order_id, side, price, quantity }) => {
+                write!(f, "DELETE,{},{},{},{}", order_id, side, price, quantity)
+            }
+            FeedBody::Level(LevelUpdate { side, price, quantity }) => {
+                write!(f, "LEVEL,{},{},{}", side, price, quantity)
+            }
+            FeedBody::Gap { missed } => write!(f, "GAP,{}", missed),
+            FeedBody::Bust { trade_id, price, quantity } => write!(f, "BUST,{},{},{}", trade_id, price, quantity),
+            FeedBody::Book(book) => {
+                let levels = |levels: &[(Decimal, Decimal)]| {
+                    levels.iter().map(|(price, quantity)| format!("{}x{}", quantity, price)).collect::<Vec<_>>().join(" ")
+                };
+                write!(
+                    f,
+                    "BOOK,{:#b},{:#b},{},{}",
+                    book.changed_bids,
+                    book.changed_asks,
+                    levels(&book.bids),
+                    levels(&book.asks)
+                )
+            }
+        }
+    }
+}
+
+/// Parses a line written by [`FeedWriter`], so a recorded feed can be
+/// replayed.
+impl FromStr for FeedMessage {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.trim_end().split(',').collect();
+        let [sequence, timestamp, instrument, kind, rest @ ..] = &fields[..] else {
+            return Err(format!("Invalid feed message '{}'", s));
+        };
+        let number = |field: &str| field.parse::<u64>().map_err(|_| format!("Invalid number '{}' in feed message '{}'", field, s));
+        let decimal = |field: &str| Decimal::from_str(field).map_err(|_| format!("Invalid decimal '{}' in feed message '{}'", field, s));
+        let uuid = |field: &str| Uuid::parse_str(field).map_err(|_| format!("Invalid UUID '{}' in feed message '{}'", field, s));
+        let body = match (*kind, rest) {
+            ("ADD", [order_id, side, price, quantity]) => FeedBody::Order(OrderUpdate::Add {
+                order_id: uuid(order_id)?,
+                side: Side::from_str(side)?,
+                price: decimal(price)?,
+                quantity: decimal(quantity)?,
+            }),
+            ("EXECUTE", [order_id, side, price, quantity, trade_id]) => FeedBody::Order(OrderUpdate::Execute {
+                order_id: uuid(order_id)?,
+                side: Side::from_str(side)?,
+                price: decimal(price)?,
+                quantity: decimal(quantity)?,
+                trade_id: uuid(trade_id)?,
+            }),
+            ("DELETE", [order_id, side, price, quantity]) => FeedBody::Order(OrderUpdate::Delete {
+                order_id: uuid(order_id)?,
+                side: Side::from_str(side)?,
+                price: decimal(price)?,
+                quantity: decimal(quantity)?,
+            }),
+            ("LEVEL", [side, price, quantity]) => FeedBody::Level(LevelUpdate { side: Side::from_str(side)?, price: decimal(price)?, quantity: decimal(quantity)? }),
+            ("GAP", [missed]) => FeedBody::Gap { missed: number(missed)? },
+            ("BUST", [trade_id, price, quantity]) => FeedBody::Bust { trade_id: uuid(trade_id)?, price: decimal(price)?, quantity: decimal(quantity)? },
+            ("BOOK", [changed_bids, changed_asks, bids, asks]) => {
+                let bitmap = |field: &str| {
+                    u32::from_str_radix(field.trim_start_matches("0b"), 2).map_err(|_| format!("Invalid bitmap '{}' in feed message '{}'", field, s))
+                };
+                let levels = |field: &str| {
+                    field
+                        .split_whitespace()
+                        .map(|level| {
+                            let (quantity, price) = level.split_once('x').ok_or_else(|| format!("Invalid level '{}' in feed message '{}'", level, s))?;
+                            Ok((decimal(price)?, decimal(quantity)?))
+                        })
+                        .collect::<Result<Vec<_>, String>>()
+                };
+                FeedBody::Book(ConflatedBook { bids: levels(bids)?, asks: levels(asks)?, changed_bids: bitmap(changed_bids)?, changed_asks: bitmap(changed_asks)? })
+            }
+            _ => return Err(format!("Unknown feed message '{}'", s)),
+        };
+        Ok(FeedMessage { sequence: number(sequence)?, timestamp: number(timestamp)?, instrument: instrument.to_string(), body })
+    }
+}
+
+/// Consumes the messages of one feed subscription.
+pub trait FeedSubscriber: Send {
+    fn on_message(&mut self, message: &FeedMessage);
+
+    /// Returning `false` makes the publisher drop the subscription.
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// Hands messages to another thread. Messages sent after the receiver is
+/// dropped are discarded.
+impl FeedSubscriber for Sender<FeedMessage> {
+    fn on_message(&mut self, message: &FeedMessage) {
+        let _ = self.send(message.clone());
+    }
+}
+
+/// Writes each message as one CSV line.
+pub struct FeedWriter<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> FeedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write + Send> FeedSubscriber for FeedWriter<W> {
+    fn on_message(&mut self, message: &FeedMessage) {
+        if let Err(e) = writeln!(self.writer, "{}", message) {
+            eprintln!("Failed to write market data: {}", e);
+        }
+    }
+}
+
+enum Delivery {
+    Live(FeedMode),
+    Conflated(Conflator),
+}
+
+struct Subscription {
+    delivery: Delivery,
+    sequence: u64,
+    subscriber: Box<dyn FeedSubscriber>,
+}
+
+impl Subscription {
+    fn send(&mut self, instrument: &str, timestamp: u64, body: FeedBody) {
+        self.sequence += 1;
+        let message = FeedMessage { sequence: self.sequence, timestamp, instrument: instrument.to_string(), body };
+        self.subscriber.on_message(&message);
+    }
+}
+
+/// Turns engine events into market data and fans it out to subscribers, each
+/// receiving the feed flavour it asked for.
+pub struct MarketDataPublisher {
+    clock: Box<dyn Clock>,
+    depth: HashMap<String, DepthBook>,
+    /// Displayed price of every resting order, where its executions come off
+    /// the book even when it trades at a better (discretionary) price.
+    resting: UuidMap<Decimal>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Default for MarketDataPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarketDataPublisher {
+    pub fn new() -> Self {
+        Self { clock: Box::new(SystemClock), depth: HashMap::new(), resting: UuidMap::default(), subscriptions: Vec::new() }
+    }
+
+    /// Stamps add and delete messages with `clock` instead of the system
+    /// time. Executions always carry the trade timestamp.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn subscribe(self, mode: FeedMode, subscriber: Box<dyn FeedSubscriber>) -> Self {
+        self.subscribe_to(FeedKind::Live(mode), subscriber)
+    }
+
+    /// Subscribes to at most one book update per instrument per
+    /// `conflation.interval`, for consumers that cannot keep up with every
+    /// change. Updates still pending when the publisher is dropped are sent
+    /// then.
+    pub fn subscribe_conflated(self, conflation: Conflation, subscriber: Box<dyn FeedSubscriber>) -> Self {
+        self.subscribe_to(FeedKind::Conflated(conflation), subscriber)
+    }
+
+    pub fn subscribe_to(mut self, kind: FeedKind, subscriber: Box<dyn FeedSubscriber>) -> Self {
+        let delivery = match kind {
+            FeedKind::Live(mode) => Delivery::Live(mode),
+            FeedKind::Conflated(conflation) => Delivery::Conflated(Conflator::new(conflation)),
+        };
+        self.subscriptions.push(Subscription { delivery, sequence: 0, subscriber });
+        self
+    }
+
+    pub fn subscription_count(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    pub fn depth(&self, instrument: &str) -> Option<&DepthBook> {
+        self.depth.get(instrument)
+    }
+
+    fn publish(&mut self, instrument: &str, timestamp: u64, update: OrderUpdate) {
+        let (side, price, delta) = match &update {
+            OrderUpdate::Add { side, price, quantity, .. } => (*side, *price, *quantity),
+            OrderUpdate::Execute { side, price, quantity, .. } | OrderUpdate::Delete { side, price, quantity, .. } => {
+                (*side, *price, -*quantity)
+            }
+        };
+        let depth = self.depth.entry(instrument.to_string()).or_default();
+        let level_quantity = depth.apply(side, price, delta);
+
+        for subscription in &mut self.subscriptions {
+            let body = match &mut subscription.delivery {
+                Delivery::Live(FeedMode::ByOrder) => FeedBody::Order(update.clone()),
+                Delivery::Live(FeedMode::ByPrice) => FeedBody::Level(LevelUpdate { side, price, quantity: level_quantity }),
+                Delivery::Conflated(conflator) => match conflator.on_change(instrument, timestamp, depth) {
+                    Some(book) => FeedBody::Book(book),
+                    None => continue,
+                },
+            };
+            subscription.send(instrument, timestamp, body);
+        }
+        self.subscriptions.retain(|subscription| subscription.subscriber.is_connected());
+    }
+
+    /// Sends a bust to the live feeds. Conflated feeds only carry the book,
+    /// which a bust does not change.
+    fn publish_bust(&mut self, trade: &Trade) {
+        let now = self.clock.now_nanos();
+        for subscription in &mut self.subscriptions {
+            if let Delivery::Live(_) = subscription.delivery {
+                let body = FeedBody::Bust { trade_id: trade.trade_id, price: trade.price, quantity: trade.quantity };
+                subscription.send(&trade.instrument, now, body);
+            }
+        }
+        self.subscriptions.retain(|subscription| subscription.subscriber.is_connected());
+    }
+}
+
+impl Drop for MarketDataPublisher {
+    fn drop(&mut self) {
+        let now = self.clock.now_nanos();
+        for subscription in &mut self.subscriptions {
+            let Delivery::Conflated(conflator) = &mut subscription.delivery else { continue };
+            let pending: Vec<String> = conflator.pending().map(str::to_string).collect();
+            let books: Vec<_> = pending
+                .into_iter()
+                .filter_map(|instrument| {
+                    let book = conflator.flush(&instrument, now, &self.depth[&instrument])?;
+                    Some((instrument, book))
+                })
+                .collect();
+            for (instrument, book) in books {
+                subscription.send(&instrument, now, FeedBody::Book(book));
+            }
+        }
+    }
+}
+
+impl EngineSubscriber for MarketDataPublisher {
+    fn on_event(&mut self, event: &EngineEvent) {
+        match event {
+            EngineEvent::OrderRested(order) => {
+                if let Some(price) = order.price {
+                    self.resting.insert(order.order_id, price);
+                    let update = OrderUpdate::Add { order_id: order.order_id, side: order.side, price, quantity: order.remaining_quantity };
+                    let now = self.clock.now_nanos();
+                    self.publish(&order.instrument, now, update);
+                }
+            }
+            EngineEvent::OrderCanceled(order) | EngineEvent::OrderExpired(order) => {
+                self.resting.remove(&order.order_id);
+                if let Some(price) = order.price {
+                    let update = OrderUpdate::Delete { order_id: order.order_id, side: order.side, price, quantity: order.remaining_quantity };
+                    let now = self.clock.now_nanos();
+                    self.publish(&order.instrument, now, update);
+                }
+            }
+            EngineEvent::Trade(trade) => {
+                let (order_id, side) = match trade.taker_side {
+                    Side::Buy => (trade.sell_order_id, Side::Sell),
+                    Side::Sell => (trade.buy_order_id, Side::Buy),
+                };
+                let update = OrderUpdate::Execute {
+                    order_id,
+                    side,
+                    price: self.resting.get(&order_id).copied().unwrap_or(trade.price),
+                    quantity: trade.quantity,
+                    trade_id: trade.trade_id,
+                };
+                self.publish(&trade.instrument, trade.timestamp, update);
+            }
+            EngineEvent::OrderRepriced { order, old_price } => {
+                if let Some(price) = order.price {
+                    self.resting.insert(order.order_id, price);
+                    let now = self.clock.now_nanos();
+                    let (order_id, side, quantity) = (order.order_id, order.side, order.remaining_quantity);
+                    self.publish(&order.instrument, now, OrderUpdate::Delete { order_id, side, price: *old_price, quantity });
+                    self.publish(&order.instrument, now, OrderUpdate::Add { order_id, side, price, quantity });
+                }
+            }
+            EngineEvent::OrderFilled { order, .. } => {
+                self.resting.remove(&order.order_id);
+            }
+            EngineEvent::TradeBusted(trade) => self.publish_bust(trade),
+            EngineEvent::OrderAccepted { .. } | EngineEvent::OrderRejected { .. } | EngineEvent::IndexPrice { .. } | EngineEvent::Imbalance { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::order::Order;
+    use rust_decimal_macros::dec;
+    use std::sync::mpsc::{channel, Receiver};
+
+    fn run_feeds() -> (Receiver<FeedMessage>, Receiver<FeedMessage>, Uuid, Uuid) {
+        let (mbo_tx, mbo_rx) = channel();
+        let (mbp_tx, mbp_rx) = channel();
+        let clock = ManualClock::new(1_000);
+        let publisher = MarketDataPublisher::new()
+            .with_clock(clock.clone())
+            .subscribe(FeedMode::ByOrder, Box::new(mbo_tx))
+            .subscribe(FeedMode::ByPrice, Box::new(mbp_tx));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock)
+            .subscriber(Box::new(publisher))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let first = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), dec!(100));
+        let second = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), dec!(40));
+        let (first_id, second_id) = (first.order_id, second.order_id);
+        engine.process_order(first, &mut logger).unwrap();
+        engine.process_order(second, &mut logger).unwrap();
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(30)), &mut logger).unwrap();
+        engine.cancel_order_by_id(&second_id, "SOFI").unwrap();
+        (mbo_rx, mbp_rx, first_id, second_id)
+    }
+
+    #[test]
+    fn test_conflated_feed_coalesces_changes_and_flushes_on_drop() {
+        let (tx, rx) = channel();
+        let clock = ManualClock::new(1_000);
+        let publisher = MarketDataPublisher::new()
+            .with_clock(clock.clone())
+            .subscribe_conflated(Conflation::new(Duration::from_nanos(100)), Box::new(tx));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .clock(clock.clone())
+            .subscriber(Box::new(publisher))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        for quantity in [dec!(100), dec!(40)] {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), quantity), &mut logger).unwrap();
+        }
+        clock.advance(50);
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(30)), &mut logger).unwrap();
+        assert_eq!(rx.try_iter().count(), 1);
+
+        drop(engine);
+        let flushed = rx.try_recv().unwrap();
+        assert_eq!(flushed.sequence, 2);
+        let FeedBody::Book(book) = flushed.body else { panic!("expected a book update") };
+        assert_eq!(book.asks, vec![(dec!(10.5), dec!(110))]);
+        assert_eq!(book.changed_asks, 0b1);
+        assert_eq!(book.changed_bids, 0);
+    }
+
+    #[test]
+    fn test_evicted_subscription_is_dropped() {
+        let (feed, receiver) = queue::bounded_feed(1, queue::SlowConsumerPolicy::Disconnect);
+        let (live, _live_rx) = channel();
+        let mut publisher = MarketDataPublisher::new()
+            .subscribe(FeedMode::ByOrder, Box::new(feed))
+            .subscribe(FeedMode::ByPrice, Box::new(live));
+
+        for _ in 0..2 {
+            let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(1));
+            publisher.on_event(&EngineEvent::OrderRested(&order));
+        }
+
+        assert_eq!(publisher.subscription_count(), 1);
+        assert!(receiver.stats().is_evicted());
+        assert_eq!(receiver.stats().dropped(), 1);
+    }
+
+    #[test]
+    fn test_gap_filled_subscription_stays_and_reports_the_gap() {
+        let (feed, receiver) = queue::bounded_feed(1, queue::SlowConsumerPolicy::GapFill);
+        let mut publisher = MarketDataPublisher::new().subscribe(FeedMode::ByOrder, Box::new(feed));
+        let mut rest = || {
+            let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(1));
+            publisher.on_event(&EngineEvent::OrderRested(&order));
+        };
+
+        for _ in 0..3 {
+            rest();
+        }
+        assert_eq!(receiver.stats().dropped(), 2);
+        assert_eq!(receiver.try_recv().unwrap().sequence, 1);
+        assert!(receiver.try_recv().is_err());
+
+        rest();
+        let gap = receiver.try_recv().unwrap();
+        assert_eq!((gap.sequence, gap.body), (3, FeedBody::Gap { missed: 2 }));
+        assert!(!receiver.stats().is_evicted());
+        assert_eq!(publisher.subscription_count(), 1);
+    }
+
+    #[test]
+    fn test_parse_feed_mode() {
+        assert_eq!(FeedMode::from_str("MBO"), Ok(FeedMode::ByOrder));
+        assert_eq!(FeedMode::from_str("by-price"), Ok(FeedMode::ByPrice));
+        assert!(FeedMode::from_str("mbx").is_err());
+        assert_eq!(
+            FeedSpec::from_str("mbp:output_logs/mbp.csv"),
+            Ok(FeedSpec { kind: FeedKind::Live(FeedMode::ByPrice), path: "output_logs/mbp.csv".to_string() })
+        );
+        assert_eq!(
+            FeedSpec::from_str("conflated@250ms:book.csv").unwrap().kind,
+            FeedKind::Conflated(Conflation::new(Duration::from_millis(250)))
+        );
+        assert!(FeedSpec::from_str("mbo").is_err());
+        assert!(FeedSpec::from_str("conflated@soon:book.csv").is_err());
+    }
+
+    #[test]
+    fn test_market_by_order_feed_carries_every_order_event() {
+        let (mbo, _, first_id, second_id) = run_feeds();
+        let messages: Vec<FeedMessage> = mbo.try_iter().collect();
+
+        assert_eq!(messages.iter().map(|m| m.sequence).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(messages[0].body, FeedBody::Order(OrderUpdate::Add { order_id: first_id, side: Side::Sell, price: dec!(10.5), quantity: dec!(100) }));
+        assert!(matches!(messages[2].body, FeedBody::Order(OrderUpdate::Execute { order_id, quantity, .. }) if order_id == first_id && quantity == dec!(30)));
+        assert_eq!(messages[3].body, FeedBody::Order(OrderUpdate::Delete { order_id: second_id, side: Side::Sell, price: dec!(10.5), quantity: dec!(40) }));
+        assert_eq!(messages[3].to_string(), format!("4,1000,SOFI,DELETE,{},SELL,10.5,40", second_id));
+    }
+
+    #[test]
+    fn test_written_messages_parse_back() {
+        let (mbo, mbp, _, _) = run_feeds();
+        let book = FeedBody::Book(ConflatedBook { bids: vec![], asks: vec![(dec!(10.5), dec!(110)), (dec!(11), dec!(3))], changed_bids: 0, changed_asks: 0b10 });
+        let bust = FeedBody::Bust { trade_id: Uuid::new_v4(), price: dec!(10.5), quantity: dec!(30) };
+        let others = [FeedBody::Gap { missed: 2 }, book, bust].map(|body| FeedMessage { sequence: 9, timestamp: 5, instrument: "SOFI".to_string(), body });
+        for message in mbo.try_iter().chain(mbp.try_iter()).chain(others) {
+            assert_eq!(FeedMessage::from_str(&message.to_string()), Ok(message));
+        }
+        assert!(FeedMessage::from_str("1,1000,SOFI,ADD,not-a-uuid,BUY,10,1").is_err());
+    }
+
+    #[test]
+    fn test_market_by_price_feed_carries_level_totals() {
+        let (_, mbp, _, _) = run_feeds();
+        let levels: Vec<Decimal> = mbp
+            .try_iter()
+            .map(|m| match m.body {
+                FeedBody::Level(level) => level.quantity,
+                other => panic!("unexpected message on MBP feed: {:?}", other),
+            })
+            .collect();
+
+        assert_eq!(levels, vec![dec!(100), dec!(140), dec!(110), dec!(70)]);
+    }
+
+    #[test]
+    fn test_discretionary_execution_comes_off_the_displayed_level() {
+        let (tx, rx) = channel();
+        let publisher = MarketDataPublisher::new().subscribe(FeedMode::ByPrice, Box::new(tx));
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).subscriber(Box::new(publisher)).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let bid = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99), dec!(5)).with_discretion(dec!(100.5));
+        engine.process_order(bid, &mut logger).unwrap();
+        let (trades, _) = engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100.2), dec!(2)), &mut logger).unwrap();
+        assert_eq!(trades[0].price, dec!(100.2));
+
+        let levels: Vec<(Decimal, Decimal)> = rx
+            .try_iter()
+            .map(|m| match m.body {
+                FeedBody::Level(level) => (level.price, level.quantity),
+                other => panic!("unexpected message on MBP feed: {:?}", other),
+            })
+            .collect();
+        assert_eq!(levels, vec![(dec!(99), dec!(5)), (dec!(99), dec!(3))]);
+    }
+}
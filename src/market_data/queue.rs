@@ -0,0 +1,216 @@
+use super::{FeedBody, FeedMessage, FeedSubscriber};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvError, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+
+/// What happens when a consumer's queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    /// Stop delivering to the consumer. It drains what is already queued and
+    /// then sees the feed end.
+    #[default]
+    Disconnect,
+    /// Drop messages while the queue is full and, once there is room again,
+    /// deliver a [`FeedBody::Gap`] saying how many were lost.
+    GapFill,
+}
+
+impl FromStr for SlowConsumerPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "disconnect" => Ok(SlowConsumerPolicy::Disconnect),
+            "gap-fill" | "gapfill" => Ok(SlowConsumerPolicy::GapFill),
+            other => Err(format!("Unknown slow consumer policy '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    consumed: AtomicU64,
+    dropped: AtomicU64,
+    max_lag: AtomicU64,
+    evicted: AtomicBool,
+}
+
+/// Lag metrics shared between the publishing and the consuming end of a
+/// queued feed.
+#[derive(Debug, Clone, Default)]
+pub struct FeedStats {
+    counters: Arc<Counters>,
+}
+
+impl FeedStats {
+    /// Messages queued but not yet received by the consumer.
+    pub fn lag(&self) -> u64 {
+        let consumed = self.counters.consumed.load(Ordering::Relaxed);
+        self.counters.enqueued.load(Ordering::Relaxed).saturating_sub(consumed)
+    }
+
+    pub fn max_lag(&self) -> u64 {
+        self.counters.max_lag.load(Ordering::Relaxed)
+    }
+
+    pub fn consumed(&self) -> u64 {
+        self.counters.consumed.load(Ordering::Relaxed)
+    }
+
+    /// Messages lost to a full queue.
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn is_evicted(&self) -> bool {
+        self.counters.evicted.load(Ordering::Relaxed)
+    }
+}
+
+/// The publishing end of a bounded feed queue. Never blocks the matching
+/// thread: a full queue is handled by the [`SlowConsumerPolicy`].
+pub struct QueuedFeed {
+    sender: Option<SyncSender<FeedMessage>>,
+    policy: SlowConsumerPolicy,
+    missed: u64,
+    stats: FeedStats,
+}
+
+impl QueuedFeed {
+    fn try_send(&mut self, message: FeedMessage) -> Result<(), TrySendError<FeedMessage>> {
+        let sender = self.sender.as_ref().ok_or(TrySendError::Disconnected(message.clone()))?;
+        sender.try_send(message)?;
+        let counters = &self.stats.counters;
+        let enqueued = counters.enqueued.fetch_add(1, Ordering::Relaxed) + 1;
+        let lag = enqueued.saturating_sub(counters.consumed.load(Ordering::Relaxed));
+        counters.max_lag.fetch_max(lag, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn evict(&mut self) {
+        self.sender = None;
+        self.stats.counters.evicted.store(true, Ordering::Relaxed);
+    }
+
+    fn deliver(&mut self, message: &FeedMessage) -> Result<(), TrySendError<FeedMessage>> {
+        if self.missed > 0 {
+            let gap = FeedMessage {
+                sequence: message.sequence - 1,
+                timestamp: message.timestamp,
+                instrument: message.instrument.clone(),
+                body: FeedBody::Gap { missed: self.missed },
+            };
+            self.try_send(gap)?;
+            self.missed = 0;
+        }
+        self.try_send(message.clone())
+    }
+}
+
+impl FeedSubscriber for QueuedFeed {
+    fn on_message(&mut self, message: &FeedMessage) {
+        match self.deliver(message) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) if self.policy == SlowConsumerPolicy::GapFill => {
+                self.missed += 1;
+                self.stats.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Full(_)) => {
+                self.stats.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                self.evict();
+            }
+            Err(TrySendError::Disconnected(_)) => self.evict(),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.sender.is_some()
+    }
+}
+
+/// The consuming end of a bounded feed queue.
+pub struct FeedReceiver {
+    receiver: Receiver<FeedMessage>,
+    stats: FeedStats,
+}
+
+impl FeedReceiver {
+    pub fn recv(&self) -> Result<FeedMessage, RecvError> {
+        let message = self.receiver.recv()?;
+        self.stats.counters.consumed.fetch_add(1, Ordering::Relaxed);
+        Ok(message)
+    }
+
+    pub fn try_recv(&self) -> Result<FeedMessage, TryRecvError> {
+        let message = self.receiver.try_recv()?;
+        self.stats.counters.consumed.fetch_add(1, Ordering::Relaxed);
+        Ok(message)
+    }
+
+    pub fn stats(&self) -> &FeedStats {
+        &self.stats
+    }
+}
+
+/// Creates a feed queue holding at most `capacity` undelivered messages.
+pub fn bounded_feed(capacity: usize, policy: SlowConsumerPolicy) -> (QueuedFeed, FeedReceiver) {
+    let (sender, receiver) = sync_channel(capacity);
+    let stats = FeedStats::default();
+    (
+        QueuedFeed { sender: Some(sender), policy, missed: 0, stats: stats.clone() },
+        FeedReceiver { receiver, stats },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_data::LevelUpdate;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+
+    fn message(sequence: u64) -> FeedMessage {
+        FeedMessage {
+            sequence,
+            timestamp: sequence,
+            instrument: "SOFI".to_string(),
+            body: FeedBody::Level(LevelUpdate { side: Side::Buy, price: dec!(10), quantity: dec!(1) }),
+        }
+    }
+
+    #[test]
+    fn test_slow_consumer_is_disconnected_when_queue_fills() {
+        let (mut feed, receiver) = bounded_feed(2, SlowConsumerPolicy::Disconnect);
+        for sequence in 1..=3 {
+            feed.on_message(&message(sequence));
+        }
+
+        assert!(!feed.is_connected());
+        assert!(receiver.stats().is_evicted());
+        assert_eq!(receiver.stats().lag(), 2);
+        assert_eq!(receiver.recv().unwrap().sequence, 1);
+        assert_eq!(receiver.recv().unwrap().sequence, 2);
+        drop(feed);
+        assert!(receiver.recv().is_err());
+        assert_eq!(receiver.stats().lag(), 0);
+        assert_eq!(receiver.stats().max_lag(), 2);
+    }
+
+    #[test]
+    fn test_gap_fill_reports_missed_messages() {
+        let (mut feed, receiver) = bounded_feed(2, SlowConsumerPolicy::GapFill);
+        for sequence in 1..=4 {
+            feed.on_message(&message(sequence));
+        }
+        assert_eq!(receiver.stats().dropped(), 2);
+        assert_eq!(receiver.try_recv().unwrap().sequence, 1);
+        assert_eq!(receiver.try_recv().unwrap().sequence, 2);
+
+        feed.on_message(&message(5));
+        let gap = receiver.try_recv().unwrap();
+        assert_eq!((gap.sequence, gap.body), (4, FeedBody::Gap { missed: 2 }));
+        assert_eq!(receiver.try_recv().unwrap().sequence, 5);
+        assert!(feed.is_connected());
+    }
+}
@@ -0,0 +1,273 @@
+//! Rebuilds one instrument's book as it stood at a given point of a recorded
+//! feed, so any moment of a run can be inspected after the fact.
+
+use super::{FeedBody, FeedMessage, LevelUpdate, OrderUpdate};
+use crate::utils::{Side, UuidMap};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// How far into a recording to replay: up to and including a feed sequence
+/// number, or every message stamped at or before a time in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayPoint {
+    Sequence(u64),
+    Time(u64),
+}
+
+impl ReplayPoint {
+    fn includes(&self, message: &FeedMessage) -> bool {
+        match self {
+            ReplayPoint::Sequence(sequence) => message.sequence <= *sequence,
+            ReplayPoint::Time(timestamp) => message.timestamp <= *timestamp,
+        }
+    }
+}
+
+impl FromStr for ReplayPoint {
+    type Err = String;
+
+    /// Parses `seq:<n>` or `time:<ns>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid replay point '{}', expected seq:<n> or time:<ns>", s);
+        let (kind, value) = s.split_once(':').ok_or_else(invalid)?;
+        let value = value.parse::<u64>().map_err(|_| invalid())?;
+        match kind {
+            "seq" => Ok(ReplayPoint::Sequence(value)),
+            "time" => Ok(ReplayPoint::Time(value)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// One price level of a reconstructed book. `orders` holds the resting
+/// orders in queue order when the recording is market-by-order, and is
+/// empty for a market-by-price or conflated recording.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub orders: Vec<(Uuid, Decimal)>,
+}
+
+/// An instrument's book at a replay point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconstructedBook {
+    pub instrument: String,
+    /// Sequence number and timestamp of the last message replayed.
+    pub sequence: Option<u64>,
+    pub timestamp: Option<u64>,
+    /// Messages applied to the book.
+    pub messages: u64,
+    /// Gaps in the recording before the point. With any, the book may be
+    /// wrong.
+    pub gaps: u64,
+    bids: BTreeMap<Decimal, BookLevel>,
+    asks: BTreeMap<Decimal, BookLevel>,
+    /// Side and price of each resting order, for executions and deletes.
+    resting: UuidMap<(Side, Decimal)>,
+}
+
+impl ReconstructedBook {
+    fn new(instrument: &str) -> Self {
+        ReconstructedBook {
+            instrument: instrument.to_string(),
+            sequence: None,
+            timestamp: None,
+            messages: 0,
+            gaps: 0,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            resting: UuidMap::default(),
+        }
+    }
+
+    /// Bid levels, best first.
+    pub fn bids(&self) -> impl Iterator<Item = &BookLevel> {
+        self.bids.values().rev()
+    }
+
+    /// Ask levels, best first.
+    pub fn asks(&self) -> impl Iterator<Item = &BookLevel> {
+        self.asks.values()
+    }
+
+    fn levels(&mut self, side: Side) -> &mut BTreeMap<Decimal, BookLevel> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    fn apply(&mut self, body: &FeedBody) {
+        match body {
+            FeedBody::Order(OrderUpdate::Add { order_id, side, price, quantity }) => {
+                self.resting.insert(*order_id, (*side, *price));
+                let level = self.levels(*side).entry(*price).or_insert_with(|| BookLevel { price: *price, ..Default::default() });
+                level.quantity += quantity;
+                level.orders.push((*order_id, *quantity));
+            }
+            FeedBody::Order(OrderUpdate::Execute { order_id, quantity, .. } | OrderUpdate::Delete { order_id, quantity, .. }) => {
+                let Some(&(side, price)) = self.resting.get(order_id) else { return };
+                let deleted = matches!(body, FeedBody::Order(OrderUpdate::Delete { .. }));
+                let levels = self.levels(side);
+                let Some(level) = levels.get_mut(&price) else { return };
+                level.quantity -= quantity;
+                let mut done = deleted;
+                if let Some(position) = level.orders.iter().position(|(id, _)| id == order_id) {
+                    level.orders[position].1 -= quantity;
+                    done |= level.orders[position].1 <= Decimal::ZERO;
+                    if done {
+                        level.orders.remove(position);
+                    }
+                }
+                if level.quantity <= Decimal::ZERO {
+                    levels.remove(&price);
+                }
+                if done {
+                    self.resting.remove(order_id);
+                }
+            }
+            FeedBody::Level(LevelUpdate { side, price, quantity }) => {
+                let levels = self.levels(*side);
+                if quantity.is_zero() {
+                    levels.remove(price);
+                } else {
+                    levels.insert(*price, BookLevel { price: *price, quantity: *quantity, orders: Vec::new() });
+                }
+            }
+            FeedBody::Book(book) => {
+                let levels = |levels: &[(Decimal, Decimal)]| {
+                    levels.iter().map(|&(price, quantity)| (price, BookLevel { price, quantity, orders: Vec::new() })).collect()
+                };
+                self.bids = levels(&book.bids);
+                self.asks = levels(&book.asks);
+            }
+            FeedBody::Gap { .. } | FeedBody::Bust { .. } => {}
+        }
+    }
+
+    pub fn print(&self) {
+        let at = match (self.sequence, self.timestamp) {
+            (Some(sequence), Some(timestamp)) => format!("sequence {}, time {}", sequence, timestamp),
+            _ => "before the first message".to_string(),
+        };
+        println!("\n--- ORDER BOOK: {} at {} ---", self.instrument, at);
+        let print_side = |name: &str, levels: Vec<&BookLevel>| {
+            println!("  {}:", name);
+            if levels.is_empty() {
+                println!("    (empty)");
+            }
+            for level in levels {
+                println!("    Price: {:<10} | Volume: {:<12} | Orders: {}", level.price, level.quantity, level.orders.len());
+            }
+        };
+        print_side("ASKS (Sell Orders)", self.asks.values().rev().collect());
+        println!("  ---------------------------");
+        print_side("BIDS (Buy Orders)", self.bids().collect());
+        if self.gaps > 0 {
+            println!("  {} gaps in the recording before this point; the book may be incomplete.", self.gaps);
+        }
+        println!("-----------------------------");
+    }
+}
+
+/// Replays a feed written by [`FeedWriter`](super::FeedWriter) (`--feed`) up
+/// to `at` and returns `instrument`'s book there. The recording is read in
+/// order and the first message past the point ends the replay.
+pub fn reconstruct_book(reader: impl BufRead, instrument: &str, at: ReplayPoint) -> Result<ReconstructedBook, String> {
+    let mut book = ReconstructedBook::new(instrument);
+    let mut previous = None;
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read the recording: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = FeedMessage::from_str(&line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+        if !at.includes(&message) {
+            break;
+        }
+        if previous.is_some_and(|previous: u64| message.sequence != previous + 1) || matches!(message.body, FeedBody::Gap { .. }) {
+            book.gaps += 1;
+        }
+        previous = Some(message.sequence);
+        if message.instrument == instrument {
+            book.apply(&message.body);
+            book.messages += 1;
+            book.sequence = Some(message.sequence);
+            book.timestamp = Some(message.timestamp);
+        }
+    }
+    Ok(book)
+}
+
+/// [`reconstruct_book`] from the recording at `path`.
+pub fn reconstruct_book_from(path: &str, instrument: &str, at: ReplayPoint) -> Result<ReconstructedBook, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    reconstruct_book(BufReader::new(file), instrument, at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::market_data::{FeedMode, FeedWriter, MarketDataPublisher};
+    use crate::order::Order;
+    use rust_decimal_macros::dec;
+    use std::io::{Cursor, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what a feed writer writes, so the test can read it back.
+    #[derive(Clone, Default)]
+    struct Recording(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Recording {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_book_is_rebuilt_at_any_sequence_or_time() {
+        let recording = Recording::default();
+        let clock = ManualClock::new(1_000);
+        let publisher = MarketDataPublisher::new().with_clock(clock.clone()).subscribe(FeedMode::ByOrder, Box::new(FeedWriter::new(recording.clone())));
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).clock(clock.clone()).subscriber(Box::new(publisher)).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let first = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), dec!(100));
+        let first_id = first.order_id;
+        engine.process_order(first, &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), dec!(40)), &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(10), dec!(5)), &mut logger).unwrap();
+        clock.advance(1_000);
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(30)), &mut logger).unwrap();
+        drop(engine);
+        let recording = String::from_utf8(recording.0.lock().unwrap().clone()).unwrap();
+
+        let before = reconstruct_book(Cursor::new(&recording), "SOFI", ReplayPoint::Sequence(2)).unwrap();
+        assert_eq!(before.asks().map(|level| level.quantity).collect::<Vec<_>>(), vec![dec!(140)]);
+        assert_eq!(before.bids().count(), 0);
+
+        let after = reconstruct_book(Cursor::new(&recording), "SOFI", ReplayPoint::Time(2_000)).unwrap();
+        let ask = after.asks().next().unwrap();
+        assert_eq!((ask.quantity, ask.orders[0]), (dec!(110), (first_id, dec!(70))));
+        assert_eq!(after.bids().next().map(|level| level.price), Some(dec!(10)));
+        assert_eq!((after.sequence, after.messages, after.gaps), (Some(4), 4, 0));
+
+        assert_eq!(reconstruct_book(Cursor::new(&recording), "SOFI", ReplayPoint::Time(0)).unwrap().sequence, None);
+        assert_eq!(ReplayPoint::from_str("seq:12"), Ok(ReplayPoint::Sequence(12)));
+        assert!(ReplayPoint::from_str("12").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use super::{FeedMessage, FeedSubscriber};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How fast a recorded feed is played back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// Every message as soon as the previous one was handled.
+    Unpaced,
+    /// The gaps between message timestamps as recorded.
+    Original,
+    /// The recorded gaps divided by a factor above zero.
+    Accelerated(f64),
+}
+
+impl FromStr for Pace {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Pace::Unpaced),
+            "original" => Ok(Pace::Original),
+            factor => factor
+                .strip_suffix('x')
+                .and_then(|factor| factor.parse::<f64>().ok())
+                .filter(|factor| factor.is_finite() && *factor > 0.0)
+                .map(Pace::Accelerated)
+                .ok_or_else(|| format!("Invalid pace '{}', expected max, original or <factor>x", s)),
+        }
+    }
+}
+
+impl Pace {
+    /// How long after the first message, stamped `first`, a message stamped
+    /// `timestamp` is due, or `None` when unpaced. A timestamp before the
+    /// first is due at once.
+    pub fn due(&self, first: u64, timestamp: u64) -> Option<Duration> {
+        let recorded = Duration::from_nanos(timestamp.saturating_sub(first));
+        match self {
+            Pace::Unpaced => None,
+            Pace::Original => Some(recorded),
+            Pace::Accelerated(factor) => Some(recorded.div_f64(*factor)),
+        }
+    }
+}
+
+/// What a replay delivered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub messages: u64,
+    /// Places where the recorded sequence numbers skip, i.e. the recording
+    /// itself missed messages.
+    pub sequence_gaps: u64,
+    pub elapsed: Duration,
+}
+
+/// Plays a feed written by [`FeedWriter`](super::FeedWriter) (`--feed`) back
+/// to subscribers. Every subscriber receives the same messages in the
+/// recorded order, so a replay is deterministic apart from its timing.
+pub struct FeedReplayer<R: BufRead> {
+    reader: R,
+    pace: Pace,
+    subscribers: Vec<Box<dyn FeedSubscriber>>,
+}
+
+impl FeedReplayer<BufReader<File>> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> FeedReplayer<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, pace: Pace::Unpaced, subscribers: Vec::new() }
+    }
+
+    pub fn pace(mut self, pace: Pace) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    pub fn subscribe(mut self, subscriber: Box<dyn FeedSubscriber>) -> Self {
+        self.subscribers.push(subscriber);
+        self
+    }
+
+    /// Replays the whole recording. Subscribers that disconnect are dropped;
+    /// the replay stops early once the last one is gone.
+    pub fn run(mut self) -> Result<ReplayStats, String> {
+        let start = Instant::now();
+        let mut stats = ReplayStats::default();
+        let mut first = None;
+        let mut previous = None;
+        for (number, line) in self.reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read the recording: {}", e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = FeedMessage::from_str(&line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            if let Some(due) = self.pace.due(*first.get_or_insert(message.timestamp), message.timestamp) {
+                let wait = due.saturating_sub(start.elapsed());
+                if !wait.is_zero() {
+                    thread::sleep(wait);
+                }
+            }
+            if previous.is_some_and(|previous: u64| message.sequence != previous + 1) {
+                stats.sequence_gaps += 1;
+            }
+            previous = Some(message.sequence);
+
+            let connected = self.subscribers.len();
+            for subscriber in &mut self.subscribers {
+                subscriber.on_message(&message);
+            }
+            self.subscribers.retain(|subscriber| subscriber.is_connected());
+            stats.messages += 1;
+            if connected > 0 && self.subscribers.is_empty() {
+                break;
+            }
+        }
+        stats.elapsed = start.elapsed();
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_data::FeedBody;
+    use std::io::Cursor;
+    use std::sync::mpsc::channel;
+
+    const RECORDING: &str = "1,1000,SOFI,LEVEL,SELL,10.5,100\n2,1500,SOFI,LEVEL,SELL,10.5,140\n\n4,3000,SOFI,GAP,1\n";
+
+    #[test]
+    fn test_parse_pace_and_schedule() {
+        assert_eq!(Pace::from_str("max"), Ok(Pace::Unpaced));
+        assert_eq!(Pace::from_str("10x"), Ok(Pace::Accelerated(10.0)));
+        assert!(Pace::from_str("0x").is_err());
+        assert!(Pace::from_str("fast").is_err());
+
+        assert_eq!(Pace::Original.due(1_000, 3_000), Some(Duration::from_nanos(2_000)));
+        assert_eq!(Pace::Accelerated(4.0).due(1_000, 3_000), Some(Duration::from_nanos(500)));
+        assert_eq!(Pace::Original.due(1_000, 500), Some(Duration::ZERO));
+        assert_eq!(Pace::Unpaced.due(1_000, 3_000), None);
+    }
+
+    #[test]
+    fn test_replay_delivers_the_recording_to_every_subscriber() {
+        let (tx, rx) = channel();
+        let (copy_tx, copy_rx) = channel();
+        let stats = FeedReplayer::new(Cursor::new(RECORDING))
+            .pace(Pace::Accelerated(1000.0))
+            .subscribe(Box::new(tx))
+            .subscribe(Box::new(copy_tx))
+            .run()
+            .unwrap();
+
+        assert_eq!((stats.messages, stats.sequence_gaps), (3, 1));
+        let received: Vec<FeedMessage> = rx.try_iter().collect();
+        assert_eq!(received.iter().map(|m| m.timestamp).collect::<Vec<_>>(), vec![1000, 1500, 3000]);
+        assert_eq!(received[2].body, FeedBody::Gap { missed: 1 });
+        assert_eq!(copy_rx.try_iter().collect::<Vec<_>>(), received);
+    }
+
+    #[test]
+    fn test_replay_reports_the_malformed_line() {
+        let recording = Cursor::new("1,1000,SOFI,LEVEL,SELL,10.5,100\n2,1500,SOFI,LEVEL\n");
+        let error = FeedReplayer::new(recording).run().unwrap_err();
+        assert!(error.starts_with("Line 2:"), "{}", error);
+    }
+}
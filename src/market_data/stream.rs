@@ -0,0 +1,281 @@
+use super::{FeedMessage, FeedSubscriber};
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::logging::types::{WaitStrategy, WorkerOptions};
+use crate::logging::worker::{LogWorker, WorkerReceiver};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Where a [`StreamSink`] publishes, given on the command line as
+/// `nats:<host:port>` or `lines:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamTarget {
+    Nats(String),
+    /// One `<topic>\t<json>` line per message, for piping into another
+    /// producer such as `kcat` for Kafka.
+    Lines(String),
+}
+
+impl FromStr for StreamTarget {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("nats", address)) if !address.is_empty() => Ok(StreamTarget::Nats(address.to_string())),
+            Some(("lines", path)) if !path.is_empty() => Ok(StreamTarget::Lines(path.to_string())),
+            _ => Err(format!("Invalid stream '{}', expected nats:<host:port> or lines:<path>", s)),
+        }
+    }
+}
+
+impl StreamTarget {
+    pub fn connect(&self) -> io::Result<Box<dyn StreamTransport>> {
+        Ok(match self {
+            StreamTarget::Nats(address) => Box::new(NatsTransport::connect(address)?),
+            StreamTarget::Lines(path) => Box::new(LineTransport::new(BufWriter::new(File::create(path)?))),
+        })
+    }
+}
+
+/// Delivers serialized messages to a message bus.
+pub trait StreamTransport: Send {
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes each message as a `<topic>\t<payload>` line.
+pub struct LineTransport<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> LineTransport<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write + Send> StreamTransport for LineTransport<W> {
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        self.writer.write_all(topic.as_bytes())?;
+        self.writer.write_all(b"\t")?;
+        self.writer.write_all(payload)?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Publishes over the NATS text protocol. A reader thread answers the
+/// server's keep-alive pings, which would otherwise drop the connection
+/// during a long run.
+pub struct NatsTransport {
+    writer: Arc<Mutex<BufWriter<TcpStream>>>,
+}
+
+impl NatsTransport {
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let writer = Arc::new(Mutex::new(BufWriter::new(stream)));
+        {
+            let mut writer = writer.lock().unwrap();
+            writer.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"exchange-matching-engine\"}\r\n")?;
+            writer.flush()?;
+        }
+        let pong = Arc::clone(&writer);
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                if line.starts_with("PING") {
+                    let mut writer = pong.lock().unwrap();
+                    if writer.write_all(b"PONG\r\n").and_then(|_| writer.flush()).is_err() {
+                        break;
+                    }
+                } else if line.starts_with("-ERR") {
+                    eprintln!("NATS server error: {}", line);
+                }
+            }
+        });
+        Ok(Self { writer })
+    }
+}
+
+impl StreamTransport for NatsTransport {
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        write!(writer, "PUB {} {}\r\n", topic, payload.len())?;
+        writer.write_all(payload)?;
+        writer.write_all(b"\r\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+struct StreamRecord {
+    topic: Arc<str>,
+    payload: Vec<u8>,
+}
+
+/// Publishes trades to `<prefix>.trades` and book updates to `<prefix>.book`
+/// as JSON. The matching thread only serializes; a background thread owns the
+/// transport, so a slow broker never stalls matching. Clones share that
+/// thread, which drains and exits when the last clone is dropped.
+///
+/// Register it with the engine for trades and with a
+/// [`MarketDataPublisher`](super::MarketDataPublisher) for book updates.
+#[derive(Clone)]
+pub struct StreamSink {
+    worker: Arc<LogWorker<StreamRecord>>,
+    trades: Arc<str>,
+    book: Arc<str>,
+}
+
+impl StreamSink {
+    pub fn new(prefix: &str, transport: Box<dyn StreamTransport>, options: WorkerOptions) -> Self {
+        let worker = LogWorker::spawn(options, move |receiver: WorkerReceiver<StreamRecord>| {
+            let mut transport = transport;
+            let mut failed = false;
+            while let Some(record) = receiver.recv(WaitStrategy::Block) {
+                if let Err(e) = transport.publish(&record.topic, &record.payload) {
+                    if !failed {
+                        eprintln!("Failed to publish to {}: {}", record.topic, e);
+                    }
+                    failed = true;
+                }
+            }
+            if let Err(e) = transport.flush() {
+                eprintln!("Failed to flush stream: {}", e);
+            }
+        });
+        Self {
+            worker: Arc::new(worker),
+            trades: format!("{}.trades", prefix).into(),
+            book: format!("{}.book", prefix).into(),
+        }
+    }
+
+    fn send(&self, topic: &Arc<str>, message: &impl Serialize) {
+        match serde_json::to_vec(message) {
+            Ok(payload) => self.worker.send(StreamRecord { topic: Arc::clone(topic), payload }),
+            Err(e) => eprintln!("Failed to serialize {} message: {}", topic, e),
+        }
+    }
+}
+
+impl EngineSubscriber for StreamSink {
+    fn on_event(&mut self, event: &EngineEvent) {
+        if let EngineEvent::Trade(trade) = event {
+            self.send(&self.trades, *trade);
+        }
+    }
+}
+
+impl FeedSubscriber for StreamSink {
+    fn on_message(&mut self, message: &FeedMessage) {
+        self.send(&self.book, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::market_data::{FeedMode, MarketDataPublisher};
+    use crate::order::Order;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+    use std::net::TcpListener;
+    use uuid::Uuid;
+
+    /// Collects what a sink wrote once every clone of it is gone.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_target() {
+        assert_eq!(StreamTarget::from_str("nats:127.0.0.1:4222").unwrap(), StreamTarget::Nats("127.0.0.1:4222".to_string()));
+        assert_eq!(StreamTarget::from_str("lines:out.jsonl").unwrap(), StreamTarget::Lines("out.jsonl".to_string()));
+        assert!(StreamTarget::from_str("kafka:localhost:9092").is_err());
+        assert!(StreamTarget::from_str("nats:").is_err());
+    }
+
+    #[test]
+    fn test_sink_publishes_trades_and_book_updates() {
+        let buffer = SharedBuffer::default();
+        let sink = StreamSink::new("sim", Box::new(LineTransport::new(buffer.clone())), WorkerOptions::default());
+        let publisher = MarketDataPublisher::new().subscribe(FeedMode::ByPrice, Box::new(sink.clone()));
+        let mut engine = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig::default())
+            .subscriber(Box::new(sink))
+            .subscriber(Box::new(publisher))
+            .build()
+            .unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10.5), dec!(100)), &mut logger).unwrap();
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(30)), &mut logger).unwrap();
+        drop(engine);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<(&str, serde_json::Value)> = output
+            .lines()
+            .map(|line| {
+                let (topic, json) = line.split_once('\t').unwrap();
+                (topic, serde_json::from_str(json).unwrap())
+            })
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0, "sim.book");
+        assert_eq!(lines[0].1["body"]["Level"]["quantity"], "100");
+        let trade = lines.iter().find(|(topic, _)| *topic == "sim.trades").unwrap();
+        assert_eq!(trade.1["quantity"], "30");
+        assert_eq!(trade.1["taker_side"], "Buy");
+    }
+
+    #[test]
+    fn test_nats_transport_speaks_the_text_protocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            writer.write_all(b"PING\r\n").unwrap();
+            let mut lines = BufReader::new(stream).lines().map(Result::unwrap);
+            (0..4).map(|_| lines.next().unwrap()).collect::<Vec<_>>()
+        });
+
+        let mut transport = NatsTransport::connect(&address).unwrap();
+        transport.publish("sim.trades", b"{}").unwrap();
+        transport.flush().unwrap();
+
+        let received = server.join().unwrap();
+        assert!(received[0].starts_with("CONNECT "));
+        let mut rest = received[1..].to_vec();
+        rest.sort();
+        assert_eq!(rest, ["PONG", "PUB sim.trades 2", "{}"]);
+    }
+}
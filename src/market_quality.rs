@@ -0,0 +1,236 @@
+//! Market-quality statistics per instrument over a run: time-weighted quoted
+//! spread and depth at the touch, book imbalance, trade-throughs and
+//! realized volatility.
+
+use crate::engine::MatchingEngine;
+use crate::orderbook::OrderBook;
+use crate::trade::Trade;
+use crate::utils::Side;
+use std::collections::BTreeMap;
+
+/// One instrument's market quality over the run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketQuality {
+    /// Average best ask minus best bid, weighted by engine time, over the
+    /// time both sides were quoted.
+    pub time_weighted_spread: Option<f64>,
+    /// Average quantity at the best bid plus the best ask, weighted the same.
+    pub average_touch_depth: Option<f64>,
+    /// Share of the observed engine time with both sides quoted.
+    pub two_sided: f64,
+    /// Average published book imbalance, weighted by engine time, over the
+    /// time the book had one. `None` unless the engine computes it.
+    pub average_imbalance: Option<f64>,
+    pub trades: usize,
+    /// Trades that executed at a worse price than one still resting on the
+    /// opposite side after the operation. Nonzero only if a better price
+    /// was skipped.
+    pub trade_throughs: usize,
+    /// Square root of the sum of squared log returns between consecutive
+    /// trade prices, over the whole run.
+    pub realized_volatility: f64,
+}
+
+#[derive(Debug, Default)]
+struct InstrumentState {
+    since: u64,
+    /// Spread and touch depth while both sides are quoted.
+    touch: Option<(f64, f64)>,
+    spread_nanos: f64,
+    depth_nanos: f64,
+    two_sided_nanos: u64,
+    observed_nanos: u64,
+    imbalance: Option<f64>,
+    imbalance_nanos: f64,
+    imbalanced_nanos: u64,
+    trades: usize,
+    trade_throughs: usize,
+    last_price: Option<f64>,
+    squared_returns: f64,
+}
+
+impl InstrumentState {
+    fn summary(&self) -> MarketQuality {
+        let per_quoted_nanos = |total: f64| (self.two_sided_nanos > 0).then(|| total / self.two_sided_nanos as f64);
+        MarketQuality {
+            time_weighted_spread: per_quoted_nanos(self.spread_nanos),
+            average_touch_depth: per_quoted_nanos(self.depth_nanos),
+            two_sided: if self.observed_nanos == 0 { 0.0 } else { self.two_sided_nanos as f64 / self.observed_nanos as f64 },
+            average_imbalance: (self.imbalanced_nanos > 0).then(|| self.imbalance_nanos / self.imbalanced_nanos as f64),
+            trades: self.trades,
+            trade_throughs: self.trade_throughs,
+            realized_volatility: self.squared_returns.sqrt(),
+        }
+    }
+}
+
+/// Samples the top of every book after each operation; a state holds until
+/// the next sample.
+#[derive(Debug, Default)]
+pub struct MarketQualityTracker {
+    instruments: BTreeMap<String, InstrumentState>,
+}
+
+impl MarketQualityTracker {
+    /// Closes the interval since the last sample of each book and starts a
+    /// new one from its current top.
+    pub fn observe(&mut self, engine: &MatchingEngine) {
+        let now = engine.now_nanos();
+        for (instrument, book) in engine.order_books() {
+            if !self.instruments.contains_key(instrument) {
+                self.instruments.insert(instrument.to_string(), InstrumentState { since: now, ..Default::default() });
+            }
+            let state = self.instruments.get_mut(instrument).expect("inserted above");
+            let elapsed = now.saturating_sub(state.since);
+            state.observed_nanos += elapsed;
+            if let Some((spread, depth)) = state.touch {
+                state.two_sided_nanos += elapsed;
+                state.spread_nanos += spread * elapsed as f64;
+                state.depth_nanos += depth * elapsed as f64;
+            }
+            if let Some(imbalance) = state.imbalance {
+                state.imbalanced_nanos += elapsed;
+                state.imbalance_nanos += imbalance * elapsed as f64;
+            }
+            state.since = now;
+            state.touch = touch(book);
+            state.imbalance = engine.imbalance(instrument).and_then(|imbalance| f64::try_from(imbalance).ok());
+        }
+    }
+
+    /// Counts the trades of one operation, checking them against the book
+    /// it left behind.
+    pub fn record_trades(&mut self, trades: &[Trade], engine: &MatchingEngine) {
+        for trade in trades {
+            let Some(book) = engine.order_book(&trade.instrument) else {
+                continue;
+            };
+            let state = self.instruments.entry(trade.instrument.clone()).or_default();
+            state.trades += 1;
+            let skipped = match trade.taker_side {
+                Side::Buy => book.best_ask().is_some_and(|ask| ask < trade.price),
+                Side::Sell => book.best_bid().is_some_and(|bid| bid > trade.price),
+            };
+            if skipped {
+                state.trade_throughs += 1;
+            }
+            let Ok(price) = f64::try_from(trade.price) else {
+                continue;
+            };
+            if let Some(last) = state.last_price {
+                state.squared_returns += (price / last).ln().powi(2);
+            }
+            state.last_price = Some(price);
+        }
+    }
+
+    pub fn summary(&self) -> BTreeMap<String, MarketQuality> {
+        self.instruments.iter().map(|(instrument, state)| (instrument.clone(), state.summary())).collect()
+    }
+}
+
+fn touch(book: &OrderBook) -> Option<(f64, f64)> {
+    let spread = book.best_ask()? - book.best_bid()?;
+    let depth = book.touch_volume(Side::Buy)? + book.touch_volume(Side::Sell)?;
+    Some((f64::try_from(spread).ok()?, f64::try_from(depth).ok()?))
+}
+
+pub fn print_market_quality(quality: &BTreeMap<String, MarketQuality>) {
+    if quality.is_empty() {
+        return;
+    }
+    let show = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |value| format!("{:.4}", value));
+    println!("\n--- Market Quality ---");
+    println!("{:<12} {:>12} {:>12} {:>10} {:>10} {:>8} {:>14} {:>12}", "Instrument", "TW spread", "Touch depth", "Two-sided", "Imbalance", "Trades", "Trade-throughs", "Realized vol");
+    for (instrument, q) in quality {
+        println!(
+            "{:<12} {:>12} {:>12} {:>9.1}% {:>10} {:>8} {:>14} {:>12.6}",
+            instrument, show(q.time_weighted_spread), show(q.average_touch_depth), q.two_sided * 100.0, show(q.average_imbalance), q.trades, q.trade_throughs, q.realized_volatility
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::logging::{create_logger, LoggingMode};
+    use crate::order::Order;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_spread_and_depth_are_weighted_by_engine_time() {
+        let clock = ManualClock::new(0);
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).clock(clock.clone()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut tracker = MarketQualityTracker::default();
+        let mut submit = |engine: &mut MatchingEngine, side, price, quantity| {
+            let order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity);
+            engine.process_order(order, &mut logger).unwrap().0
+        };
+
+        submit(&mut engine, Side::Buy, dec!(99), dec!(5));
+        tracker.observe(&engine);
+        clock.set(10);
+        submit(&mut engine, Side::Sell, dec!(101), dec!(5));
+        tracker.observe(&engine);
+        // 30 ns at a spread of 2 and depth 10, then 10 ns at 1 and depth 15.
+        clock.set(40);
+        submit(&mut engine, Side::Sell, dec!(100), dec!(10));
+        tracker.observe(&engine);
+        clock.set(50);
+        tracker.observe(&engine);
+
+        let quality = &tracker.summary()["SOFI"];
+        assert_eq!(quality.time_weighted_spread, Some(1.75));
+        assert_eq!(quality.average_touch_depth, Some(11.25));
+        assert_eq!(quality.two_sided, 0.8);
+        assert_eq!(quality.average_imbalance, None);
+    }
+
+    #[test]
+    fn test_imbalance_is_weighted_by_engine_time() {
+        let clock = ManualClock::new(0);
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).imbalance_levels(1).clock(clock.clone()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut tracker = MarketQualityTracker::default();
+        let mut submit = |engine: &mut MatchingEngine, side, price, quantity| {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), side, price, quantity), &mut logger).unwrap();
+        };
+
+        // 10 ns with only bids, then 30 ns at 3 bid against 1 ask.
+        submit(&mut engine, Side::Buy, dec!(99), dec!(3));
+        tracker.observe(&engine);
+        clock.set(10);
+        submit(&mut engine, Side::Sell, dec!(101), dec!(1));
+        tracker.observe(&engine);
+        clock.set(40);
+        tracker.observe(&engine);
+
+        assert_eq!(tracker.summary()["SOFI"].average_imbalance, Some(0.625));
+    }
+
+    #[test]
+    fn test_trades_count_returns_and_trade_throughs() {
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        for price in [dec!(100), dec!(110)] {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, price, dec!(1)), &mut logger).unwrap();
+        }
+        let mut tracker = MarketQualityTracker::default();
+        let taker = Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(2));
+        let (trades, _) = engine.process_order(taker, &mut logger).unwrap();
+        tracker.record_trades(&trades, &engine);
+
+        // A print at 105 while an offer at 100 still rests is a trade-through.
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100), dec!(1)), &mut logger).unwrap();
+        let through = Trade::new("SOFI".to_string(), dec!(105), dec!(1), Uuid::new_v4(), Uuid::new_v4(), Side::Buy);
+        tracker.record_trades(&[through], &engine);
+
+        let quality = &tracker.summary()["SOFI"];
+        assert_eq!((quality.trades, quality.trade_throughs), (3, 1));
+        let expected = ((110f64 / 100.0).ln().powi(2) + (105f64 / 110.0).ln().powi(2)).sqrt();
+        assert!((quality.realized_volatility - expected).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,179 @@
+//! Markouts: how the mid moved after each fill, seen from the resting side.
+//! A maker that is consistently picked off by better-informed takers shows
+//! negative markouts that grow with the horizon.
+
+use crate::clients::ClientId;
+use crate::trade::Trade;
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Engine-clock delays after a fill at which the mid is sampled: 1 s, 5 s
+/// and 30 s.
+pub const MARKOUT_HORIZONS: [u64; 3] = [1_000_000_000, 5_000_000_000, 30_000_000_000];
+
+/// A maker fill waiting for the mid at one horizon.
+#[derive(Debug, Clone)]
+struct PendingFill {
+    due: u64,
+    client: Option<ClientId>,
+    maker_side: Side,
+    price: Decimal,
+    quantity: Decimal,
+}
+
+/// Quantity-weighted markouts of one set of maker fills at each horizon.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarkoutCurve {
+    pub fills: [usize; MARKOUT_HORIZONS.len()],
+    pub quantity: [Decimal; MARKOUT_HORIZONS.len()],
+    /// Sum over fills of quantity times the mid's move in the maker's favour.
+    pub value: [Decimal; MARKOUT_HORIZONS.len()],
+}
+
+impl MarkoutCurve {
+    fn add(&mut self, horizon: usize, quantity: Decimal, markout: Decimal) {
+        self.fills[horizon] += 1;
+        self.quantity[horizon] += quantity;
+        self.value[horizon] += quantity * markout;
+    }
+
+    /// The average markout per unit at `horizon`, an index into
+    /// [`MARKOUT_HORIZONS`], or `None` before any fill reached it.
+    pub fn average(&self, horizon: usize) -> Option<Decimal> {
+        (!self.quantity[horizon].is_zero()).then(|| self.value[horizon] / self.quantity[horizon])
+    }
+}
+
+/// Samples the mid of every instrument with fills awaiting a markout and
+/// settles each horizon with the mid that prevailed when it came due.
+#[derive(Debug, Default)]
+pub struct MarkoutTracker {
+    pending: HashMap<String, [VecDeque<PendingFill>; MARKOUT_HORIZONS.len()]>,
+    mids: HashMap<String, Decimal>,
+    clients: BTreeMap<ClientId, MarkoutCurve>,
+    total: MarkoutCurve,
+}
+
+impl MarkoutTracker {
+    /// Queues the maker side of `trade`, executed at engine time `now`, for
+    /// a markout at every horizon. `maker` owns the resting order, if known,
+    /// and `mid` is the instrument's mid once the trade is done.
+    pub fn record_fill(&mut self, trade: &Trade, maker: Option<&ClientId>, mid: Option<Decimal>, now: u64) {
+        if let Some(mid) = mid {
+            self.mids.insert(trade.instrument.clone(), mid);
+        }
+        let queues = self.pending.entry(trade.instrument.clone()).or_default();
+        for (queue, delay) in queues.iter_mut().zip(MARKOUT_HORIZONS) {
+            queue.push_back(PendingFill {
+                due: now.saturating_add(delay),
+                client: maker.cloned(),
+                maker_side: trade.taker_side.opposite(),
+                price: trade.price,
+                quantity: trade.quantity,
+            });
+        }
+    }
+
+    /// Settles every horizon due by `now` against the mid that has held
+    /// since the last call, then records the current mids from `mid_of`.
+    /// Call it before the book changes at `now`.
+    pub fn advance(&mut self, now: u64, mid_of: impl Fn(&str) -> Option<Decimal>) {
+        for (instrument, queues) in &mut self.pending {
+            if let Some(&mid) = self.mids.get(instrument) {
+                for (horizon, queue) in queues.iter_mut().enumerate() {
+                    while queue.front().is_some_and(|fill| fill.due <= now) {
+                        let fill = queue.pop_front().expect("front was just checked");
+                        let markout = match fill.maker_side {
+                            Side::Buy => mid - fill.price,
+                            Side::Sell => fill.price - mid,
+                        };
+                        self.total.add(horizon, fill.quantity, markout);
+                        if let Some(client) = fill.client {
+                            self.clients.entry(client).or_default().add(horizon, fill.quantity, markout);
+                        }
+                    }
+                }
+            }
+            if let Some(mid) = mid_of(instrument) {
+                self.mids.insert(instrument.clone(), mid);
+            }
+        }
+        self.pending.retain(|_, queues| queues.iter().any(|queue| !queue.is_empty()));
+    }
+
+    /// Fills still waiting for their longest horizon when the run ended.
+    pub fn unsettled(&self) -> usize {
+        self.pending.values().map(|queues| queues[MARKOUT_HORIZONS.len() - 1].len()).sum()
+    }
+
+    pub fn client_curves(&self) -> &BTreeMap<ClientId, MarkoutCurve> {
+        &self.clients
+    }
+
+    /// All maker fills, with or without a client.
+    pub fn total(&self) -> &MarkoutCurve {
+        &self.total
+    }
+
+    pub fn print_curves(&self) {
+        if self.total.fills[0] == 0 {
+            return;
+        }
+        let headers: Vec<String> = MARKOUT_HORIZONS.iter().map(|nanos| format!("{}s", nanos / 1_000_000_000)).collect();
+        println!("\n--- Maker Markouts (mid move per unit after the fill, positive favours the maker) ---");
+        println!("{:<16} {:>8} {:>12} {:>12} {:>12}", "Client", "Fills", headers[0], headers[1], headers[2]);
+        let show = |curve: &MarkoutCurve, horizon: usize| curve.average(horizon).map_or_else(|| "-".to_string(), |value| value.round_dp(4).to_string());
+        let rows = self.clients.iter().map(|(client, curve)| (client.to_string(), curve));
+        for (client, curve) in rows.chain([("(all makers)".to_string(), &self.total)]) {
+            println!("{:<16} {:>8} {:>12} {:>12} {:>12}", client, curve.fills[0], show(curve, 0), show(curve, 1), show(curve, 2));
+        }
+        if self.unsettled() > 0 {
+            println!("{} fills were too close to the end of the run for every horizon.", self.unsettled());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_markouts_use_the_mid_prevailing_at_each_horizon() {
+        let mut tracker = MarkoutTracker::default();
+        let alice = ClientId::new("alice");
+        let mid = |price: Decimal| move |_: &str| Some(price);
+        // A taker buys 10 from Alice's offer at 100.05 with the mid at 100.
+        let trade = Trade::new("SOFI".to_string(), dec!(100.05), dec!(10), Uuid::new_v4(), Uuid::new_v4(), Side::Buy);
+        tracker.record_fill(&trade, Some(&alice), Some(dec!(100)), 0);
+
+        tracker.advance(SECOND / 2, mid(dec!(100.5)));
+        tracker.advance(2 * SECOND, mid(dec!(101)));
+        assert_eq!(tracker.client_curves()[&alice].average(0), Some(dec!(-0.45)));
+        tracker.advance(40 * SECOND, mid(dec!(99)));
+        let curve = &tracker.client_curves()[&alice];
+        assert_eq!(curve.average(1), Some(dec!(-0.95)));
+        assert_eq!(curve.average(2), Some(dec!(-0.95)));
+        assert_eq!(tracker.unsettled(), 0);
+    }
+
+    #[test]
+    fn test_buying_makers_gain_when_the_mid_rises_and_anonymous_fills_count_in_the_total() {
+        let mut tracker = MarkoutTracker::default();
+        let bob = ClientId::new("bob");
+        let hit = Trade::new("SOFI".to_string(), dec!(9.9), dec!(4), Uuid::new_v4(), Uuid::new_v4(), Side::Sell);
+        tracker.record_fill(&hit, Some(&bob), Some(dec!(10)), 0);
+        tracker.record_fill(&hit, None, Some(dec!(10)), 0);
+
+        tracker.advance(SECOND / 2, |_| Some(dec!(10.1)));
+        tracker.advance(SECOND, |_| Some(dec!(10.1)));
+        assert_eq!(tracker.client_curves()[&bob].average(0), Some(dec!(0.2)));
+        assert_eq!(tracker.total().fills[0], 2);
+        assert_eq!(tracker.client_curves()[&bob].average(1), None);
+        assert_eq!(tracker.unsettled(), 2);
+    }
+}
@@ -0,0 +1,221 @@
+//! Allocation and resident memory per run phase. Allocation counts need the
+//! counting allocator installed by the `alloc-stats` feature; peak RSS is read
+//! from `/proc` on Linux and is unavailable elsewhere.
+
+#[cfg(feature = "alloc-stats")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+#[cfg(feature = "alloc-stats")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Wraps the system allocator, counting every allocation.
+#[cfg(feature = "alloc-stats")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "alloc-stats")]
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "alloc-stats")]
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "alloc-stats")]
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "alloc-stats")]
+static PEAK_LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[cfg(feature = "alloc-stats")]
+impl CountingAllocator {
+    fn record_alloc(size: usize) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+        let live = LIVE_BYTES.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+        PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+            Self::record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Allocator counters at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocCounters {
+    pub allocations: u64,
+    pub bytes: u64,
+    pub live_bytes: u64,
+}
+
+/// The current counters, or `None` without the `alloc-stats` feature.
+pub fn alloc_counters() -> Option<AllocCounters> {
+    #[cfg(feature = "alloc-stats")]
+    return Some(AllocCounters {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+    });
+    #[cfg(not(feature = "alloc-stats"))]
+    None
+}
+
+/// Restarts the heap high-water mark from the bytes live now.
+fn reset_peak_live_bytes() {
+    #[cfg(feature = "alloc-stats")]
+    PEAK_LIVE_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+fn peak_live_bytes() -> Option<u64> {
+    #[cfg(feature = "alloc-stats")]
+    return Some(PEAK_LIVE_BYTES.load(Ordering::Relaxed));
+    #[cfg(not(feature = "alloc-stats"))]
+    None
+}
+
+/// The process's resident set high-water mark in kilobytes.
+pub fn peak_rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Restarts the RSS high-water mark from the current RSS. Returns `false`
+/// where the kernel does not support it.
+fn reset_peak_rss() -> bool {
+    fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// What one phase of a run allocated and how much memory it peaked at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseMemory {
+    pub phase: &'static str,
+    pub allocations: Option<u64>,
+    pub bytes: Option<u64>,
+    pub peak_heap_bytes: Option<u64>,
+    pub peak_rss_kb: Option<u64>,
+    /// `false` when the RSS high-water mark could not be reset at the start
+    /// of the phase, so `peak_rss_kb` covers the run so far.
+    pub rss_since_phase_start: bool,
+}
+
+/// Splits a run into consecutive phases and records the memory of each.
+#[derive(Debug)]
+pub struct MemoryTracker {
+    phase_start: Option<AllocCounters>,
+    rss_reset: bool,
+    phases: Vec<PhaseMemory>,
+}
+
+impl Default for MemoryTracker {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+impl MemoryTracker {
+    /// Starts the first phase now.
+    pub fn start() -> Self {
+        reset_peak_live_bytes();
+        Self { phase_start: alloc_counters(), rss_reset: reset_peak_rss(), phases: Vec::new() }
+    }
+
+    /// Ends the current phase under `phase` and starts the next one.
+    pub fn end_phase(&mut self, phase: &'static str) {
+        let now = alloc_counters();
+        let delta = |field: fn(&AllocCounters) -> u64| Some(field(now.as_ref()?) - field(self.phase_start.as_ref()?));
+        self.phases.push(PhaseMemory {
+            phase,
+            allocations: delta(|c| c.allocations),
+            bytes: delta(|c| c.bytes),
+            peak_heap_bytes: peak_live_bytes(),
+            peak_rss_kb: peak_rss_kb(),
+            rss_since_phase_start: self.rss_reset,
+        });
+        reset_peak_live_bytes();
+        self.phase_start = now;
+        self.rss_reset = reset_peak_rss();
+    }
+
+    pub fn phases(&self) -> &[PhaseMemory] {
+        &self.phases
+    }
+
+    pub fn into_phases(self) -> Vec<PhaseMemory> {
+        self.phases
+    }
+}
+
+pub fn print_phases(phases: &[PhaseMemory]) {
+    let show = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |value| value.to_string());
+    println!("\n--- Memory by Phase ---");
+    println!("{:<12} {:>14} {:>16} {:>16} {:>14}", "Phase", "Allocations", "Bytes", "Peak heap", "Peak RSS kB");
+    for phase in phases {
+        let rss = show(phase.peak_rss_kb) + if phase.rss_since_phase_start { "" } else { "*" };
+        println!(
+            "{:<12} {:>14} {:>16} {:>16} {:>14}",
+            phase.phase, show(phase.allocations), show(phase.bytes), show(phase.peak_heap_bytes), rss
+        );
+    }
+    if phases.iter().any(|phase| !phase.rss_since_phase_start) {
+        println!("* the kernel could not reset the high-water mark, so this is the peak of the run so far");
+    }
+    if phases.iter().all(|phase| phase.allocations.is_none()) {
+        println!("Build with --features alloc-stats to count allocations.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_records_each_phase_in_order() {
+        let mut tracker = MemoryTracker::start();
+        let buffer: Vec<u64> = (0..100_000).collect();
+        tracker.end_phase("load");
+        drop(buffer);
+        tracker.end_phase("simulate");
+
+        let phases = tracker.phases();
+        assert_eq!(phases.iter().map(|p| p.phase).collect::<Vec<_>>(), ["load", "simulate"]);
+        if cfg!(target_os = "linux") {
+            assert!(phases[0].peak_rss_kb.is_some_and(|kb| kb > 0));
+        }
+        #[cfg(feature = "alloc-stats")]
+        {
+            assert!(phases[0].allocations.is_some_and(|n| n >= 1));
+            assert!(phases[0].bytes.is_some_and(|bytes| bytes >= 800_000));
+        }
+        #[cfg(not(feature = "alloc-stats"))]
+        assert_eq!(phases[0].allocations, None);
+    }
+}
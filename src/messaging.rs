@@ -0,0 +1,238 @@
+//! Message accounting per client and the order-to-trade ratio (OTR) policy
+//! exchanges use to discourage quote stuffing.
+
+use crate::clients::ClientId;
+use crate::utils::MatchingEngineError;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Messages a client may send before its ratio is checked, unless the policy
+/// names another grace.
+pub const DEFAULT_OTR_GRACE: u64 = 100;
+
+/// What happens to a message that takes its client past the cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtrAction {
+    /// New orders are rejected until the client trades more. Cancels are
+    /// always accepted, so a client can still get out of the market.
+    Reject,
+    /// The message goes through and the client is charged `fee`.
+    Penalty { fee: Decimal },
+}
+
+/// A cap on messages per trade, checked once a client has sent `grace`
+/// messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OtrPolicy {
+    pub max_ratio: f64,
+    pub grace: u64,
+    pub action: OtrAction,
+}
+
+impl FromStr for OtrPolicy {
+    type Err = String;
+
+    /// Parses `<ratio>[:reject|:penalty:<fee>]`; the default action rejects.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ratio, action) = s.split_once(':').unwrap_or((s, "reject"));
+        let max_ratio = ratio
+            .parse::<f64>()
+            .ok()
+            .filter(|ratio| ratio.is_finite() && *ratio >= 1.0)
+            .ok_or_else(|| format!("Invalid order-to-trade ratio '{}', expected a number of at least 1", ratio))?;
+        let action = match action.split_once(':') {
+            None if action == "reject" => OtrAction::Reject,
+            Some(("penalty", fee)) => OtrAction::Penalty {
+                fee: Decimal::from_str(fee)
+                    .ok()
+                    .filter(|fee| *fee > Decimal::ZERO)
+                    .ok_or_else(|| format!("Invalid OTR penalty fee '{}'", fee))?,
+            },
+            _ => return Err(format!("Unknown OTR action '{}', expected reject or penalty:<fee>", action)),
+        };
+        Ok(OtrPolicy { max_ratio, grace: DEFAULT_OTR_GRACE, action })
+    }
+}
+
+/// One client's traffic. Amendments are not counted because the engine has
+/// no amend message; a cancel and a new order is two messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub new_orders: u64,
+    pub cancels: u64,
+    /// Fills of the client's orders, one per trade it took part in.
+    pub trades: u64,
+    pub executed_quantity: Decimal,
+    /// New orders refused by the OTR cap. They still count as messages.
+    pub rejected: u64,
+    pub penalties: Decimal,
+}
+
+impl MessageCounts {
+    pub fn messages(&self) -> u64 {
+        self.new_orders + self.cancels
+    }
+
+    /// Messages per trade, or `None` before the client has traded.
+    pub fn order_to_trade_ratio(&self) -> Option<f64> {
+        (self.trades > 0).then(|| self.messages() as f64 / self.trades as f64)
+    }
+
+    /// Whether the client is past the cap. A client that has not traded is
+    /// measured as if it had traded once.
+    fn exceeds(&self, policy: &OtrPolicy) -> bool {
+        self.messages() > policy.grace && self.messages() as f64 / self.trades.max(1) as f64 > policy.max_ratio
+    }
+}
+
+/// The message counts of every client and the policy applied to them.
+#[derive(Debug, Default)]
+pub struct MessageAccounting {
+    clients: BTreeMap<ClientId, MessageCounts>,
+    policy: Option<OtrPolicy>,
+}
+
+impl MessageAccounting {
+    pub fn new(policy: Option<OtrPolicy>) -> Self {
+        MessageAccounting { clients: BTreeMap::new(), policy }
+    }
+
+    pub fn policy(&self) -> Option<&OtrPolicy> {
+        self.policy.as_ref()
+    }
+
+    pub fn set_policy(&mut self, policy: Option<OtrPolicy>) {
+        self.policy = policy;
+    }
+
+    fn counts(&mut self, client: &ClientId) -> &mut MessageCounts {
+        if !self.clients.contains_key(client) {
+            self.clients.insert(client.clone(), MessageCounts::default());
+        }
+        self.clients.get_mut(client).expect("inserted above")
+    }
+
+    /// Whether the policy lets `client` send one more new order, without
+    /// counting it.
+    pub fn check_new_order(&self, client: &ClientId) -> Result<(), MatchingEngineError> {
+        let Some(policy) = self.policy else { return Ok(()) };
+        let mut counts = self.clients.get(client).cloned().unwrap_or_default();
+        counts.new_orders += 1;
+        match policy.action {
+            OtrAction::Reject if counts.exceeds(&policy) => {
+                Err(MatchingEngineError::OrderToTradeRatioExceeded { client: client.to_string(), max_ratio: policy.max_ratio })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a new order from `client`, whether it was accepted or not,
+    /// recording a refusal or charging the penalty if it is past the cap.
+    pub fn count_new_order(&mut self, client: &ClientId) {
+        let policy = self.policy;
+        let counts = self.counts(client);
+        counts.new_orders += 1;
+        if let Some(policy) = policy
+            && counts.exceeds(&policy) {
+            match policy.action {
+                OtrAction::Reject => counts.rejected += 1,
+                OtrAction::Penalty { fee } => counts.penalties += fee,
+            }
+        }
+    }
+
+    /// Counts a new order from `client` and applies the policy to it.
+    pub fn new_order(&mut self, client: &ClientId) -> Result<(), MatchingEngineError> {
+        let checked = self.check_new_order(client);
+        self.count_new_order(client);
+        checked
+    }
+
+    /// Counts a cancel request from `client`, charging the penalty if the
+    /// client is past the cap. Cancels are never rejected.
+    pub fn cancel(&mut self, client: &ClientId) {
+        let policy = self.policy;
+        let counts = self.counts(client);
+        counts.cancels += 1;
+        if let Some(policy) = policy
+            && let OtrAction::Penalty { fee } = policy.action
+            && counts.exceeds(&policy) {
+            counts.penalties += fee;
+        }
+    }
+
+    pub fn execution(&mut self, client: &ClientId, quantity: Decimal) {
+        let counts = self.counts(client);
+        counts.trades += 1;
+        counts.executed_quantity += quantity;
+    }
+
+    pub fn clients(&self) -> &BTreeMap<ClientId, MessageCounts> {
+        &self.clients
+    }
+}
+
+pub fn print_message_counts(accounting: &MessageAccounting) {
+    if accounting.clients().is_empty() {
+        return;
+    }
+    let cap = accounting.policy().map_or_else(|| "no cap".to_string(), |policy| format!("cap {} after {} messages", policy.max_ratio, policy.grace));
+    println!("\n--- Messages per Client ({}) ---", cap);
+    println!("{:<16} {:>10} {:>10} {:>8} {:>12} {:>10} {:>10} {:>10}", "Client", "New", "Cancels", "Trades", "Executed", "OTR", "Rejected", "Penalties");
+    for (client, counts) in accounting.clients() {
+        let ratio = counts.order_to_trade_ratio().map_or_else(|| "-".to_string(), |ratio| format!("{:.2}", ratio));
+        println!(
+            "{:<16} {:>10} {:>10} {:>8} {:>12} {:>10} {:>10} {:>10}",
+            client.to_string(), counts.new_orders, counts.cancels, counts.trades, counts.executed_quantity, ratio, counts.rejected, counts.penalties
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse_otr_policy() {
+        assert_eq!("20".parse::<OtrPolicy>().unwrap(), OtrPolicy { max_ratio: 20.0, grace: DEFAULT_OTR_GRACE, action: OtrAction::Reject });
+        assert_eq!("5.5:penalty:0.01".parse::<OtrPolicy>().unwrap().action, OtrAction::Penalty { fee: dec!(0.01) });
+        assert!("0.5".parse::<OtrPolicy>().is_err());
+        assert!("20:fine".parse::<OtrPolicy>().is_err());
+        assert!("20:penalty:-1".parse::<OtrPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_cap_rejects_new_orders_but_not_cancels() {
+        let mut accounting = MessageAccounting::new(Some(OtrPolicy { max_ratio: 2.0, grace: 3, action: OtrAction::Reject }));
+        let alice = ClientId::new("alice");
+        for _ in 0..3 {
+            accounting.new_order(&alice).unwrap();
+        }
+        assert!(matches!(accounting.new_order(&alice), Err(MatchingEngineError::OrderToTradeRatioExceeded { .. })));
+        accounting.cancel(&alice);
+        accounting.execution(&alice, dec!(5));
+        accounting.execution(&alice, dec!(5));
+        accounting.execution(&alice, dec!(5));
+        // Six messages over three trades is back within the cap.
+        accounting.new_order(&alice).unwrap();
+
+        let counts = &accounting.clients()[&alice];
+        assert_eq!((counts.new_orders, counts.cancels, counts.rejected), (5, 1, 1));
+        assert_eq!(counts.order_to_trade_ratio(), Some(2.0));
+        assert_eq!(counts.executed_quantity, dec!(15));
+    }
+
+    #[test]
+    fn test_penalty_charges_every_message_past_the_cap() {
+        let mut accounting = MessageAccounting::new(Some(OtrPolicy { max_ratio: 1.0, grace: 1, action: OtrAction::Penalty { fee: dec!(0.5) } }));
+        let bob = ClientId::new("bob");
+        for _ in 0..3 {
+            accounting.new_order(&bob).unwrap();
+        }
+        accounting.cancel(&bob);
+        assert_eq!(accounting.clients()[&bob].penalties, dec!(1.5));
+        assert_eq!(accounting.clients()[&bob].rejected, 0);
+    }
+}
@@ -0,0 +1,515 @@
+use crate::clients::ClientId;
+use crate::order::{CancelTimer, Order, OrderAttributes, Peg, PegReference};
+use crate::utils::{OrderType, Side};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "csv")]
+use std::fs::File;
+#[cfg(feature = "csv")]
+use std::io::Read;
+use uuid::Uuid;
+
+#[cfg(feature = "csv")]
+const EXPECTED_RECORDS: usize = 100_000;
+
+/// One row of an operations CSV exactly as it appears in the file.
+#[derive(Debug, Deserialize)]
+pub struct OperationRecord {
+    pub operation: String,
+    pub instrument: String,
+    pub side: Option<String>,
+    pub order_type: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub order_to_cancel: Option<String>,
+    /// Optional owning client; files without the column parse as before.
+    #[serde(default)]
+    pub client: Option<String>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// `PRIMARY`, `MIDPOINT` or `MARKET` makes a LIMIT order pegged; its
+    /// `price`, if any, is then the peg's limit.
+    #[serde(default)]
+    pub peg: Option<String>,
+    #[serde(default)]
+    pub peg_offset: Option<Decimal>,
+    /// Hidden price a LIMIT order may trade up to beyond its displayed price.
+    #[serde(default)]
+    pub discretion: Option<Decimal>,
+    /// `|`-separated order attributes such as `POST_ONLY|IOC`.
+    #[serde(default)]
+    pub attributes: Option<String>,
+    /// Engine-clock nanoseconds at which a resting LIMIT order expires.
+    #[serde(default)]
+    pub expire_at: Option<u64>,
+    /// Nanoseconds after resting at which a LIMIT order is canceled.
+    #[serde(default)]
+    pub cancel_after: Option<u64>,
+    /// Makes the order a stop, held until a trade reaches this price.
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+}
+
+/// A validated order submission. A `price` makes it a limit order, its absence
+/// a market order, unless `peg` is set: a pegged order is always a limit order
+/// and its optional `price` is the peg's limit. `client`, when set, owns the
+/// order once it rests; together with a `client_order_id` it makes
+/// resubmitting the same order harmless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewOrder {
+    pub order_id: Uuid,
+    pub instrument: String,
+    pub side: Side,
+    pub quantity: Decimal,
+    pub price: Option<Decimal>,
+    pub client: Option<ClientId>,
+    pub client_order_id: Option<String>,
+    pub peg: Option<Peg>,
+    pub discretion: Option<Decimal>,
+    pub attributes: OrderAttributes,
+    pub expire_at: Option<u64>,
+    pub cancel_after: Option<u64>,
+    pub stop_price: Option<Decimal>,
+}
+
+impl NewOrder {
+    pub fn order_type(&self) -> OrderType {
+        match (self.price, self.peg) {
+            (Some(_), _) | (None, Some(_)) => OrderType::Limit,
+            (None, None) => OrderType::Market,
+        }
+    }
+
+    pub fn to_order(&self) -> Order {
+        let order = match (self.peg, self.price) {
+            (Some(peg), limit) => Order::new_pegged(self.order_id, self.instrument.clone(), self.side, Peg { limit, ..peg }, self.quantity),
+            (None, Some(price)) => Order::new_limit(self.order_id, self.instrument.clone(), self.side, price, self.quantity),
+            (None, None) => Order::new_market(self.order_id, self.instrument.clone(), self.side, self.quantity),
+        };
+        Order { discretion: self.discretion, attributes: self.attributes, expire_at: self.expire_at, cancel_timer: self.cancel_after.map(CancelTimer::After), stop_price: self.stop_price, ..order }
+    }
+}
+
+/// A simulation operation that passed parsing and can be handed to the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimOp {
+    New(NewOrder),
+    /// With a `client`, only an order that client owns is canceled.
+    Cancel { instrument: String, order_id: Uuid, client: Option<ClientId> },
+    /// Cancels every live order the client owns, on every instrument.
+    CancelAll { client: ClientId },
+}
+
+impl SimOp {
+    pub fn client(&self) -> Option<&ClientId> {
+        match self {
+            SimOp::New(new_order) => new_order.client.as_ref(),
+            SimOp::Cancel { client, .. } => client.as_ref(),
+            SimOp::CancelAll { client } => Some(client),
+        }
+    }
+
+    /// The operation as a row of the operations CSV, up to the `client`
+    /// column, for error messages.
+    pub fn to_row(&self) -> String {
+        let client = |client: Option<&ClientId>| client.map(ToString::to_string).unwrap_or_default();
+        match self {
+            SimOp::New(new_order) => format!(
+                "NEW,{},{},{},{},{},{},{}",
+                new_order.instrument,
+                new_order.side,
+                new_order.order_type(),
+                new_order.quantity,
+                new_order.price.map(|price| price.to_string()).unwrap_or_default(),
+                new_order.order_id,
+                client(new_order.client.as_ref()),
+            ),
+            SimOp::Cancel { instrument, order_id, client: owner } => format!("CANCEL,{},,,,,{},{}", instrument, order_id, client(owner.as_ref())),
+            SimOp::CancelAll { client: owner } => format!("CANCEL_ALL,,,,,,,{}", owner),
+        }
+    }
+
+    /// Attributes the operation to `client`, replacing whatever it claimed.
+    pub fn set_client(&mut self, client: ClientId) {
+        match self {
+            SimOp::New(new_order) => new_order.client = Some(client),
+            SimOp::Cancel { client: owner, .. } => *owner = Some(client),
+            SimOp::CancelAll { client: owner } => *owner = client,
+        }
+    }
+}
+
+impl TryFrom<OperationRecord> for SimOp {
+    type Error = String;
+
+    fn try_from(record: OperationRecord) -> Result<Self, Self::Error> {
+        let client = record.client.filter(|id| !id.is_empty()).map(ClientId);
+        let client_order_id = record.client_order_id.filter(|id| !id.is_empty());
+        match record.operation.as_str() {
+            "NEW" => {
+                let id_str = record
+                    .order_to_cancel
+                    .ok_or("NEW operation requires an ID in the 'order_to_cancel' column.")?;
+                let order_id = Uuid::parse_str(&id_str)
+                    .map_err(|_| format!("Invalid UUID format for new order: '{}'", id_str))?;
+                let side = record
+                    .side
+                    .as_deref()
+                    .and_then(|s| s.parse::<Side>().ok())
+                    .ok_or("NEW operation requires a valid SIDE.")?;
+                let order_type = record
+                    .order_type
+                    .as_deref()
+                    .and_then(|s| s.parse::<OrderType>().ok())
+                    .ok_or("NEW operation requires a valid ORDER_TYPE.")?;
+                let quantity = record.quantity.ok_or("NEW operation requires a QUANTITY.")?;
+                let peg = match record.peg.as_deref().filter(|peg| !peg.is_empty()) {
+                    Some(_) if order_type == OrderType::Market => return Err("MARKET orders cannot be pegged.".to_string()),
+                    Some(reference) => Some(Peg {
+                        offset: record.peg_offset.unwrap_or_default(),
+                        ..Peg::new(reference.parse::<PegReference>()?)
+                    }),
+                    None => None,
+                };
+                if record.discretion.is_some() && order_type == OrderType::Market {
+                    return Err("MARKET orders cannot have a DISCRETION price.".to_string());
+                }
+                if record.stop_price.is_some() && peg.is_some() {
+                    return Err("Pegged orders cannot have a STOP_PRICE.".to_string());
+                }
+                if record.expire_at.is_some() && order_type == OrderType::Market {
+                    return Err("MARKET orders cannot have an EXPIRE_AT time.".to_string());
+                }
+                if record.cancel_after.is_some() && order_type == OrderType::Market {
+                    return Err("MARKET orders cannot have a CANCEL_AFTER delay.".to_string());
+                }
+                let attributes = record.attributes.as_deref().unwrap_or_default().parse::<OrderAttributes>()?;
+                let price = match order_type {
+                    OrderType::Limit if peg.is_some() => record.price,
+                    OrderType::Limit => Some(record.price.ok_or("LIMIT order requires a valid PRICE.")?),
+                    OrderType::Market => None,
+                };
+                Ok(SimOp::New(NewOrder { order_id, instrument: record.instrument, side, quantity, price, client, client_order_id, peg, discretion: record.discretion, attributes, expire_at: record.expire_at, cancel_after: record.cancel_after, stop_price: record.stop_price }))
+            }
+            "CANCEL" => {
+                let id_str = record
+                    .order_to_cancel
+                    .ok_or("CANCEL operation requires an ID in the 'order_to_cancel' column.")?;
+                let order_id = Uuid::parse_str(&id_str)
+                    .map_err(|_| format!("Invalid UUID format for order to cancel: '{}'", id_str))?;
+                Ok(SimOp::Cancel { instrument: record.instrument, order_id, client })
+            }
+            other => Err(format!("Unknown operation type '{}'", other)),
+        }
+    }
+}
+
+/// A row that could not be parsed, with its 1-based line in the source file
+/// and the raw fields as they were read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: u64,
+    pub message: String,
+    pub fields: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)?;
+        if !self.fields.is_empty() {
+            write!(f, " [{}]", self.fields)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {}
+
+/// What to do with a malformed row.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParsePolicy {
+    /// Abort on the first malformed row.
+    Strict,
+    /// Skip malformed rows and collect them in the [`ParseReport`].
+    #[default]
+    Lenient,
+}
+
+/// The parsed operations together with every row that was skipped.
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    pub operations: Vec<SimOp>,
+    pub errors: Vec<ParseError>,
+}
+
+impl ParseReport {
+    const MAX_PRINTED_ERRORS: usize = 10;
+
+    pub fn print_summary(&self) {
+        if self.errors.is_empty() {
+            return;
+        }
+        eprintln!("Skipped {} malformed operation rows:", self.errors.len());
+        for error in self.errors.iter().take(Self::MAX_PRINTED_ERRORS) {
+            eprintln!("  {}", error);
+        }
+        if self.errors.len() > Self::MAX_PRINTED_ERRORS {
+            eprintln!("  ... and {} more", self.errors.len() - Self::MAX_PRINTED_ERRORS);
+        }
+    }
+}
+
+/// Produces simulation operations one at a time, from a file, a socket, or
+/// anything else that speaks the operations CSV schema.
+pub trait OperationSource {
+    /// The next operation, or `None` once the source is exhausted. A malformed
+    /// row is returned as `Some(Err(_))` and the source keeps going; after an
+    /// I/O error it ends.
+    fn next_operation(&mut self) -> Option<Result<SimOp, ParseError>>;
+}
+
+#[cfg(feature = "csv")]
+/// Streams operations out of any reader producing the operations CSV schema,
+/// header row included.
+pub struct OperationReader<R: Read> {
+    reader: csv::Reader<R>,
+    headers: csv::StringRecord,
+    record: csv::StringRecord,
+    finished: bool,
+}
+
+#[cfg(feature = "csv")]
+impl<R: Read> OperationReader<R> {
+    pub fn new(source: R) -> Result<Self, ParseError> {
+        let mut reader = csv::Reader::from_reader(source);
+        let headers = reader
+            .headers()
+            .map_err(|e| ParseError { line: 1, message: e.to_string(), fields: String::new() })?
+            .clone();
+        Ok(Self { reader, headers, record: csv::StringRecord::new(), finished: false })
+    }
+
+    /// Reads one row. The outer error is fatal (I/O); the inner one is a
+    /// malformed row that can be skipped.
+    fn read(&mut self) -> Result<Option<Result<SimOp, ParseError>>, ParseError> {
+        match self.reader.read_record(&mut self.record) {
+            Ok(true) => {
+                let line = self.record.position().map_or(0, |p| p.line());
+                Ok(Some(
+                    self.record
+                        .deserialize::<OperationRecord>(Some(&self.headers))
+                        .map_err(|e| e.to_string())
+                        .and_then(SimOp::try_from)
+                        .map_err(|message| ParseError { line, message, fields: self.record.iter().collect::<Vec<_>>().join(",") }),
+                ))
+            }
+            Ok(false) => Ok(None),
+            Err(e) => {
+                let error = ParseError {
+                    line: e.position().map_or(0, |p| p.line()),
+                    message: e.to_string(),
+                    fields: String::new(),
+                };
+                if e.is_io_error() {
+                    Err(error)
+                } else {
+                    Ok(Some(Err(error)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<R: Read> OperationSource for OperationReader<R> {
+    fn next_operation(&mut self) -> Option<Result<SimOp, ParseError>> {
+        if self.finished {
+            return None;
+        }
+        match self.read() {
+            Ok(Some(parsed)) => Some(parsed),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(error) => {
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+/// Parses an operations CSV. Under `ParsePolicy::Strict` the first malformed
+/// row is returned as the error; I/O errors always abort.
+pub fn parse_operations<R: Read>(source: R, policy: ParsePolicy) -> Result<ParseReport, ParseError> {
+    let mut reader = OperationReader::new(source)?;
+    let mut report = ParseReport { operations: Vec::with_capacity(EXPECTED_RECORDS), errors: Vec::new() };
+
+    while let Some(parsed) = reader.read()? {
+        match parsed {
+            Ok(op) => report.operations.push(op),
+            Err(error) if policy == ParsePolicy::Lenient => report.errors.push(error),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(feature = "csv")]
+pub fn load_operations(path: &str, policy: ParsePolicy) -> Result<ParseReport, Box<dyn Error>> {
+    let file = File::open(path)?;
+    Ok(parse_operations(file, policy)?)
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const HEADER: &str = "operation,instrument,side,order_type,quantity,price,order_to_cancel\n";
+
+    fn parse(rows: &str) -> Result<Vec<SimOp>, ParseError> {
+        parse_operations(format!("{}{}", HEADER, rows).as_bytes(), ParsePolicy::Strict).map(|report| report.operations)
+    }
+
+    #[test]
+    fn test_parse_new_and_cancel_operations() {
+        let ops = parse(
+            "NEW,PUMPTHIS,BUY,LIMIT,10,99.50,00000000-0000-0000-0000-000000000001\n\
+             NEW,PUMPTHIS,SELL,MARKET,5,,00000000-0000-0000-0000-000000000002\n\
+             CANCEL,PUMPTHIS,,,,,00000000-0000-0000-0000-000000000001\n",
+        )
+        .unwrap();
+
+        assert_eq!(ops.len(), 3);
+        let SimOp::New(limit) = &ops[0] else { panic!("expected a NEW operation") };
+        assert_eq!(limit.side, Side::Buy);
+        assert_eq!(limit.order_type(), OrderType::Limit);
+        assert_eq!(limit.price, Some(dec!(99.50)));
+        let SimOp::New(market) = &ops[1] else { panic!("expected a NEW operation") };
+        assert_eq!(market.order_type(), OrderType::Market);
+        assert_eq!(market.to_order().price, None);
+        assert_eq!(ops[2], SimOp::Cancel { instrument: "PUMPTHIS".to_string(), order_id: limit.order_id, client: None });
+    }
+
+    #[test]
+    fn test_parse_optional_client_column() {
+        let csv = "operation,instrument,side,order_type,quantity,price,order_to_cancel,client,client_order_id\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,99.50,00000000-0000-0000-0000-000000000001,alice,A-1\n\
+                   CANCEL,PUMPTHIS,,,,,00000000-0000-0000-0000-000000000001,,\n";
+        let ops = parse_operations(csv.as_bytes(), ParsePolicy::Strict).unwrap().operations;
+
+        assert_eq!(ops[0].client(), Some(&ClientId::new("alice")));
+        let SimOp::New(new_order) = &ops[0] else { panic!("expected a NEW operation") };
+        assert_eq!(new_order.client_order_id.as_deref(), Some("A-1"));
+        assert_eq!(ops[1].client(), None);
+        assert!(parse("NEW,PUMPTHIS,BUY,MARKET,5,,00000000-0000-0000-0000-000000000002\n").unwrap()[0].client().is_none());
+    }
+
+    #[test]
+    fn test_parse_pegged_orders() {
+        let csv = "operation,instrument,side,order_type,quantity,price,order_to_cancel,peg,peg_offset\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,,00000000-0000-0000-0000-000000000001,MIDPOINT,\n\
+                   NEW,PUMPTHIS,SELL,LIMIT,10,101,00000000-0000-0000-0000-000000000002,primary,0.01\n\
+                   NEW,PUMPTHIS,SELL,MARKET,10,,00000000-0000-0000-0000-000000000003,MARKET,\n";
+        let report = parse_operations(csv.as_bytes(), ParsePolicy::Lenient).unwrap();
+
+        assert_eq!(report.operations.len(), 2);
+        assert_eq!(report.errors.len(), 1);
+        let SimOp::New(midpoint) = &report.operations[0] else { panic!("expected a NEW operation") };
+        assert_eq!(midpoint.order_type(), OrderType::Limit);
+        assert_eq!(midpoint.to_order().peg, Some(Peg::new(PegReference::Midpoint)));
+        let SimOp::New(primary) = &report.operations[1] else { panic!("expected a NEW operation") };
+        let peg = primary.to_order().peg.unwrap();
+        assert_eq!((peg.reference, peg.offset, peg.limit), (PegReference::Primary, dec!(0.01), Some(dec!(101))));
+    }
+
+    #[test]
+    fn test_parse_discretionary_orders() {
+        let csv = "operation,instrument,side,order_type,quantity,price,order_to_cancel,discretion\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,99,00000000-0000-0000-0000-000000000001,99.5\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,99,00000000-0000-0000-0000-000000000002,\n\
+                   NEW,PUMPTHIS,BUY,MARKET,10,,00000000-0000-0000-0000-000000000003,99.5\n";
+        let report = parse_operations(csv.as_bytes(), ParsePolicy::Lenient).unwrap();
+
+        assert_eq!(report.operations.len(), 2);
+        assert_eq!(report.errors[0].message, "MARKET orders cannot have a DISCRETION price.");
+        let SimOp::New(discretionary) = &report.operations[0] else { panic!("expected a NEW operation") };
+        assert_eq!(discretionary.to_order().trading_limit(), Some(dec!(99.5)));
+        let SimOp::New(plain) = &report.operations[1] else { panic!("expected a NEW operation") };
+        assert_eq!(plain.to_order().discretion, None);
+    }
+
+    #[test]
+    fn test_parse_order_attributes() {
+        let csv = "operation,instrument,side,order_type,quantity,price,order_to_cancel,attributes\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,99,00000000-0000-0000-0000-000000000001,POST_ONLY\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,99,00000000-0000-0000-0000-000000000002,IOC|BOGUS\n";
+        let report = parse_operations(csv.as_bytes(), ParsePolicy::Lenient).unwrap();
+
+        assert_eq!(report.errors[0].message, "Unknown order attribute 'BOGUS'");
+        let SimOp::New(post_only) = &report.operations[0] else { panic!("expected a NEW operation") };
+        assert!(post_only.to_order().attributes.is_post_only());
+    }
+
+    #[test]
+    fn test_parse_expiry_times() {
+        let csv = "operation,instrument,side,order_type,quantity,price,order_to_cancel,expire_at\n\
+                   NEW,PUMPTHIS,BUY,LIMIT,10,99,00000000-0000-0000-0000-000000000001,5000\n\
+                   NEW,PUMPTHIS,BUY,MARKET,10,,00000000-0000-0000-0000-000000000002,5000\n";
+        let report = parse_operations(csv.as_bytes(), ParsePolicy::Lenient).unwrap();
+
+        assert_eq!(report.errors[0].message, "MARKET orders cannot have an EXPIRE_AT time.");
+        let SimOp::New(good_till_date) = &report.operations[0] else { panic!("expected a NEW operation") };
+        assert_eq!(good_till_date.to_order().expire_at, Some(5_000));
+    }
+
+    #[test]
+    fn test_parse_cancel_after_delays() {
+        let csv = "operation,instrument,side,order_type,quantity,price,order_to_cancel,cancel_after\n\
+                   NEW,PUMPTHIS,SELL,LIMIT,10,101,00000000-0000-0000-0000-000000000001,500000000\n\
+                   NEW,PUMPTHIS,BUY,MARKET,10,,00000000-0000-0000-0000-000000000002,500000000\n";
+        let report = parse_operations(csv.as_bytes(), ParsePolicy::Lenient).unwrap();
+
+        assert_eq!(report.errors[0].message, "MARKET orders cannot have a CANCEL_AFTER delay.");
+        let SimOp::New(quote) = &report.operations[0] else { panic!("expected a NEW operation") };
+        assert_eq!(quote.to_order().cancel_timer, Some(CancelTimer::After(500_000_000)));
+    }
+
+    #[test]
+    fn test_parse_error_reports_line_number() {
+        let err = parse(
+            "NEW,PUMPTHIS,BUY,LIMIT,10,99.50,00000000-0000-0000-0000-000000000001\n\
+             NEW,PUMPTHIS,BUY,LIMIT,10,,00000000-0000-0000-0000-000000000002\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "LIMIT order requires a valid PRICE.");
+
+        let err = parse("AMEND,PUMPTHIS,,,,,00000000-0000-0000-0000-000000000001\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.to_string().starts_with("line 2: Unknown operation type"));
+
+        assert_eq!(parse("NEW,PUMPTHIS,BUY,LIMIT,abc,1,00000000-0000-0000-0000-000000000001\n").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_lenient_policy_collects_errors_and_continues() {
+        let rows = "NEW,PUMPTHIS,BUY,LIMIT,10,,00000000-0000-0000-0000-000000000001\n\
+                    NEW,PUMPTHIS,BUY\n\
+                    NEW,PUMPTHIS,SELL,LIMIT,10,99.50,00000000-0000-0000-0000-000000000002\n\
+                    CANCEL,PUMPTHIS,,,,,not-a-uuid\n";
+        let report = parse_operations(format!("{}{}", HEADER, rows).as_bytes(), ParsePolicy::Lenient).unwrap();
+
+        assert_eq!(report.operations.len(), 1);
+        let lines: Vec<u64> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 5]);
+        assert_eq!(report.errors[2].fields, "CANCEL,PUMPTHIS,,,,,not-a-uuid");
+        assert!(report.errors[2].to_string().contains("not-a-uuid"));
+    }
+}
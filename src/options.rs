@@ -0,0 +1,133 @@
+//! Expiry of option instruments: positions in the money are exercised into
+//! the underlying at the strike, and every option position is closed.
+
+use crate::clients::ClientId;
+use crate::config::{OptionContract, OptionKind};
+use crate::order::Order;
+use crate::positions::Position;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// An option instrument reaching its expiry. Its book is closed: resting
+/// orders are removed and new ones rejected.
+#[derive(Debug, Clone)]
+pub struct OptionExpiry {
+    pub instrument: String,
+    pub contract: OptionContract,
+    pub timestamp: u64,
+    /// The underlying's last trade price, or its mid before the first trade.
+    /// Without one, every position expires unexercised.
+    pub settlement: Option<Decimal>,
+    /// Orders removed from the option's book.
+    pub orders: Vec<Order>,
+}
+
+impl OptionExpiry {
+    pub fn in_the_money(&self) -> bool {
+        self.settlement.is_some_and(|settlement| self.contract.intrinsic_value(settlement) > Decimal::ZERO)
+    }
+
+    /// The underlying quantity a position of `quantity` options takes at the
+    /// strike: positive to receive, negative to deliver, zero when the option
+    /// expires out of the money. Writers are assigned against holders.
+    pub fn delivery(&self, quantity: Decimal) -> Decimal {
+        if !self.in_the_money() {
+            return Decimal::ZERO;
+        }
+        match self.contract.kind {
+            OptionKind::Call => quantity,
+            OptionKind::Put => -quantity,
+        }
+    }
+
+    /// Closes the option position among `positions` and moves what it
+    /// delivers into the underlying position at the strike. Returns the
+    /// delivery.
+    pub fn exercise(&self, positions: &mut BTreeMap<String, Position>) -> Decimal {
+        let Some(option) = positions.get_mut(&self.instrument) else { return Decimal::ZERO };
+        let delivery = self.delivery(std::mem::take(&mut option.quantity));
+        if !delivery.is_zero() {
+            let underlying = positions.entry(self.contract.underlying.clone()).or_default();
+            underlying.quantity += delivery;
+            underlying.cash -= delivery * self.contract.strike;
+        }
+        delivery
+    }
+}
+
+/// One client's exercise or assignment at expiry: it took `quantity` of the
+/// underlying (negative when it delivered) at the strike.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exercise {
+    pub client: ClientId,
+    pub option: String,
+    pub underlying: String,
+    pub quantity: Decimal,
+    pub strike: Decimal,
+    pub settlement: Decimal,
+    pub timestamp: u64,
+}
+
+impl Exercise {
+    /// The cash the exercise moved: positive when the client received it.
+    pub fn cash(&self) -> Decimal {
+        -self.quantity * self.strike
+    }
+}
+
+pub fn print_exercises(expiries: &[OptionExpiry], exercises: &[Exercise]) {
+    if expiries.is_empty() {
+        return;
+    }
+    println!("\n--- Option Expiries ---");
+    println!("{:<12} {:>10} {:>12} {:>12} {:>10} {:>12}", "Option", "Strike", "Settlement", "Exercises", "Removed", "Delivered");
+    for expiry in expiries {
+        let delivered: Decimal = exercises.iter().filter(|e| e.option == expiry.instrument && e.quantity > Decimal::ZERO).map(|e| e.quantity).sum();
+        println!(
+            "{:<12} {:>10} {:>12} {:>12} {:>10} {:>12}",
+            expiry.instrument,
+            expiry.contract.strike,
+            expiry.settlement.map_or("-".to_string(), |price| price.to_string()),
+            exercises.iter().filter(|e| e.option == expiry.instrument).count(),
+            expiry.orders.len(),
+            delivered
+        );
+    }
+    let mut clients: BTreeMap<&ClientId, (Decimal, Decimal)> = BTreeMap::new();
+    for exercise in exercises {
+        let entry = clients.entry(&exercise.client).or_default();
+        *entry = (entry.0 + exercise.quantity, entry.1 + exercise.cash());
+    }
+    if !clients.is_empty() {
+        println!("{:<16} {:>16} {:>16}", "Client", "Underlying", "Cash");
+        for (client, (quantity, cash)) in clients {
+            println!("{:<16} {:>16} {:>16}", client.to_string(), quantity, cash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn expiry(kind: OptionKind, settlement: Option<Decimal>) -> OptionExpiry {
+        OptionExpiry {
+            instrument: "SOFI-C10".to_string(),
+            contract: OptionContract { kind, underlying: "SOFI".to_string(), strike: dec!(10), expiry: None },
+            timestamp: 0,
+            settlement,
+            orders: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_in_the_money_positions_take_the_underlying_at_the_strike() {
+        let call = expiry(OptionKind::Call, Some(dec!(12)));
+        assert_eq!((call.delivery(dec!(3)), call.delivery(dec!(-3))), (dec!(3), dec!(-3)));
+        let put = expiry(OptionKind::Put, Some(dec!(9)));
+        assert_eq!(put.delivery(dec!(2)), dec!(-2));
+        assert_eq!(expiry(OptionKind::Put, Some(dec!(12))).delivery(dec!(2)), dec!(0));
+        assert_eq!(expiry(OptionKind::Call, None).delivery(dec!(2)), dec!(0));
+    }
+}
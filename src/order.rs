@@ -1,8 +1,239 @@
+use crate::clock::{Clock, SystemClock};
 use crate::utils::{OrderStatus, OrderType, Side};
 use rust_decimal::Decimal;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
+/// The price a pegged order follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PegReference {
+    /// The best price on the order's own side: the best bid for a buy.
+    Primary,
+    /// The midpoint of the best bid and best ask.
+    Midpoint,
+    /// The best price on the opposite side: the best ask for a buy.
+    Market,
+}
+
+impl FromStr for PegReference {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "PRIMARY" => Ok(PegReference::Primary),
+            "MIDPOINT" | "MID" => Ok(PegReference::Midpoint),
+            "MARKET" => Ok(PegReference::Market),
+            _ => Err(format!("Unknown peg reference '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for PegReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PegReference::Primary => "PRIMARY",
+            PegReference::Midpoint => "MIDPOINT",
+            PegReference::Market => "MARKET",
+        })
+    }
+}
+
+/// Keeps a limit order priced `offset` away from its reference, never beyond
+/// `limit`. A positive offset is less aggressive: below the reference for a
+/// buy, above it for a sell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peg {
+    pub reference: PegReference,
+    pub offset: Decimal,
+    pub limit: Option<Decimal>,
+}
+
+impl Peg {
+    pub fn new(reference: PegReference) -> Self {
+        Peg { reference, offset: Decimal::ZERO, limit: None }
+    }
+
+    /// The pegged price for `side` given the best bid and ask, or `None` when
+    /// the reference price is not available.
+    pub fn price(&self, side: Side, best_bid: Option<Decimal>, best_ask: Option<Decimal>) -> Option<Decimal> {
+        let reference = match (self.reference, side) {
+            (PegReference::Primary, Side::Buy) | (PegReference::Market, Side::Sell) => best_bid?,
+            (PegReference::Primary, Side::Sell) | (PegReference::Market, Side::Buy) => best_ask?,
+            (PegReference::Midpoint, _) => (best_bid? + best_ask?) / Decimal::TWO,
+        };
+        Some(match (side, self.limit) {
+            (Side::Buy, Some(limit)) => (reference - self.offset).min(limit),
+            (Side::Buy, None) => reference - self.offset,
+            (Side::Sell, Some(limit)) => (reference + self.offset).max(limit),
+            (Side::Sell, None) => reference + self.offset,
+        })
+    }
+}
+
+/// How long an order keeps working after its first pass through the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeInForce {
+    /// Rests until filled or canceled.
+    #[default]
+    Day,
+    /// Trades what it can on arrival; the remainder expires.
+    ImmediateOrCancel,
+    /// Trades its full quantity on arrival or expires without trading.
+    FillOrKill,
+}
+
+/// What to do when an order would trade against another order of the same
+/// owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelfTradePrevention {
+    #[default]
+    Allow,
+    CancelNewest,
+    CancelOldest,
+    CancelBoth,
+}
+
+/// When a resting order cancels itself: `After` a delay in nanoseconds
+/// counted from when it rests, which the book turns into an `At` deadline on
+/// the engine clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelTimer {
+    After(u64),
+    At(u64),
+}
+
+/// Boolean and enumerated order options packed into three bytes, so adding
+/// an option does not grow `Order`. Built by chaining, e.g.
+/// `OrderAttributes::default().post_only().with_time_in_force(TimeInForce::ImmediateOrCancel)`.
+/// Written as `|`-separated tokens such as `POST_ONLY|IOC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OrderAttributes {
+    flags: u8,
+    time_in_force: TimeInForce,
+    self_trade: SelfTradePrevention,
+}
+
+impl OrderAttributes {
+    const POST_ONLY: u8 = 1;
+    const REDUCE_ONLY: u8 = 1 << 1;
+    const HIDDEN: u8 = 1 << 2;
+    const ALL_OR_NONE: u8 = 1 << 3;
+
+    const FLAG_NAMES: [(u8, &'static str); 4] = [
+        (Self::POST_ONLY, "POST_ONLY"),
+        (Self::REDUCE_ONLY, "REDUCE_ONLY"),
+        (Self::HIDDEN, "HIDDEN"),
+        (Self::ALL_OR_NONE, "AON"),
+    ];
+
+    /// Rejected instead of trading on arrival.
+    pub fn post_only(mut self) -> Self {
+        self.flags |= Self::POST_ONLY;
+        self
+    }
+
+    /// May only reduce the owner's position.
+    pub fn reduce_only(mut self) -> Self {
+        self.flags |= Self::REDUCE_ONLY;
+        self
+    }
+
+    /// Rests without being displayed.
+    pub fn hidden(mut self) -> Self {
+        self.flags |= Self::HIDDEN;
+        self
+    }
+
+    /// Only fills its full quantity at once.
+    pub fn all_or_none(mut self) -> Self {
+        self.flags |= Self::ALL_OR_NONE;
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn with_self_trade_prevention(mut self, self_trade: SelfTradePrevention) -> Self {
+        self.self_trade = self_trade;
+        self
+    }
+
+    pub fn is_post_only(&self) -> bool {
+        self.flags & Self::POST_ONLY != 0
+    }
+
+    pub fn is_reduce_only(&self) -> bool {
+        self.flags & Self::REDUCE_ONLY != 0
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.flags & Self::HIDDEN != 0
+    }
+
+    pub fn is_all_or_none(&self) -> bool {
+        self.flags & Self::ALL_OR_NONE != 0
+    }
+
+    pub fn time_in_force(&self) -> TimeInForce {
+        self.time_in_force
+    }
+
+    pub fn self_trade_prevention(&self) -> SelfTradePrevention {
+        self.self_trade
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == OrderAttributes::default()
+    }
+}
+
+impl FromStr for OrderAttributes {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut attributes = OrderAttributes::default();
+        for token in s.split('|').map(str::trim).filter(|token| !token.is_empty()) {
+            attributes = match token.to_uppercase().as_str() {
+                "POST_ONLY" => attributes.post_only(),
+                "REDUCE_ONLY" => attributes.reduce_only(),
+                "HIDDEN" => attributes.hidden(),
+                "AON" | "ALL_OR_NONE" => attributes.all_or_none(),
+                "DAY" => attributes.with_time_in_force(TimeInForce::Day),
+                "IOC" => attributes.with_time_in_force(TimeInForce::ImmediateOrCancel),
+                "FOK" => attributes.with_time_in_force(TimeInForce::FillOrKill),
+                "STP_CANCEL_NEWEST" => attributes.with_self_trade_prevention(SelfTradePrevention::CancelNewest),
+                "STP_CANCEL_OLDEST" => attributes.with_self_trade_prevention(SelfTradePrevention::CancelOldest),
+                "STP_CANCEL_BOTH" => attributes.with_self_trade_prevention(SelfTradePrevention::CancelBoth),
+                _ => return Err(format!("Unknown order attribute '{}'", token)),
+            };
+        }
+        Ok(attributes)
+    }
+}
+
+impl fmt::Display for OrderAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tokens: Vec<&str> = Self::FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.flags & flag != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        match self.time_in_force {
+            TimeInForce::Day => {}
+            TimeInForce::ImmediateOrCancel => tokens.push("IOC"),
+            TimeInForce::FillOrKill => tokens.push("FOK"),
+        }
+        match self.self_trade {
+            SelfTradePrevention::Allow => {}
+            SelfTradePrevention::CancelNewest => tokens.push("STP_CANCEL_NEWEST"),
+            SelfTradePrevention::CancelOldest => tokens.push("STP_CANCEL_OLDEST"),
+            SelfTradePrevention::CancelBoth => tokens.push("STP_CANCEL_BOTH"),
+        }
+        f.write_str(&tokens.join("|"))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Order {
     pub order_id: Uuid,
@@ -13,7 +244,28 @@ pub struct Order {
     pub price: Option<Decimal>,
     pub quantity: Decimal,
     pub remaining_quantity: Decimal,
+    /// Wall-clock time the order was created. Informational only: two orders
+    /// can share it, and the clock can step back.
     pub timestamp: u64,
+    /// Arrival number in its book, from 1, given when the book takes the
+    /// order; 0 before. This, not `timestamp`, is the order's time priority.
+    pub sequence: u64,
+    /// Set for pegged orders, whose `price` is filled in and kept up to date
+    /// by the book.
+    pub peg: Option<Peg>,
+    /// A hidden price beyond the displayed `price` up to which a limit order
+    /// may trade when that is the only way to fill.
+    pub discretion: Option<Decimal>,
+    pub attributes: OrderAttributes,
+    /// Engine-clock time at which a resting order leaves the book, making it
+    /// good-till-date.
+    pub expire_at: Option<u64>,
+    /// Cancels the order some time after it rests, as market makers give
+    /// quotes a lifetime.
+    pub cancel_timer: Option<CancelTimer>,
+    /// Makes the order a stop: the engine holds it until a trade prints at or
+    /// above this price for a buy, at or below it for a sell.
+    pub stop_price: Option<Decimal>,
 }
 
 impl Order {
@@ -36,6 +288,18 @@ impl Order {
         Self::new(order_id, instrument, side, OrderType::Market, None, quantity)
     }
 
+    /// A limit order whose price tracks `peg`. It has no price until the
+    /// engine accepts it.
+    pub fn new_pegged(
+        order_id: Uuid,
+        instrument: String,
+        side: Side,
+        peg: Peg,
+        quantity: Decimal,
+    ) -> Self {
+        Order { peg: Some(peg), ..Self::new(order_id, instrument, side, OrderType::Limit, None, quantity) }
+    }
+
     fn new(
         order_id: Uuid,
         instrument: String,
@@ -44,11 +308,6 @@ impl Order {
         price: Option<Decimal>,
         quantity: Decimal,
     ) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("System time is before the UNIX epoch, something is very wrong.")
-            .as_nanos() as u64;
-
         Order {
             order_id,
             instrument,
@@ -58,9 +317,89 @@ impl Order {
             price,
             quantity,
             remaining_quantity: quantity,
-            timestamp,
+            // Replaced with engine time when the engine takes the order.
+            timestamp: SystemClock.now_nanos(),
+            sequence: 0,
+            peg: None,
+            discretion: None,
+            attributes: OrderAttributes::default(),
+            expire_at: None,
+            cancel_timer: None,
+            stop_price: None,
+        }
+    }
+
+    pub fn with_attributes(mut self, attributes: OrderAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_discretion(mut self, discretion: Decimal) -> Self {
+        self.discretion = Some(discretion);
+        self
+    }
+
+    /// Makes the order good-till-date: whatever still rests at `expire_at` on
+    /// the engine clock expires.
+    pub fn with_expiry(mut self, expire_at: u64) -> Self {
+        self.expire_at = Some(expire_at);
+        self
+    }
+
+    /// Cancels whatever of the order rests `delay` nanoseconds of engine
+    /// time after it starts resting. Unlike an expiry, the clock starts
+    /// when the order reaches the book, so time spent behind a speed bump
+    /// or a stop does not count.
+    pub fn with_cancel_after(mut self, delay: u64) -> Self {
+        self.cancel_timer = Some(CancelTimer::After(delay));
+        self
+    }
+
+    /// Turns a `cancel_after` delay into a deadline as the order starts
+    /// resting at `now`.
+    pub fn start_cancel_timer(&mut self, now: u64) {
+        if let Some(CancelTimer::After(delay)) = self.cancel_timer {
+            self.cancel_timer = Some(CancelTimer::At(now.saturating_add(delay)));
+        }
+    }
+
+    /// Engine-clock time at which the resting order is canceled.
+    pub fn cancel_at(&self) -> Option<u64> {
+        match self.cancel_timer {
+            Some(CancelTimer::At(cancel_at)) => Some(cancel_at),
+            _ => None,
+        }
+    }
+
+    /// Makes the order a stop order, held until a trade reaches `stop_price`.
+    /// A stop without a limit price becomes a market order when triggered.
+    pub fn with_stop(mut self, stop_price: Decimal) -> Self {
+        self.stop_price = Some(stop_price);
+        self
+    }
+
+    /// Whether trades printing between `low` and `high` trigger the order's
+    /// stop. Orders without a stop are always live.
+    pub fn stop_triggered(&self, low: Decimal, high: Decimal) -> bool {
+        match (self.stop_price, self.side) {
+            (None, _) => true,
+            (Some(stop), Side::Buy) => high >= stop,
+            (Some(stop), Side::Sell) => low <= stop,
         }
     }
+
+    /// The most aggressive price the order will trade at: its discretionary
+    /// price if it has one, otherwise its limit price.
+    pub fn trading_limit(&self) -> Option<Decimal> {
+        self.discretion.or(self.price)
+    }
+
+    /// Whether an unfilled remainder rests on the book after matching, rather
+    /// than expiring.
+    pub fn rests(&self) -> bool {
+        self.order_type == OrderType::Limit && self.attributes.time_in_force() == TimeInForce::Day
+    }
+
     pub fn is_filled(&self) -> bool {
         self.remaining_quantity.is_zero()
     }
@@ -86,6 +425,23 @@ mod tests {
     use super::*;
     use rust_decimal_macros::dec;
 
+    #[test]
+    fn test_attributes_compose_and_round_trip() {
+        let attributes = OrderAttributes::default()
+            .post_only()
+            .hidden()
+            .with_time_in_force(TimeInForce::ImmediateOrCancel)
+            .with_self_trade_prevention(SelfTradePrevention::CancelOldest);
+        assert!(attributes.is_post_only() && attributes.is_hidden());
+        assert!(!attributes.is_reduce_only() && !attributes.is_all_or_none());
+        assert_eq!(attributes.to_string(), "POST_ONLY|HIDDEN|IOC|STP_CANCEL_OLDEST");
+        assert_eq!(attributes.to_string().parse::<OrderAttributes>(), Ok(attributes));
+        assert_eq!("aon | fok".parse::<OrderAttributes>(), Ok(OrderAttributes::default().all_or_none().with_time_in_force(TimeInForce::FillOrKill)));
+        assert_eq!("".parse::<OrderAttributes>(), Ok(OrderAttributes::default()));
+        assert!("GTX".parse::<OrderAttributes>().is_err());
+        assert_eq!(std::mem::size_of::<OrderAttributes>(), 3);
+    }
+
     #[test]
     fn test_limit_order_filling() {
         let mut order = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(29), dec!(1));
@@ -119,6 +475,23 @@ mod tests {
         assert!(order.is_filled());
     }
 
+    #[test]
+    fn test_peg_prices() {
+        let (bid, ask) = (Some(dec!(99)), Some(dec!(101)));
+        assert_eq!(Peg::new(PegReference::Primary).price(Side::Buy, bid, ask), Some(dec!(99)));
+        assert_eq!(Peg::new(PegReference::Primary).price(Side::Sell, bid, ask), Some(dec!(101)));
+        assert_eq!(Peg::new(PegReference::Midpoint).price(Side::Sell, bid, ask), Some(dec!(100)));
+        assert_eq!(Peg::new(PegReference::Market).price(Side::Buy, bid, ask), Some(dec!(101)));
+
+        let offset = Peg { offset: dec!(0.5), ..Peg::new(PegReference::Market) };
+        assert_eq!(offset.price(Side::Buy, bid, ask), Some(dec!(100.5)));
+        assert_eq!(offset.price(Side::Sell, bid, ask), Some(dec!(99.5)));
+        let capped = Peg { limit: Some(dec!(98.5)), ..Peg::new(PegReference::Primary) };
+        assert_eq!(capped.price(Side::Buy, bid, ask), Some(dec!(98.5)));
+        assert_eq!(capped.price(Side::Sell, bid, ask), Some(dec!(101)));
+        assert_eq!(Peg::new(PegReference::Midpoint).price(Side::Buy, bid, None), None);
+    }
+
     #[test]
     fn test_market_order_creation() {
         let order = Order::new_market(Uuid::new_v4(), "NVO".to_string(), Side::Sell, dec!(2));
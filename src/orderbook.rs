@@ -1,210 +1,1355 @@
-use crate::order::Order;
+use crate::accounting::QuantityTally;
+use crate::allocation::{allocator, Allocator};
+use crate::bands::{Band, BandReference};
+use crate::compact::{CompactOrder, OrderKey, OrderSlab};
+use crate::config::{BandAction, InstrumentConfig, Perpetual, ReferencePrice};
+use crate::engine::EngineContext;
+use crate::fixed::Fixed;
+use crate::order::{Order, Peg, TimeInForce};
+use crate::shadow;
+use crate::timer_wheel::TimerWheel;
 use crate::trade::Trade;
-use crate::utils::{MatchingEngineError, OrderBookDisplay, OrderStatus, OrderType, PriceLevel, Side};
-use rust_decimal::Decimal;
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use crate::utils::{MatchingEngineError, OrderBookDisplay, OrderStatus, PriceLevel, Side};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Bound;
 use uuid::Uuid;
+
+/// Book operations between sweeps that drop emptied price levels.
+const COMPACTION_INTERVAL: u64 = 1024;
+/// Emptied levels kept before a sweep runs early. They sit where the book
+/// just traded, so scans from the touch pass over them.
+const MAX_EMPTY_LEVELS: usize = 32;
+
 pub struct OrderBook {
     instrument: String,
-    bids: BTreeMap<Decimal, VecDeque<Uuid>>,
-    asks: BTreeMap<Decimal, VecDeque<Uuid>>,
-    orders: HashMap<Uuid, Order>,
+    instrument_id: u32,
+    config: InstrumentConfig,
+    /// Bids and asks, indexed by `Side`.
+    sides: [BookSide; 2],
+    orders: OrderSlab,
+    tally: QuantityTally,
+    last_trade_price: Option<Fixed>,
+    band: Option<BandReference>,
+    /// The band as of the order being matched, which no trade may pass.
+    band_in_force: Option<Band>,
+    allocator: Box<dyn Allocator>,
+    /// Resting pegged orders, oldest first.
+    pegged: Vec<OrderKey>,
+    /// Resting orders with a discretionary price, oldest first.
+    discretionary: Vec<OrderKey>,
+    /// Good-till-date orders by expiry time. Entries for orders that have
+    /// since left the book no longer resolve and are skipped when they fire.
+    expiries: TimerWheel<OrderKey>,
+    /// Orders resting with a lifetime, by the time they are canceled.
+    /// Stale entries are skipped the same way.
+    cancellations: TimerWheel<OrderKey>,
+    /// Where levels emptied since the last compaction, oldest first. Some
+    /// may have taken orders again since.
+    emptied: Vec<(Side, Fixed)>,
+    operations: u64,
+    level_stats: LevelStats,
+    /// Trades made so far; the last trade's sequence number.
+    trade_sequence: u64,
+    /// The last arrival number given to an order.
+    arrival_sequence: u64,
+}
+
+/// How a book's price levels came and went. A level that empties stays in
+/// the book until the next periodic compaction, so a price that refills in
+/// the meantime reuses it instead of allocating a new one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelStats {
+    pub created: u64,
+    /// Emptied levels that took new orders before being compacted away.
+    pub reused: u64,
+    pub compactions: u64,
+    /// Empty levels dropped by compactions.
+    pub compacted: u64,
+}
+
+impl LevelStats {
+    pub fn add(&mut self, other: &LevelStats) {
+        self.created += other.created;
+        self.reused += other.reused;
+        self.compactions += other.compactions;
+        self.compacted += other.compacted;
+    }
+}
+
+/// The orders queued at one price, oldest first. An order leaving the book
+/// other than by a fill at the front stays queued as a tombstone: its key no
+/// longer resolves in the slab, so removal is O(1) and matching skips it.
+#[derive(Debug, Default)]
+struct Level {
+    queue: VecDeque<OrderKey>,
+    /// Keys in `queue` that still resolve.
+    live: usize,
+    /// Whether the level is listed for the next compaction.
+    emptied: bool,
+    /// When an order resting here last traded.
+    last_trade_at: Option<u64>,
+}
+
+impl Level {
+    fn push(&mut self, key: OrderKey) {
+        self.queue.push_back(key);
+        self.live += 1;
+    }
+
+    /// The keys of the level's orders, oldest first, skipping tombstones.
+    fn keys<'a>(&'a self, orders: &'a OrderSlab) -> impl Iterator<Item = OrderKey> + 'a {
+        self.queue.iter().copied().filter(|&key| orders.get(key).is_some())
+    }
+}
+
+/// One side of the book. Levels are keyed by rank, the price negated for
+/// bids, so the best level sorts first on both sides and matching walks
+/// either side with the same code.
+#[derive(Debug)]
+struct BookSide {
+    side: Side,
+    levels: BTreeMap<Fixed, Level>,
+    /// Levels holding live orders, kept up to date as levels fill and
+    /// empty so capacity checks need not walk the side.
+    live_levels: usize,
+}
+
+impl BookSide {
+    fn new(side: Side) -> Self {
+        BookSide { side, levels: BTreeMap::new(), live_levels: 0 }
+    }
+
+    /// The sort key of `price`, lowest for the best price. Its own inverse.
+    fn rank(&self, price: Fixed) -> Fixed {
+        match self.side {
+            Side::Buy => -price,
+            Side::Sell => price,
+        }
+    }
+
+    fn get(&self, price: Fixed) -> Option<&Level> {
+        self.levels.get(&self.rank(price))
+    }
+
+    fn get_mut(&mut self, price: Fixed) -> Option<&mut Level> {
+        let rank = self.rank(price);
+        self.levels.get_mut(&rank)
+    }
+
+    fn remove(&mut self, price: Fixed) -> Option<Level> {
+        let rank = self.rank(price);
+        self.levels.remove(&rank)
+    }
+
+    /// The level at `price`, created if missing, and whether it was created.
+    fn get_or_insert(&mut self, price: Fixed) -> (&mut Level, bool) {
+        match self.levels.entry(self.rank(price)) {
+            Entry::Vacant(entry) => (entry.insert(Level::default()), true),
+            Entry::Occupied(entry) => (entry.into_mut(), false),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Every level with its price, best first.
+    fn levels(&self) -> impl Iterator<Item = (Fixed, &Level)> {
+        self.through(None)
+    }
+
+    /// Levels priced at `limit` or better, best first; all of them without
+    /// a limit.
+    fn through(&self, limit: Option<Fixed>) -> impl Iterator<Item = (Fixed, &Level)> {
+        let end = limit.map_or(Bound::Unbounded, |limit| Bound::Included(self.rank(limit)));
+        self.levels.range((Bound::Unbounded, end)).map(|(&rank, level)| (self.rank(rank), level))
+    }
+
+    /// The best price with live orders, passing over emptied levels awaiting
+    /// compaction.
+    fn best(&self) -> Option<Fixed> {
+        if let Some((&rank, level)) = self.levels.first_key_value()
+            && level.live > 0 {
+            return Some(self.rank(rank));
+        }
+        self.levels().find(|(_, level)| level.live > 0).map(|(price, _)| price)
+    }
+}
+
+/// The outcome of a batch auction.
+#[derive(Debug, Default)]
+pub struct Uncross {
+    /// The single price every trade executed at, if anything crossed.
+    pub price: Option<Decimal>,
+    pub trades: Vec<Trade>,
+    /// Orders fully filled by the auction, resting and batched alike.
+    pub filled: Vec<Order>,
+    /// The batched orders' states after the auction, in arrival order.
+    pub batch: Vec<Order>,
+}
+
+/// An order taking part in a batch auction: resting in the book, or a
+/// batched market order, which never rests.
+#[derive(Debug, Clone, Copy)]
+enum Participant {
+    Resting(OrderKey),
+    Market(usize),
 }
 
 impl OrderBook {
     pub fn new(instrument: String) -> Self {
+        Self::with_config(instrument, InstrumentConfig::default())
+    }
+
+    pub fn with_config(instrument: String, config: InstrumentConfig) -> Self {
         OrderBook {
             instrument,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-            orders: HashMap::new(),
+            instrument_id: 0,
+            allocator: allocator(config.matching),
+            band: config.price_band.map(BandReference::new),
+            band_in_force: None,
+            pegged: Vec::new(),
+            discretionary: Vec::new(),
+            expiries: TimerWheel::new(),
+            cancellations: TimerWheel::new(),
+            emptied: Vec::new(),
+            operations: 0,
+            level_stats: LevelStats::default(),
+            config,
+            sides: [BookSide::new(Side::Buy), BookSide::new(Side::Sell)],
+            orders: OrderSlab::default(),
+            tally: QuantityTally::default(),
+            last_trade_price: None,
+            trade_sequence: 0,
+            arrival_sequence: 0,
+        }
+    }
+
+    /// Tags the book's resting orders with `id`, the engine's compact name
+    /// for the instrument.
+    pub fn with_instrument_id(mut self, id: u32) -> Self {
+        self.instrument_id = id;
+        self
+    }
+
+    /// Takes back the instrument id and trade count saved in a snapshot, so
+    /// the next trade is numbered `trade_sequence + 1`.
+    pub fn restore_numbering(&mut self, instrument_id: u32, trade_sequence: u64) {
+        self.instrument_id = instrument_id;
+        self.trade_sequence = trade_sequence;
+    }
+
+    pub fn instrument(&self) -> &str {
+        &self.instrument
+    }
+
+    pub fn instrument_id(&self) -> u32 {
+        self.instrument_id
+    }
+
+    pub fn config(&self) -> &InstrumentConfig {
+        &self.config
+    }
+
+    /// Changes the tick and lot size checked on new orders. Orders already
+    /// resting keep their price and quantity.
+    pub fn set_order_sizes(&mut self, tick_size: Option<Decimal>, lot_size: Option<Decimal>) -> Result<(), String> {
+        let config = InstrumentConfig { tick_size, lot_size, ..self.config.clone() };
+        config.validate()?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Moves the index price of a perpetual book.
+    pub fn set_index_price(&mut self, index_price: Decimal) -> Result<(), String> {
+        let Some(perpetual) = self.config.perpetual else { return Err("not a perpetual".to_string()) };
+        let config = InstrumentConfig { perpetual: Some(Perpetual { index_price, ..perpetual }), ..self.config.clone() };
+        config.validate()?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Moves the reference prices the book takes from its index: the index
+    /// price of a perpetual and the centre of the price band.
+    pub fn follow_index(&mut self, price: Decimal) {
+        if let Some(perpetual) = &mut self.config.perpetual {
+            perpetual.index_price = price;
         }
+        if let Some(band) = &mut self.band {
+            band.anchor(price);
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.best(Side::Buy).map(Fixed::to_decimal)
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.best(Side::Sell).map(Fixed::to_decimal)
+    }
+
+    fn book_side(&self, side: Side) -> &BookSide {
+        &self.sides[side as usize]
+    }
+
+    fn best(&self, side: Side) -> Option<Fixed> {
+        self.book_side(side).best()
+    }
+
+    pub fn level_stats(&self) -> LevelStats {
+        self.level_stats
     }
 
-    pub fn add_order(&mut self, mut order: Order) -> (Vec<Trade>, Vec<Order>, Order) {
-        let (trades, filled_orders) = self.match_order(&mut order);
+    /// The quantity resting at the best price on `side`.
+    pub fn touch_volume(&self, side: Side) -> Option<Decimal> {
+        let level = self.book_side(side).get(self.best(side)?)?;
+        Some(self.level_volume(level))
+    }
 
-        if !order.is_filled() && order.order_type == OrderType::Limit {
-            let order_id = order.order_id;
-            if let Some(price) = order.price {
-                let book_side = match order.side {
-                    Side::Buy => &mut self.bids,
-                    Side::Sell => &mut self.asks,
-                };
-                book_side.entry(price).or_default().push_back(order_id);
-                
-                self.orders.insert(order_id, order.clone());
+    /// Price and resting quantity of up to `levels` levels on `side`, best
+    /// first.
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<(Decimal, Decimal)> {
+        self.iter_levels(side).take(levels).map(|(price, volume, _)| (price, volume)).collect()
+    }
+
+    /// The bid levels, best first, as price, resting quantity and number of
+    /// orders.
+    pub fn iter_bids(&self) -> impl Iterator<Item = (Decimal, Decimal, usize)> + '_ {
+        self.iter_levels(Side::Buy)
+    }
+
+    /// The ask levels, best first, as price, resting quantity and number of
+    /// orders.
+    pub fn iter_asks(&self) -> impl Iterator<Item = (Decimal, Decimal, usize)> + '_ {
+        self.iter_levels(Side::Sell)
+    }
+
+    /// The levels of `side` with orders resting, best first. Each level is
+    /// totalled only when the iterator reaches it, so taking the top few
+    /// costs no more than those few.
+    pub fn iter_levels(&self, side: Side) -> impl Iterator<Item = (Decimal, Decimal, usize)> + '_ {
+        self.book_side(side)
+            .levels()
+            .filter(|(_, level)| level.live > 0)
+            .map(|(price, level)| (price.to_decimal(), self.level_volume(level), level.live))
+    }
+
+    /// Like `iter_levels`, with the levels merged into buckets `group` wide
+    /// and the quantities and order counts in each summed. A bid bucket is
+    /// priced at its floor and an ask bucket at its ceiling, so no bucket
+    /// shows a better price than the orders in it.
+    pub fn iter_grouped(&self, side: Side, group: Decimal) -> impl Iterator<Item = (Decimal, Decimal, usize)> + '_ {
+        let levels = self.iter_levels(side).map(|(price, volume, orders)| (price, (volume, orders)));
+        group_levels(side, group, levels, |total, (volume, orders)| {
+            total.0 += volume;
+            total.1 += orders;
+        })
+        .map(|(price, (volume, orders))| (price, volume, orders))
+    }
+
+    /// Bid quantity minus ask quantity over their sum, across the top
+    /// `levels` levels of each side: 1 with only bids resting, -1 with only
+    /// asks. `None` if both sides are empty.
+    pub fn imbalance(&self, levels: usize) -> Option<Decimal> {
+        let volume = |side: Side| self.iter_levels(side).take(levels).map(|(_, volume, _)| volume).sum::<Decimal>();
+        let (bids, asks) = (volume(Side::Buy), volume(Side::Sell));
+        let total = bids + asks;
+        (!total.is_zero()).then(|| (bids - asks) / total)
+    }
+
+    /// How many price levels on `side` have orders resting.
+    pub fn level_count(&self, side: Side) -> usize {
+        self.book_side(side).live_levels
+    }
+
+    /// Whether an order resting at `price` on `side` would start a level
+    /// rather than join one.
+    pub fn opens_level(&self, side: Side, price: Decimal) -> bool {
+        Fixed::from_decimal(price)
+            .and_then(|price| self.book_side(side).get(price))
+            .is_none_or(|level| level.live == 0)
+    }
+
+    /// How many orders rest in the book.
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// How many trades the book has made, busted ones included.
+    pub fn trade_count(&self) -> u64 {
+        self.trade_sequence
+    }
+
+    pub fn mid_price(&self) -> Option<Decimal> {
+        Some((self.best_bid()? + self.best_ask()?) / Decimal::TWO)
+    }
+
+    pub fn last_trade_price(&self) -> Option<Decimal> {
+        self.last_trade_price.map(Fixed::to_decimal)
+    }
+
+    /// Resets the last trade price after the last trade was busted.
+    pub fn restate_last_trade(&mut self, price: Option<Decimal>) {
+        self.last_trade_price = price.and_then(Fixed::from_decimal);
+    }
+
+    /// The price the instrument's collar is measured against, if any.
+    pub fn reference_price(&self, reference: ReferencePrice) -> Option<Decimal> {
+        match reference {
+            ReferencePrice::LastTrade => self.last_trade_price().or_else(|| self.mid_price()),
+            ReferencePrice::Mid => self.mid_price(),
+        }
+    }
+
+    /// The best bid and ask ignoring levels that hold only pegged orders, so
+    /// pegs never follow each other.
+    fn peg_reference(&self) -> (Option<Decimal>, Option<Decimal>) {
+        if self.pegged.is_empty() {
+            return (self.best_bid(), self.best_ask());
+        }
+        let unpegged = |level: &Level| level.keys(&self.orders).any(|key| !self.pegged.contains(&key));
+        let reference = |side: Side| self.book_side(side).levels().find(|(_, level)| unpegged(level)).map(|(price, _)| price.to_decimal());
+        (reference(Side::Buy), reference(Side::Sell))
+    }
+
+    /// The current price of a pegged order, rounded to the tick size away
+    /// from the opposite side. `None` if the order is not pegged or its
+    /// reference price is not available.
+    pub fn peg_price(&self, order: &Order) -> Option<Decimal> {
+        self.pegged_price(order.side, order.peg?)
+    }
+
+    fn pegged_price(&self, side: Side, peg: Peg) -> Option<Decimal> {
+        let (bid, ask) = self.peg_reference();
+        let price = peg.price(side, bid, ask)?;
+        Some(match (self.config.tick_size, side) {
+            (Some(tick), Side::Buy) => (price / tick).floor() * tick,
+            (Some(tick), Side::Sell) => (price / tick).ceil() * tick,
+            // A midpoint can have more decimal places than the book keeps.
+            (None, Side::Buy) => price.round_dp_with_strategy(Fixed::SCALE, RoundingStrategy::ToNegativeInfinity),
+            (None, Side::Sell) => price.round_dp_with_strategy(Fixed::SCALE, RoundingStrategy::ToPositiveInfinity),
+        })
+    }
+
+    /// Moves resting pegged orders whose reference has changed to their new
+    /// price, at the back of the new level's queue. A peg is never re-priced
+    /// into a price that would lock or cross the book; it waits for the next
+    /// move instead. Returns each re-priced order with its previous price.
+    pub fn reprice_pegs(&mut self) -> Vec<(Order, Decimal)> {
+        let mut repriced = Vec::new();
+        for key in self.pegged.clone() {
+            let (Some(record), Some(meta)) = (self.orders.get(key), self.orders.meta(key)) else { continue };
+            let (side, old_price) = (record.side, record.price);
+            let Some(new_price) = meta.peg.and_then(|peg| self.pegged_price(side, peg)).and_then(Fixed::from_decimal) else {
+                continue;
+            };
+            let opposite = side.opposite();
+            let crosses = self.best(opposite).is_some_and(|best| opposite.at_or_better(best, new_price));
+            if new_price == old_price || crosses {
+                continue;
             }
+
+            // The order moves to a fresh key, leaving a tombstone at its old level.
+            let mut order = self.orders.remove(key, &self.instrument).expect("pegged orders are in the book");
+            self.unlink(side, old_price);
+            order.price = Some(new_price.to_decimal());
+            self.sequence_arrival(&mut order);
+            let new_key = self.orders.insert(&order, new_price, self.instrument_id);
+            self.enqueue(side, new_price, new_key);
+            if let Some(expire_at) = order.expire_at {
+                self.expiries.insert(expire_at, new_key);
+            }
+            if let Some(cancel_at) = order.cancel_at() {
+                self.cancellations.insert(cancel_at, new_key);
+            }
+            for tracked in [&mut self.pegged, &mut self.discretionary] {
+                if let Some(slot) = tracked.iter_mut().find(|tracked| **tracked == key) {
+                    *slot = new_key;
+                }
+            }
+            repriced.push((order, old_price.to_decimal()));
         }
-        
+        repriced
+    }
+
+    /// The price band in force at `now`, if the instrument has one and has
+    /// traded.
+    pub fn price_band(&self, now: u64) -> Option<Band> {
+        self.band.as_ref()?.band(now, self.config.tick_size)
+    }
+
+    /// Rejects a limit order priced through the band at `now` when the band
+    /// rejects rather than slices.
+    pub fn check_price_band(&self, order: &Order, now: u64) -> Result<(), MatchingEngineError> {
+        let (Some(band), Some(price)) = (self.price_band(now), order.price) else {
+            return Ok(());
+        };
+        if band.action == BandAction::Reject && band.beyond(order.side, price) {
+            return Err(MatchingEngineError::PriceOutsideBand { price, lower: band.lower, upper: band.upper });
+        }
+        Ok(())
+    }
+
+    /// The band edge a slicing band reprices `order` to, or `None` if the
+    /// order is within the band or the band rejects.
+    pub fn banded_price(&self, order: &Order, now: u64) -> Option<Decimal> {
+        let band = self.price_band(now)?;
+        (band.action == BandAction::Slice && band.beyond(order.side, order.price?)).then(|| band.edge(order.side))
+    }
+
+    /// Widens the band while trading resumes after a halt at `now`.
+    pub fn widen_price_band(&mut self, now: u64) {
+        if let Some(band) = &mut self.band {
+            band.widen(now);
+        }
+    }
+
+    /// Whether `order` would trade against the book on arrival, i.e. take
+    /// liquidity rather than post it, using its discretion if it has any.
+    /// Market orders always count.
+    pub fn is_marketable(&self, order: &Order) -> bool {
+        let Some(limit) = order.trading_limit() else { return true };
+        let opposite = order.side.opposite();
+        self.best(opposite).is_some_and(|best| opposite.at_or_better(best.to_decimal(), limit))
+    }
+
+    /// Checks a limit order's price against the configured collar. Market
+    /// orders and books without a reference price always pass.
+    pub fn check_price_collar(&self, order: &Order) -> Result<(), MatchingEngineError> {
+        let (Some(collar), Some(price)) = (self.config.price_collar, order.price) else {
+            return Ok(());
+        };
+        match self.reference_price(collar.reference) {
+            Some(reference) => collar.check(price, reference),
+            None => Ok(()),
+        }
+    }
+
+    pub fn add_order(&mut self, order: Order) -> (Vec<Trade>, Vec<Order>, Order) {
+        self.add_order_with(order, &mut EngineContext::default())
+    }
+
+    pub fn add_order_with(&mut self, mut order: Order, ctx: &mut EngineContext) -> (Vec<Trade>, Vec<Order>, Order) {
+        phase_begin!();
+        self.sequence_arrival(&mut order);
+        self.tally.submitted += order.quantity;
+        self.band_in_force = self.price_band(ctx.clock.now_nanos());
+        let killed = order.attributes.time_in_force() == TimeInForce::FillOrKill
+            && self.fillable_quantity(&order) < order.remaining_quantity;
+        let (trades, filled_orders) = if killed {
+            phase_lap!(PriceDiscovery);
+            (Vec::new(), Vec::new())
+        } else {
+            self.match_order(&mut order, ctx)
+        };
+        for trade in &trades {
+            self.tally.filled += trade.quantity * Decimal::TWO;
+        }
+        if let Some(trade) = trades.last() {
+            self.last_trade_price = Fixed::from_decimal(trade.price);
+        }
+        if let Some(band) = &mut self.band {
+            band.record(&trades);
+        }
+        if !order.rests() {
+            self.tally.expired += order.remaining_quantity;
+        }
+
+        if !order.is_filled() && order.rests() {
+            order.start_cancel_timer(ctx.clock.now_nanos());
+            self.rest(&order);
+        }
+        self.count_operation();
+        phase_lap!(Bookkeeping);
+        phase_end!();
+        #[cfg(debug_assertions)]
+        if ctx.check_crossed {
+            self.assert_uncrossed(&order);
+        }
+
         (trades, filled_orders, order)
     }
 
-    pub fn cancel_order(&mut self, order_id: &Uuid) -> Result<Order, MatchingEngineError> {
-        if let Some(mut order_to_cancel) = self.orders.remove(order_id) {
-            let book = match order_to_cancel.side {
-                Side::Buy => &mut self.bids,
-                Side::Sell => &mut self.asks,
-            };
+    /// Panics with the order just added and the top of the book if the book
+    /// is locked or crossed.
+    #[cfg(debug_assertions)]
+    fn assert_uncrossed(&self, order: &Order) {
+        let (Some(bid), Some(ask)) = (self.best(Side::Buy), self.best(Side::Sell)) else { return };
+        if bid < ask {
+            return;
+        }
+        let display = self.display();
+        let top = |levels: &[PriceLevel]| levels.iter().take(5).map(|level| format!("{} x {}", level.price, level.volume)).collect::<Vec<_>>().join(", ");
+        panic!(
+            "{} book is {} at bid {} ask {} after adding {:?}\n  bids: {}\n  asks: {}",
+            self.instrument,
+            if bid == ask { "locked" } else { "crossed" },
+            bid,
+            ask,
+            order,
+            top(&display.bids),
+            top(&display.asks),
+        );
+    }
+
+    /// Gives `order` the next arrival number, its place in time priority.
+    fn sequence_arrival(&mut self, order: &mut Order) {
+        self.arrival_sequence += 1;
+        order.sequence = self.arrival_sequence;
+    }
+
+    /// Puts a priced order in its level's queue, behind every order that
+    /// arrived before it.
+    fn rest(&mut self, order: &Order) -> Option<OrderKey> {
+        let price = Fixed::from_validated(order.price?);
+        let key = self.orders.insert(order, price, self.instrument_id);
+        self.enqueue(order.side, price, key);
+        self.track(key, order);
+        Some(key)
+    }
+
+    /// Queues `key` at `price` in arrival order, reusing the level if it
+    /// emptied since the last compaction. Orders arrive in sequence, so this
+    /// is a push to the back except when restoring.
+    fn enqueue(&mut self, side: Side, price: Fixed, key: OrderKey) {
+        let book_side = &mut self.sides[side as usize];
+        let (level, created) = book_side.get_or_insert(price);
+        if created {
+            self.level_stats.created += 1;
+        } else if level.live == 0 {
+            self.level_stats.reused += 1;
+        }
+        let opened = level.live == 0;
+        let orders = &self.orders;
+        let arrival = orders.get(key).map_or(u64::MAX, |record| record.sequence);
+        if level.queue.iter().rev().find_map(|&queued| orders.get(queued)).is_some_and(|last| last.sequence > arrival) {
+            let position = level
+                .queue
+                .iter()
+                .rposition(|&queued| orders.get(queued).is_some_and(|record| record.sequence < arrival))
+                .map_or(0, |position| position + 1);
+            level.queue.insert(position, key);
+            level.live += 1;
+        } else {
+            level.push(key);
+        }
+        if opened {
+            self.sides[side as usize].live_levels += 1;
+        }
+    }
+
+    /// Runs a batch auction: adds `batch` to the book without matching, then
+    /// executes everything that crosses at the single price that maximizes
+    /// the executed quantity. Ties go to the smallest imbalance, then to the
+    /// price closest to the last trade, then to the lower price. Market
+    /// orders take priority over all limit orders and expire unfilled
+    /// remainders; limit orders keep price-time priority and rest if unfilled.
+    pub fn uncross(&mut self, batch: Vec<Order>, ctx: &mut EngineContext) -> Uncross {
+        let mut markets = Vec::new();
+        let mut arrivals = Vec::new();
+        for mut order in batch {
+            self.tally.submitted += order.quantity;
+            self.sequence_arrival(&mut order);
+            match self.rest(&order) {
+                Some(key) => arrivals.push(Participant::Resting(key)),
+                None => {
+                    arrivals.push(Participant::Market(markets.len()));
+                    markets.push(order);
+                }
+            }
+        }
+        let market_side = |side: Side| -> Vec<Participant> {
+            (0..markets.len()).filter(|&index| markets[index].side == side).map(Participant::Market).collect()
+        };
+        let (market_buys, market_sells) = (market_side(Side::Buy), market_side(Side::Sell));
 
-            if let Some(price) = order_to_cancel.price {
-                if let Some(queue) = book.get_mut(&price) {
-                    queue.retain(|id| id != order_id);
-                    if queue.is_empty() {
-                        book.remove(&price);
+        let mut uncross = Uncross::default();
+        let mut filled = Vec::new();
+        if let Some((price, taker_side)) = self.clearing_price(&markets) {
+            let buyers = self.auction_queue(&market_buys, Side::Buy, price);
+            let sellers = self.auction_queue(&market_sells, Side::Sell, price);
+            let (mut buyers, mut sellers) = (buyers.into_iter().peekable(), sellers.into_iter().peekable());
+
+            while let (Some(&buyer), Some(&seller)) = (buyers.peek(), sellers.peek()) {
+                let quantity = self.remaining(buyer, &markets).min(self.remaining(seller, &markets));
+                let timestamp = ctx.clock.now_nanos();
+                for (participant, queue) in [(buyer, &mut buyers), (seller, &mut sellers)] {
+                    let done = match participant {
+                        Participant::Resting(key) => {
+                            let record = self.orders.get_mut(key).expect("auction participants are in the book");
+                            record.fill(quantity);
+                            let (side, price, filled) = (record.side, record.price, record.is_filled());
+                            self.record_level_trade(side, price, timestamp);
+                            filled
+                        }
+                        Participant::Market(index) => {
+                            markets[index].fill(quantity.to_decimal());
+                            markets[index].is_filled()
+                        }
+                    };
+                    if done {
+                        filled.push(participant);
+                        queue.next();
                     }
                 }
+                let quantity = quantity.to_decimal();
+                self.tally.filled += quantity * Decimal::TWO;
+                self.trade_sequence += 1;
+                uncross.trades.push(Trade {
+                    trade_id: ctx.ids.trade_id(self.instrument_id, self.trade_sequence),
+                    sequence: self.trade_sequence,
+                    instrument: self.instrument.clone(),
+                    price: price.to_decimal(),
+                    quantity,
+                    timestamp,
+                    buy_order_id: self.participant_id(buyer, &markets),
+                    sell_order_id: self.participant_id(seller, &markets),
+                    taker_side,
+                });
+            }
+            if !uncross.trades.is_empty() {
+                uncross.price = Some(price.to_decimal());
+                self.last_trade_price = Some(price);
+            }
+            if let Some(band) = &mut self.band {
+                band.record(&uncross.trades);
+            }
+        }
+
+        uncross.batch = arrivals
+            .iter()
+            .map(|participant| match *participant {
+                Participant::Resting(key) => self.orders.order(key, &self.instrument).expect("batched orders are in the book"),
+                Participant::Market(index) => markets[index].clone(),
+            })
+            .collect();
+        for participant in filled {
+            uncross.filled.push(match participant {
+                Participant::Resting(key) => self.remove_resting(key),
+                Participant::Market(index) => markets[index].clone(),
+            });
+        }
+        for order in &markets {
+            self.tally.expired += order.remaining_quantity;
+        }
+        self.count_operation();
+        for participant in arrivals {
+            if let Participant::Resting(key) = participant
+                && self.orders.get(key).is_some_and(|record| record.attributes.time_in_force() != TimeInForce::Day) {
+                let order = self.remove_resting(key);
+                self.tally.expired += order.remaining_quantity;
+            }
+        }
+        uncross
+    }
+
+    fn remaining(&self, participant: Participant, markets: &[Order]) -> Fixed {
+        match participant {
+            Participant::Resting(key) => self.orders.get(key).expect("auction participants are in the book").remaining,
+            Participant::Market(index) => Fixed::from_validated(markets[index].remaining_quantity),
+        }
+    }
+
+    fn participant_id(&self, participant: Participant, markets: &[Order]) -> Uuid {
+        match participant {
+            Participant::Resting(key) => self.orders.meta(key).expect("auction participants are in the book").order_id,
+            Participant::Market(index) => markets[index].order_id,
+        }
+    }
+
+    fn record_level_trade(&mut self, side: Side, price: Fixed, timestamp: u64) {
+        if let Some(level) = self.sides[side as usize].get_mut(price) {
+            level.last_trade_at = Some(timestamp);
+        }
+    }
+
+    fn level_volume(&self, level: &Level) -> Decimal {
+        Fixed::total(level.queue.iter().filter_map(|key| self.orders.get(*key)).map(|record| record.remaining))
+    }
+
+    /// The auction price and the side with more interest at it, which is
+    /// recorded as the taker side of auction trades.
+    fn clearing_price(&self, markets: &[Order]) -> Option<(Fixed, Side)> {
+        let market_volume = |side: Side| markets.iter().filter(|order| order.side == side).map(|order| order.remaining_quantity).sum::<Decimal>();
+        let (market_demand, market_supply) = (market_volume(Side::Buy), market_volume(Side::Sell));
+        let volume_at = |side: Side, price: Fixed| {
+            self.book_side(side).through(Some(price)).map(|(_, level)| self.level_volume(level)).sum::<Decimal>()
+        };
+        let demand_at = |price: Fixed| market_demand + volume_at(Side::Buy, price);
+        let supply_at = |price: Fixed| market_supply + volume_at(Side::Sell, price);
+
+        let candidates = self.sides
+            .iter()
+            .flat_map(BookSide::levels)
+            .filter(|(_, level)| level.live > 0)
+            .map(|(price, _)| price)
+            .chain(self.last_trade_price);
+        let mut best: Option<(Fixed, Decimal, Decimal, Fixed)> = None;
+        for price in candidates {
+            let (demand, supply) = (demand_at(price), supply_at(price));
+            let executed = demand.min(supply);
+            if executed.is_zero() {
+                continue;
+            }
+            let imbalance = (demand - supply).abs();
+            let distance = self.last_trade_price.map_or(Fixed::ZERO, |last| (price - last).abs());
+            let better = match best {
+                None => true,
+                Some((best_price, best_executed, best_imbalance, best_distance)) => (executed, -imbalance, -distance, -price)
+                    > (best_executed, -best_imbalance, -best_distance, -best_price),
+            };
+            if better {
+                best = Some((price, executed, imbalance, distance));
+            }
+        }
+
+        let (price, ..) = best?;
+        Some((price, if demand_at(price) >= supply_at(price) { Side::Buy } else { Side::Sell }))
+    }
+
+    /// One side's auction participants at `price` in priority order.
+    fn auction_queue(&self, market: &[Participant], side: Side, price: Fixed) -> Vec<Participant> {
+        let resting = self.book_side(side).through(Some(price)).flat_map(|(_, level)| level.keys(&self.orders)).map(Participant::Resting);
+        market.iter().copied().chain(resting).collect()
+    }
+
+    /// Takes a limit order out of the book, leaving a tombstone in its level.
+    fn remove_resting(&mut self, key: OrderKey) -> Order {
+        self.forget(key);
+        let (side, price) = self.orders.get(key).map(|record| (record.side, record.price)).expect("order is in the book");
+        let order = self.orders.remove(key, &self.instrument).expect("order is in the book");
+        self.unlink(side, price);
+        order
+    }
+
+    /// Accounts for an order removed from the slab but still queued at
+    /// `price`. Empties the level once nothing live is left, and compacts it
+    /// once tombstones outnumber live orders, which keeps removal amortized
+    /// O(1).
+    fn unlink(&mut self, side: Side, price: Fixed) {
+        let book_side = &mut self.sides[side as usize];
+        let Some(level) = book_side.get_mut(price) else { return };
+        level.live -= 1;
+        if level.live == 0 {
+            level.queue.clear();
+            if !std::mem::replace(&mut level.emptied, true) {
+                self.emptied.push((side, price));
+            }
+            book_side.live_levels -= 1;
+        } else if level.queue.len() > 2 * level.live {
+            let orders = &self.orders;
+            level.queue.retain(|&key| orders.get(key).is_some());
+        }
+    }
+
+    /// Registers a resting order that needs attention beyond its level.
+    fn track(&mut self, key: OrderKey, order: &Order) {
+        if order.peg.is_some() {
+            self.pegged.push(key);
+        }
+        if order.discretion.is_some() {
+            self.discretionary.push(key);
+        }
+        if let Some(expire_at) = order.expire_at {
+            self.expiries.insert(expire_at, key);
+        }
+        if let Some(cancel_at) = order.cancel_at() {
+            self.cancellations.insert(cancel_at, key);
+        }
+    }
+
+    fn forget(&mut self, key: OrderKey) {
+        if !self.pegged.is_empty() {
+            self.pegged.retain(|tracked| *tracked != key);
+        }
+        if !self.discretionary.is_empty() {
+            self.discretionary.retain(|tracked| *tracked != key);
+        }
+    }
+
+    /// Checks the book's internal invariants and describes the first one
+    /// broken. The `fast-path` feature relies on them for its unchecked
+    /// slab accesses, and debug builds with it audit after every matched
+    /// level.
+    pub fn audit(&self) -> Result<(), String> {
+        let mut queued = 0;
+        for book_side in &self.sides {
+            let live_levels = book_side.levels().filter(|(_, level)| level.live > 0).count();
+            if live_levels != book_side.live_levels {
+                return Err(format!("{} side counts {} live levels but holds {}", book_side.side, book_side.live_levels, live_levels));
+            }
+            for (price, level) in book_side.levels() {
+                let live: Vec<&CompactOrder> = level.queue.iter().filter_map(|&key| self.orders.get(key)).collect();
+                if live.len() != level.live {
+                    return Err(format!("{} level {} counts {} live orders but queues {}", book_side.side, price, level.live, live.len()));
+                }
+                if level.live == 0 && !level.queue.is_empty() {
+                    return Err(format!("{} level {} is empty but still queues tombstones", book_side.side, price));
+                }
+                if let Some(record) = live.iter().find(|record| record.side != book_side.side || record.price != price) {
+                    return Err(format!("{} order at {} is queued at {} level {}", record.side, record.price, book_side.side, price));
+                }
+                if let Some(record) = live.iter().find(|record| record.remaining <= Fixed::ZERO || record.remaining > record.quantity) {
+                    return Err(format!("order at {} rests with {} of {} remaining", price, record.remaining, record.quantity));
+                }
+                queued += live.len();
             }
-            
-            order_to_cancel.status = OrderStatus::Canceled;
-            Ok(order_to_cancel)
+        }
+        if queued != self.orders.len() {
+            return Err(format!("{} orders are queued but {} are stored", queued, self.orders.len()));
+        }
+        if let (Some(bid), Some(ask)) = (self.best(Side::Buy), self.best(Side::Sell))
+            && bid >= ask {
+            return Err(format!("book is crossed: bid {} ask {}", bid, ask));
+        }
+        if self.pegged.iter().chain(&self.discretionary).any(|&key| self.orders.get(key).is_none()) {
+            return Err("a tracked pegged or discretionary order has left the book".to_string());
+        }
+        Ok(())
+    }
+
+    /// Rests `order` without matching, as loaded from a snapshot. The order
+    /// must meet the instrument's tick and lot sizes and must not cross the
+    /// book, since a restored book is never uncrossed. It keeps its arrival
+    /// number, and its queue place with it; an order without one queues last.
+    pub fn restore_order(&mut self, mut order: Order) -> Result<(), MatchingEngineError> {
+        let Some(price) = order.price else {
+            return Err(MatchingEngineError::InvalidOrderPrice);
+        };
+        for value in [price, order.quantity, order.remaining_quantity] {
+            if Fixed::from_decimal(value).is_none() {
+                return Err(MatchingEngineError::ExcessPrecision(value));
+            }
+        }
+        self.config.check_order(&order)?;
+        if self.best(order.side.opposite()).is_some_and(|best| order.side.at_or_better(Fixed::from_validated(price), best)) {
+            return Err(MatchingEngineError::RestoreWouldCross { order_id: order.order_id, price });
+        }
+        self.tally.opening += order.remaining_quantity;
+        if order.sequence == 0 {
+            self.sequence_arrival(&mut order);
         } else {
-            Err(MatchingEngineError::OrderNotFound(*order_id))
+            self.arrival_sequence = self.arrival_sequence.max(order.sequence);
+        }
+        self.rest(&order);
+        Ok(())
+    }
+
+    /// The running quantity tally with `resting` filled in from the live book.
+    pub fn tally(&self) -> QuantityTally {
+        QuantityTally {
+            resting: Fixed::total(self.orders.records().map(|record| record.remaining)),
+            ..self.tally.clone()
         }
     }
 
-    fn match_order(&mut self, incoming: &mut Order) -> (Vec<Trade>, Vec<Order>) {
+    pub fn contains(&self, order_id: &Uuid) -> bool {
+        self.orders.contains(order_id)
+    }
+
+    pub fn order(&self, order_id: &Uuid) -> Option<Order> {
+        self.orders.order(self.orders.key(order_id)?, &self.instrument)
+    }
+
+    /// The resting orders hashed by [`shadow::checksum`], for comparing
+    /// against another book implementation.
+    pub fn checksum(&self) -> u64 {
+        shadow::checksum([Side::Buy, Side::Sell].into_iter().flat_map(|side| {
+            self.book_side(side).levels().flat_map(move |(price, level)| {
+                level.keys(&self.orders).filter_map(move |key| {
+                    let (record, meta) = (self.orders.get(key)?, self.orders.meta(key)?);
+                    Some((side, price, meta.order_id, record.remaining))
+                })
+            })
+        }))
+    }
+
+    pub fn resting_orders(&self) -> Vec<Order> {
+        self.sides
+            .iter()
+            .flat_map(BookSide::levels)
+            .flat_map(|(_, level)| level.keys(&self.orders))
+            .filter_map(|key| self.orders.order(key, &self.instrument))
+            .collect()
+    }
+
+    pub fn cancel_order(&mut self, order_id: &Uuid) -> Result<Order, MatchingEngineError> {
+        let key = self.orders.key(order_id).ok_or(MatchingEngineError::OrderNotFound(*order_id))?;
+        let mut order_to_cancel = self.remove_resting(key);
+        self.tally.canceled += order_to_cancel.remaining_quantity;
+        order_to_cancel.status = OrderStatus::Canceled;
+        self.count_operation();
+        Ok(order_to_cancel)
+    }
+
+    /// Removes every good-till-date order whose expiry time is at or before
+    /// `now` and returns them, oldest expiry first.
+    pub fn expire_orders(&mut self, now: u64) -> Vec<Order> {
+        let mut due = Vec::new();
+        self.expiries.advance(now, &mut due);
+        let mut expired = Vec::new();
+        for key in due {
+            if self.orders.meta(key).is_some_and(|meta| meta.expire_at.is_some_and(|expire_at| expire_at <= now)) {
+                let order = self.remove_resting(key);
+                self.tally.expired += order.remaining_quantity;
+                expired.push(order);
+            }
+        }
+        if !expired.is_empty() {
+            self.count_operation();
+        }
+        expired
+    }
+
+    /// Cancels every order whose lifetime ran out at or before `now` and
+    /// returns them, earliest first.
+    pub fn cancel_timed_out(&mut self, now: u64) -> Vec<Order> {
+        let mut due = Vec::new();
+        self.cancellations.advance(now, &mut due);
+        let mut canceled = Vec::new();
+        for key in due {
+            if self.orders.meta(key).is_some_and(|meta| meta.cancel_at.is_some_and(|cancel_at| cancel_at <= now)) {
+                let mut order = self.remove_resting(key);
+                self.tally.canceled += order.remaining_quantity;
+                order.status = OrderStatus::Canceled;
+                canceled.push(order);
+            }
+        }
+        if !canceled.is_empty() {
+            self.count_operation();
+        }
+        canceled
+    }
+
+    /// Removes every resting order, as when the instrument expires, and
+    /// returns them.
+    pub fn expire_all(&mut self) -> Vec<Order> {
+        let keys: Vec<OrderKey> = self.sides.iter().flat_map(BookSide::levels).flat_map(|(_, level)| level.keys(&self.orders)).collect();
+        let expired: Vec<Order> = keys
+            .into_iter()
+            .map(|key| {
+                let order = self.remove_resting(key);
+                self.tally.expired += order.remaining_quantity;
+                order
+            })
+            .collect();
+        if !expired.is_empty() {
+            self.count_operation();
+        }
+        expired
+    }
+
+    /// Counts a book operation, sweeping emptied levels every
+    /// `COMPACTION_INTERVAL` operations or once more than
+    /// `MAX_EMPTY_LEVELS` have piled up.
+    fn count_operation(&mut self) {
+        self.operations += 1;
+        let due = self.operations.is_multiple_of(COMPACTION_INTERVAL) && !self.emptied.is_empty();
+        if due || self.emptied.len() > MAX_EMPTY_LEVELS {
+            self.compact_levels();
+        }
+    }
+
+    /// Drops every price level that is still empty.
+    pub fn compact_levels(&mut self) {
+        let mut emptied = std::mem::take(&mut self.emptied);
+        for (side, price) in emptied.drain(..) {
+            let book_side = &mut self.sides[side as usize];
+            match book_side.get_mut(price) {
+                Some(level) if level.live == 0 => {
+                    book_side.remove(price);
+                    self.level_stats.compacted += 1;
+                }
+                Some(level) => level.emptied = false,
+                None => {}
+            }
+        }
+        self.emptied = emptied;
+        self.level_stats.compactions += 1;
+    }
+
+    fn match_order(&mut self, incoming: &mut Order, ctx: &mut EngineContext) -> (Vec<Trade>, Vec<Order>) {
         let mut trades = Vec::new();
         let mut filled_orders = Vec::new();
         let prices_to_process = self.get_matchable_prices(incoming);
+        phase_lap!(PriceDiscovery);
 
         for price in prices_to_process {
             if incoming.is_filled() {
                 break;
             }
-            let (mut trades_at_price, mut filled_at_price) = self.process_level(incoming, price);
+            let (mut trades_at_price, mut filled_at_price) = self.process_level(incoming, price, ctx);
             trades.append(&mut trades_at_price);
             filled_orders.append(&mut filled_at_price);
         }
+        if !incoming.is_filled() && !self.discretionary.is_empty() {
+            let (mut discretion_trades, mut discretion_filled) = self.match_discretion(incoming, ctx);
+            trades.append(&mut discretion_trades);
+            filled_orders.append(&mut discretion_filled);
+        }
+        phase_lap!(LevelProcessing);
 
         (trades, filled_orders)
     }
 
-    fn process_level(&mut self, incoming: &mut Order, price: Decimal) -> (Vec<Trade>, Vec<Order>) {
+    /// Once the displayed liquidity is exhausted, fills a limit order against
+    /// resting orders whose hidden discretion reaches its price. The trades
+    /// execute at the incoming order's price; the most aggressive discretion
+    /// goes first, then time priority.
+    fn match_discretion(&mut self, incoming: &mut Order, ctx: &mut EngineContext) -> (Vec<Trade>, Vec<Order>) {
         let mut trades = Vec::new();
         let mut filled_orders = Vec::new();
-        let opposite_book = match incoming.side {
-            Side::Buy => &mut self.asks,
-            Side::Sell => &mut self.bids,
-        };
+        let Some(price) = incoming.price else { return (trades, filled_orders) };
+        let mut incoming_remaining = Fixed::from_validated(incoming.remaining_quantity);
+
+        for key in self.discretion_candidates(incoming) {
+            if incoming.is_filled() {
+                break;
+            }
+            let resting_id = self.orders.meta(key).expect("discretionary orders are in the book").order_id;
+            let resting = self.orders.get_mut(key).expect("discretionary orders are in the book");
+            let (resting_side, resting_price) = (resting.side, resting.price);
+            let quantity = incoming_remaining.min(resting.remaining);
+            incoming_remaining -= quantity;
+            resting.fill(quantity);
+            let resting_filled = resting.is_filled();
+            let quantity = quantity.to_decimal();
+            incoming.fill(quantity);
+            let (buy_order_id, sell_order_id) = buyer_and_seller(incoming, resting_id);
+            let timestamp = ctx.clock.now_nanos();
+            self.record_level_trade(resting_side, resting_price, timestamp);
+            self.trade_sequence += 1;
+            trades.push(Trade {
+                trade_id: ctx.ids.trade_id(self.instrument_id, self.trade_sequence),
+                sequence: self.trade_sequence,
+                instrument: self.instrument.clone(),
+                price,
+                quantity,
+                timestamp,
+                buy_order_id,
+                sell_order_id,
+                taker_side: incoming.side,
+            });
+            if resting_filled {
+                filled_orders.push(self.remove_resting(key));
+            }
+        }
+        (trades, filled_orders)
+    }
+
+    /// Resting orders whose discretion reaches `incoming`'s limit price, in
+    /// the order `match_discretion` fills them.
+    fn discretion_candidates(&self, incoming: &Order) -> Vec<OrderKey> {
+        let Some(price) = incoming.price else { return Vec::new() };
+        let resting_side = incoming.side.opposite();
+        let mut candidates: Vec<(Decimal, OrderKey)> = self
+            .discretionary
+            .iter()
+            .filter(|&&key| self.orders.get(key).is_some_and(|record| record.side != incoming.side))
+            .filter_map(|&key| Some((self.orders.meta(key)?.discretion?, key)))
+            .filter(|(discretion, _)| resting_side.at_or_better(*discretion, price))
+            .collect();
+        candidates.sort_by(|(a, _), (b, _)| resting_side.cmp_priority(a, b));
+        candidates.into_iter().map(|(_, key)| key).collect()
+    }
 
-        while let Some(queue) = opposite_book.get_mut(&price) {
-            if incoming.is_filled() || queue.is_empty() {
+    /// How much of `incoming` would fill on arrival, counting displayed levels
+    /// it crosses and discretion that reaches it.
+    pub(crate) fn fillable_quantity(&self, incoming: &Order) -> Decimal {
+        let opposite_book = self.book_side(incoming.side.opposite());
+        let prices = self.get_matchable_prices(incoming);
+        let displayed: Decimal = prices.iter().filter_map(|&price| opposite_book.get(price)).map(|level| self.level_volume(level)).sum();
+        let discretionary = Fixed::total(
+            self.discretion_candidates(incoming)
+                .iter()
+                .filter_map(|&key| self.orders.get(key))
+                .filter(|record| !prices.contains(&record.price))
+                .map(|record| record.remaining),
+        );
+        displayed + discretionary
+    }
+
+    fn process_level(&mut self, incoming: &mut Order, price: Fixed, ctx: &mut EngineContext) -> (Vec<Trade>, Vec<Order>) {
+        let mut trades = Vec::new();
+        let mut filled_orders = Vec::new();
+        let trade_price = price.to_decimal();
+        let mut incoming_remaining = Fixed::from_validated(incoming.remaining_quantity);
+        let mut emptied = false;
+        let opposite_book = &mut self.sides[incoming.side.opposite() as usize];
+
+        while let Some(level) = opposite_book.get_mut(price) {
+            if incoming.is_filled() || level.live == 0 {
                 break;
             }
 
-            let resting_id = *queue.front().expect("Queue is not empty, so front must exist.");
-            
-            let resting = self.orders.get_mut(&resting_id).expect("Order must exist in master map.");
+            let orders = &self.orders;
+            let queue = &mut level.queue;
+            let position = self.allocator.pick(queue.len(), &|index| orders.get(queue[index]).map_or(Fixed::ZERO, |record| record.remaining));
+            let key = queue[position];
+            if orders.get(key).is_none() {
+                queue.remove(position);
+                continue;
+            }
 
-            let trade_qty = incoming.remaining_quantity.min(resting.remaining_quantity);
+            #[cfg(not(feature = "fast-path"))]
+            let (resting, meta) = self.orders.entry_mut(key).expect("Order must exist in master map.");
+            // SAFETY: `key` resolved just above, and the slab has not changed since.
+            #[cfg(feature = "fast-path")]
+            let (resting, meta) = unsafe { self.orders.entry_unchecked(key) };
+            let resting_id = meta.order_id;
 
-            incoming.fill(trade_qty);
+            let trade_qty = incoming_remaining.min(resting.remaining);
+            incoming_remaining -= trade_qty;
             resting.fill(trade_qty);
+            let resting_filled = resting.is_filled();
+            let trade_qty = trade_qty.to_decimal();
+            incoming.fill(trade_qty);
 
-            let (buy_order_id, sell_order_id) = if incoming.side == Side::Buy {
-                (incoming.order_id, resting.order_id)
-            } else {
-                (resting.order_id, incoming.order_id)
-            };
-            
-            trades.push(Trade::new(
-                self.instrument.clone(),
-                price,
-                trade_qty,
+            let (buy_order_id, sell_order_id) = buyer_and_seller(incoming, resting_id);
+            let timestamp = ctx.clock.now_nanos();
+            level.last_trade_at = Some(timestamp);
+
+            self.trade_sequence += 1;
+            trades.push(Trade {
+                trade_id: ctx.ids.trade_id(self.instrument_id, self.trade_sequence),
+                sequence: self.trade_sequence,
+                instrument: self.instrument.clone(),
+                price: trade_price,
+                quantity: trade_qty,
+                timestamp,
                 buy_order_id,
                 sell_order_id,
-                incoming.side,
-            ));
+                taker_side: incoming.side,
+            });
 
-            if resting.is_filled() {
-                queue.pop_front();
-                filled_orders.push(resting.clone());
-                self.orders.remove(&resting_id);
+            if resting_filled {
+                level.queue.remove(position);
+                level.live -= 1;
+                emptied = level.live == 0;
+                filled_orders.push(self.orders.remove(key, &self.instrument).expect("Order must exist in master map."));
+                if !self.pegged.is_empty() {
+                    self.pegged.retain(|tracked| *tracked != key);
+                }
+                if !self.discretionary.is_empty() {
+                    self.discretionary.retain(|tracked| *tracked != key);
+                }
             }
         }
 
-        if let Some(queue) = opposite_book.get(&price) {
-            if queue.is_empty() {
-                opposite_book.remove(&price);
+        if emptied {
+            opposite_book.live_levels -= 1;
+        }
+        if emptied
+            && let Some(level) = opposite_book.get_mut(price) {
+            level.queue.clear();
+            if !std::mem::replace(&mut level.emptied, true) {
+                self.emptied.push((opposite_book.side, price));
             }
         }
 
+        #[cfg(all(feature = "fast-path", debug_assertions))]
+        if let Err(problem) = self.audit() {
+            panic!("Order book integrity violated: {}", problem);
+        }
+
         (trades, filled_orders)
     }
 
-    fn get_matchable_prices(&self, incoming: &Order) -> Vec<Decimal> {
-        let mut prices = Vec::new();
-        match incoming.side {
-            Side::Buy => {
-                for (&price, queue) in self.asks.iter() {
-                    if queue.is_empty() { continue; }
-
-                    if let Some(limit_price) = incoming.price {
-                        if price <= limit_price {
-                            prices.push(price);
-                        } else {
-                            break;
-                        }
-                    } else {
-                        prices.push(price);
-                    }
-                }
-            }
-            Side::Sell => {
-                for (&price, queue) in self.bids.iter().rev() {
-                     if queue.is_empty() { continue; }
-
-                    if let Some(limit_price) = incoming.price {
-                        if price >= limit_price {
-                            prices.push(price);
-                        } else {
-                            break;
-                        }
-                    } else {
-                        prices.push(price);
-                    }
-                }
-            }
-        }
-        prices
+    /// Prices of the opposite side's live levels that `incoming` can trade
+    /// at, best first.
+    fn get_matchable_prices(&self, incoming: &Order) -> Vec<Fixed> {
+        let limit = match (incoming.trading_limit(), self.band_in_force) {
+            (Some(limit), Some(band)) if band.beyond(incoming.side, limit) => Some(band.edge(incoming.side)),
+            (None, Some(band)) => Some(band.edge(incoming.side)),
+            (limit, _) => limit,
+        };
+        let limit = limit.map(Fixed::from_validated);
+        self.book_side(incoming.side.opposite())
+            .through(limit)
+            .filter(|(_, level)| level.live > 0)
+            .map(|(price, _)| price)
+            .collect()
     }
-    
+
+    /// The book as printed, in buckets of the instrument's price grouping
+    /// if it has one.
     pub fn display(&self) -> OrderBookDisplay {
-        let bids = self.bids
-            .iter()
-            .rev()
-            .map(|(&price, queue)| {
-                let volume: Decimal = queue
-                    .iter()
-                    .filter_map(|id| self.orders.get(id))
-                    .map(|order| order.remaining_quantity)
-                    .sum();
-                PriceLevel { price, volume }
-            })
-            .filter(|level| !level.volume.is_zero())
-            .collect();
+        let precision = self.config.precision.unwrap_or_default();
+        let levels = |side: Side| {
+            let levels = self
+                .book_side(side)
+                .levels()
+                .map(|(price, level)| PriceLevel {
+                    price: price.to_decimal(),
+                    volume: self.level_volume(level),
+                    oldest_order_at: level.keys(&self.orders).next().and_then(|key| self.orders.get(key)).map(|record| record.timestamp),
+                    last_trade_at: level.last_trade_at,
+                })
+                .filter(|level| !level.volume.is_zero());
+            let levels: Vec<PriceLevel> = match self.config.price_grouping {
+                Some(group) => group_levels(side, group, levels.map(|level| (level.price, level)), |total, level| {
+                    total.volume += level.volume;
+                    total.oldest_order_at = total.oldest_order_at.into_iter().chain(level.oldest_order_at).min();
+                    total.last_trade_at = total.last_trade_at.max(level.last_trade_at);
+                })
+                .map(|(price, level)| PriceLevel { price, ..level })
+                .collect(),
+                None => levels.collect(),
+            };
+            levels
+                .into_iter()
+                .map(|level| PriceLevel { price: precision.scale_price(level.price), volume: precision.scale_quantity(level.volume), ..level })
+                .collect()
+        };
+        OrderBookDisplay { instrument: self.instrument.clone(), as_of: None, bids: levels(Side::Buy), asks: levels(Side::Sell) }
+    }
+}
 
-        let asks = self.asks
-            .iter()
-            .map(|(&price, queue)| {
-                let volume: Decimal = queue
-                    .iter()
-                    .filter_map(|id| self.orders.get(id))
-                    .map(|order| order.remaining_quantity)
-                    .sum();
-                PriceLevel { price, volume }
-            })
-            .filter(|level| !level.volume.is_zero())
-            .collect();
+/// Merges levels of `side`, best first, into buckets `group` wide, priced at
+/// the edge nearest the spread. Prices only move away from the spread, so
+/// the levels of a bucket are always next to each other.
+fn group_levels<T>(side: Side, group: Decimal, levels: impl Iterator<Item = (Decimal, T)>, merge: impl Fn(&mut T, T)) -> impl Iterator<Item = (Decimal, T)> {
+    let bucket = move |price: Decimal| {
+        let steps = price / group;
+        let steps = match side {
+            Side::Buy => steps.floor(),
+            Side::Sell => steps.ceil(),
+        };
+        steps * group
+    };
+    let mut levels = levels.map(move |(price, item)| (bucket(price), item)).peekable();
+    std::iter::from_fn(move || {
+        let (price, mut total) = levels.next()?;
+        while let Some((_, item)) = levels.next_if(|(next, _)| *next == price) {
+            merge(&mut total, item);
+        }
+        Some((price, total))
+    })
+}
 
-        OrderBookDisplay { bids, asks }
+/// The buy and sell order ids of a trade between `incoming` and a resting
+/// order.
+fn buyer_and_seller(incoming: &Order, resting_id: Uuid) -> (Uuid, Uuid) {
+    match incoming.side {
+        Side::Buy => (incoming.order_id, resting_id),
+        Side::Sell => (resting_id, incoming.order_id),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::{MatchingAlgorithm, PriceCollar};
+    use crate::order::{OrderAttributes, Peg, PegReference};
     use rust_decimal_macros::dec;
     use uuid::Uuid;
 
@@ -212,12 +1357,236 @@ mod tests {
         OrderBook::new("TEST-STOCK".to_string())
     }
 
+    impl OrderBook {
+        fn bids(&self) -> &BookSide {
+            self.book_side(Side::Buy)
+        }
+
+        fn asks(&self) -> &BookSide {
+            self.book_side(Side::Sell)
+        }
+    }
+
+    /// Price levels holding live orders, leaving out emptied levels that
+    /// await compaction.
+    fn live_levels(side: &BookSide) -> usize {
+        side.levels().filter(|(_, level)| level.live > 0).count()
+    }
+
+    /// The ids queued at a bid level, front first.
+    fn bid_queue(book: &OrderBook, price: Decimal) -> Vec<Uuid> {
+        book.bids().get(Fixed::from_validated(price)).unwrap().keys(&book.orders).map(|key| book.orders.meta(key).unwrap().order_id).collect()
+    }
+
+    #[test]
+    fn test_book_sides_walk_levels_best_first() {
+        let mut book = setup_book();
+        for (side, price) in [(Side::Buy, dec!(99)), (Side::Buy, dec!(98)), (Side::Buy, dec!(99.5)), (Side::Sell, dec!(101)), (Side::Sell, dec!(100.5))] {
+            book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), side, price, dec!(1)));
+        }
+        let prices = |levels: Vec<(Fixed, &Level)>| levels.into_iter().map(|(price, _)| price.to_decimal()).collect::<Vec<_>>();
+
+        assert_eq!(prices(book.bids().levels().collect()), vec![dec!(99.5), dec!(99), dec!(98)]);
+        assert_eq!(prices(book.asks().levels().collect()), vec![dec!(100.5), dec!(101)]);
+        assert_eq!(prices(book.bids().through(Some(Fixed::from_validated(dec!(99)))).collect()), vec![dec!(99.5), dec!(99)]);
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(dec!(99.5)), Some(dec!(100.5))));
+    }
+
+    #[test]
+    fn test_is_marketable() {
+        let mut book = setup_book();
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(1)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(99), dec!(1)));
+
+        assert!(book.is_marketable(&Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(101), dec!(1))));
+        assert!(!book.is_marketable(&Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(1))));
+        assert!(book.is_marketable(&Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(99), dec!(1))));
+        assert!(book.is_marketable(&Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(1))));
+    }
+
+    #[test]
+    fn test_uncross_executes_at_a_single_price() {
+        let mut book = setup_book();
+        let resting_ask = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100), dec!(4));
+        book.add_order(resting_ask);
+
+        let batch = vec![
+            Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(102), dec!(5)),
+            Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(3)),
+            Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(101), dec!(3)),
+            Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(2)),
+        ];
+        let uncross = book.uncross(batch, &mut EngineContext::default());
+
+        // At 101 demand is 8 and supply 9; no other price executes more.
+        assert_eq!(uncross.price, Some(dec!(101)));
+        assert!(uncross.trades.iter().all(|t| t.price == dec!(101) && t.taker_side == Side::Sell));
+        assert_eq!(uncross.trades.iter().map(|t| t.quantity).sum::<Decimal>(), dec!(8));
+        assert_eq!(uncross.batch.len(), 4);
+        assert!(uncross.batch[3].is_filled());
+        assert_eq!(book.best_bid(), None);
+        assert_eq!(book.best_ask(), Some(dec!(101)));
+        assert_eq!(book.last_trade_price(), Some(dec!(101)));
+    }
+
+    #[test]
+    fn test_uncross_without_a_cross_rests_the_batch() {
+        let mut book = setup_book();
+        let batch = vec![
+            Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(99), dec!(5)),
+            Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(3)),
+        ];
+        let uncross = book.uncross(batch, &mut EngineContext::default());
+        assert!(uncross.price.is_none());
+        assert!(uncross.trades.is_empty());
+        assert_eq!(book.resting_orders().len(), 2);
+
+        let unmatched_market = Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(4));
+        let uncross = book.uncross(vec![unmatched_market], &mut EngineContext::default());
+        assert_eq!(uncross.price, Some(dec!(101)));
+        assert_eq!(uncross.batch[0].remaining_quantity, dec!(1));
+        assert_eq!(book.best_ask(), None);
+        assert_eq!(book.tally().expired, dec!(1));
+    }
+
+    #[test]
+    fn test_lottery_matching_is_reproducible() {
+        let fills = |seed| {
+            let config = InstrumentConfig { matching: MatchingAlgorithm::Lottery { seed }, ..Default::default() };
+            let mut book = OrderBook::with_config("TEST-STOCK".to_string(), config);
+            let ids: Vec<Uuid> = (1..=5u128).map(Uuid::from_u128).collect();
+            for (id, size) in ids.iter().zip([dec!(10), dec!(20), dec!(30), dec!(40), dec!(50)]) {
+                book.add_order(Order::new_limit(*id, "TEST-STOCK".to_string(), Side::Sell, dec!(100), size));
+            }
+            let (trades, _, _) = book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(60)));
+            trades.iter().map(|t| (t.sell_order_id, t.quantity)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(fills(3), fills(3));
+        assert_eq!(fills(3).iter().map(|(_, quantity)| *quantity).sum::<Decimal>(), dec!(60));
+        assert!((0..20).any(|seed| fills(seed)[0].0 != Uuid::from_u128(1)));
+    }
+
+    #[test]
+    fn test_pegged_orders_follow_their_reference() {
+        let mut book = setup_book();
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(99), dec!(5)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(5)));
+
+        let mut pegged = Order::new_pegged(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, Peg::new(PegReference::Primary), dec!(2));
+        pegged.price = book.peg_price(&pegged);
+        let pegged_id = pegged.order_id;
+        book.add_order(pegged);
+        assert_eq!(book.order(&pegged_id).unwrap().price, Some(dec!(99)));
+
+        let better_bid = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(1));
+        let better_bid_id = better_bid.order_id;
+        book.add_order(better_bid);
+        let repriced = book.reprice_pegs();
+        assert_eq!(repriced.len(), 1);
+        assert_eq!((repriced[0].0.price, repriced[0].1), (Some(dec!(100)), dec!(99)));
+        assert_eq!(book.resting_orders()[1].order_id, pegged_id);
+
+        // The peg ignores its own level, so it falls back once the bid leaves.
+        book.cancel_order(&better_bid_id).unwrap();
+        book.reprice_pegs();
+        assert_eq!(book.order(&pegged_id).unwrap().price, Some(dec!(99)));
+        assert!(book.reprice_pegs().is_empty());
+    }
+
+    #[test]
+    fn test_pegs_are_not_repriced_through_the_book() {
+        let mut book = setup_book();
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(99), dec!(5)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(5)));
+        let peg = Peg { offset: dec!(-1), ..Peg::new(PegReference::Primary) };
+        let mut pegged = Order::new_pegged(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, peg, dec!(2));
+        pegged.price = book.peg_price(&pegged);
+        let pegged_id = pegged.order_id;
+        book.add_order(pegged);
+        assert_eq!(book.order(&pegged_id).unwrap().price, Some(dec!(100)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(99.5), dec!(1)));
+
+        // Following the new offer to 99.4 would cross the 99.5 bid, so the peg stays put.
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100.4), dec!(1)));
+        assert!(book.reprice_pegs().is_empty());
+        assert_eq!(book.order(&pegged_id).unwrap().price, Some(dec!(100)));
+    }
+
+    #[test]
+    fn test_discretionary_order_takes_inside_its_discretion() {
+        let mut book = setup_book();
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100.3), dec!(4)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(4)));
+
+        let buy = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(10)).with_discretion(dec!(100.5));
+        let (trades, _, remaining) = book.add_order(buy);
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].price, trades[0].quantity), (dec!(100.3), dec!(4)));
+        assert_eq!(remaining.remaining_quantity, dec!(6));
+        assert_eq!(book.best_bid(), Some(dec!(100)));
+    }
+
+    #[test]
+    fn test_resting_discretion_fills_after_displayed_liquidity() {
+        let mut book = setup_book();
+        let displayed = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100.2), dec!(3));
+        let displayed_id = displayed.order_id;
+        book.add_order(displayed);
+        let hidden = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(99), dec!(5)).with_discretion(dec!(100.5));
+        let hidden_id = hidden.order_id;
+        book.add_order(hidden);
+
+        let sell = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100.2), dec!(6));
+        let (trades, filled, remaining) = book.add_order(sell);
+
+        // The displayed bid keeps priority; the discretionary bid trades at the seller's price.
+        assert_eq!(trades.len(), 2);
+        assert_eq!((trades[0].buy_order_id, trades[0].price, trades[0].quantity), (displayed_id, dec!(100.2), dec!(3)));
+        assert_eq!((trades[1].buy_order_id, trades[1].price, trades[1].quantity), (hidden_id, dec!(100.2), dec!(3)));
+        assert_eq!(filled.len(), 1);
+        assert!(remaining.is_filled());
+        assert_eq!(book.order(&hidden_id).unwrap().remaining_quantity, dec!(2));
+        assert_eq!(book.best_bid(), Some(dec!(99)));
+
+        // A sell beyond the discretion rests instead.
+        let (trades, _, _) = book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100.6), dec!(1)));
+        assert!(trades.is_empty());
+        assert_eq!(book.best_ask(), Some(dec!(100.6)));
+    }
+
+    #[test]
+    fn test_immediate_or_cancel_and_fill_or_kill() {
+        let mut book = setup_book();
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100), dec!(4)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(4)).with_discretion(dec!(99)));
+        let buy = |tif, quantity| {
+            Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), quantity)
+                .with_attributes(OrderAttributes::default().with_time_in_force(tif))
+        };
+
+        // Only 4 are displayed at 100, but the discretionary offer reaches down to it.
+        let (trades, _, killed) = book.add_order(buy(TimeInForce::FillOrKill, dec!(9)));
+        assert!(trades.is_empty());
+        assert_eq!(killed.remaining_quantity, dec!(9));
+        let (trades, _, filled) = book.add_order(buy(TimeInForce::FillOrKill, dec!(6)));
+        assert_eq!(trades.len(), 2);
+        assert!(filled.is_filled());
+
+        let (trades, _, expired) = book.add_order(buy(TimeInForce::ImmediateOrCancel, dec!(5)));
+        assert_eq!(trades[0].quantity, dec!(2));
+        assert_eq!(expired.remaining_quantity, dec!(3));
+        assert_eq!(live_levels(book.bids()), 0);
+        assert_eq!(book.tally.expired, dec!(12));
+    }
+
     #[test]
     fn test_new_order_book_is_empty() {
         let book = setup_book();
         assert_eq!(book.instrument, "TEST-STOCK");
-        assert!(book.bids.is_empty());
-        assert!(book.asks.is_empty());
+        assert_eq!(live_levels(book.bids()), 0);
+        assert_eq!(live_levels(book.asks()), 0);
         assert!(book.orders.is_empty());
     }
 
@@ -231,10 +1600,10 @@ mod tests {
 
         assert!(trades.is_empty());
         assert_eq!(book.orders.len(), 1);
-        assert_eq!(book.bids.len(), 1);
-        assert!(book.asks.is_empty());
-        assert!(book.orders.contains_key(&order_id));
-        assert_eq!(book.bids.get(&dec!(150.0)).unwrap().front().unwrap(), &order_id);
+        assert_eq!(live_levels(book.bids()), 1);
+        assert_eq!(live_levels(book.asks()), 0);
+        assert!(book.orders.contains(&order_id));
+        assert_eq!(bid_queue(&book, dec!(150.0)), vec![order_id]);
     }
 
     #[test]
@@ -249,12 +1618,9 @@ mod tests {
         book.add_order(order2);
 
         assert_eq!(book.orders.len(), 2);
-        assert_eq!(book.bids.len(), 1);
+        assert_eq!(live_levels(book.bids()), 1);
         
-        let price_level_queue = book.bids.get(&dec!(150.0)).unwrap();
-        assert_eq!(price_level_queue.len(), 2);
-        assert_eq!(price_level_queue.get(0).unwrap(), &order1_id);
-        assert_eq!(price_level_queue.get(1).unwrap(), &order2_id);
+        assert_eq!(bid_queue(&book, dec!(150.0)), vec![order1_id, order2_id]);
     }
 
     #[test]
@@ -264,16 +1630,28 @@ mod tests {
         let order_id = order.order_id;
         book.add_order(order);
         assert!(!book.orders.is_empty());
-        assert!(!book.asks.is_empty());
+        assert_ne!(live_levels(book.asks()), 0);
 
         let result = book.cancel_order(&order_id);
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap().order_id, order_id);
         assert!(book.orders.is_empty());
-        assert!(book.asks.is_empty());
+        assert_eq!(live_levels(book.asks()), 0);
     }
     
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "TEST-STOCK book is crossed at bid 101 ask 100")]
+    fn test_crossed_book_panics_when_checked() {
+        let mut book = setup_book();
+        let mut ctx = EngineContext { check_crossed: true, ..EngineContext::default() };
+        book.add_order_with(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100), dec!(5)), &mut ctx);
+        // Rest a bid through the ask without matching, as a broken book would.
+        book.rest(&Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(101), dec!(5)));
+        book.add_order_with(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(90), dec!(1)), &mut ctx);
+    }
+
     #[test]
     fn test_cancel_order_from_level_with_multiple_orders() {
         let mut book = setup_book();
@@ -288,11 +1666,206 @@ mod tests {
 
         assert!(result.is_ok());
         assert_eq!(book.orders.len(), 1);
-        assert_eq!(book.bids.len(), 1);
+        assert_eq!(live_levels(book.bids()), 1);
+
+        assert_eq!(bid_queue(&book, dec!(100.0)), vec![order2_id]);
+    }
+
+    #[test]
+    fn test_level_iterators_walk_best_first_and_skip_emptied_levels() {
+        let mut book = setup_book();
+        for (side, price, quantity) in [(Side::Buy, dec!(99), dec!(2)), (Side::Buy, dec!(99), dec!(3)), (Side::Buy, dec!(98.5), dec!(1)), (Side::Sell, dec!(101), dec!(4)), (Side::Sell, dec!(100.5), dec!(6))] {
+            book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), side, price, quantity));
+        }
+        assert_eq!(book.iter_bids().collect::<Vec<_>>(), [(dec!(99), dec!(5), 2), (dec!(98.5), dec!(1), 1)]);
+        assert_eq!(book.iter_asks().next(), Some((dec!(100.5), dec!(6), 1)));
 
-        let price_level_queue = book.bids.get(&dec!(100.0)).unwrap();
-        assert_eq!(price_level_queue.len(), 1);
-        assert_eq!(price_level_queue.front().unwrap(), &order2_id);
+        // The emptied level waits for compaction but is not listed.
+        book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(6)));
+        assert_eq!(book.iter_asks().collect::<Vec<_>>(), [(dec!(101), dec!(4), 1)]);
+        assert_eq!(book.depth(Side::Buy, 1), [(dec!(99), dec!(5))]);
+    }
+
+    #[test]
+    fn test_imbalance_weighs_the_top_levels() {
+        let mut book = setup_book();
+        assert_eq!(book.imbalance(2), None);
+        for (side, price, quantity) in [(Side::Buy, dec!(99), dec!(6)), (Side::Buy, dec!(98), dec!(2)), (Side::Buy, dec!(97), dec!(100))] {
+            book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), side, price, quantity));
+        }
+        assert_eq!(book.imbalance(2), Some(Decimal::ONE));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(2)));
+        // Eight bid against two ask; the third bid level is out of reach.
+        assert_eq!(book.imbalance(2), Some(dec!(0.6)));
+        assert_eq!(book.imbalance(1), Some(dec!(0.5)));
+    }
+
+    #[test]
+    fn test_grouped_levels_merge_into_buckets_away_from_the_spread() {
+        let config = InstrumentConfig { tick_size: Some(dec!(0.05)), price_grouping: Some(dec!(0.25)), ..Default::default() };
+        let mut book = OrderBook::with_config("TEST-STOCK".to_string(), config);
+        let levels = [(Side::Buy, dec!(99.95), dec!(1)), (Side::Buy, dec!(99.80), dec!(2)), (Side::Buy, dec!(99.75), dec!(3)), (Side::Buy, dec!(99.70), dec!(4)), (Side::Sell, dec!(100.05), dec!(5)), (Side::Sell, dec!(100.25), dec!(6)), (Side::Sell, dec!(100.30), dec!(7))];
+        for (side, price, quantity) in levels {
+            book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), side, price, quantity));
+        }
+        // Bids floor to the bucket and asks ceil, so the spread only widens.
+        assert_eq!(book.iter_grouped(Side::Buy, dec!(0.25)).collect::<Vec<_>>(), [(dec!(99.75), dec!(6), 3), (dec!(99.50), dec!(4), 1)]);
+        assert_eq!(book.iter_grouped(Side::Sell, dec!(0.25)).collect::<Vec<_>>(), [(dec!(100.25), dec!(11), 2), (dec!(100.50), dec!(7), 1)]);
+        assert_eq!(book.iter_grouped(Side::Buy, dec!(1)).collect::<Vec<_>>(), [(dec!(99), dec!(10), 4)]);
+
+        let display = book.display();
+        let levels = |levels: &[PriceLevel]| levels.iter().map(|level| (level.price, level.volume)).collect::<Vec<_>>();
+        assert_eq!(levels(&display.bids), [(dec!(99.75), dec!(6)), (dec!(99.50), dec!(4))]);
+        assert_eq!(levels(&display.asks), [(dec!(100.25), dec!(11)), (dec!(100.50), dec!(7))]);
+    }
+
+    #[test]
+    fn test_arrival_sequence_decides_time_priority() {
+        let mut book = setup_book();
+        // Same timestamp twice, then a clock that stepped back.
+        let orders: Vec<Order> = [5_000, 5_000, 4_000]
+            .into_iter()
+            .map(|timestamp| Order { timestamp, ..Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(5)) })
+            .collect();
+        let ids: Vec<Uuid> = orders.iter().map(|order| order.order_id).collect();
+        let sequences: Vec<u64> = orders.into_iter().map(|order| book.add_order(order).2.sequence).collect();
+        assert_eq!(sequences, [1, 2, 3]);
+        assert_eq!(bid_queue(&book, dec!(100)), ids);
+
+        let (_, filled, _) = book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(5)));
+        assert_eq!(filled[0].order_id, ids[0]);
+
+        // Restored orders take their place by sequence, whatever the load order.
+        let mut restored = setup_book();
+        let resting: Vec<Order> = [ids[2], ids[1]].iter().map(|id| book.order(id).unwrap()).collect();
+        for order in resting {
+            restored.restore_order(order).unwrap();
+        }
+        assert_eq!(bid_queue(&restored, dec!(100)), [ids[1], ids[2]]);
+        let late = restored.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(5))).2;
+        assert_eq!(late.sequence, 4);
+    }
+
+    #[test]
+    fn test_display_reports_queue_age_and_last_trade_per_level() {
+        let mut book = setup_book();
+        let clock = ManualClock::new(5_000);
+        let mut ctx = EngineContext { clock: Box::new(clock.clone()), ..EngineContext::default() };
+        let mut first = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(5));
+        first.timestamp = 1_000;
+        let mut second = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(5));
+        second.timestamp = 2_000;
+        let mut deeper = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(102), dec!(5));
+        deeper.timestamp = 3_000;
+        for order in [first, second, deeper] {
+            book.add_order_with(order, &mut ctx);
+        }
+
+        let asks = book.display().asks;
+        assert_eq!((asks[0].oldest_order_at, asks[0].last_trade_at), (Some(1_000), None));
+
+        clock.set(8_000);
+        book.add_order_with(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(5)), &mut ctx);
+        let asks = book.display().asks;
+        assert_eq!((asks[0].oldest_order_at, asks[0].last_trade_at), (Some(2_000), Some(8_000)));
+        assert_eq!((asks[1].oldest_order_at, asks[1].last_trade_at), (Some(3_000), None));
+    }
+
+    #[test]
+    fn test_cancel_leaves_a_tombstone_that_matching_skips() {
+        let mut book = setup_book();
+        let bids: Vec<Order> = (0..6)
+            .map(|_| Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(5)))
+            .collect();
+        let level = |book: &OrderBook| {
+            let level = book.bids().get(Fixed::from_validated(dec!(100))).unwrap();
+            (level.queue.len(), level.live)
+        };
+        for bid in &bids[..4] {
+            book.add_order(bid.clone());
+        }
+
+        book.cancel_order(&bids[1].order_id).unwrap();
+        book.cancel_order(&bids[0].order_id).unwrap();
+        assert_eq!(level(&book), (4, 2));
+        assert_eq!(bid_queue(&book, dec!(100)), vec![bids[2].order_id, bids[3].order_id]);
+        assert_eq!(book.display().bids[0].volume, dec!(10));
+
+        let sell = |book: &mut OrderBook| {
+            let (trades, ..) = book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(5)));
+            trades.iter().map(|trade| trade.buy_order_id).collect::<Vec<_>>()
+        };
+        assert_eq!(sell(&mut book), vec![bids[2].order_id]);
+        assert_eq!(level(&book), (1, 1));
+
+        // Once tombstones outnumber live orders the level is compacted.
+        book.add_order(bids[4].clone());
+        book.add_order(bids[5].clone());
+        book.cancel_order(&bids[4].order_id).unwrap();
+        assert_eq!(level(&book), (3, 2));
+        book.cancel_order(&bids[3].order_id).unwrap();
+        assert_eq!(level(&book), (1, 1));
+        assert_eq!(sell(&mut book), vec![bids[5].order_id]);
+        assert_eq!(live_levels(book.bids()), 0);
+    }
+
+    #[test]
+    fn test_audit_reports_a_broken_level() {
+        let mut book = setup_book();
+        let bids: Vec<Order> = (0..3)
+            .map(|_| Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100), dec!(5)))
+            .collect();
+        for bid in &bids {
+            book.add_order(bid.clone());
+        }
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101), dec!(5)));
+        book.cancel_order(&bids[1].order_id).unwrap();
+        book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(7)));
+        assert_eq!(book.audit(), Ok(()));
+
+        book.sides[Side::Buy as usize].get_mut(Fixed::from_validated(dec!(100))).unwrap().live += 1;
+        assert_eq!(book.audit(), Err("BUY level 100 counts 2 live orders but queues 1".to_string()));
+    }
+
+    #[test]
+    fn test_emptied_levels_are_reused_until_compacted() {
+        let mut book = setup_book();
+        let bid = |price| Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, price, dec!(1));
+
+        let first = bid(dec!(100));
+        book.add_order(first.clone());
+        book.cancel_order(&first.order_id).unwrap();
+        assert_eq!((book.bids().len(), book.best_bid()), (1, None));
+        let second = bid(dec!(100));
+        book.add_order(second.clone());
+        assert_eq!(book.level_stats(), LevelStats { created: 1, reused: 1, ..Default::default() });
+
+        book.cancel_order(&second.order_id).unwrap();
+        book.compact_levels();
+        assert!(book.bids().is_empty());
+        assert_eq!(book.level_stats().compacted, 1);
+
+        // More than MAX_EMPTY_LEVELS emptied levels trigger a sweep early.
+        for tick in 0..=MAX_EMPTY_LEVELS {
+            let order = bid(dec!(90) + Decimal::new(tick as i64, 2));
+            book.add_order(order.clone());
+            book.cancel_order(&order.order_id).unwrap();
+        }
+        assert!(book.bids().is_empty());
+        assert_eq!(book.level_stats().compactions, 2);
+
+        // A level emptied over and over is listed once, and otherwise swept
+        // every COMPACTION_INTERVAL operations.
+        let ticks = COMPACTION_INTERVAL - book.operations % COMPACTION_INTERVAL;
+        for _ in 0..ticks / 2 {
+            let order = bid(dec!(80));
+            book.add_order(order.clone());
+            book.cancel_order(&order.order_id).unwrap();
+        }
+        assert!(book.bids().is_empty());
+        let stats = book.level_stats();
+        assert_eq!((stats.compactions, stats.compacted), (3, MAX_EMPTY_LEVELS as u64 + 3));
+        assert_eq!(stats.reused, ticks / 2);
     }
 
     #[test]
@@ -306,6 +1879,26 @@ mod tests {
         matches!(result.unwrap_err(), MatchingEngineError::OrderNotFound(id) if id == non_existent_id);
     }
     
+    #[test]
+    fn test_tally_is_conserved_across_fills_cancels_and_expiry() {
+        let mut book = setup_book();
+        let resting = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100.0), dec!(10));
+        let resting_id = resting.order_id;
+        book.add_order(resting);
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(101.0), dec!(5)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(100.0), dec!(4)));
+        book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(3)));
+        book.cancel_order(&resting_id).unwrap();
+
+        let tally = book.tally();
+        assert_eq!(tally.submitted, dec!(22));
+        assert_eq!(tally.filled, dec!(8));
+        assert_eq!(tally.canceled, dec!(6));
+        assert_eq!(tally.expired, dec!(3));
+        assert_eq!(tally.resting, dec!(5));
+        assert!(tally.is_conserved());
+    }
+
     #[test]
     fn test_get_matchable_prices_for_buy_limit_order() {
         let mut book = setup_book();
@@ -316,7 +1909,7 @@ mod tests {
 
         let incoming_order = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(102.0), dec!(5));
 
-        let prices = book.get_matchable_prices(&incoming_order);
+        let prices: Vec<Decimal> = book.get_matchable_prices(&incoming_order).into_iter().map(Fixed::to_decimal).collect();
 
         assert_eq!(prices, vec![dec!(101.0), dec!(102.0)]);
     }
@@ -330,7 +1923,7 @@ mod tests {
 
         let incoming_order = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(100.0), dec!(5));
 
-        let prices = book.get_matchable_prices(&incoming_order);
+        let prices: Vec<Decimal> = book.get_matchable_prices(&incoming_order).into_iter().map(Fixed::to_decimal).collect();
 
         assert_eq!(prices, vec![dec!(101.0), dec!(100.0)]);
     }
@@ -344,7 +1937,7 @@ mod tests {
 
         let incoming_order = Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(5));
 
-        let prices = book.get_matchable_prices(&incoming_order);
+        let prices: Vec<Decimal> = book.get_matchable_prices(&incoming_order).into_iter().map(Fixed::to_decimal).collect();
 
         assert_eq!(prices, vec![dec!(101.0), dec!(102.0), dec!(103.0)]);
     }
@@ -358,9 +1951,29 @@ mod tests {
 
         let incoming_order = Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(5));
 
-        let prices = book.get_matchable_prices(&incoming_order);
+        let prices: Vec<Decimal> = book.get_matchable_prices(&incoming_order).into_iter().map(Fixed::to_decimal).collect();
 
         assert_eq!(prices, vec![dec!(99.0), dec!(98.0), dec!(97.0)]);
     }
-}
 
+    #[test]
+    fn test_reference_price_and_collar() {
+        let config = InstrumentConfig { price_collar: Some(PriceCollar::new(dec!(0.1))), ..Default::default() };
+        let mut book = OrderBook::with_config("TEST-STOCK".to_string(), config);
+        let far = Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(500.0), dec!(10));
+        assert!(book.check_price_collar(&far).is_ok());
+
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(98.0), dec!(10)));
+        book.add_order(Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Sell, dec!(102.0), dec!(10)));
+        assert_eq!(book.reference_price(ReferencePrice::LastTrade), Some(dec!(100.0)));
+        assert!(matches!(book.check_price_collar(&far), Err(MatchingEngineError::PriceOutsideCollar { .. })));
+
+        book.add_order(Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(5)));
+        assert_eq!(book.last_trade_price(), Some(dec!(102.0)));
+        assert_eq!(book.reference_price(ReferencePrice::LastTrade), Some(dec!(102.0)));
+        assert_eq!(book.reference_price(ReferencePrice::Mid), Some(dec!(100.0)));
+
+        let market = Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(5));
+        assert!(book.check_price_collar(&market).is_ok());
+    }
+}
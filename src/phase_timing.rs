@@ -0,0 +1,262 @@
+//! Cycle counts of the phases inside `OrderBook::add_order`, compiled in
+//! with the `phase-timing` feature and printed with the latency report.
+//! Without it the `phase_*!` macros expand to nothing.
+
+/// Starts timing an order's phases on the current thread.
+macro_rules! phase_begin {
+    () => {
+        #[cfg(feature = "phase-timing")]
+        $crate::phase_timing::begin();
+    };
+}
+
+/// Charges the cycles since the last boundary to `Phase::$phase`.
+macro_rules! phase_lap {
+    ($phase:ident) => {
+        #[cfg(feature = "phase-timing")]
+        $crate::phase_timing::lap($crate::phase_timing::Phase::$phase);
+    };
+}
+
+/// Records the order's phases into their histograms.
+macro_rules! phase_end {
+    () => {
+        #[cfg(feature = "phase-timing")]
+        $crate::phase_timing::end();
+    };
+}
+
+#[cfg(feature = "phase-timing")]
+pub use enabled::*;
+
+#[cfg(feature = "phase-timing")]
+mod enabled {
+    use std::cell::RefCell;
+    use std::time::{Duration, Instant};
+
+    /// A phase of adding one order to a book.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Phase {
+        /// The band in force, the fill-or-kill check and the prices the
+        /// order can trade at.
+        PriceDiscovery,
+        /// Matching against each of those levels and against discretion.
+        LevelProcessing,
+        /// Tallies, the band reference, resting the remainder and level
+        /// compaction.
+        Bookkeeping,
+    }
+
+    impl Phase {
+        pub const ALL: [Phase; 3] = [Phase::PriceDiscovery, Phase::LevelProcessing, Phase::Bookkeeping];
+
+        pub fn name(self) -> &'static str {
+            match self {
+                Phase::PriceDiscovery => "price_discovery",
+                Phase::LevelProcessing => "level_processing",
+                Phase::Bookkeeping => "bookkeeping",
+            }
+        }
+    }
+
+    /// The time-stamp counter on x86-64, elsewhere nanoseconds since the
+    /// thread first asked.
+    fn cycles() -> u64 {
+        #[cfg(target_arch = "x86_64")]
+        {
+            // SAFETY: RDTSC is available on every x86-64 CPU.
+            unsafe { core::arch::x86_64::_rdtsc() }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            thread_local! {
+                static EPOCH: Instant = Instant::now();
+            }
+            EPOCH.with(|epoch| epoch.elapsed().as_nanos() as u64)
+        }
+    }
+
+    /// Sub-buckets per power of two, as a number of bits: values are kept
+    /// to within about 3%.
+    const SUB_BITS: u32 = 5;
+    const SUB_BUCKETS: usize = 1 << SUB_BITS;
+
+    /// A log-linear histogram in the manner of HdrHistogram: exact below
+    /// `SUB_BUCKETS`, then `SUB_BUCKETS` equal buckets per power of two, so
+    /// the tail keeps its shape without storing every sample.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CycleHistogram {
+        counts: Vec<u64>,
+        count: u64,
+        sum: u128,
+        max: u64,
+    }
+
+    impl CycleHistogram {
+        fn bucket(value: u64) -> usize {
+            if value < SUB_BUCKETS as u64 {
+                return value as usize;
+            }
+            let shift = 63 - value.leading_zeros() - SUB_BITS;
+            (shift as usize + 1) * SUB_BUCKETS + (value >> shift) as usize - SUB_BUCKETS
+        }
+
+        /// The largest value that falls in `bucket`.
+        fn upper_bound(bucket: usize) -> u64 {
+            let (block, sub) = (bucket / SUB_BUCKETS, (bucket % SUB_BUCKETS) as u64);
+            if block == 0 {
+                return sub;
+            }
+            let shift = block as u32 - 1;
+            ((SUB_BUCKETS as u64 + sub) << shift) + ((1 << shift) - 1)
+        }
+
+        pub fn record(&mut self, value: u64) {
+            let bucket = Self::bucket(value);
+            if self.counts.len() <= bucket {
+                self.counts.resize(bucket + 1, 0);
+            }
+            self.counts[bucket] += 1;
+            self.count += 1;
+            self.sum += value as u128;
+            self.max = self.max.max(value);
+        }
+
+        pub fn count(&self) -> u64 {
+            self.count
+        }
+
+        pub fn mean(&self) -> f64 {
+            if self.count == 0 { 0.0 } else { self.sum as f64 / self.count as f64 }
+        }
+
+        pub fn max(&self) -> u64 {
+            self.max
+        }
+
+        /// The value below which `quantile` of the samples fall, to the
+        /// histogram's precision.
+        pub fn percentile(&self, quantile: f64) -> u64 {
+            let rank = ((self.count as f64 * quantile).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (bucket, &count) in self.counts.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    return Self::upper_bound(bucket).min(self.max);
+                }
+            }
+            self.max
+        }
+    }
+
+    #[derive(Default)]
+    struct State {
+        last: u64,
+        pending: [Option<u64>; 3],
+        histograms: [CycleHistogram; 3],
+    }
+
+    thread_local! {
+        static STATE: RefCell<State> = RefCell::new(State::default());
+    }
+
+    pub fn begin() {
+        let now = cycles();
+        STATE.with_borrow_mut(|state| {
+            state.pending = [None; 3];
+            state.last = now;
+        });
+    }
+
+    pub fn lap(phase: Phase) {
+        let now = cycles();
+        STATE.with_borrow_mut(|state| {
+            let elapsed = now.saturating_sub(state.last);
+            *state.pending[phase as usize].get_or_insert(0) += elapsed;
+            state.last = now;
+        });
+    }
+
+    pub fn end() {
+        STATE.with_borrow_mut(|state| {
+            for (histogram, pending) in state.histograms.iter_mut().zip(std::mem::take(&mut state.pending)) {
+                if let Some(cycles) = pending {
+                    histogram.record(cycles);
+                }
+            }
+        });
+    }
+
+    /// The histogram of each phase timed on the calling thread so far.
+    pub fn phase_histograms() -> Vec<(Phase, CycleHistogram)> {
+        STATE.with_borrow(|state| Phase::ALL.into_iter().zip(state.histograms.iter().cloned()).collect())
+    }
+
+    /// Counter ticks per nanosecond, measured over a few milliseconds.
+    fn ticks_per_nanosecond() -> f64 {
+        let (start, start_cycles) = (Instant::now(), cycles());
+        while start.elapsed() < Duration::from_millis(20) {
+            std::hint::spin_loop();
+        }
+        (cycles() - start_cycles) as f64 / start.elapsed().as_nanos() as f64
+    }
+
+    pub fn print_phases() {
+        let histograms = phase_histograms();
+        if histograms.iter().all(|(_, histogram)| histogram.count() == 0) {
+            return;
+        }
+        let per_ns = ticks_per_nanosecond();
+        println!("\n--- add_order Phases (cycles, ~{:.2} per ns) ---", per_ns);
+        println!("{:<18} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}", "Phase", "Orders", "Mean", "Median", "p99", "p99.9", "Max");
+        for (phase, histogram) in histograms {
+            println!(
+                "{:<18} {:>10} {:>10.1} {:>10} {:>10} {:>10} {:>10}",
+                phase.name(),
+                histogram.count(),
+                histogram.mean(),
+                histogram.percentile(0.5),
+                histogram.percentile(0.99),
+                histogram.percentile(0.999),
+                histogram.max(),
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_histogram_keeps_the_tail_within_its_precision() {
+            let mut histogram = CycleHistogram::default();
+            for value in 1..=1_000 {
+                histogram.record(value);
+            }
+            histogram.record(1_000_000);
+            assert_eq!(histogram.count(), 1_001);
+            assert_eq!(histogram.percentile(0.0), 1);
+            let median = histogram.percentile(0.5);
+            assert!((500..=520).contains(&median), "median {}", median);
+            assert_eq!(histogram.percentile(1.0), 1_000_000);
+            assert!(histogram.percentile(0.99) >= 990 && histogram.percentile(0.99) <= 1_024);
+            for value in [0, 31, 32, 33, 1_000, u64::MAX] {
+                assert!(CycleHistogram::upper_bound(CycleHistogram::bucket(value)) >= value);
+            }
+        }
+
+        #[test]
+        fn test_laps_are_summed_per_order() {
+            begin();
+            lap(Phase::PriceDiscovery);
+            lap(Phase::LevelProcessing);
+            lap(Phase::LevelProcessing);
+            end();
+            begin();
+            end();
+            let histograms = phase_histograms();
+            let counts: Vec<u64> = histograms.iter().map(|(_, histogram)| histogram.count()).collect();
+            assert_eq!(counts, [1, 1, 0]);
+        }
+    }
+}
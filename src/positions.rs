@@ -0,0 +1,284 @@
+//! Positions, fills and mark-to-market P&L of every client that traded in a
+//! run, ranked into a leaderboard at the end.
+
+use crate::clients::ClientId;
+use crate::engine::MatchingEngine;
+use crate::funding::{funding_payment, FundingRound};
+use crate::options::{Exercise, OptionExpiry};
+use crate::trade::Trade;
+use crate::utils::{Side, UuidMap};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Leaderboard rows printed unless `--leaderboard` asks for another count.
+pub const DEFAULT_LEADERBOARD_SIZE: usize = 10;
+
+/// One client's holding in one instrument. `cash` is what the fills paid
+/// (negative) or received (positive).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Position {
+    pub quantity: Decimal,
+    pub cash: Decimal,
+}
+
+/// Fill statistics of one client. Auction fills count as neither maker nor
+/// taker fills unless the client's order was the trade's aggressor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FillStats {
+    pub fills: usize,
+    pub maker_fills: usize,
+    pub taker_fills: usize,
+    pub bought: Decimal,
+    pub sold: Decimal,
+}
+
+impl FillStats {
+    pub fn volume(&self) -> Decimal {
+        self.bought + self.sold
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClientBook {
+    positions: BTreeMap<String, Position>,
+    fills: FillStats,
+}
+
+/// A client's standing once its positions are marked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientPnl {
+    pub client: ClientId,
+    pub pnl: Decimal,
+    /// Net quantity summed over instruments.
+    pub net_position: Decimal,
+    /// Open instruments that had no mark and were left out of `pnl`.
+    pub unmarked: usize,
+    pub fills: FillStats,
+}
+
+/// Follows the fills of client orders through a run. Orders are attributed
+/// when submitted, because the engine forgets an owner once its order fills.
+#[derive(Debug, Default)]
+pub struct PositionLedger {
+    owners: UuidMap<ClientId>,
+    clients: HashMap<ClientId, ClientBook>,
+    last_prices: BTreeMap<String, Decimal>,
+}
+
+impl PositionLedger {
+    pub fn register(&mut self, order_id: Uuid, client: ClientId) {
+        self.owners.insert(order_id, client);
+    }
+
+    /// The client an order was submitted for.
+    pub fn owner(&self, order_id: &Uuid) -> Option<&ClientId> {
+        self.owners.get(order_id)
+    }
+
+    pub fn record_trade(&mut self, trade: &Trade) {
+        self.last_prices.insert(trade.instrument.clone(), trade.price);
+        self.book_fills(trade, false);
+    }
+
+    /// Reverses the fills of a busted trade. `last_price` is the
+    /// instrument's last trade price once the bust is off the tape.
+    pub fn bust(&mut self, trade: &Trade, last_price: Option<Decimal>) {
+        match last_price {
+            Some(price) => self.last_prices.insert(trade.instrument.clone(), price),
+            None => self.last_prices.remove(&trade.instrument),
+        };
+        self.book_fills(trade, true);
+    }
+
+    fn book_fills(&mut self, trade: &Trade, reverse: bool) {
+        let (quantity, notional) = match reverse {
+            false => (trade.quantity, trade.price * trade.quantity),
+            true => (-trade.quantity, -trade.price * trade.quantity),
+        };
+        let count = |fills: &mut usize| *fills = if reverse { fills.saturating_sub(1) } else { *fills + 1 };
+        for (order_id, side) in [(trade.buy_order_id, Side::Buy), (trade.sell_order_id, Side::Sell)] {
+            let Some(client) = self.owners.get(&order_id) else {
+                continue;
+            };
+            let book = self.clients.entry(client.clone()).or_default();
+            let position = book.positions.entry(trade.instrument.clone()).or_default();
+            match side {
+                Side::Buy => {
+                    position.quantity += quantity;
+                    position.cash -= notional;
+                    book.fills.bought += quantity;
+                }
+                Side::Sell => {
+                    position.quantity -= quantity;
+                    position.cash += notional;
+                    book.fills.sold += quantity;
+                }
+            }
+            count(&mut book.fills.fills);
+            if trade.aggressor_order_id() == order_id {
+                count(&mut book.fills.taker_fills);
+            } else {
+                count(&mut book.fills.maker_fills);
+            }
+        }
+    }
+
+    /// Books the funding of `round` to the cash of every client holding its
+    /// instrument, and returns what each paid or received.
+    pub fn apply_funding(&mut self, round: &FundingRound) -> Vec<(ClientId, Decimal)> {
+        let mut payments = Vec::new();
+        for (client, book) in &mut self.clients {
+            let Some(position) = book.positions.get_mut(&round.instrument) else { continue };
+            if position.quantity.is_zero() {
+                continue;
+            }
+            let payment = funding_payment(position.quantity, round);
+            position.cash += payment;
+            payments.push((client.clone(), payment));
+        }
+        payments.sort_by(|a, b| a.0.cmp(&b.0));
+        payments
+    }
+
+    /// Closes every client's position in the expiring option, moving the
+    /// underlying at the strike into the positions of those in the money,
+    /// and returns their exercises and assignments.
+    pub fn exercise(&mut self, expiry: &OptionExpiry) -> Vec<Exercise> {
+        let mut exercises = Vec::new();
+        for (client, book) in &mut self.clients {
+            let quantity = expiry.exercise(&mut book.positions);
+            if quantity.is_zero() {
+                continue;
+            }
+            exercises.push(Exercise {
+                client: client.clone(),
+                option: expiry.instrument.clone(),
+                underlying: expiry.contract.underlying.clone(),
+                quantity,
+                strike: expiry.contract.strike,
+                settlement: expiry.settlement.unwrap_or_default(),
+                timestamp: expiry.timestamp,
+            });
+        }
+        if let Some(settlement) = expiry.settlement {
+            self.last_prices.entry(expiry.contract.underlying.clone()).or_insert(settlement);
+        }
+        exercises.sort_by(|a, b| a.client.cmp(&b.client));
+        exercises
+    }
+
+    pub fn position(&self, client: &ClientId, instrument: &str) -> Option<&Position> {
+        self.clients.get(client)?.positions.get(instrument)
+    }
+
+    /// The mark of each traded instrument: the book's mid when both sides
+    /// are quoted, otherwise the last trade price.
+    pub fn marks(&self, engine: &MatchingEngine) -> BTreeMap<String, Decimal> {
+        self.last_prices
+            .iter()
+            .map(|(instrument, last)| {
+                let mid = engine.order_book(instrument).and_then(|book| book.mid_price());
+                (instrument.clone(), mid.unwrap_or(*last))
+            })
+            .collect()
+    }
+
+    /// Every client that traded, best P&L first; ties go by client id.
+    pub fn leaderboard(&self, marks: &BTreeMap<String, Decimal>) -> Vec<ClientPnl> {
+        let mut standings: Vec<ClientPnl> = self
+            .clients
+            .iter()
+            .map(|(client, book)| {
+                let mut pnl = Decimal::ZERO;
+                let mut unmarked = 0;
+                for (instrument, position) in &book.positions {
+                    match marks.get(instrument) {
+                        Some(mark) => pnl += position.cash + position.quantity * mark,
+                        None if position.quantity.is_zero() => pnl += position.cash,
+                        None => unmarked += 1,
+                    }
+                }
+                ClientPnl {
+                    client: client.clone(),
+                    pnl,
+                    net_position: book.positions.values().map(|position| position.quantity).sum(),
+                    unmarked,
+                    fills: book.fills.clone(),
+                }
+            })
+            .collect();
+        standings.sort_by(|a, b| b.pnl.cmp(&a.pnl).then_with(|| a.client.cmp(&b.client)));
+        standings
+    }
+}
+
+/// Prints the `top` best clients with their fill statistics.
+pub fn print_leaderboard(standings: &[ClientPnl], top: usize) {
+    if standings.is_empty() {
+        return;
+    }
+    println!("\n--- P&L Leaderboard (top {} of {} clients, marked to mid or last trade) ---", top.min(standings.len()), standings.len());
+    println!(
+        "{:>4} {:<16} {:>14} {:>12} {:>8} {:>8} {:>8} {:>12}",
+        "Rank", "Client", "P&L", "Net pos", "Fills", "Maker", "Taker", "Volume"
+    );
+    for (rank, standing) in standings.iter().take(top).enumerate() {
+        let unmarked = if standing.unmarked > 0 { "*" } else { "" };
+        println!(
+            "{:>4} {:<16} {:>14} {:>12} {:>8} {:>8} {:>8} {:>12}",
+            rank + 1,
+            standing.client.to_string(),
+            format!("{}{}", standing.pnl.round_dp(4), unmarked),
+            standing.net_position,
+            standing.fills.fills,
+            standing.fills.maker_fills,
+            standing.fills.taker_fills,
+            standing.fills.volume()
+        );
+    }
+    if standings.iter().take(top).any(|standing| standing.unmarked > 0) {
+        println!("* has an open position in an instrument without a mark, left out of its P&L");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn trade(price: Decimal, quantity: Decimal, buy_order_id: Uuid, sell_order_id: Uuid, taker_side: Side) -> Trade {
+        Trade::new("SOFI".to_string(), price, quantity, buy_order_id, sell_order_id, taker_side)
+    }
+
+    #[test]
+    fn test_leaderboard_marks_positions_and_ranks_clients() {
+        let (alice, bob, carol) = (ClientId::new("alice"), ClientId::new("bob"), ClientId::new("carol"));
+        let (ask, bid, lift, hit) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut ledger = PositionLedger::default();
+        ledger.register(ask, alice.clone());
+        ledger.register(bid, alice.clone());
+        ledger.register(lift, bob.clone());
+        ledger.register(hit, carol.clone());
+
+        // Bob lifts Alice's offer at 10, then Carol hits Alice's bid at 9.
+        ledger.record_trade(&trade(dec!(10), dec!(5), lift, ask, Side::Buy));
+        ledger.record_trade(&trade(dec!(9), dec!(2), bid, hit, Side::Sell));
+        // An order nobody registered trades without touching any client.
+        ledger.record_trade(&trade(dec!(9.5), dec!(1), Uuid::new_v4(), Uuid::new_v4(), Side::Buy));
+        assert_eq!(ledger.position(&alice, "SOFI"), Some(&Position { quantity: dec!(-3), cash: dec!(32) }));
+
+        let marks = BTreeMap::from([("SOFI".to_string(), dec!(9.5))]);
+        let standings = ledger.leaderboard(&marks);
+        let ranked: Vec<_> = standings.iter().map(|s| (s.client.0.as_str(), s.pnl)).collect();
+        assert_eq!(ranked, [("alice", dec!(3.5)), ("carol", dec!(-1.0)), ("bob", dec!(-2.5))]);
+        let alice_stats = &standings[0].fills;
+        assert_eq!((alice_stats.fills, alice_stats.maker_fills, alice_stats.taker_fills), (2, 2, 0));
+        assert_eq!(alice_stats.volume(), dec!(7));
+        assert_eq!(standings[2].fills.taker_fills, 1);
+        assert_eq!(standings[2].net_position, dec!(5));
+
+        let unmarked = ledger.leaderboard(&BTreeMap::new());
+        assert!(unmarked.iter().all(|standing| standing.unmarked == 1 && standing.pnl.is_zero()));
+    }
+}
@@ -0,0 +1,111 @@
+//! Optional timing of the matching and logging phases, compiled in with the
+//! `profiling` feature. Without it `profile_scope!` expands to nothing.
+
+/// Times the rest of the enclosing block under `name` on the current thread.
+macro_rules! profile_scope {
+    ($name:literal) => {
+        #[cfg(feature = "profiling")]
+        let _profile_scope = $crate::profiling::Scope::enter($name);
+    };
+}
+
+#[cfg(feature = "profiling")]
+pub use enabled::*;
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use std::cell::RefCell;
+    use std::fs::File;
+    use std::time::Instant;
+
+    /// Calls and inclusive time of one named scope.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ScopeStats {
+        pub name: &'static str,
+        pub calls: u64,
+        pub total_ns: u128,
+    }
+
+    thread_local! {
+        // A handful of scopes, so a linear search beats hashing the name.
+        static SCOPES: RefCell<Vec<ScopeStats>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub struct Scope {
+        name: &'static str,
+        start: Instant,
+    }
+
+    impl Scope {
+        pub fn enter(name: &'static str) -> Self {
+            Self { name, start: Instant::now() }
+        }
+    }
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            let elapsed = self.start.elapsed().as_nanos();
+            SCOPES.with_borrow_mut(|scopes| match scopes.iter_mut().find(|stats| stats.name == self.name) {
+                Some(stats) => {
+                    stats.calls += 1;
+                    stats.total_ns += elapsed;
+                }
+                None => scopes.push(ScopeStats { name: self.name, calls: 1, total_ns: elapsed }),
+            });
+        }
+    }
+
+    /// The scopes timed on the calling thread so far, in first-entered order.
+    pub fn scope_stats() -> Vec<ScopeStats> {
+        SCOPES.with_borrow(|scopes| scopes.clone())
+    }
+
+    pub fn print_scopes() {
+        println!("\n--- Profile Scopes (matching thread, inclusive) ---");
+        println!("{:<20} {:>12} {:>16} {:>12}", "Scope", "Calls", "Total ns", "Mean ns");
+        for stats in scope_stats() {
+            let mean = stats.total_ns as f64 / stats.calls as f64;
+            println!("{:<20} {:>12} {:>16} {:>12.1}", stats.name, stats.calls, stats.total_ns, mean);
+        }
+    }
+
+    /// Samples every thread's stack for the lifetime of the run and renders
+    /// the samples as a flamegraph.
+    pub struct FlamegraphProfiler {
+        guard: pprof::ProfilerGuard<'static>,
+    }
+
+    impl FlamegraphProfiler {
+        pub fn start(frequency: i32) -> Result<Self, pprof::Error> {
+            let guard = pprof::ProfilerGuardBuilder::default()
+                .frequency(frequency)
+                .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+                .build()?;
+            Ok(Self { guard })
+        }
+
+        pub fn write_flamegraph(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+            let report = self.guard.report().build()?;
+            report.flamegraph(File::create(path)?)?;
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_scopes_count_calls_and_nest() {
+            for _ in 0..3 {
+                profile_scope!("test_outer");
+                profile_scope!("test_inner");
+            }
+            let stats = scope_stats();
+            let outer = stats.iter().find(|stats| stats.name == "test_outer").unwrap();
+            let inner = stats.iter().find(|stats| stats.name == "test_inner").unwrap();
+            assert_eq!((outer.calls, inner.calls), (3, 3));
+            assert!(outer.total_ns >= inner.total_ns);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Immutable summaries of the books, published by the matching thread and
+//! read from any other thread without stopping it. Each publication builds a
+//! new [`MarketSnapshot`] and swaps it in whole, so a reader always sees
+//! every book as of the same moment and never waits on the engine.
+
+use crate::engine::MatchingEngine;
+use crate::utils::Side;
+use arc_swap::ArcSwap;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Levels per side kept in a [`BookSummary`], unless the publisher is given
+/// another depth.
+pub const DEFAULT_PUBLISHED_DEPTH: usize = 10;
+
+/// One instrument's book as of a publication.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BookSummary {
+    pub instrument: String,
+    pub halted: bool,
+    pub tick_size: Option<Decimal>,
+    pub lot_size: Option<Decimal>,
+    pub resting_orders: usize,
+    pub last_trade_price: Option<Decimal>,
+    /// Price and quantity of the top levels, best first.
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+}
+
+impl BookSummary {
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.first().map(|&(price, _)| price)
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.first().map(|&(price, _)| price)
+    }
+}
+
+/// Every book as of one publication.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MarketSnapshot {
+    /// Counts publications from 1; 0 before the first.
+    pub version: u64,
+    /// Engine time of the publication, in nanoseconds.
+    pub published_at: u64,
+    /// Sorted by instrument.
+    pub books: Vec<BookSummary>,
+}
+
+impl MarketSnapshot {
+    pub fn book(&self, instrument: &str) -> Option<&BookSummary> {
+        self.books.iter().find(|book| book.instrument == instrument)
+    }
+}
+
+/// The matching thread's end: builds and swaps in a new snapshot on each
+/// [`publish`](BookPublisher::publish).
+pub struct BookPublisher {
+    current: Arc<ArcSwap<MarketSnapshot>>,
+    depth: usize,
+}
+
+impl BookPublisher {
+    pub fn new(depth: usize) -> Self {
+        Self { current: Arc::new(ArcSwap::from_pointee(MarketSnapshot::default())), depth }
+    }
+
+    /// A handle for reading the latest snapshot from any thread.
+    pub fn reader(&self) -> BookReader {
+        BookReader { current: Arc::clone(&self.current) }
+    }
+
+    /// Publishes every book of `engine` as it stands.
+    pub fn publish(&mut self, engine: &MatchingEngine) {
+        let books = engine
+            .instruments()
+            .into_iter()
+            .filter_map(|instrument| {
+                let book = engine.order_book(&instrument)?;
+                Some(BookSummary {
+                    halted: engine.is_halted(&instrument),
+                    tick_size: book.config().tick_size,
+                    lot_size: book.config().lot_size,
+                    resting_orders: book.order_count(),
+                    last_trade_price: book.last_trade_price(),
+                    bids: book.depth(Side::Buy, self.depth),
+                    asks: book.depth(Side::Sell, self.depth),
+                    instrument,
+                })
+            })
+            .collect();
+        let version = self.current.load().version + 1;
+        self.current.store(Arc::new(MarketSnapshot { version, published_at: engine.now_nanos(), books }));
+    }
+}
+
+impl Default for BookPublisher {
+    fn default() -> Self {
+        Self::new(DEFAULT_PUBLISHED_DEPTH)
+    }
+}
+
+/// A reader's end. Cloning it is cheap, and loading never blocks the
+/// publisher.
+#[derive(Clone)]
+pub struct BookReader {
+    current: Arc<ArcSwap<MarketSnapshot>>,
+}
+
+impl BookReader {
+    /// The latest snapshot. It stays valid, unchanged, for as long as the
+    /// caller holds it.
+    pub fn load(&self) -> Arc<MarketSnapshot> {
+        self.current.load_full()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::order::Order;
+    use rust_decimal_macros::dec;
+    use std::thread;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_readers_keep_their_snapshot_while_newer_ones_are_published() {
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut publisher = BookPublisher::new(2);
+        let reader = publisher.reader();
+        assert_eq!(reader.load().version, 0);
+
+        for price in [dec!(101), dec!(102), dec!(103)] {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, price, dec!(5)), &mut logger).unwrap();
+        }
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99), dec!(3)), &mut logger).unwrap();
+        publisher.publish(&engine);
+        let first = reader.load();
+
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(5)), &mut logger).unwrap();
+        publisher.publish(&engine);
+
+        let book = first.book("SOFI").unwrap();
+        assert_eq!((first.version, book.resting_orders), (1, 4));
+        assert_eq!(book.asks, vec![(dec!(101), dec!(5)), (dec!(102), dec!(5))]);
+        let latest = thread::spawn(move || reader.load()).join().unwrap();
+        let book = latest.book("SOFI").unwrap();
+        assert_eq!((latest.version, book.best_ask(), book.best_bid(), book.last_trade_price), (2, Some(dec!(102)), Some(dec!(99)), Some(dec!(101))));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::accounting::AccountingReport;
+use crate::clearing::ClearingHouse;
+use crate::funding::FundingLedger;
+use crate::logging::LoggerStats;
+use crate::options::{print_exercises, Exercise, OptionExpiry};
+use crate::markouts::MarkoutTracker;
+use crate::market_quality::{print_market_quality, MarketQualityTracker};
+use crate::memory::{print_phases, PhaseMemory};
+use crate::orderbook::LevelStats;
+use crate::positions::{ClientPnl, PositionLedger};
+use crate::simulation::SimulationError;
+use crate::stops::{print_stop_cascades, StopCascade};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// What happened to a single simulation operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationOutcome {
+    /// The order was accepted by the engine. `filled_quantity` is the part of
+    /// the incoming order executed immediately across `trades` trades.
+    Accepted { order_id: Uuid, quantity: Decimal, filled_quantity: Decimal, trades: usize },
+    /// The engine refused the order.
+    Rejected { order_id: Uuid, reason: String },
+    Canceled { order_id: Uuid },
+    /// The cancel target was not resting, usually because it already filled.
+    CancelFailed { order_id: Uuid },
+    /// The client had already used the client order id today; `order_id` is
+    /// the original order, which was left as it was.
+    Duplicate { order_id: Uuid, client_order_id: String },
+    /// The order was held by a speed bump or for a batch auction. It gets its
+    /// own `Accepted` outcome once released and matched.
+    Delayed { order_id: Uuid },
+    /// A batch auction ran. Its orders are recorded as `Accepted` with no
+    /// trades of their own; the auction's trades are counted here.
+    Auction { instrument: String, price: Option<Decimal>, quantity: Decimal, trades: usize },
+    /// A resting good-till-date order reached its expiry time.
+    Expired { order_id: Uuid },
+    /// A resting order was canceled once its `cancel_after` lifetime ran
+    /// out. Not an operation.
+    TimedOut { order_id: Uuid },
+    /// The stop order was accepted and waits for its stop price.
+    Stopped { order_id: Uuid },
+    /// A trade reached the stop price of a waiting stop order, which was then
+    /// matched. Not an operation of its own; its trades count as fills.
+    StopTriggered { order_id: Uuid, quantity: Decimal, filled_quantity: Decimal, trades: usize },
+    /// The engine sent a market order closing a position of an account
+    /// below maintenance margin. Not an operation; its trades count as fills.
+    Liquidated { order_id: Uuid, quantity: Decimal, filled_quantity: Decimal, trades: usize },
+    /// An earlier trade was busted through the admin API. Not an operation;
+    /// it takes the trade and its quantity back out of the totals.
+    Busted { trade_id: Uuid, quantity: Decimal },
+}
+
+/// Aggregated results of a simulation run.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub outcomes: Vec<OperationOutcome>,
+    pub accounting: AccountingReport,
+    pub levels: LevelStats,
+    /// Fills of client orders, as they happen.
+    pub positions: PositionLedger,
+    /// Client fills netted for settlement at the end of the session.
+    pub clearing: ClearingHouse,
+    /// Funding rounds of perpetuals and what each client paid or received.
+    pub funding: FundingLedger,
+    /// Options that expired during the run, and the exercises and
+    /// assignments of the clients holding them.
+    pub option_expiries: Vec<OptionExpiry>,
+    pub exercises: Vec<Exercise>,
+    /// Clients ranked by P&L, marked when the run finished.
+    pub leaderboard: Vec<ClientPnl>,
+    /// Mid moves after each maker fill, by client.
+    pub markouts: MarkoutTracker,
+    /// Spread, depth, trade-throughs and volatility per instrument.
+    pub market_quality: MarketQualityTracker,
+    /// Stop cascades in the order they happened, copied from the engine when
+    /// the run finished.
+    pub stop_cascades: Vec<StopCascade>,
+    /// Filled in by the caller, which sees the phases outside the simulation.
+    pub memory: Vec<PhaseMemory>,
+    /// What each async logger's background thread handled, filled in by the
+    /// caller once the logger is finalized.
+    pub loggers: Vec<LoggerStats>,
+    /// Engine errors and malformed rows met during the run, in order.
+    pub errors: Vec<SimulationError>,
+}
+
+impl RunReport {
+    const MAX_PRINTED_ERRORS: usize = 10;
+
+    pub fn record(&mut self, outcome: OperationOutcome) {
+        self.outcomes.push(outcome);
+    }
+
+    /// Operations processed. Delayed orders count once, when released, and
+    /// auctions, expiries, timed cancels, triggered stops, liquidations and
+    /// busts are not operations.
+    pub fn operations(&self) -> usize {
+        self.outcomes.len() - self.delayed() - self.auctions() - self.expired() - self.timed_out() - self.stops_triggered() - self.liquidations() - self.busts()
+    }
+
+    pub fn accepted(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Accepted { .. }))
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Rejected { .. }))
+    }
+
+    pub fn canceled(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Canceled { .. }))
+    }
+
+    pub fn cancel_failed(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::CancelFailed { .. }))
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Duplicate { .. }))
+    }
+
+    pub fn delayed(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Delayed { .. }))
+    }
+
+    pub fn auctions(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Auction { .. }))
+    }
+
+    pub fn expired(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Expired { .. }))
+    }
+
+    pub fn timed_out(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::TimedOut { .. }))
+    }
+
+    pub fn stopped(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Stopped { .. }))
+    }
+
+    pub fn stops_triggered(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::StopTriggered { .. }))
+    }
+
+    pub fn liquidations(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Liquidated { .. }))
+    }
+
+    pub fn busts(&self) -> usize {
+        self.count(|o| matches!(o, OperationOutcome::Busted { .. }))
+    }
+
+    /// Trades executed, less those busted.
+    pub fn trades(&self) -> usize {
+        let executed: usize = self
+            .outcomes
+            .iter()
+            .map(|o| match o {
+                OperationOutcome::Accepted { trades, .. }
+                | OperationOutcome::Auction { trades, .. }
+                | OperationOutcome::StopTriggered { trades, .. }
+                | OperationOutcome::Liquidated { trades, .. } => *trades,
+                _ => 0,
+            })
+            .sum();
+        executed.saturating_sub(self.busts())
+    }
+
+    pub fn submitted_quantity(&self) -> Decimal {
+        self.outcomes
+            .iter()
+            .map(|o| match o {
+                OperationOutcome::Accepted { quantity, .. }
+                | OperationOutcome::StopTriggered { quantity, .. }
+                | OperationOutcome::Liquidated { quantity, .. } => *quantity,
+                _ => Decimal::ZERO,
+            })
+            .sum()
+    }
+
+    /// Quantity executed on arrival by accepted orders, by stops when
+    /// triggered and by liquidations (taker side only), less busted trades.
+    pub fn filled_quantity(&self) -> Decimal {
+        self.outcomes
+            .iter()
+            .map(|o| match o {
+                OperationOutcome::Accepted { filled_quantity, .. }
+                | OperationOutcome::StopTriggered { filled_quantity, .. }
+                | OperationOutcome::Liquidated { filled_quantity, .. } => *filled_quantity,
+                OperationOutcome::Busted { quantity, .. } => -*quantity,
+                _ => Decimal::ZERO,
+            })
+            .sum()
+    }
+
+    /// Fraction of accepted quantity that executed on arrival.
+    pub fn fill_rate(&self) -> f64 {
+        let submitted = self.submitted_quantity();
+        if submitted.is_zero() {
+            return 0.0;
+        }
+        (self.filled_quantity() / submitted).try_into().unwrap_or(0.0)
+    }
+
+    fn count(&self, predicate: impl Fn(&OperationOutcome) -> bool) -> usize {
+        self.outcomes.iter().filter(|o| predicate(o)).count()
+    }
+
+    pub fn print_summary(&self) {
+        println!("\n--- Run Report ---");
+        println!("{:<25} {}", "Operations:", self.operations());
+        println!("{:<25} {}", "Orders accepted:", self.accepted());
+        println!("{:<25} {}", "Orders rejected:", self.rejected());
+        println!("{:<25} {}", "Cancels succeeded:", self.canceled());
+        println!("{:<25} {}", "Cancels failed:", self.cancel_failed());
+        println!("{:<25} {}", "Duplicate submissions:", self.duplicates());
+        println!("{:<25} {}", "Orders delayed:", self.delayed());
+        println!("{:<25} {}", "Batch auctions:", self.auctions());
+        println!("{:<25} {}", "Orders expired:", self.expired());
+        println!("{:<25} {}", "Orders timed out:", self.timed_out());
+        println!("{:<25} {} ({} triggered)", "Stop orders:", self.stopped(), self.stops_triggered());
+        println!("{:<25} {}", "Liquidation orders:", self.liquidations());
+        println!("{:<25} {} ({} busted)", "Trades:", self.trades(), self.busts());
+        println!("{:<25} {}", "Submitted quantity:", self.submitted_quantity());
+        println!("{:<25} {}", "Filled on arrival:", self.filled_quantity());
+        println!("{:<25} {:.2}%", "Fill rate:", self.fill_rate() * 100.0);
+        println!("{:<25} {}", "Price levels created:", self.levels.created);
+        println!("{:<25} {}", "Emptied levels reused:", self.levels.reused);
+        println!("{:<25} {} ({} levels)", "Level compactions:", self.levels.compactions, self.levels.compacted);
+        println!("------------------");
+        self.print_errors();
+        self.accounting.print_summary();
+        print_market_quality(&self.market_quality.summary());
+        print_stop_cascades(&self.stop_cascades);
+        self.funding.print();
+        print_exercises(&self.option_expiries, &self.exercises);
+    }
+
+    fn print_errors(&self) {
+        if self.errors.is_empty() {
+            return;
+        }
+        eprintln!("{} operations failed:", self.errors.len());
+        for error in self.errors.iter().take(Self::MAX_PRINTED_ERRORS) {
+            eprintln!("  {}", error);
+        }
+        if self.errors.len() > Self::MAX_PRINTED_ERRORS {
+            eprintln!("  ... and {} more", self.errors.len() - Self::MAX_PRINTED_ERRORS);
+        }
+    }
+
+    pub fn print_memory(&self) {
+        if !self.memory.is_empty() {
+            print_phases(&self.memory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_report_aggregates() {
+        let mut report = RunReport::default();
+        report.record(OperationOutcome::Accepted { order_id: Uuid::new_v4(), quantity: dec!(10), filled_quantity: dec!(5), trades: 2 });
+        report.record(OperationOutcome::Accepted { order_id: Uuid::new_v4(), quantity: dec!(10), filled_quantity: dec!(0), trades: 0 });
+        report.record(OperationOutcome::Rejected { order_id: Uuid::new_v4(), reason: "bad".to_string() });
+        report.record(OperationOutcome::Canceled { order_id: Uuid::new_v4() });
+        report.record(OperationOutcome::Duplicate { order_id: Uuid::new_v4(), client_order_id: "A-1".to_string() });
+        report.record(OperationOutcome::Delayed { order_id: Uuid::new_v4() });
+        report.record(OperationOutcome::Auction { instrument: "SOFI".to_string(), price: Some(dec!(10)), quantity: dec!(3), trades: 1 });
+        report.record(OperationOutcome::Expired { order_id: Uuid::new_v4() });
+        report.record(OperationOutcome::TimedOut { order_id: Uuid::new_v4() });
+        report.record(OperationOutcome::Stopped { order_id: Uuid::new_v4() });
+        report.record(OperationOutcome::StopTriggered { order_id: Uuid::new_v4(), quantity: dec!(4), filled_quantity: dec!(4), trades: 1 });
+        report.record(OperationOutcome::Liquidated { order_id: Uuid::new_v4(), quantity: dec!(6), filled_quantity: dec!(3), trades: 2 });
+        report.record(OperationOutcome::Busted { trade_id: Uuid::new_v4(), quantity: dec!(2) });
+
+        assert_eq!(report.accepted(), 2);
+        assert_eq!(report.rejected(), 1);
+        assert_eq!(report.canceled(), 1);
+        assert_eq!(report.duplicates(), 1);
+        assert_eq!(report.delayed(), 1);
+        assert_eq!(report.operations(), 6);
+        assert_eq!(report.auctions(), 1);
+        assert_eq!((report.expired(), report.timed_out()), (1, 1));
+        assert_eq!((report.stopped(), report.stops_triggered(), report.liquidations()), (1, 1, 1));
+        assert_eq!((report.trades(), report.busts()), (5, 1));
+        assert_eq!(report.filled_quantity(), dec!(10));
+        assert!((report.fill_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_fill_rate_of_empty_report_is_zero() {
+        assert_eq!(RunReport::default().fill_rate(), 0.0);
+    }
+}
@@ -0,0 +1,466 @@
+//! Shadow mode: every order the engine matches is replayed through a plain
+//! `BTreeMap` book, the layout the engine used before its books moved to
+//! fixed-point records in a slab. The two must print the same trades and
+//! hold the same orders in the same queue places, so the first operation
+//! where they disagree points at a bug in one of them.
+
+use crate::config::{InstrumentConfig, MatchingAlgorithm};
+use crate::fixed::Fixed;
+use crate::order::{Order, TimeInForce};
+use crate::orderbook::OrderBook;
+use crate::trade::Trade;
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use uuid::Uuid;
+
+/// A hash of a book's resting orders as side, price, order id and remaining
+/// quantity, bids then asks, each side best level first and each level in
+/// queue order. Books holding the same orders in the same places hash
+/// alike, however they store them.
+pub fn checksum(orders: impl IntoIterator<Item = (Side, Fixed, Uuid, Fixed)>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (side, price, order_id, remaining) in orders {
+        (side as u8, price, order_id, remaining).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// A trade as both books must print it. Trade ids and timestamps come from
+/// the engine's generators, not the book, so they are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub buy_order_id: Uuid,
+    pub sell_order_id: Uuid,
+}
+
+impl From<&Trade> for Fill {
+    fn from(trade: &Trade) -> Self {
+        Fill { price: trade.price, quantity: trade.quantity, buy_order_id: trade.buy_order_id, sell_order_id: trade.sell_order_id }
+    }
+}
+
+impl fmt::Display for Fill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} @ {} ({} / {})", self.quantity, self.price, self.buy_order_id, self.sell_order_id)
+    }
+}
+
+/// A price-time priority book keeping whole `Order` values: a queue of ids
+/// per price level and the orders in a map. It knows limit and market
+/// orders with their time in force and nothing else.
+#[derive(Debug, Default)]
+pub struct LegacyBook {
+    bids: BTreeMap<Decimal, VecDeque<Uuid>>,
+    asks: BTreeMap<Decimal, VecDeque<Uuid>>,
+    orders: HashMap<Uuid, Order>,
+    /// Arrival number of the latest order, numbered as the engine's book
+    /// numbers them.
+    arrival: u64,
+}
+
+impl LegacyBook {
+    /// Matches `order` and rests what is left of it if it rests, returning
+    /// its fills in the order they happened.
+    pub fn add_order(&mut self, mut order: Order) -> Vec<Fill> {
+        self.arrival += 1;
+        order.sequence = self.arrival;
+        let killed = order.attributes.time_in_force() == TimeInForce::FillOrKill
+            && self.fillable_quantity(&order) < order.remaining_quantity;
+        let fills = if killed { Vec::new() } else { self.match_order(&mut order) };
+        if !order.is_filled() && order.rests()
+            && let Some(price) = order.price {
+            self.side_mut(order.side).entry(price).or_default().push_back(order.order_id);
+            self.orders.insert(order.order_id, order);
+        }
+        fills
+    }
+
+    /// Rests `order` without matching, behind the orders of its level that
+    /// arrived before it. An order without an arrival number queues last.
+    pub fn restore(&mut self, mut order: Order) {
+        let Some(price) = order.price else { return };
+        if order.sequence == 0 {
+            self.arrival += 1;
+            order.sequence = self.arrival;
+        } else {
+            self.arrival = self.arrival.max(order.sequence);
+        }
+        let book_side = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        let queue = book_side.entry(price).or_default();
+        let position = queue.iter().position(|id| self.orders[id].sequence > order.sequence).unwrap_or(queue.len());
+        queue.insert(position, order.order_id);
+        self.orders.insert(order.order_id, order);
+    }
+
+    pub fn cancel(&mut self, order_id: &Uuid) -> Option<Order> {
+        let order = self.orders.remove(order_id)?;
+        let book_side = self.side_mut(order.side);
+        if let Some(price) = order.price
+            && let Some(queue) = book_side.get_mut(&price) {
+            queue.retain(|id| id != order_id);
+            if queue.is_empty() {
+                book_side.remove(&price);
+            }
+        }
+        Some(order)
+    }
+
+    pub fn checksum(&self) -> u64 {
+        let bids = self.bids.iter().rev().map(|level| (Side::Buy, level));
+        let asks = self.asks.iter().map(|level| (Side::Sell, level));
+        checksum(bids.chain(asks).flat_map(|(side, (&price, queue))| {
+            queue.iter().map(move |id| {
+                let order = &self.orders[id];
+                (side, Fixed::from_validated(price), *id, Fixed::from_validated(order.remaining_quantity))
+            })
+        }))
+    }
+
+    fn side(&self, side: Side) -> &BTreeMap<Decimal, VecDeque<Uuid>> {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Decimal, VecDeque<Uuid>> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    /// Prices of the opposite side that `incoming` can trade at, best first.
+    fn matchable_prices(&self, incoming: &Order) -> Vec<Decimal> {
+        let crosses = |price: &Decimal| match (incoming.side, incoming.price) {
+            (_, None) => true,
+            (Side::Buy, Some(limit)) => *price <= limit,
+            (Side::Sell, Some(limit)) => *price >= limit,
+        };
+        let opposite = self.side(incoming.side.opposite());
+        let prices: Box<dyn Iterator<Item = &Decimal>> = match incoming.side {
+            Side::Buy => Box::new(opposite.keys()),
+            Side::Sell => Box::new(opposite.keys().rev()),
+        };
+        prices.take_while(|price| crosses(price)).copied().collect()
+    }
+
+    fn fillable_quantity(&self, incoming: &Order) -> Decimal {
+        let opposite = self.side(incoming.side.opposite());
+        self.matchable_prices(incoming)
+            .iter()
+            .flat_map(|price| &opposite[price])
+            .map(|id| self.orders[id].remaining_quantity)
+            .sum()
+    }
+
+    fn match_order(&mut self, incoming: &mut Order) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        for price in self.matchable_prices(incoming) {
+            let opposite = match incoming.side {
+                Side::Buy => &mut self.asks,
+                Side::Sell => &mut self.bids,
+            };
+            let queue = opposite.get_mut(&price).expect("matchable prices have a level");
+            while !incoming.is_filled()
+                && let Some(&resting_id) = queue.front() {
+                let resting = self.orders.get_mut(&resting_id).expect("queued orders are in the book");
+                let quantity = incoming.remaining_quantity.min(resting.remaining_quantity);
+                incoming.fill(quantity);
+                resting.fill(quantity);
+                let (buy_order_id, sell_order_id) = match incoming.side {
+                    Side::Buy => (incoming.order_id, resting_id),
+                    Side::Sell => (resting_id, incoming.order_id),
+                };
+                fills.push(Fill { price, quantity, buy_order_id, sell_order_id });
+                if resting.is_filled() {
+                    queue.pop_front();
+                    self.orders.remove(&resting_id);
+                }
+            }
+            if queue.is_empty() {
+                opposite.remove(&price);
+            }
+            if incoming.is_filled() {
+                break;
+            }
+        }
+        fills
+    }
+}
+
+/// Why an instrument's two books stopped agreeing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Trades { expected: Vec<Fill>, actual: Vec<Fill> },
+    /// The resting orders differ. They last hashed alike after message
+    /// `agreed_at`, 0 if they were never compared before.
+    Checksum { expected: u64, actual: u64, agreed_at: u64 },
+}
+
+/// The first operation after which the books disagreed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Inbound message number, as counted by
+    /// [`MatchingEngine::messages_received`](crate::engine::MatchingEngine::messages_received).
+    pub message: u64,
+    pub instrument: String,
+    /// The order matched or canceled, unless the books were compared at
+    /// the end of the run.
+    pub order_id: Option<Uuid>,
+    pub mismatch: Mismatch,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "message {} ({}", self.message, self.instrument)?;
+        if let Some(order_id) = self.order_id {
+            write!(f, " order {}", order_id)?;
+        }
+        f.write_str("): ")?;
+        match &self.mismatch {
+            Mismatch::Trades { expected, actual } => {
+                let list = |fills: &[Fill]| fills.iter().map(Fill::to_string).collect::<Vec<_>>().join(", ");
+                write!(f, "legacy book traded [{}], engine traded [{}]", list(expected), list(actual))
+            }
+            Mismatch::Checksum { expected, actual, agreed_at } => write!(
+                f,
+                "resting orders differ, checksum {:016x} in the legacy book, {:016x} in the engine; they last agreed after message {}",
+                expected, actual, agreed_at
+            ),
+        }
+    }
+}
+
+/// The legacy book of one instrument, with how far it has been checked.
+#[derive(Debug, Default)]
+struct Shadowed {
+    book: LegacyBook,
+    operations: u64,
+    agreed_at: u64,
+}
+
+/// A legacy book per instrument, run in the engine's shadow. Trades are
+/// compared on every order. Hashing the resting orders walks both books, so
+/// it is done every `checksum_every` operations of an instrument and when
+/// the run ends. Instruments using anything the legacy book never had
+/// (pegs, discretion, expiry, order flags, price bands, batch auctions,
+/// lottery matching or option expiry) are dropped from the comparison when
+/// that first shows up. Comparison stops at the first divergence.
+#[derive(Debug)]
+pub struct ShadowBooks {
+    books: HashMap<String, Shadowed>,
+    unshadowed: BTreeMap<String, &'static str>,
+    checksum_every: u64,
+    compared: u64,
+    divergence: Option<Divergence>,
+}
+
+impl ShadowBooks {
+    /// Panics if `checksum_every` is 0.
+    pub fn new(checksum_every: u64) -> Self {
+        assert!(checksum_every > 0, "books must be hashed every so many operations");
+        ShadowBooks { books: HashMap::new(), unshadowed: BTreeMap::new(), checksum_every, compared: 0, divergence: None }
+    }
+
+    /// Replays an order the engine just matched into `trades`, leaving
+    /// `book` as it is now.
+    pub fn match_order(&mut self, message: u64, order: Order, trades: &[Trade], book: &OrderBook) {
+        if !self.follows(&order.instrument) {
+            return;
+        }
+        if let Some(reason) = unsupported_config(book.config()).or_else(|| unsupported_order(&order)) {
+            self.books.remove(&order.instrument);
+            self.unshadowed.insert(order.instrument, reason);
+            return;
+        }
+        let order_id = order.order_id;
+        let shadowed = self.books.entry(order.instrument.clone()).or_default();
+        let expected = shadowed.book.add_order(order);
+        let actual: Vec<Fill> = trades.iter().map(Fill::from).collect();
+        if expected != actual {
+            self.diverge(message, book.instrument(), Some(order_id), Mismatch::Trades { expected, actual });
+            return;
+        }
+        self.compared += 1;
+        self.after_operation(message, Some(order_id), book);
+    }
+
+    /// Replays cancels the engine just took out of `book`, together as
+    /// one operation.
+    pub fn cancel(&mut self, message: u64, order_ids: &[Uuid], book: &OrderBook) {
+        if !self.follows(book.instrument()) || order_ids.is_empty() {
+            return;
+        }
+        let shadowed = self.books.entry(book.instrument().to_string()).or_default();
+        for order_id in order_ids {
+            shadowed.book.cancel(order_id);
+        }
+        self.compared += order_ids.len() as u64;
+        self.after_operation(message, order_ids.last().copied(), book);
+    }
+
+    /// Adds an order loaded from a snapshot.
+    pub fn restore(&mut self, order: &Order) {
+        if self.follows(&order.instrument) {
+            self.books.entry(order.instrument.clone()).or_default().book.restore(order.clone());
+        }
+    }
+
+    /// Hashes every shadowed book against its engine book now, as at the
+    /// end of a run.
+    pub fn verify_all<'a>(&mut self, message: u64, books: impl IntoIterator<Item = &'a OrderBook>) {
+        for book in books {
+            if self.follows(book.instrument()) && self.books.contains_key(book.instrument()) {
+                self.verify(message, None, book);
+            }
+        }
+    }
+
+    /// Operations both books went through with the same trades.
+    pub fn compared(&self) -> u64 {
+        self.compared
+    }
+
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Instruments left out of the comparison, with what the legacy book
+    /// could not replay.
+    pub fn unshadowed(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.unshadowed.iter().map(|(instrument, reason)| (instrument.as_str(), *reason))
+    }
+
+    fn follows(&self, instrument: &str) -> bool {
+        self.divergence.is_none() && !self.unshadowed.contains_key(instrument)
+    }
+
+    fn after_operation(&mut self, message: u64, order_id: Option<Uuid>, book: &OrderBook) {
+        let shadowed = self.books.get_mut(book.instrument()).expect("operations are replayed into a shadowed book");
+        shadowed.operations += 1;
+        if shadowed.operations.is_multiple_of(self.checksum_every) {
+            self.verify(message, order_id, book);
+        }
+    }
+
+    fn verify(&mut self, message: u64, order_id: Option<Uuid>, book: &OrderBook) {
+        let shadowed = self.books.get_mut(book.instrument()).expect("only shadowed books are verified");
+        let (expected, actual) = (shadowed.book.checksum(), book.checksum());
+        if expected == actual {
+            shadowed.agreed_at = message;
+        } else {
+            let agreed_at = shadowed.agreed_at;
+            self.diverge(message, book.instrument(), order_id, Mismatch::Checksum { expected, actual, agreed_at });
+        }
+    }
+
+    fn diverge(&mut self, message: u64, instrument: &str, order_id: Option<Uuid>, mismatch: Mismatch) {
+        let divergence = Divergence { message, instrument: instrument.to_string(), order_id, mismatch };
+        eprintln!("Shadow book diverged at {}", divergence);
+        self.divergence = Some(divergence);
+        self.books.clear();
+    }
+}
+
+fn unsupported_config(config: &InstrumentConfig) -> Option<&'static str> {
+    if config.matching != MatchingAlgorithm::Fifo {
+        Some("lottery matching")
+    } else if config.price_band.is_some() {
+        Some("price bands")
+    } else if config.batch_interval.is_some() {
+        Some("batch auctions")
+    } else if config.option.is_some() {
+        Some("option expiry")
+    } else {
+        None
+    }
+}
+
+fn unsupported_order(order: &Order) -> Option<&'static str> {
+    if order.peg.is_some() {
+        Some("pegged orders")
+    } else if order.discretion.is_some() {
+        Some("discretionary orders")
+    } else if order.expire_at.is_some() {
+        Some("good-till-date orders")
+    } else if !order.attributes.with_time_in_force(TimeInForce::Day).is_default() {
+        Some("order attributes")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderAttributes;
+    use rust_decimal_macros::dec;
+
+    fn limit(side: Side, price: Decimal, quantity: Decimal) -> Order {
+        Order::new_limit(Uuid::new_v4(), "TEST-STOCK".to_string(), side, price, quantity)
+    }
+
+    /// Sends `order` through both books, the engine's first.
+    fn replay(shadow: &mut ShadowBooks, book: &mut OrderBook, message: u64, order: Order) {
+        let (trades, _, _) = book.add_order(order.clone());
+        shadow.match_order(message, order, &trades, book);
+    }
+
+    #[test]
+    fn test_legacy_book_agrees_with_the_engine_book() {
+        let mut shadow = ShadowBooks::new(1);
+        let mut book = OrderBook::new("TEST-STOCK".to_string());
+        let resting = limit(Side::Sell, dec!(101), dec!(5));
+        let resting_id = resting.order_id;
+        replay(&mut shadow, &mut book, 1, limit(Side::Sell, dec!(100), dec!(3)));
+        replay(&mut shadow, &mut book, 2, resting);
+        replay(&mut shadow, &mut book, 3, limit(Side::Sell, dec!(101), dec!(2)));
+        replay(&mut shadow, &mut book, 4, limit(Side::Buy, dec!(101), dec!(6)));
+        let fok = limit(Side::Buy, dec!(101), dec!(9)).with_attributes(OrderAttributes::default().with_time_in_force(TimeInForce::FillOrKill));
+        replay(&mut shadow, &mut book, 5, fok);
+        replay(&mut shadow, &mut book, 6, Order::new_market(Uuid::new_v4(), "TEST-STOCK".to_string(), Side::Buy, dec!(1)));
+        book.cancel_order(&resting_id).unwrap();
+        shadow.cancel(7, &[resting_id], &book);
+
+        assert_eq!(shadow.divergence(), None);
+        assert_eq!(shadow.compared(), 7);
+
+        replay(&mut shadow, &mut book, 8, limit(Side::Buy, dec!(99), dec!(1)).with_attributes(OrderAttributes::default().post_only()));
+        assert_eq!(shadow.unshadowed().collect::<Vec<_>>(), [("TEST-STOCK", "order attributes")]);
+        assert_eq!(shadow.compared(), 7);
+    }
+
+    #[test]
+    fn test_reports_the_first_divergence() {
+        // An order only the engine's book sees, then one that trades with it.
+        let diverged = |last: Order| {
+            let mut shadow = ShadowBooks::new(10);
+            let mut book = OrderBook::new("TEST-STOCK".to_string());
+            replay(&mut shadow, &mut book, 1, limit(Side::Sell, dec!(100), dec!(3)));
+            book.add_order(limit(Side::Sell, dec!(99), dec!(1)));
+            replay(&mut shadow, &mut book, 2, last);
+            shadow.verify_all(2, [&book]);
+            shadow.divergence().cloned().unwrap()
+        };
+
+        let divergence = diverged(limit(Side::Buy, dec!(100), dec!(1)));
+        assert_eq!(divergence.message, 2);
+        let Mismatch::Trades { expected, actual } = divergence.mismatch else { panic!("expected a trade mismatch") };
+        assert_eq!((expected[0].price, actual[0].price), (dec!(100), dec!(99)));
+
+        // Nothing trades, so only hashing the books finds it.
+        let divergence = diverged(limit(Side::Sell, dec!(102), dec!(1)));
+        assert_eq!((divergence.message, divergence.order_id), (2, None));
+        assert!(matches!(divergence.mismatch, Mismatch::Checksum { agreed_at: 0, .. }));
+    }
+}
@@ -1,109 +1,501 @@
-use crate::engine::{MatchingEngine};
-use crate::order::Order;
-use crate::utils::Side;
+use crate::admin::AdminServer;
+use crate::checkpoint::Checkpointer;
+use crate::control::SimulationControl;
+use crate::engine::{AuctionResult, MatchingEngine, ReleasedOrder, Submission};
+use crate::funding::FundingRound;
+use crate::governor::SpeedGovernor;
+use crate::options::OptionExpiry;
 use std::error::Error;
-use uuid::Uuid;
 use crate::logging::logger_trait::SimLogger;
-use crate::utils::Operation;
-use std::time::Instant;
+use crate::margin::Liquidation;
+use crate::operation::{OperationSource, SimOp};
+use crate::report::{OperationOutcome, RunReport};
+use crate::trade::Trade;
+use crate::utils::Side;
+use rust_decimal::Decimal;
+use std::fmt;
+use uuid::Uuid;
+
+/// An engine or parse error raised while running an operation, with where in
+/// the run it happened.
+#[derive(Debug)]
+pub struct SimulationError {
+    /// 1-based number of the operation in the run.
+    pub operation: usize,
+    /// The operation as an operations CSV row, or the raw fields of a row
+    /// that did not parse.
+    pub row: String,
+    pub order_id: Option<Uuid>,
+    pub source: Box<dyn Error + Send + Sync>,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation {}", self.operation)?;
+        if let Some(order_id) = self.order_id {
+            write!(f, " (order {})", order_id)?;
+        }
+        write!(f, ": {}", self.source)?;
+        if !self.row.is_empty() {
+            write!(f, " [{}]", self.row)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for SimulationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Under `fail_fast`, ends the run with the first error the report collected.
+fn check_errors(report: &mut RunReport, fail_fast: bool) -> Result<(), Box<dyn Error>> {
+    match report.errors.pop() {
+        Some(error) if fail_fast => Err(Box::new(error)),
+        Some(error) => {
+            report.errors.push(error);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
 
 pub fn run_simulation(
     logger: &mut Box<dyn SimLogger>,
     engine: &mut MatchingEngine,
-    operations: &[Operation],
+    operations: &[SimOp],
+    latencies: &mut Vec<(u128, u128)>,
+) -> Result<RunReport, Box<dyn Error>> {
+    run_simulation_with(logger, engine, operations, latencies, None, false)
+}
+
+/// `run_simulation` with optional interactive control between operations.
+/// Errors are collected in the report, or with `fail_fast` the first one
+/// ends the run.
+pub fn run_simulation_with(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    operations: &[SimOp],
+    latencies: &mut Vec<(u128, u128)>,
+    mut control: Option<&mut SimulationControl>,
+    fail_fast: bool,
+) -> Result<RunReport, Box<dyn Error>> {
+    let mut report = RunReport::default();
+
+    for (index, operation) in operations.iter().enumerate() {
+        if let Some(control) = control.as_deref_mut() {
+            control.before_operation(index, engine);
+        }
+        let trades = process_operation(logger, engine, index, operation, latencies, &mut report);
+        if let Some(control) = control.as_deref_mut() {
+            control.after_operation(index, operation, &trades, engine);
+        }
+        check_errors(&mut report, fail_fast)?;
+    }
+
+    finish_run(logger, engine, report)
+}
+
+/// `run_simulation_with` that holds each operation back until `governor`
+/// allows it, so the run goes at its target rate whatever the engine could
+/// manage. The wait is not part of the measured latencies.
+pub fn run_governed(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    operations: &[SimOp],
+    latencies: &mut Vec<(u128, u128)>,
+    mut control: Option<&mut SimulationControl>,
+    governor: &mut SpeedGovernor,
+    fail_fast: bool,
+) -> Result<RunReport, Box<dyn Error>> {
+    let mut report = RunReport::default();
+
+    for (index, operation) in operations.iter().enumerate() {
+        if let Some(control) = control.as_deref_mut() {
+            control.before_operation(index, engine);
+        }
+        governor.wait();
+        let trades = process_operation(logger, engine, index, operation, latencies, &mut report);
+        if let Some(control) = control.as_deref_mut() {
+            control.after_operation(index, operation, &trades, engine);
+        }
+        check_errors(&mut report, fail_fast)?;
+    }
+
+    finish_run(logger, engine, report)
+}
+
+/// `run_simulation_with` that checkpoints through `checkpointer`, skipping
+/// the operations its last checkpoint already covers.
+pub fn run_checkpointed(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    operations: &[SimOp],
+    latencies: &mut Vec<(u128, u128)>,
+    mut control: Option<&mut SimulationControl>,
+    checkpointer: &mut Checkpointer,
+    fail_fast: bool,
+) -> Result<RunReport, Box<dyn Error>> {
+    let mut report = RunReport::default();
+
+    for (index, operation) in operations.iter().enumerate().skip(checkpointer.completed()) {
+        if let Some(control) = control.as_deref_mut() {
+            control.before_operation(index, engine);
+        }
+        let trades = process_operation(logger, engine, index, operation, latencies, &mut report);
+        if let Some(control) = control.as_deref_mut() {
+            control.after_operation(index, operation, &trades, engine);
+        }
+        check_errors(&mut report, fail_fast)?;
+        checkpointer.after_operation(index + 1, engine, latencies)?;
+    }
+
+    finish_run(logger, engine, report)
+}
+
+/// Like `run_simulation`, but pulls operations from `source` until it is
+/// exhausted. Malformed rows are collected in the report and skipped.
+pub fn run_source(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    source: &mut dyn OperationSource,
+    latencies: &mut Vec<(u128, u128)>,
+    control: Option<&mut SimulationControl>,
+) -> Result<RunReport, Box<dyn Error>> {
+    run_server(logger, engine, source, latencies, control, None, false)
+}
+
+/// `run_source` that also applies the requests queued on an admin API before
+/// each operation. With `fail_fast`, a malformed row or an engine error ends
+/// the run.
+pub fn run_server(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    source: &mut dyn OperationSource,
+    latencies: &mut Vec<(u128, u128)>,
+    mut control: Option<&mut SimulationControl>,
+    mut admin: Option<&mut AdminServer>,
+    fail_fast: bool,
+) -> Result<RunReport, Box<dyn Error>> {
+    let mut report = RunReport::default();
+    let mut index = 0;
+
+    while let Some(parsed) = source.next_operation() {
+        if let Some(admin) = admin.as_deref_mut() {
+            admin.apply_pending(engine);
+        }
+        match parsed {
+            Ok(operation) => {
+                if let Some(control) = control.as_deref_mut() {
+                    control.before_operation(index, engine);
+                }
+                let trades = process_operation(logger, engine, index, &operation, latencies, &mut report);
+                if let Some(control) = control.as_deref_mut() {
+                    control.after_operation(index, &operation, &trades, engine);
+                }
+                if let Some(admin) = admin.as_deref_mut() {
+                    admin.publish(engine);
+                }
+                index += 1;
+            }
+            Err(e) => report.errors.push(SimulationError {
+                operation: index + 1,
+                row: e.fields.clone(),
+                order_id: None,
+                source: Box::new(e),
+            }),
+        }
+        check_errors(&mut report, fail_fast)?;
+    }
+
+    finish_run(logger, engine, report)
+}
+
+/// Runs the operation at `index` of the run, after whatever came due before
+/// it.
+pub(crate) fn process_operation(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    index: usize,
+    operation: &SimOp,
     latencies: &mut Vec<(u128, u128)>,
-) -> Result<(), Box<dyn Error>> {
-    for operation in operations {
-        match operation.operation.as_str() {
-            "NEW" => {
-                let Some(id_str) = operation.order_to_cancel.as_ref() else {
-                    eprintln!(" -> Error: NEW operation requires an ID in the 'order_to_cancel' column.");
-                    continue;
-                };
-
-                let Ok(order_id) = Uuid::parse_str(id_str) else {
-                    eprintln!(" -> Error: Invalid UUID format for new order: '{}'", id_str);
-                    continue;
-                };
-
-                let side = match operation.side.as_deref() {
-                    Some("BUY") => Side::Buy,
-                    Some("SELL") => Side::Sell,
-                    _ => {
-                        eprintln!(" -> Error: NEW operation requires a valid SIDE.");
-                        continue;
-                    }
-                };
-                
-                let order = match operation.order_type.as_deref() {
-                    Some("LIMIT") => {
-                        let Some(price) = operation.price else {
-                            eprintln!(" -> Error: LIMIT order requires a valid PRICE.");
-                            continue;
-                        };
-                        Order::new_limit(
-                            order_id,
-                            operation.instrument.clone(),
-                            side,
-                            price,
-                            operation.quantity.unwrap_or_default(),
-                        )
-                    },
-                    Some("MARKET") => Order::new_market(
+    report: &mut RunReport,
+) -> Vec<Trade> {
+    report.markouts.advance(engine.now_nanos(), |instrument| mark_price(engine, instrument));
+    record_busts(logger, engine, report);
+    for order in engine.expire_orders(logger) {
+        report.record(OperationOutcome::Expired { order_id: order.order_id });
+    }
+    for order in engine.cancel_timed_out_orders(logger) {
+        report.record(OperationOutcome::TimedOut { order_id: order.order_id });
+    }
+    record_funding(engine.run_due_funding(logger), report);
+    record_expiries(engine.run_due_expiries(logger), report);
+    let mut trades = release_delayed(engine.release_delayed_orders(logger), report);
+    trades.extend(record_auctions(engine.run_due_auctions(logger), report));
+    trades.extend(apply_operation(logger, engine, index, operation, latencies, report));
+    trades.extend(record_triggered_stops(engine.take_triggered_stops(), report));
+    trades.extend(record_liquidations(engine.take_liquidations(), report));
+    record_fills(engine, &trades, report);
+    trades
+}
+
+/// Books `trades` to their clients' positions, queues their maker sides for
+/// markouts and samples the books they left behind.
+fn record_fills(engine: &MatchingEngine, trades: &[Trade], report: &mut RunReport) {
+    report.market_quality.record_trades(trades, engine);
+    report.market_quality.observe(engine);
+    let now = engine.now_nanos();
+    for trade in trades {
+        report.positions.record_trade(trade);
+        let positions = &report.positions;
+        report.clearing.record_trade(trade, |order_id| positions.owner(order_id));
+        let maker_order_id = match trade.taker_side {
+            Side::Buy => trade.sell_order_id,
+            Side::Sell => trade.buy_order_id,
+        };
+        let maker = report.positions.owner(&maker_order_id);
+        report.markouts.record_fill(trade, maker, mark_price(engine, &trade.instrument), now);
+    }
+}
+
+/// Logs the trades busted through the admin API since the last operation
+/// and reverses their fills in the positions and the clearing accounts.
+fn record_busts(logger: &mut Box<dyn SimLogger>, engine: &mut MatchingEngine, report: &mut RunReport) {
+    for trade in engine.take_busted_trades() {
+        logger.log_trade_bust(&trade);
+        let last_price = engine.order_book(&trade.instrument).and_then(|book| book.last_trade_price());
+        report.positions.bust(&trade, last_price);
+        let positions = &report.positions;
+        report.clearing.bust(&trade, |order_id| positions.owner(order_id));
+        report.record(OperationOutcome::Busted { trade_id: trade.trade_id, quantity: trade.quantity });
+    }
+}
+
+/// Books each funding round to the positions and settlement of the clients
+/// holding the perpetual.
+fn record_funding(rounds: Vec<FundingRound>, report: &mut RunReport) {
+    for round in rounds {
+        let payments = report.positions.apply_funding(&round);
+        for (client, amount) in &payments {
+            report.clearing.record_funding(client, &round.instrument, *amount);
+        }
+        report.funding.record(round, payments);
+    }
+}
+
+/// Records the orders option expiries removed, and exercises the clients'
+/// option positions into the underlying.
+fn record_expiries(expiries: Vec<OptionExpiry>, report: &mut RunReport) {
+    for expiry in expiries {
+        for order in &expiry.orders {
+            report.record(OperationOutcome::Expired { order_id: order.order_id });
+        }
+        let exercises = report.positions.exercise(&expiry);
+        report.clearing.record_exercises(&expiry, &exercises);
+        report.exercises.extend(exercises);
+        report.option_expiries.push(expiry);
+    }
+}
+
+/// The mid, or the last trade price while one side of the book is empty.
+fn mark_price(engine: &MatchingEngine, instrument: &str) -> Option<Decimal> {
+    let book = engine.order_book(instrument)?;
+    book.mid_price().or_else(|| book.last_trade_price())
+}
+
+/// Records orders released from a speed bump as accepted and returns the
+/// trades they made.
+fn release_delayed(released: Vec<ReleasedOrder>, report: &mut RunReport) -> Vec<Trade> {
+    let mut trades = Vec::new();
+    for ReleasedOrder { order, trades: fills, .. } in released {
+        report.record(OperationOutcome::Accepted {
+            order_id: order.order_id,
+            quantity: order.quantity,
+            filled_quantity: fills.iter().map(|t| t.quantity).sum::<Decimal>(),
+            trades: fills.len(),
+        });
+        trades.extend(fills);
+    }
+    trades
+}
+
+/// Records stops the operation triggered and returns the trades they made.
+fn record_triggered_stops(triggered: Vec<ReleasedOrder>, report: &mut RunReport) -> Vec<Trade> {
+    let mut trades = Vec::new();
+    for ReleasedOrder { order, trades: fills, .. } in triggered {
+        report.record(OperationOutcome::StopTriggered {
+            order_id: order.order_id,
+            quantity: order.quantity,
+            filled_quantity: fills.iter().map(|t| t.quantity).sum::<Decimal>(),
+            trades: fills.len(),
+        });
+        trades.extend(fills);
+    }
+    trades
+}
+
+/// Records the engine's close-out orders, attributing them to the clients
+/// they closed out, and returns their trades.
+fn record_liquidations(liquidations: Vec<Liquidation>, report: &mut RunReport) -> Vec<Trade> {
+    let mut trades = Vec::new();
+    for Liquidation { client, order, trades: fills, .. } in liquidations {
+        report.positions.register(order.order_id, client);
+        report.record(OperationOutcome::Liquidated {
+            order_id: order.order_id,
+            quantity: order.quantity,
+            filled_quantity: fills.iter().map(|t| t.quantity).sum::<Decimal>(),
+            trades: fills.len(),
+        });
+        trades.extend(fills);
+    }
+    trades
+}
+
+/// Records each batched order as accepted with what it filled in its
+/// auction, and the auction itself, returning the auction trades.
+fn record_auctions(auctions: Vec<AuctionResult>, report: &mut RunReport) -> Vec<Trade> {
+    let mut trades = Vec::new();
+    for AuctionResult { instrument, price, orders, trades: fills, .. } in auctions {
+        for order in orders {
+            report.record(OperationOutcome::Accepted {
+                order_id: order.order_id,
+                quantity: order.quantity,
+                filled_quantity: order.quantity - order.remaining_quantity,
+                trades: 0,
+            });
+        }
+        report.record(OperationOutcome::Auction {
+            instrument,
+            price,
+            quantity: fills.iter().map(|t| t.quantity).sum(),
+            trades: fills.len(),
+        });
+        trades.extend(fills);
+    }
+    trades
+}
+
+fn apply_operation(
+    logger: &mut Box<dyn SimLogger>,
+    engine: &mut MatchingEngine,
+    index: usize,
+    operation: &SimOp,
+    latencies: &mut Vec<(u128, u128)>,
+    report: &mut RunReport,
+) -> Vec<Trade> {
+    match operation {
+        SimOp::New(new_order) => {
+            let order = new_order.to_order();
+            let order_id = order.order_id;
+            let quantity = order.quantity;
+            if let Some(client) = &new_order.client {
+                report.positions.register(order_id, client.clone());
+            }
+
+            let log_submission_start = engine.latency_nanos();
+            logger.log_order_submission(&order);
+            let log_submission_duration = engine.elapsed_since(log_submission_start);
+
+            let op_start = engine.latency_nanos();
+            let submission = match (&new_order.client, &new_order.client_order_id) {
+                (Some(client), Some(client_order_id)) => engine.submit_client_order(order, client.clone(), client_order_id, logger),
+                _ => engine.submit_order(order, new_order.client.clone(), logger),
+            };
+            match submission {
+                Ok(Submission::New(trades, log_process_duration)) => {
+                    let process_duration = engine.elapsed_since(op_start);
+                    latencies.push((process_duration, log_submission_duration + log_process_duration));
+                    report.record(OperationOutcome::Accepted {
                         order_id,
-                        operation.instrument.clone(),
-                        side,
-                        operation.quantity.unwrap_or_default(),
-                    ),
-                    _ => {
-                        eprintln!(" -> Error: NEW operation requires a valid ORDER_TYPE.");
-                        continue;
-                    }
-                };
-
-                let log_submission_start = Instant::now();
-                logger.log_order_submission(&order);
-                let log_submission_duration = log_submission_start.elapsed().as_nanos();
-
-                let op_start = Instant::now();
-                match engine.process_order(order, logger) {
-                    Ok((_, log_process_duration)) => {
-                        let process_duration = op_start.elapsed().as_nanos();
-                        latencies.push((process_duration, log_submission_duration + log_process_duration));
-                    }
-                    Err(e) => {
-                        eprintln!(" -> Error processing order: {}", e);
-                        latencies.push((op_start.elapsed().as_nanos(), log_submission_duration));
-                    }
+                        quantity,
+                        filled_quantity: trades.iter().map(|t| t.quantity).sum::<Decimal>(),
+                        trades: trades.len(),
+                    });
+                    trades
+                }
+                Ok(Submission::Delayed { .. }) => {
+                    latencies.push((engine.elapsed_since(op_start), log_submission_duration));
+                    report.record(OperationOutcome::Delayed { order_id });
+                    Vec::new()
+                }
+                Ok(Submission::Stopped) => {
+                    latencies.push((engine.elapsed_since(op_start), log_submission_duration));
+                    report.record(OperationOutcome::Stopped { order_id });
+                    Vec::new()
                 }
+                Ok(Submission::Duplicate(original)) => {
+                    latencies.push((engine.elapsed_since(op_start), log_submission_duration));
+                    report.record(OperationOutcome::Duplicate {
+                        order_id: original.order_id,
+                        client_order_id: new_order.client_order_id.clone().unwrap_or_default(),
+                    });
+                    Vec::new()
+                }
+                Err(e) => {
+                    latencies.push((engine.elapsed_since(op_start), log_submission_duration));
+                    report.record(OperationOutcome::Rejected { order_id, reason: e.to_string() });
+                    report.errors.push(SimulationError { operation: index + 1, row: operation.to_row(), order_id: Some(order_id), source: Box::new(e) });
+                    Vec::new()
+                }
+            }
+        }
+        SimOp::Cancel { instrument, order_id, client } => {
+            let cancel_start = engine.latency_nanos();
+            let success = match client {
+                Some(client) => engine.cancel_client_order(client, order_id, instrument, logger).is_ok(),
+                None => engine.cancel_order_by_id(order_id, instrument).is_ok(),
+            };
+            let process_duration = engine.elapsed_since(cancel_start);
+            
+            let log_cancel_start = engine.latency_nanos();
+            logger.log_order_cancel(order_id, success);
+            let log_cancel_duration = engine.elapsed_since(log_cancel_start) + engine.log_repricing(logger);
+
+            latencies.push((process_duration, log_cancel_duration));
+            if success {
+                report.record(OperationOutcome::Canceled { order_id: *order_id });
+            } else {
+                report.record(OperationOutcome::CancelFailed { order_id: *order_id });
             }
-            "CANCEL" => {
-                let Some(id_str_to_cancel) = operation.order_to_cancel.as_ref() else {
-                    eprintln!(" -> Error: CANCEL operation requires an ID in the 'order_to_cancel' column.");
-                    continue;
-                };
-
-                let Ok(order_id) = Uuid::parse_str(id_str_to_cancel) else {
-                    eprintln!(" -> Error: Invalid UUID format for order to cancel: '{}'", id_str_to_cancel);
-                    continue;
-                };
-
-                let cancel_start = Instant::now();
-                let success = engine.cancel_order_by_id(&order_id, &operation.instrument).is_ok();
-                let process_duration = cancel_start.elapsed().as_nanos();
-                
-                let log_cancel_start = Instant::now();
-                logger.log_order_cancel(&order_id, success);
-                let log_cancel_duration = log_cancel_start.elapsed().as_nanos();
-
-                latencies.push((process_duration, log_cancel_duration));
+            Vec::new()
+        }
+        SimOp::CancelAll { client } => {
+            let cancel_start = engine.latency_nanos();
+            let canceled = engine.cancel_client_orders(client);
+            let process_duration = engine.elapsed_since(cancel_start);
+
+            let log_cancel_start = engine.latency_nanos();
+            for order in &canceled {
+                logger.log_order_cancel(&order.order_id, true);
             }
-            _ => {
-                eprintln!(" -> Error: Unknown operation type '{}'", operation.operation);
+            let log_cancel_duration = engine.elapsed_since(log_cancel_start) + engine.log_repricing(logger);
+
+            latencies.push((process_duration, log_cancel_duration));
+            for order in canceled {
+                report.record(OperationOutcome::Canceled { order_id: order.order_id });
             }
+            Vec::new()
         }
     }
+}
 
+pub(crate) fn finish_run(logger: &mut Box<dyn SimLogger>, engine: &mut MatchingEngine, mut report: RunReport) -> Result<RunReport, Box<dyn Error>> {
+    record_busts(logger, engine, &mut report);
+    let mut trades = release_delayed(engine.flush_delayed_orders(logger), &mut report);
+    trades.extend(record_auctions(engine.flush_auctions(logger), &mut report));
+    trades.extend(record_triggered_stops(engine.take_triggered_stops(), &mut report));
+    trades.extend(record_liquidations(engine.take_liquidations(), &mut report));
+    record_fills(engine, &trades, &mut report);
+    record_expiries(engine.flush_expiries(logger), &mut report);
+    report.markouts.advance(engine.now_nanos(), |instrument| mark_price(engine, instrument));
     println!("\nFinished processing simulation operations.");
-    Ok(())
-}
\ No newline at end of file
+
+    report.accounting = engine.accounting_report();
+    report.levels = engine.level_stats();
+    report.stop_cascades = engine.stop_cascades().to_vec();
+    report.leaderboard = report.positions.leaderboard(&report.positions.marks(engine));
+    report.accounting.verify()?;
+    Ok(report)
+}
@@ -0,0 +1,319 @@
+use crate::config::InstrumentConfig;
+use crate::engine::MatchingEngine;
+use crate::orderbook::OrderBook;
+use crate::order::{CancelTimer, Order, OrderAttributes, Peg, PegReference};
+use crate::utils::{OrderStatus, OrderType, Side};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use uuid::Uuid;
+
+/// One resting order as stored in a book snapshot file. Rows are written in
+/// time priority per price level, so loading them back in file order rebuilds
+/// every queue exactly as it was. Each instrument's orders follow a market
+/// row, which has no order id and carries the instrument's id and trade
+/// count instead.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotRow {
+    pub instrument: String,
+    pub order_id: String,
+    pub side: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub remaining_quantity: Decimal,
+    pub timestamp: u64,
+    /// Optional order features, empty for plain limit orders; older snapshots
+    /// without these columns still load.
+    #[serde(default)]
+    pub peg: Option<String>,
+    #[serde(default)]
+    pub peg_offset: Option<Decimal>,
+    #[serde(default)]
+    pub peg_limit: Option<Decimal>,
+    #[serde(default)]
+    pub discretion: Option<Decimal>,
+    #[serde(default)]
+    pub attributes: Option<String>,
+    #[serde(default)]
+    pub expire_at: Option<u64>,
+    /// The order's arrival number in its book; 0 in older snapshots, which
+    /// rely on file order alone.
+    #[serde(default)]
+    pub sequence: u64,
+    /// When a timed order is canceled, on the engine clock.
+    #[serde(default)]
+    pub cancel_at: Option<u64>,
+    /// Set on market rows only. Older snapshots have none, and their
+    /// markets number trades from 1 again.
+    #[serde(default)]
+    pub instrument_id: Option<u32>,
+    #[serde(default)]
+    pub trade_sequence: Option<u64>,
+}
+
+impl SnapshotRow {
+    fn from_market(book: &OrderBook) -> Self {
+        SnapshotRow {
+            instrument: book.instrument().to_string(),
+            order_id: String::new(),
+            side: String::new(),
+            price: Decimal::ZERO,
+            quantity: Decimal::ZERO,
+            remaining_quantity: Decimal::ZERO,
+            timestamp: 0,
+            peg: None,
+            peg_offset: None,
+            peg_limit: None,
+            discretion: None,
+            attributes: None,
+            expire_at: None,
+            sequence: 0,
+            cancel_at: None,
+            instrument_id: Some(book.instrument_id()),
+            trade_sequence: Some(book.trade_count()),
+        }
+    }
+
+    fn from_order(order: &Order) -> Option<Self> {
+        let price = order.price?;
+        Some(SnapshotRow {
+            instrument: order.instrument.clone(),
+            order_id: order.order_id.to_string(),
+            side: order.side.to_string(),
+            price,
+            quantity: order.quantity,
+            remaining_quantity: order.remaining_quantity,
+            timestamp: order.timestamp,
+            peg: order.peg.map(|peg| peg.reference.to_string()),
+            peg_offset: order.peg.map(|peg| peg.offset),
+            peg_limit: order.peg.and_then(|peg| peg.limit),
+            discretion: order.discretion,
+            attributes: Some(order.attributes.to_string()).filter(|attributes| !attributes.is_empty()),
+            expire_at: order.expire_at,
+            sequence: order.sequence,
+            cancel_at: order.cancel_at(),
+            instrument_id: None,
+            trade_sequence: None,
+        })
+    }
+
+    fn into_order(self) -> Result<Order, Box<dyn Error>> {
+        let order_id = Uuid::parse_str(&self.order_id)
+            .map_err(|_| format!("Invalid UUID format in snapshot: '{}'", self.order_id))?;
+        let side = self
+            .side
+            .parse::<Side>()
+            .map_err(|e| format!("Invalid side in snapshot: {}", e))?;
+        if self.remaining_quantity <= Decimal::ZERO || self.remaining_quantity > self.quantity {
+            return Err(format!("Invalid remaining quantity in snapshot for order {}", order_id).into());
+        }
+        let peg = match self.peg.as_deref().filter(|peg| !peg.is_empty()) {
+            Some(reference) => Some(Peg {
+                reference: reference.parse::<PegReference>()?,
+                offset: self.peg_offset.unwrap_or_default(),
+                limit: self.peg_limit,
+            }),
+            None => None,
+        };
+        let attributes = self.attributes.as_deref().unwrap_or_default().parse::<OrderAttributes>()?;
+        let status = if self.remaining_quantity < self.quantity {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::New
+        };
+
+        Ok(Order {
+            order_id,
+            instrument: self.instrument,
+            side,
+            order_type: OrderType::Limit,
+            status,
+            price: Some(self.price),
+            quantity: self.quantity,
+            remaining_quantity: self.remaining_quantity,
+            timestamp: self.timestamp,
+            sequence: self.sequence,
+            peg,
+            discretion: self.discretion,
+            attributes,
+            expire_at: self.expire_at,
+            cancel_timer: self.cancel_at.map(CancelTimer::At),
+            // A resting order is a live limit order, whether or not it
+            // started as a stop.
+            stop_price: None,
+        })
+    }
+}
+
+/// Writes every resting order of the given instruments to a CSV snapshot
+/// file, each instrument's after its market row. Returns the number of
+/// orders written.
+pub fn save_snapshot(path: &str, instruments: &[String], engine: &MatchingEngine) -> Result<usize, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut count = 0;
+
+    for instrument in instruments {
+        if let Some(book) = engine.order_book(instrument) {
+            writer.serialize(SnapshotRow::from_market(book))?;
+        }
+        for order in engine.resting_orders(instrument) {
+            if let Some(row) = SnapshotRow::from_order(&order) {
+                writer.serialize(row)?;
+                count += 1;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Loads a CSV snapshot file into the engine, creating markets for any
+/// instrument that is not registered yet and restoring their ids and trade
+/// counts. Returns the number of orders restored.
+pub fn load_snapshot(path: &str, engine: &mut MatchingEngine) -> Result<usize, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut count = 0;
+
+    for result in reader.deserialize() {
+        let row: SnapshotRow = result?;
+        if !engine.has_market(&row.instrument) {
+            engine.create_market(row.instrument.clone(), InstrumentConfig::default())?;
+        }
+        if let (Some(instrument_id), Some(trade_sequence)) = (row.instrument_id, row.trade_sequence) {
+            engine.restore_market(&row.instrument, instrument_id, trade_sequence)?;
+            continue;
+        }
+        engine.restore_order(row.into_order()?)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::ids::InstrumentSequenceIds;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_snapshot_round_trip_preserves_book() {
+        let mut engine = MatchingEngine::new();
+        engine.create_market("SOFI".to_string(), InstrumentConfig::default()).unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        let first = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101.0), dec!(10));
+        let second = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101.0), dec!(4));
+        let first_id = first.order_id;
+        engine.process_order(first, &mut logger).unwrap();
+        engine.process_order(second, &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99.0), dec!(7)), &mut logger).unwrap();
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(101.0), dec!(3)), &mut logger).unwrap();
+
+        let path = std::env::temp_dir().join(format!("snapshot_{}.csv", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let instruments = vec!["SOFI".to_string()];
+        assert_eq!(save_snapshot(path, &instruments, &engine).unwrap(), 3);
+
+        let mut restored = MatchingEngine::new();
+        assert_eq!(load_snapshot(path, &mut restored).unwrap(), 3);
+        std::fs::remove_file(path).unwrap();
+
+        let book = restored.get_order_book_display("SOFI").unwrap();
+        assert_eq!(book.asks.len(), 1);
+        assert_eq!(book.asks[0].volume, dec!(11));
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.bids[0].volume, dec!(7));
+
+        let resting = restored.resting_orders("SOFI");
+        let head = resting.iter().find(|o| o.side == Side::Sell).unwrap();
+        assert_eq!(head.order_id, first_id);
+        assert_eq!(head.status, OrderStatus::PartiallyFilled);
+        assert_eq!(head.remaining_quantity, dec!(7));
+    }
+
+    #[test]
+    fn test_snapshot_that_would_cross_or_break_the_tick_is_rejected() {
+        let row = |side: &str, price: &str| format!("SOFI,{},{},{},5,5,0\n", Uuid::new_v4(), side, price);
+        let load = |rows: &[String], engine: &mut MatchingEngine| {
+            let path = std::env::temp_dir().join(format!("snapshot_{}.csv", Uuid::new_v4()));
+            let header = "instrument,order_id,side,price,quantity,remaining_quantity,timestamp\n";
+            std::fs::write(&path, header.to_string() + &rows.concat()).unwrap();
+            let result = load_snapshot(path.to_str().unwrap(), engine).map_err(|e| e.to_string());
+            std::fs::remove_file(path).unwrap();
+            result
+        };
+
+        let mut engine = MatchingEngine::new();
+        assert_eq!(load(&[row("BUY", "100"), row("SELL", "100.5")], &mut engine), Ok(2));
+        let crossed = load(&[row("SELL", "99.5")], &mut engine).unwrap_err();
+        assert!(crossed.contains("would cross the book"), "{}", crossed);
+        assert!(load(&[row("BUY", "100.5")], &mut engine).is_err());
+        assert_eq!(engine.resting_orders("SOFI").len(), 2);
+
+        let mut ticked = MatchingEngine::builder()
+            .instrument("SOFI", InstrumentConfig { tick_size: Some(dec!(0.05)), ..Default::default() })
+            .build()
+            .unwrap();
+        let off_tick = load(&[row("BUY", "100.02")], &mut ticked).unwrap_err();
+        assert!(off_tick.contains("tick size"), "{}", off_tick);
+    }
+
+    #[test]
+    fn test_snapshot_continues_trade_numbering() {
+        let mut engine = MatchingEngine::new();
+        engine.create_market("SOFI".to_string(), InstrumentConfig::default()).unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut trade = |engine: &mut MatchingEngine| {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(100), dec!(1)), &mut logger).unwrap();
+            engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(1)), &mut logger).unwrap().0
+        };
+        trade(&mut engine);
+        trade(&mut engine);
+
+        let path = std::env::temp_dir().join(format!("snapshot_{}.csv", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        assert_eq!(save_snapshot(path, &["SOFI".to_string()], &engine).unwrap(), 0);
+        // Markets created in another order keep their ids.
+        let mut restored = MatchingEngine::new();
+        restored.create_market("AAPL".to_string(), InstrumentConfig::default()).unwrap();
+        assert_eq!(load_snapshot(path, &mut restored).unwrap(), 0);
+        std::fs::remove_file(path).unwrap();
+
+        let trades = trade(&mut restored);
+        let sofi = engine.order_book("SOFI").unwrap().instrument_id();
+        assert_eq!(restored.order_book("SOFI").unwrap().instrument_id(), sofi);
+        assert_eq!(trades[0].sequence, 3);
+        assert_eq!(InstrumentSequenceIds::decode(trades[0].trade_id), Some((sofi, 3)));
+        assert!(restored.restore_market("SOFI", sofi, 0).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_keeps_pegs_and_discretion() {
+        let mut engine = MatchingEngine::new();
+        engine.create_market("SOFI".to_string(), InstrumentConfig::default()).unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(99), dec!(5)), &mut logger).unwrap();
+        let peg = Peg { offset: dec!(0.01), limit: Some(dec!(98.5)), ..Peg::new(PegReference::Primary) };
+        engine.process_order(Order::new_pegged(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, peg, dec!(2)), &mut logger).unwrap();
+        let discretionary = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101), dec!(3)).with_discretion(dec!(100.5));
+        engine.process_order(discretionary, &mut logger).unwrap();
+
+        let path = std::env::temp_dir().join(format!("snapshot_{}.csv", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        save_snapshot(path, &["SOFI".to_string()], &engine).unwrap();
+        let mut restored = MatchingEngine::new();
+        load_snapshot(path, &mut restored).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let pegged = restored.resting_orders("SOFI").into_iter().find(|o| o.peg.is_some()).unwrap();
+        assert_eq!(pegged.peg, Some(peg));
+        assert_eq!(pegged.price, Some(dec!(98.5)));
+        let ask = restored.resting_orders("SOFI").into_iter().find(|o| o.side == Side::Sell).unwrap();
+        assert_eq!(ask.discretion, Some(dec!(100.5)));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::operation::OperationReader;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// Where a live operation feed is served: `tcp:<host:port>` (or a bare
+/// `<host:port>`) or, on Unix, `unix:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(format!("Unix sockets are not supported on this platform: '{}'", path));
+        }
+        let addr = s.strip_prefix("tcp:").unwrap_or(s);
+        if addr.is_empty() {
+            return Err("Missing socket address".to_string());
+        }
+        Ok(Endpoint::Tcp(addr.to_string()))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addr) => write!(f, "tcp:{}", addr),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub type SocketSource = OperationReader<Box<dyn Read + Send>>;
+
+/// A bound endpoint waiting for the process that will stream operations.
+pub enum OperationListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl OperationListener {
+    pub fn bind(endpoint: &Endpoint) -> io::Result<Self> {
+        match endpoint {
+            Endpoint::Tcp(addr) => Ok(OperationListener::Tcp(TcpListener::bind(addr)?)),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(OperationListener::Unix(UnixListener::bind(path)?, path.clone()))
+            }
+        }
+    }
+
+    /// The bound endpoint, with the actual port when binding to port 0.
+    pub fn local_endpoint(&self) -> io::Result<Endpoint> {
+        match self {
+            OperationListener::Tcp(listener) => Ok(Endpoint::Tcp(listener.local_addr()?.to_string())),
+            #[cfg(unix)]
+            OperationListener::Unix(_, path) => Ok(Endpoint::Unix(path.clone())),
+        }
+    }
+
+    /// Blocks until one producer connects and returns its operation stream.
+    /// The stream ends when the producer closes the connection.
+    pub fn accept(&self) -> io::Result<SocketSource> {
+        OperationReader::new(self.accept_stream()?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Blocks until a peer connects and returns the raw byte stream.
+    pub fn accept_stream(&self) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            OperationListener::Tcp(listener) => Box::new(listener.accept()?.0),
+            #[cfg(unix)]
+            OperationListener::Unix(listener, _) => Box::new(listener.accept()?.0),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for OperationListener {
+    fn drop(&mut self) {
+        if let OperationListener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Opens the producer side of a live feed.
+pub fn connect(endpoint: &Endpoint) -> io::Result<Box<dyn Write + Send>> {
+    match endpoint {
+        Endpoint::Tcp(addr) => Ok(Box::new(TcpStream::connect(addr)?)),
+        #[cfg(unix)]
+        Endpoint::Unix(path) => Ok(Box::new(UnixStream::connect(path)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::{OperationSource, SimOp};
+    use std::thread;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(Endpoint::from_str("127.0.0.1:9000"), Ok(Endpoint::Tcp("127.0.0.1:9000".to_string())));
+        assert_eq!(Endpoint::from_str("tcp:localhost:9000"), Ok(Endpoint::Tcp("localhost:9000".to_string())));
+        #[cfg(unix)]
+        assert_eq!(Endpoint::from_str("unix:/tmp/ops.sock"), Ok(Endpoint::Unix(PathBuf::from("/tmp/ops.sock"))));
+        assert!(Endpoint::from_str("tcp:").is_err());
+    }
+
+    #[test]
+    fn test_stream_operations_over_tcp() {
+        let listener = OperationListener::bind(&Endpoint::Tcp("127.0.0.1:0".to_string())).unwrap();
+        let endpoint = listener.local_endpoint().unwrap();
+
+        let producer = thread::spawn(move || {
+            let mut stream = connect(&endpoint).unwrap();
+            stream.write_all(b"operation,instrument,side,order_type,quantity,price,order_to_cancel\n").unwrap();
+            stream.write_all(b"NEW,PUMPTHIS,BUY,LIMIT,10,99.50,00000000-0000-0000-0000-000000000001\n").unwrap();
+            stream.write_all(b"BOGUS,PUMPTHIS,,,,,\n").unwrap();
+            stream.write_all(b"CANCEL,PUMPTHIS,,,,,00000000-0000-0000-0000-000000000001\n").unwrap();
+        });
+
+        let mut source = listener.accept().unwrap();
+        let mut parsed = Vec::new();
+        while let Some(op) = source.next_operation() {
+            parsed.push(op);
+        }
+        producer.join().unwrap();
+
+        assert_eq!(parsed.len(), 3);
+        assert!(matches!(parsed[0], Ok(SimOp::New(_))));
+        assert_eq!(parsed[1].as_ref().unwrap_err().line, 3);
+        assert!(matches!(parsed[2], Ok(SimOp::Cancel { .. })));
+    }
+}
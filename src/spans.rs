@@ -0,0 +1,179 @@
+//! `tracing` spans around the engine's order and cancel operations, compiled
+//! in with the `spans` feature so any subscriber can be attached to them
+//! (tracing-tracy, OpenTelemetry, a flame graph layer). Each span carries the
+//! instrument and order id, and records the trades made, the latency and any
+//! error when the operation returns. Without the feature the spans compile
+//! to nothing.
+
+use crate::engine::Submission;
+use crate::order::Order;
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// The span of one engine operation.
+pub(crate) struct OperationSpan {
+    #[cfg(feature = "spans")]
+    span: tracing::Span,
+    /// The operation's name on the exported latency histogram.
+    #[cfg(feature = "otel")]
+    operation: &'static str,
+}
+
+impl OperationSpan {
+    /// A `process_order` span for submitting `order`.
+    #[cfg_attr(not(feature = "spans"), allow(unused_variables))]
+    #[inline(always)]
+    pub(crate) fn order(order: &Order) -> Self {
+        OperationSpan {
+            #[cfg(feature = "spans")]
+            span: tracing::info_span!(
+                "process_order",
+                instrument = %order.instrument,
+                order_id = %order.order_id,
+                side = ?order.side,
+                order_type = ?order.order_type,
+                quantity = %order.quantity,
+                trades = tracing::field::Empty,
+                latency_ns = tracing::field::Empty,
+                error = tracing::field::Empty,
+            ),
+            #[cfg(feature = "otel")]
+            operation: "process_order",
+        }
+    }
+
+    /// A `cancel_order` span for canceling `order_id`.
+    #[cfg_attr(not(feature = "spans"), allow(unused_variables))]
+    #[inline(always)]
+    pub(crate) fn cancel(order_id: &Uuid, instrument: &str) -> Self {
+        OperationSpan {
+            #[cfg(feature = "spans")]
+            span: tracing::info_span!(
+                "cancel_order",
+                instrument = %instrument,
+                order_id = %order_id,
+                latency_ns = tracing::field::Empty,
+                error = tracing::field::Empty,
+            ),
+            #[cfg(feature = "otel")]
+            operation: "cancel_order",
+        }
+    }
+
+    /// Runs `operation` inside the span and records how long it took and
+    /// what it failed with.
+    #[inline(always)]
+    pub(crate) fn run<T, E: Display>(&self, operation: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        #[cfg(feature = "spans")]
+        {
+            let start = std::time::Instant::now();
+            let result = self.span.in_scope(operation);
+            let latency = start.elapsed().as_nanos() as u64;
+            self.span.record("latency_ns", latency);
+            #[cfg(feature = "otel")]
+            crate::telemetry::record_latency(self.operation, latency);
+            if let Err(error) = &result {
+                self.span.record("error", tracing::field::display(error));
+            }
+            result
+        }
+        #[cfg(not(feature = "spans"))]
+        operation()
+    }
+
+    /// [`run`](OperationSpan::run) for a submission, also recording the
+    /// trades it made on arrival.
+    #[inline(always)]
+    pub(crate) fn submit<E: Display>(&self, operation: impl FnOnce() -> Result<Submission, E>) -> Result<Submission, E> {
+        let result = self.run(operation);
+        #[cfg(feature = "spans")]
+        if let Ok(submission) = &result {
+            let trades = match submission {
+                Submission::New(trades, _) => trades.len(),
+                _ => 0,
+            };
+            self.span.record("trades", trades);
+        }
+        result
+    }
+}
+
+#[cfg(all(test, feature = "spans"))]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// A span's name and the fields recorded on it.
+    type RecordedSpan = (&'static str, Vec<(String, String)>);
+
+    /// Keeps every span, in the order they were created; a span's id is its
+    /// position plus one.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<RecordedSpan>>>);
+
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            let mut fields = Vec::new();
+            span.record(&mut Fields(&mut fields));
+            spans.push((span.metadata().name(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_operations_record_their_fields() {
+        let recorder = Recorder::default();
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let ask = Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5));
+        let ask_id = ask.order_id;
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            engine.process_order(ask, &mut logger).unwrap();
+            engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(2)), &mut logger).unwrap();
+            engine.cancel_order_by_id(&ask_id, "SOFI").unwrap();
+            assert!(engine.cancel_order_by_id(&ask_id, "SOFI").is_err());
+        });
+
+        let spans = recorder.0.lock().unwrap();
+        let field = |span: usize, name: &str| spans[span].1.iter().find(|(field, _)| field == name).map(|(_, value)| value.clone());
+        assert_eq!(spans.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["process_order", "process_order", "cancel_order", "cancel_order"]);
+        assert_eq!(field(0, "order_id"), Some(ask_id.to_string()));
+        assert_eq!(field(0, "instrument").as_deref(), Some("SOFI"));
+        assert_eq!((field(0, "trades").as_deref(), field(1, "trades").as_deref()), (Some("0"), Some("1")));
+        assert!(field(2, "latency_ns").is_some() && field(2, "error").is_none());
+        assert!(field(3, "error").is_some());
+    }
+}
@@ -0,0 +1,102 @@
+//! Stop-order cascades: chains in which the trades of triggered stops trigger
+//! further stops. The engine records one cascade per trade-producing event
+//! that triggers at least one stop.
+
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Stops triggered, directly or through other stops, by one order, release
+/// or auction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StopCascade {
+    pub instrument: String,
+    /// Rounds of triggering: 1 when only the initiating trades triggered
+    /// stops, 2 when those stops' trades triggered more, and so on.
+    pub depth: usize,
+    pub stops: usize,
+    /// Quantity traded by the triggered stops.
+    pub volume: Decimal,
+    /// The first price of the initiating trades.
+    pub start_price: Decimal,
+    /// The last price the cascade printed.
+    pub end_price: Decimal,
+}
+
+impl StopCascade {
+    /// How far the cascade moved the price, from the first initiating trade
+    /// to its last print.
+    pub fn price_move(&self) -> Decimal {
+        self.end_price - self.start_price
+    }
+}
+
+/// The cascades of one instrument summed up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CascadeSummary {
+    pub cascades: usize,
+    pub stops: usize,
+    pub volume: Decimal,
+    pub max_depth: usize,
+    /// Cascades that went beyond the first round.
+    pub chained: usize,
+    /// The largest price move of a single cascade, in either direction.
+    pub largest_move: Decimal,
+}
+
+pub fn summarize_cascades(cascades: &[StopCascade]) -> BTreeMap<String, CascadeSummary> {
+    let mut summaries: BTreeMap<String, CascadeSummary> = BTreeMap::new();
+    for cascade in cascades {
+        let summary = summaries.entry(cascade.instrument.clone()).or_default();
+        summary.cascades += 1;
+        summary.stops += cascade.stops;
+        summary.volume += cascade.volume;
+        summary.max_depth = summary.max_depth.max(cascade.depth);
+        if cascade.depth > 1 {
+            summary.chained += 1;
+        }
+        if cascade.price_move().abs() > summary.largest_move.abs() {
+            summary.largest_move = cascade.price_move();
+        }
+    }
+    summaries
+}
+
+pub fn print_stop_cascades(cascades: &[StopCascade]) {
+    if cascades.is_empty() {
+        return;
+    }
+    println!("\n--- Stop Cascades ---");
+    println!("{:<12} {:>9} {:>8} {:>8} {:>10} {:>12} {:>13} {:>13}", "Instrument", "Cascades", "Chained", "Stops", "Max depth", "Mean depth", "Volume", "Largest move");
+    for (instrument, summary) in summarize_cascades(cascades) {
+        let depths: usize = cascades.iter().filter(|cascade| cascade.instrument == instrument).map(|cascade| cascade.depth).sum();
+        println!(
+            "{:<12} {:>9} {:>8} {:>8} {:>10} {:>12.2} {:>13} {:>13}",
+            instrument, summary.cascades, summary.chained, summary.stops, summary.max_depth, depths as f64 / summary.cascades as f64, summary.volume, summary.largest_move
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_summary_per_instrument() {
+        let cascade = |instrument: &str, depth, stops, volume, end_price| StopCascade {
+            instrument: instrument.to_string(),
+            depth,
+            stops,
+            volume,
+            start_price: dec!(100),
+            end_price,
+        };
+        let cascades = [cascade("SOFI", 1, 1, dec!(5), dec!(99)), cascade("SOFI", 3, 4, dec!(20), dec!(96.5)), cascade("NVO", 2, 2, dec!(3), dec!(101))];
+
+        let summaries = summarize_cascades(&cascades);
+        let sofi = &summaries["SOFI"];
+        assert_eq!((sofi.cascades, sofi.chained, sofi.stops, sofi.max_depth), (2, 1, 5, 3));
+        assert_eq!((sofi.volume, sofi.largest_move), (dec!(25), dec!(-3.5)));
+        assert_eq!(summaries["NVO"].largest_move, dec!(1));
+    }
+}
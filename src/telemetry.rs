@@ -0,0 +1,203 @@
+//! Export of the engine's metrics and order spans to an OpenTelemetry
+//! collector over OTLP/HTTP, compiled in with the `otel` feature and started
+//! with `--otlp <url>` in server mode. Matching latency goes into a
+//! histogram, accepted orders, rejections, cancels, trades and traded
+//! quantity into counters, and the `process_order` and `cancel_order` spans
+//! of [`spans`](crate::spans) become traces.
+
+use crate::events::{EngineEvent, EngineSubscriber};
+use opentelemetry::metrics::{Counter, Histogram, Meter, MeterProvider};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use rust_decimal::prelude::ToPrimitive;
+use std::sync::OnceLock;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// The service the engine reports as, and the name of its meter and tracer.
+pub const SERVICE_NAME: &str = "exchange-matching-engine";
+
+/// Bucket bounds of the latency histogram, in nanoseconds.
+const LATENCY_BOUNDS: [f64; 12] = [250.0, 500.0, 1e3, 2.5e3, 5e3, 1e4, 2.5e4, 5e4, 1e5, 2.5e5, 1e6, 1e7];
+
+/// The latency histogram of the running exporter, if one was started.
+static LATENCY: OnceLock<Histogram<u64>> = OnceLock::new();
+
+/// Records that `operation` took `nanos`, if an exporter is running.
+pub(crate) fn record_latency(operation: &'static str, nanos: u64) {
+    if let Some(histogram) = LATENCY.get() {
+        histogram.record(nanos, &[KeyValue::new("operation", operation)]);
+    }
+}
+
+/// The running exporter. Metrics are pushed every minute, or as the
+/// `OTEL_METRIC_EXPORT_INTERVAL` environment variable says, and spans in
+/// batches.
+pub struct Telemetry {
+    meters: SdkMeterProvider,
+    tracers: SdkTracerProvider,
+    meter: Meter,
+}
+
+impl Telemetry {
+    /// Exports to the collector at `endpoint`, fx `http://localhost:4318`.
+    /// Spans are exported only if no other `tracing` subscriber is installed;
+    /// a tracing logging mode installs one first.
+    pub fn start(endpoint: &str) -> Result<Self, String> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .build()
+            .map_err(|e| format!("OTLP metrics exporter: {}", e))?;
+        let spans = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .build()
+            .map_err(|e| format!("OTLP span exporter: {}", e))?;
+        let meters = SdkMeterProvider::builder().with_periodic_exporter(metrics).with_resource(resource.clone()).build();
+        let tracers = SdkTracerProvider::builder().with_batch_exporter(spans).with_resource(resource).build();
+
+        let meter = meters.meter(SERVICE_NAME);
+        let latency = meter
+            .u64_histogram("matching.latency")
+            .with_unit("ns")
+            .with_description("Time the engine took over one order or cancel")
+            .with_boundaries(LATENCY_BOUNDS.to_vec())
+            .build();
+        if LATENCY.set(latency).is_err() {
+            return Err("An OTLP exporter is already running".to_string());
+        }
+        let layer = tracing_opentelemetry::layer().with_tracer(tracers.tracer(SERVICE_NAME));
+        if tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer)).is_err() {
+            eprintln!("A tracing subscriber is already installed; order spans are not exported");
+        }
+        Ok(Telemetry { meters, tracers, meter })
+    }
+
+    /// Counters of the engine's throughput, to register with the engine as
+    /// a subscriber.
+    pub fn engine_metrics(&self) -> EngineMetrics {
+        EngineMetrics::new(&self.meter)
+    }
+
+    /// Exports what is still buffered and stops the exporters.
+    pub fn shutdown(self) {
+        if let Err(e) = self.tracers.shutdown() {
+            eprintln!("Failed to export the last spans: {}", e);
+        }
+        if let Err(e) = self.meters.shutdown() {
+            eprintln!("Failed to export the last metrics: {}", e);
+        }
+    }
+}
+
+/// Counts what the engine does, by instrument for trades.
+pub struct EngineMetrics {
+    orders: Counter<u64>,
+    rejections: Counter<u64>,
+    cancels: Counter<u64>,
+    trades: Counter<u64>,
+    traded_quantity: Counter<f64>,
+}
+
+impl EngineMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        EngineMetrics {
+            orders: meter.u64_counter("orders.accepted").with_description("Orders the engine accepted").build(),
+            rejections: meter.u64_counter("orders.rejected").with_description("Orders the engine rejected").build(),
+            cancels: meter.u64_counter("orders.canceled").with_description("Orders canceled").build(),
+            trades: meter.u64_counter("trades").with_description("Trades executed").build(),
+            traded_quantity: meter.f64_counter("trades.quantity").with_description("Quantity traded").build(),
+        }
+    }
+}
+
+impl EngineSubscriber for EngineMetrics {
+    fn on_event(&mut self, event: &EngineEvent) {
+        match event {
+            EngineEvent::OrderAccepted { .. } => self.orders.add(1, &[]),
+            EngineEvent::OrderRejected { .. } => self.rejections.add(1, &[]),
+            EngineEvent::OrderCanceled(_) => self.cancels.add(1, &[]),
+            EngineEvent::Trade(trade) => {
+                let instrument = [KeyValue::new("instrument", trade.instrument.clone())];
+                self.trades.add(1, &instrument);
+                self.traded_quantity.add(trade.quantity.to_f64().unwrap_or_default(), &instrument);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InstrumentConfig;
+    use crate::engine::MatchingEngine;
+    use crate::logging::create_logger;
+    use crate::logging::types::LoggingMode;
+    use crate::order::Order;
+    use crate::utils::Side;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+    use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+    use opentelemetry_sdk::metrics::{PeriodicReader, Temporality};
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    /// Keeps the sum of each counter it is sent.
+    #[derive(Debug, Clone, Default)]
+    struct Sums(Arc<Mutex<HashMap<String, f64>>>);
+
+    impl PushMetricExporter for Sums {
+        async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+            let mut sums = self.0.lock().unwrap();
+            for metric in metrics.scope_metrics().flat_map(|scope| scope.metrics()) {
+                let sum = match metric.data() {
+                    AggregatedMetrics::U64(MetricData::Sum(sum)) => sum.data_points().map(|point| point.value() as f64).sum(),
+                    AggregatedMetrics::F64(MetricData::Sum(sum)) => sum.data_points().map(|point| point.value()).sum(),
+                    _ => continue,
+                };
+                sums.insert(metric.name().to_string(), sum);
+            }
+            Ok(())
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _: Duration) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn temporality(&self) -> Temporality {
+            Temporality::Cumulative
+        }
+    }
+
+    #[test]
+    fn test_engine_events_are_counted() {
+        let exporter = Sums::default();
+        let meters = SdkMeterProvider::builder().with_reader(PeriodicReader::builder(exporter.clone()).build()).build();
+        let metrics = EngineMetrics::new(&meters.meter(SERVICE_NAME));
+        let mut engine = MatchingEngine::builder().instrument("SOFI", InstrumentConfig::default()).subscriber(Box::new(metrics)).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+
+        engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(5)), &mut logger).unwrap();
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(2)), &mut logger).unwrap();
+        engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(1)), &mut logger).unwrap();
+        meters.force_flush().unwrap();
+
+        let sums = exporter.0.lock().unwrap();
+        let sum = |name: &str| sums[name];
+        assert_eq!((sum("orders.accepted"), sum("trades"), sum("trades.quantity")), (3.0, 2.0, 3.0));
+    }
+}
@@ -0,0 +1,160 @@
+//! A hierarchical timer wheel over `u64` deadlines, used to expire
+//! good-till-date orders without scanning the book.
+//!
+//! Level `n` has 64 slots of `64^n` ticks each. A timer sits at the lowest
+//! level whose slot width covers the distance to its deadline, measured by
+//! the highest bit in which the deadline differs from the wheel's current
+//! time. Advancing takes the earliest occupied slot, fires the timers that
+//! are due and re-files the others one or more levels down, so each timer is
+//! touched at most once per level: amortized O(1) per timer, independent of
+//! how many timers are waiting or how far time jumps.
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+/// Enough levels to cover every `u64` deadline.
+const LEVELS: usize = u64::BITS.div_ceil(SLOT_BITS) as usize;
+
+struct WheelLevel<T> {
+    /// Bit `i` is set when slot `i` holds timers.
+    occupied: u64,
+    slots: Vec<Vec<(u64, T)>>,
+}
+
+impl<T> WheelLevel<T> {
+    fn new() -> Self {
+        Self { occupied: 0, slots: (0..SLOTS).map(|_| Vec::new()).collect() }
+    }
+}
+
+pub struct TimerWheel<T> {
+    levels: Vec<WheelLevel<T>>,
+    /// The wheel's current time: every timer at or before it has fired.
+    elapsed: u64,
+    len: usize,
+}
+
+impl<T> Default for TimerWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new() -> Self {
+        Self { levels: (0..LEVELS).map(|_| WheelLevel::new()).collect(), elapsed: 0, len: 0 }
+    }
+
+    /// Timers waiting to fire.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Schedules `item` to fire once the wheel advances to `deadline`. A
+    /// deadline already passed fires on the next advance.
+    pub fn insert(&mut self, deadline: u64, item: T) {
+        self.file(deadline, item);
+        self.len += 1;
+    }
+
+    /// Moves the wheel to `now` and appends every timer due by then to
+    /// `fired`, earliest slot first.
+    pub fn advance(&mut self, now: u64, fired: &mut Vec<T>) {
+        while let Some((level, slot, start)) = self.next_slot()
+            && start <= now {
+            self.elapsed = start;
+            self.levels[level].occupied &= !(1 << slot);
+            for (deadline, item) in std::mem::take(&mut self.levels[level].slots[slot]) {
+                if deadline <= now {
+                    fired.push(item);
+                    self.len -= 1;
+                } else {
+                    self.file(deadline, item);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+
+    fn file(&mut self, deadline: u64, item: T) {
+        // A past deadline is filed at the current time so it is never behind
+        // the slot the wheel looks at first.
+        let at = deadline.max(self.elapsed);
+        let differing = (at ^ self.elapsed) | (SLOTS as u64 - 1);
+        let level = ((u64::BITS - 1 - differing.leading_zeros()) / SLOT_BITS) as usize;
+        let slot = ((at >> (level as u32 * SLOT_BITS)) as usize) & (SLOTS - 1);
+        self.levels[level].occupied |= 1 << slot;
+        self.levels[level].slots[slot].push((deadline, item));
+    }
+
+    /// The earliest occupied slot as (level, slot, first tick it covers).
+    /// Lower levels always expire before higher ones, and within a level no
+    /// occupied slot lies behind the current time.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        let (level, wheel_level) = self.levels.iter().enumerate().find(|(_, level)| level.occupied != 0)?;
+        let shift = level as u32 * SLOT_BITS;
+        let current = ((self.elapsed >> shift) as usize) & (SLOTS - 1);
+        debug_assert_eq!(wheel_level.occupied & ((1u64 << current) - 1), 0, "a timer fell behind the wheel");
+        let slot = current + (wheel_level.occupied >> current).trailing_zeros() as usize;
+        let span = shift + SLOT_BITS;
+        let base = if span >= u64::BITS { 0 } else { self.elapsed & !((1u64 << span) - 1) };
+        Some((level, slot, base + ((slot as u64) << shift)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_timers_fire_once_due_and_not_before() {
+        let mut wheel = TimerWheel::new();
+        for deadline in [5, 64, 70, 4_096, 1 << 40, u64::MAX] {
+            wheel.insert(deadline, deadline);
+        }
+        let mut fired = Vec::new();
+        wheel.advance(4, &mut fired);
+        assert!(fired.is_empty());
+        wheel.advance(69, &mut fired);
+        assert_eq!(fired, vec![5, 64]);
+        wheel.advance(5_000, &mut fired);
+        assert_eq!(fired, vec![5, 64, 70, 4_096]);
+        wheel.advance(u64::MAX, &mut fired);
+        assert_eq!(fired.len(), 6);
+        assert!(wheel.is_empty());
+
+        wheel.insert(10, 10);
+        wheel.advance(u64::MAX, &mut fired);
+        assert_eq!(fired.last(), Some(&10));
+    }
+
+    #[test]
+    fn test_random_timers_fire_in_the_step_that_reaches_them() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut wheel = TimerWheel::new();
+        let mut now = 0u64;
+        let mut pending = Vec::new();
+        for _ in 0..200 {
+            for _ in 0..50 {
+                let reach = 1u64 << rng.random_range(1..40);
+                let deadline = now + rng.random_range(0..reach);
+                wheel.insert(deadline, deadline);
+                pending.push(deadline);
+            }
+            now += rng.random_range(0..1u64 << 30);
+            let mut fired = Vec::new();
+            wheel.advance(now, &mut fired);
+            let mut due: Vec<u64> = pending.iter().copied().filter(|&deadline| deadline <= now).collect();
+            pending.retain(|&deadline| deadline > now);
+            fired.sort_unstable();
+            due.sort_unstable();
+            assert_eq!(fired, due);
+            assert_eq!(wheel.len(), pending.len());
+        }
+    }
+}
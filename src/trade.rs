@@ -1,11 +1,16 @@
+#[cfg(any(test, feature = "uuid-v4"))]
+use crate::clock::{Clock, SystemClock};
 use crate::utils::Side;
 use rust_decimal::Decimal;
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
 use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Trade {
     pub trade_id: Uuid,
+    /// 1 for an instrument's first trade, counting up without gaps. Zero
+    /// for a trade made outside a book.
+    pub sequence: u64,
     pub instrument: String,
     pub price: Decimal,
     pub quantity: Decimal,
@@ -16,6 +21,9 @@ pub struct Trade {
 }
 
 impl Trade {
+    /// A trade with a random id, stamped with the system clock. The engine
+    /// names its trades through its [`IdGenerator`](crate::ids::IdGenerator).
+    #[cfg(any(test, feature = "uuid-v4"))]
     pub fn new(
         instrument: String,
         price: Decimal,
@@ -24,21 +32,50 @@ impl Trade {
         sell_order_id: Uuid,
         taker_side: Side,
     ) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64;
-
-
         Trade {
             trade_id: Uuid::new_v4(),
+            sequence: 0,
             instrument,
             price,
             quantity,
-            timestamp,
+            timestamp: SystemClock.now_nanos(),
             buy_order_id,
             sell_order_id,
             taker_side,
         }
     }
 }
+
+impl Trade {
+    /// The order that took liquidity. For an auction trade, the order on the
+    /// side with more interest at the clearing price.
+    pub fn aggressor_order_id(&self) -> Uuid {
+        match self.taker_side {
+            Side::Buy => self.buy_order_id,
+            Side::Sell => self.sell_order_id,
+        }
+    }
+}
+
+/// The trade that completed an order, carried on its fill report so the
+/// owner can reconcile the fill against the public tape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Execution {
+    pub trade_id: Uuid,
+    pub aggressor_order_id: Uuid,
+}
+
+impl Execution {
+    pub fn of(trade: &Trade) -> Self {
+        Self { trade_id: trade.trade_id, aggressor_order_id: trade.aggressor_order_id() }
+    }
+
+    /// The last of `trades` that `order_id` took part in.
+    pub fn last_for(order_id: Uuid, trades: &[Trade]) -> Option<Self> {
+        trades
+            .iter()
+            .rev()
+            .find(|trade| trade.buy_order_id == order_id || trade.sell_order_id == order_id)
+            .map(Self::of)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::trade::Trade;
+use std::collections::VecDeque;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const DEFAULT_TRADE_RETENTION: usize = 100_000;
+
+/// How long after it printed a trade can still be busted.
+pub const DEFAULT_BUST_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+/// Per-instrument trade history kept sorted by timestamp so time range queries
+/// are two binary searches. Only the most recent `retention` trades are kept.
+#[derive(Debug)]
+pub struct TradeStore {
+    trades: VecDeque<Trade>,
+    retention: usize,
+}
+
+impl TradeStore {
+    pub fn new(retention: usize) -> Self {
+        TradeStore {
+            trades: VecDeque::with_capacity(retention.min(DEFAULT_TRADE_RETENTION)),
+            retention,
+        }
+    }
+
+    pub fn record(&mut self, trade: Trade) {
+        if self.retention == 0 {
+            return;
+        }
+
+        match self.trades.back() {
+            Some(last) if last.timestamp > trade.timestamp => {
+                let index = self.trades.partition_point(|t| t.timestamp <= trade.timestamp);
+                self.trades.insert(index, trade);
+            }
+            _ => self.trades.push_back(trade),
+        }
+
+        while self.trades.len() > self.retention {
+            self.trades.pop_front();
+        }
+    }
+
+    /// Trades with `from <= timestamp < to`, oldest first.
+    pub fn trades_between(&self, from: u64, to: u64) -> impl Iterator<Item = &Trade> {
+        let start = self.trades.partition_point(|t| t.timestamp < from);
+        let end = self.trades.partition_point(|t| t.timestamp < to).max(start);
+        self.trades.range(start..end)
+    }
+
+    pub fn get(&self, trade_id: &Uuid) -> Option<&Trade> {
+        self.trades.iter().rev().find(|t| t.trade_id == *trade_id)
+    }
+
+    /// Takes a trade off the tape.
+    pub fn remove(&mut self, trade_id: &Uuid) -> Option<Trade> {
+        let index = self.trades.iter().rposition(|t| t.trade_id == *trade_id)?;
+        self.trades.remove(index)
+    }
+
+    /// The `count` most recent trades, oldest first.
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &Trade> {
+        let start = self.trades.len().saturating_sub(count);
+        self.trades.range(start..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn trade_at(timestamp: u64) -> Trade {
+        let mut trade = Trade::new("TEST-STOCK".to_string(), dec!(100), dec!(1), Uuid::new_v4(), Uuid::new_v4(), Side::Buy);
+        trade.timestamp = timestamp;
+        trade
+    }
+
+    fn timestamps<'a>(trades: impl Iterator<Item = &'a Trade>) -> Vec<u64> {
+        trades.map(|t| t.timestamp).collect()
+    }
+
+    #[test]
+    fn test_trades_between_is_half_open() {
+        let mut store = TradeStore::new(10);
+        for ts in [10, 20, 20, 30, 40] {
+            store.record(trade_at(ts));
+        }
+
+        assert_eq!(timestamps(store.trades_between(20, 40)), vec![20, 20, 30]);
+        assert_eq!(timestamps(store.trades_between(0, 15)), vec![10]);
+        assert!(store.trades_between(41, 100).next().is_none());
+        assert!(store.trades_between(30, 20).next().is_none());
+    }
+
+    #[test]
+    fn test_out_of_order_trades_are_kept_sorted() {
+        let mut store = TradeStore::new(10);
+        for ts in [10, 30, 20] {
+            store.record(trade_at(ts));
+        }
+
+        assert_eq!(timestamps(store.trades_between(0, u64::MAX)), vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn test_retention_drops_oldest_trades() {
+        let mut store = TradeStore::new(3);
+        for ts in 1..=5 {
+            store.record(trade_at(ts));
+        }
+
+        assert_eq!(store.len(), 3);
+        assert_eq!(timestamps(store.recent(2)), vec![4, 5]);
+        assert_eq!(timestamps(store.trades_between(0, u64::MAX)), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_removed_trades_leave_the_tape() {
+        let mut store = TradeStore::new(10);
+        let trades: Vec<Trade> = [10, 20, 30].into_iter().map(trade_at).collect();
+        trades.iter().for_each(|trade| store.record(trade.clone()));
+
+        assert_eq!(store.remove(&trades[1].trade_id).map(|t| t.timestamp), Some(20));
+        assert!(store.remove(&trades[1].trade_id).is_none());
+        assert_eq!(timestamps(store.recent(10)), vec![10, 30]);
+    }
+
+    #[test]
+    fn test_zero_retention_disables_store() {
+        let mut store = TradeStore::new(0);
+        store.record(trade_at(1));
+        assert!(store.is_empty());
+    }
+}
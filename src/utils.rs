@@ -1,10 +1,12 @@
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use crate::engine::MatchingEngine;
-use serde::Deserialize;
-use std::error::Error;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Side {
     Buy,
     Sell,
@@ -16,7 +18,7 @@ pub enum OrderType {
     Limit,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum OrderStatus {
     New,
     PartiallyFilled,
@@ -24,15 +26,91 @@ pub enum OrderStatus {
     Canceled,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Operation {
-    pub operation: String,
-    pub instrument: String,
-    pub side: Option<String>,
-    pub order_type: Option<String>,
-    pub quantity: Option<Decimal>,
-    pub price: Option<Decimal>,
-    pub order_to_cancel: Option<String>,
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+
+    /// Orders prices best first for this side: highest first for bids,
+    /// lowest first for asks.
+    pub fn cmp_priority<T: Ord>(self, price: &T, other: &T) -> std::cmp::Ordering {
+        match self {
+            Side::Buy => other.cmp(price),
+            Side::Sell => price.cmp(other),
+        }
+    }
+
+    /// Whether `price` is as good as `other` or better for this side.
+    pub fn at_or_better<T: Ord>(self, price: T, other: T) -> bool {
+        self.cmp_priority(&price, &other).is_le()
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Buy => "BUY",
+            Side::Sell => "SELL",
+        })
+    }
+}
+
+impl FromStr for Side {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "BUY" => Ok(Side::Buy),
+            "SELL" => Ok(Side::Sell),
+            _ => Err(format!("Unknown side '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OrderType::Market => "MARKET",
+            OrderType::Limit => "LIMIT",
+        })
+    }
+}
+
+impl FromStr for OrderType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "MARKET" => Ok(OrderType::Market),
+            "LIMIT" => Ok(OrderType::Limit),
+            _ => Err(format!("Unknown order type '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OrderStatus::New => "NEW",
+            OrderStatus::PartiallyFilled => "PARTIALLY_FILLED",
+            OrderStatus::Filled => "FILLED",
+            OrderStatus::Canceled => "CANCELED",
+        })
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "NEW" => Ok(OrderStatus::New),
+            "PARTIALLY_FILLED" => Ok(OrderStatus::PartiallyFilled),
+            "FILLED" => Ok(OrderStatus::Filled),
+            "CANCELED" | "CANCELLED" => Ok(OrderStatus::Canceled),
+            _ => Err(format!("Unknown order status '{}'", s)),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -43,99 +121,342 @@ pub enum MatchingEngineError {
     OrderNotFound(uuid::Uuid),
     #[error("Invalid order price: Market orders cannot have a price, and limit orders must")]
     InvalidOrderPrice,
+    #[error("Price {price} is not a multiple of the tick size {tick_size}")]
+    InvalidTickSize { price: Decimal, tick_size: Decimal },
+    #[error("Quantity {quantity} is not a multiple of the lot size {lot_size}")]
+    InvalidLotSize { quantity: Decimal, lot_size: Decimal },
+    #[error("{value} has more than the instrument's {decimals} decimal places")]
+    InstrumentPrecision { value: Decimal, decimals: u32 },
+    #[error("Price {price} is outside the {max_deviation} collar around reference price {reference}")]
+    PriceOutsideCollar { price: Decimal, reference: Decimal, max_deviation: Decimal },
+    #[error("Price {price} is outside the price band {lower} to {upper}")]
+    PriceOutsideBand { price: Decimal, lower: Decimal, upper: Decimal },
+    #[error("Client '{client}' has equity {equity} but needs {required} of initial margin")]
+    InsufficientMargin { client: String, equity: Decimal, required: Decimal },
+    #[error("Risk limit exceeded: {0}")]
+    RiskLimitExceeded(String),
+    #[error("Capacity exceeded: {0}")]
+    CapacityExceeded(String),
+    #[error("Invalid engine configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Order ID '{order_id}' is not owned by client '{client}'")]
+    NotOrderOwner { order_id: uuid::Uuid, client: String },
+    #[error("No reference price is available to peg order '{0}'")]
+    NoPegReference(uuid::Uuid),
+    #[error("Discretion price {discretion} must be on a limit order and at or beyond its price {price}")]
+    InvalidDiscretion { price: Decimal, discretion: Decimal },
+    #[error("Post-only order {0} would trade on arrival")]
+    PostOnlyWouldTrade(uuid::Uuid),
+    #[error("Order attribute not supported: {0}")]
+    UnsupportedAttribute(String),
+    #[error("{0} has more than 8 decimal places or is out of range")]
+    ExcessPrecision(Decimal),
+    #[error("Restored order '{order_id}' at {price} would cross the book")]
+    RestoreWouldCross { order_id: uuid::Uuid, price: Decimal },
+    #[error("Trading in '{0}' is halted")]
+    MarketHalted(String),
+    #[error("Option '{0}' has expired")]
+    InstrumentExpired(String),
+    #[error("Expiry time of order '{0}' must be in the future, on a limit order that can rest")]
+    InvalidExpiry(uuid::Uuid),
+    #[error("Cancel-after delay of order '{0}' must be positive, on a limit order that can rest")]
+    InvalidCancelAfter(uuid::Uuid),
+    #[error("Stop price {stop_price} of order '{order_id}' must be positive, and pegged orders cannot be stops")]
+    InvalidStopPrice { order_id: uuid::Uuid, stop_price: Decimal },
+    #[error("The engine thread has stopped")]
+    EngineStopped,
+    #[error("Client '{client}' is over its order-to-trade ratio of {max_ratio}")]
+    OrderToTradeRatioExceeded { client: String, max_ratio: f64 },
+    #[error("Trade '{0}' not found")]
+    TradeNotFound(uuid::Uuid),
+    #[error("Trade '{trade_id}' is older than the bust window of {window:?}")]
+    BustWindowElapsed { trade_id: uuid::Uuid, window: std::time::Duration },
 }
 
 #[derive(Debug)]
 pub struct PriceLevel {
     pub price: Decimal,
     pub volume: Decimal,
+    /// Entry time of the order at the front of the queue.
+    pub oldest_order_at: Option<u64>,
+    /// When an order at this price last traded, if it has since the level
+    /// was created.
+    pub last_trade_at: Option<u64>,
+}
+
+/// Price and volume, as one line of the printed book.
+impl fmt::Display for PriceLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Price: {:<10} | Volume: {:<12}", self.price.round_dp(2), self.volume)
+    }
 }
 
 #[derive(Debug)]
 pub struct OrderBookDisplay {
+    pub instrument: String,
+    /// Engine time the level ages are measured to. Without it the printed
+    /// book leaves the ages out.
+    pub as_of: Option<u64>,
     pub bids: Vec<PriceLevel>,
     pub asks: Vec<PriceLevel>,
 }
 
-pub fn display_final_matching_engine(instruments: &[String], engine: &MatchingEngine) {
-    println!("\n--- FINAL ORDER BOOKS ---");
-    for instrument in instruments {
-        if let Some(display) = engine.get_order_book_display(instrument) {
-            println!("\n--- ORDER BOOK: {} ---", instrument);
-            
-            println!("  ASKS (Sell Orders):");
-            if display.asks.is_empty() {
-                println!("    (empty)");
-            } else {
-                for level in display.asks.iter().rev() {
-                    println!("    Price: {:<10} | Volume: {}", level.price.round_dp(2), level.volume);
+/// The book as printed at the end of a run: asks above bids, highest price
+/// first, each level with how long the front of its queue has waited and
+/// how long ago it last traded.
+impl fmt::Display for OrderBookDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- ORDER BOOK: {} ---", self.instrument)?;
+        let side = |f: &mut fmt::Formatter<'_>, name: &str, levels: &mut dyn Iterator<Item = &PriceLevel>| -> fmt::Result {
+            writeln!(f, "  {}:", name)?;
+            let mut empty = true;
+            for level in levels {
+                empty = false;
+                write!(f, "    {}", level)?;
+                if let Some(now) = self.as_of {
+                    let ago = |at: Option<u64>| at.map_or_else(|| "-".to_string(), |at| format_age(now.saturating_sub(at)));
+                    write!(f, " | Oldest: {:<9} | Last trade: {}", ago(level.oldest_order_at), ago(level.last_trade_at))?;
                 }
+                writeln!(f)?;
             }
-            
-            println!("  ---------------------------");
-
-            println!("  BIDS (Buy Orders):");
-            if display.bids.is_empty() {
-                println!("    (empty)");
-            } else {
-                for level in &display.bids {
-                    println!("    Price: {:<10} | Volume: {}", level.price.round_dp(2), level.volume);
-                }
+            if empty {
+                writeln!(f, "    (empty)")?;
             }
-            println!("-----------------------------");
+            Ok(())
+        };
+        side(f, "ASKS (Sell Orders)", &mut self.asks.iter().rev())?;
+        writeln!(f, "  ---------------------------")?;
+        side(f, "BIDS (Buy Orders)", &mut self.bids.iter())?;
+        write!(f, "-----------------------------")
+    }
+}
+
+/// One instrument's line in an [`EngineSummary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstrumentSummary {
+    pub instrument: String,
+    /// Best price and the quantity resting there.
+    pub bid: Option<(Decimal, Decimal)>,
+    pub ask: Option<(Decimal, Decimal)>,
+    pub last_trade_price: Option<Decimal>,
+    pub resting_orders: usize,
+    /// Trades made, busted ones included.
+    pub trades: u64,
+    pub halted: bool,
+}
+
+/// Every instrument of an engine, one line each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineSummary {
+    /// Sorted by instrument.
+    pub instruments: Vec<InstrumentSummary>,
+}
+
+impl fmt::Display for EngineSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let touch = |touch: Option<(Decimal, Decimal)>| touch.map_or_else(|| "-".to_string(), |(price, quantity)| format!("{} x {}", price, quantity));
+        write!(f, "{:<16} {:>20} {:>20} {:>12} {:>8} {:>8}", "Instrument", "Bid", "Ask", "Last", "Orders", "Trades")?;
+        for summary in &self.instruments {
+            write!(
+                f,
+                "\n{:<16} {:>20} {:>20} {:>12} {:>8} {:>8}{}",
+                summary.instrument,
+                touch(summary.bid),
+                touch(summary.ask),
+                summary.last_trade_price.map_or_else(|| "-".to_string(), |price| price.to_string()),
+                summary.resting_orders,
+                summary.trades,
+                if summary.halted { "  HALTED" } else { "" },
+            )?;
         }
+        Ok(())
     }
 }
 
-pub fn load_operations(path: &str) -> Result<Vec<Operation>, Box<dyn Error>> {
-    let mut reader = csv::Reader::from_path(path)?;
-    
-    const EXPECTED_RECORDS: usize = 100_000; 
-    let mut ops = Vec::with_capacity(EXPECTED_RECORDS);
+pub fn display_final_matching_engine(instruments: &[String], engine: &MatchingEngine) {
+    println!("\n--- FINAL ORDER BOOKS ---");
+    println!("{}", engine.summary());
+    display_order_books(instruments, engine);
+}
 
-    for result in reader.deserialize() {
-        ops.push(result?);
+pub fn display_order_books(instruments: &[String], engine: &MatchingEngine) {
+    for instrument in instruments {
+        if let Some(display) = engine.get_order_book_display(instrument) {
+            println!("\n{}", display);
+        }
     }
-    
-    Ok(ops)
 }
 
-pub fn report_latencies(latencies: &[(u128, u128)]) {
-    if latencies.is_empty() {
-        println!("No latencies recorded.");
-        return;
+/// A duration in nanoseconds at the coarsest unit that keeps it above one.
+pub fn format_age(nanos: u64) -> String {
+    match nanos {
+        0..1_000 => format!("{}ns", nanos),
+        1_000..1_000_000 => format!("{:.1}µs", nanos as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.1}ms", nanos as f64 / 1e6),
+        _ => format!("{:.1}s", nanos as f64 / 1e9),
     }
+}
+
+/// Hasher for maps and sets keyed by order id, which are hit on every fill
+/// and cancel. SipHash by default; FxHash with the `fxhash` feature.
+#[cfg(feature = "fxhash")]
+pub type OrderIdHasher = rustc_hash::FxBuildHasher;
+#[cfg(not(feature = "fxhash"))]
+pub type OrderIdHasher = std::hash::RandomState;
 
-    let mut process_latencies: Vec<u128> = latencies.iter().map(|(p, _)| *p).collect();
-    let mut log_latencies: Vec<u128> = latencies.iter().map(|(_, l)| *l).collect();
+pub type UuidMap<V> = HashMap<uuid::Uuid, V, OrderIdHasher>;
+pub type UuidSet = HashSet<uuid::Uuid, OrderIdHasher>;
 
-    process_latencies.sort_unstable();
-    log_latencies.sort_unstable();
+/// Nearest-rank percentile of an ascending slice, `q` in `0.0..=1.0`.
+pub fn percentile(sorted: &[u128], q: f64) -> u128 {
+    let count = sorted.len();
+    sorted[((count as f64 * q).ceil() as usize).min(count - 1)]
+}
+
+/// The distribution of one kind of latency, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub mean: f64,
+    pub median: u128,
+    pub p99: u128,
+    pub p999: u128,
+}
+
+impl LatencyStats {
+    /// Summarizes `samples`, or returns `None` if there are none.
+    pub fn of(mut samples: Vec<u128>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let count = samples.len();
+        Some(LatencyStats {
+            count,
+            mean: samples.iter().sum::<u128>() as f64 / count as f64,
+            median: samples[count / 2],
+            p99: percentile(&samples, 0.99),
+            p999: percentile(&samples, 0.999),
+        })
+    }
+
+    fn print(&self) {
+        println!("{:<25} {}", "Count:", self.count);
+        println!("{:<25} {:.2}", "Mean:", self.mean);
+        println!("{:<25} {}", "Median:", self.median);
+        println!("{:<25} {}", "99th Percentile:", self.p99);
+        println!("{:<25} {}", "99.9th Percentile:", self.p999);
+    }
+}
+
+/// Processing and logging latencies of a run, from `(processing, logging)`
+/// pairs.
+pub fn summarize_latencies(latencies: &[(u128, u128)]) -> Option<(LatencyStats, LatencyStats)> {
+    let process = LatencyStats::of(latencies.iter().map(|(p, _)| *p).collect())?;
+    let log = LatencyStats::of(latencies.iter().map(|(_, l)| *l).collect())?;
+    Some((process, log))
+}
 
-    let count = process_latencies.len();
-    let process_sum: u128 = process_latencies.iter().sum();
-    let log_sum: u128 = log_latencies.iter().sum();
-    let process_mean = process_sum as f64 / count as f64;
-    let log_mean = log_sum as f64 / count as f64;
-    let process_median = process_latencies[count / 2];
-    let log_median = log_latencies[count / 2];
-    let process_p99 = process_latencies[((count as f64 * 0.99).ceil() as usize).min(count - 1)];
-    let log_p99 = log_latencies[((count as f64 * 0.99).ceil() as usize).min(count - 1)];
-    let process_p999 = process_latencies[((count as f64 * 0.999).ceil() as usize).min(count - 1)];
-    let log_p999 = log_latencies[((count as f64 * 0.999).ceil() as usize).min(count - 1)];
+pub fn report_latencies(latencies: &[(u128, u128)]) {
+    let Some((process, log)) = summarize_latencies(latencies) else {
+        println!("No latencies recorded.");
+        return;
+    };
 
     println!("\n--- Latency Distribution (nanoseconds) ---");
     println!("Processing:");
-    println!("{:<25} {}", "Count:", count);
-    println!("{:<25} {:.2}", "Mean:", process_mean);
-    println!("{:<25} {}", "Median:", process_median);
-    println!("{:<25} {}", "99th Percentile:", process_p99);
-    println!("{:<25} {}", "99.9th Percentile:", process_p999);
+    process.print();
     println!("Logging:");
-    println!("{:<25} {}", "Count:", count);
-    println!("{:<25} {:.2}", "Mean:", log_mean);
-    println!("{:<25} {}", "Median:", log_median);
-    println!("{:<25} {}", "99th Percentile:", log_p99);
-    println!("{:<25} {}", "99.9th Percentile:", log_p999);
+    log.print();
     println!("------------------------------------------");
-}
\ No newline at end of file
+    #[cfg(feature = "phase-timing")]
+    crate::phase_timing::print_phases();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_enums_round_trip_through_strings() {
+        for side in [Side::Buy, Side::Sell] {
+            assert_eq!(side.to_string().parse::<Side>(), Ok(side));
+        }
+        for order_type in [OrderType::Market, OrderType::Limit] {
+            assert_eq!(order_type.to_string().parse::<OrderType>(), Ok(order_type));
+        }
+        for status in [OrderStatus::New, OrderStatus::PartiallyFilled, OrderStatus::Filled, OrderStatus::Canceled] {
+            assert_eq!(status.to_string().parse::<OrderStatus>(), Ok(status));
+        }
+    }
+
+    #[test]
+    fn test_format_age_picks_the_coarsest_unit() {
+        assert_eq!(format_age(750), "750ns");
+        assert_eq!(format_age(12_500), "12.5µs");
+        assert_eq!(format_age(3_000_000), "3.0ms");
+        assert_eq!(format_age(90_000_000_000), "90.0s");
+    }
+
+    #[test]
+    fn test_book_and_summary_print_without_the_engine() {
+        let level = |price: Decimal, volume: Decimal, oldest_order_at| PriceLevel { price, volume, oldest_order_at, last_trade_at: None };
+        let mut book = OrderBookDisplay {
+            instrument: "SOFI".to_string(),
+            as_of: Some(5_000),
+            bids: vec![level(dec!(99.5), dec!(7), Some(4_000))],
+            asks: Vec::new(),
+        };
+        let printed = book.to_string();
+        assert!(printed.starts_with("--- ORDER BOOK: SOFI ---\n  ASKS (Sell Orders):\n    (empty)\n"), "{}", printed);
+        assert!(printed.contains("    Price: 99.5       | Volume: 7            | Oldest: 1.0µs     | Last trade: -\n"), "{}", printed);
+        book.as_of = None;
+        assert!(book.to_string().contains("| Volume: 7           \n"));
+
+        let summary = EngineSummary {
+            instruments: vec![InstrumentSummary {
+                instrument: "SOFI".to_string(),
+                bid: Some((dec!(99.5), dec!(7))),
+                ask: None,
+                last_trade_price: Some(dec!(100)),
+                resting_orders: 1,
+                trades: 3,
+                halted: true,
+            }],
+        };
+        let lines: Vec<String> = summary.to_string().lines().map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")).collect();
+        assert_eq!(lines, ["Instrument Bid Ask Last Orders Trades", "SOFI 99.5 x 7 - 100 1 3 HALTED"]);
+    }
+
+    #[test]
+    fn test_side_priority_puts_the_best_price_first() {
+        let mut prices = vec![3, 1, 2];
+        prices.sort_by(|a, b| Side::Buy.cmp_priority(a, b));
+        assert_eq!(prices, vec![3, 2, 1]);
+        prices.sort_by(|a, b| Side::Sell.cmp_priority(a, b));
+        assert_eq!(prices, vec![1, 2, 3]);
+        assert!(Side::Buy.at_or_better(2, 2) && Side::Buy.at_or_better(3, 2) && !Side::Sell.at_or_better(3, 2));
+        assert_eq!(Side::Buy.opposite(), Side::Sell);
+    }
+
+    #[test]
+    fn test_parsing_is_case_insensitive_and_rejects_unknown_values() {
+        assert_eq!("buy".parse::<Side>(), Ok(Side::Buy));
+        assert_eq!("Limit".parse::<OrderType>(), Ok(OrderType::Limit));
+        assert!("HOLD".parse::<Side>().is_err());
+        assert!("STOP".parse::<OrderType>().is_err());
+    }
+
+    #[test]
+    fn test_latency_summary_of_known_samples() {
+        let latencies: Vec<(u128, u128)> = (1..=1000).rev().map(|n| (n, 10 * n)).collect();
+        let (process, log) = summarize_latencies(&latencies).unwrap();
+        assert_eq!(process, LatencyStats { count: 1000, mean: 500.5, median: 501, p99: 991, p999: 1000 });
+        assert_eq!((log.mean, log.median, log.p99, log.p999), (5005.0, 5010, 9910, 10000));
+
+        let (single, _) = summarize_latencies(&[(7, 3)]).unwrap();
+        assert_eq!((single.median, single.p99, single.p999), (7, 7, 7));
+        assert!(summarize_latencies(&[]).is_none());
+    }
+}
@@ -0,0 +1,204 @@
+//! Several venues trading side by side, each on its own simulated clock, with
+//! a consolidated tape that stamps every print in both venue time and
+//! reference time. A venue's clock is the reference clock plus a fixed offset
+//! and a drift, so cross-venue studies can see how clock skew reorders what
+//! happened.
+
+use crate::clock::{Clock, SkewedClock};
+use crate::engine::MatchingEngine;
+use crate::events::{EngineEvent, EngineSubscriber};
+use crate::trade::Trade;
+use crate::utils::MatchingEngineError;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// One venue and how far its clock is off the reference, given as
+/// `<name>[:<offset_ns>[:<drift_ppm>]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueSpec {
+    pub name: String,
+    pub offset_nanos: i64,
+    pub drift_ppm: f64,
+}
+
+impl FromStr for VenueSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().filter(|name| !name.is_empty()).ok_or_else(|| format!("Invalid venue '{}', expected <name>[:<offset_ns>[:<drift_ppm>]]", s))?;
+        let offset_nanos = match parts.next() {
+            Some(offset) => offset.parse::<i64>().map_err(|_| format!("Invalid clock offset '{}' for venue {}", offset, name))?,
+            None => 0,
+        };
+        let drift_ppm = match parts.next() {
+            Some(drift) => drift.parse::<f64>().ok().filter(|drift| drift.is_finite()).ok_or_else(|| format!("Invalid clock drift '{}' for venue {}", drift, name))?,
+            None => 0.0,
+        };
+        if parts.next().is_some() {
+            return Err(format!("Invalid venue '{}', expected <name>[:<offset_ns>[:<drift_ppm>]]", s));
+        }
+        Ok(VenueSpec { name: name.to_string(), offset_nanos, drift_ppm })
+    }
+}
+
+/// A trade as it appears on the consolidated tape.
+#[derive(Debug, Clone)]
+pub struct TapePrint {
+    pub venue: String,
+    /// The venue's own timestamp, the one on the trade.
+    pub venue_nanos: u64,
+    /// The reference clock when the trade happened.
+    pub reference_nanos: u64,
+    pub trade: Trade,
+}
+
+impl TapePrint {
+    /// How far ahead of the reference the venue stamped the trade.
+    pub fn skew_nanos(&self) -> i64 {
+        self.venue_nanos as i64 - self.reference_nanos as i64
+    }
+}
+
+/// The prints of every venue in the order they happened. Clones share the
+/// same tape.
+#[derive(Debug, Clone, Default)]
+pub struct ConsolidatedTape {
+    prints: Arc<Mutex<Vec<TapePrint>>>,
+}
+
+impl ConsolidatedTape {
+    fn push(&self, print: TapePrint) {
+        self.prints.lock().expect("tape lock poisoned").push(print);
+    }
+
+    pub fn prints(&self) -> Vec<TapePrint> {
+        self.prints.lock().expect("tape lock poisoned").clone()
+    }
+
+    /// Consecutive prints from different venues whose venue timestamps put
+    /// them in the opposite order to the one they happened in. A consumer
+    /// that sorts the tape by venue time gets these pairs wrong.
+    pub fn misordered(&self) -> usize {
+        let prints = self.prints.lock().expect("tape lock poisoned");
+        prints.windows(2).filter(|pair| pair[0].venue != pair[1].venue && pair[1].venue_nanos < pair[0].venue_nanos).count()
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "venue,venue_time,reference_time,skew,instrument,price,quantity,trade_id")?;
+        for print in self.prints.lock().expect("tape lock poisoned").iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                print.venue, print.venue_nanos, print.reference_nanos, print.skew_nanos(), print.trade.instrument, print.trade.price, print.trade.quantity, print.trade.trade_id
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+/// Puts one venue's trades on the tape.
+struct TapeReporter {
+    venue: String,
+    reference: Box<dyn Clock>,
+    tape: ConsolidatedTape,
+}
+
+impl EngineSubscriber for TapeReporter {
+    fn on_event(&mut self, event: &EngineEvent) {
+        if let EngineEvent::Trade(trade) = event {
+            self.tape.push(TapePrint {
+                venue: self.venue.clone(),
+                venue_nanos: trade.timestamp,
+                reference_nanos: self.reference.now_nanos(),
+                trade: (*trade).clone(),
+            });
+        }
+    }
+}
+
+/// A matching engine per venue, all reporting to one tape.
+pub struct Venues {
+    venues: Vec<(String, MatchingEngine)>,
+    tape: ConsolidatedTape,
+}
+
+impl Venues {
+    /// Creates a venue per spec, in order, each auto-creating its markets
+    /// and running on `reference` skewed by its offset and drift.
+    pub fn new<C: Clock + Clone + 'static>(reference: C, specs: &[VenueSpec]) -> Result<Self, MatchingEngineError> {
+        let tape = ConsolidatedTape::default();
+        let mut venues = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let engine = MatchingEngine::builder()
+                .auto_create_markets(true)
+                .clock(SkewedClock::new(reference.clone(), spec.offset_nanos, spec.drift_ppm))
+                .subscriber(Box::new(TapeReporter { venue: spec.name.clone(), reference: Box::new(reference.clone()), tape: tape.clone() }))
+                .build()?;
+            venues.push((spec.name.clone(), engine));
+        }
+        Ok(Venues { venues, tape })
+    }
+
+    pub fn venue(&self, name: &str) -> Option<&MatchingEngine> {
+        self.venues.iter().find(|(venue, _)| venue == name).map(|(_, engine)| engine)
+    }
+
+    pub fn venue_mut(&mut self, name: &str) -> Option<&mut MatchingEngine> {
+        self.venues.iter_mut().find(|(venue, _)| venue == name).map(|(_, engine)| engine)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.venues.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn tape(&self) -> &ConsolidatedTape {
+        &self.tape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::logging::{create_logger, LoggingMode};
+    use crate::order::Order;
+    use crate::utils::Side;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parse_venue_spec() {
+        assert_eq!("XNAS".parse::<VenueSpec>(), Ok(VenueSpec { name: "XNAS".to_string(), offset_nanos: 0, drift_ppm: 0.0 }));
+        assert_eq!("BATS:-500:2.5".parse::<VenueSpec>(), Ok(VenueSpec { name: "BATS".to_string(), offset_nanos: -500, drift_ppm: 2.5 }));
+        assert!(":100".parse::<VenueSpec>().is_err());
+        assert!("XNAS:soon".parse::<VenueSpec>().is_err());
+        assert!("XNAS:1:2:3".parse::<VenueSpec>().is_err());
+    }
+
+    #[test]
+    fn test_tape_stamps_both_clocks_and_counts_misordered_prints() {
+        let reference = ManualClock::new(1_000);
+        let specs = ["FAST".parse().unwrap(), "SLOW:-500".parse().unwrap()];
+        let mut venues = Venues::new(reference.clone(), &specs).unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let mut cross = |engine: &mut MatchingEngine| {
+            engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(10), dec!(1)), &mut logger).unwrap();
+            engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(1)), &mut logger).unwrap();
+        };
+
+        cross(venues.venue_mut("FAST").unwrap());
+        reference.advance(200);
+        cross(venues.venue_mut("SLOW").unwrap());
+
+        let prints = venues.tape().prints();
+        assert_eq!(prints.iter().map(|p| (p.venue.as_str(), p.venue_nanos, p.reference_nanos)).collect::<Vec<_>>(), vec![("FAST", 1_000, 1_000), ("SLOW", 700, 1_200)]);
+        assert_eq!(prints[1].skew_nanos(), -500);
+        // SLOW traded later but its clock says it traded first.
+        assert_eq!(venues.tape().misordered(), 1);
+        assert_eq!(venues.names().collect::<Vec<_>>(), vec!["FAST", "SLOW"]);
+    }
+}
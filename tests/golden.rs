@@ -0,0 +1,77 @@
+use exchange_matching_engine::clock::ManualClock;
+use exchange_matching_engine::config::InstrumentConfig;
+use exchange_matching_engine::engine::MatchingEngine;
+use exchange_matching_engine::ids::SequentialIds;
+use exchange_matching_engine::logging::create_logger;
+use exchange_matching_engine::logging::types::LoggingMode;
+use exchange_matching_engine::simulation::run_simulation;
+use exchange_matching_engine::operation::{load_operations, ParsePolicy};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const INSTRUMENT: &str = "PUMPTHIS";
+
+/// Runs a fixture through a deterministic engine and renders every trade and
+/// the final book as text.
+fn render_run(fixture: &str) -> String {
+    let operations = load_operations(fixture, ParsePolicy::Strict).unwrap().operations;
+    let mut engine = MatchingEngine::builder()
+        .instrument(INSTRUMENT, InstrumentConfig::default())
+        .clock(ManualClock::new(1_700_000_000_000_000_000))
+        .id_generator(SequentialIds::new(1))
+        .build()
+        .unwrap();
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let mut latencies = Vec::new();
+
+    let report = run_simulation(&mut logger, &mut engine, &operations, &mut latencies).unwrap();
+
+    let mut out = String::new();
+    writeln!(out, "operations={} accepted={} rejected={} canceled={} cancel_failed={}",
+        report.outcomes.len(), report.accepted(), report.rejected(), report.canceled(), report.cancel_failed()).unwrap();
+    for trade in engine.trades_between(INSTRUMENT, 0, u64::MAX) {
+        writeln!(out, "TRADE id={} ts={} price={} qty={} taker={:?} buy={} sell={}",
+            trade.trade_id, trade.timestamp, trade.price, trade.quantity, trade.taker_side, trade.buy_order_id, trade.sell_order_id).unwrap();
+    }
+    let book = engine.get_order_book_display(INSTRUMENT).unwrap();
+    for level in book.asks.iter().rev() {
+        writeln!(out, "ASK price={} volume={}", level.price, level.volume).unwrap();
+    }
+    for level in &book.bids {
+        writeln!(out, "BID price={} volume={}", level.price, level.volume).unwrap();
+    }
+    for order in engine.resting_orders(INSTRUMENT) {
+        writeln!(out, "RESTING id={} side={:?} price={} remaining={}",
+            order.order_id, order.side, order.price.unwrap_or_default(), order.remaining_quantity).unwrap();
+    }
+    out
+}
+
+/// Compares against the golden file; set `UPDATE_GOLDEN=1` to rewrite it after
+/// an intentional change in matching semantics.
+fn assert_golden(name: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+    let fixture = dir.join(format!("{}.csv", name));
+    let golden = dir.join(format!("{}.golden", name));
+
+    let actual = render_run(fixture.to_str().unwrap());
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&golden).expect("golden file missing, run with UPDATE_GOLDEN=1");
+    assert_eq!(actual, expected, "output of {} differs from {}", fixture.display(), golden.display());
+}
+
+#[test]
+fn test_mini_operations_golden() {
+    assert_golden("mini_operations");
+}
+
+#[test]
+fn test_run_is_deterministic() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mini_operations.csv");
+    assert_eq!(render_run(fixture.to_str().unwrap()), render_run(fixture.to_str().unwrap()));
+}
@@ -1,14 +1,28 @@
+use exchange_matching_engine::audit::AuditEvent;
+use exchange_matching_engine::checkpoint::Checkpointer;
+use exchange_matching_engine::clients::ClientId;
+use exchange_matching_engine::clock::{Clock, ManualClock};
+use exchange_matching_engine::config::InstrumentConfig;
 use exchange_matching_engine::engine::MatchingEngine;
+use exchange_matching_engine::experiment::seeded_workload;
 use exchange_matching_engine::logging::create_logger;
 use exchange_matching_engine::logging::types::LoggingMode;
-use exchange_matching_engine::order::Order;
-use exchange_matching_engine::utils::{MatchingEngineError, Side};
+use exchange_matching_engine::order::{Order, OrderAttributes};
+use exchange_matching_engine::report::OperationOutcome;
+use exchange_matching_engine::governor::{RateLimit, SpeedGovernor};
+use exchange_matching_engine::simulation::{run_checkpointed, run_governed, run_simulation, run_simulation_with};
+use exchange_matching_engine::snapshot::save_snapshot;
+use exchange_matching_engine::operation::{NewOrder, SimOp};
+use exchange_matching_engine::utils::{summarize_latencies, MatchingEngineError, Side};
+use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 fn setup() -> MatchingEngine {
     let mut engine = MatchingEngine::new();
-    engine.add_market("SOFI".to_string());
+    engine.create_market("SOFI".to_string(), InstrumentConfig::default()).unwrap();
     engine
 }
 
@@ -173,4 +187,233 @@ fn test_process_order_for_unknown_market() {
 
     assert!(result.is_err());
     matches!(result.unwrap_err(), MatchingEngineError::MarketNotFound(market) if market == "UNKNOWN");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_recent_trades_and_time_range_queries() {
+    let mut engine = setup();
+    let mut logger = create_logger(LoggingMode::Baseline);
+
+    engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(101.0), dec!(5)), &mut logger).unwrap();
+    engine.process_order(Order::new_limit(Uuid::new_v4(), "SOFI".to_string(), Side::Sell, dec!(102.0), dec!(5)), &mut logger).unwrap();
+    let (trades, _log_duration) = engine.process_order(Order::new_market(Uuid::new_v4(), "SOFI".to_string(), Side::Buy, dec!(8)), &mut logger).unwrap();
+
+    let recent = engine.recent_trades("SOFI", 1);
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].trade_id, trades[1].trade_id);
+
+    let all = engine.trades_between("SOFI", 0, u64::MAX);
+    assert_eq!(all.len(), 2);
+    assert!(engine.trades_between("SOFI", trades[1].timestamp + 1, u64::MAX).is_empty());
+    assert!(engine.trades_between("UNKNOWN", 0, u64::MAX).is_empty());
+}
+
+fn new_op(side: Side, quantity: Decimal, price: Option<Decimal>) -> SimOp {
+    SimOp::New(NewOrder { order_id: Uuid::new_v4(), instrument: "SOFI".to_string(), side, quantity, price, client: None, client_order_id: None, peg: None, discretion: None, attributes: OrderAttributes::default(), expire_at: None, cancel_after: None, stop_price: None })
+}
+
+#[test]
+fn test_simulation_run_report_fill_rates() {
+    let mut engine = setup();
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let resting = new_op(Side::Sell, dec!(10), Some(dec!(100)));
+    let SimOp::New(NewOrder { order_id: resting_id, .. }) = resting else { unreachable!() };
+    let operations = vec![
+        resting,
+        new_op(Side::Buy, dec!(4), Some(dec!(100))),
+        new_op(Side::Buy, dec!(6), None),
+        SimOp::Cancel { instrument: "SOFI".to_string(), order_id: resting_id, client: None },
+        new_op(Side::Buy, dec!(10), Some(dec!(100))),
+    ];
+    let mut latencies = Vec::new();
+
+    let report = run_simulation(&mut logger, &mut engine, &operations, &mut latencies).unwrap();
+
+    assert_eq!(report.outcomes.len(), 5);
+    assert_eq!(report.accepted(), 4);
+    assert_eq!(report.cancel_failed(), 1);
+    assert_eq!(report.trades(), 2);
+    assert_eq!(report.submitted_quantity(), dec!(30));
+    assert_eq!(report.filled_quantity(), dec!(10));
+    assert!(matches!(report.outcomes[2], OperationOutcome::Accepted { trades: 1, .. }));
+}
+
+#[test]
+fn test_simulation_ranks_clients_by_marked_pnl() {
+    let mut engine = setup();
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let client_op = |client: &str, side, quantity, price| match new_op(side, quantity, price) {
+        SimOp::New(new_order) => SimOp::New(NewOrder { client: Some(ClientId::new(client)), ..new_order }),
+        _ => unreachable!(),
+    };
+    let operations = vec![
+        client_op("maker", Side::Sell, dec!(5), Some(dec!(101))),
+        client_op("taker", Side::Buy, dec!(5), None),
+        client_op("maker", Side::Buy, dec!(1), Some(dec!(98))),
+        new_op(Side::Sell, dec!(1), Some(dec!(100))),
+    ];
+    let mut latencies = Vec::new();
+
+    let report = run_simulation(&mut logger, &mut engine, &operations, &mut latencies).unwrap();
+
+    // The book ends at 98 / 100, so positions are marked at 99.
+    let ranked: Vec<_> = report.leaderboard.iter().map(|standing| (standing.client.0.as_str(), standing.pnl)).collect();
+    assert_eq!(ranked, [("maker", dec!(10)), ("taker", dec!(-10))]);
+    assert_eq!(report.leaderboard[0].fills.maker_fills, 1);
+    assert_eq!(report.leaderboard[1].fills.taker_fills, 1);
+}
+
+/// A latency clock that moves 10 ns every time it is read.
+struct SteppingClock(AtomicU64);
+
+impl Clock for SteppingClock {
+    fn now_nanos(&self) -> u64 {
+        self.0.fetch_add(10, Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_simulation_latencies_come_from_the_latency_clock() {
+    let mut engine = MatchingEngine::builder().latency_clock(SteppingClock(AtomicU64::new(0))).auto_create_markets(true).build().unwrap();
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let resting = new_op(Side::Sell, dec!(10), Some(dec!(100)));
+    let SimOp::New(NewOrder { order_id: resting_id, .. }) = resting else { unreachable!() };
+    let operations = vec![
+        resting,
+        new_op(Side::Buy, dec!(4), Some(dec!(100))),
+        SimOp::Cancel { instrument: "SOFI".to_string(), order_id: resting_id, client: None },
+    ];
+    let mut latencies = Vec::new();
+
+    run_simulation(&mut logger, &mut engine, &operations, &mut latencies).unwrap();
+
+    // An order reads the clock around the submission log, around the match
+    // and around the fill logs inside it; a cancel around the cancel and its log.
+    assert_eq!(latencies, [(30, 20), (30, 20), (10, 10)]);
+    let (process, log) = summarize_latencies(&latencies).unwrap();
+    assert_eq!((process.mean, process.median, log.p99), (70.0 / 3.0, 30, 20));
+}
+
+#[test]
+fn test_audit_trail_links_each_event_to_its_operation() {
+    let mut engine = MatchingEngine::builder().auto_create_markets(true).audit_trail(true).build().unwrap();
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let resting = new_op(Side::Sell, dec!(10), Some(dec!(100)));
+    let SimOp::New(NewOrder { order_id: resting_id, .. }) = resting else { unreachable!() };
+    let taker = new_op(Side::Buy, dec!(4), None);
+    let SimOp::New(NewOrder { order_id: taker_id, .. }) = taker else { unreachable!() };
+    let operations = vec![
+        resting,
+        new_op(Side::Buy, dec!(1), Some(dec!(99))),
+        taker,
+        SimOp::Cancel { instrument: "SOFI".to_string(), order_id: resting_id, client: None },
+    ];
+    let mut latencies = Vec::new();
+
+    run_simulation(&mut logger, &mut engine, &operations, &mut latencies).unwrap();
+
+    let audit = engine.audit().unwrap();
+    assert_eq!(engine.messages_received(), 4);
+    let story: Vec<(u64, &AuditEvent)> = audit.story(&resting_id).iter().map(|entry| (entry.operation, &entry.event)).collect();
+    let [(1, AuditEvent::Submitted { .. }), (1, AuditEvent::Rested { .. }), (3, AuditEvent::Traded { trade_id, aggressor: false, .. }), (4, AuditEvent::Canceled { remaining })] = story[..] else {
+        panic!("unexpected story {:?}", story);
+    };
+    assert_eq!(*remaining, dec!(6));
+    assert_eq!(audit.trade_origin(trade_id), Some(3));
+    assert!(matches!(audit.story(&taker_id).last().unwrap().event, AuditEvent::Filled));
+}
+
+#[test]
+fn test_resumed_run_matches_an_uninterrupted_one() {
+    let engine = || MatchingEngine::builder().clock(ManualClock::new(0)).latency_clock(SteppingClock(AtomicU64::new(0))).auto_create_markets(true).build().unwrap();
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let operations = vec![
+        new_op(Side::Sell, dec!(10), Some(dec!(100))),
+        new_op(Side::Sell, dec!(5), Some(dec!(101))),
+        new_op(Side::Buy, dec!(4), Some(dec!(100))),
+        new_op(Side::Buy, dec!(3), Some(dec!(99))),
+        new_op(Side::Buy, dec!(8), None),
+    ];
+    let dir = std::env::temp_dir().join(format!("checkpoint_{}", Uuid::new_v4()));
+    let book = |engine: &MatchingEngine| {
+        let path = dir.join(format!("book_{}.csv", Uuid::new_v4()));
+        save_snapshot(path.to_str().unwrap(), &engine.instruments(), engine).unwrap();
+        // Order timestamps are taken from the wall clock when an order is created.
+        let rows = std::fs::read_to_string(path).unwrap();
+        rows.lines().map(|row| row.split(',').enumerate().filter(|(column, _)| *column != 6).map(|(_, field)| field).collect::<Vec<_>>().join(",")).collect::<Vec<_>>()
+    };
+
+    let mut uninterrupted = engine();
+    let mut expected = Vec::new();
+    run_simulation(&mut logger, &mut uninterrupted, &operations, &mut expected).unwrap();
+
+    // The first run stops after three operations, past its checkpoint at two.
+    let mut interrupted = engine();
+    let mut checkpointer = Checkpointer::new(&dir, 2).unwrap();
+    run_checkpointed(&mut logger, &mut interrupted, &operations[..3], &mut Vec::new(), None, &mut checkpointer, false).unwrap();
+
+    let mut resumed = engine();
+    let mut latencies = Vec::new();
+    let mut checkpointer = Checkpointer::resume(&dir, 2, &mut resumed, &mut latencies).unwrap();
+    assert_eq!((checkpointer.completed(), latencies.len()), (2, 2));
+    run_checkpointed(&mut logger, &mut resumed, &operations, &mut latencies, None, &mut checkpointer, false).unwrap();
+
+    assert_eq!(latencies, expected);
+    assert_eq!(book(&resumed), book(&uninterrupted));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_simulation_errors_carry_their_operation_and_can_end_the_run() {
+    let mut logger = create_logger(LoggingMode::Baseline);
+    let unknown = match new_op(Side::Buy, dec!(5), Some(dec!(100))) {
+        SimOp::New(new_order) => SimOp::New(NewOrder { instrument: "NOPE".to_string(), ..new_order }),
+        _ => unreachable!(),
+    };
+    let SimOp::New(NewOrder { order_id: unknown_id, .. }) = &unknown else { unreachable!() };
+    let unknown_id = *unknown_id;
+    let operations = vec![new_op(Side::Sell, dec!(5), Some(dec!(101))), unknown, new_op(Side::Buy, dec!(1), Some(dec!(99)))];
+
+    let report = run_simulation(&mut logger, &mut setup(), &operations, &mut Vec::new()).unwrap();
+    assert_eq!(report.errors.len(), 1);
+    let error = &report.errors[0];
+    assert_eq!((error.operation, error.order_id), (2, Some(unknown_id)));
+    assert!(error.row.starts_with("NEW,NOPE,BUY,LIMIT,5,100,"));
+    assert_eq!(report.accepted(), 2);
+
+    let mut engine = setup();
+    let error = run_simulation_with(&mut logger, &mut engine, &operations, &mut Vec::new(), None, true).unwrap_err();
+    assert!(error.to_string().starts_with(&format!("operation 2 (order {})", unknown_id)));
+    assert_eq!(engine.order_book("SOFI").unwrap().best_bid(), None);
+}
+
+#[test]
+fn test_seeded_workloads_never_cross_the_book() {
+    for seed in 1..=5 {
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).check_crossed(true).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let report = run_simulation(&mut logger, &mut engine, &seeded_workload(2_000, seed), &mut Vec::new()).unwrap();
+        assert!(report.errors.is_empty(), "seed {}: {}", seed, report.errors[0]);
+    }
+}
+
+#[test]
+fn test_governed_run_holds_its_rate_and_matches_an_unpaced_one() {
+    let operations = seeded_workload(200, 3);
+    let run = |governor: Option<&mut SpeedGovernor>| {
+        let mut engine = MatchingEngine::builder().auto_create_markets(true).build().unwrap();
+        let mut logger = create_logger(LoggingMode::Baseline);
+        let report = match governor {
+            Some(governor) => run_governed(&mut logger, &mut engine, &operations, &mut Vec::new(), None, governor, false),
+            None => run_simulation(&mut logger, &mut engine, &operations, &mut Vec::new()),
+        };
+        report.unwrap().outcomes
+    };
+
+    // 200 operations at 10,000 a second, 50 at once, take at least 15 ms.
+    let mut governor = SpeedGovernor::new(RateLimit { per_second: 10_000.0, burst: 50 });
+    let start = Instant::now();
+    assert_eq!(run(Some(&mut governor)), run(None));
+    assert!(start.elapsed() >= Duration::from_millis(15), "{:?}", start.elapsed());
+    assert_eq!(governor.counts().0, 200);
+}